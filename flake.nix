@@ -19,7 +19,7 @@
             inherit system overlays;
           };
           rustToolchain = pkgs.pkgsBuildHost.rust-bin.fromRustupToolchainFile ./rust-toolchain.toml;
-          nativeBuildInputs = with pkgs; [ rustToolchain pkg-config libvpx_1_8 ffmpeg_5 libclang libllvm opencl-headers ocl-icd rustPlatform.bindgenHook ];
+          nativeBuildInputs = with pkgs; [ rustToolchain pkg-config libvpx_1_8 ffmpeg_5 libclang libllvm opencl-headers ocl-icd xorg.libxcb xorg.libXrandr dbus pipewire rustPlatform.bindgenHook ];
           buildInputs = with pkgs; [
             (rust-bin.stable.latest.rust.override {
               extensions = [ "rust-src" "cargo" "rustc" ];
@@ -0,0 +1,44 @@
+/*
+    Phaneron media compositing software.
+    Copyright (C) 2023 SuperFlyTV AB.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+__constant sampler_t sampler1 =
+    CLK_NORMALIZED_COORDS_FALSE
+    | CLK_ADDRESS_CLAMP_TO_EDGE
+    | CLK_FILTER_NEAREST;
+
+__kernel void burn_in(
+    __read_only image2d_t input,
+    __read_only image2d_t overlay,
+    __private unsigned int overlay_x,
+    __private unsigned int overlay_y,
+    __write_only image2d_t output
+) {
+    int x = get_global_id(0);
+    int y = get_global_id(1);
+    float4 in = read_imagef(input, sampler1, (int2)(x, y));
+
+    int2 overlay_pos = (int2)(x - (int)overlay_x, y - (int)overlay_y);
+    int2 overlay_size = get_image_dim(overlay);
+    if (all(overlay_pos >= (int2)(0, 0)) && all(overlay_pos < overlay_size)) {
+        float4 over = read_imagef(overlay, sampler1, overlay_pos);
+        // The overlay's alpha covers the input, the input's own alpha is kept.
+        in.xyz = mix(in.xyz, over.xyz, over.w);
+    }
+
+    write_imagef(output, (int2)(x, y), in);
+}
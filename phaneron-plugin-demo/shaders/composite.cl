@@ -0,0 +1,61 @@
+/*
+    Phaneron media compositing software.
+    Copyright (C) 2023 SuperFlyTV AB.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+__constant sampler_t sampler1 =
+    CLK_NORMALIZED_COORDS_FALSE
+    | CLK_ADDRESS_CLAMP_TO_EDGE
+    | CLK_FILTER_NEAREST;
+
+// Porter-Duff over for straight alpha, the result is also straight alpha.
+float4 over(float4 top, float4 bottom) {
+    float alpha = top.w + bottom.w * (1.0f - top.w);
+    if (alpha <= 0.0f) {
+        return (float4)(0.0f, 0.0f, 0.0f, 0.0f);
+    }
+    float3 rgb = top.xyz * top.w + bottom.xyz * bottom.w * (1.0f - top.w);
+    return (float4)(rgb / alpha, alpha);
+}
+
+// Places the foreground at its own size with its top left corner at the offset in the background.
+// Parts of the foreground outside of the background are clipped.
+__kernel void composite(
+    __read_only image2d_t background,
+    __read_only image2d_t foreground,
+    __private int offset_x,
+    __private int offset_y,
+    __private float opacity,
+    __private unsigned int premultiplied,
+    __write_only image2d_t output
+) {
+    int x = get_global_id(0);
+    int y = get_global_id(1);
+
+    float4 out = read_imagef(background, sampler1, (int2)(x, y));
+
+    int2 pos = (int2)(x - offset_x, y - offset_y);
+    if (all(pos >= (int2)(0, 0)) && all(pos < get_image_dim(foreground))) {
+        float4 top = read_imagef(foreground, sampler1, pos);
+        if (premultiplied != 0 && top.w > 0.0f) {
+            top.xyz /= top.w;
+        }
+        top.w *= opacity;
+        out = over(top, out);
+    }
+
+    write_imagef(output, (int2)(x, y), out);
+}
@@ -33,6 +33,7 @@ __kernel void transition_dissolve(
     int y = get_global_id(1);
     float4 in0 = read_imagef(input0, sampler1, (int2)(x,y));
     float4 in1 = read_imagef(input1, sampler1, (int2)(x,y));
+    // Frames are held in linear light, so mixing them directly gives a colour-correct blend.
     float4 mix4 = (float4)(mix, mix, mix, mix);
     float rmix = 1.0f - mix;
 
@@ -0,0 +1,52 @@
+/*
+    Phaneron media compositing software.
+    Copyright (C) 2023 SuperFlyTV AB.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+__constant sampler_t sampler1 =
+    CLK_NORMALIZED_COORDS_FALSE
+    | CLK_ADDRESS_CLAMP_TO_EDGE
+    | CLK_FILTER_LINEAR;
+
+// The viewport is the area of the output covered by the input, in output pixels.
+// It may extend beyond the output, in which case the input is cropped.
+__kernel void fit(
+    __read_only image2d_t input,
+    __private float viewport_x,
+    __private float viewport_y,
+    __private float viewport_width,
+    __private float viewport_height,
+    __private float background_r,
+    __private float background_g,
+    __private float background_b,
+    __private float background_a,
+    __write_only image2d_t output
+) {
+    int x = get_global_id(0);
+    int y = get_global_id(1);
+
+    float2 pos = (float2)(x + 0.5f, y + 0.5f);
+    float2 rel = (pos - (float2)(viewport_x, viewport_y)) / (float2)(viewport_width, viewport_height);
+
+    float4 out = (float4)(background_r, background_g, background_b, background_a);
+    if (all(rel >= (float2)(0.0f, 0.0f)) && all(rel < (float2)(1.0f, 1.0f))) {
+        // Pixel centres are at +0.5 with unnormalised coordinates, so this scales centre to centre
+        float2 sample_pos = rel * convert_float2(get_image_dim(input));
+        out = read_imagef(input, sampler1, sample_pos);
+    }
+
+    write_imagef(output, (int2)(x, y), out);
+}
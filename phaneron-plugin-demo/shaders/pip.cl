@@ -0,0 +1,55 @@
+/*
+    Phaneron media compositing software.
+    Copyright (C) 2023 SuperFlyTV AB.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+__constant sampler_t sampler1 =
+    CLK_NORMALIZED_COORDS_FALSE
+    | CLK_ADDRESS_CLAMP_TO_EDGE
+    | CLK_FILTER_LINEAR;
+
+float4 over(float4 top, float4 bottom) {
+    return (float4)(mix(bottom.xyz, top.xyz, top.w), top.w + bottom.w * (1.0f - top.w));
+}
+
+// The window is the area of the output covered by the inset, in output pixels.
+// Parts of it outside of the output are clipped, the border is drawn outside of the window.
+__kernel void pip(
+    __read_only image2d_t background,
+    __read_only image2d_t inset,
+    __private float2 window_pos,
+    __private float2 window_size,
+    __private float border_width,
+    __private float4 border_colour,
+    __write_only image2d_t output
+) {
+    int x = get_global_id(0);
+    int y = get_global_id(1);
+
+    float2 pos = (float2)(x + 0.5f, y + 0.5f);
+    float2 rel = (pos - window_pos) / window_size;
+
+    float4 out = read_imagef(background, sampler1, pos);
+    if (all(rel >= (float2)(0.0f, 0.0f)) && all(rel < (float2)(1.0f, 1.0f))) {
+        // Scales the inset to the window, centre to centre, whatever its own size
+        float2 sample_pos = rel * convert_float2(get_image_dim(inset));
+        out = over(read_imagef(inset, sampler1, sample_pos), out);
+    } else if (all(pos >= window_pos - border_width) && all(pos < window_pos + window_size + border_width)) {
+        out = over(border_colour, out);
+    }
+
+    write_imagef(output, (int2)(x, y), out);
+}
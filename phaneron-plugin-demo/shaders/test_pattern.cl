@@ -0,0 +1,57 @@
+/*
+    Phaneron media compositing software.
+    Copyright (C) 2023 SuperFlyTV AB.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+// Must match the patterns in test_pattern.rs
+#define PATTERN_COLOUR_BARS 0
+#define PATTERN_SOLID 1
+#define PATTERN_GRADIENT 2
+
+// Bars are given as segments of 5 floats: the bottom edge of the row and the right edge of the
+// bar as fractions of the frame, followed by the linear RGB colour of the bar. Each pixel takes
+// the colour of the first segment it is above and to the left of.
+__kernel void test_pattern(
+    __private unsigned int pattern,
+    __private float4 colour,
+    __private float position,
+    __global const float* bars,
+    __private unsigned int num_bars,
+    __write_only image2d_t output
+) {
+    int x = get_global_id(0);
+    int y = get_global_id(1);
+    float2 pos = (float2)(x + 0.5f, y + 0.5f) / convert_float2(get_image_dim(output));
+
+    float4 out = (float4)(0.0f, 0.0f, 0.0f, 1.0f);
+    if (pattern == PATTERN_COLOUR_BARS) {
+        for (unsigned int i = 0; i < num_bars; ++i) {
+            __global const float* bar = bars + i * 5;
+            if (pos.y < bar[0] && pos.x < bar[1]) {
+                out = (float4)(bar[2], bar[3], bar[4], 1.0f);
+                break;
+            }
+        }
+    } else if (pattern == PATTERN_SOLID) {
+        out = colour;
+    } else {
+        // A ramp from black to the colour that scrolls left as the position advances
+        float ramp = pos.x + position;
+        out = (float4)(colour.xyz * (ramp - floor(ramp)), colour.w);
+    }
+
+    write_imagef(output, (int2)(x, y), out);
+}
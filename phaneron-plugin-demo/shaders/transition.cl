@@ -0,0 +1,71 @@
+/*
+    Phaneron media compositing software.
+    Copyright (C) 2023 SuperFlyTV AB.
+
+    This program is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+
+__constant sampler_t sampler1 =
+    CLK_NORMALIZED_COORDS_FALSE
+    | CLK_ADDRESS_CLAMP_TO_EDGE
+    | CLK_FILTER_NEAREST;
+
+// Must match the transition kinds and WipeDirection in traditional_mixer_emulator.rs
+#define KIND_MIX 0
+#define KIND_DIP 1
+#define KIND_WIPE 2
+
+#define WIPE_LEFT 0
+#define WIPE_RIGHT 1
+#define WIPE_UP 2
+#define WIPE_DOWN 3
+
+// Goes from the active frame at position 0 to the next frame at position 1.
+__kernel void transition(
+    __read_only image2d_t active,
+    __read_only image2d_t next,
+    __private unsigned int kind,
+    __private float position,
+    __private unsigned int direction,
+    __private float4 colour,
+    __write_only image2d_t output
+) {
+    int x = get_global_id(0);
+    int y = get_global_id(1);
+    float4 from = read_imagef(active, sampler1, (int2)(x, y));
+    float4 to = read_imagef(next, sampler1, (int2)(x, y));
+
+    // Frames are held in linear light, so mixing them directly gives a colour-correct blend.
+    float4 out;
+    if (kind == KIND_DIP) {
+        out = position < 0.5f
+            ? mix(from, colour, position * 2.0f)
+            : mix(colour, to, position * 2.0f - 1.0f);
+    } else if (kind == KIND_WIPE) {
+        // The edge travels in the wipe direction, uncovering the next frame behind it
+        float2 pos = (float2)(x + 0.5f, y + 0.5f) / convert_float2(get_image_dim(output));
+        bool uncovered;
+        switch (direction) {
+            case WIPE_LEFT: uncovered = pos.x > 1.0f - position; break;
+            case WIPE_RIGHT: uncovered = pos.x < position; break;
+            case WIPE_UP: uncovered = pos.y > 1.0f - position; break;
+            default: uncovered = pos.y < position; break;
+        }
+        out = uncovered ? to : from;
+    } else {
+        out = mix(from, to, position);
+    }
+
+    write_imagef(output, (int2)(x, y), out);
+}
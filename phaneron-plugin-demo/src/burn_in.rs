@@ -0,0 +1,306 @@
+use std::sync::Mutex;
+
+use abi_stable::{
+    sabi_trait::TD_Opaque,
+    std_types::{ROption, RSlice, RString},
+};
+use log::error;
+use phaneron_plugin::{
+    traits::Node_TO,
+    types::{Node, NodeContext, ProcessFrameContext, ProcessShader, ToRGBA, VideoOutput},
+    ColourRange, ColourSpace, FrameRate, ShaderParams, VideoFormat, VideoInputId,
+};
+use serde::{Deserialize, Serialize};
+
+use self::font::{glyph, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+mod font;
+#[cfg(test)]
+mod tests;
+
+/// Size of a font pixel in video pixels.
+const SCALE: usize = 3;
+/// Horizontal space taken by each character in font pixels, including spacing.
+const CELL_WIDTH: usize = GLYPH_WIDTH + 1;
+/// Vertical space taken by each line in font pixels, including spacing.
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 2;
+/// Space around the text in font pixels.
+const PADDING: usize = 2;
+/// Distance of the overlay from the edges of the frame in video pixels.
+const MARGIN: usize = 32;
+/// The RGBA8 loader processes 64 pixels per work item, so overlays are padded to a multiple of this.
+const WIDTH_ALIGNMENT: usize = 64;
+const BACKGROUND_ALPHA: u8 = 160;
+
+pub struct BurnInHandle {
+    node_id: String,
+}
+impl BurnInHandle {
+    pub(super) fn new(node_id: String) -> Self {
+        Self { node_id }
+    }
+}
+impl phaneron_plugin::traits::NodeHandle for BurnInHandle {
+    fn initialize(&self, context: NodeContext, _configuration: ROption<RString>) -> Node {
+        let node = BurnIn::new(self.node_id.clone(), context);
+
+        Node_TO::from_value(node, TD_Opaque)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BurnInPosition {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl BurnInPosition {
+    /// Top left corner of an overlay of the given size.
+    fn origin(
+        &self,
+        width: usize,
+        height: usize,
+        overlay_width: usize,
+        overlay_height: usize,
+    ) -> (usize, usize) {
+        let right = width.saturating_sub(overlay_width + MARGIN);
+        let bottom = height.saturating_sub(overlay_height + MARGIN);
+        match self {
+            Self::TopLeft => (MARGIN, MARGIN),
+            Self::TopRight => (right, MARGIN),
+            Self::BottomLeft => (MARGIN, bottom),
+            Self::BottomRight => (right, bottom),
+        }
+    }
+}
+
+/// The frame number is the frame of the graph clock, counted from when the graph was created.
+/// The timecode is derived from the frame number at the graph's frame rate, so both restart from
+/// zero when the graph is recreated.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default, rename_all = "snake_case")]
+pub struct BurnInState {
+    pub show_timecode: bool,
+    pub show_frame_number: bool,
+    /// Shows the timecode carried in the metadata of the input frames, e.g. from the FFmpeg producer.
+    /// Nothing is shown for frames without a timecode.
+    pub show_source_timecode: bool,
+    /// Usually the name of the source, not shown when empty.
+    pub label: String,
+    pub position: BurnInPosition,
+}
+
+impl Default for BurnInState {
+    fn default() -> Self {
+        Self {
+            show_timecode: true,
+            show_frame_number: true,
+            show_source_timecode: false,
+            label: String::new(),
+            position: BurnInPosition::default(),
+        }
+    }
+}
+
+impl BurnInState {
+    fn lines(
+        &self,
+        frame_number: u64,
+        frame_rate: FrameRate,
+        source_timecode: Option<&str>,
+    ) -> Vec<String> {
+        let mut lines = vec![];
+        if !self.label.is_empty() {
+            lines.push(self.label.clone());
+        }
+        if self.show_timecode {
+            lines.push(timecode(frame_number, frame_rate));
+        }
+        if self.show_frame_number {
+            lines.push(format!("#{frame_number}"));
+        }
+        if let (true, Some(source_timecode)) = (self.show_source_timecode, source_timecode) {
+            lines.push(format!("SRC {source_timecode}"));
+        }
+
+        lines
+    }
+}
+
+pub struct BurnIn {
+    node_id: String,
+    context: NodeContext,
+    state: Mutex<BurnInState>,
+    to_rgba: Mutex<Option<(usize, usize, ToRGBA)>>,
+    shader: ProcessShader,
+    video_input: VideoInputId,
+    video_output: VideoOutput,
+}
+
+impl BurnIn {
+    pub fn new(node_id: String, context: NodeContext) -> Self {
+        let video_input = context.add_video_input();
+        let video_output = context.add_video_output();
+        let kernel = include_str!("../shaders/burn_in.cl");
+        let shader = context
+            .create_process_shader(kernel.into(), "burn_in".into())
+            .unwrap();
+
+        Self {
+            node_id,
+            context,
+            state: Default::default(),
+            to_rgba: Default::default(),
+            shader,
+            video_input,
+            video_output,
+        }
+    }
+}
+
+impl phaneron_plugin::traits::Node for BurnIn {
+    fn apply_state(&self, state: RString) -> bool {
+        let state: BurnInState = match serde_json::from_str(&state) {
+            Ok(state) => state,
+            Err(err) => {
+                error!("{}: Invalid state: {err}", self.node_id);
+                return false;
+            }
+        };
+
+        *self.state.lock().unwrap() = state;
+
+        true
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let state = self.state.lock().unwrap();
+        let frame_number = frame_context.get_frame_number();
+        let frame_rate = frame_context.get_frame_rate();
+
+        let input = frame_context
+            .get_video_input(&self.video_input)
+            .unwrap_or(frame_context.get_black_frame())
+            .frame
+            .clone();
+
+        let lines = state.lines(frame_number, frame_rate, input.metadata().get("timecode"));
+        let output = if lines.is_empty() {
+            input
+        } else {
+            let overlay = Overlay::rasterise(&lines);
+            let mut to_rgba = self.to_rgba.lock().unwrap();
+            if !matches!(&*to_rgba, Some((w, h, _)) if *w == overlay.width && *h == overlay.height)
+            {
+                *to_rgba = Some((
+                    overlay.width,
+                    overlay.height,
+                    self.context.create_to_rgba(
+                        &VideoFormat::RGBA8,
+                        &ColourSpace::sRGB.colour_spec(),
+                        ColourRange::Full,
+                        overlay.width,
+                        overlay.height,
+                    ),
+                ));
+            }
+            let (_, _, to_rgba) = to_rgba.as_ref().unwrap();
+            let inputs: Vec<RSlice<u8>> = vec![overlay.pixels.as_slice().into()];
+            let loaded_frame = to_rgba.load_frame(&inputs.as_slice().into());
+            let overlay_frame = to_rgba.process_frame(loaded_frame);
+
+            let (width, height) = (input.width(), input.height());
+            let (x, y) = state
+                .position
+                .origin(width, height, overlay.width, overlay.height);
+            let mut params = ShaderParams::default();
+            params.set_param_video_frame_input(input);
+            params.set_param_video_frame_input(overlay_frame);
+            params.set_param_u32_input(x as u32);
+            params.set_param_u32_input(y as u32);
+            params.set_param_video_frame_output(width, height);
+
+            self.shader.run(params, &[width, height])[0].clone()
+        };
+
+        let frame_context = frame_context.submit().unwrap();
+        self.video_output.push_frame(&frame_context, output).ok();
+    }
+}
+
+/// RGBA8 image of the burn-in text, white text on a translucent black box.
+struct Overlay {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Overlay {
+    fn rasterise(lines: &[String]) -> Self {
+        let columns = lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or_default();
+        let box_width = (columns * CELL_WIDTH + 2 * PADDING) * SCALE;
+        let width = box_width.div_ceil(WIDTH_ALIGNMENT) * WIDTH_ALIGNMENT;
+        let height = (lines.len() * LINE_HEIGHT + 2 * PADDING) * SCALE;
+
+        let mut pixels = vec![0u8; width * height * 4];
+        for y in 0..height {
+            for x in 0..box_width {
+                pixels[(y * width + x) * 4 + 3] = BACKGROUND_ALPHA;
+            }
+        }
+
+        for (line_index, line) in lines.iter().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                let left = PADDING + column * CELL_WIDTH;
+                let top = PADDING + line_index * LINE_HEIGHT;
+                for (row, bits) in glyph(c).iter().enumerate() {
+                    for bit in 0..GLYPH_WIDTH {
+                        if bits & (1 << (GLYPH_WIDTH - 1 - bit)) != 0 {
+                            fill_font_pixel(&mut pixels, width, left + bit, top + row);
+                        }
+                    }
+                }
+            }
+        }
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+fn fill_font_pixel(pixels: &mut [u8], width: usize, x: usize, y: usize) {
+    for dy in 0..SCALE {
+        for dx in 0..SCALE {
+            let offset = ((y * SCALE + dy) * width + x * SCALE + dx) * 4;
+            pixels[offset..offset + 4].copy_from_slice(&[255, 255, 255, 255]);
+        }
+    }
+}
+
+/// Formats a frame number as `HH:MM:SS:FF`, wrapping after 24 hours. Fractional rates count
+/// frames at their nominal rate without dropping frame numbers, e.g. 30 for 29.97.
+fn timecode(frame_number: u64, frame_rate: FrameRate) -> String {
+    let frame_rate = u64::from(frame_rate.num)
+        .div_ceil(u64::from(frame_rate.den.max(1)))
+        .max(1);
+    let frames = frame_number % frame_rate;
+    let seconds = frame_number / frame_rate;
+    format!(
+        "{:02}:{:02}:{:02}:{:02}",
+        (seconds / 3600) % 24,
+        (seconds / 60) % 60,
+        seconds % 60,
+        frames
+    )
+}
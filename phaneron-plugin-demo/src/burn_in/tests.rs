@@ -0,0 +1,89 @@
+use phaneron_plugin::FrameRate;
+
+use super::{timecode, BurnInPosition, BurnInState, Overlay, MARGIN, SCALE, WIDTH_ALIGNMENT};
+
+const PAL: FrameRate = FrameRate { num: 25, den: 1 };
+
+#[test]
+fn timecode_is_derived_from_frame_number() {
+    assert_eq!(timecode(0, PAL), "00:00:00:00");
+    assert_eq!(timecode(24, PAL), "00:00:00:24");
+    assert_eq!(timecode(25, PAL), "00:00:01:00");
+    assert_eq!(timecode(25 * 3661 + 7, PAL), "01:01:01:07");
+    assert_eq!(timecode(25 * 60 * 60 * 24, PAL), "00:00:00:00");
+}
+
+#[test]
+fn timecode_counts_fractional_rates_at_nominal_rate() {
+    let ntsc = FrameRate {
+        num: 30000,
+        den: 1001,
+    };
+    assert_eq!(timecode(29, ntsc), "00:00:00:29");
+    assert_eq!(timecode(30, ntsc), "00:00:01:00");
+    assert_eq!(timecode(60, FrameRate { num: 50, den: 1 }), "00:00:01:10");
+}
+
+#[test]
+fn lines_follow_state() {
+    let state = BurnInState {
+        show_timecode: true,
+        show_frame_number: false,
+        label: "Camera 1".to_string(),
+        ..Default::default()
+    };
+
+    assert_eq!(state.lines(50, PAL, None), vec!["Camera 1", "00:00:02:00"]);
+}
+
+#[test]
+fn source_timecode_is_shown_when_present() {
+    let state = BurnInState {
+        show_timecode: false,
+        show_frame_number: false,
+        show_source_timecode: true,
+        ..Default::default()
+    };
+
+    assert_eq!(
+        state.lines(50, PAL, Some("10:00:00:00")),
+        vec!["SRC 10:00:00:00"]
+    );
+    assert!(state.lines(50, PAL, None).is_empty());
+}
+
+#[test]
+fn overlay_is_aligned_for_loading() {
+    let overlay = Overlay::rasterise(&["#1".to_string()]);
+
+    assert_eq!(overlay.width % WIDTH_ALIGNMENT, 0);
+    assert_eq!(overlay.pixels.len(), overlay.width * overlay.height * 4);
+    // Outside of the text box is transparent
+    let last_pixel = overlay.pixels.len() - 4;
+    assert_eq!(overlay.pixels[last_pixel + 3], 0);
+}
+
+#[test]
+fn overlay_draws_glyphs() {
+    // The top row of '1' only has its middle column set
+    let overlay = Overlay::rasterise(&["1".to_string()]);
+    let pixel = |x: usize, y: usize| {
+        let offset = ((y * SCALE) * overlay.width + x * SCALE) * 4;
+        &overlay.pixels[offset..offset + 4]
+    };
+
+    assert_eq!(pixel(2 + 2, 2), &[255, 255, 255, 255]);
+    assert_eq!(pixel(2, 2)[..3], [0, 0, 0]);
+}
+
+#[test]
+fn position_keeps_overlay_inside_frame() {
+    assert_eq!(
+        BurnInPosition::TopLeft.origin(1920, 1080, 128, 64),
+        (MARGIN, MARGIN)
+    );
+    assert_eq!(
+        BurnInPosition::BottomRight.origin(1920, 1080, 128, 64),
+        (1920 - 128 - MARGIN, 1080 - 64 - MARGIN)
+    );
+}
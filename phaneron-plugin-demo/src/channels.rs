@@ -0,0 +1,213 @@
+use std::sync::Mutex;
+
+use abi_stable::{
+    sabi_trait::TD_Opaque,
+    std_types::{ROption, RString},
+};
+
+use phaneron_plugin::{
+    traits::Node_TO, types::AudioFrame, types::AudioOutput, types::Node, types::NodeContext,
+    types::ProcessFrameContext, types::ToAudioF32, AudioChannelLayout, AudioFormat, AudioInputId,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Splits a stereo frame into left and right mono channels.
+/// Mono audio is duplicated to both channels and silence gives two silent channels.
+fn split(buffers: &[&[f32]]) -> (Vec<f32>, Vec<f32>) {
+    match buffers {
+        [] => (vec![], vec![]),
+        [mono] => (mono.to_vec(), mono.to_vec()),
+        [left, right, ..] => (left.to_vec(), right.to_vec()),
+    }
+}
+
+/// Merges two mono channels into stereo, padding the shorter channel with silence.
+fn merge(left: &[f32], right: &[f32]) -> Vec<Vec<f32>> {
+    let samples = left.len().max(right.len());
+    [left, right]
+        .iter()
+        .map(|channel| {
+            let mut channel = channel.to_vec();
+            channel.resize(samples, 0.0);
+            channel
+        })
+        .collect()
+}
+
+/// Loads separate channel buffers as an audio frame.
+pub(crate) fn load_channels(to_audio_f32: &ToAudioF32, buffers: &[Vec<f32>]) -> AudioFrame {
+    let samples = buffers.iter().map(Vec::len).max().unwrap_or_default();
+    let mut interleaved = Vec::with_capacity(samples * buffers.len() * 4);
+    for index in 0..samples {
+        for buffer in buffers {
+            let sample = buffer.get(index).copied().unwrap_or_default();
+            interleaved.extend_from_slice(&sample.to_le_bytes());
+        }
+    }
+
+    let loaded_frame = to_audio_f32.load_frame(&interleaved.as_slice().into());
+    to_audio_f32.process_frame(loaded_frame)
+}
+
+/// The first channel of a frame, which is the whole of a mono frame.
+fn first_channel(frame: &AudioFrame) -> &[f32] {
+    frame
+        .buffers()
+        .first()
+        .map(|buffer| buffer.as_slice())
+        .unwrap_or_default()
+}
+
+pub struct ChannelSplitHandle {
+    node_id: String,
+}
+impl ChannelSplitHandle {
+    pub(super) fn new(node_id: String) -> Self {
+        Self { node_id }
+    }
+}
+impl phaneron_plugin::traits::NodeHandle for ChannelSplitHandle {
+    fn initialize(&self, context: NodeContext, _configuration: ROption<RString>) -> Node {
+        let node = ChannelSplit::new(self.node_id.clone(), context);
+
+        Node_TO::from_value(node, TD_Opaque)
+    }
+}
+
+/// Splits a stereo audio input into a left and a right mono output.
+pub struct ChannelSplit {
+    node_id: String,
+    context: NodeContext,
+    input: AudioInputId,
+    left_output: AudioOutput,
+    right_output: AudioOutput,
+    to_audio_f32: Mutex<Option<ToAudioF32>>,
+}
+
+impl ChannelSplit {
+    pub fn new(node_id: String, context: NodeContext) -> Self {
+        let input = context.add_audio_input();
+        let left_output = context.add_audio_output();
+        let right_output = context.add_audio_output();
+
+        Self {
+            node_id,
+            context,
+            input,
+            left_output,
+            right_output,
+            to_audio_f32: Default::default(),
+        }
+    }
+}
+
+impl phaneron_plugin::traits::Node for ChannelSplit {
+    fn apply_state(&self, _state: RString) -> bool {
+        false
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let input = frame_context
+            .get_audio_input(&self.input)
+            .unwrap_or(frame_context.get_silence_frame())
+            .frame
+            .clone();
+        let buffers: Vec<&[f32]> = input.buffers().iter().map(|b| b.as_slice()).collect();
+        let (left, right) = match input.channel_layout() {
+            AudioChannelLayout::R_L => {
+                let (right, left) = split(&buffers);
+                (left, right)
+            }
+            _ => split(&buffers),
+        };
+
+        let mut to_audio_f32 = self.to_audio_f32.lock().unwrap();
+        let to_audio_f32 = to_audio_f32.get_or_insert_with(|| {
+            self.context
+                .create_to_audio_f32(AudioFormat::F32, AudioChannelLayout::Mono)
+        });
+        let left = load_channels(to_audio_f32, &[left]);
+        let right = load_channels(to_audio_f32, &[right]);
+
+        let frame_context = frame_context.submit().unwrap();
+        self.left_output.push_frame(&frame_context, left).ok();
+        self.right_output.push_frame(&frame_context, right).ok();
+    }
+}
+
+pub struct ChannelMergeHandle {
+    node_id: String,
+}
+impl ChannelMergeHandle {
+    pub(super) fn new(node_id: String) -> Self {
+        Self { node_id }
+    }
+}
+impl phaneron_plugin::traits::NodeHandle for ChannelMergeHandle {
+    fn initialize(&self, context: NodeContext, _configuration: ROption<RString>) -> Node {
+        let node = ChannelMerge::new(self.node_id.clone(), context);
+
+        Node_TO::from_value(node, TD_Opaque)
+    }
+}
+
+/// Merges a left and a right mono audio input into a stereo output.
+/// A missing input is silent, and only the first channel of an input that isn't mono is used.
+pub struct ChannelMerge {
+    node_id: String,
+    context: NodeContext,
+    left_input: AudioInputId,
+    right_input: AudioInputId,
+    output: AudioOutput,
+    to_audio_f32: Mutex<Option<ToAudioF32>>,
+}
+
+impl ChannelMerge {
+    pub fn new(node_id: String, context: NodeContext) -> Self {
+        let left_input = context.add_audio_input();
+        let right_input = context.add_audio_input();
+        let output = context.add_audio_output();
+
+        Self {
+            node_id,
+            context,
+            left_input,
+            right_input,
+            output,
+            to_audio_f32: Default::default(),
+        }
+    }
+}
+
+impl phaneron_plugin::traits::Node for ChannelMerge {
+    fn apply_state(&self, _state: RString) -> bool {
+        false
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let silence = frame_context.get_silence_frame();
+        let left = frame_context
+            .get_audio_input(&self.left_input)
+            .unwrap_or(silence)
+            .frame
+            .clone();
+        let right = frame_context
+            .get_audio_input(&self.right_input)
+            .unwrap_or(silence)
+            .frame
+            .clone();
+        let stereo = merge(first_channel(&left), first_channel(&right));
+
+        let mut to_audio_f32 = self.to_audio_f32.lock().unwrap();
+        let to_audio_f32 = to_audio_f32.get_or_insert_with(|| {
+            self.context
+                .create_to_audio_f32(AudioFormat::F32, AudioChannelLayout::L_R)
+        });
+        let output = load_channels(to_audio_f32, &stereo);
+
+        let frame_context = frame_context.submit().unwrap();
+        self.output.push_frame(&frame_context, output).ok();
+    }
+}
@@ -0,0 +1,25 @@
+use super::{merge, split};
+
+#[test]
+fn split_then_merge_reconstructs_stereo() {
+    let stereo: [&[f32]; 2] = [&[0.1, 0.2, 0.3], &[-0.1, -0.2, -0.3]];
+
+    let (left, right) = split(&stereo);
+    let merged = merge(&left, &right);
+
+    assert_eq!(merged, vec![stereo[0].to_vec(), stereo[1].to_vec()]);
+}
+
+#[test]
+fn split_duplicates_mono() {
+    let mono: [&[f32]; 1] = [&[0.5, -0.5]];
+
+    assert_eq!(split(&mono), (vec![0.5, -0.5], vec![0.5, -0.5]));
+}
+
+#[test]
+fn merge_pads_missing_channel_with_silence() {
+    let merged = merge(&[0.5, 0.5], &[]);
+
+    assert_eq!(merged, vec![vec![0.5, 0.5], vec![0.0, 0.0]]);
+}
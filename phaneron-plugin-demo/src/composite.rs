@@ -0,0 +1,167 @@
+use std::sync::Mutex;
+
+use abi_stable::{
+    sabi_trait::TD_Opaque,
+    std_types::{ROption, RString},
+};
+use log::error;
+use phaneron_plugin::{
+    traits::Node_TO,
+    types::{Node, NodeContext, ProcessFrameContext, ProcessShader, VideoOutput},
+    ShaderParams, VideoInputId,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+mod tests;
+
+pub struct CompositeHandle {
+    node_id: String,
+}
+impl CompositeHandle {
+    pub(super) fn new(node_id: String) -> Self {
+        Self { node_id }
+    }
+}
+impl phaneron_plugin::traits::NodeHandle for CompositeHandle {
+    fn initialize(&self, context: NodeContext, _configuration: ROption<RString>) -> Node {
+        let node = Composite::new(self.node_id.clone(), context);
+
+        Node_TO::from_value(node, TD_Opaque)
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", default)]
+pub struct CompositeState {
+    /// Left edge of the foreground in the background, in pixels.
+    pub x: i32,
+    /// Top edge of the foreground in the background, in pixels.
+    pub y: i32,
+    /// Multiplies the alpha of the foreground, 0 hides it and 1 shows it as is.
+    pub opacity: f32,
+    /// Whether the colours of the foreground have already been multiplied by its alpha.
+    pub premultiplied: bool,
+}
+
+impl Default for CompositeState {
+    fn default() -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            opacity: 1.0,
+            premultiplied: false,
+        }
+    }
+}
+
+impl CompositeState {
+    /// Offset of the foreground for the given frame sizes, as `(x, y)`. The offset is limited so
+    /// the foreground can be partly or just entirely off screen but no further.
+    /// `None` if no part of the foreground is on screen.
+    fn offset(&self, foreground: (usize, usize), background: (usize, usize)) -> Option<(i32, i32)> {
+        let x = clamp_offset(self.x, foreground.0, background.0);
+        let y = clamp_offset(self.y, foreground.1, background.1);
+        let visible = x > -(foreground.0 as i32)
+            && x < background.0 as i32
+            && y > -(foreground.1 as i32)
+            && y < background.1 as i32;
+
+        visible.then_some((x, y))
+    }
+}
+
+fn clamp_offset(offset: i32, foreground: usize, background: usize) -> i32 {
+    let min = -i32::try_from(foreground).unwrap_or(i32::MAX);
+    let max = i32::try_from(background).unwrap_or(i32::MAX);
+    offset.clamp(min, max)
+}
+
+/// Places a foreground over a background at the foreground's own size, using the alpha of the
+/// foreground, e.g. for logos and lower thirds.
+pub struct Composite {
+    node_id: String,
+    state: Mutex<CompositeState>,
+    shader: ProcessShader,
+    background_input: VideoInputId,
+    foreground_input: VideoInputId,
+    video_output: VideoOutput,
+}
+
+impl Composite {
+    pub fn new(node_id: String, context: NodeContext) -> Self {
+        let background_input = context.add_video_input();
+        let foreground_input = context.add_video_input();
+        let video_output = context.add_video_output();
+        let kernel = include_str!("../shaders/composite.cl");
+        let shader = context
+            .create_process_shader(kernel.into(), "composite".into())
+            .unwrap();
+
+        Self {
+            node_id,
+            state: Default::default(),
+            shader,
+            background_input,
+            foreground_input,
+            video_output,
+        }
+    }
+}
+
+impl phaneron_plugin::traits::Node for Composite {
+    fn apply_state(&self, state: RString) -> bool {
+        let state: CompositeState = match serde_json::from_str(&state) {
+            Ok(state) => state,
+            Err(err) => {
+                error!("{}: Invalid state: {err}", self.node_id);
+                return false;
+            }
+        };
+        if !(0.0..=1.0).contains(&state.opacity) {
+            error!("{}: opacity must be between 0 and 1", self.node_id);
+            return false;
+        }
+
+        *self.state.lock().unwrap() = state;
+
+        true
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let state = self.state.lock().unwrap();
+
+        let background = frame_context
+            .get_video_input(&self.background_input)
+            .unwrap_or(frame_context.get_black_frame())
+            .frame
+            .clone();
+        let foreground = frame_context
+            .get_video_input(&self.foreground_input)
+            .into_option();
+
+        let (width, height) = (background.width(), background.height());
+        let offset = foreground.and_then(|foreground| {
+            let size = (foreground.frame.width(), foreground.frame.height());
+            state.offset(size, (width, height))
+        });
+        let output = match (foreground, offset) {
+            (Some(foreground), Some((x, y))) if state.opacity > 0.0 => {
+                let mut params = ShaderParams::default();
+                params.set_param_video_frame_input(background);
+                params.set_param_video_frame_input(foreground.frame.clone());
+                params.set_param_i32_input(x);
+                params.set_param_i32_input(y);
+                params.set_param_f32_input(state.opacity);
+                params.set_param_u32_input(state.premultiplied as u32);
+                params.set_param_video_frame_output(width, height);
+
+                self.shader.run(params, &[width, height])[0].clone()
+            }
+            _ => background,
+        };
+
+        let frame_context = frame_context.submit().unwrap();
+        self.video_output.push_frame(&frame_context, output).ok();
+    }
+}
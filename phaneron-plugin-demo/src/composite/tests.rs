@@ -0,0 +1,43 @@
+use super::CompositeState;
+
+fn state(x: i32, y: i32) -> CompositeState {
+    CompositeState {
+        x,
+        y,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn state_defaults_to_opaque_straight_alpha_at_origin() {
+    let state: CompositeState = serde_json::from_str("{}").unwrap();
+    assert_eq!(state, CompositeState::default());
+    assert_eq!(state.opacity, 1.0);
+    assert!(!state.premultiplied);
+}
+
+#[test]
+fn smaller_foreground_is_placed_at_offset() {
+    assert_eq!(
+        state(1600, 880).offset((200, 100), (1920, 1080)),
+        Some((1600, 880))
+    );
+}
+
+#[test]
+fn partially_off_screen_foreground_is_visible() {
+    assert_eq!(
+        state(-150, 1000).offset((200, 100), (1920, 1080)),
+        Some((-150, 1000))
+    );
+}
+
+#[test]
+fn fully_off_screen_foreground_is_not_visible() {
+    assert_eq!(state(-200, 0).offset((200, 100), (1920, 1080)), None);
+    assert_eq!(state(0, 1080).offset((200, 100), (1920, 1080)), None);
+    assert_eq!(
+        state(i32::MIN, i32::MAX).offset((200, 100), (1920, 1080)),
+        None
+    );
+}
@@ -29,7 +29,9 @@ pub struct DissolveCl {
 impl DissolveCl {
     fn new(context: &NodeContext, width: usize, height: usize) -> Self {
         let kernel = include_str!("../shaders/dissolve.cl");
-        let shader = context.create_process_shader(kernel.into(), "transition_dissolve".into());
+        let shader = context
+            .create_process_shader(kernel.into(), "transition_dissolve".into())
+            .unwrap();
 
         Self {
             width,
@@ -0,0 +1,197 @@
+use std::sync::Mutex;
+
+use abi_stable::{
+    sabi_trait::TD_Opaque,
+    std_types::{ROption, RString},
+};
+use log::error;
+use phaneron_plugin::{
+    traits::Node_TO,
+    types::{Node, NodeContext, ProcessFrameContext, ProcessShader, VideoOutput},
+    ShaderParams, VideoInputId,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+mod tests;
+
+pub struct FitHandle {
+    node_id: String,
+}
+impl FitHandle {
+    pub(super) fn new(node_id: String) -> Self {
+        Self { node_id }
+    }
+}
+impl phaneron_plugin::traits::NodeHandle for FitHandle {
+    fn initialize(&self, context: NodeContext, _configuration: ROption<RString>) -> Node {
+        let node = Fit::new(self.node_id.clone(), context);
+
+        Node_TO::from_value(node, TD_Opaque)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FitMode {
+    /// Scales the input to fit inside the output, filling the remaining area with the background.
+    #[default]
+    Contain,
+    /// Scales the input to cover the output, cropping the parts that fall outside of it.
+    Cover,
+    /// Scales the input to the size of the output, ignoring the aspect ratio.
+    Stretch,
+}
+
+impl FitMode {
+    /// Area of the output covered by an input of the given size.
+    fn viewport(
+        &self,
+        input_width: usize,
+        input_height: usize,
+        width: usize,
+        height: usize,
+    ) -> Viewport {
+        // Whether the input is wider than the output, compared exactly in integers
+        let wider = input_width * height > width * input_height;
+        let fill_width = match self {
+            Self::Contain => wider,
+            Self::Cover => !wider,
+            Self::Stretch => {
+                return Viewport {
+                    x: 0.0,
+                    y: 0.0,
+                    width: width as f32,
+                    height: height as f32,
+                }
+            }
+        };
+        let (viewport_width, viewport_height) = if fill_width {
+            (
+                width as f32,
+                (input_height * width) as f32 / input_width as f32,
+            )
+        } else {
+            (
+                (input_width * height) as f32 / input_height as f32,
+                height as f32,
+            )
+        };
+
+        Viewport {
+            x: (width as f32 - viewport_width) / 2.0,
+            y: (height as f32 - viewport_height) / 2.0,
+            width: viewport_width,
+            height: viewport_height,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Viewport {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct FitState {
+    /// Width of the output frame.
+    pub width: usize,
+    /// Height of the output frame.
+    pub height: usize,
+    #[serde(default)]
+    pub fit: FitMode,
+    /// RGBA colour of the areas of the output not covered by the input, in linear light.
+    #[serde(default = "default_background")]
+    pub background: [f32; 4],
+}
+
+fn default_background() -> [f32; 4] {
+    [0.0, 0.0, 0.0, 1.0]
+}
+
+pub struct Fit {
+    node_id: String,
+    /// Frames are passed through unchanged until a state is applied.
+    state: Mutex<Option<FitState>>,
+    shader: ProcessShader,
+    video_input: VideoInputId,
+    video_output: VideoOutput,
+}
+
+impl Fit {
+    pub fn new(node_id: String, context: NodeContext) -> Self {
+        let video_input = context.add_video_input();
+        let video_output = context.add_video_output();
+        let kernel = include_str!("../shaders/fit.cl");
+        let shader = context
+            .create_process_shader(kernel.into(), "fit".into())
+            .unwrap();
+
+        Self {
+            node_id,
+            state: Default::default(),
+            shader,
+            video_input,
+            video_output,
+        }
+    }
+}
+
+impl phaneron_plugin::traits::Node for Fit {
+    fn apply_state(&self, state: RString) -> bool {
+        let state: FitState = match serde_json::from_str(&state) {
+            Ok(state) => state,
+            Err(err) => {
+                error!("{}: Invalid state: {err}", self.node_id);
+                return false;
+            }
+        };
+        if state.width == 0 || state.height == 0 {
+            error!("{}: width and height must be at least 1", self.node_id);
+            return false;
+        }
+
+        self.state.lock().unwrap().replace(state);
+
+        true
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let state = self.state.lock().unwrap();
+
+        let input = frame_context
+            .get_video_input(&self.video_input)
+            .unwrap_or(frame_context.get_black_frame())
+            .frame
+            .clone();
+
+        let output = match &*state {
+            None => input,
+            Some(state) => {
+                let viewport =
+                    state
+                        .fit
+                        .viewport(input.width(), input.height(), state.width, state.height);
+                let mut params = ShaderParams::default();
+                params.set_param_video_frame_input(input);
+                params.set_param_f32_input(viewport.x);
+                params.set_param_f32_input(viewport.y);
+                params.set_param_f32_input(viewport.width);
+                params.set_param_f32_input(viewport.height);
+                for component in state.background {
+                    params.set_param_f32_input(component);
+                }
+                params.set_param_video_frame_output(state.width, state.height);
+
+                self.shader.run(params, &[state.width, state.height])[0].clone()
+            }
+        };
+
+        let frame_context = frame_context.submit().unwrap();
+        self.video_output.push_frame(&frame_context, output).ok();
+    }
+}
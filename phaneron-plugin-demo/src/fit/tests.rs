@@ -0,0 +1,66 @@
+use super::{FitMode, FitState, Viewport};
+
+#[test]
+fn contain_pillarboxes_square_input_symmetrically() {
+    let viewport = FitMode::Contain.viewport(1080, 1080, 1920, 1080);
+
+    let left_bar = viewport.x;
+    let right_bar = 1920.0 - (viewport.x + viewport.width);
+    assert_eq!(left_bar, 420.0);
+    assert_eq!(right_bar, left_bar);
+    assert_eq!(viewport.y, 0.0);
+    assert_eq!(viewport.height, 1080.0);
+}
+
+#[test]
+fn contain_letterboxes_wide_input() {
+    let viewport = FitMode::Contain.viewport(1920, 1080, 1440, 1080);
+
+    assert_eq!(
+        viewport,
+        Viewport {
+            x: 0.0,
+            y: 135.0,
+            width: 1440.0,
+            height: 810.0,
+        }
+    );
+}
+
+#[test]
+fn cover_crops_square_input() {
+    let viewport = FitMode::Cover.viewport(1080, 1080, 1920, 1080);
+
+    assert_eq!(
+        viewport,
+        Viewport {
+            x: 0.0,
+            y: -420.0,
+            width: 1920.0,
+            height: 1920.0,
+        }
+    );
+}
+
+#[test]
+fn stretch_fills_output() {
+    let viewport = FitMode::Stretch.viewport(1080, 1080, 1920, 1080);
+
+    assert_eq!(
+        viewport,
+        Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: 1920.0,
+            height: 1080.0,
+        }
+    );
+}
+
+#[test]
+fn state_defaults_to_contain_on_black() {
+    let state: FitState = serde_json::from_str(r#"{"width":1920,"height":1080}"#).unwrap();
+
+    assert_eq!(state.fit, FitMode::Contain);
+    assert_eq!(state.background, [0.0, 0.0, 0.0, 1.0]);
+}
@@ -0,0 +1,209 @@
+use std::sync::Mutex;
+
+use abi_stable::{
+    sabi_trait::TD_Opaque,
+    std_types::{ROption, RString},
+};
+use log::error;
+use phaneron_plugin::{
+    traits::Node_TO,
+    types::{Node, NodeContext, ProcessFrameContext, VideoFrame, VideoOutput},
+    VideoInputId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::dissolve::Dissolve;
+
+#[cfg(test)]
+mod tests;
+
+pub struct FpsConvertHandle {
+    node_id: String,
+}
+impl FpsConvertHandle {
+    pub(super) fn new(node_id: String) -> Self {
+        Self { node_id }
+    }
+}
+impl phaneron_plugin::traits::NodeHandle for FpsConvertHandle {
+    fn initialize(&self, context: NodeContext, _configuration: ROption<RString>) -> Node {
+        let node = FpsConvert::new(self.node_id.clone(), context);
+
+        Node_TO::from_value(node, TD_Opaque)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameRate {
+    pub num: u32,
+    pub den: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FpsConvertMode {
+    /// Repeats or drops frames.
+    #[default]
+    Nearest,
+    /// Blends the two input frames either side of each output frame.
+    Blend,
+}
+
+/// Frames don't carry timestamps, so the rate of the input is declared alongside the target rate.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct FpsConvertState {
+    pub source_fps: FrameRate,
+    pub target_fps: FrameRate,
+    #[serde(default)]
+    pub mode: FpsConvertMode,
+}
+
+pub struct FpsConvert {
+    node_id: String,
+    context: NodeContext,
+    mode: Mutex<FpsConvertMode>,
+    converter: Mutex<RateConverter>,
+    previous_frame: Mutex<Option<VideoFrame>>,
+    blend: Mutex<Option<(usize, usize, Dissolve)>>,
+    video_input: VideoInputId,
+    video_output: VideoOutput,
+}
+
+impl FpsConvert {
+    pub fn new(node_id: String, context: NodeContext) -> Self {
+        let video_input = context.add_video_input();
+        let video_output = context.add_video_output();
+        let rate = FrameRate { num: 25, den: 1 };
+
+        Self {
+            node_id,
+            context,
+            mode: Default::default(),
+            converter: Mutex::new(RateConverter::new(rate, rate)),
+            previous_frame: Default::default(),
+            blend: Default::default(),
+            video_input,
+            video_output,
+        }
+    }
+}
+
+impl phaneron_plugin::traits::Node for FpsConvert {
+    fn apply_state(&self, state: RString) -> bool {
+        let state: FpsConvertState = match serde_json::from_str(&state) {
+            Ok(state) => state,
+            Err(err) => {
+                error!("{}: Invalid state: {err}", self.node_id);
+                return false;
+            }
+        };
+        let rates = [state.source_fps, state.target_fps];
+        if rates.iter().any(|rate| rate.num == 0 || rate.den == 0) {
+            error!("{}: Frame rates must be non-zero", self.node_id);
+            return false;
+        }
+
+        let mut converter = self.converter.lock().unwrap();
+        if converter.source_fps != state.source_fps || converter.target_fps != state.target_fps {
+            *converter = RateConverter::new(state.source_fps, state.target_fps);
+            // The previous frame is kept so output continues without a gap
+            converter.push_input();
+        }
+        *self.mode.lock().unwrap() = state.mode;
+
+        true
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let mode = *self.mode.lock().unwrap();
+        let mut previous_frame = self.previous_frame.lock().unwrap();
+
+        let input = frame_context
+            .get_video_input(&self.video_input)
+            .unwrap_or(frame_context.get_black_frame())
+            .frame
+            .clone();
+        let positions = self.converter.lock().unwrap().push_input();
+        let previous = previous_frame
+            .replace(input.clone())
+            .unwrap_or(input.clone());
+
+        let mut outputs = Vec::with_capacity(positions.len());
+        for position in positions {
+            let output = match mode {
+                FpsConvertMode::Nearest if use_current_frame(position) => input.clone(),
+                FpsConvertMode::Nearest => previous.clone(),
+                FpsConvertMode::Blend if position == 0.0 => previous.clone(),
+                FpsConvertMode::Blend => {
+                    let (width, height) = (input.width(), input.height());
+                    let mut blend = self.blend.lock().unwrap();
+                    if !matches!(&*blend, Some((w, h, _)) if *w == width && *h == height) {
+                        *blend = Some((width, height, Dissolve::new(&self.context, width, height)));
+                    }
+                    let (_, _, dissolve) = blend.as_mut().unwrap();
+                    dissolve.run(&previous, &input, 1.0 - position).remove(0)
+                }
+            };
+            outputs.push(output);
+        }
+
+        // Frames are pushed one at a time as downstream consumes them, so a higher target rate
+        // produces several frames per input frame and a lower one produces none for some input frames
+        let frame_context = frame_context.submit().unwrap();
+        for output in outputs {
+            self.video_output.push_frame(&frame_context, output).ok();
+        }
+    }
+}
+
+/// Works out where output frames fall relative to input frames using exact ratios so that
+/// long-running conversions don't drift.
+struct RateConverter {
+    source_fps: FrameRate,
+    target_fps: FrameRate,
+    /// Length of an output frame in input frames is `ratio_num / ratio_den`.
+    ratio_num: u128,
+    ratio_den: u128,
+    inputs: u64,
+    next_output: u64,
+}
+
+impl RateConverter {
+    fn new(source_fps: FrameRate, target_fps: FrameRate) -> Self {
+        Self {
+            source_fps,
+            target_fps,
+            ratio_num: source_fps.num as u128 * target_fps.den as u128,
+            ratio_den: source_fps.den as u128 * target_fps.num as u128,
+            inputs: 0,
+            next_output: 0,
+        }
+    }
+
+    /// Called for each input frame. Returns the position of each output frame that falls between the
+    /// previous input frame and this one, from 0 at the previous frame towards 1 at this frame.
+    /// Output is one input frame behind as the following input frame is needed to place output frames.
+    fn push_input(&mut self) -> Vec<f32> {
+        let input = self.inputs;
+        self.inputs += 1;
+
+        let mut positions = vec![];
+        if input == 0 {
+            return positions;
+        }
+        loop {
+            let position = self.next_output as u128 * self.ratio_num;
+            if position / self.ratio_den >= input as u128 {
+                return positions;
+            }
+            positions.push((position % self.ratio_den) as f32 / self.ratio_den as f32);
+            self.next_output += 1;
+        }
+    }
+}
+
+/// Output frames exactly half way between two input frames repeat the earlier frame.
+fn use_current_frame(position: f32) -> bool {
+    position > 0.5
+}
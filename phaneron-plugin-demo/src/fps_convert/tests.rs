@@ -0,0 +1,58 @@
+use super::{use_current_frame, FrameRate, RateConverter};
+
+/// Converts `inputs` frames using nearest selection and returns the input frame used for each output frame.
+fn convert_nearest(source_fps: FrameRate, target_fps: FrameRate, inputs: usize) -> Vec<usize> {
+    let mut converter = RateConverter::new(source_fps, target_fps);
+    let mut outputs = vec![];
+    for input in 0..inputs {
+        for position in converter.push_input() {
+            outputs.push(if use_current_frame(position) {
+                input
+            } else {
+                input - 1
+            });
+        }
+    }
+
+    outputs
+}
+
+const FPS_25: FrameRate = FrameRate { num: 25, den: 1 };
+const FPS_50: FrameRate = FrameRate { num: 50, den: 1 };
+
+#[test]
+fn doubling_rate_duplicates_each_frame() {
+    assert_eq!(
+        convert_nearest(FPS_25, FPS_50, 5),
+        vec![0, 0, 1, 1, 2, 2, 3, 3]
+    );
+}
+
+#[test]
+fn halving_rate_drops_every_other_frame() {
+    assert_eq!(convert_nearest(FPS_50, FPS_25, 7), vec![0, 2, 4]);
+}
+
+#[test]
+fn same_rate_passes_frames_through() {
+    assert_eq!(convert_nearest(FPS_25, FPS_25, 4), vec![0, 1, 2]);
+}
+
+#[test]
+fn ntsc_rate_does_not_drift() {
+    let ntsc = FrameRate {
+        num: 30000,
+        den: 1001,
+    };
+    let mut converter = RateConverter::new(FPS_25, ntsc);
+    let outputs: usize = (0..=25 * 1001).map(|_| converter.push_input().len()).sum();
+    // 1001 seconds of 25fps input is exactly 30000 frames at 29.97fps
+    assert_eq!(outputs, 30000);
+}
+
+#[test]
+fn blend_positions_are_fractions_of_input_frame() {
+    let mut converter = RateConverter::new(FPS_25, FrameRate { num: 100, den: 1 });
+    converter.push_input();
+    assert_eq!(converter.push_input(), vec![0.0, 0.25, 0.5, 0.75]);
+}
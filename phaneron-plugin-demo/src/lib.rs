@@ -9,7 +9,7 @@ use abi_stable::{
     },
 };
 use phaneron_plugin::{
-    traits::{CreateNodeDescription, PhaneronPlugin_TO},
+    traits::{BuildInfo, CreateNodeDescription, PhaneronPlugin_TO},
     traits::{NodeHandle_TO, PluginNodeDescription},
     types::NodeHandle,
     types::PhaneronPlugin,
@@ -17,10 +17,29 @@ use phaneron_plugin::{
 };
 
 use self::{
-    traditional_mixer_emulator::TraditionalMixerEmulatorHandle, turbo_consumer::TurboConsumerHandle,
+    burn_in::BurnInHandle,
+    channels::{ChannelMergeHandle, ChannelSplitHandle},
+    composite::CompositeHandle,
+    fit::FitHandle,
+    fps_convert::FpsConvertHandle,
+    pip::PipHandle,
+    resample::ResampleHandle,
+    temporal_blend::TemporalBlendHandle,
+    test_pattern::TestPatternHandle,
+    traditional_mixer_emulator::TraditionalMixerEmulatorHandle,
+    turbo_consumer::TurboConsumerHandle,
 };
 
+mod burn_in;
+mod channels;
+mod composite;
 mod dissolve;
+mod fit;
+mod fps_convert;
+mod pip;
+mod resample;
+mod temporal_blend;
+mod test_pattern;
 mod traditional_mixer_emulator;
 mod turbo_consumer;
 
@@ -51,6 +70,46 @@ impl phaneron_plugin::traits::PhaneronPlugin for DemoPlugin {
                 id: "turbo_consumer".into(),
                 name: "Turbo Consumer".into(),
             },
+            PluginNodeDescription {
+                id: "temporal_blend".into(),
+                name: "Temporal Blend".into(),
+            },
+            PluginNodeDescription {
+                id: "burn_in".into(),
+                name: "Burn In".into(),
+            },
+            PluginNodeDescription {
+                id: "fps_convert".into(),
+                name: "Frame Rate Converter".into(),
+            },
+            PluginNodeDescription {
+                id: "fit".into(),
+                name: "Fit".into(),
+            },
+            PluginNodeDescription {
+                id: "pip".into(),
+                name: "Picture in Picture".into(),
+            },
+            PluginNodeDescription {
+                id: "channel_split".into(),
+                name: "Channel Split".into(),
+            },
+            PluginNodeDescription {
+                id: "channel_merge".into(),
+                name: "Channel Merge".into(),
+            },
+            PluginNodeDescription {
+                id: "resample".into(),
+                name: "Resample".into(),
+            },
+            PluginNodeDescription {
+                id: "test_pattern".into(),
+                name: "Test Pattern".into(),
+            },
+            PluginNodeDescription {
+                id: "composite".into(),
+                name: "Composite".into(),
+            },
         ]
         .into()
     }
@@ -67,11 +126,75 @@ impl phaneron_plugin::traits::PhaneronPlugin for DemoPlugin {
 
                 ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
             }
+            "temporal_blend" => {
+                let handle = TemporalBlendHandle::new(description.node_id.to_string());
+
+                ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+            }
+            "burn_in" => {
+                let handle = BurnInHandle::new(description.node_id.to_string());
+
+                ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+            }
+            "fps_convert" => {
+                let handle = FpsConvertHandle::new(description.node_id.to_string());
+
+                ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+            }
+            "fit" => {
+                let handle = FitHandle::new(description.node_id.to_string());
+
+                ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+            }
+            "pip" => {
+                let handle = PipHandle::new(description.node_id.to_string());
+
+                ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+            }
+            "channel_split" => {
+                let handle = ChannelSplitHandle::new(description.node_id.to_string());
+
+                ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+            }
+            "channel_merge" => {
+                let handle = ChannelMergeHandle::new(description.node_id.to_string());
+
+                ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+            }
+            "resample" => {
+                let handle = ResampleHandle::new(description.node_id.to_string());
+
+                ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+            }
+            "test_pattern" => {
+                let handle = TestPatternHandle::new(description.node_id.to_string());
+
+                ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+            }
+            "composite" => {
+                let handle = CompositeHandle::new(description.node_id.to_string());
+
+                ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+            }
             _ => RErr(format!("Unknown node type: {}", description.node_type).into()),
         }
     }
 
-    fn destroy_node(&self, node_id: RString) -> RResult<(), RString> {
-        todo!()
+    fn destroy_node(&self, _node_id: RString) -> RResult<(), RString> {
+        // Nodes don't share any resources with the plugin, they are released when the node is dropped
+        ROk(())
+    }
+
+    fn build_info(&self) -> BuildInfo {
+        phaneron_plugin::build_info!()
+    }
+
+    fn get_node_state_schema(&self, node_type: RString) -> RResult<RString, RString> {
+        match node_type.as_str() {
+            "traditional_mixer_emulator" => ROk(traditional_mixer_emulator::state_schema()
+                .to_string()
+                .into()),
+            _ => RErr(format!("Node type {node_type} does not provide a state schema").into()),
+        }
     }
 }
@@ -0,0 +1,198 @@
+use std::sync::Mutex;
+
+use abi_stable::{
+    sabi_trait::TD_Opaque,
+    std_types::{ROption, RString},
+};
+use log::error;
+use phaneron_plugin::{
+    traits::Node_TO,
+    types::{Node, NodeContext, ProcessFrameContext, ProcessShader, VideoOutput},
+    ShaderParams, VideoInputId,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+mod tests;
+
+pub struct PipHandle {
+    node_id: String,
+}
+impl PipHandle {
+    pub(super) fn new(node_id: String) -> Self {
+        Self { node_id }
+    }
+}
+impl phaneron_plugin::traits::NodeHandle for PipHandle {
+    fn initialize(&self, context: NodeContext, _configuration: ROption<RString>) -> Node {
+        let node = Pip::new(self.node_id.clone(), context);
+
+        Node_TO::from_value(node, TD_Opaque)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct PipState {
+    /// Left edge of the window, as a fraction of the background width.
+    pub x: f32,
+    /// Top edge of the window, as a fraction of the background height.
+    pub y: f32,
+    /// Width of the window, as a fraction of the background width.
+    pub width: f32,
+    /// Height of the window, as a fraction of the background height.
+    pub height: f32,
+    /// Width of the border drawn around the outside of the window, in output pixels.
+    #[serde(default)]
+    pub border_width: f32,
+    /// RGBA colour of the border, in linear light.
+    #[serde(default = "default_border_color")]
+    pub border_color: [f32; 4],
+}
+
+fn default_border_color() -> [f32; 4] {
+    [1.0, 1.0, 1.0, 1.0]
+}
+
+impl PipState {
+    /// Window in output pixels for a background of the given size.
+    fn window(&self, width: usize, height: usize) -> Window {
+        Window {
+            x: self.x * width as f32,
+            y: self.y * height as f32,
+            width: self.width * width as f32,
+            height: self.height * height as f32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Window {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+impl Window {
+    /// Pixels of a `width` x `height` output whose centres fall inside the window grown by `border`,
+    /// as `(x, y, width, height)`. `None` if nothing of the window is on screen.
+    fn visible_region(
+        &self,
+        border: f32,
+        width: usize,
+        height: usize,
+    ) -> Option<(usize, usize, usize, usize)> {
+        let left = (self.x - border - 0.5).ceil().max(0.0);
+        let top = (self.y - border - 0.5).ceil().max(0.0);
+        let right = (self.x + self.width + border - 0.5)
+            .ceil()
+            .min(width as f32);
+        let bottom = (self.y + self.height + border - 0.5)
+            .ceil()
+            .min(height as f32);
+        if right <= left || bottom <= top {
+            return None;
+        }
+
+        Some((
+            left as usize,
+            top as usize,
+            (right - left) as usize,
+            (bottom - top) as usize,
+        ))
+    }
+}
+
+pub struct Pip {
+    node_id: String,
+    /// The background is passed through unchanged until a state is applied.
+    state: Mutex<Option<PipState>>,
+    shader: ProcessShader,
+    background_input: VideoInputId,
+    inset_input: VideoInputId,
+    video_output: VideoOutput,
+}
+
+impl Pip {
+    pub fn new(node_id: String, context: NodeContext) -> Self {
+        let background_input = context.add_video_input();
+        let inset_input = context.add_video_input();
+        let video_output = context.add_video_output();
+        let kernel = include_str!("../shaders/pip.cl");
+        let shader = context
+            .create_process_shader(kernel.into(), "pip".into())
+            .unwrap();
+
+        Self {
+            node_id,
+            state: Default::default(),
+            shader,
+            background_input,
+            inset_input,
+            video_output,
+        }
+    }
+}
+
+impl phaneron_plugin::traits::Node for Pip {
+    fn apply_state(&self, state: RString) -> bool {
+        let state: PipState = match serde_json::from_str(&state) {
+            Ok(state) => state,
+            Err(err) => {
+                error!("{}: Invalid state: {err}", self.node_id);
+                return false;
+            }
+        };
+        if state.width <= 0.0 || state.height <= 0.0 || state.border_width < 0.0 {
+            error!(
+                "{}: width and height must be positive and border_width must not be negative",
+                self.node_id
+            );
+            return false;
+        }
+
+        self.state.lock().unwrap().replace(state);
+
+        true
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let state = self.state.lock().unwrap();
+
+        let background = frame_context
+            .get_video_input(&self.background_input)
+            .unwrap_or(frame_context.get_black_frame())
+            .frame
+            .clone();
+        let inset = frame_context
+            .get_video_input(&self.inset_input)
+            .into_option();
+
+        let (width, height) = (background.width(), background.height());
+        let output = match (&*state, inset) {
+            (Some(state), Some(inset)) => {
+                let window = state.window(width, height);
+                match window.visible_region(state.border_width, width, height) {
+                    None => background,
+                    Some(_) => {
+                        let mut params = ShaderParams::default();
+                        params.set_param_video_frame_input(background);
+                        params.set_param_video_frame_input(inset.frame.clone());
+                        params.set_param_vec2_input([window.x, window.y]);
+                        params.set_param_vec2_input([window.width, window.height]);
+                        params.set_param_f32_input(state.border_width);
+                        params.set_param_vec4_input(state.border_color);
+                        params.set_param_video_frame_output(width, height);
+
+                        self.shader.run(params, &[width, height])[0].clone()
+                    }
+                }
+            }
+            _ => background,
+        };
+
+        let frame_context = frame_context.submit().unwrap();
+        self.video_output.push_frame(&frame_context, output).ok();
+    }
+}
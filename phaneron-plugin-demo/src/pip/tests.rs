@@ -0,0 +1,68 @@
+use super::{PipState, Window};
+
+fn state(x: f32, y: f32, width: f32, height: f32, border_width: f32) -> PipState {
+    PipState {
+        x,
+        y,
+        width,
+        height,
+        border_width,
+        border_color: [1.0, 1.0, 1.0, 1.0],
+    }
+}
+
+#[test]
+fn inset_appears_in_bottom_right_quarter() {
+    let state = state(0.5, 0.5, 0.5, 0.5, 0.0);
+    let window = state.window(1920, 1080);
+
+    assert_eq!(
+        window,
+        Window {
+            x: 960.0,
+            y: 540.0,
+            width: 960.0,
+            height: 540.0,
+        }
+    );
+    assert_eq!(
+        window.visible_region(0.0, 1920, 1080),
+        Some((960, 540, 960, 540))
+    );
+}
+
+#[test]
+fn border_grows_region_outside_window() {
+    let window = state(0.25, 0.25, 0.5, 0.5, 4.0).window(1920, 1080);
+
+    assert_eq!(
+        window.visible_region(4.0, 1920, 1080),
+        Some((476, 266, 968, 548))
+    );
+}
+
+#[test]
+fn partially_off_screen_window_is_clipped() {
+    let window = state(0.9, -0.1, 0.2, 0.2, 0.0).window(1000, 1000);
+
+    assert_eq!(
+        window.visible_region(0.0, 1000, 1000),
+        Some((900, 0, 100, 100))
+    );
+}
+
+#[test]
+fn fully_off_screen_window_is_not_visible() {
+    let window = state(1.5, 0.0, 0.25, 0.25, 2.0).window(1920, 1080);
+
+    assert_eq!(window.visible_region(2.0, 1920, 1080), None);
+}
+
+#[test]
+fn border_defaults_to_none() {
+    let state: PipState =
+        serde_json::from_str(r#"{"x":0.7,"y":0.7,"width":0.25,"height":0.25}"#).unwrap();
+
+    assert_eq!(state.border_width, 0.0);
+    assert_eq!(state.border_color, [1.0, 1.0, 1.0, 1.0]);
+}
@@ -0,0 +1,237 @@
+use std::sync::Mutex;
+
+use abi_stable::{
+    sabi_trait::TD_Opaque,
+    std_types::{ROption, RString},
+};
+use log::error;
+use phaneron_plugin::{
+    traits::Node_TO,
+    types::{AudioOutput, Node, NodeContext, ProcessFrameContext, ToAudioF32},
+    AudioChannelLayout, AudioFormat, AudioInputId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::channels::load_channels;
+
+#[cfg(test)]
+mod tests;
+
+/// Resampled audio held beyond this many output frames is dropped. This only happens when the
+/// declared source rate doesn't match the rate the input actually arrives at.
+const MAX_PENDING_FRAMES: usize = 4;
+
+pub struct ResampleHandle {
+    node_id: String,
+}
+impl ResampleHandle {
+    pub(super) fn new(node_id: String) -> Self {
+        Self { node_id }
+    }
+}
+impl phaneron_plugin::traits::NodeHandle for ResampleHandle {
+    fn initialize(&self, context: NodeContext, _configuration: ROption<RString>) -> Node {
+        let node = Resample::new(self.node_id.clone(), context);
+
+        Node_TO::from_value(node, TD_Opaque)
+    }
+}
+
+/// Audio frames don't carry their sample rate, so the rate of the input is declared alongside the
+/// target rate, e.g. `{ "source_rate": 44100, "target_rate": 48000 }`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ResampleState {
+    pub source_rate: u32,
+    pub target_rate: u32,
+}
+
+/// Converts audio between sample rates with linear interpolation.
+/// Each output frame is as long as a frame of the graph, the first frames are delayed with
+/// silence until enough audio has been resampled.
+pub struct Resample {
+    node_id: String,
+    context: NodeContext,
+    input: AudioInputId,
+    output: AudioOutput,
+    resampler: Mutex<Resampler>,
+    to_audio_f32: Mutex<Option<(AudioChannelLayout, ToAudioF32)>>,
+}
+
+impl Resample {
+    pub fn new(node_id: String, context: NodeContext) -> Self {
+        let input = context.add_audio_input();
+        let output = context.add_audio_output();
+
+        Self {
+            node_id,
+            context,
+            input,
+            output,
+            resampler: Mutex::new(Resampler::new(48000, 48000)),
+            to_audio_f32: Default::default(),
+        }
+    }
+}
+
+impl phaneron_plugin::traits::Node for Resample {
+    fn apply_state(&self, state: RString) -> bool {
+        let state: ResampleState = match serde_json::from_str(&state) {
+            Ok(state) => state,
+            Err(err) => {
+                error!("{}: Invalid state: {err}", self.node_id);
+                return false;
+            }
+        };
+        if state.source_rate == 0 || state.target_rate == 0 {
+            error!("{}: Sample rates must be non-zero", self.node_id);
+            return false;
+        }
+
+        self.resampler
+            .lock()
+            .unwrap()
+            .set_rates(state.source_rate, state.target_rate);
+
+        true
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let silence = frame_context.get_silence_frame();
+        // The graph's silence frame is as long as a frame of the graph
+        let frame_len = silence
+            .frame
+            .buffers()
+            .first()
+            .map_or(0, |buffer| buffer.len());
+        let input = frame_context
+            .get_audio_input(&self.input)
+            .unwrap_or(silence)
+            .frame
+            .clone();
+        let buffers: Vec<&[f32]> = input.buffers().iter().map(|b| b.as_slice()).collect();
+        let resampled = self.resampler.lock().unwrap().process(&buffers, frame_len);
+
+        let channel_layout = input.channel_layout();
+        let mut to_audio_f32 = self.to_audio_f32.lock().unwrap();
+        if !matches!(&*to_audio_f32, Some((layout, _)) if *layout == channel_layout) {
+            let created = self
+                .context
+                .create_to_audio_f32(AudioFormat::F32, channel_layout);
+            *to_audio_f32 = Some((channel_layout, created));
+        }
+        let (_, to_audio_f32) = to_audio_f32.as_ref().unwrap();
+        let output = load_channels(to_audio_f32, &resampled);
+
+        let frame_context = frame_context.submit().unwrap();
+        self.output.push_frame(&frame_context, output).ok();
+    }
+}
+
+/// Resamples every channel of the input and cuts the result into frames, keeping what is left
+/// over for the next frame so that no samples are lost when frames don't divide evenly.
+struct Resampler {
+    source_rate: u32,
+    target_rate: u32,
+    channels: Vec<ChannelResampler>,
+    /// Resampled samples of each channel that have not been output yet.
+    pending: Vec<Vec<f32>>,
+}
+
+impl Resampler {
+    fn new(source_rate: u32, target_rate: u32) -> Self {
+        Self {
+            source_rate,
+            target_rate,
+            channels: vec![],
+            pending: vec![],
+        }
+    }
+
+    /// Changes the rates, audio that has already been resampled is still output.
+    fn set_rates(&mut self, source_rate: u32, target_rate: u32) {
+        if self.source_rate != source_rate || self.target_rate != target_rate {
+            self.source_rate = source_rate;
+            self.target_rate = target_rate;
+            self.channels.clear();
+        }
+    }
+
+    /// Resamples one buffer per channel and returns `frame_len` samples per channel.
+    fn process(&mut self, input: &[&[f32]], frame_len: usize) -> Vec<Vec<f32>> {
+        if self.pending.len() != input.len() {
+            // The number of channels changed, what is pending no longer lines up with the input
+            self.pending = vec![vec![]; input.len()];
+            self.channels.clear();
+        }
+        if self.channels.len() != input.len() {
+            self.channels = vec![ChannelResampler::new(self.target_rate); input.len()];
+        }
+
+        let (source_rate, target_rate) = (self.source_rate, self.target_rate);
+        let max_pending = frame_len * MAX_PENDING_FRAMES;
+        let channels = self.channels.iter_mut().zip(self.pending.iter_mut());
+        channels
+            .zip(input)
+            .map(|((channel, pending), input)| {
+                if source_rate == target_rate {
+                    pending.extend_from_slice(input);
+                } else {
+                    channel.push(source_rate, target_rate, input, pending);
+                }
+                if pending.len() > max_pending {
+                    pending.drain(..pending.len() - max_pending);
+                }
+
+                // Silence is output ahead of the audio until enough has been resampled
+                let available = pending.len().min(frame_len);
+                let mut output = vec![0.0; frame_len - available];
+                output.extend(pending.drain(..available));
+                output
+            })
+            .collect()
+    }
+}
+
+/// Linearly interpolates one channel. The position of the next output sample is kept as an exact
+/// fraction between buffers so that non-integer ratios neither drop nor repeat samples.
+#[derive(Debug, Clone, Copy)]
+struct ChannelResampler {
+    /// Position of the next output sample in `1 / target_rate` input samples, counted from the
+    /// last sample of the previous buffer.
+    next: u64,
+    /// Last sample of the previous buffer.
+    previous: f32,
+}
+
+impl ChannelResampler {
+    fn new(target_rate: u32) -> Self {
+        // Output starts at the first input sample
+        Self {
+            next: target_rate as u64,
+            previous: 0.0,
+        }
+    }
+
+    fn push(&mut self, source_rate: u32, target_rate: u32, input: &[f32], output: &mut Vec<f32>) {
+        let (source_rate, target_rate) = (source_rate as u64, target_rate as u64);
+        let previous = self.previous;
+        let sample = |index: usize| match index {
+            0 => previous,
+            index => input[index - 1],
+        };
+
+        while self.next / target_rate < input.len() as u64 {
+            let index = (self.next / target_rate) as usize;
+            let fraction = (self.next % target_rate) as f32 / target_rate as f32;
+            let (from, to) = (sample(index), sample(index + 1));
+            output.push(from + (to - from) * fraction);
+            self.next += source_rate;
+        }
+
+        self.next -= input.len() as u64 * target_rate;
+        if let Some(last) = input.last() {
+            self.previous = *last;
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use super::{ChannelResampler, Resampler};
+
+/// Resamples a ramp of `len` samples split into buffers of `chunks` sizes.
+fn resample_ramp(source_rate: u32, target_rate: u32, len: usize, chunks: &[usize]) -> Vec<f32> {
+    let ramp: Vec<f32> = (0..len).map(|sample| sample as f32).collect();
+    let mut resampler = ChannelResampler::new(target_rate);
+    let mut output = vec![];
+    let mut start = 0;
+    for chunk in chunks.iter().cycle() {
+        if start >= len {
+            break;
+        }
+        let end = (start + chunk).min(len);
+        resampler.push(source_rate, target_rate, &ramp[start..end], &mut output);
+        start = end;
+    }
+
+    output
+}
+
+#[test]
+fn buffer_boundaries_do_not_drop_samples() {
+    // Linear interpolation of a ramp is exact, so any dropped or repeated sample shows
+    let step = 44100.0 / 48000.0;
+    let whole = resample_ramp(44100, 48000, 4410, &[4410]);
+    let chunked = resample_ramp(44100, 48000, 4410, &[100, 37, 1, 500]);
+
+    assert_eq!(whole.len(), 4799);
+    assert_eq!(chunked, whole);
+    for (index, sample) in whole.iter().enumerate() {
+        assert!(
+            (sample - index as f32 * step).abs() < 1e-2,
+            "sample {index}"
+        );
+    }
+}
+
+#[test]
+fn downsampling_keeps_the_ratio() {
+    let output = resample_ramp(96000, 48000, 9600, &[1920, 7]);
+
+    assert_eq!(output.len(), 4800);
+    assert!(output
+        .iter()
+        .enumerate()
+        .all(|(index, sample)| *sample == (index * 2) as f32));
+}
+
+#[test]
+fn frames_are_graph_sized_and_continuous() {
+    // 44.1 kHz frames at 25 fps into a 48 kHz graph
+    let mut resampler = Resampler::new(44100, 48000);
+    let mut output = vec![];
+    for frame in 0..10 {
+        let input: Vec<f32> = (0..1764)
+            .map(|sample| (frame * 1764 + sample + 1) as f32)
+            .collect();
+        let frames = resampler.process(&[&input, &input], 1920);
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(|channel| channel.len() == 1920));
+        assert_eq!(frames[0], frames[1]);
+        output.extend_from_slice(&frames[0]);
+    }
+
+    // Output is delayed by silence once, then continues without gaps
+    let delay = output.iter().take_while(|sample| **sample == 0.0).count();
+    assert_eq!(delay, 1);
+    let step = 44100.0 / 48000.0;
+    for (index, sample) in output[delay..].iter().enumerate() {
+        let expected = 1.0 + index as f32 * step;
+        assert!((sample - expected).abs() < 1e-2, "sample {index}");
+    }
+}
+
+#[test]
+fn equal_rates_pass_through() {
+    let mut resampler = Resampler::new(48000, 48000);
+    let input: Vec<f32> = (0..1920).map(|sample| sample as f32).collect();
+
+    assert_eq!(resampler.process(&[&input], 1920), vec![input]);
+}
@@ -0,0 +1,145 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use abi_stable::{
+    sabi_trait::TD_Opaque,
+    std_types::{ROption, RString},
+};
+use log::error;
+use phaneron_plugin::{
+    traits::Node_TO,
+    types::{Node, NodeContext, ProcessFrameContext, VideoFrame, VideoOutput},
+    VideoInputId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::dissolve::Dissolve;
+
+#[cfg(test)]
+mod tests;
+
+pub struct TemporalBlendHandle {
+    node_id: String,
+}
+impl TemporalBlendHandle {
+    pub(super) fn new(node_id: String) -> Self {
+        Self { node_id }
+    }
+}
+impl phaneron_plugin::traits::NodeHandle for TemporalBlendHandle {
+    fn initialize(&self, context: NodeContext, _configuration: ROption<RString>) -> Node {
+        let node = TemporalBlend::new(self.node_id.clone(), context);
+
+        Node_TO::from_value(node, TD_Opaque)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TemporalBlendState {
+    /// Number of frames to blend, including the current frame.
+    pub frames: usize,
+    /// Weight of each frame, starting with the current frame. Frames without a weight have a weight of 1.
+    #[serde(default)]
+    pub weights: Vec<f32>,
+}
+
+pub struct TemporalBlend {
+    node_id: String,
+    context: NodeContext,
+    state: Mutex<TemporalBlendState>,
+    /// Most recent frame first.
+    history: Mutex<VecDeque<VideoFrame>>,
+    blend: Mutex<Option<(usize, usize, Dissolve)>>,
+    video_input: VideoInputId,
+    video_output: VideoOutput,
+}
+
+impl TemporalBlend {
+    pub fn new(node_id: String, context: NodeContext) -> Self {
+        let video_input = context.add_video_input();
+        let video_output = context.add_video_output();
+
+        Self {
+            node_id,
+            context,
+            state: Mutex::new(TemporalBlendState {
+                frames: 1,
+                weights: vec![],
+            }),
+            history: Default::default(),
+            blend: Default::default(),
+            video_input,
+            video_output,
+        }
+    }
+}
+
+impl phaneron_plugin::traits::Node for TemporalBlend {
+    fn apply_state(&self, state: RString) -> bool {
+        let state: TemporalBlendState = match serde_json::from_str(&state) {
+            Ok(state) => state,
+            Err(err) => {
+                error!("{}: Invalid state: {err}", self.node_id);
+                return false;
+            }
+        };
+        if state.frames == 0 {
+            error!("{}: frames must be at least 1", self.node_id);
+            return false;
+        }
+
+        // Release frames that are no longer needed straight away so they return to the pool
+        self.history.lock().unwrap().truncate(state.frames);
+        *self.state.lock().unwrap() = state;
+
+        true
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let state = self.state.lock().unwrap();
+        let mut history = self.history.lock().unwrap();
+
+        let input = frame_context
+            .get_video_input(&self.video_input)
+            .unwrap_or(frame_context.get_black_frame());
+        history.push_front(input.frame.clone());
+        history.truncate(state.frames);
+
+        // At startup there may be fewer frames than requested, blend what there is
+        let weights: Vec<f32> = (0..history.len())
+            .map(|i| state.weights.get(i).copied().unwrap_or(1.0))
+            .collect();
+        let mut output = history[0].clone();
+        for (frame, mix) in history.iter().skip(1).zip(blend_mixes(&weights)) {
+            let (width, height) = (output.width(), output.height());
+            let mut blend = self.blend.lock().unwrap();
+            if !matches!(&*blend, Some((w, h, _)) if *w == width && *h == height) {
+                *blend = Some((width, height, Dissolve::new(&self.context, width, height)));
+            }
+            let (_, _, dissolve) = blend.as_mut().unwrap();
+            output = dissolve.run(&output, frame, mix).remove(0);
+        }
+
+        let frame_context = frame_context.submit().unwrap();
+        self.video_output.push_frame(&frame_context, output).ok();
+    }
+}
+
+/// Frames are blended pairwise, the current frame is the starting point and each older frame `i`
+/// is mixed in as `accumulated * mix + frame * (1 - mix)` using `mixes[i - 1]`, which results in the
+/// weighted average of all of the frames. A single frame needs no mixes and is passed through.
+fn blend_mixes(weights: &[f32]) -> Vec<f32> {
+    let mut mixes = Vec::with_capacity(weights.len().saturating_sub(1));
+    let mut total = weights.first().copied().unwrap_or_default();
+    for weight in weights.iter().skip(1) {
+        let new_total = total + weight;
+        mixes.push(if new_total > 0.0 {
+            total / new_total
+        } else {
+            1.0
+        });
+        total = new_total;
+    }
+
+    mixes
+}
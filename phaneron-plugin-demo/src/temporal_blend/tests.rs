@@ -0,0 +1,27 @@
+use super::blend_mixes;
+
+/// Applies the mixes to scalar "frames" the same way the dissolve shader blends pixels.
+fn blend(values: &[f32], weights: &[f32]) -> f32 {
+    let mut output = values[0];
+    for (value, mix) in values.iter().skip(1).zip(blend_mixes(weights)) {
+        output = output * mix + value * (1.0 - mix);
+    }
+
+    output
+}
+
+#[test]
+fn single_frame_is_passthrough() {
+    assert!(blend_mixes(&[1.0]).is_empty());
+    assert!(blend_mixes(&[0.25]).is_empty());
+    assert_eq!(blend(&[0.3], &[1.0]), 0.3);
+}
+
+#[test]
+fn blends_weighted_average() {
+    let average = blend(&[0.0, 0.5, 1.0], &[1.0, 1.0, 1.0]);
+    assert!((average - 0.5).abs() < 1e-6);
+
+    let weighted = blend(&[1.0, 0.0], &[3.0, 1.0]);
+    assert!((weighted - 0.75).abs() < 1e-6);
+}
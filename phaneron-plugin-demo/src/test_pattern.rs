@@ -0,0 +1,261 @@
+use std::sync::Mutex;
+
+use abi_stable::{
+    sabi_trait::TD_Opaque,
+    std_types::{ROption, RString},
+};
+use log::error;
+use phaneron_plugin::{
+    traits::Node_TO,
+    types::{Node, NodeContext, ProcessFrameContext, ProcessShader, VideoOutput},
+    ShaderParams, COLOUR_SPEC_BT_709,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+mod tests;
+
+// Must match the patterns in shaders/test_pattern.cl
+const PATTERN_COLOUR_BARS: u32 = 0;
+const PATTERN_SOLID: u32 = 1;
+const PATTERN_GRADIENT: u32 = 2;
+
+/// Seconds the moving gradient takes to scroll across the frame.
+const GRADIENT_PERIOD_SECONDS: f32 = 4.0;
+
+pub struct TestPatternHandle {
+    node_id: String,
+}
+impl TestPatternHandle {
+    pub(super) fn new(node_id: String) -> Self {
+        Self { node_id }
+    }
+}
+impl phaneron_plugin::traits::NodeHandle for TestPatternHandle {
+    fn initialize(&self, context: NodeContext, _configuration: ROption<RString>) -> Node {
+        let node = TestPattern::new(self.node_id.clone(), context);
+
+        Node_TO::from_value(node, TD_Opaque)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pattern {
+    /// SMPTE colour bars.
+    #[default]
+    ColourBars,
+    /// The whole frame in `colour`.
+    Solid,
+    /// A ramp from black to `colour` that scrolls across the frame.
+    Gradient,
+}
+
+impl Pattern {
+    fn kind(&self) -> u32 {
+        match self {
+            Pattern::ColourBars => PATTERN_COLOUR_BARS,
+            Pattern::Solid => PATTERN_SOLID,
+            Pattern::Gradient => PATTERN_GRADIENT,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TestPatternState {
+    #[serde(default)]
+    pub pattern: Pattern,
+    #[serde(default = "default_width")]
+    pub width: usize,
+    #[serde(default = "default_height")]
+    pub height: usize,
+    /// RGBA colour of the solid and gradient patterns, in linear light.
+    #[serde(default = "default_colour")]
+    pub colour: [f32; 4],
+    /// Frames per second the graph runs at, which sets how fast the gradient moves.
+    #[serde(default = "default_frame_rate")]
+    pub frame_rate: f32,
+}
+
+fn default_width() -> usize {
+    1920
+}
+
+fn default_height() -> usize {
+    1080
+}
+
+fn default_colour() -> [f32; 4] {
+    [1.0, 1.0, 1.0, 1.0]
+}
+
+fn default_frame_rate() -> f32 {
+    25.0
+}
+
+impl Default for TestPatternState {
+    fn default() -> Self {
+        Self {
+            pattern: Pattern::default(),
+            width: default_width(),
+            height: default_height(),
+            colour: default_colour(),
+            frame_rate: default_frame_rate(),
+        }
+    }
+}
+
+/// Generates a test pattern on the GPU, for testing graphs without any media.
+/// Colour bars are shown until a state is applied.
+pub struct TestPattern {
+    node_id: String,
+    state: Mutex<TestPatternState>,
+    /// Position of the gradient as a fraction of the frame width.
+    position: Mutex<f32>,
+    bars: Vec<f32>,
+    shader: ProcessShader,
+    video_output: VideoOutput,
+}
+
+impl TestPattern {
+    pub fn new(node_id: String, context: NodeContext) -> Self {
+        let video_output = context.add_video_output();
+        let kernel = include_str!("../shaders/test_pattern.cl");
+        let shader = context
+            .create_process_shader(kernel.into(), "test_pattern".into())
+            .unwrap();
+
+        Self {
+            node_id,
+            state: Default::default(),
+            position: Default::default(),
+            bars: colour_bars(),
+            shader,
+            video_output,
+        }
+    }
+}
+
+impl phaneron_plugin::traits::Node for TestPattern {
+    fn apply_state(&self, state: RString) -> bool {
+        let state: TestPatternState = match serde_json::from_str(&state) {
+            Ok(state) => state,
+            Err(err) => {
+                error!("{}: Invalid state: {err}", self.node_id);
+                return false;
+            }
+        };
+        if state.width == 0 || state.height == 0 || state.frame_rate <= 0.0 {
+            error!(
+                "{}: width, height and frame_rate must be positive",
+                self.node_id
+            );
+            return false;
+        }
+
+        *self.state.lock().unwrap() = state;
+
+        true
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let state = self.state.lock().unwrap().clone();
+        let position = {
+            let mut position = self.position.lock().unwrap();
+            let current = *position;
+            *position = advance_gradient(current, state.frame_rate);
+            current
+        };
+
+        let mut params = ShaderParams::default();
+        params.set_param_u32_input(state.pattern.kind());
+        params.set_param_vec4_input(state.colour);
+        params.set_param_f32_input(position);
+        params.set_param_f32_array_input(&self.bars);
+        params.set_param_u32_input((self.bars.len() / 5) as u32);
+        params.set_param_video_frame_output(state.width, state.height);
+        let output = self.shader.run(params, &[state.width, state.height])[0].clone();
+
+        let frame_context = frame_context.submit().unwrap();
+        self.video_output.push_frame(&frame_context, output).ok();
+    }
+}
+
+/// Position of the gradient on the next frame, wrapping around at the edge of the frame.
+fn advance_gradient(position: f32, frame_rate: f32) -> f32 {
+    (position + 1.0 / (GRADIENT_PERIOD_SECONDS * frame_rate)).fract()
+}
+
+/// SMPTE colour bars as segments for the shader, see shaders/test_pattern.cl.
+/// Colours are given as BT.709 signal levels and converted to linear light.
+fn colour_bars() -> Vec<f32> {
+    const GREY: [f32; 3] = [0.75, 0.75, 0.75];
+    const YELLOW: [f32; 3] = [0.75, 0.75, 0.0];
+    const CYAN: [f32; 3] = [0.0, 0.75, 0.75];
+    const GREEN: [f32; 3] = [0.0, 0.75, 0.0];
+    const MAGENTA: [f32; 3] = [0.75, 0.0, 0.75];
+    const RED: [f32; 3] = [0.75, 0.0, 0.0];
+    const BLUE: [f32; 3] = [0.0, 0.0, 0.75];
+    const BLACK: [f32; 3] = [0.0, 0.0, 0.0];
+    const WHITE: [f32; 3] = [1.0, 1.0, 1.0];
+    const MINUS_I: [f32; 3] = [0.0, 0.129, 0.298];
+    const PLUS_Q: [f32; 3] = [0.196, 0.0, 0.416];
+    const SUPER_BLACK: [f32; 3] = [-0.04, -0.04, -0.04];
+    const PLUGE_GREY: [f32; 3] = [0.04, 0.04, 0.04];
+
+    let bar = 1.0 / 7.0;
+    let rows: [(f32, Vec<(f32, [f32; 3])>); 3] = [
+        (
+            2.0 / 3.0,
+            [GREY, YELLOW, CYAN, GREEN, MAGENTA, RED, BLUE]
+                .into_iter()
+                .enumerate()
+                .map(|(i, colour)| ((i + 1) as f32 / 7.0, colour))
+                .collect(),
+        ),
+        (
+            0.75,
+            [BLUE, BLACK, MAGENTA, BLACK, CYAN, BLACK, GREY]
+                .into_iter()
+                .enumerate()
+                .map(|(i, colour)| ((i + 1) as f32 / 7.0, colour))
+                .collect(),
+        ),
+        (
+            1.0,
+            vec![
+                (1.25 * bar, MINUS_I),
+                (2.5 * bar, WHITE),
+                (3.75 * bar, PLUS_Q),
+                (5.0 * bar, BLACK),
+                ((5.0 + 1.0 / 3.0) * bar, SUPER_BLACK),
+                ((5.0 + 2.0 / 3.0) * bar, BLACK),
+                (6.0 * bar, PLUGE_GREY),
+                (1.0, BLACK),
+            ],
+        ),
+    ];
+
+    let mut segments = vec![];
+    for (bottom, bars) in rows {
+        for (right, colour) in bars {
+            segments.push(bottom);
+            segments.push(right);
+            segments.extend(colour.map(to_linear));
+        }
+    }
+
+    segments
+}
+
+/// BT.709 transfer function from signal level to linear light, the linear segment near black
+/// carries the below black PLUGE bar through as a negative value.
+fn to_linear(value: f32) -> f32 {
+    let spec = COLOUR_SPEC_BT_709;
+    if value < spec.beta * spec.delta {
+        value / spec.delta
+    } else {
+        ((value + (spec.alpha - 1.0)) / spec.alpha).powf(1.0 / spec.gamma)
+    }
+}
@@ -0,0 +1,51 @@
+use super::{advance_gradient, colour_bars, to_linear, Pattern, TestPatternState};
+
+#[test]
+fn state_defaults_to_hd_colour_bars() {
+    let state: TestPatternState = serde_json::from_str("{}").unwrap();
+    assert_eq!(state, TestPatternState::default());
+    assert_eq!(state.pattern, Pattern::ColourBars);
+    assert_eq!((state.width, state.height), (1920, 1080));
+
+    let state: TestPatternState =
+        serde_json::from_str(r#"{ "pattern": "solid", "colour": [1.0, 0.0, 0.0, 1.0] }"#).unwrap();
+    assert_eq!(state.pattern, Pattern::Solid);
+    assert_eq!(state.colour, [1.0, 0.0, 0.0, 1.0]);
+}
+
+#[test]
+fn bars_cover_the_frame() {
+    let bars = colour_bars();
+    let segments: Vec<&[f32]> = bars.chunks(5).collect();
+    assert_eq!(segments.len(), 7 + 7 + 8);
+
+    // The last bar of each row reaches the right edge, the last row reaches the bottom
+    for row_end in [6, 13, 21] {
+        assert_eq!(segments[row_end][1], 1.0);
+    }
+    assert_eq!(segments[21][0], 1.0);
+}
+
+#[test]
+fn bars_are_in_linear_light() {
+    let bars = colour_bars();
+    // 75% grey
+    assert!((bars[2] - 0.5635).abs() < 0.001, "got {}", bars[2]);
+    assert_eq!(to_linear(0.0), 0.0);
+    assert!((to_linear(1.0) - 1.0).abs() < 0.0001);
+    // Below black stays below black
+    assert!(to_linear(-0.04) < 0.0);
+}
+
+#[test]
+fn gradient_wraps_around() {
+    let mut position = 0.0;
+    for _ in 0..50 {
+        position = advance_gradient(position, 25.0);
+    }
+    assert!((position - 0.5).abs() < 0.0001, "got {position}");
+    for _ in 0..50 {
+        position = advance_gradient(position, 25.0);
+    }
+    assert!(position < 0.0001 || position > 0.9999, "got {position}");
+}
@@ -1,4 +1,4 @@
-use std::sync::Mutex;
+use std::{collections::HashMap, f32::consts::FRAC_PI_2, sync::Mutex};
 
 use abi_stable::{
     sabi_trait::TD_Opaque,
@@ -7,11 +7,13 @@ use abi_stable::{
 use serde::{Deserialize, Serialize};
 
 use phaneron_plugin::{
-    traits::Node_TO, types::Node, types::NodeContext, types::ProcessFrameContext,
-    types::VideoOutput, VideoInputId,
+    traits::Node_TO, types::AudioFrame, types::AudioOutput, types::Node, types::NodeContext,
+    types::ProcessFrameContext, types::ProcessShader, types::ToAudioF32, types::VideoOutput,
+    AudioChannelLayout, AudioFormat, AudioInputId, ShaderParams, VideoInputId,
 };
 
-use crate::dissolve::Dissolve;
+#[cfg(test)]
+mod tests;
 
 pub struct TraditionalMixerEmulatorHandle {
     node_id: String,
@@ -38,6 +40,9 @@ impl phaneron_plugin::traits::NodeHandle for TraditionalMixerEmulatorHandle {
 #[serde(rename_all = "camelCase")]
 pub struct TraditionalMixerEmulatorConfiguration {
     pub number_of_inputs: usize,
+    /// Adds an audio input for each video input, and an audio output that can follow the video.
+    #[serde(default)]
+    pub paired_audio: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -46,13 +51,223 @@ pub struct TraditionalMixerEmulatorState {
     pub active_input: Option<String>,
     pub next_input: Option<String>,
     pub transition: Option<TraditionalMixerEmulatorTransition>,
+    /// Switches and mixes the audio paired with the active and next inputs along with the video.
+    /// Silence is output when this is off. Requires the mixer to be configured with paired audio.
+    #[serde(default)]
+    pub audio_follow: bool,
 }
 
+/// Transitions from the active input at `position` 0 to the next input at `position` 1.
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 #[serde(tag = "transition")]
 pub enum TraditionalMixerEmulatorTransition {
+    /// Cross-fades between the inputs.
     Mix { position: f32 },
+    /// Switches straight to the next input.
+    Cut,
+    /// Fades to `colour` at the midpoint, then from it to the next input.
+    /// Audio fades out to silence and back in.
+    Dip {
+        position: f32,
+        /// RGBA colour in linear light, defaults to opaque black.
+        #[serde(default = "default_dip_colour")]
+        colour: [f32; 4],
+    },
+    /// The next input is uncovered behind an edge moving in `direction`.
+    Wipe {
+        position: f32,
+        direction: WipeDirection,
+    },
+}
+
+fn default_dip_colour() -> [f32; 4] {
+    [0.0, 0.0, 0.0, 1.0]
+}
+
+/// The direction the edge of a wipe travels across the output.
+/// Discriminants are the `direction` parameter of the transition shader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WipeDirection {
+    Left = 0,
+    Right = 1,
+    Up = 2,
+    Down = 3,
+}
+
+// `kind` parameter of the transition shader, must match the defines in transition.cl
+const TRANSITION_KIND_MIX: u32 = 0;
+const TRANSITION_KIND_DIP: u32 = 1;
+const TRANSITION_KIND_WIPE: u32 = 2;
+
+impl TraditionalMixerEmulatorTransition {
+    /// Position within the transition, a cut is already at the next input.
+    fn position(&self) -> f32 {
+        match self {
+            TraditionalMixerEmulatorTransition::Mix { position }
+            | TraditionalMixerEmulatorTransition::Dip { position, .. }
+            | TraditionalMixerEmulatorTransition::Wipe { position, .. } => position.clamp(0.0, 1.0),
+            TraditionalMixerEmulatorTransition::Cut => 1.0,
+        }
+    }
+
+    /// Parameters of the transition shader, `None` if the next input is output as is.
+    fn shader_params(&self) -> Option<(u32, u32, [f32; 4])> {
+        match self {
+            TraditionalMixerEmulatorTransition::Mix { .. } => {
+                Some((TRANSITION_KIND_MIX, 0, [0.0; 4]))
+            }
+            TraditionalMixerEmulatorTransition::Cut => None,
+            TraditionalMixerEmulatorTransition::Dip { colour, .. } => {
+                Some((TRANSITION_KIND_DIP, 0, *colour))
+            }
+            TraditionalMixerEmulatorTransition::Wipe { direction, .. } => {
+                Some((TRANSITION_KIND_WIPE, *direction as u32, [0.0; 4]))
+            }
+        }
+    }
+
+    /// Mixes the audio of the active and next inputs for this point of the transition.
+    fn mix_audio(&self, active: &[&[f32]], next: &[&[f32]]) -> Vec<Vec<f32>> {
+        let position = self.position();
+        match self {
+            TraditionalMixerEmulatorTransition::Dip { .. } if position < 0.5 => {
+                crossfade(active, &[], position * 2.0)
+            }
+            TraditionalMixerEmulatorTransition::Dip { .. } => {
+                crossfade(&[], next, position * 2.0 - 1.0)
+            }
+            _ => crossfade(active, next, position),
+        }
+    }
+}
+
+/// JSON Schema of [`TraditionalMixerEmulatorState`].
+pub fn state_schema() -> serde_json::Value {
+    let input = serde_json::json!({
+        "type": ["string", "null"],
+        "description": "Id of one of the mixer's video inputs"
+    });
+    let position = serde_json::json!({
+        "type": "number",
+        "minimum": 0.0,
+        "maximum": 1.0,
+        "description": "From 0 on the active input to 1 on the next input"
+    });
+    serde_json::json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Traditional Mixer Emulator",
+        "type": "object",
+        "properties": {
+            "activeInput": input,
+            "nextInput": input,
+            "transition": {
+                "oneOf": [
+                    { "type": "null" },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "transition": { "const": "mix" },
+                            "position": position
+                        },
+                        "required": ["transition", "position"]
+                    },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "transition": { "const": "cut" }
+                        },
+                        "required": ["transition"]
+                    },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "transition": { "const": "dip" },
+                            "position": position,
+                            "colour": {
+                                "type": "array",
+                                "items": { "type": "number" },
+                                "minItems": 4,
+                                "maxItems": 4,
+                                "default": [0.0, 0.0, 0.0, 1.0],
+                                "description": "RGBA colour dipped through at the midpoint, in linear light"
+                            }
+                        },
+                        "required": ["transition", "position"]
+                    },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "transition": { "const": "wipe" },
+                            "position": position,
+                            "direction": {
+                                "enum": ["left", "right", "up", "down"],
+                                "description": "Direction the edge of the wipe travels"
+                            }
+                        },
+                        "required": ["transition", "position", "direction"]
+                    }
+                ]
+            },
+            "audioFollow": {
+                "type": "boolean",
+                "default": false,
+                "description": "Mix the audio paired with the active and next inputs along with the video"
+            }
+        }
+    })
+}
+
+/// The audio input paired with each video input, by position.
+#[derive(Debug, Default)]
+struct InputPairs {
+    video: Vec<VideoInputId>,
+    audio: Vec<AudioInputId>,
+}
+
+impl InputPairs {
+    fn audio_for(&self, video_input: &VideoInputId) -> Option<&AudioInputId> {
+        let index = self.video.iter().position(|input| input == video_input)?;
+        self.audio.get(index)
+    }
+}
+
+/// Mixes the channels of two audio frames with equal-power gains, so that the loudness of
+/// uncorrelated sources stays constant through the mix. Missing samples are treated as silence.
+fn crossfade(active: &[&[f32]], next: &[&[f32]], position: f32) -> Vec<Vec<f32>> {
+    let position = position.clamp(0.0, 1.0);
+    let (active_gain, next_gain) = ((position * FRAC_PI_2).cos(), (position * FRAC_PI_2).sin());
+    let sample = |buffers: &[&[f32]], channel: usize, index: usize| {
+        buffers
+            .get(channel)
+            .and_then(|buffer| buffer.get(index))
+            .copied()
+            .unwrap_or_default()
+    };
+
+    let channels = active.len().max(next.len());
+    (0..channels)
+        .map(|channel| {
+            let samples = active
+                .get(channel)
+                .map_or(0, |buffer| buffer.len())
+                .max(next.get(channel).map_or(0, |buffer| buffer.len()));
+            (0..samples)
+                .map(|index| {
+                    sample(active, channel, index) * active_gain
+                        + sample(next, channel, index) * next_gain
+                })
+                .collect()
+        })
+        .collect()
+}
+
+struct AudioFollow {
+    inputs: InputPairs,
+    output: AudioOutput,
+    /// Loaders for mixed audio, by number of channels.
+    to_audio_f32: Mutex<HashMap<usize, ToAudioF32>>,
 }
 
 pub struct TraditionalMixerEmlator {
@@ -60,7 +275,8 @@ pub struct TraditionalMixerEmlator {
     context: NodeContext,
     state: Mutex<Option<TraditionalMixerEmulatorState>>,
     active_video_output: VideoOutput,
-    video_transition: Mutex<Option<Dissolve>>,
+    transition_shader: ProcessShader,
+    audio: Option<AudioFollow>,
 }
 
 impl TraditionalMixerEmlator {
@@ -71,20 +287,57 @@ impl TraditionalMixerEmlator {
     ) -> Self {
         let active_video_output = context.add_video_output();
 
+        let mut inputs = InputPairs::default();
+        let mut paired_audio = false;
         if let Some(configuration) = configuration {
             for _ in 0..configuration.number_of_inputs {
-                context.add_video_input();
+                inputs.video.push(context.add_video_input());
+                if configuration.paired_audio {
+                    inputs.audio.push(context.add_audio_input());
+                }
             }
+            paired_audio = configuration.paired_audio;
         }
+        let audio = paired_audio.then(|| AudioFollow {
+            inputs,
+            output: context.add_audio_output(),
+            to_audio_f32: Default::default(),
+        });
+        let kernel = include_str!("../shaders/transition.cl");
+        let transition_shader = context
+            .create_process_shader(kernel.into(), "transition".into())
+            .unwrap();
 
         Self {
             node_id,
             context,
             active_video_output,
             state: Default::default(),
-            video_transition: Default::default(),
+            transition_shader,
+            audio,
         }
     }
+
+    /// Creates an audio frame from separate channel buffers, `None` if no layout has as many channels.
+    fn load_audio(&self, audio: &AudioFollow, buffers: &[Vec<f32>]) -> Option<AudioFrame> {
+        let channel_layout = AudioChannelLayout::from_num_channels(buffers.len())?;
+        let samples = buffers.iter().map(Vec::len).max().unwrap_or_default();
+        let mut interleaved = Vec::with_capacity(samples * buffers.len() * 4);
+        for index in 0..samples {
+            for buffer in buffers {
+                let sample = buffer.get(index).copied().unwrap_or_default();
+                interleaved.extend_from_slice(&sample.to_le_bytes());
+            }
+        }
+
+        let mut to_audio_f32 = audio.to_audio_f32.lock().unwrap();
+        let to_audio_f32 = to_audio_f32.entry(buffers.len()).or_insert_with(|| {
+            self.context
+                .create_to_audio_f32(AudioFormat::F32, channel_layout)
+        });
+        let loaded_frame = to_audio_f32.load_frame(&interleaved.as_slice().into());
+        Some(to_audio_f32.process_frame(loaded_frame))
+    }
 }
 
 impl phaneron_plugin::traits::Node for TraditionalMixerEmlator {
@@ -128,26 +381,70 @@ impl phaneron_plugin::traits::Node for TraditionalMixerEmlator {
             frame_context.get_black_frame()
         };
 
-        let output = if let Some(state) = &*state {
-            if let Some(transition) = &state.transition {
-                match transition {
-                    TraditionalMixerEmulatorTransition::Mix { position } => {
-                        let mut video_transition_lock = self.video_transition.lock().unwrap();
-                        let video_transition = video_transition_lock
-                            .get_or_insert_with(|| Dissolve::new(&self.context, 1920, 1080));
-                        let output =
-                            video_transition.run(&active_input.frame, &next_input.frame, *position);
-                        output.first().unwrap().clone()
-                    }
+        let transition = state.as_ref().and_then(|state| state.transition.as_ref());
+        let output = match transition {
+            Some(transition) => match transition.shader_params() {
+                Some((kind, direction, colour)) => {
+                    let (width, height) = (active_input.frame.width(), active_input.frame.height());
+                    let mut params = ShaderParams::default();
+                    params.set_param_video_frame_input(active_input.frame.clone());
+                    params.set_param_video_frame_input(next_input.frame.clone());
+                    params.set_param_u32_input(kind);
+                    params.set_param_f32_input(transition.position());
+                    params.set_param_u32_input(direction);
+                    params.set_param_vec4_input(colour);
+                    params.set_param_video_frame_output(width, height);
+
+                    self.transition_shader.run(params, &[width, height])[0].clone()
                 }
-            } else {
-                active_input.frame.clone()
-            }
-        } else {
-            active_input.frame.clone()
+                None => next_input.frame.clone(),
+            },
+            None => active_input.frame.clone(),
         };
 
+        let audio_output = self.audio.as_ref().map(|audio| {
+            let silence = frame_context.get_silence_frame().frame.clone();
+            let Some(state) = state.as_ref().filter(|state| state.audio_follow) else {
+                return (audio, silence);
+            };
+            let paired_frame = |video_input: &Option<String>| {
+                video_input
+                    .as_ref()
+                    .and_then(|id| audio.inputs.audio_for(&VideoInputId::new_from(id.into())))
+                    .and_then(|audio_input| {
+                        frame_context.get_audio_input(audio_input).into_option()
+                    })
+                    .map(|input| input.frame.clone())
+                    .unwrap_or_else(|| silence.clone())
+            };
+            let active_audio = paired_frame(&state.active_input);
+
+            let frame = match &state.transition {
+                Some(TraditionalMixerEmulatorTransition::Cut) => paired_frame(&state.next_input),
+                Some(transition) => {
+                    let next_audio = paired_frame(&state.next_input);
+                    let active_buffers: Vec<&[f32]> = active_audio
+                        .buffers()
+                        .iter()
+                        .map(|b| b.as_slice())
+                        .collect();
+                    let next_buffers: Vec<&[f32]> =
+                        next_audio.buffers().iter().map(|b| b.as_slice()).collect();
+                    let mixed = transition.mix_audio(&active_buffers, &next_buffers);
+                    self.load_audio(audio, &mixed).unwrap_or(active_audio)
+                }
+                None => active_audio,
+            };
+
+            (audio, frame)
+        });
+
         let frame_context = frame_context.submit().unwrap();
-        self.active_video_output.push_frame(&frame_context, output);
+        self.active_video_output
+            .push_frame(&frame_context, output)
+            .ok();
+        if let Some((audio, frame)) = audio_output {
+            audio.output.push_frame(&frame_context, frame).ok();
+        }
     }
 }
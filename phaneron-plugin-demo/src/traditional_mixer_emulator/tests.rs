@@ -0,0 +1,174 @@
+use phaneron_plugin::{AudioInputId, VideoInputId};
+
+use super::{
+    crossfade, state_schema, InputPairs, TraditionalMixerEmulatorState,
+    TraditionalMixerEmulatorTransition, WipeDirection,
+};
+
+fn input_pairs() -> InputPairs {
+    InputPairs {
+        video: vec![
+            VideoInputId::new_from("video_a".into()),
+            VideoInputId::new_from("video_b".into()),
+        ],
+        audio: vec![
+            AudioInputId::new_from("audio_a".into()),
+            AudioInputId::new_from("audio_b".into()),
+        ],
+    }
+}
+
+#[test]
+fn switching_video_input_switches_paired_audio() {
+    let inputs = input_pairs();
+
+    let before = inputs.audio_for(&VideoInputId::new_from("video_a".into()));
+    let after = inputs.audio_for(&VideoInputId::new_from("video_b".into()));
+
+    assert_eq!(before, Some(&AudioInputId::new_from("audio_a".into())));
+    assert_eq!(after, Some(&AudioInputId::new_from("audio_b".into())));
+}
+
+#[test]
+fn unknown_video_input_has_no_audio() {
+    let inputs = input_pairs();
+
+    assert_eq!(
+        inputs.audio_for(&VideoInputId::new_from("video_c".into())),
+        None
+    );
+}
+
+#[test]
+fn crossfade_starts_on_active_and_ends_on_next() {
+    let active: [&[f32]; 2] = [&[1.0, 1.0], &[0.5, 0.5]];
+    let next: [&[f32]; 2] = [&[-1.0, -1.0], &[0.25, 0.25]];
+
+    let start = crossfade(&active, &next, 0.0);
+    let end = crossfade(&active, &next, 1.0);
+
+    assert_eq!(start, vec![vec![1.0, 1.0], vec![0.5, 0.5]]);
+    for (channel, expected) in end.iter().zip(next) {
+        for (sample, expected) in channel.iter().zip(expected) {
+            assert!((sample - expected).abs() < 1e-6);
+        }
+    }
+}
+
+#[test]
+fn crossfade_keeps_power_constant_at_midpoint() {
+    let active: [&[f32]; 1] = [&[1.0]];
+    let next: [&[f32]; 1] = [&[0.0]];
+
+    let mid = crossfade(&active, &next, 0.5);
+
+    assert!((mid[0][0] * mid[0][0] - 0.5).abs() < 1e-6);
+}
+
+#[test]
+fn crossfade_pads_shorter_input_with_silence() {
+    let active: [&[f32]; 1] = [&[1.0, 1.0, 1.0]];
+    let next: [&[f32]; 2] = [&[1.0], &[1.0]];
+
+    let mixed = crossfade(&active, &next, 1.0);
+
+    assert_eq!(mixed.len(), 2);
+    assert_eq!(mixed[0].len(), 3);
+    assert!(mixed[0][2].abs() < 1e-6);
+}
+
+#[test]
+fn audio_follow_defaults_to_off() {
+    let state: TraditionalMixerEmulatorState =
+        serde_json::from_str(r#"{"activeInput":"video_a"}"#).unwrap();
+
+    assert!(!state.audio_follow);
+}
+
+#[test]
+fn state_schema_describes_every_state_field() {
+    let schema = state_schema();
+    let state: TraditionalMixerEmulatorState = serde_json::from_value(serde_json::json!({
+        "activeInput": "video_a",
+        "nextInput": "video_b",
+        "transition": { "transition": "mix", "position": 0.5 },
+        "audioFollow": true
+    }))
+    .unwrap();
+
+    let state = serde_json::to_value(state).unwrap();
+    for field in state.as_object().unwrap().keys() {
+        assert!(
+            schema["properties"].get(field).is_some(),
+            "{field} is missing from the schema"
+        );
+    }
+}
+
+#[test]
+fn transitions_parse() {
+    let parse = |json: serde_json::Value| -> TraditionalMixerEmulatorTransition {
+        serde_json::from_value(json).unwrap()
+    };
+
+    assert!(matches!(
+        parse(serde_json::json!({ "transition": "cut" })),
+        TraditionalMixerEmulatorTransition::Cut
+    ));
+    assert!(matches!(
+        parse(serde_json::json!({ "transition": "dip", "position": 0.25 })),
+        TraditionalMixerEmulatorTransition::Dip { colour, .. } if colour == [0.0, 0.0, 0.0, 1.0]
+    ));
+    assert!(matches!(
+        parse(serde_json::json!({ "transition": "wipe", "position": 0.5, "direction": "left" })),
+        TraditionalMixerEmulatorTransition::Wipe {
+            direction: WipeDirection::Left,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn schema_describes_every_transition() {
+    let schema = state_schema();
+    let transitions: Vec<&str> = schema["properties"]["transition"]["oneOf"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|variant| variant["properties"]["transition"]["const"].as_str())
+        .collect();
+
+    assert_eq!(transitions, vec!["mix", "cut", "dip", "wipe"]);
+}
+
+#[test]
+fn dip_audio_is_silent_at_midpoint() {
+    let active: [&[f32]; 1] = [&[1.0]];
+    let next: [&[f32]; 1] = [&[1.0]];
+    let dip = |position| TraditionalMixerEmulatorTransition::Dip {
+        position,
+        colour: [0.0, 0.0, 0.0, 1.0],
+    };
+
+    assert_eq!(dip(0.0).mix_audio(&active, &next), vec![vec![1.0]]);
+    assert!(dip(0.5).mix_audio(&active, &next)[0][0].abs() < 1e-6);
+    assert!((dip(1.0).mix_audio(&active, &next)[0][0] - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn switching_transition_type_keeps_position() {
+    let active: [&[f32]; 1] = [&[1.0]];
+    let next: [&[f32]; 1] = [&[0.0]];
+    let mix = TraditionalMixerEmulatorTransition::Mix { position: 0.3 };
+    let wipe = TraditionalMixerEmulatorTransition::Wipe {
+        position: 0.3,
+        direction: WipeDirection::Right,
+    };
+
+    // Both go from the active input at 0 to the next at 1, so switching mid-transition doesn't jump
+    assert_eq!(mix.position(), wipe.position());
+    assert_eq!(
+        mix.mix_audio(&active, &next),
+        wipe.mix_audio(&active, &next)
+    );
+}
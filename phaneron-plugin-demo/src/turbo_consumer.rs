@@ -8,7 +8,7 @@ use log::info;
 
 use phaneron_plugin::{
     traits::Node_TO, types::FromRGBA, types::Node, types::NodeContext, types::ProcessFrameContext,
-    ColourSpace, InterlaceMode, VideoFormat, VideoInputId,
+    ColourRange, ColourSpace, InterlaceMode, VideoFormat, VideoInputId,
 };
 
 pub struct TurboConsumerHandle {
@@ -63,6 +63,7 @@ impl phaneron_plugin::traits::Node for TurboConsumer {
         let from_rgba = from_rgba_lock.get_or_insert(self.context.create_from_rgba(
             &VideoFormat::YUV420p,
             &ColourSpace::sRGB.colour_spec(),
+            ColourRange::Limited,
             1920,
             1080,
             InterlaceMode::Progressive,
@@ -75,7 +76,7 @@ impl phaneron_plugin::traits::Node for TurboConsumer {
         let frame = from_rgba.process_frame(&frame_context, frame.frame);
 
         let copy_context = frame_context.submit().unwrap();
-        let _frame = from_rgba.copy_frame(&copy_context, frame);
+        let _frame = copy_context.copy_video_frame(from_rgba, frame);
 
         let timer = last_frame_time.replace(Instant::now());
         if let Some(timer) = timer {
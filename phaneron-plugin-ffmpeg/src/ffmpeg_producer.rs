@@ -18,8 +18,8 @@
 
 extern crate ffmpeg_the_third as ffmpeg;
 use std::collections::HashMap;
-use std::sync::Mutex;
-use std::thread::JoinHandle;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
 
 use abi_stable::{
     sabi_trait::TD_Opaque,
@@ -29,13 +29,19 @@ use anyhow::anyhow;
 use phaneron_plugin::{
     traits::Node_TO, types::AudioFrame, types::AudioOutput, types::Node, types::NodeContext,
     types::ProcessFrameContext, types::ToAudioF32, types::ToRGBA, types::VideoFrame,
-    types::VideoOutput, AudioChannelLayout, AudioFormat, ColourSpace, VideoFormat,
+    types::VideoOutput, AudioChannelLayout, AudioFormat, ColourRange, ColourSpace, VideoFormat,
 };
 use serde::{Deserialize, Serialize};
 use std::ops::Deref;
-use tracing::{debug, info};
+use tracing::{debug, error, info, warn};
 
-use phaneron_plugin_utils::yadif::{Yadif, YadifConfig, YadifMode};
+use phaneron_plugin_utils::yadif::{Yadif, YadifConfig, YadifMode, YadifPrediction};
+
+use crate::{
+    hwaccel::{download_hw_frame, is_hw_frame, HwDevice},
+    playback::{EndAction, PlaybackControl, RateCadence},
+    timecode::SourceTimecode,
+};
 
 const READ_BUFFER_SIZE: usize = 2;
 
@@ -43,10 +49,288 @@ const READ_BUFFER_SIZE: usize = 2;
 #[serde(rename_all = "camelCase")]
 pub struct FFmpegProducerState {
     pub file: String,
+    /// Hardware device type to decode video with, named as in FFmpeg, e.g. `cuda` or `vaapi`.
+    /// Falls back to software decoding if the device can't be opened or the codec isn't supported.
+    #[serde(default)]
+    pub hwaccel: Option<String>,
+    /// Language of the audio stream to play, matched against the `language` tag of each audio
+    /// stream, e.g. `eng`. Falls back to the first audio stream if no stream has the language.
+    #[serde(default, alias = "audio_language")]
+    pub audio_language: Option<String>,
+    /// Position in seconds to seek to, frames before it are decoded but not output.
+    /// The producer seeks whenever the position changes.
+    #[serde(default, alias = "position_seconds")]
+    pub position_seconds: Option<f64>,
+    /// Holds the current frame and mutes audio.
+    #[serde(default)]
+    pub paused: bool,
+    /// Starts again from the beginning at the end of the file, otherwise the last frame is held.
+    #[serde(default = "default_loop_playback", alias = "loop_playback")]
+    pub loop_playback: bool,
+    /// Playback speed, frames are repeated below 1.0 and dropped above it. Audio is muted at
+    /// any rate other than 1.0.
+    #[serde(default = "default_rate")]
+    pub rate: f32,
+    /// How video flagged as interlaced is deinterlaced, progressive video is output as decoded.
+    #[serde(default)]
+    pub deinterlace: Deinterlace,
+    /// Overrides the field order flagged in the video, for files that are flagged incorrectly.
+    #[serde(default, alias = "field_order")]
+    pub field_order: Option<FieldOrder>,
+}
+
+/// Deinterlacing of the producer, which outputs one frame for each decoded frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Deinterlace {
+    /// Frames are output as decoded, for video that is flagged as interlaced but is progressive.
+    Off,
+    /// Both fields are kept as decoded, interleaved in each frame. The same as `Off` for the
+    /// producer, named for operators who expect it.
+    Weave,
+    /// The lines of the second field are replaced by the average of the lines of the first field
+    /// above and below them.
+    Bob,
+    Yadif {
+        #[serde(default)]
+        mode: DeinterlaceMode,
+    },
+}
+
+impl Default for Deinterlace {
+    fn default() -> Self {
+        Deinterlace::Yadif {
+            mode: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeinterlaceMode {
+    /// Predicts the second field from the previous, current and next frames, delaying output by
+    /// one frame.
+    #[default]
+    Frame,
+    /// As `Frame` without the spatial check, which is faster but can leave more artifacts.
+    FrameNospatial,
+    /// Predicts the second field from the first field only, without delaying output.
+    SpatialOnly,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FieldOrder {
+    TopFieldFirst,
+    BottomFieldFirst,
+}
+
+impl Deinterlace {
+    /// `None` if frames are output as decoded.
+    fn yadif_config(&self, tff: bool) -> Option<YadifConfig> {
+        let (mode, prediction) = match self {
+            Deinterlace::Off | Deinterlace::Weave => return None,
+            Deinterlace::Bob => (YadifMode::Frame, YadifPrediction::Linear),
+            Deinterlace::Yadif {
+                mode: DeinterlaceMode::Frame,
+            } => (YadifMode::Frame, YadifPrediction::Temporal),
+            Deinterlace::Yadif {
+                mode: DeinterlaceMode::FrameNospatial,
+            } => (YadifMode::FrameNospatial, YadifPrediction::Temporal),
+            Deinterlace::Yadif {
+                mode: DeinterlaceMode::SpatialOnly,
+            } => (YadifMode::Frame, YadifPrediction::Spatial),
+        };
+
+        Some(YadifConfig {
+            mode,
+            tff,
+            prediction,
+        })
+    }
+}
+
+fn default_loop_playback() -> bool {
+    true
 }
 
-type FFmpegAudioProcess = (Mutex<std::sync::mpsc::Receiver<AudioFrame>>, AudioOutput);
-type FFmpegVideoProcess = (Mutex<std::sync::mpsc::Receiver<VideoFrame>>, VideoOutput);
+fn default_rate() -> f32 {
+    1.0
+}
+
+/// JSON Schema of [`FFmpegProducerState`].
+pub fn state_schema() -> serde_json::Value {
+    serde_json::json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "FFmpeg Producer",
+        "type": "object",
+        "properties": {
+            "file": {
+                "type": "string",
+                "description": "Path or URL of the file to play"
+            },
+            "hwaccel": {
+                "type": ["string", "null"],
+                "description": "Hardware device type to decode video with, e.g. cuda or vaapi"
+            },
+            "audioLanguage": {
+                "type": ["string", "null"],
+                "description": "Language of the audio stream to play, e.g. eng"
+            },
+            "positionSeconds": {
+                "type": ["number", "null"],
+                "minimum": 0,
+                "description": "Position in seconds to seek to"
+            },
+            "paused": {
+                "type": "boolean",
+                "default": false,
+                "description": "Hold the current frame"
+            },
+            "loopPlayback": {
+                "type": "boolean",
+                "default": true,
+                "description": "Start again from the beginning at the end of the file"
+            },
+            "rate": {
+                "type": "number",
+                "minimum": 0,
+                "default": 1.0,
+                "description": "Playback speed, audio is muted at any rate other than 1.0"
+            },
+            "deinterlace": {
+                "description": "How video flagged as interlaced is deinterlaced",
+                "default": { "yadif": { "mode": "frame" } },
+                "oneOf": [
+                    { "enum": ["off", "weave", "bob"] },
+                    {
+                        "type": "object",
+                        "properties": {
+                            "yadif": {
+                                "type": "object",
+                                "properties": {
+                                    "mode": {
+                                        "enum": ["frame", "frameNospatial", "spatialOnly"],
+                                        "default": "frame"
+                                    }
+                                }
+                            }
+                        },
+                        "required": ["yadif"]
+                    }
+                ]
+            },
+            "fieldOrder": {
+                "enum": ["topFieldFirst", "bottomFieldFirst", null],
+                "description": "Overrides the field order flagged in the video"
+            }
+        },
+        "required": ["file"]
+    })
+}
+
+/// Sent from the reader thread to the loader threads.
+enum ReadMessage {
+    Packet(ffmpeg::packet::Packet),
+    /// The reader has sought to a position in seconds, starting a new generation
+    Seek {
+        generation: u64,
+        position: f64,
+    },
+    /// The reader has looped back to the beginning of the file
+    Restart,
+    /// The reader has reached the end of the file and isn't looping
+    End,
+}
+
+/// Sent from the loader threads to the producer, tagged with the generation they were loaded in.
+enum LoadedMessage<T> {
+    Frame(u64, T),
+    End(u64),
+}
+
+/// Frames loaded from a stream, along with the frame most recently taken from it.
+struct LoadedStream<T> {
+    receiver: Receiver<LoadedMessage<T>>,
+    generation: u64,
+    current: Option<T>,
+    ended: bool,
+}
+
+impl<T> LoadedStream<T> {
+    fn new(receiver: Receiver<LoadedMessage<T>>) -> Self {
+        Self {
+            receiver,
+            generation: 0,
+            current: None,
+            ended: false,
+        }
+    }
+
+    /// Whether a frame must be taken even if playback isn't advancing, e.g. after a seek.
+    fn needs_frame(&self, generation: u64) -> bool {
+        self.generation != generation || (self.current.is_none() && !self.ended)
+    }
+
+    fn start_generation(&mut self, generation: u64) {
+        if self.generation != generation {
+            self.generation = generation;
+            self.current = None;
+            self.ended = false;
+        }
+    }
+
+    /// Takes up to `steps` frames loaded in the given generation, discarding stale frames.
+    fn advance(&mut self, generation: u64, steps: usize) {
+        self.start_generation(generation);
+
+        let mut remaining = steps;
+        while remaining > 0 && !self.ended {
+            match self.receiver.recv() {
+                Ok(LoadedMessage::Frame(frame_generation, frame))
+                    if frame_generation == generation =>
+                {
+                    self.current = Some(frame);
+                    remaining -= 1;
+                }
+                Ok(LoadedMessage::End(end_generation)) if end_generation == generation => {
+                    self.ended = true
+                }
+                // Loaded before a seek
+                Ok(_) => {}
+                // The loader has stopped
+                Err(_) => self.ended = true,
+            }
+        }
+    }
+
+    /// Discards frames that have already been loaded without waiting for more.
+    fn skip(&mut self, generation: u64) {
+        self.start_generation(generation);
+
+        while let Ok(message) = self.receiver.try_recv() {
+            if let LoadedMessage::End(end_generation) = message {
+                self.ended |= end_generation == generation;
+            }
+        }
+    }
+}
+
+/// Decoding of the current file, the reader and loader threads stop once it is dropped.
+struct FFmpegPipeline {
+    control: Arc<PlaybackControl>,
+    cadence: RateCadence,
+    video_streams: Vec<LoadedStream<VideoFrame>>,
+    audio_streams: Vec<LoadedStream<AudioFrame>>,
+    /// Set when no frame pushed to the outputs was delivered, decoding is paused until one is.
+    idle: bool,
+}
+
+impl Drop for FFmpegPipeline {
+    fn drop(&mut self) {
+        self.control.close();
+    }
+}
 
 pub struct FFmpegProducerHandle {
     node_id: String,
@@ -67,11 +351,11 @@ impl phaneron_plugin::traits::NodeHandle for FFmpegProducerHandle {
 pub struct FFmpegProducer {
     node_id: String,
     context: NodeContext,
-    read_thread: Mutex<Option<JoinHandle<()>>>,
-    loader_threads: Mutex<Option<Vec<JoinHandle<()>>>>,
     state: Mutex<Option<FFmpegProducerState>>,
-    audio_processes: Mutex<Option<Vec<FFmpegAudioProcess>>>,
-    video_processes: Mutex<Option<Vec<FFmpegVideoProcess>>>,
+    pipeline: Mutex<Option<FFmpegPipeline>>,
+    // Outputs are kept when the file changes, so that connections to them remain valid
+    video_outputs: Mutex<Vec<VideoOutput>>,
+    audio_outputs: Mutex<Vec<AudioOutput>>,
 }
 
 impl FFmpegProducer {
@@ -79,140 +363,322 @@ impl FFmpegProducer {
         Self {
             node_id,
             context,
-            read_thread: Default::default(),
-            loader_threads: Default::default(),
             state: Default::default(),
-            audio_processes: Default::default(),
-            video_processes: Default::default(),
+            pipeline: Default::default(),
+            video_outputs: Default::default(),
+            audio_outputs: Default::default(),
         }
     }
 }
 
-impl phaneron_plugin::traits::Node for FFmpegProducer {
-    fn apply_state(&self, state: RString) -> bool {
-        let current_state = self.state.lock().unwrap();
-        if current_state.is_some() {
-            return false;
-        }
+impl FFmpegProducer {
+    /// Picks the audio stream to play from `(stream index, language tag)` pairs.
+    fn select_audio_stream(
+        &self,
+        audio_streams: &[(usize, Option<String>)],
+        language: Option<&str>,
+    ) -> Option<usize> {
+        let (first_index, _) = audio_streams.first()?;
+
+        let Some(language) = language else {
+            info!(
+                "FFmpeg producer {} using audio stream {first_index}",
+                self.node_id
+            );
+            return Some(*first_index);
+        };
 
-        let state: FFmpegProducerState = serde_json::from_str(&state).unwrap();
+        let matching = audio_streams.iter().find(|(_, tag)| {
+            tag.as_deref()
+                .map_or(false, |tag| tag.eq_ignore_ascii_case(language))
+        });
+        match matching {
+            Some((index, _)) => {
+                info!(
+                    "FFmpeg producer {} using audio stream {index} for language {language}",
+                    self.node_id
+                );
+                Some(*index)
+            }
+            None if audio_streams.iter().all(|(_, tag)| tag.is_none()) => {
+                warn!(
+                    "FFmpeg producer {} audio streams have no language tags, using audio stream {first_index} instead of {language}",
+                    self.node_id
+                );
+                Some(*first_index)
+            }
+            None => {
+                let available: Vec<&str> = audio_streams
+                    .iter()
+                    .filter_map(|(_, tag)| tag.as_deref())
+                    .collect();
+                warn!(
+                    "FFmpeg producer {} has no audio stream for language {language} (available: {}), using audio stream {first_index}",
+                    self.node_id,
+                    available.join(", ")
+                );
+                Some(*first_index)
+            }
+        }
+    }
 
-        let mut loaded_video_frame_receivers: Vec<std::sync::mpsc::Receiver<VideoFrame>> = vec![];
-        let mut loaded_audio_frame_receivers: Vec<std::sync::mpsc::Receiver<AudioFrame>> = vec![];
+    /// Opens the file of the state and starts reading and decoding it.
+    fn open(&self, state: &FFmpegProducerState) -> Result<FFmpegPipeline, ffmpeg::Error> {
+        let mut loaded_video_frame_receivers: Vec<Receiver<LoadedMessage<VideoFrame>>> = vec![];
+        let mut loaded_audio_frame_receivers: Vec<Receiver<LoadedMessage<AudioFrame>>> = vec![];
 
         // Uses a hashmap so that `stream.index()` can be used in the reading thread
-        let mut read_frame_senders: HashMap<
-            usize,
-            std::sync::mpsc::SyncSender<ffmpeg::packet::Packet>,
-        > = HashMap::new();
+        let mut read_frame_senders: HashMap<usize, std::sync::mpsc::SyncSender<ReadMessage>> =
+            HashMap::new();
 
-        let mut load_threads: Vec<JoinHandle<()>> = vec![];
+        let mut ictx = ffmpeg::format::input(&state.file)?;
+        let control = Arc::new(PlaybackControl::default());
 
-        // let mut ictx = ffmpeg::format::input(&initial_state.media_file).unwrap();
-        let mut ictx = ffmpeg::format::input(&state.file).unwrap();
-        // *self.state.lock().unwrap() = Some(initial_state);
+        let audio_streams: Vec<(usize, Option<String>)> = ictx
+            .streams()
+            .filter(|stream| stream.parameters().medium() == ffmpeg::media::Type::Audio)
+            .map(|stream| {
+                let language = stream.metadata().get("language").map(str::to_string);
+                (stream.index(), language)
+            })
+            .collect();
+        let audio_stream =
+            self.select_audio_stream(&audio_streams, state.audio_language.as_deref());
 
-        // TODO: Remove this later, makes the demo work as intended 100% of the time instead of some of the time
-        let mut audio_stream: Option<usize> = None;
+        let container_timecode = ictx.metadata().get("timecode").map(str::to_string);
 
         // TODO: Flatten this out and make it more readable
         for stream in ictx.streams() {
             let stream_type = stream.parameters().medium();
             match stream_type {
                 ffmpeg::media::Type::Video => {
-                    let video_decoder_context =
+                    let mut video_decoder_context =
                         ffmpeg::codec::context::Context::from_parameters(stream.parameters())
                             .unwrap();
+                    if let Some(hwaccel) = &state.hwaccel {
+                        match HwDevice::new(hwaccel) {
+                            Ok(hw_device) => hw_device.attach(&mut video_decoder_context),
+                            Err(err) => warn!(
+                                "FFmpeg producer {} falling back to software decoding: {err}",
+                                self.node_id
+                            ),
+                        }
+                    }
                     let mut video_decoder = video_decoder_context.decoder().video().unwrap();
+                    // Frames carry their source timecode in their metadata, if the file has one
+                    let timecode_tag = stream
+                        .metadata()
+                        .get("timecode")
+                        .map(str::to_string)
+                        .or(container_timecode.clone());
+                    let frame_rate = match stream.avg_frame_rate() {
+                        rate if rate.denominator() != 0 && rate.numerator() != 0 => rate,
+                        _ => stream.rate(),
+                    };
+                    let source_timecode = timecode_tag.and_then(|tag| {
+                        let timecode = SourceTimecode::new(&tag, frame_rate);
+                        if timecode.is_none() {
+                            warn!(
+                                "FFmpeg producer {} ignoring invalid timecode {tag} at {frame_rate} fps",
+                                self.node_id
+                            );
+                        }
+                        timecode
+                    });
                     let (loaded_frame_sender, loaded_frame_receiver) =
                         std::sync::mpsc::sync_channel(1);
                     loaded_video_frame_receivers.push(loaded_frame_receiver);
                     let (read_frame_sender, read_frame_receiver) =
-                        std::sync::mpsc::sync_channel::<ffmpeg::packet::Packet>(READ_BUFFER_SIZE);
+                        std::sync::mpsc::sync_channel::<ReadMessage>(READ_BUFFER_SIZE);
                     read_frame_senders.insert(stream.index(), read_frame_sender);
                     let context = self.context.clone();
-                    let thread = std::thread::spawn(move || {
+                    let node_id = self.node_id.clone();
+                    let stream_index = stream.index();
+                    let time_base = f64::from(stream.time_base());
+                    let loader_control = control.clone();
+                    let deinterlace = state.deinterlace;
+                    let field_order = state.field_order;
+                    std::thread::spawn(move || {
                         let mut to_rgba: Option<ToRGBA> = None;
+                        let mut converter: Option<ffmpeg::software::scaling::Context> = None;
+                        let mut hw_decoding: Option<bool> = None;
                         let mut yadif: Option<Yadif> = None;
                         let mut colour_space: Option<ColourSpace> = None;
+                        let mut colour_range: Option<ColourRange> = None;
                         let mut video_format: Option<VideoFormat> = None;
+                        let mut frame_number: i32 = 0;
+                        let mut generation = 0;
+                        let mut skip_before: Option<i64> = None;
                         loop {
-                            let packet = read_frame_receiver.recv().unwrap();
+                            // The reader has stopped, the node has been destroyed
+                            let Ok(message) = read_frame_receiver.recv() else {
+                                return;
+                            };
+                            let packet = match message {
+                                ReadMessage::Packet(packet) => packet,
+                                ReadMessage::Seek {
+                                    generation: seek_generation,
+                                    position,
+                                } => {
+                                    video_decoder.flush();
+                                    generation = seek_generation;
+                                    skip_before = Some((position / time_base) as i64);
+                                    frame_number =
+                                        (position * f64::from(frame_rate)).round() as i32;
+                                    continue;
+                                }
+                                ReadMessage::Restart => {
+                                    skip_before = None;
+                                    continue;
+                                }
+                                ReadMessage::End => {
+                                    skip_before = None;
+                                    if loaded_frame_sender
+                                        .send(LoadedMessage::End(generation))
+                                        .is_err()
+                                    {
+                                        return;
+                                    }
+                                    continue;
+                                }
+                            };
+                            // Packets read before a seek are stale
+                            if generation != loader_control.generation() {
+                                continue;
+                            }
                             video_decoder.send_packet(&packet).unwrap();
 
                             let mut decoded = ffmpeg::frame::Video::empty();
                             let frame = video_decoder.receive_frame(&mut decoded);
 
                             if frame.is_ok() {
+                                // Decoding restarts from the keyframe before a seek position,
+                                // frames up to the position are decoded but not output
+                                if let (Some(skip_before), Some(timestamp)) =
+                                    (skip_before, decoded.timestamp())
+                                {
+                                    if timestamp < skip_before {
+                                        continue;
+                                    }
+                                }
+                                let is_hw = is_hw_frame(&decoded);
+                                if hw_decoding.replace(is_hw) != Some(is_hw) {
+                                    info!(
+                                        "FFmpeg producer {} decoding video stream {} in {}",
+                                        node_id,
+                                        stream_index,
+                                        if is_hw { "hardware" } else { "software" }
+                                    );
+                                }
+                                // Frames decoded in hardware are downloaded from the GPU, usually as NV12 or
+                                // P010, and are only converted if the downloaded format is not supported
+                                let downloaded = is_hw.then(|| {
+                                    let downloaded = download_hw_frame(&decoded).unwrap();
+                                    if VideoFormat::try_from(FFmpegPixelFormat(downloaded.format()))
+                                        .is_ok()
+                                    {
+                                        return downloaded;
+                                    }
+                                    let converter = converter.get_or_insert_with(|| {
+                                        downloaded
+                                            .converter(ffmpeg::format::Pixel::YUV420P)
+                                            .unwrap()
+                                    });
+                                    let mut converted = ffmpeg::frame::Video::empty();
+                                    converter.run(&downloaded, &mut converted).unwrap();
+                                    converted
+                                });
+                                // Colour properties are always taken from the decoded frame
+                                let pixels = downloaded.as_ref().unwrap_or(&decoded);
+
                                 let colour_space = colour_space.get_or_insert_with(|| {
-                                    FFmegColourSpace(decoded.color_space()).try_into().unwrap()
+                                    FFmegColourSpace(
+                                        decoded.color_space(),
+                                        decoded.color_transfer_characteristic(),
+                                    )
+                                    .try_into()
+                                    .unwrap()
+                                });
+                                let colour_range = *colour_range.get_or_insert_with(|| {
+                                    FFmpegColourRange(decoded.color_range()).into()
                                 });
                                 let video_format = video_format.get_or_insert_with(|| {
-                                    FFmpegPixelFormat(decoded.format()).try_into().unwrap()
+                                    FFmpegPixelFormat(pixels.format()).try_into().unwrap()
                                 });
                                 let to_rgba = to_rgba.get_or_insert_with(|| {
                                     context.create_to_rgba(
                                         video_format,
                                         &colour_space.colour_spec(),
-                                        decoded.width() as usize,
-                                        decoded.height() as usize,
+                                        colour_range,
+                                        pixels.width() as usize,
+                                        pixels.height() as usize,
                                     ) // TODO: Make sure the format, colourspace, width + height haven't changed on us
                                 });
 
                                 let inputs: Vec<RSlice<u8>> = match video_format {
                                     VideoFormat::BGRA8 | VideoFormat::RGBA8 | VideoFormat::V210 => {
-                                        vec![decoded.data(0).into()]
+                                        vec![pixels.data(0).into()]
                                     }
                                     VideoFormat::YUV420p
                                     | VideoFormat::YUV422p8
                                     | VideoFormat::YUV422p10 => {
                                         vec![
-                                            decoded.data(0).into(),
-                                            decoded.data(1).into(),
-                                            decoded.data(2).into(),
+                                            pixels.data(0).into(),
+                                            pixels.data(1).into(),
+                                            pixels.data(2).into(),
                                         ]
                                     }
+                                    VideoFormat::NV12 | VideoFormat::P010 => {
+                                        vec![pixels.data(0).into(), pixels.data(1).into()]
+                                    }
                                 };
 
                                 let interlaced = decoded.is_interlaced();
                                 let loaded_frame = to_rgba.load_frame(&inputs.as_slice().into());
                                 let frame = to_rgba.process_frame(loaded_frame);
 
-                                if interlaced {
-                                    let yadif = yadif.get_or_insert_with(|| {
-                                        let yadif_mode = YadifMode::Field;
-                                        let tff = decoded.is_top_first();
-                                        Yadif::new(
-                                            &context,
-                                            decoded.width() as usize,
-                                            decoded.height() as usize,
-                                            YadifConfig {
-                                                mode: yadif_mode,
-                                                tff,
-                                            },
-                                        )
-                                    });
-                                    if let Some(frame) = yadif.run(&frame).first() {
-                                        loaded_frame_sender.send(frame.clone()).unwrap();
+                                let tff = match field_order {
+                                    Some(field_order) => field_order == FieldOrder::TopFieldFirst,
+                                    None => decoded.is_top_first(),
+                                };
+                                let frame = match deinterlace.yadif_config(tff) {
+                                    Some(config) if interlaced => {
+                                        let yadif = yadif.get_or_insert_with(|| {
+                                            Yadif::new(
+                                                &context,
+                                                decoded.width() as usize,
+                                                decoded.height() as usize,
+                                                config,
+                                            )
+                                        });
+                                        yadif.run(&frame).first().cloned()
+                                    }
+                                    _ => Some(frame),
+                                };
+                                if let Some(frame) = frame {
+                                    let frame = match &source_timecode {
+                                        Some(source_timecode) => {
+                                            let mut metadata = frame.metadata().clone();
+                                            metadata
+                                                .set("timecode", &source_timecode.at(frame_number));
+                                            frame.with_metadata(metadata)
+                                        }
+                                        None => frame,
+                                    };
+                                    if loaded_frame_sender
+                                        .send(LoadedMessage::Frame(generation, frame))
+                                        .is_err()
+                                    {
+                                        return;
                                     }
-                                } else {
-                                    loaded_frame_sender.send(frame).unwrap();
                                 }
+                                frame_number = frame_number.wrapping_add(1);
                             }
                         }
                     });
-                    load_threads.push(thread);
                 }
                 ffmpeg::media::Type::Audio => {
-                    let wanted_audio_index = audio_stream.get_or_insert_with(|| {
-                        info!(
-                            "FFmpeg producer {} using audio stream {}",
-                            self.node_id,
-                            stream.index()
-                        );
-                        stream.index()
-                    });
-                    if stream.index() != *wanted_audio_index {
+                    if Some(stream.index()) != audio_stream {
                         continue;
                     }
                     let audio_decoder_context =
@@ -223,84 +689,280 @@ impl phaneron_plugin::traits::Node for FFmpegProducer {
                         std::sync::mpsc::sync_channel(1);
                     loaded_audio_frame_receivers.push(loaded_frame_receiver);
                     let (read_frame_sender, read_frame_receiver) =
-                        std::sync::mpsc::sync_channel::<ffmpeg::packet::Packet>(READ_BUFFER_SIZE);
+                        std::sync::mpsc::sync_channel::<ReadMessage>(READ_BUFFER_SIZE);
                     read_frame_senders.insert(stream.index(), read_frame_sender);
                     let context = self.context.clone();
-                    let thread = std::thread::spawn(move || {
+                    let time_base = f64::from(stream.time_base());
+                    let loader_control = control.clone();
+                    std::thread::spawn(move || {
                         let mut to_audio_f32: Option<ToAudioF32> = None;
+                        let mut resampler: Option<ffmpeg::software::resampling::Context> = None;
+                        let mut channel_layout: Option<AudioChannelLayout> = None;
+                        let mut generation = 0;
+                        let mut skip_before: Option<i64> = None;
                         loop {
-                            let packet = read_frame_receiver.recv().unwrap();
+                            // The reader has stopped, the node has been destroyed
+                            let Ok(message) = read_frame_receiver.recv() else {
+                                return;
+                            };
+                            let packet = match message {
+                                ReadMessage::Packet(packet) => packet,
+                                ReadMessage::Seek {
+                                    generation: seek_generation,
+                                    position,
+                                } => {
+                                    audio_decoder.flush();
+                                    generation = seek_generation;
+                                    skip_before = Some((position / time_base) as i64);
+                                    continue;
+                                }
+                                ReadMessage::Restart => {
+                                    skip_before = None;
+                                    continue;
+                                }
+                                ReadMessage::End => {
+                                    skip_before = None;
+                                    if loaded_frame_sender
+                                        .send(LoadedMessage::End(generation))
+                                        .is_err()
+                                    {
+                                        return;
+                                    }
+                                    continue;
+                                }
+                            };
+                            // Packets read before a seek are stale
+                            if generation != loader_control.generation() {
+                                continue;
+                            }
                             audio_decoder.send_packet(&packet).unwrap();
 
                             let mut decoded = ffmpeg::frame::Audio::empty();
                             let frame = audio_decoder.receive_frame(&mut decoded);
 
-                            if frame.is_ok() {
-                                let planes = decoded.planes();
-                                for i in 0..planes {
-                                    let decoded_data = decoded.data(i);
-                                    let to_audio_f32 = to_audio_f32.get_or_insert_with(|| {
-                                        context.create_to_audio_f32(
-                                            AudioFormat::I32,
-                                            AudioChannelLayout::Mono,
+                            // Muted audio is decoded and discarded so that the producer doesn't wait for it
+                            if frame.is_ok() && !loader_control.mute_audio() {
+                                if let (Some(skip_before), Some(timestamp)) =
+                                    (skip_before, decoded.timestamp())
+                                {
+                                    if timestamp < skip_before {
+                                        continue;
+                                    }
+                                }
+                                if decoded.channel_layout().is_empty() {
+                                    decoded.set_channel_layout(ffmpeg::ChannelLayout::default(
+                                        decoded.channels() as i32,
+                                    ));
+                                }
+                                // Layouts without a matching channel layout are downmixed to stereo
+                                let channel_layout = *channel_layout.get_or_insert_with(|| {
+                                    AudioChannelLayout::from_num_channels(
+                                        decoded.channels() as usize
+                                    )
+                                    .unwrap_or(AudioChannelLayout::L_R)
+                                });
+                                // Samples are converted to interleaved 32 bit integers in the channel order of the layout
+                                let resampler = resampler.get_or_insert_with(|| {
+                                    decoded
+                                        .resampler(
+                                            ffmpeg::format::Sample::I32(
+                                                ffmpeg::format::sample::Type::Packed,
+                                            ),
+                                            FFmpegChannelLayout::from(channel_layout).0,
+                                            decoded.rate(),
                                         )
-                                    });
-                                    let loaded_frame =
-                                        to_audio_f32.load_frame(&decoded_data.into());
-                                    let audio_frame = to_audio_f32.process_frame(loaded_frame);
-                                    loaded_frame_sender.send(audio_frame).unwrap();
+                                        .unwrap()
+                                });
+                                let mut resampled = ffmpeg::frame::Audio::empty();
+                                resampler.run(&decoded, &mut resampled).unwrap();
+                                // The plane may be padded beyond the last sample
+                                let num_bytes =
+                                    resampled.samples() * channel_layout.num_channels() * 4;
+                                let resampled_data = &resampled.data(0)[..num_bytes];
+
+                                let to_audio_f32 = to_audio_f32.get_or_insert_with(|| {
+                                    context.create_to_audio_f32(AudioFormat::I32, channel_layout)
+                                });
+                                let loaded_frame = to_audio_f32.load_frame(&resampled_data.into());
+                                let audio_frame = to_audio_f32.process_frame(loaded_frame);
+                                if loaded_frame_sender
+                                    .send(LoadedMessage::Frame(generation, audio_frame))
+                                    .is_err()
+                                {
+                                    return;
                                 }
                             }
                         }
                     });
-                    load_threads.push(thread);
                 }
                 _ => {}
             }
         }
 
-        let reader_thread = std::thread::spawn(move || loop {
-            let packets = ictx.packets();
-            for (stream, packet) in packets {
+        let reader_control = control.clone();
+        let node_id = self.node_id.clone();
+        std::thread::spawn(move || loop {
+            for (stream, packet) in ictx.packets() {
                 if let Some(sender) = read_frame_senders.get(&stream.index()) {
-                    sender.send(packet).unwrap();
+                    // The loader has stopped, the node has been destroyed
+                    if sender.send(ReadMessage::Packet(packet)).is_err() {
+                        return;
+                    }
+                }
+                if reader_control.has_pending_seek() {
+                    break;
                 }
             }
-            ictx.seek(0, std::ops::RangeFull).unwrap();
-        });
 
-        let mut video_processes: Vec<(Mutex<std::sync::mpsc::Receiver<VideoFrame>>, VideoOutput)> =
-            vec![];
-        let mut audio_processes: Vec<(Mutex<std::sync::mpsc::Receiver<AudioFrame>>, AudioOutput)> =
-            vec![];
+            let (generation, position) = match reader_control.take_seek() {
+                Some(seek) => seek,
+                None if reader_control.loop_playback() => {
+                    // Playback ends rather than looping over a file that can't be rewound
+                    if let Err(err) = ictx.seek(0, std::ops::RangeFull) {
+                        error!("FFmpeg producer {node_id} failed to loop to the start: {err}");
+                        for sender in read_frame_senders.values() {
+                            if sender.send(ReadMessage::End).is_err() {
+                                return;
+                            }
+                        }
+                        return;
+                    }
+                    for sender in read_frame_senders.values() {
+                        if sender.send(ReadMessage::Restart).is_err() {
+                            return;
+                        }
+                    }
+                    continue;
+                }
+                None => {
+                    for sender in read_frame_senders.values() {
+                        if sender.send(ReadMessage::End).is_err() {
+                            return;
+                        }
+                    }
+                    match reader_control.wait_at_end() {
+                        EndAction::Seek(generation, position) => (generation, position),
+                        EndAction::Close => return,
+                    }
+                }
+            };
 
-        for receiver in loaded_video_frame_receivers {
-            let video_output = self.context.add_video_output();
-            video_processes.push((Mutex::new(receiver), video_output));
-        }
+            let timestamp = (position * f64::from(ffmpeg::ffi::AV_TIME_BASE)) as i64;
+            if let Err(err) = ictx.seek(timestamp, ..=timestamp) {
+                warn!("FFmpeg producer {node_id} failed to seek to {position}s: {err}");
+            }
+            for sender in read_frame_senders.values() {
+                if sender
+                    .send(ReadMessage::Seek {
+                        generation,
+                        position,
+                    })
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        });
 
-        for receiver in loaded_audio_frame_receivers {
-            let audio_output = self.context.add_audio_output();
-            audio_processes.push((Mutex::new(receiver), audio_output));
+        let mut video_outputs = self.video_outputs.lock().unwrap();
+        while video_outputs.len() < loaded_video_frame_receivers.len() {
+            video_outputs.push(self.context.add_video_output());
+        }
+        let mut audio_outputs = self.audio_outputs.lock().unwrap();
+        while audio_outputs.len() < loaded_audio_frame_receivers.len() {
+            audio_outputs.push(self.context.add_audio_output());
         }
 
         debug!(
             "FFmpeg producer {} loaded file with {} video streams and {} audio streams",
             self.node_id,
-            video_processes.len(),
-            audio_processes.len()
+            loaded_video_frame_receivers.len(),
+            loaded_audio_frame_receivers.len()
         );
 
-        self.read_thread.lock().unwrap().replace(reader_thread);
-        self.loader_threads.lock().unwrap().replace(load_threads);
-        self.video_processes
-            .lock()
-            .unwrap()
-            .replace(video_processes);
-        self.audio_processes
-            .lock()
-            .unwrap()
-            .replace(audio_processes);
+        Ok(FFmpegPipeline {
+            control,
+            cadence: RateCadence::default(),
+            idle: false,
+            video_streams: loaded_video_frame_receivers
+                .into_iter()
+                .map(LoadedStream::new)
+                .collect(),
+            audio_streams: loaded_audio_frame_receivers
+                .into_iter()
+                .map(LoadedStream::new)
+                .collect(),
+        })
+    }
+}
+
+impl phaneron_plugin::traits::Node for FFmpegProducer {
+    fn apply_state(&self, state: RString) -> bool {
+        let state: FFmpegProducerState = match serde_json::from_str(&state) {
+            Ok(state) => state,
+            Err(err) => {
+                warn!(
+                    "FFmpeg producer {} received invalid state: {err}",
+                    self.node_id
+                );
+                return false;
+            }
+        };
+        if !state.rate.is_finite() || state.rate < 0.0 {
+            warn!(
+                "FFmpeg producer {} received invalid rate {}",
+                self.node_id, state.rate
+            );
+            return false;
+        }
+
+        let mut current_state = self.state.lock().unwrap();
+        let mut pipeline = self.pipeline.lock().unwrap();
+
+        // Changing what is decoded rebuilds the pipeline, playback controls are applied to it
+        let reopen = match &*current_state {
+            Some(current) => {
+                current.file != state.file
+                    || current.hwaccel != state.hwaccel
+                    || current.audio_language != state.audio_language
+                    || current.deinterlace != state.deinterlace
+                    || current.field_order != state.field_order
+            }
+            None => true,
+        };
+        if reopen {
+            // Dropping the current pipeline stops its threads
+            pipeline.take();
+            *current_state = None;
+            match self.open(&state) {
+                Ok(opened) => *pipeline = Some(opened),
+                Err(err) => {
+                    warn!(
+                        "FFmpeg producer {} failed to open {}: {err}",
+                        self.node_id, state.file
+                    );
+                    return false;
+                }
+            }
+        }
+        let Some(pipeline) = pipeline.as_mut() else {
+            return false;
+        };
+
+        pipeline.control.set_loop_playback(state.loop_playback);
+        pipeline
+            .control
+            .set_mute_audio(state.paused || state.rate != 1.0);
+        let previous_position = current_state
+            .as_ref()
+            .and_then(|current| current.position_seconds);
+        if let Some(position) = state.position_seconds {
+            if previous_position != Some(position) {
+                pipeline.control.seek(position.max(0.0));
+            }
+        }
+
+        *current_state = Some(state);
 
         true
     }
@@ -308,25 +970,74 @@ impl phaneron_plugin::traits::Node for FFmpegProducer {
     fn process_frame(&self, context: ProcessFrameContext) {
         let frame_context = context.submit().unwrap();
 
-        let video_processes_lock = self.video_processes.lock().unwrap();
-        if let Some(video_processes) = &*video_processes_lock {
-            for (video_receiver, video_output) in video_processes.iter() {
-                let frame = video_receiver.lock().unwrap().recv().unwrap();
-                video_output.push_frame(&frame_context, frame);
+        let (paused, rate) = match &*self.state.lock().unwrap() {
+            Some(state) => (state.paused, state.rate),
+            None => return,
+        };
+        let mut pipeline_lock = self.pipeline.lock().unwrap();
+        let Some(pipeline) = &mut *pipeline_lock else {
+            return;
+        };
+
+        let generation = pipeline.control.generation();
+        // While nothing is connected the streams are not advanced, so the reader and loader threads
+        // stop decoding once their queues are full
+        let steps = if paused || pipeline.idle {
+            0
+        } else {
+            pipeline.cadence.next_steps(rate)
+        };
+        let mut pushed = false;
+        let mut delivered = false;
+
+        let video_outputs = self.video_outputs.lock().unwrap();
+        for (stream, video_output) in pipeline.video_streams.iter_mut().zip(video_outputs.iter()) {
+            let steps = if stream.needs_frame(generation) {
+                steps.max(1)
+            } else {
+                steps
+            };
+            stream.advance(generation, steps);
+            // The current frame is repeated while paused, slowed down or at the end of the file
+            if let Some(frame) = &stream.current {
+                pushed = true;
+                delivered |= video_output
+                    .push_frame(&frame_context, frame.clone())
+                    .is_ok();
             }
         }
 
-        let audio_processes_lock = self.audio_processes.lock().unwrap();
-        if let Some(audio_processes) = &*audio_processes_lock {
-            for (audio_receiver, audio_output) in audio_processes.iter() {
-                let frame = audio_receiver.lock().unwrap().recv().unwrap();
-                audio_output.push_frame(&frame_context, frame);
+        let mute_audio = pipeline.control.mute_audio();
+        let audio_outputs = self.audio_outputs.lock().unwrap();
+        for (stream, audio_output) in pipeline.audio_streams.iter_mut().zip(audio_outputs.iter()) {
+            if mute_audio {
+                stream.skip(generation);
+                continue;
+            }
+            if pipeline.idle {
+                // Silence is pushed instead of decoding audio, so that a new connection is noticed
+                pushed = true;
+                delivered |= audio_output
+                    .push_frame(&frame_context, context.get_silence_frame().frame.clone())
+                    .is_ok();
+                continue;
+            }
+            stream.advance(generation, 1);
+            if let Some(frame) = stream.current.take() {
+                pushed = true;
+                delivered |= audio_output.push_frame(&frame_context, frame).is_ok();
             }
         }
+
+        if pushed {
+            pipeline.idle = !delivered;
+        }
     }
 }
 
-struct FFmegColourSpace(ffmpeg::color::Space);
+/// The matrix coefficients of a stream, along with its transfer characteristic which tells HDR
+/// apart from SDR BT.2020.
+struct FFmegColourSpace(ffmpeg::color::Space, ffmpeg::color::TransferCharacteristic);
 
 impl Deref for FFmegColourSpace {
     type Target = ffmpeg::color::Space;
@@ -344,6 +1055,11 @@ impl TryFrom<FFmegColourSpace> for ColourSpace {
             ffmpeg::color::Space::RGB => Ok(ColourSpace::sRGB),
             ffmpeg::color::Space::BT709 => Ok(ColourSpace::BT_709),
             ffmpeg::color::Space::Unspecified => Ok(ColourSpace::BT_709),
+            ffmpeg::color::Space::BT2020NCL => match value.1 {
+                ffmpeg::color::TransferCharacteristic::SMPTE2084 => Ok(ColourSpace::BT_2100_PQ),
+                ffmpeg::color::TransferCharacteristic::ARIB_STD_B67 => Ok(ColourSpace::BT_2100_HLG),
+                _ => Ok(ColourSpace::BT_2020),
+            },
             _ => Err(anyhow!(
                 "Unsupported colour space: {}",
                 value.deref().name().unwrap_or("Unknown")
@@ -352,6 +1068,32 @@ impl TryFrom<FFmegColourSpace> for ColourSpace {
     }
 }
 
+struct FFmpegColourRange(ffmpeg::color::Range);
+
+impl From<FFmpegColourRange> for ColourRange {
+    fn from(value: FFmpegColourRange) -> Self {
+        match value.0 {
+            ffmpeg::color::Range::JPEG => ColourRange::Full,
+            // Unspecified is treated as limited, which is the norm for video
+            ffmpeg::color::Range::MPEG | ffmpeg::color::Range::Unspecified => ColourRange::Limited,
+        }
+    }
+}
+
+struct FFmpegChannelLayout(ffmpeg::ChannelLayout);
+
+impl From<AudioChannelLayout> for FFmpegChannelLayout {
+    fn from(value: AudioChannelLayout) -> Self {
+        Self(match value {
+            AudioChannelLayout::Mono | AudioChannelLayout::L | AudioChannelLayout::R => {
+                ffmpeg::ChannelLayout::MONO
+            }
+            AudioChannelLayout::L_R | AudioChannelLayout::R_L => ffmpeg::ChannelLayout::STEREO,
+            AudioChannelLayout::L_R_C_LFE_Ls_Rs => ffmpeg::ChannelLayout::_5POINT1,
+        })
+    }
+}
+
 struct FFmpegPixelFormat(ffmpeg::format::Pixel);
 
 impl Deref for FFmpegPixelFormat {
@@ -374,6 +1116,8 @@ impl TryFrom<FFmpegPixelFormat> for VideoFormat {
             ffmpeg::format::Pixel::YUV422P10 | ffmpeg::format::Pixel::YUV422P10LE => {
                 Ok(VideoFormat::YUV422p10)
             }
+            ffmpeg::format::Pixel::NV12 => Ok(VideoFormat::NV12),
+            ffmpeg::format::Pixel::P010LE => Ok(VideoFormat::P010),
             _ => Err(anyhow!(
                 "Unsupported pixel format: {}",
                 value.deref().descriptor().unwrap().name()
@@ -0,0 +1,98 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Hardware accelerated decoding. Frames are decoded into GPU memory by FFmpeg and downloaded
+//! into system memory, from where they are loaded in the same way as software decoded frames.
+
+use std::ffi::CString;
+
+use anyhow::{anyhow, bail};
+use ffmpeg_the_third::{self as ffmpeg, ffi};
+
+/// A reference to an FFmpeg hardware device context.
+pub struct HwDevice {
+    device: *mut ffi::AVBufferRef,
+}
+
+impl HwDevice {
+    /// Opens the default device of a hardware device type, named as in FFmpeg, e.g. `cuda` or `vaapi`.
+    pub fn new(device_type_name: &str) -> anyhow::Result<Self> {
+        let name = CString::new(device_type_name)?;
+        let device_type = unsafe { ffi::av_hwdevice_find_type_by_name(name.as_ptr()) };
+        if device_type == ffi::AVHWDeviceType::AV_HWDEVICE_TYPE_NONE {
+            bail!("Unknown hardware device type {device_type_name}");
+        }
+
+        let mut device = std::ptr::null_mut();
+        let result = unsafe {
+            ffi::av_hwdevice_ctx_create(
+                &mut device,
+                device_type,
+                std::ptr::null(),
+                std::ptr::null_mut(),
+                0,
+            )
+        };
+        if result < 0 {
+            return Err(anyhow!(
+                "Failed to create {device_type_name} device: {}",
+                ffmpeg::Error::from(result)
+            ));
+        }
+
+        Ok(Self { device })
+    }
+
+    /// Requests hardware decoding from a decoder that has not been opened yet.
+    /// Decoders without support for the device will still decode, in software.
+    pub fn attach(&self, decoder_context: &mut ffmpeg::codec::context::Context) {
+        unsafe {
+            let decoder_context = decoder_context.as_mut_ptr();
+            (*decoder_context).hw_device_ctx = ffi::av_buffer_ref(self.device);
+        }
+    }
+}
+
+impl Drop for HwDevice {
+    fn drop(&mut self) {
+        unsafe { ffi::av_buffer_unref(&mut self.device) };
+    }
+}
+
+pub fn is_hw_frame(frame: &ffmpeg::frame::Video) -> bool {
+    unsafe { !(*frame.as_ptr()).hw_frames_ctx.is_null() }
+}
+
+/// Copies a frame decoded into GPU memory into system memory, usually as NV12 or P010.
+pub fn download_hw_frame(
+    frame: &ffmpeg::frame::Video,
+) -> Result<ffmpeg::frame::Video, ffmpeg::Error> {
+    let mut downloaded = ffmpeg::frame::Video::empty();
+    let result =
+        unsafe { ffi::av_hwframe_transfer_data(downloaded.as_mut_ptr(), frame.as_ptr(), 0) };
+    if result < 0 {
+        return Err(ffmpeg::Error::from(result));
+    }
+    // Colour properties and interlacing flags aren't copied by the transfer
+    let result = unsafe { ffi::av_frame_copy_props(downloaded.as_mut_ptr(), frame.as_ptr()) };
+    if result < 0 {
+        return Err(ffmpeg::Error::from(result));
+    }
+
+    Ok(downloaded)
+}
@@ -0,0 +1,218 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+extern crate ffmpeg_the_third as ffmpeg;
+use std::sync::Mutex;
+
+use abi_stable::{
+    sabi_trait::TD_Opaque,
+    std_types::{ROption, RSlice, RString},
+};
+use anyhow::anyhow;
+use phaneron_plugin::{
+    traits::Node_TO, types::Node, types::NodeContext, types::ProcessFrameContext,
+    types::VideoFrame, types::VideoOutput, ColourRange, ColourSpace, VideoFormat,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageProducerState {
+    /// Path of a PNG or JPEG image, or any other still image FFmpeg can decode.
+    pub file: String,
+}
+
+pub fn state_schema() -> serde_json::Value {
+    serde_json::json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Image Producer",
+        "type": "object",
+        "properties": {
+            "file": {
+                "type": "string",
+                "description": "Path of the image to show, e.g. a PNG or JPEG"
+            }
+        },
+        "required": ["file"]
+    })
+}
+
+pub struct ImageProducerHandle {
+    node_id: String,
+}
+impl ImageProducerHandle {
+    pub(super) fn new(node_id: String) -> Self {
+        Self { node_id }
+    }
+}
+impl phaneron_plugin::traits::NodeHandle for ImageProducerHandle {
+    fn initialize(&self, context: NodeContext, _configuration: ROption<RString>) -> Node {
+        let node = ImageProducer::new(self.node_id.clone(), context);
+
+        Node_TO::from_value(node, TD_Opaque)
+    }
+}
+
+/// Shows a still image, e.g. a logo or slate. The image is decoded and loaded onto the GPU once
+/// when the file changes and the same frame is output on every frame of the graph.
+pub struct ImageProducer {
+    node_id: String,
+    context: NodeContext,
+    /// The file and the frame loaded from it, nothing is output until an image has been loaded.
+    image: Mutex<Option<(String, VideoFrame)>>,
+    video_output: VideoOutput,
+}
+
+impl ImageProducer {
+    pub fn new(node_id: String, context: NodeContext) -> Self {
+        let video_output = context.add_video_output();
+
+        Self {
+            node_id,
+            context,
+            image: Default::default(),
+            video_output,
+        }
+    }
+
+    fn load(&self, file: &str) -> Result<VideoFrame, anyhow::Error> {
+        let image = decode_image(file)?;
+        // Images are in sRGB, with the full range of code values
+        let to_rgba = self.context.create_to_rgba(
+            &VideoFormat::RGBA8,
+            &ColourSpace::sRGB.colour_spec(),
+            ColourRange::Full,
+            image.width,
+            image.height,
+        );
+        let inputs: Vec<RSlice<u8>> = vec![image.pixels.as_slice().into()];
+        let loaded_frame = to_rgba.load_frame(&inputs.as_slice().into());
+
+        Ok(to_rgba.process_frame(loaded_frame))
+    }
+}
+
+impl phaneron_plugin::traits::Node for ImageProducer {
+    fn apply_state(&self, state: RString) -> bool {
+        let state: ImageProducerState = match serde_json::from_str(&state) {
+            Ok(state) => state,
+            Err(err) => {
+                warn!(
+                    "Image producer {} received invalid state: {err}",
+                    self.node_id
+                );
+                return false;
+            }
+        };
+
+        let mut image = self.image.lock().unwrap();
+        if matches!(&*image, Some((file, _)) if *file == state.file) {
+            return true;
+        }
+        // The previous image is kept if the new one can't be loaded
+        match self.load(&state.file) {
+            Ok(frame) => {
+                info!(
+                    "Image producer {} loaded {} ({}x{})",
+                    self.node_id,
+                    state.file,
+                    frame.width(),
+                    frame.height()
+                );
+                *image = Some((state.file, frame));
+                true
+            }
+            Err(err) => {
+                warn!(
+                    "Image producer {} failed to load {}: {err}",
+                    self.node_id, state.file
+                );
+                false
+            }
+        }
+    }
+
+    fn process_frame(&self, context: ProcessFrameContext) {
+        let frame_context = context.submit().unwrap();
+
+        if let Some((_, frame)) = &*self.image.lock().unwrap() {
+            self.video_output
+                .push_frame(&frame_context, frame.clone())
+                .ok();
+        }
+    }
+}
+
+/// An image decoded to 8 bit RGBA without any padding between rows.
+struct DecodedImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+/// Decodes the first frame of the best video stream of `file`.
+fn decode_image(file: &str) -> Result<DecodedImage, anyhow::Error> {
+    let mut ictx = ffmpeg::format::input(&file)?;
+    let stream = ictx
+        .streams()
+        .best(ffmpeg::media::Type::Video)
+        .ok_or_else(|| anyhow!("No image found"))?;
+    let stream_index = stream.index();
+    let mut decoder = ffmpeg::codec::context::Context::from_parameters(stream.parameters())?
+        .decoder()
+        .video()?;
+
+    let mut decoded = ffmpeg::frame::Video::empty();
+    let mut has_frame = false;
+    for (stream, packet) in ictx.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+        decoder.send_packet(&packet)?;
+        if decoder.receive_frame(&mut decoded).is_ok() {
+            has_frame = true;
+            break;
+        }
+    }
+    if !has_frame {
+        // Some decoders only output the frame once they have been flushed
+        decoder.send_eof()?;
+        decoder.receive_frame(&mut decoded)?;
+    }
+
+    let mut rgba = ffmpeg::frame::Video::empty();
+    decoded
+        .converter(ffmpeg::format::Pixel::RGBA)?
+        .run(&decoded, &mut rgba)?;
+
+    let (width, height) = (rgba.width() as usize, rgba.height() as usize);
+    let pixels = rgba
+        .data(0)
+        .chunks(rgba.stride(0))
+        .take(height)
+        .flat_map(|row| &row[..width * 4])
+        .copied()
+        .collect();
+
+    Ok(DecodedImage {
+        width,
+        height,
+        pixels,
+    })
+}
@@ -16,7 +16,7 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use self::ffmpeg_producer::FFmpegProducerHandle;
+use self::{ffmpeg_producer::FFmpegProducerHandle, image_producer::ImageProducerHandle};
 
 use abi_stable::{
     export_root_module,
@@ -24,20 +24,25 @@ use abi_stable::{
     sabi_extern_fn,
     sabi_trait::TD_Opaque,
     std_types::{
-        RResult::{self, ROk},
+        RResult::{self, RErr, ROk},
         RString, RVec,
     },
 };
 use phaneron_plugin::{
     traits::NodeHandle_TO,
-    traits::{CreateNodeDescription, PhaneronPlugin_TO, PluginNodeDescription},
+    traits::{BuildInfo, CreateNodeDescription, PhaneronPlugin_TO, PluginNodeDescription},
     types::NodeHandle,
     types::PhaneronPlugin,
     PhaneronPluginContext, PhaneronPluginRootModule, PhaneronPluginRootModuleRef,
 };
 
 mod ffmpeg_producer;
-pub use ffmpeg_producer::FFmpegProducerState;
+mod hwaccel;
+mod image_producer;
+mod playback;
+mod timecode;
+pub use ffmpeg_producer::{Deinterlace, DeinterlaceMode, FFmpegProducerState, FieldOrder};
+pub use image_producer::ImageProducerState;
 
 #[export_root_module]
 fn instantiate_root_module() -> PhaneronPluginRootModuleRef {
@@ -55,20 +60,49 @@ pub fn load(context: PhaneronPluginContext) -> RResult<PhaneronPlugin, RString>
 struct FFmpegPlugin {}
 impl phaneron_plugin::traits::PhaneronPlugin for FFmpegPlugin {
     fn get_available_node_types(&self) -> RVec<PluginNodeDescription> {
-        vec![PluginNodeDescription {
-            id: "ffmpeg_producer".into(),
-            name: "FFmpeg producer".into(),
-        }]
+        vec![
+            PluginNodeDescription {
+                id: "ffmpeg_producer".into(),
+                name: "FFmpeg producer".into(),
+            },
+            PluginNodeDescription {
+                id: "image_producer".into(),
+                name: "Image producer".into(),
+            },
+        ]
         .into()
     }
 
     fn create_node(&self, description: CreateNodeDescription) -> RResult<NodeHandle, RString> {
-        let handle = FFmpegProducerHandle::new(description.node_id.into());
+        match description.node_type.as_str() {
+            "ffmpeg_producer" => {
+                let handle = FFmpegProducerHandle::new(description.node_id.into());
 
-        ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+                ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+            }
+            "image_producer" => {
+                let handle = ImageProducerHandle::new(description.node_id.into());
+
+                ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+            }
+            _ => RErr(format!("Unknown node type: {}", description.node_type).into()),
+        }
+    }
+
+    fn destroy_node(&self, _node_id: RString) -> RResult<(), RString> {
+        // Nodes don't share any resources with the plugin, they are released when the node is dropped
+        ROk(())
+    }
+
+    fn build_info(&self) -> BuildInfo {
+        phaneron_plugin::build_info!()
     }
 
-    fn destroy_node(&self, node_id: RString) -> RResult<(), RString> {
-        todo!()
+    fn get_node_state_schema(&self, node_type: RString) -> RResult<RString, RString> {
+        match node_type.as_str() {
+            "ffmpeg_producer" => ROk(ffmpeg_producer::state_schema().to_string().into()),
+            "image_producer" => ROk(image_producer::state_schema().to_string().into()),
+            _ => RErr(format!("Unknown node type: {node_type}").into()),
+        }
     }
 }
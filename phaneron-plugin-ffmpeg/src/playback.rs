@@ -0,0 +1,137 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::sync::{Condvar, Mutex};
+
+#[cfg(test)]
+mod tests;
+
+/// Playback controls shared between a producer and its reader and loader threads.
+///
+/// Every seek starts a new generation, frames loaded for an earlier generation are stale
+/// and are discarded instead of being output.
+#[derive(Default)]
+pub struct PlaybackControl {
+    inner: Mutex<PlaybackControlInner>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct PlaybackControlInner {
+    generation: u64,
+    pending_seek: Option<f64>,
+    loop_playback: bool,
+    mute_audio: bool,
+    closed: bool,
+}
+
+/// What the reader should do once it has reached the end of the file.
+pub enum EndAction {
+    /// Seek to a position in seconds, starting the given generation.
+    Seek(u64, f64),
+    /// The producer has been closed.
+    Close,
+}
+
+impl PlaybackControl {
+    /// Requests a seek to a position in seconds, returning the generation it starts.
+    pub fn seek(&self, position: f64) -> u64 {
+        let mut inner = self.inner.lock().unwrap();
+        inner.generation += 1;
+        inner.pending_seek = Some(position);
+        self.changed.notify_all();
+        inner.generation
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.inner.lock().unwrap().generation
+    }
+
+    /// Takes the pending seek, if any, as a generation and position in seconds.
+    pub fn take_seek(&self) -> Option<(u64, f64)> {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .pending_seek
+            .take()
+            .map(|position| (inner.generation, position))
+    }
+
+    pub fn has_pending_seek(&self) -> bool {
+        self.inner.lock().unwrap().pending_seek.is_some()
+    }
+
+    pub fn set_loop_playback(&self, loop_playback: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.loop_playback = loop_playback;
+        self.changed.notify_all();
+    }
+
+    pub fn loop_playback(&self) -> bool {
+        self.inner.lock().unwrap().loop_playback
+    }
+
+    /// Audio is muted while paused or playing at any rate other than 1.0.
+    pub fn set_mute_audio(&self, mute_audio: bool) {
+        self.inner.lock().unwrap().mute_audio = mute_audio;
+    }
+
+    pub fn mute_audio(&self) -> bool {
+        self.inner.lock().unwrap().mute_audio
+    }
+
+    /// Blocks the reader at the end of the file until it is asked to seek, looping is
+    /// enabled or the producer is closed. Enabling looping seeks back to the start in a
+    /// new generation, as the end of the file has already been output.
+    pub fn wait_at_end(&self) -> EndAction {
+        let mut inner = self.inner.lock().unwrap();
+        loop {
+            if inner.closed {
+                return EndAction::Close;
+            }
+            if let Some(position) = inner.pending_seek.take() {
+                return EndAction::Seek(inner.generation, position);
+            }
+            if inner.loop_playback {
+                inner.generation += 1;
+                return EndAction::Seek(inner.generation, 0.0);
+            }
+            inner = self.changed.wait(inner).unwrap();
+        }
+    }
+
+    pub fn close(&self) {
+        self.inner.lock().unwrap().closed = true;
+        self.changed.notify_all();
+    }
+}
+
+/// Converts a playback rate into the number of source frames to advance by for each
+/// output frame, repeating frames below 1.0 and dropping them above it.
+#[derive(Default)]
+pub struct RateCadence {
+    accumulated: f64,
+}
+
+impl RateCadence {
+    pub fn next_steps(&mut self, rate: f32) -> usize {
+        self.accumulated += rate.max(0.0) as f64;
+        let steps = self.accumulated.floor();
+        self.accumulated -= steps;
+        steps as usize
+    }
+}
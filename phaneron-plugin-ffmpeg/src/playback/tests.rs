@@ -0,0 +1,124 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{sync::Arc, time::Duration};
+
+use super::{EndAction, PlaybackControl, RateCadence};
+
+#[test]
+fn every_seek_starts_a_generation() {
+    let control = PlaybackControl::default();
+    assert_eq!(control.generation(), 0);
+    assert_eq!(control.take_seek(), None);
+
+    assert_eq!(control.seek(2.0), 1);
+    assert_eq!(control.seek(5.0), 2);
+    assert!(control.has_pending_seek());
+    // Only the latest seek is taken, the earlier one is superseded
+    assert_eq!(control.take_seek(), Some((2, 5.0)));
+    assert!(!control.has_pending_seek());
+    assert_eq!(control.generation(), 2);
+}
+
+#[test]
+fn end_takes_a_pending_seek() {
+    let control = PlaybackControl::default();
+    control.seek(3.0);
+    assert!(matches!(control.wait_at_end(), EndAction::Seek(1, position) if position == 3.0));
+    assert!(!control.has_pending_seek());
+}
+
+#[test]
+fn end_loops_to_the_start_in_a_new_generation() {
+    let control = PlaybackControl::default();
+    control.set_loop_playback(true);
+    assert!(matches!(control.wait_at_end(), EndAction::Seek(1, position) if position == 0.0));
+    assert_eq!(control.generation(), 1);
+}
+
+#[test]
+fn end_waits_until_closed() {
+    let control = Arc::new(PlaybackControl::default());
+    let reader = std::thread::spawn({
+        let control = control.clone();
+        move || control.wait_at_end()
+    });
+
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(!reader.is_finished());
+    control.close();
+    assert!(matches!(reader.join().unwrap(), EndAction::Close));
+}
+
+#[test]
+fn end_waits_for_looping_to_be_enabled() {
+    let control = Arc::new(PlaybackControl::default());
+    let reader = std::thread::spawn({
+        let control = control.clone();
+        move || control.wait_at_end()
+    });
+
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(!reader.is_finished());
+    control.set_loop_playback(true);
+    assert!(matches!(reader.join().unwrap(), EndAction::Seek(1, position) if position == 0.0));
+}
+
+#[test]
+fn close_wins_over_a_pending_seek() {
+    let control = PlaybackControl::default();
+    control.seek(1.0);
+    control.close();
+    assert!(matches!(control.wait_at_end(), EndAction::Close));
+}
+
+#[test]
+fn normal_rate_advances_a_frame_each_frame() {
+    let mut cadence = RateCadence::default();
+    assert!((0..10).all(|_| cadence.next_steps(1.0) == 1));
+}
+
+#[test]
+fn slow_rates_repeat_frames() {
+    let mut cadence = RateCadence::default();
+    let steps: Vec<usize> = (0..8).map(|_| cadence.next_steps(0.5)).collect();
+    assert_eq!(steps, [0, 1, 0, 1, 0, 1, 0, 1]);
+
+    let mut cadence = RateCadence::default();
+    let steps: usize = (0..100).map(|_| cadence.next_steps(0.25)).sum();
+    assert_eq!(steps, 25);
+}
+
+#[test]
+fn fast_rates_drop_frames() {
+    let mut cadence = RateCadence::default();
+    assert!((0..10).all(|_| cadence.next_steps(2.0) == 2));
+
+    let mut cadence = RateCadence::default();
+    let steps: Vec<usize> = (0..4).map(|_| cadence.next_steps(1.5)).collect();
+    assert_eq!(steps, [1, 2, 1, 2]);
+}
+
+#[test]
+fn paused_and_negative_rates_hold_the_frame() {
+    let mut cadence = RateCadence::default();
+    assert_eq!(cadence.next_steps(0.0), 0);
+    assert_eq!(cadence.next_steps(-1.0), 0);
+    // A negative rate doesn't build up a debt that delays playback once it resumes
+    assert_eq!(cadence.next_steps(1.0), 1);
+}
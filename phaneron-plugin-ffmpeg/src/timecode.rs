@@ -0,0 +1,69 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Source timecode, from the `timecode` tag FFmpeg exposes for files with a timecode track or header.
+
+use std::ffi::{c_char, CStr, CString};
+
+use ffmpeg_the_third::{self as ffmpeg, ffi};
+
+/// Size of the buffer needed by `av_timecode_make_string`, `AV_TIMECODE_STR_SIZE` in FFmpeg.
+const TIMECODE_STRING_SIZE: usize = 23;
+
+/// The timecode of the first frame of a stream, used to work out the timecode of later frames.
+pub struct SourceTimecode {
+    timecode: ffi::AVTimecode,
+}
+
+// AVTimecode is plain data
+unsafe impl Send for SourceTimecode {}
+
+impl SourceTimecode {
+    /// Parses a timecode tag such as `10:00:00:00`, or `10:00:00;00` for drop-frame timecode.
+    /// Returns `None` if the tag is not a valid timecode at the frame rate.
+    pub fn new(tag: &str, frame_rate: ffmpeg::Rational) -> Option<Self> {
+        let tag = CString::new(tag).ok()?;
+        let mut timecode = std::mem::MaybeUninit::<ffi::AVTimecode>::zeroed();
+        let result = unsafe {
+            ffi::av_timecode_init_from_string(
+                timecode.as_mut_ptr(),
+                frame_rate.into(),
+                tag.as_ptr(),
+                std::ptr::null_mut(),
+            )
+        };
+        if result < 0 {
+            return None;
+        }
+
+        Some(Self {
+            timecode: unsafe { timecode.assume_init() },
+        })
+    }
+
+    /// Timecode of a frame, counting frames from the start of the stream.
+    pub fn at(&self, frame_number: i32) -> String {
+        let mut buffer = [0 as c_char; TIMECODE_STRING_SIZE];
+        unsafe {
+            ffi::av_timecode_make_string(&self.timecode, buffer.as_mut_ptr(), frame_number);
+            CStr::from_ptr(buffer.as_ptr())
+                .to_string_lossy()
+                .into_owned()
+        }
+    }
+}
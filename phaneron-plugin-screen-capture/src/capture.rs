@@ -0,0 +1,162 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+use xcap::{Monitor, Window};
+
+#[cfg(test)]
+mod tests;
+
+/// The RGBA8 loader processes 64 pixels per work item, so frames are padded to a multiple of this.
+pub const WIDTH_ALIGNMENT: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum CaptureSource {
+    /// A display, by its index in the list of displays.
+    Display { index: usize },
+    /// The first window with a title that contains `title`.
+    Window { title: String },
+}
+
+impl Default for CaptureSource {
+    fn default() -> Self {
+        Self::Display { index: 0 }
+    }
+}
+
+impl Display for CaptureSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureSource::Display { index } => write!(f, "display {index}"),
+            CaptureSource::Window { title } => write!(f, "window \"{title}\""),
+        }
+    }
+}
+
+/// Area of the source to capture, in pixels from the top left of the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+#[derive(Debug)]
+pub enum CaptureError {
+    SourceDoesNotExist(CaptureSource),
+    /// The region doesn't overlap the captured source.
+    RegionOutsideSource(Region),
+    CaptureFailed(String),
+}
+
+impl Display for CaptureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureError::SourceDoesNotExist(source) => write!(f, "Could not find {source}"),
+            CaptureError::RegionOutsideSource(region) => write!(
+                f,
+                "Region {}x{} at {},{} is outside of the source",
+                region.width, region.height, region.x, region.y
+            ),
+            CaptureError::CaptureFailed(err) => write!(f, "Capture failed: {err}"),
+        }
+    }
+}
+
+/// An RGBA8 frame, which may be wider than what was captured due to padding.
+#[derive(Debug)]
+pub struct CapturedFrame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+/// Whether there is a display that can be captured.
+pub fn is_available() -> bool {
+    Monitor::all()
+        .map(|monitors| !monitors.is_empty())
+        .unwrap_or(false)
+}
+
+/// Captures the source, which is looked up each time so that windows that are closed and reopened,
+/// or that change size, are followed.
+pub fn capture(
+    source: &CaptureSource,
+    region: Option<Region>,
+) -> Result<CapturedFrame, CaptureError> {
+    let image = match source {
+        CaptureSource::Display { index } => Monitor::all()
+            .map_err(|err| CaptureError::CaptureFailed(err.to_string()))?
+            .get(*index)
+            .ok_or_else(|| CaptureError::SourceDoesNotExist(source.clone()))?
+            .capture_image(),
+        CaptureSource::Window { title } => Window::all()
+            .map_err(|err| CaptureError::CaptureFailed(err.to_string()))?
+            .iter()
+            .find(|window| !window.is_minimized() && window.title().contains(title.as_str()))
+            .ok_or_else(|| CaptureError::SourceDoesNotExist(source.clone()))?
+            .capture_image(),
+    }
+    .map_err(|err| CaptureError::CaptureFailed(err.to_string()))?;
+
+    crop_and_pad(
+        image.as_raw(),
+        image.width() as usize,
+        image.height() as usize,
+        region,
+    )
+}
+
+/// Copies the region out of an RGBA8 image, or all of it without a region, padding each row with
+/// opaque black up to a multiple of [`WIDTH_ALIGNMENT`]. Regions that extend past the image are clipped.
+pub fn crop_and_pad(
+    pixels: &[u8],
+    width: usize,
+    height: usize,
+    region: Option<Region>,
+) -> Result<CapturedFrame, CaptureError> {
+    let region = region.unwrap_or(Region {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    });
+    let source_width = region.width.min(width.saturating_sub(region.x));
+    let source_height = region.height.min(height.saturating_sub(region.y));
+    if source_width == 0 || source_height == 0 {
+        return Err(CaptureError::RegionOutsideSource(region));
+    }
+
+    let aligned_width = source_width.div_ceil(WIDTH_ALIGNMENT) * WIDTH_ALIGNMENT;
+    let mut frame = [0, 0, 0, 255].repeat(aligned_width * source_height);
+    for row in 0..source_height {
+        let from = ((region.y + row) * width + region.x) * 4;
+        let to = row * aligned_width * 4;
+        frame[to..to + source_width * 4].copy_from_slice(&pixels[from..from + source_width * 4]);
+    }
+
+    Ok(CapturedFrame {
+        width: aligned_width,
+        height: source_height,
+        pixels: frame,
+    })
+}
@@ -0,0 +1,101 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::{crop_and_pad, CaptureError, CaptureSource, Region, WIDTH_ALIGNMENT};
+
+/// An RGBA8 image where the red channel of each pixel is its x coordinate and green is its y coordinate.
+fn test_image(width: usize, height: usize) -> Vec<u8> {
+    (0..height)
+        .flat_map(|y| (0..width).flat_map(move |x| [x as u8, y as u8, 0, 255]))
+        .collect()
+}
+
+fn pixel(pixels: &[u8], width: usize, x: usize, y: usize) -> [u8; 4] {
+    let offset = (y * width + x) * 4;
+    pixels[offset..offset + 4].try_into().unwrap()
+}
+
+#[test]
+fn whole_image_is_padded_with_black() {
+    let frame = crop_and_pad(&test_image(100, 2), 100, 2, None).unwrap();
+
+    assert_eq!(frame.width, 2 * WIDTH_ALIGNMENT);
+    assert_eq!(frame.height, 2);
+    assert_eq!(frame.pixels.len(), frame.width * frame.height * 4);
+    assert_eq!(pixel(&frame.pixels, frame.width, 99, 1), [99, 1, 0, 255]);
+    assert_eq!(pixel(&frame.pixels, frame.width, 100, 1), [0, 0, 0, 255]);
+}
+
+#[test]
+fn region_is_cropped() {
+    let region = Region {
+        x: 10,
+        y: 5,
+        width: 64,
+        height: 3,
+    };
+    let frame = crop_and_pad(&test_image(100, 10), 100, 10, Some(region)).unwrap();
+
+    assert_eq!((frame.width, frame.height), (64, 3));
+    assert_eq!(pixel(&frame.pixels, frame.width, 0, 0), [10, 5, 0, 255]);
+    assert_eq!(pixel(&frame.pixels, frame.width, 63, 2), [73, 7, 0, 255]);
+}
+
+#[test]
+fn region_is_clipped_to_image() {
+    let region = Region {
+        x: 90,
+        y: 8,
+        width: 64,
+        height: 64,
+    };
+    let frame = crop_and_pad(&test_image(100, 10), 100, 10, Some(region)).unwrap();
+
+    assert_eq!((frame.width, frame.height), (WIDTH_ALIGNMENT, 2));
+    assert_eq!(pixel(&frame.pixels, frame.width, 9, 1), [99, 9, 0, 255]);
+    assert_eq!(pixel(&frame.pixels, frame.width, 10, 1), [0, 0, 0, 255]);
+}
+
+#[test]
+fn region_outside_image_is_an_error() {
+    let region = Region {
+        x: 100,
+        y: 0,
+        width: 10,
+        height: 10,
+    };
+    let result = crop_and_pad(&test_image(100, 10), 100, 10, Some(region));
+
+    assert!(matches!(result, Err(CaptureError::RegionOutsideSource(_))));
+}
+
+#[test]
+fn source_defaults_to_first_display() {
+    let source: CaptureSource =
+        serde_json::from_str(r#"{"type":"window","title":"Slides"}"#).unwrap();
+    assert_eq!(
+        source,
+        CaptureSource::Window {
+            title: "Slides".to_string()
+        }
+    );
+    assert_eq!(
+        CaptureSource::default(),
+        CaptureSource::Display { index: 0 }
+    );
+}
@@ -0,0 +1,100 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use abi_stable::{
+    export_root_module,
+    prefix_type::PrefixTypeTrait,
+    sabi_extern_fn,
+    sabi_trait::TD_Opaque,
+    std_types::{
+        RResult::{self, RErr, ROk},
+        RString, RVec,
+    },
+};
+use phaneron_plugin::{
+    traits::{BuildInfo, CreateNodeDescription, PhaneronPlugin_TO, PluginNodeDescription},
+    types::NodeHandle,
+    types::PhaneronPlugin,
+    PhaneronPluginContext, PhaneronPluginRootModule, PhaneronPluginRootModuleRef,
+};
+
+#[cfg(any(target_os = "linux", target_os = "windows", target_os = "macos"))]
+use phaneron_plugin::traits::NodeHandle_TO;
+
+#[cfg(any(target_os = "linux", target_os = "windows", target_os = "macos"))]
+use self::screen_capture_producer::ScreenCaptureProducerHandle;
+
+#[cfg(any(target_os = "linux", target_os = "windows", target_os = "macos"))]
+mod capture;
+#[cfg(any(target_os = "linux", target_os = "windows", target_os = "macos"))]
+mod screen_capture_producer;
+
+#[export_root_module]
+fn instantiate_root_module() -> PhaneronPluginRootModuleRef {
+    PhaneronPluginRootModule { load }.leak_into_prefix()
+}
+
+#[sabi_extern_fn]
+pub fn load(context: PhaneronPluginContext) -> RResult<PhaneronPlugin, RString> {
+    phaneron_plugin::get_logger(&context).init().unwrap();
+    let plugin = ScreenCapturePlugin {};
+
+    ROk(PhaneronPlugin_TO::from_value(plugin, TD_Opaque))
+}
+
+struct ScreenCapturePlugin {}
+impl phaneron_plugin::traits::PhaneronPlugin for ScreenCapturePlugin {
+    fn get_available_node_types(&self) -> RVec<PluginNodeDescription> {
+        // Capture is implemented for Linux (X11 and Wayland), Windows and macOS, the plugin loads elsewhere
+        // but provides no nodes. It also provides no nodes when there is no display to capture, e.g. on a
+        // headless server.
+        #[cfg(any(target_os = "linux", target_os = "windows", target_os = "macos"))]
+        let node_types = if capture::is_available() {
+            vec![PluginNodeDescription {
+                id: "screen_capture_producer".into(),
+                name: "Screen Capture Producer".into(),
+            }]
+        } else {
+            vec![]
+        };
+        #[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+        let node_types = vec![];
+
+        node_types.into()
+    }
+
+    fn create_node(&self, description: CreateNodeDescription) -> RResult<NodeHandle, RString> {
+        match description.node_type.as_str() {
+            #[cfg(any(target_os = "linux", target_os = "windows", target_os = "macos"))]
+            "screen_capture_producer" => {
+                let handle = ScreenCaptureProducerHandle::new(description.node_id.to_string());
+
+                ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+            }
+            _ => RErr(format!("Unknown node type: {}", description.node_type).into()),
+        }
+    }
+
+    fn destroy_node(&self, _node_id: RString) -> RResult<(), RString> {
+        ROk(())
+    }
+
+    fn build_info(&self) -> BuildInfo {
+        phaneron_plugin::build_info!()
+    }
+}
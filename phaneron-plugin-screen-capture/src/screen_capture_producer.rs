@@ -0,0 +1,211 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    sync::{mpsc::RecvTimeoutError, Arc, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use abi_stable::{
+    sabi_trait::TD_Opaque,
+    std_types::{ROption, RSlice, RString},
+};
+use phaneron_plugin::{
+    traits::Node_TO,
+    types::{Node, NodeContext, ProcessFrameContext, ToRGBA, VideoFrame, VideoOutput},
+    ColourRange, ColourSpace, VideoFormat,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::capture::{capture, CaptureSource, CapturedFrame, Region};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScreenCaptureState {
+    #[serde(default)]
+    pub source: CaptureSource,
+    /// Area of the source to capture, the whole source is captured when not set.
+    #[serde(default)]
+    pub region: Option<Region>,
+    /// Captures per second, frames are captured independently of the graph and the latest capture is
+    /// used for each frame, so this is usually the graph frame rate.
+    #[serde(default = "default_frame_rate")]
+    pub frame_rate: f64,
+}
+
+fn default_frame_rate() -> f64 {
+    25.0
+}
+
+pub struct ScreenCaptureProducerHandle {
+    node_id: String,
+}
+impl ScreenCaptureProducerHandle {
+    pub(super) fn new(node_id: String) -> Self {
+        Self { node_id }
+    }
+}
+impl phaneron_plugin::traits::NodeHandle for ScreenCaptureProducerHandle {
+    fn initialize(&self, context: NodeContext, _configuration: ROption<RString>) -> Node {
+        let node = ScreenCaptureProducer::new(self.node_id.clone(), context);
+
+        Node_TO::from_value(node, TD_Opaque)
+    }
+}
+
+struct CaptureThread {
+    /// Dropped to stop the thread.
+    _stop: std::sync::mpsc::Sender<()>,
+    _thread: JoinHandle<()>,
+}
+
+pub struct ScreenCaptureProducer {
+    node_id: String,
+    context: NodeContext,
+    state: Mutex<Option<ScreenCaptureState>>,
+    capture_thread: Mutex<Option<CaptureThread>>,
+    /// The most recent capture that has not been uploaded yet.
+    latest_capture: Arc<Mutex<Option<CapturedFrame>>>,
+    to_rgba: Mutex<Option<(usize, usize, ToRGBA)>>,
+    /// Repeated until a new capture is available.
+    last_frame: Mutex<Option<VideoFrame>>,
+    video_output: VideoOutput,
+}
+
+impl ScreenCaptureProducer {
+    pub fn new(node_id: String, context: NodeContext) -> Self {
+        let video_output = context.add_video_output();
+
+        Self {
+            node_id,
+            context,
+            state: Default::default(),
+            capture_thread: Default::default(),
+            latest_capture: Default::default(),
+            to_rgba: Default::default(),
+            last_frame: Default::default(),
+            video_output,
+        }
+    }
+}
+
+impl phaneron_plugin::traits::Node for ScreenCaptureProducer {
+    fn apply_state(&self, state: RString) -> bool {
+        let state: ScreenCaptureState = match serde_json::from_str(&state) {
+            Ok(state) => state,
+            Err(err) => {
+                error!("{}: Invalid state: {err}", self.node_id);
+                return false;
+            }
+        };
+        if state.frame_rate <= 0.0 {
+            error!("{}: frameRate must be greater than 0", self.node_id);
+            return false;
+        }
+
+        let mut current_state = self.state.lock().unwrap();
+        if current_state.as_ref() != Some(&state) {
+            info!("{}: Capturing {}", self.node_id, state.source);
+            let (stop, stop_rx) = std::sync::mpsc::channel();
+            let thread = std::thread::spawn({
+                let node_id = self.node_id.clone();
+                let state = state.clone();
+                let latest_capture = self.latest_capture.clone();
+                move || run_capture(node_id, state, latest_capture, stop_rx)
+            });
+            // Replacing the previous thread stops it
+            self.capture_thread.lock().unwrap().replace(CaptureThread {
+                _stop: stop,
+                _thread: thread,
+            });
+            *current_state = Some(state);
+        }
+
+        true
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let mut last_frame = self.last_frame.lock().unwrap();
+
+        if let Some(captured) = self.latest_capture.lock().unwrap().take() {
+            let mut to_rgba = self.to_rgba.lock().unwrap();
+            // The captured size changes when a captured window is resized
+            if !matches!(&*to_rgba, Some((w, h, _)) if *w == captured.width && *h == captured.height)
+            {
+                *to_rgba = Some((
+                    captured.width,
+                    captured.height,
+                    self.context.create_to_rgba(
+                        &VideoFormat::RGBA8,
+                        &ColourSpace::sRGB.colour_spec(),
+                        ColourRange::Full,
+                        captured.width,
+                        captured.height,
+                    ),
+                ));
+            }
+            let (_, _, to_rgba) = to_rgba.as_ref().unwrap();
+            let inputs: Vec<RSlice<u8>> = vec![captured.pixels.as_slice().into()];
+            let loaded_frame = to_rgba.load_frame(&inputs.as_slice().into());
+            last_frame.replace(to_rgba.process_frame(loaded_frame));
+        }
+
+        let frame = last_frame
+            .clone()
+            .unwrap_or_else(|| frame_context.get_black_frame().frame.clone());
+        let frame_context = frame_context.submit().unwrap();
+        self.video_output.push_frame(&frame_context, frame).ok();
+    }
+}
+
+/// Captures at the state's frame rate until `stop` is dropped.
+fn run_capture(
+    node_id: String,
+    state: ScreenCaptureState,
+    latest_capture: Arc<Mutex<Option<CapturedFrame>>>,
+    stop: std::sync::mpsc::Receiver<()>,
+) {
+    let interval = Duration::from_secs_f64(1.0 / state.frame_rate);
+    let mut failing = false;
+    loop {
+        let started = Instant::now();
+        match capture(&state.source, state.region) {
+            Ok(captured) => {
+                if failing {
+                    info!("{node_id}: Capturing {} again", state.source);
+                    failing = false;
+                }
+                latest_capture.lock().unwrap().replace(captured);
+            }
+            Err(err) => {
+                // Only logged once, a window may be closed for a while
+                if !failing {
+                    warn!("{node_id}: {err}");
+                    failing = true;
+                }
+            }
+        }
+
+        match stop.recv_timeout(interval.saturating_sub(started.elapsed())) {
+            Err(RecvTimeoutError::Timeout) => continue,
+            _ => return,
+        }
+    }
+}
@@ -0,0 +1,157 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+
+use serde::Serialize;
+
+#[cfg(test)]
+mod tests;
+
+/// Holds up to `depth` frames between the graph and an output clock.
+///
+/// Frames are only released once the buffer has filled up to its depth, after an underrun
+/// the buffer refills before releasing frames again. While the buffer is filling the last
+/// released frame is repeated.
+pub struct JitterBuffer<T> {
+    inner: Mutex<JitterBufferInner<T>>,
+    space_available: Condvar,
+}
+
+struct JitterBufferInner<T> {
+    frames: VecDeque<T>,
+    depth: usize,
+    filling: bool,
+    last: Option<T>,
+    released: u64,
+    repeated: u64,
+    underruns: u64,
+    closed: bool,
+}
+
+pub enum JitterBufferOutput<T> {
+    /// The next frame from the graph.
+    Next(T),
+    /// The last released frame, the buffer is filling.
+    Repeat(T),
+    /// Nothing has been released yet.
+    Empty,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JitterBufferStats {
+    pub depth: usize,
+    pub occupancy: usize,
+    pub released: u64,
+    pub repeated: u64,
+    pub underruns: u64,
+}
+
+impl<T: Clone> JitterBuffer<T> {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            inner: Mutex::new(JitterBufferInner {
+                frames: VecDeque::new(),
+                depth: depth.max(1),
+                filling: true,
+                last: None,
+                released: 0,
+                repeated: 0,
+                underruns: 0,
+                closed: false,
+            }),
+            space_available: Condvar::new(),
+        }
+    }
+
+    /// Adds a frame to the buffer, blocking while the buffer is full.
+    /// Frames pushed after the buffer has been closed are dropped.
+    pub fn push(&self, frame: T) {
+        let mut inner = self.inner.lock().unwrap();
+        while !inner.closed && inner.frames.len() >= inner.depth {
+            inner = self.space_available.wait(inner).unwrap();
+        }
+        if inner.closed {
+            return;
+        }
+        inner.frames.push_back(frame);
+        if inner.frames.len() >= inner.depth {
+            inner.filling = false;
+        }
+    }
+
+    /// Called on each tick of the output clock.
+    pub fn pop(&self) -> JitterBufferOutput<T> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.filling {
+            if let Some(frame) = inner.frames.pop_front() {
+                inner.last = Some(frame.clone());
+                inner.released += 1;
+                self.space_available.notify_one();
+                return JitterBufferOutput::Next(frame);
+            }
+
+            inner.filling = true;
+            inner.underruns += 1;
+        }
+
+        match inner.last.clone() {
+            Some(frame) => {
+                inner.repeated += 1;
+                JitterBufferOutput::Repeat(frame)
+            }
+            None => JitterBufferOutput::Empty,
+        }
+    }
+
+    /// Changes the number of frames that are buffered, frames beyond the new depth are dropped.
+    pub fn set_depth(&self, depth: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.depth = depth.max(1);
+        while inner.frames.len() > inner.depth {
+            inner.frames.pop_front();
+        }
+        if inner.frames.len() >= inner.depth {
+            inner.filling = false;
+        }
+        self.space_available.notify_one();
+    }
+
+    /// Stops the output clock from consuming the buffer, any blocked `push` returns.
+    pub fn close(&self) {
+        self.inner.lock().unwrap().closed = true;
+        self.space_available.notify_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.lock().unwrap().closed
+    }
+
+    pub fn stats(&self) -> JitterBufferStats {
+        let inner = self.inner.lock().unwrap();
+        JitterBufferStats {
+            depth: inner.depth,
+            occupancy: inner.frames.len(),
+            released: inner.released,
+            repeated: inner.repeated,
+            underruns: inner.underruns,
+        }
+    }
+}
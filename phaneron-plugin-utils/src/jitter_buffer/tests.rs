@@ -0,0 +1,76 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::{JitterBuffer, JitterBufferOutput};
+
+fn released(output: JitterBufferOutput<u32>) -> Option<u32> {
+    match output {
+        JitterBufferOutput::Next(frame) => Some(frame),
+        _ => None,
+    }
+}
+
+#[test]
+fn releases_frames_once_filled() {
+    let buffer = JitterBuffer::new(2);
+    assert!(matches!(buffer.pop(), JitterBufferOutput::Empty));
+
+    buffer.push(1);
+    assert!(matches!(buffer.pop(), JitterBufferOutput::Empty));
+
+    buffer.push(2);
+    assert_eq!(buffer.stats().occupancy, 2);
+    assert_eq!(released(buffer.pop()), Some(1));
+    assert_eq!(released(buffer.pop()), Some(2));
+}
+
+#[test]
+fn repeats_last_frame_on_underrun() {
+    let buffer = JitterBuffer::new(2);
+    buffer.push(1);
+    buffer.push(2);
+    buffer.pop();
+    buffer.pop();
+
+    assert!(matches!(buffer.pop(), JitterBufferOutput::Repeat(2)));
+    buffer.push(3);
+    assert!(matches!(buffer.pop(), JitterBufferOutput::Repeat(2)));
+    buffer.push(4);
+    assert_eq!(released(buffer.pop()), Some(3));
+
+    let stats = buffer.stats();
+    assert_eq!(stats.underruns, 1);
+    assert_eq!(stats.repeated, 2);
+    assert_eq!(stats.released, 3);
+}
+
+#[test]
+fn close_releases_a_blocked_push() {
+    let buffer = std::sync::Arc::new(JitterBuffer::new(1));
+    buffer.push(1);
+
+    let pusher = std::thread::spawn({
+        let buffer = buffer.clone();
+        move || buffer.push(2)
+    });
+    buffer.close();
+    pusher.join().unwrap();
+
+    assert!(buffer.is_closed());
+    assert_eq!(buffer.stats().occupancy, 1);
+}
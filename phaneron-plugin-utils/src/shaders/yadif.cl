@@ -101,6 +101,7 @@ __kernel void yadif(
     __private int parity,
     __private int tff,
     __private int skipSpatial,
+    __private int prediction,
     __write_only image2d_t output
 ) {
     int xo = get_global_id(0);
@@ -129,7 +130,15 @@ __kernel void yadif(
     float4 m = read_imagef(cur, sampler1, (int2) (xo + 2, yo + 1));
     float4 n = read_imagef(cur, sampler1, (int2) (xo + 3, yo + 1));
 
-    float4 spatialPred = spatial_predictor(a, b, c, d, e, f, g, h, i, j, k, l, m, n);
+    // 0 is temporal, 1 is spatial only and 2 is the average of the lines above and below (bob)
+    float4 spatialPred = (2 == prediction)
+        ? (d + k) / 2.0f
+        : spatial_predictor(a, b, c, d, e, f, g, h, i, j, k, l, m, n);
+    if (0 != prediction) {
+        spatialPred.s3 = read_imagef(cur, sampler1, (int2) (xo, yo)).s3;
+        write_imagef(output, (int2)(xo, yo), spatialPred);
+        return;
+    }
 
     // Calculate temporal prediction
     int isSecondField = !(parity ^ tff);
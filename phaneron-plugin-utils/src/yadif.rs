@@ -20,72 +20,144 @@ use std::collections::VecDeque;
 
 use phaneron_plugin::{traits::ProcessShader, types::NodeContext, types::VideoFrame, ShaderParams};
 
+#[cfg(test)]
+mod tests;
+
 #[derive(PartialEq, Eq)]
 pub enum YadifMode {
+    /// One output per field, doubling the frame rate.
     Field,
+    /// One output per frame, from the first field of each frame.
+    Frame,
     FrameNospatial,
     FieldNospatial,
 }
 
+/// How the lines of the missing field are predicted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum YadifPrediction {
+    /// Uses the previous and next frames as well as the current one, which delays the output by
+    /// one frame.
+    #[default]
+    Temporal,
+    /// Interpolates along edges within the current field, there is no delay.
+    Spatial,
+    /// Averages the lines above and below within the current field (bob), there is no delay.
+    Linear,
+}
+
 pub struct YadifConfig {
     pub mode: YadifMode,
+    /// Whether the top field is the first field in time.
     pub tff: bool,
+    pub prediction: YadifPrediction,
 }
 
 pub struct Yadif {
     config: YadifConfig,
-    send_field: bool,
     skip_spatial: bool,
     yadif_cl: YadifCl,
-    input: VecDeque<VideoFrame>,
+    queue: YadifQueue<VideoFrame>,
 }
 
 impl Yadif {
     pub fn new(context: &NodeContext, width: usize, height: usize, config: YadifConfig) -> Self {
-        let send_field =
-            config.mode == YadifMode::Field || config.mode == YadifMode::FieldNospatial;
         let skip_spatial =
             config.mode == YadifMode::FrameNospatial || config.mode == YadifMode::FieldNospatial;
         let yadif_cl = YadifCl::new(context, width, height);
+        let queue = YadifQueue::new(&config);
         Self {
             config,
-            send_field,
             skip_spatial,
             yadif_cl,
-            input: VecDeque::with_capacity(4), // 3 fields + last one pushed
+            queue,
         }
     }
 
+    /// Returns the deinterlaced frames for `source` in the order they should be shown, either
+    /// one per field or one per frame depending on the mode.
     pub fn run(&mut self, source: &VideoFrame) -> Vec<VideoFrame> {
-        self.input.push_front(source.clone());
-        if self.input.len() < 3 {
-            return vec![];
-        }
+        self.queue
+            .push(source.clone())
+            .into_iter()
+            .map(|pass| {
+                self.yadif_cl.run(
+                    &[&pass.prev, &pass.cur, &pass.next],
+                    pass.parity,
+                    u32::from(self.config.tff),
+                    u32::from(self.skip_spatial),
+                    self.config.prediction,
+                )
+            })
+            .collect()
+    }
+}
 
-        if self.input.len() > 3 {
-            self.input.pop_back();
-        }
+/// The frames and field used to produce one output frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct YadifPass<T> {
+    prev: T,
+    cur: T,
+    next: T,
+    /// Lines with `y % 2 == parity` are kept from `cur`, the other lines are predicted.
+    parity: u32,
+}
 
-        let mut outputs: Vec<VideoFrame> = vec![];
+/// Holds the frames needed for temporal prediction and works out the passes for each frame.
+struct YadifQueue<T> {
+    send_field: bool,
+    tff: bool,
+    temporal: bool,
+    /// Oldest first, 3 frames once enough have been pushed.
+    input: VecDeque<T>,
+}
 
-        let output = self.run_yadif(false);
-        outputs.push(output);
+impl<T: Clone> YadifQueue<T> {
+    fn new(config: &YadifConfig) -> Self {
+        Self {
+            send_field: config.mode == YadifMode::Field || config.mode == YadifMode::FieldNospatial,
+            tff: config.tff,
+            temporal: config.prediction == YadifPrediction::Temporal,
+            input: VecDeque::with_capacity(4), // 3 frames + last one pushed
+        }
+    }
 
-        if self.send_field {
-            let output = self.run_yadif(true);
-            outputs.push(output);
+    fn push(&mut self, frame: T) -> Vec<YadifPass<T>> {
+        if !self.temporal {
+            return self
+                .parities()
+                .map(|parity| YadifPass {
+                    prev: frame.clone(),
+                    cur: frame.clone(),
+                    next: frame.clone(),
+                    parity,
+                })
+                .collect();
+        }
+
+        self.input.push_back(frame);
+        if self.input.len() > 3 {
+            self.input.pop_front();
+        }
+        if self.input.len() < 3 {
+            return vec![];
         }
 
-        outputs
+        self.parities()
+            .map(|parity| YadifPass {
+                prev: self.input[0].clone(),
+                cur: self.input[1].clone(),
+                next: self.input[2].clone(),
+                parity,
+            })
+            .collect()
     }
 
-    fn run_yadif(&mut self, is_second: bool) -> VideoFrame {
-        self.yadif_cl.run(
-            self.input.iter().collect::<Vec<&VideoFrame>>().as_slice(),
-            u32::from(self.config.tff) ^ u32::from(!is_second),
-            u32::from(self.config.tff),
-            u32::from(self.skip_spatial),
-        )
+    /// The field kept from the current frame for each output, the first field first.
+    fn parities(&self) -> impl Iterator<Item = u32> {
+        let first = u32::from(!self.tff);
+        let fields = if self.send_field { 2 } else { 1 };
+        [first, 1 - first].into_iter().take(fields)
     }
 }
 
@@ -98,7 +170,9 @@ pub struct YadifCl {
 impl YadifCl {
     fn new(context: &NodeContext, width: usize, height: usize) -> Self {
         let kernel = include_str!("shaders/yadif.cl");
-        let shader = context.create_process_shader(kernel.into(), "yadif".into());
+        let shader = context
+            .create_process_shader(kernel.into(), "yadif".into())
+            .unwrap();
 
         Self {
             width,
@@ -107,7 +181,14 @@ impl YadifCl {
         }
     }
 
-    fn run(&self, inputs: &[&VideoFrame], parity: u32, tff: u32, skip_spatial: u32) -> VideoFrame {
+    fn run(
+        &self,
+        inputs: &[&VideoFrame],
+        parity: u32,
+        tff: u32,
+        skip_spatial: u32,
+        prediction: YadifPrediction,
+    ) -> VideoFrame {
         let mut params = ShaderParams::default();
         params.set_param_video_frame_input(inputs[0].clone());
         params.set_param_video_frame_input(inputs[1].clone());
@@ -115,6 +196,11 @@ impl YadifCl {
         params.set_param_u32_input(parity);
         params.set_param_u32_input(tff);
         params.set_param_u32_input(skip_spatial);
+        params.set_param_u32_input(match prediction {
+            YadifPrediction::Temporal => 0,
+            YadifPrediction::Spatial => 1,
+            YadifPrediction::Linear => 2,
+        });
         params.set_param_video_frame_output(self.width, self.height);
 
         let outputs = self.shader.run(params, &[self.width, self.height]);
@@ -0,0 +1,107 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::{YadifConfig, YadifMode, YadifPass, YadifPrediction, YadifQueue};
+
+fn queue(mode: YadifMode, tff: bool, prediction: YadifPrediction) -> YadifQueue<&'static str> {
+    YadifQueue::new(&YadifConfig {
+        mode,
+        tff,
+        prediction,
+    })
+}
+
+fn pass(
+    prev: &'static str,
+    cur: &'static str,
+    next: &'static str,
+    parity: u32,
+) -> YadifPass<&'static str> {
+    YadifPass {
+        prev,
+        cur,
+        next,
+        parity,
+    }
+}
+
+#[test]
+fn top_field_first_outputs_top_field_then_bottom_field() {
+    let mut queue = queue(YadifMode::Field, true, YadifPrediction::Temporal);
+
+    assert_eq!(queue.push("frame 0"), vec![]);
+    assert_eq!(queue.push("frame 1"), vec![]);
+    // Line 0 is in the top field, parity 0 keeps the top field of the current frame
+    assert_eq!(
+        queue.push("frame 2"),
+        vec![
+            pass("frame 0", "frame 1", "frame 2", 0),
+            pass("frame 0", "frame 1", "frame 2", 1),
+        ]
+    );
+    assert_eq!(
+        queue.push("frame 3"),
+        vec![
+            pass("frame 1", "frame 2", "frame 3", 0),
+            pass("frame 1", "frame 2", "frame 3", 1),
+        ]
+    );
+}
+
+#[test]
+fn bottom_field_first_outputs_bottom_field_then_top_field() {
+    let mut queue = queue(YadifMode::FieldNospatial, false, YadifPrediction::Temporal);
+
+    queue.push("frame 0");
+    queue.push("frame 1");
+    assert_eq!(
+        queue.push("frame 2"),
+        vec![
+            pass("frame 0", "frame 1", "frame 2", 1),
+            pass("frame 0", "frame 1", "frame 2", 0),
+        ]
+    );
+}
+
+#[test]
+fn frame_mode_outputs_first_field_only() {
+    let mut queue = queue(YadifMode::Frame, false, YadifPrediction::Temporal);
+
+    queue.push("frame 0");
+    queue.push("frame 1");
+    assert_eq!(
+        queue.push("frame 2"),
+        vec![pass("frame 0", "frame 1", "frame 2", 1)]
+    );
+}
+
+#[test]
+fn spatial_prediction_is_not_delayed() {
+    for prediction in [YadifPrediction::Spatial, YadifPrediction::Linear] {
+        let mut queue = queue(YadifMode::Frame, true, prediction);
+
+        assert_eq!(
+            queue.push("frame 0"),
+            vec![pass("frame 0", "frame 0", "frame 0", 0)]
+        );
+        assert_eq!(
+            queue.push("frame 1"),
+            vec![pass("frame 1", "frame 1", "frame 1", 0)]
+        );
+    }
+}
@@ -0,0 +1,359 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Minimal bindings for writing frames to a Video4Linux output device.
+
+use std::{
+    fmt::Display,
+    fs::{File, OpenOptions},
+    io::Write,
+    os::unix::io::AsRawFd,
+};
+
+use serde::{Deserialize, Serialize};
+
+const VIDIOC_QUERYCAP: u64 = ioc_read(0, std::mem::size_of::<V4L2Capability>());
+const VIDIOC_G_FMT: u64 = ioc_read_write(4, std::mem::size_of::<V4L2Format>());
+const VIDIOC_S_FMT: u64 = ioc_read_write(5, std::mem::size_of::<V4L2Format>());
+
+const V4L2_CAP_VIDEO_OUTPUT: u32 = 0x00000002;
+const V4L2_CAP_DEVICE_CAPS: u32 = 0x80000000;
+const V4L2_BUF_TYPE_VIDEO_OUTPUT: u32 = 2;
+const V4L2_FIELD_NONE: u32 = 1;
+const V4L2_COLORSPACE_REC709: u32 = 3;
+const V4L2_QUANTIZATION_LIM_RANGE: u32 = 2;
+
+const fn ioc(direction: u64, number: u64, size: usize) -> u64 {
+    (direction << 30) | ((size as u64) << 16) | ((b'V' as u64) << 8) | number
+}
+
+const fn ioc_read(number: u64, size: usize) -> u64 {
+    ioc(2, number, size)
+}
+
+const fn ioc_read_write(number: u64, size: usize) -> u64 {
+    ioc(3, number, size)
+}
+
+const fn fourcc(code: &[u8; 4]) -> u32 {
+    (code[0] as u32) | (code[1] as u32) << 8 | (code[2] as u32) << 16 | (code[3] as u32) << 24
+}
+
+// The structs below mirror the kernel's layout, not every field is used.
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Default)]
+struct V4L2Capability {
+    driver: [u8; 16],
+    card: [u8; 32],
+    bus_info: [u8; 32],
+    version: u32,
+    capabilities: u32,
+    device_caps: u32,
+    reserved: [u32; 3],
+}
+
+#[allow(dead_code)]
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct V4L2PixFormat {
+    width: u32,
+    height: u32,
+    pixelformat: u32,
+    field: u32,
+    bytesperline: u32,
+    sizeimage: u32,
+    colorspace: u32,
+    private: u32,
+    flags: u32,
+    ycbcr_enc: u32,
+    quantization: u32,
+    xfer_func: u32,
+}
+
+/// The kernel's format is a union of the format types, sized to 200 bytes and aligned
+/// to pointers because some of the union members contain pointers.
+#[allow(dead_code)]
+#[repr(C)]
+struct V4L2FormatUnion {
+    pix: V4L2PixFormat,
+    raw_data: [u8; 200 - std::mem::size_of::<V4L2PixFormat>()],
+    _align: [usize; 0],
+}
+
+#[repr(C)]
+struct V4L2Format {
+    buffer_type: u32,
+    format: V4L2FormatUnion,
+}
+
+impl V4L2Format {
+    fn new(pix: V4L2PixFormat) -> Self {
+        Self {
+            buffer_type: V4L2_BUF_TYPE_VIDEO_OUTPUT,
+            format: V4L2FormatUnion {
+                pix,
+                raw_data: [0; 200 - std::mem::size_of::<V4L2PixFormat>()],
+                _align: [],
+            },
+        }
+    }
+}
+
+/// Pixel formats that can be written to a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PixelFormat {
+    /// Packed 4:2:2, Y0 U Y1 V.
+    #[default]
+    Yuyv,
+    /// 4:2:0 with a Y plane followed by an interleaved UV plane.
+    Nv12,
+    /// Planar 4:2:0, Y then U then V.
+    Yuv420,
+}
+
+impl PixelFormat {
+    fn fourcc(&self) -> u32 {
+        match self {
+            PixelFormat::Yuyv => fourcc(b"YUYV"),
+            PixelFormat::Nv12 => fourcc(b"NV12"),
+            PixelFormat::Yuv420 => fourcc(b"YU12"),
+        }
+    }
+
+    fn from_fourcc(code: u32) -> Option<Self> {
+        [PixelFormat::Yuyv, PixelFormat::Nv12, PixelFormat::Yuv420]
+            .into_iter()
+            .find(|format| format.fourcc() == code)
+    }
+}
+
+/// The format that the driver agreed to, which may differ from the format that was requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NegotiatedFormat {
+    pub pixel_format: PixelFormat,
+    pub width: usize,
+    pub height: usize,
+    /// Bytes per line of the luma plane, may include padding.
+    pub bytes_per_line: usize,
+    pub size_image: usize,
+}
+
+impl NegotiatedFormat {
+    /// Packs planar Y, U and V planes into a frame of this format.
+    /// The planes must be 4:2:2 for YUYV and 4:2:0 otherwise.
+    pub fn pack(&self, planes: &[&[u8]]) -> Vec<u8> {
+        let (y, u, v) = (planes[0], planes[1], planes[2]);
+        let width = self.width;
+        let chroma_width = width / 2;
+        let mut frame = vec![0u8; self.size_image];
+
+        match self.pixel_format {
+            PixelFormat::Yuyv => {
+                for (row, line) in frame
+                    .chunks_exact_mut(self.bytes_per_line)
+                    .take(self.height)
+                    .enumerate()
+                {
+                    for x in 0..chroma_width {
+                        line[x * 4] = y[row * width + x * 2];
+                        line[x * 4 + 1] = u[row * chroma_width + x];
+                        line[x * 4 + 2] = y[row * width + x * 2 + 1];
+                        line[x * 4 + 3] = v[row * chroma_width + x];
+                    }
+                }
+            }
+            PixelFormat::Nv12 => {
+                let (luma, chroma) = frame.split_at_mut(self.bytes_per_line * self.height);
+                copy_rows(luma, self.bytes_per_line, y, width);
+                for (row, line) in chroma
+                    .chunks_exact_mut(self.bytes_per_line)
+                    .take(self.height / 2)
+                    .enumerate()
+                {
+                    for x in 0..chroma_width {
+                        line[x * 2] = u[row * chroma_width + x];
+                        line[x * 2 + 1] = v[row * chroma_width + x];
+                    }
+                }
+            }
+            PixelFormat::Yuv420 => {
+                let chroma_bytes_per_line = self.bytes_per_line / 2;
+                let chroma_plane_size = chroma_bytes_per_line * (self.height / 2);
+                let (luma, chroma) = frame.split_at_mut(self.bytes_per_line * self.height);
+                let (chroma_u, chroma_v) = chroma.split_at_mut(chroma_plane_size);
+                copy_rows(luma, self.bytes_per_line, y, width);
+                copy_rows(chroma_u, chroma_bytes_per_line, u, chroma_width);
+                copy_rows(chroma_v, chroma_bytes_per_line, v, chroma_width);
+            }
+        }
+
+        frame
+    }
+}
+
+fn copy_rows(destination: &mut [u8], destination_stride: usize, source: &[u8], width: usize) {
+    for (destination, source) in destination
+        .chunks_exact_mut(destination_stride)
+        .zip(source.chunks_exact(width))
+    {
+        destination[..width].copy_from_slice(source);
+    }
+}
+
+impl Display for NegotiatedFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} {}x{} ({} bytes per line, {} bytes per frame)",
+            self.pixel_format, self.width, self.height, self.bytes_per_line, self.size_image
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum V4L2Error {
+    DeviceDoesNotExist(String),
+    OpenFailed(String, std::io::Error),
+    NotAnOutputDevice(String),
+    IoctlFailed(&'static str, std::io::Error),
+    UnsupportedFormat(u32),
+    WriteFailed(std::io::Error),
+}
+
+impl Display for V4L2Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            V4L2Error::DeviceDoesNotExist(path) => write!(
+                f,
+                "V4L2 device {path} does not exist, is the v4l2loopback module loaded?"
+            ),
+            V4L2Error::OpenFailed(path, err) => write!(f, "Failed to open {path}: {err}"),
+            V4L2Error::NotAnOutputDevice(path) => {
+                write!(f, "{path} is not a V4L2 video output device")
+            }
+            V4L2Error::IoctlFailed(name, err) => write!(f, "{name} failed: {err}"),
+            V4L2Error::UnsupportedFormat(code) => write!(
+                f,
+                "Device negotiated unsupported pixel format {}",
+                String::from_utf8_lossy(&code.to_le_bytes())
+            ),
+            V4L2Error::WriteFailed(err) => write!(f, "Failed to write frame: {err}"),
+        }
+    }
+}
+
+/// An open V4L2 output device with a negotiated format.
+pub struct V4L2OutputDevice {
+    file: File,
+    format: NegotiatedFormat,
+}
+
+impl V4L2OutputDevice {
+    /// Opens the device and requests the given format, the driver may adjust the format.
+    pub fn open(
+        path: &str,
+        pixel_format: PixelFormat,
+        width: usize,
+        height: usize,
+    ) -> Result<Self, V4L2Error> {
+        let file = OpenOptions::new()
+            .write(true)
+            .open(path)
+            .map_err(|err| match err.kind() {
+                std::io::ErrorKind::NotFound => V4L2Error::DeviceDoesNotExist(path.to_string()),
+                _ => V4L2Error::OpenFailed(path.to_string(), err),
+            })?;
+
+        let mut capability = V4L2Capability::default();
+        ioctl(&file, VIDIOC_QUERYCAP, &mut capability, "VIDIOC_QUERYCAP")?;
+        let capabilities = if capability.capabilities & V4L2_CAP_DEVICE_CAPS != 0 {
+            capability.device_caps
+        } else {
+            capability.capabilities
+        };
+        if capabilities & V4L2_CAP_VIDEO_OUTPUT == 0 {
+            return Err(V4L2Error::NotAnOutputDevice(path.to_string()));
+        }
+
+        let mut format = V4L2Format::new(V4L2PixFormat {
+            width: width as u32,
+            height: height as u32,
+            pixelformat: pixel_format.fourcc(),
+            field: V4L2_FIELD_NONE,
+            colorspace: V4L2_COLORSPACE_REC709,
+            quantization: V4L2_QUANTIZATION_LIM_RANGE,
+            ..Default::default()
+        });
+        ioctl(&file, VIDIOC_S_FMT, &mut format, "VIDIOC_S_FMT")?;
+        ioctl(&file, VIDIOC_G_FMT, &mut format, "VIDIOC_G_FMT")?;
+
+        let pix = format.format.pix;
+        let negotiated_pixel_format = PixelFormat::from_fourcc(pix.pixelformat)
+            .ok_or(V4L2Error::UnsupportedFormat(pix.pixelformat))?;
+        let width = pix.width as usize;
+        let height = pix.height as usize;
+        let min_bytes_per_line = match negotiated_pixel_format {
+            PixelFormat::Yuyv => width * 2,
+            PixelFormat::Nv12 | PixelFormat::Yuv420 => width,
+        };
+        let bytes_per_line = (pix.bytesperline as usize).max(min_bytes_per_line);
+        let min_size_image = match negotiated_pixel_format {
+            PixelFormat::Yuyv => bytes_per_line * height,
+            PixelFormat::Nv12 | PixelFormat::Yuv420 => bytes_per_line * height * 3 / 2,
+        };
+
+        Ok(Self {
+            file,
+            format: NegotiatedFormat {
+                pixel_format: negotiated_pixel_format,
+                width,
+                height,
+                bytes_per_line,
+                size_image: (pix.sizeimage as usize).max(min_size_image),
+            },
+        })
+    }
+
+    pub fn format(&self) -> &NegotiatedFormat {
+        &self.format
+    }
+
+    /// Writes a frame that has been packed into the negotiated format.
+    pub fn write_frame(&mut self, frame: &[u8]) -> Result<(), V4L2Error> {
+        self.file.write_all(frame).map_err(V4L2Error::WriteFailed)
+    }
+}
+
+fn ioctl<T>(
+    file: &File,
+    request: u64,
+    argument: &mut T,
+    name: &'static str,
+) -> Result<(), V4L2Error> {
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), request as _, argument as *mut T) };
+    if result < 0 {
+        return Err(V4L2Error::IoctlFailed(
+            name,
+            std::io::Error::last_os_error(),
+        ));
+    }
+
+    Ok(())
+}
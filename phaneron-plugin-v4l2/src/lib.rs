@@ -0,0 +1,94 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use abi_stable::{
+    export_root_module,
+    prefix_type::PrefixTypeTrait,
+    sabi_extern_fn,
+    sabi_trait::TD_Opaque,
+    std_types::{
+        RResult::{self, RErr, ROk},
+        RString, RVec,
+    },
+};
+use phaneron_plugin::{
+    traits::{BuildInfo, CreateNodeDescription, PhaneronPlugin_TO, PluginNodeDescription},
+    types::NodeHandle,
+    types::PhaneronPlugin,
+    PhaneronPluginContext, PhaneronPluginRootModule, PhaneronPluginRootModuleRef,
+};
+
+#[cfg(target_os = "linux")]
+use phaneron_plugin::traits::NodeHandle_TO;
+
+#[cfg(target_os = "linux")]
+use self::v4l2_consumer::V4L2ConsumerHandle;
+
+#[cfg(target_os = "linux")]
+mod device;
+#[cfg(target_os = "linux")]
+mod v4l2_consumer;
+
+#[export_root_module]
+fn instantiate_root_module() -> PhaneronPluginRootModuleRef {
+    PhaneronPluginRootModule { load }.leak_into_prefix()
+}
+
+#[sabi_extern_fn]
+pub fn load(context: PhaneronPluginContext) -> RResult<PhaneronPlugin, RString> {
+    phaneron_plugin::get_logger(&context).init().unwrap();
+    let plugin = V4L2Plugin {};
+
+    ROk(PhaneronPlugin_TO::from_value(plugin, TD_Opaque))
+}
+
+struct V4L2Plugin {}
+impl phaneron_plugin::traits::PhaneronPlugin for V4L2Plugin {
+    fn get_available_node_types(&self) -> RVec<PluginNodeDescription> {
+        // Video4Linux is only available on Linux, the plugin loads elsewhere but provides no nodes.
+        #[cfg(target_os = "linux")]
+        let node_types = vec![PluginNodeDescription {
+            id: "v4l2_consumer".into(),
+            name: "V4L2 Consumer".into(),
+        }];
+        #[cfg(not(target_os = "linux"))]
+        let node_types = vec![];
+
+        node_types.into()
+    }
+
+    fn create_node(&self, description: CreateNodeDescription) -> RResult<NodeHandle, RString> {
+        match description.node_type.as_str() {
+            #[cfg(target_os = "linux")]
+            "v4l2_consumer" => {
+                let handle = V4L2ConsumerHandle::new(description.node_id.to_string());
+
+                ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+            }
+            _ => RErr(format!("Unknown node type: {}", description.node_type).into()),
+        }
+    }
+
+    fn destroy_node(&self, _node_id: RString) -> RResult<(), RString> {
+        ROk(())
+    }
+
+    fn build_info(&self) -> BuildInfo {
+        phaneron_plugin::build_info!()
+    }
+}
@@ -0,0 +1,201 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::sync::Mutex;
+
+use abi_stable::{
+    sabi_trait::TD_Opaque,
+    std_types::{ROption, RString},
+};
+use phaneron_plugin::{
+    traits::Node_TO, types::FromRGBA, types::Node, types::NodeContext, types::ProcessFrameContext,
+    ColourRange, ColourSpace, InterlaceMode, VideoFormat, VideoInputId,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::device::{NegotiatedFormat, PixelFormat, V4L2OutputDevice};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct V4L2ConsumerState {
+    /// Path to the output device, e.g. `/dev/video10`.
+    pub device: String,
+    #[serde(default)]
+    pub format: PixelFormat,
+    #[serde(default = "default_width")]
+    pub width: usize,
+    #[serde(default = "default_height")]
+    pub height: usize,
+}
+
+fn default_width() -> usize {
+    1920
+}
+
+fn default_height() -> usize {
+    1080
+}
+
+pub struct V4L2ConsumerHandle {
+    node_id: String,
+}
+impl V4L2ConsumerHandle {
+    pub(super) fn new(node_id: String) -> Self {
+        Self { node_id }
+    }
+}
+impl phaneron_plugin::traits::NodeHandle for V4L2ConsumerHandle {
+    fn initialize(&self, context: NodeContext, _configuration: ROption<RString>) -> Node {
+        let node = V4L2Consumer::new(self.node_id.clone(), context);
+
+        Node_TO::from_value(node, TD_Opaque)
+    }
+}
+
+enum V4L2Output {
+    /// The device will be opened on the next frame.
+    Pending,
+    Open {
+        device: V4L2OutputDevice,
+        from_rgba: FromRGBA,
+    },
+    /// Opening or writing to the device failed, nothing is retried until new state is applied.
+    Failed,
+}
+
+pub struct V4L2Consumer {
+    node_id: String,
+    context: NodeContext,
+    state: Mutex<Option<V4L2ConsumerState>>,
+    output: Mutex<V4L2Output>,
+    input_id: VideoInputId,
+}
+
+impl V4L2Consumer {
+    pub fn new(node_id: String, context: NodeContext) -> Self {
+        let input_id = context.add_video_input();
+
+        Self {
+            node_id,
+            context,
+            state: Default::default(),
+            output: Mutex::new(V4L2Output::Pending),
+            input_id,
+        }
+    }
+
+    fn open(&self, state: &V4L2ConsumerState) -> V4L2Output {
+        let device =
+            match V4L2OutputDevice::open(&state.device, state.format, state.width, state.height) {
+                Ok(device) => device,
+                Err(err) => {
+                    error!("{}: {err}", self.node_id);
+                    return V4L2Output::Failed;
+                }
+            };
+
+        let format = *device.format();
+        info!("{}: Opened {} as {format}", self.node_id, state.device);
+        self.report_format(&format);
+        let video_format = match format.pixel_format {
+            PixelFormat::Yuyv => VideoFormat::YUV422p8,
+            PixelFormat::Nv12 | PixelFormat::Yuv420 => VideoFormat::YUV420p,
+        };
+        let from_rgba = self.context.create_from_rgba(
+            &video_format,
+            &ColourSpace::BT_709.colour_spec(),
+            ColourRange::Limited,
+            format.width,
+            format.height,
+            InterlaceMode::Progressive,
+        );
+
+        V4L2Output::Open { device, from_rgba }
+    }
+
+    fn report_format(&self, format: &NegotiatedFormat) {
+        self.context.report_state(
+            serde_json::json!({
+                "negotiatedFormat": {
+                    "format": format.pixel_format,
+                    "width": format.width,
+                    "height": format.height,
+                }
+            })
+            .to_string()
+            .into(),
+        );
+    }
+}
+
+impl phaneron_plugin::traits::Node for V4L2Consumer {
+    fn apply_state(&self, state: RString) -> bool {
+        let state: V4L2ConsumerState = match serde_json::from_str(&state) {
+            Ok(state) => state,
+            Err(err) => {
+                error!("{}: Invalid state: {err}", self.node_id);
+                return false;
+            }
+        };
+
+        let mut current_state = self.state.lock().unwrap();
+        let mut output = self.output.lock().unwrap();
+        if current_state.as_ref() != Some(&state) {
+            *output = V4L2Output::Pending;
+            *current_state = Some(state);
+        } else if let V4L2Output::Open { device, .. } = &*output {
+            // Applying state replaces what was reported, the device stays open so report it again
+            self.report_format(device.format());
+        }
+
+        true
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let state = match self.state.lock().unwrap().clone() {
+            Some(state) => state,
+            None => return,
+        };
+
+        let mut output = self.output.lock().unwrap();
+        if let V4L2Output::Pending = *output {
+            *output = self.open(&state);
+        }
+        let (device, from_rgba) = match &mut *output {
+            V4L2Output::Open { device, from_rgba } => (device, from_rgba),
+            _ => return,
+        };
+
+        let frame = frame_context
+            .get_video_input(&self.input_id)
+            .unwrap_or(frame_context.get_black_frame())
+            .clone();
+        let frame = from_rgba.process_frame(&frame_context, frame.frame);
+
+        let copy_context = frame_context.submit().unwrap();
+        let planes = copy_context.copy_video_frame(from_rgba, frame);
+        let planes: Vec<&[u8]> = planes.iter().map(|plane| plane.as_slice()).collect();
+        let frame = device.format().pack(&planes);
+
+        if let Err(err) = device.write_frame(&frame) {
+            error!("{}: {err}", self.node_id);
+            *output = V4L2Output::Failed;
+        }
+    }
+}
@@ -0,0 +1,110 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use openh264::encoder::{Encoder, EncoderConfig};
+use openh264::formats::YUVSource;
+
+/// Encodes frames of planar YUV 4:2:0, as read back by the WebRTC consumer, into H.264 Annex B.
+pub struct H264Encoder {
+    encoder: Encoder,
+    width: usize,
+    height: usize,
+}
+
+impl H264Encoder {
+    pub fn new(
+        width: usize,
+        height: usize,
+        bitrate_kbps: u32,
+        frame_rate: f32,
+    ) -> Result<Self, openh264::Error> {
+        let config = EncoderConfig::new(width as u32, height as u32)
+            .set_bitrate_bps(bitrate_kbps * 1000)
+            .max_frame_rate(frame_rate);
+
+        Ok(Self {
+            encoder: Encoder::with_config(config)?,
+            width,
+            height,
+        })
+    }
+
+    pub fn encode(&mut self, data: &[u8]) -> Result<Vec<u8>, openh264::Error> {
+        let frame = YUV420pFrame {
+            data,
+            width: self.width,
+            height: self.height,
+        };
+        let bitstream = self.encoder.encode(&frame)?;
+
+        Ok(bitstream.to_vec())
+    }
+}
+
+/// Planes written back-to-back, each line of luma is padded to a multiple of 8 bytes.
+struct YUV420pFrame<'a> {
+    data: &'a [u8],
+    width: usize,
+    height: usize,
+}
+
+impl YUV420pFrame<'_> {
+    fn luma_pitch(&self) -> usize {
+        (self.width + 7) / 8 * 8
+    }
+
+    fn luma_bytes(&self) -> usize {
+        self.luma_pitch() * self.height
+    }
+}
+
+impl YUVSource for YUV420pFrame<'_> {
+    fn width(&self) -> i32 {
+        self.width as i32
+    }
+
+    fn height(&self) -> i32 {
+        self.height as i32
+    }
+
+    fn y(&self) -> &[u8] {
+        &self.data[..self.luma_bytes()]
+    }
+
+    fn u(&self) -> &[u8] {
+        let start = self.luma_bytes();
+        &self.data[start..start + self.luma_bytes() / 4]
+    }
+
+    fn v(&self) -> &[u8] {
+        let start = self.luma_bytes() + self.luma_bytes() / 4;
+        &self.data[start..start + self.luma_bytes() / 4]
+    }
+
+    fn y_stride(&self) -> i32 {
+        self.luma_pitch() as i32
+    }
+
+    fn u_stride(&self) -> i32 {
+        (self.luma_pitch() / 2) as i32
+    }
+
+    fn v_stride(&self) -> i32 {
+        (self.luma_pitch() / 2) as i32
+    }
+}
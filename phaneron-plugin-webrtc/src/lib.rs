@@ -16,8 +16,10 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-#[macro_use]
-extern crate lazy_static;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
 
 use abi_stable::{
     export_root_module,
@@ -25,22 +27,29 @@ use abi_stable::{
     sabi_extern_fn,
     sabi_trait::TD_Opaque,
     std_types::{
-        RResult::{self, ROk},
+        RResult::{self, RErr, ROk},
         RString, RVec,
     },
 };
 use phaneron_plugin::{
-    traits::{CreateNodeDescription, PhaneronPlugin_TO},
+    traits::{BuildInfo, CreateNodeDescription, PhaneronPlugin_TO},
     traits::{NodeHandle_TO, PluginNodeDescription},
     types::NodeHandle,
     types::PhaneronPlugin,
     PhaneronPluginContext, PhaneronPluginRootModule, PhaneronPluginRootModuleRef,
 };
 
-use self::webrtc_consumer::WebRTCConsumerHandle;
+use self::{mjpeg_consumer::MjpegConsumerHandle, webrtc_consumer::WebRTCConsumerHandle};
 
+#[cfg(feature = "h264")]
+mod h264;
+mod mjpeg_consumer;
+mod trickle_ice;
 mod webrtc_consumer;
 
+/// Shuts down the runtime and threads started by a node, keyed by node id.
+pub(crate) type NodeShutdowns = Arc<Mutex<HashMap<String, Box<dyn FnOnce() + Send>>>>;
+
 #[export_root_module]
 fn instantiate_root_module() -> PhaneronPluginRootModuleRef {
     PhaneronPluginRootModule { load }.leak_into_prefix()
@@ -49,28 +58,58 @@ fn instantiate_root_module() -> PhaneronPluginRootModuleRef {
 #[sabi_extern_fn]
 pub fn load(context: PhaneronPluginContext) -> RResult<PhaneronPlugin, RString> {
     phaneron_plugin::get_logger(&context).init().unwrap();
-    let plugin = WebRTCPlugin {};
+    let plugin = WebRTCPlugin {
+        shutdowns: Default::default(),
+    };
 
     ROk(PhaneronPlugin_TO::from_value(plugin, TD_Opaque))
 }
 
-struct WebRTCPlugin {}
+struct WebRTCPlugin {
+    shutdowns: NodeShutdowns,
+}
 impl phaneron_plugin::traits::PhaneronPlugin for WebRTCPlugin {
     fn get_available_node_types(&self) -> RVec<PluginNodeDescription> {
-        vec![PluginNodeDescription {
-            id: "webrtc_consumer".into(),
-            name: "WebRTC Consumer".into(),
-        }]
+        vec![
+            PluginNodeDescription {
+                id: "webrtc_consumer".into(),
+                name: "WebRTC Consumer".into(),
+            },
+            PluginNodeDescription {
+                id: "mjpeg_consumer".into(),
+                name: "MJPEG Consumer".into(),
+            },
+        ]
         .into()
     }
 
     fn create_node(&self, description: CreateNodeDescription) -> RResult<NodeHandle, RString> {
-        let handle = WebRTCConsumerHandle::new(description.node_id.into());
-
-        ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+        match description.node_type.as_str() {
+            "webrtc_consumer" => {
+                let handle =
+                    WebRTCConsumerHandle::new(description.node_id.into(), self.shutdowns.clone());
+                ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+            }
+            "mjpeg_consumer" => {
+                let handle =
+                    MjpegConsumerHandle::new(description.node_id.into(), self.shutdowns.clone());
+                ROk(NodeHandle_TO::from_value(handle, TD_Opaque))
+            }
+            _ => RErr(format!("Unknown node type: {}", description.node_type).into()),
+        }
     }
 
     fn destroy_node(&self, node_id: RString) -> RResult<(), RString> {
-        todo!()
+        // Nodes that were never initialized have nothing to shut down
+        let shutdown = self.shutdowns.lock().unwrap().remove(node_id.as_str());
+        if let Some(shutdown) = shutdown {
+            shutdown();
+        }
+
+        ROk(())
+    }
+
+    fn build_info(&self) -> BuildInfo {
+        phaneron_plugin::build_info!()
     }
 }
@@ -0,0 +1,332 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::convert::Infallible;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
+
+use abi_stable::sabi_trait::TD_Opaque;
+use abi_stable::std_types::{ROption, RString, RVec};
+use axum::body::{Bytes, StreamBody};
+use axum::extract::State;
+use axum::http::{header, HeaderValue, Method};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use jpeg_encoder::{ColorType, Encoder};
+use phaneron_plugin::types::{FromRGBA, NodeContext};
+use phaneron_plugin::{
+    traits::Node_TO, types::Node, types::ProcessFrameContext, ColourRange, ColourSpace,
+    InterlaceMode, VideoFormat, VideoInputId,
+};
+use serde::Deserialize;
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+use tower_http::cors::{Any, CorsLayer};
+use tracing::{error, info};
+
+use crate::NodeShutdowns;
+
+#[cfg(test)]
+mod tests;
+
+const DEFAULT_PORT: u16 = 9092;
+const BOUNDARY: &str = "frame";
+const CONTENT_TYPE: &str = "multipart/x-mixed-replace; boundary=frame";
+
+pub struct MjpegConsumerHandle {
+    node_id: String,
+    shutdowns: NodeShutdowns,
+}
+impl MjpegConsumerHandle {
+    pub(super) fn new(node_id: String, shutdowns: NodeShutdowns) -> Self {
+        Self { node_id, shutdowns }
+    }
+}
+impl phaneron_plugin::traits::NodeHandle for MjpegConsumerHandle {
+    fn initialize(&self, context: NodeContext, configuration: ROption<RString>) -> Node {
+        let configuration: MjpegConsumerConfiguration = configuration
+            .into_option()
+            .map(|configuration| serde_json::from_str(&configuration).unwrap())
+            .unwrap_or_default();
+        let node = MjpegConsumer::new(
+            self.node_id.clone(),
+            context,
+            configuration,
+            &self.shutdowns,
+        );
+
+        Node_TO::from_value(node, TD_Opaque)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct MjpegConsumerConfiguration {
+    /// Port that the stream is served on, each MJPEG consumer needs its own port.
+    port: u16,
+}
+
+impl Default for MjpegConsumerConfiguration {
+    fn default() -> Self {
+        Self { port: DEFAULT_PORT }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct MjpegConsumerState {
+    /// JPEG quality from 1 to 100.
+    quality: u8,
+    /// Maximum number of frames per second sent to viewers, frames in between are skipped.
+    frame_rate: u32,
+}
+
+impl Default for MjpegConsumerState {
+    fn default() -> Self {
+        Self {
+            quality: 80,
+            frame_rate: 25,
+        }
+    }
+}
+
+/// An RGBA8 frame that has been read back from the GPU and is waiting to be encoded.
+struct RawFrame {
+    width: usize,
+    height: usize,
+    quality: u8,
+    pixels: RVec<u8>,
+}
+
+pub struct MjpegConsumer {
+    node_id: String,
+    context: NodeContext,
+    state: Mutex<MjpegConsumerState>,
+    from_rgba: Mutex<Option<(usize, usize, FromRGBA)>>,
+    last_frame_sent: Mutex<Option<Instant>>,
+    raw_frame_tx: mpsc::SyncSender<RawFrame>,
+    jpeg_tx: tokio::sync::broadcast::Sender<Bytes>,
+    video_input: VideoInputId,
+}
+
+impl MjpegConsumer {
+    fn new(
+        node_id: String,
+        context: NodeContext,
+        configuration: MjpegConsumerConfiguration,
+        shutdowns: &NodeShutdowns,
+    ) -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let (terminate_sender, terminate_receiver) = tokio::sync::oneshot::channel::<()>();
+        std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            let handle = runtime.handle();
+            sender.send(handle.clone()).unwrap();
+            runtime.block_on(terminate_receiver).ok();
+        });
+
+        let handle = receiver.recv().unwrap();
+
+        // Viewers that fall behind skip to the latest frame
+        let (jpeg_tx, _) = tokio::sync::broadcast::channel(1);
+        handle.spawn(serve_web_server(configuration.port, jpeg_tx.clone()));
+
+        // Encoding happens off the graph thread, frames are dropped while the encoder is busy
+        let (raw_frame_tx, raw_frame_rx) = mpsc::sync_channel(1);
+        std::thread::spawn({
+            let node_id = node_id.clone();
+            let jpeg_tx = jpeg_tx.clone();
+            move || run_encoder(node_id, raw_frame_rx, jpeg_tx)
+        });
+
+        // Stopping the runtime stops the server, the encoder stops once the node is dropped
+        shutdowns.lock().unwrap().insert(
+            node_id.clone(),
+            Box::new(move || {
+                terminate_sender.send(()).ok();
+            }),
+        );
+
+        let video_input = context.add_video_input();
+
+        Self {
+            node_id,
+            context,
+            state: Default::default(),
+            from_rgba: Default::default(),
+            last_frame_sent: Default::default(),
+            raw_frame_tx,
+            jpeg_tx,
+            video_input,
+        }
+    }
+}
+
+impl phaneron_plugin::traits::Node for MjpegConsumer {
+    fn apply_state(&self, state: RString) -> bool {
+        let state: MjpegConsumerState = match serde_json::from_str(&state) {
+            Ok(state) => state,
+            Err(err) => {
+                error!("{}: Invalid state: {err}", self.node_id);
+                return false;
+            }
+        };
+        if !(1..=100).contains(&state.quality) {
+            error!("{}: quality must be between 1 and 100", self.node_id);
+            return false;
+        }
+        if state.frame_rate == 0 {
+            error!("{}: frameRate must be at least 1", self.node_id);
+            return false;
+        }
+
+        *self.state.lock().unwrap() = state;
+
+        true
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let (quality, frame_interval) = {
+            let state = self.state.lock().unwrap();
+            (state.quality, Duration::from_secs(1) / state.frame_rate)
+        };
+        let mut last_frame_sent = self.last_frame_sent.lock().unwrap();
+        let due = last_frame_sent.map_or(true, |sent| sent.elapsed() >= frame_interval);
+        // Nothing is read back from the GPU unless someone is watching
+        if !due || self.jpeg_tx.receiver_count() == 0 {
+            frame_context.submit().unwrap();
+            return;
+        }
+
+        let frame = frame_context
+            .get_video_input(&self.video_input)
+            .unwrap_or(frame_context.get_black_frame())
+            .frame
+            .clone();
+        let (width, height) = (frame.width(), frame.height());
+
+        let mut from_rgba = self.from_rgba.lock().unwrap();
+        if !matches!(&*from_rgba, Some((w, h, _)) if *w == width && *h == height) {
+            *from_rgba = Some((
+                width,
+                height,
+                self.context.create_from_rgba(
+                    &VideoFormat::RGBA8,
+                    &ColourSpace::sRGB.colour_spec(),
+                    ColourRange::Full,
+                    width,
+                    height,
+                    InterlaceMode::Progressive,
+                ),
+            ));
+        }
+        let (_, _, from_rgba) = from_rgba.as_ref().unwrap();
+        let frame = from_rgba.process_frame(&frame_context, frame);
+
+        let copy_context = frame_context.submit().unwrap();
+        let pixels = copy_context.copy_video_frame_contiguous(from_rgba, frame);
+
+        last_frame_sent.replace(Instant::now());
+        self.raw_frame_tx
+            .try_send(RawFrame {
+                width,
+                height,
+                quality,
+                pixels,
+            })
+            .ok();
+    }
+}
+
+fn run_encoder(
+    node_id: String,
+    raw_frame_rx: mpsc::Receiver<RawFrame>,
+    jpeg_tx: tokio::sync::broadcast::Sender<Bytes>,
+) {
+    while let Ok(frame) = raw_frame_rx.recv() {
+        let mut jpeg = vec![];
+        let encoder = Encoder::new(&mut jpeg, frame.quality);
+        if let Err(err) = encoder.encode(
+            &frame.pixels,
+            frame.width as u16,
+            frame.height as u16,
+            ColorType::Rgba,
+        ) {
+            error!("{node_id}: Failed to encode frame: {err}");
+            continue;
+        }
+
+        jpeg_tx.send(multipart_frame(&jpeg)).ok();
+    }
+}
+
+/// Wraps a JPEG image in a part of a `multipart/x-mixed-replace` response.
+fn multipart_frame(jpeg: &[u8]) -> Bytes {
+    let mut part = format!(
+        "--{BOUNDARY}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+        jpeg.len()
+    )
+    .into_bytes();
+    part.extend_from_slice(jpeg);
+    part.extend_from_slice(b"\r\n");
+
+    part.into()
+}
+
+async fn serve_web_server(port: u16, jpeg_tx: tokio::sync::broadcast::Sender<Bytes>) {
+    let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port));
+    info!("Serving MJPEG stream on {}", addr);
+    let _ = axum::Server::bind(&addr)
+        .serve(app(jpeg_tx).into_make_service())
+        .await;
+}
+
+// The stream is long-lived, so this doesn't use the timeout and compression layers of the WebRTC server
+fn app(jpeg_tx: tokio::sync::broadcast::Sender<Bytes>) -> Router {
+    let cors = CorsLayer::new()
+        .allow_methods(vec![Method::GET, Method::OPTIONS])
+        .allow_headers(Any)
+        .allow_origin(Any)
+        .allow_credentials(false);
+
+    Router::new()
+        .route("/stream", get(get_stream))
+        .layer(cors)
+        .with_state(jpeg_tx)
+}
+
+async fn get_stream(
+    State(jpeg_tx): State<tokio::sync::broadcast::Sender<Bytes>>,
+) -> impl IntoResponse {
+    // Frames missed by a slow viewer are skipped
+    let frames = BroadcastStream::new(jpeg_tx.subscribe())
+        .filter_map(|frame| frame.ok())
+        .map(Ok::<_, Infallible>);
+
+    (
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE)),
+            (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+        ],
+        StreamBody::new(frames),
+    )
+}
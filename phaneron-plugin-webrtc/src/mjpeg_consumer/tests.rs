@@ -0,0 +1,34 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::*;
+
+#[test]
+fn wraps_jpeg_in_multipart_part() {
+    let part = multipart_frame(&[0xff, 0xd8, 0xff, 0xd9]);
+
+    let mut expected = b"--frame\r\nContent-Type: image/jpeg\r\nContent-Length: 4\r\n\r\n".to_vec();
+    expected.extend_from_slice(&[0xff, 0xd8, 0xff, 0xd9]);
+    expected.extend_from_slice(b"\r\n");
+    assert_eq!(part.as_ref(), expected.as_slice());
+}
+
+#[test]
+fn boundary_matches_content_type() {
+    assert!(CONTENT_TYPE.ends_with(&format!("boundary={BOUNDARY}")));
+}
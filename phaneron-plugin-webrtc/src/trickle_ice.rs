@@ -0,0 +1,126 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::sync::{Arc, Mutex};
+
+use axum::extract::ws::{Message, WebSocket};
+use tracing::{debug, error};
+use webrtc::{
+    ice_transport::ice_candidate::RTCIceCandidateInit, peer_connection::RTCPeerConnection,
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Local ICE candidates gathered so far, `None` marks the end of gathering.
+///
+/// Candidates are kept so that a client which connects to the candidate exchange after gathering
+/// started still receives every candidate.
+#[derive(Clone)]
+pub struct IceCandidates {
+    inner: Arc<Mutex<Vec<Option<RTCIceCandidateInit>>>>,
+    tx: tokio::sync::broadcast::Sender<Option<RTCIceCandidateInit>>,
+}
+
+impl Default for IceCandidates {
+    fn default() -> Self {
+        let (tx, _) = tokio::sync::broadcast::channel(32);
+        Self {
+            inner: Default::default(),
+            tx,
+        }
+    }
+}
+
+impl IceCandidates {
+    pub fn push(&self, candidate: Option<RTCIceCandidateInit>) {
+        let mut candidates = self.inner.lock().unwrap();
+        candidates.push(candidate.clone());
+        self.tx.send(candidate).ok();
+    }
+
+    /// Returns the candidates gathered so far and a receiver for those gathered later.
+    pub fn subscribe(
+        &self,
+    ) -> (
+        Vec<Option<RTCIceCandidateInit>>,
+        tokio::sync::broadcast::Receiver<Option<RTCIceCandidateInit>>,
+    ) {
+        let candidates = self.inner.lock().unwrap();
+        (candidates.clone(), self.tx.subscribe())
+    }
+}
+
+/// Sends local candidates to the client as JSON, with `null` once gathering is complete,
+/// and adds the candidates that the client sends to the peer connection.
+pub async fn exchange_ice_candidates(
+    mut socket: WebSocket,
+    peer_connection: Arc<RTCPeerConnection>,
+    ice_candidates: IceCandidates,
+) {
+    let (gathered, mut candidates_rx) = ice_candidates.subscribe();
+    for candidate in gathered {
+        if send_candidate(&mut socket, candidate).await.is_err() {
+            return;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            candidate = candidates_rx.recv() => {
+                let Ok(candidate) = candidate else {
+                    break;
+                };
+                if send_candidate(&mut socket, candidate).await.is_err() {
+                    break;
+                }
+            }
+            message = socket.recv() => {
+                let message = match message {
+                    Some(Ok(Message::Text(message))) => message,
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => continue,
+                    Some(Err(err)) => {
+                        error!("Error receiving ICE candidate: {err}");
+                        break;
+                    }
+                };
+                let candidate: RTCIceCandidateInit = match serde_json::from_str(&message) {
+                    Ok(candidate) => candidate,
+                    Err(err) => {
+                        error!("Invalid ICE candidate: {err}");
+                        continue;
+                    }
+                };
+                debug!("Adding remote ICE candidate {}", candidate.candidate);
+                if let Err(err) = peer_connection.add_ice_candidate(candidate).await {
+                    error!("Failed to add ICE candidate: {err}");
+                }
+            }
+        }
+    }
+}
+
+async fn send_candidate(
+    socket: &mut WebSocket,
+    candidate: Option<RTCIceCandidateInit>,
+) -> Result<(), axum::Error> {
+    socket
+        .send(Message::Text(serde_json::to_string(&candidate).unwrap()))
+        .await
+}
@@ -0,0 +1,49 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::*;
+
+fn candidate(candidate: &str) -> Option<RTCIceCandidateInit> {
+    Some(RTCIceCandidateInit {
+        candidate: candidate.to_string(),
+        ..Default::default()
+    })
+}
+
+#[test]
+fn late_subscriber_receives_gathered_candidates() {
+    let ice_candidates = IceCandidates::default();
+    ice_candidates.push(candidate("a"));
+    ice_candidates.push(candidate("b"));
+
+    let (gathered, _) = ice_candidates.subscribe();
+    assert_eq!(gathered, vec![candidate("a"), candidate("b")]);
+}
+
+#[test]
+fn subscriber_receives_later_candidates() {
+    let ice_candidates = IceCandidates::default();
+    ice_candidates.push(candidate("a"));
+    let (gathered, mut candidates_rx) = ice_candidates.subscribe();
+    ice_candidates.push(candidate("b"));
+    ice_candidates.push(None);
+
+    assert_eq!(gathered, vec![candidate("a")]);
+    assert_eq!(candidates_rx.try_recv().unwrap(), candidate("b"));
+    assert_eq!(candidates_rx.try_recv().unwrap(), None);
+}
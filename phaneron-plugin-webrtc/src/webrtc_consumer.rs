@@ -16,18 +16,19 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::collections::HashMap;
 use std::net::Ipv4Addr;
 use std::sync::Mutex;
 use std::time::SystemTime;
 use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use abi_stable::sabi_trait::TD_Opaque;
-use abi_stable::std_types::{ROption, RString};
+use abi_stable::std_types::{ROption, RString, RVec};
 use axum::body::{Body, Bytes};
-use axum::extract::State;
+use axum::extract::{Query, State, WebSocketUpgrade};
 use axum::http::Method;
 use axum::response::{IntoResponse, Response};
-use axum::routing::post;
+use axum::routing::{get, post};
 use axum::Json;
 use axum::{
     http::{header, HeaderValue, StatusCode},
@@ -37,21 +38,24 @@ use byteorder::{ByteOrder, LittleEndian};
 use phaneron_plugin::types::{FromAudioF32, FromRGBA, NodeContext};
 use phaneron_plugin::{
     traits::Node_TO, types::Node, types::ProcessFrameContext, AudioChannelLayout, AudioFormat,
-    AudioInputId, ColourSpace, InterlaceMode, VideoFormat, VideoInputId,
+    AudioInputId, ColourRange, ColourSpace, FrameRate, InterlaceMode, VideoFormat, VideoInputId,
 };
+use phaneron_plugin_utils::jitter_buffer::{JitterBuffer, JitterBufferOutput};
+use serde::{Deserialize, Serialize};
 use tokio::time::{Instant, MissedTickBehavior};
 use tower::ServiceBuilder;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::timeout::TimeoutLayer;
 use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
 use tower_http::{LatencyUnit, ServiceBuilderExt};
-use tracing::{debug, info};
-use webrtc::api::media_engine::{MIME_TYPE_OPUS, MIME_TYPE_VP8};
+use tracing::{debug, error, info, warn};
+use webrtc::api::media_engine::{MIME_TYPE_H264, MIME_TYPE_OPUS, MIME_TYPE_VP8};
 use webrtc::{
     api::{
         interceptor_registry::register_default_interceptors, media_engine::MediaEngine, APIBuilder,
+        API,
     },
-    ice_transport::ice_server::RTCIceServer,
+    ice_transport::{ice_candidate::RTCIceCandidate, ice_server::RTCIceServer},
     interceptor::registry::Registry,
     media::Sample,
     peer_connection::{
@@ -62,48 +66,202 @@ use webrtc::{
     track::track_local::{track_local_static_sample::TrackLocalStaticSample, TrackLocal},
 };
 
-lazy_static! {
-    static ref PEER_CONNECTION_MUTEX: Arc<Mutex<Option<Arc<RTCPeerConnection>>>> =
-        Arc::new(Mutex::new(None));
-}
+#[cfg(feature = "h264")]
+use crate::h264::H264Encoder;
+use crate::trickle_ice::{exchange_ice_candidates, IceCandidates};
+use crate::NodeShutdowns;
+
+#[cfg(test)]
+mod tests;
+
+const DEFAULT_JITTER_BUFFER_DEPTH: usize = 1;
+const OPUS_SAMPLE_RATE: u32 = 48000;
+/// Samples per channel in each Opus packet, 20ms at [`OPUS_SAMPLE_RATE`].
+const OPUS_FRAME_SAMPLES: usize = 960;
+/// Time between ticks of the output clock until the first frame gives the graph's frame rate.
+const DEFAULT_FRAME_DURATION: Duration = Duration::from_millis(40);
 
 pub struct WebRTCConsumerHandle {
     node_id: String,
+    shutdowns: NodeShutdowns,
 }
 impl WebRTCConsumerHandle {
-    pub(super) fn new(node_id: String) -> Self {
-        Self { node_id }
+    pub(super) fn new(node_id: String, shutdowns: NodeShutdowns) -> Self {
+        Self { node_id, shutdowns }
     }
 }
 impl phaneron_plugin::traits::NodeHandle for WebRTCConsumerHandle {
     fn initialize(&self, context: NodeContext, _configuration: ROption<RString>) -> Node {
-        let node = WebRTCConsumer::new(self.node_id.clone(), context);
+        let node = WebRTCConsumer::new(self.node_id.clone(), context, &self.shutdowns);
 
         Node_TO::from_value(node, TD_Opaque)
     }
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WebRTCConsumerState {
+    /// Number of frames held between the graph and the output clock, each frame adds a frame of latency.
+    jitter_buffer_depth: usize,
+    #[serde(flatten)]
+    format: OutputFormat,
+}
+
+/// Format that frames are converted to and encoded in.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct OutputFormat {
+    width: usize,
+    height: usize,
+    codec: VideoCodec,
+    video_bitrate_kbps: u32,
+    /// Number of audio channels sent to viewers, audio with other channel layouts is remixed.
+    audio_channels: usize,
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        Self {
+            width: 1920,
+            height: 1080,
+            codec: VideoCodec::Vp8,
+            video_bitrate_kbps: 5000,
+            audio_channels: 1,
+        }
+    }
+}
+
+impl OutputFormat {
+    fn validate(&self) -> Result<(), String> {
+        // Chroma is subsampled in both directions
+        if self.width == 0 || self.height == 0 || self.width % 2 != 0 || self.height % 2 != 0 {
+            return Err(format!(
+                "Invalid resolution {}x{}, width and height must be even",
+                self.width, self.height
+            ));
+        }
+        if self.codec == VideoCodec::H264 && !cfg!(feature = "h264") {
+            return Err(
+                "H.264 isn't available, the plugin was built without the h264 feature".to_string(),
+            );
+        }
+        if self.video_bitrate_kbps == 0 {
+            return Err("Video bitrate must be greater than 0".to_string());
+        }
+        if !matches!(self.audio_channels, 1 | 2) {
+            return Err(format!(
+                "Unsupported number of audio channels {}, Opus supports 1 or 2",
+                self.audio_channels
+            ));
+        }
+        Ok(())
+    }
+
+    fn vpx_config(&self) -> vpx_encode::Config {
+        vpx_encode::Config {
+            width: self.width as u32,
+            height: self.height as u32,
+            timebase: [1, 1000],
+            bitrate: self.video_bitrate_kbps,
+            codec: vpx_encode::VideoCodecId::VP8,
+        }
+    }
+
+    fn same_video(&self, other: &OutputFormat) -> bool {
+        self.width == other.width
+            && self.height == other.height
+            && self.codec == other.codec
+            && self.video_bitrate_kbps == other.video_bitrate_kbps
+    }
+
+    fn channel_layout(&self) -> AudioChannelLayout {
+        match self.audio_channels {
+            2 => AudioChannelLayout::L_R,
+            _ => AudioChannelLayout::Mono,
+        }
+    }
+
+    fn opus_channels(&self) -> opus::Channels {
+        match self.audio_channels {
+            2 => opus::Channels::Stereo,
+            _ => opus::Channels::Mono,
+        }
+    }
+
+    /// Space for an encoded packet of audio, which is never larger than the 16-bit samples it encodes.
+    fn opus_packet_capacity(&self) -> usize {
+        OPUS_FRAME_SAMPLES * self.audio_channels * std::mem::size_of::<i16>()
+    }
+}
+
+/// Codec that video is encoded with, viewers have to reconnect after it changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum VideoCodec {
+    Vp8,
+    /// Only available when the plugin is built with the `h264` feature.
+    H264,
+}
+
+impl VideoCodec {
+    fn codec_capability(&self) -> RTCRtpCodecCapability {
+        match self {
+            VideoCodec::Vp8 => RTCRtpCodecCapability {
+                mime_type: MIME_TYPE_VP8.to_owned(),
+                ..Default::default()
+            },
+            // Constrained baseline, which every browser can decode
+            VideoCodec::H264 => RTCRtpCodecCapability {
+                mime_type: MIME_TYPE_H264.to_owned(),
+                clock_rate: 90000,
+                sdp_fmtp_line:
+                    "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f"
+                        .to_owned(),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// Converters from the graph into the output format, rebuilt when the format changes.
+struct Converters {
+    format: OutputFormat,
+    from_rgba: FromRGBA,
+    from_audio_f32: FromAudioF32,
+}
+
+/// A frame that has been read back from the GPU and is waiting to be encoded.
+struct OutputFrame {
+    format: OutputFormat,
+    /// Frame rate of the graph, which the output clock follows.
+    frame_rate: FrameRate,
+    /// Sample rate of the graph, audio is only sent at [`OPUS_SAMPLE_RATE`].
+    sample_rate: u32,
+    video: Option<RVec<u8>>,
+    audio: Vec<i16>,
+}
+
+/// Time between frames of the graph at `frame_rate`.
+fn frame_duration(frame_rate: FrameRate) -> Duration {
+    Duration::from_nanos(1_000_000_000 * frame_rate.den as u64 / frame_rate.num as u64)
+}
+
 pub struct WebRTCConsumer {
     node_id: String,
     context: NodeContext,
-    start: Mutex<Option<tokio::time::Instant>>,
-    interval: Mutex<Option<tokio::time::Interval>>,
-    from_rgba: Mutex<Option<FromRGBA>>,
-    from_audio_f32: Mutex<Option<FromAudioF32>>,
-    vpx: Mutex<Option<VPXEncoder>>,
-    opus: Mutex<Option<opus::Encoder>>,
-    video_tracks: VideoTracks,
-    audio_tracks: AudioTracks,
-    tokio_handle: tokio::runtime::Handle,
-    tokio_terminate_sender: tokio::sync::oneshot::Sender<()>,
+    format: Arc<Mutex<OutputFormat>>,
+    converters: Mutex<Option<Converters>>,
+    jitter_buffer: Arc<JitterBuffer<Arc<OutputFrame>>>,
     video_input: VideoInputId,
     audio_input: AudioInputId,
 }
 
 impl WebRTCConsumer {
-    pub fn new(node_id: String, context: NodeContext) -> Self {
+    pub fn new(node_id: String, context: NodeContext, shutdowns: &NodeShutdowns) -> Self {
         let (sender, receiver) = std::sync::mpsc::channel();
         let (terminate_sender, terminate_receiver) = tokio::sync::oneshot::channel::<()>();
+        let (output_stopped_sender, output_stopped_receiver) =
+            tokio::sync::oneshot::channel::<()>();
         std::thread::spawn(move || {
             let runtime = tokio::runtime::Builder::new_current_thread()
                 .enable_all()
@@ -111,7 +269,11 @@ impl WebRTCConsumer {
                 .unwrap();
             let handle = runtime.handle();
             sender.send(handle.clone()).unwrap();
-            runtime.block_on(terminate_receiver).ok();
+            // The output thread's clock runs on this runtime, so keep it running until the output has stopped
+            runtime.block_on(async move {
+                terminate_receiver.await.ok();
+                output_stopped_receiver.await.ok();
+            });
         });
 
         let handle = receiver.recv().unwrap();
@@ -135,59 +297,52 @@ impl WebRTCConsumer {
             .with_interceptor_registry(registry)
             .build();
 
-        // Prepare the configuration
-        let config = RTCConfiguration {
-            ice_servers: vec![RTCIceServer {
-                urls: vec!["stun:stun.l.google.com:19302".to_owned()],
-                ..Default::default()
-            }],
-            ..Default::default()
-        };
+        let viewers: Viewers = Default::default();
+        let format: Arc<Mutex<OutputFormat>> = Default::default();
 
-        // Create a new RTCPeerConnection
-        let peer_connection = handle.block_on(api.new_peer_connection(config)).unwrap();
-        let peer_connection = Arc::new(peer_connection);
-        let (done_tx, mut done_rx) = tokio::sync::mpsc::channel::<()>(1);
-
-        // Set the handler for Peer connection state
-        // This will notify you when the peer has connected/disconnected
-        peer_connection.on_peer_connection_state_change(Box::new(
-            move |s: RTCPeerConnectionState| {
-                info!("Peer Connection State has changed: {s}");
-
-                if s == RTCPeerConnectionState::Failed {
-                    // Wait until PeerConnection has had no network activity for 30 seconds or another failure. It may be reconnected using an ICE Restart.
-                    // Use webrtc.PeerConnectionStateDisconnected if you are interested in detecting faster timeout.
-                    // Note that the PeerConnection may come back from PeerConnectionStateDisconnected.
-                    info!("Peer Connection has gone to failed exiting");
-                    let _ = done_tx.try_send(());
-                }
-
-                Box::pin(async {})
-            },
-        ));
-
-        {
-            let mut pcm = PEER_CONNECTION_MUTEX.lock().unwrap();
-            *pcm = Some(Arc::clone(&peer_connection));
-        }
-
-        let video_tracks: VideoTracks = Default::default();
-        let audio_tracks: AudioTracks = Default::default();
-
-        let pc = {
-            let pcm = PEER_CONNECTION_MUTEX.lock().unwrap();
-            pcm.clone().unwrap()
-        };
+        let jitter_buffer = Arc::new(JitterBuffer::new(DEFAULT_JITTER_BUFFER_DEPTH));
 
         let state = AppState {
-            video_tracks: video_tracks.clone(),
-            audio_tracks: audio_tracks.clone(),
-            peer_connection: pc,
+            api: Arc::new(api),
+            viewers: viewers.clone(),
+            format: format.clone(),
+            jitter_buffer: jitter_buffer.clone(),
         };
 
         handle.spawn(serve_web_server(state));
 
+        // Frames are encoded and sent on the output clock, decoupled from the graph by the jitter buffer
+        std::thread::spawn({
+            let handle = handle.clone();
+            let jitter_buffer = jitter_buffer.clone();
+            let viewers = viewers.clone();
+            move || run_output(handle, jitter_buffer, viewers, output_stopped_sender)
+        });
+
+        shutdowns.lock().unwrap().insert(
+            node_id.clone(),
+            Box::new({
+                let jitter_buffer = jitter_buffer.clone();
+                move || {
+                    jitter_buffer.close();
+                    let peer_connections: Vec<Arc<RTCPeerConnection>> = viewers
+                        .lock()
+                        .unwrap()
+                        .drain()
+                        .map(|(_, viewer)| viewer.peer_connection)
+                        .collect();
+                    handle.spawn(async move {
+                        for peer_connection in peer_connections {
+                            if let Err(err) = peer_connection.close().await {
+                                error!("Failed to close peer connection: {err}");
+                            }
+                        }
+                        terminate_sender.send(()).ok();
+                    });
+                }
+            }),
+        );
+
         let video_input = context.add_video_input();
 
         let audio_input = context.add_audio_input();
@@ -195,16 +350,9 @@ impl WebRTCConsumer {
         Self {
             node_id,
             context,
-            start: Default::default(),
-            interval: Default::default(),
-            from_rgba: Default::default(),
-            from_audio_f32: Default::default(),
-            vpx: Default::default(),
-            opus: Default::default(),
-            video_tracks,
-            audio_tracks,
-            tokio_handle: handle,
-            tokio_terminate_sender: terminate_sender,
+            format,
+            converters: Default::default(),
+            jitter_buffer,
             video_input,
             audio_input,
         }
@@ -213,33 +361,52 @@ impl WebRTCConsumer {
 
 impl phaneron_plugin::traits::Node for WebRTCConsumer {
     fn apply_state(&self, state: RString) -> bool {
-        false
+        let state: WebRTCConsumerState = match serde_json::from_str(&state) {
+            Ok(state) => state,
+            Err(err) => {
+                error!("{}: Invalid state: {err}", self.node_id);
+                return false;
+            }
+        };
+
+        if let Err(err) = state.format.validate() {
+            error!("{}: Invalid state: {err}", self.node_id);
+            return false;
+        }
+
+        self.jitter_buffer.set_depth(state.jitter_buffer_depth);
+        *self.format.lock().unwrap() = state.format;
+
+        true
     }
     fn process_frame(&self, frame_context: ProcessFrameContext) {
-        let mut interval_lock = self.interval.lock().unwrap();
-        let interval = interval_lock.get_or_insert_with(|| {
-            self.tokio_handle.block_on(async move {
-                let mut interval = tokio::time::interval(Duration::from_millis(40));
-                interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
-                interval
-            })
-        });
-
-        let mut from_rgba_lock = self.from_rgba.lock().unwrap();
-        let from_rgba = from_rgba_lock.get_or_insert(self.context.create_from_rgba(
-            &VideoFormat::YUV420p,
-            &ColourSpace::sRGB.colour_spec(),
-            1920,
-            1080,
-            InterlaceMode::Progressive,
-        ));
-        let mut from_audio_f32_lock = self.from_audio_f32.lock().unwrap();
-        let from_audio_f32 = from_audio_f32_lock.get_or_insert(
-            self.context
-                .create_from_audio_f32(AudioFormat::I16, AudioChannelLayout::Mono),
-        );
-        let mut start_lock = self.start.lock().unwrap();
-        let start = start_lock.get_or_insert(Instant::now());
+        let format = *self.format.lock().unwrap();
+        let mut converters_lock = self.converters.lock().unwrap();
+        // Replacing the converters drops the frame still being read back by the old ones
+        if converters_lock
+            .as_ref()
+            .map_or(true, |converters| converters.format != format)
+        {
+            *converters_lock = Some(Converters {
+                format,
+                from_rgba: self.context.create_from_rgba(
+                    &VideoFormat::YUV420p,
+                    &ColourSpace::sRGB.colour_spec(),
+                    ColourRange::Limited,
+                    format.width,
+                    format.height,
+                    InterlaceMode::Progressive,
+                ),
+                from_audio_f32: self
+                    .context
+                    .create_from_audio_f32(AudioFormat::I16, format.channel_layout()),
+            });
+        }
+        let Converters {
+            format,
+            from_rgba,
+            from_audio_f32,
+        } = converters_lock.as_ref().unwrap();
 
         let video_frame = frame_context
             .get_video_input(&self.video_input)
@@ -251,88 +418,180 @@ impl phaneron_plugin::traits::Node for WebRTCConsumer {
             .get_audio_input(&self.audio_input)
             .unwrap_or(frame_context.get_silence_frame())
             .clone();
-
-        let mut audio_encoder_lock = self.opus.lock().unwrap();
-        let audio_encoder = audio_encoder_lock.get_or_insert_with(|| {
-            opus::Encoder::new(48000, opus::Channels::Mono, opus::Application::Audio).unwrap()
-        });
         let audio_frame = from_audio_f32.process_frame(&frame_context, audio_frame.frame);
 
         let copy_context = frame_context.submit().unwrap();
 
-        let video_frame = from_rgba.copy_frame(&copy_context, video_frame);
-        let video_frame: Vec<u8> = video_frame.iter().flatten().cloned().collect();
+        // The download of this frame overlaps with encoding the previous one
+        let video = copy_context
+            .copy_video_frame_pipelined(from_rgba, video_frame)
+            .into_option();
 
-        let now = Instant::now();
-        let time = now - *start;
-        let ms = time.as_secs() * 1000 + time.subsec_millis() as u64;
-        let video_frames = {
-            let mut vpx_lock = self.vpx.lock().unwrap();
-            let vpx = vpx_lock.get_or_insert_with(|| {
-                let vpx = vpx_encode::Encoder::new(vpx_encode::Config {
-                    width: 1920,
-                    height: 1080,
-                    timebase: [1, 1000],
-                    bitrate: 5000,
-                    codec: vpx_encode::VideoCodecId::VP8,
-                })
-                .unwrap();
-                VPXEncoder::new(vpx)
-            });
-            let packets = vpx.encode(ms as i64, &video_frame).unwrap();
-            packets
-                .into_iter()
-                .map(|frame| frame.data.to_vec())
-                .collect::<Vec<Vec<u8>>>()
-        };
-
-        let audio_frame = {
-            let fr = from_audio_f32.copy_frame(&copy_context, audio_frame);
+        let audio = {
+            let fr = copy_context.copy_audio_frame(from_audio_f32, audio_frame);
 
             let mut frame: Vec<i16> = vec![0i16; fr.len() / 2];
             LittleEndian::read_i16_into(&fr, &mut frame);
+            frame
+        };
 
-            const OUT_SIZE: usize = 256; // TODO: This is only correct for mono up to 48kHz
-            let mut out = vec![0u8; OUT_SIZE];
-            let bytes = audio_encoder.encode(&frame, &mut out).unwrap();
+        // Blocks while the jitter buffer is full, this paces the graph to the output clock
+        self.jitter_buffer.push(Arc::new(OutputFrame {
+            format: *format,
+            frame_rate: frame_context.get_frame_rate(),
+            sample_rate: frame_context.get_sample_rate(),
+            video,
+            audio,
+        }));
+    }
+}
+
+fn run_output(
+    handle: tokio::runtime::Handle,
+    jitter_buffer: Arc<JitterBuffer<Arc<OutputFrame>>>,
+    viewers: Viewers,
+    // Dropped when the output stops, which lets the runtime shut down
+    _output_stopped: tokio::sync::oneshot::Sender<()>,
+) {
+    let mut duration = DEFAULT_FRAME_DURATION;
+    let mut interval = handle.block_on(output_interval(duration));
+    let start = Instant::now();
+    let mut video_encoder: Option<(OutputFormat, VideoEncoder)> = None;
+    let mut audio_encoder: Option<AudioEncoder> = None;
+    let mut rejected_sample_rate: Option<u32> = None;
+
+    loop {
+        handle.block_on(async { interval.tick().await });
+        if jitter_buffer.is_closed() {
+            return;
+        }
 
-            out[0..bytes].to_vec()
+        let (frame, repeated) = match jitter_buffer.pop() {
+            JitterBufferOutput::Next(frame) => (frame, false),
+            JitterBufferOutput::Repeat(frame) => (frame, true),
+            JitterBufferOutput::Empty => continue,
         };
+        if repeated {
+            debug!("WebRTC jitter buffer is empty, repeating the last frame");
+        }
+        // The output clock ticks once per frame of the graph
+        if frame_duration(frame.frame_rate) != duration {
+            duration = frame_duration(frame.frame_rate);
+            interval = handle.block_on(output_interval(duration));
+        }
 
-        self.tokio_handle
-            .block_on(async move { interval.tick().await });
+        let time = Instant::now() - start;
+        let ms = time.as_secs() * 1000 + time.subsec_millis() as u64;
+        let video_frames = if let Some(video_frame) = &frame.video {
+            let mut video_frames: Vec<Vec<u8>> = vec![];
+            // Frames held by the old encoder are sent before switching to the new format
+            if let Some((format, _)) = &video_encoder {
+                if !format.same_video(&frame.format) {
+                    let (_, old_encoder) = video_encoder.take().unwrap();
+                    video_frames.extend(old_encoder.finish());
+                }
+            }
+            let (_, video_encoder) = video_encoder.get_or_insert_with(|| {
+                (
+                    frame.format,
+                    VideoEncoder::new(&frame.format, frame.frame_rate),
+                )
+            });
+            video_frames.extend(video_encoder.encode(ms as i64, video_frame));
+            video_frames
+        } else {
+            vec![]
+        };
 
-        for track in self.audio_tracks.lock().unwrap().iter() {
-            self.tokio_handle.spawn(write_audio_to_track(
-                track.clone(),
-                audio_frame.clone().into(),
-            ));
-        }
+        let audio_packets = if frame.sample_rate != OPUS_SAMPLE_RATE {
+            if rejected_sample_rate != Some(frame.sample_rate) {
+                error!(
+                    "WebRTC audio is only sent at {OPUS_SAMPLE_RATE}Hz, dropping audio at {}Hz",
+                    frame.sample_rate
+                );
+                rejected_sample_rate = Some(frame.sample_rate);
+            }
+            vec![]
+        } else {
+            rejected_sample_rate = None;
+            // Repeating audio is more noticeable than silence
+            let samples = if repeated {
+                vec![0i16; frame.audio.len()]
+            } else {
+                frame.audio.clone()
+            };
+
+            if let Some(encoder) = &audio_encoder {
+                if encoder.format.audio_channels != frame.format.audio_channels {
+                    audio_encoder = None;
+                }
+            }
+            if audio_encoder.is_none() {
+                match AudioEncoder::new(&frame.format) {
+                    Ok(encoder) => audio_encoder = Some(encoder),
+                    Err(err) => error!("Failed to create WebRTC audio encoder: {err}"),
+                }
+            }
 
-        for frame in video_frames {
-            for track in self.video_tracks.lock().unwrap().iter() {
-                self.tokio_handle
-                    .spawn(write_video_to_track(track.clone(), frame.clone().into()));
+            match audio_encoder
+                .as_mut()
+                .map(|encoder| encoder.encode(&samples))
+            {
+                Some(Ok(packets)) => packets,
+                Some(Err(err)) => {
+                    // Buffered samples are dropped with the encoder, which is recreated for the next frame
+                    error!("Failed to encode WebRTC audio: {err}");
+                    audio_encoder = None;
+                    vec![]
+                }
+                None => vec![],
+            }
+        };
+
+        // Every viewer receives the same encoded samples, on tracks that were negotiated for the codec
+        for viewer in viewers.lock().unwrap().values() {
+            for track in viewer.audio_tracks.iter() {
+                for packet in audio_packets.iter() {
+                    handle.spawn(write_sample_to_track(
+                        track.clone(),
+                        packet.clone().into(),
+                        OPUS_FRAME_DURATION,
+                    ));
+                }
+            }
+
+            let frame_codec = frame.format.codec;
+            for frame in video_frames.iter() {
+                for (codec, track) in viewer.video_tracks.iter() {
+                    if *codec != frame_codec {
+                        continue;
+                    }
+                    handle.spawn(write_sample_to_track(
+                        track.clone(),
+                        frame.clone().into(),
+                        duration,
+                    ));
+                }
             }
         }
     }
 }
 
-async fn write_video_to_track<'a>(t: Arc<TrackLocalStaticSample>, data: Bytes) {
-    t.write_sample(&Sample {
-        data,
-        duration: Duration::from_millis(40),
-        timestamp: SystemTime::now(),
-        ..Default::default()
-    })
-    .await
-    .unwrap();
+/// Ticks once per frame, skipping ticks that were missed while encoding.
+async fn output_interval(duration: Duration) -> tokio::time::Interval {
+    let mut interval = tokio::time::interval(duration);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    interval
 }
 
-async fn write_audio_to_track<'a>(t: Arc<TrackLocalStaticSample>, data: Bytes) {
+async fn write_sample_to_track<'a>(
+    t: Arc<TrackLocalStaticSample>,
+    data: Bytes,
+    duration: Duration,
+) {
     t.write_sample(&Sample {
         data,
-        duration: Duration::from_millis(40),
+        duration,
         timestamp: SystemTime::now(),
         ..Default::default()
     })
@@ -340,11 +599,85 @@ async fn write_audio_to_track<'a>(t: Arc<TrackLocalStaticSample>, data: Bytes) {
     .unwrap();
 }
 
+/// Duration of each Opus packet.
+const OPUS_FRAME_DURATION: Duration = Duration::from_millis(20);
+
+/// Encodes interleaved audio into Opus packets of [`OPUS_FRAME_SAMPLES`], samples that don't fill
+/// a packet are held until the next frame as frames of the graph rarely hold a whole number of
+/// packets, e.g. 1601 or 1602 samples at 29.97fps.
+struct AudioEncoder {
+    format: OutputFormat,
+    encoder: opus::Encoder,
+    samples: Vec<i16>,
+}
+
+impl AudioEncoder {
+    fn new(format: &OutputFormat) -> Result<Self, opus::Error> {
+        let encoder = opus::Encoder::new(
+            OPUS_SAMPLE_RATE,
+            format.opus_channels(),
+            opus::Application::Audio,
+        )?;
+
+        Ok(Self {
+            format: *format,
+            encoder,
+            samples: vec![],
+        })
+    }
+
+    /// Returns a packet for each whole Opus frame of the held and the given samples.
+    fn encode(&mut self, samples: &[i16]) -> Result<Vec<Vec<u8>>, opus::Error> {
+        self.samples.extend_from_slice(samples);
+        let packet_samples = OPUS_FRAME_SAMPLES * self.format.audio_channels;
+        let packet_capacity = self.format.opus_packet_capacity();
+        let encoded = self.samples.len() - self.samples.len() % packet_samples;
+
+        let packets = self.samples[..encoded]
+            .chunks_exact(packet_samples)
+            .map(|frame| self.encoder.encode_vec(frame, packet_capacity))
+            .collect();
+        self.samples.drain(..encoded);
+
+        packets
+    }
+}
+
 #[derive(Clone)]
 struct AppState {
-    video_tracks: VideoTracks,
-    audio_tracks: AudioTracks,
+    api: Arc<API>,
+    viewers: Viewers,
+    format: Arc<Mutex<OutputFormat>>,
+    jitter_buffer: Arc<JitterBuffer<Arc<OutputFrame>>>,
+}
+
+/// A browser watching the output over its own peer connection.
+struct Viewer {
     peer_connection: Arc<RTCPeerConnection>,
+    ice_candidates: IceCandidates,
+    video_tracks: Vec<(VideoCodec, Arc<TrackLocalStaticSample>)>,
+    audio_tracks: Vec<Arc<TrackLocalStaticSample>>,
+}
+
+/// Connected viewers, keyed by viewer id.
+type Viewers = Arc<Mutex<HashMap<String, Viewer>>>;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct SignalingQuery {
+    /// Answer without waiting for ICE gathering, candidates are exchanged over `/iceCandidates` instead.
+    trickle: bool,
+    /// Viewer returned by `/createPeerConnection`, required by `/addMedia` and `/iceCandidates`.
+    viewer_id: Option<String>,
+}
+
+/// Session description answering an offer, along with the viewer it belongs to.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SignalingAnswer {
+    viewer_id: String,
+    #[serde(flatten)]
+    description: RTCSessionDescription,
 }
 
 async fn serve_web_server(state: AppState) {
@@ -398,29 +731,163 @@ fn app(state: AppState) -> Router {
     Router::new()
         .route("/createPeerConnection", post(create_peer_connection))
         .route("/addMedia", post(add_media))
+        .route("/jitterBuffer", get(get_jitter_buffer))
+        .route("/iceCandidates", get(ice_candidates_ws))
         .layer(middleware)
         .layer(cors)
         .with_state(state)
 }
 
+async fn get_jitter_buffer(state: State<AppState>) -> impl IntoResponse {
+    Json(state.jitter_buffer.stats())
+}
+
+async fn ice_candidates_ws(
+    ws: WebSocketUpgrade,
+    state: State<AppState>,
+    Query(query): Query<SignalingQuery>,
+) -> Response {
+    let viewer = query.viewer_id.as_ref().and_then(|viewer_id| {
+        let viewers = state.viewers.lock().unwrap();
+        viewers.get(viewer_id).map(|viewer| {
+            (
+                viewer.peer_connection.clone(),
+                viewer.ice_candidates.clone(),
+            )
+        })
+    });
+    let Some((peer_connection, ice_candidates)) = viewer else {
+        return unknown_viewer(&query);
+    };
+    ws.on_upgrade(move |socket| exchange_ice_candidates(socket, peer_connection, ice_candidates))
+}
+
+/// Looks up the peer connection of the viewer named in the query.
+fn find_viewer(
+    viewers: &Viewers,
+    query: &SignalingQuery,
+) -> Option<(String, Arc<RTCPeerConnection>)> {
+    let viewer_id = query.viewer_id.as_ref()?;
+    let viewers = viewers.lock().unwrap();
+    let viewer = viewers.get(viewer_id)?;
+    Some((viewer_id.clone(), viewer.peer_connection.clone()))
+}
+
+fn unknown_viewer(query: &SignalingQuery) -> Response {
+    match &query.viewer_id {
+        Some(viewer_id) => (
+            StatusCode::NOT_FOUND,
+            format!("Viewer {viewer_id} does not exist"),
+        )
+            .into_response(),
+        None => (StatusCode::BAD_REQUEST, "Missing viewerId").into_response(),
+    }
+}
+
 async fn create_peer_connection(
     state: State<AppState>,
+    Query(query): Query<SignalingQuery>,
     Json(body): Json<RTCSessionDescription>,
-) -> impl IntoResponse {
-    if state.peer_connection.connection_state() != RTCPeerConnectionState::New {
-        panic!(
-            "create_peer_connection called in non-new state ({})",
-            state.peer_connection.connection_state()
-        );
-    }
+) -> Response {
+    let (viewer_id, peer_connection) = match create_viewer(&state).await {
+        Ok(viewer) => viewer,
+        Err(err) => {
+            error!("Failed to create peer connection: {err}");
+            return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response();
+        }
+    };
 
-    info!("PeerConnection has been created");
-    do_signaling(&state.peer_connection, body).await
+    info!("PeerConnection has been created for viewer {viewer_id}");
+    do_signaling(&viewer_id, &peer_connection, body, query.trickle)
+        .await
+        .into_response()
+}
+
+/// Creates a peer connection for a new viewer, which is removed again once the connection
+/// has failed or been closed.
+async fn create_viewer(
+    state: &AppState,
+) -> Result<(String, Arc<RTCPeerConnection>), webrtc::Error> {
+    // Prepare the configuration
+    let config = RTCConfiguration {
+        ice_servers: vec![RTCIceServer {
+            urls: vec!["stun:stun.l.google.com:19302".to_owned()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+
+    // Create a new RTCPeerConnection
+    let peer_connection = Arc::new(state.api.new_peer_connection(config).await?);
+    let viewer_id = uuid::Uuid::new_v4().to_string();
+
+    // Set the handler for Peer connection state
+    // This will notify you when the peer has connected/disconnected
+    peer_connection.on_peer_connection_state_change(Box::new({
+        let viewers = state.viewers.clone();
+        let viewer_id = viewer_id.clone();
+        move |s: RTCPeerConnectionState| {
+            info!("Peer Connection State of viewer {viewer_id} has changed: {s}");
+
+            // A failed connection may only come back with an ICE restart, which isn't supported
+            if matches!(
+                s,
+                RTCPeerConnectionState::Failed | RTCPeerConnectionState::Closed
+            ) {
+                let removed = viewers.lock().unwrap().remove(&viewer_id);
+                if let Some(viewer) = removed {
+                    info!("Viewer {viewer_id} has been removed");
+                    if s == RTCPeerConnectionState::Failed {
+                        tokio::spawn(async move {
+                            if let Err(err) = viewer.peer_connection.close().await {
+                                warn!("Failed to close peer connection: {err}");
+                            }
+                        });
+                    }
+                }
+            }
+
+            Box::pin(async {})
+        }
+    }));
+
+    // Candidates are kept for clients using trickle ICE, see `exchange_ice_candidates`
+    let ice_candidates = IceCandidates::default();
+    peer_connection.on_ice_candidate(Box::new({
+        let ice_candidates = ice_candidates.clone();
+        move |candidate: Option<RTCIceCandidate>| {
+            let candidate = candidate.map(|candidate| candidate.to_json());
+            match candidate {
+                Some(Ok(candidate)) => ice_candidates.push(Some(candidate)),
+                Some(Err(err)) => error!("Failed to serialize ICE candidate: {err}"),
+                None => ice_candidates.push(None),
+            }
+
+            Box::pin(async {})
+        }
+    }));
+
+    state.viewers.lock().unwrap().insert(
+        viewer_id.clone(),
+        Viewer {
+            peer_connection: peer_connection.clone(),
+            ice_candidates,
+            video_tracks: vec![],
+            audio_tracks: vec![],
+        },
+    );
+
+    Ok((viewer_id, peer_connection))
 }
 
 // do_signaling exchanges all state of the local PeerConnection and is called
 // every time a video is added or removed
-async fn do_signaling(pc: &Arc<RTCPeerConnection>, body: RTCSessionDescription) -> Response<Body> {
+async fn do_signaling(
+    viewer_id: &str,
+    pc: &Arc<RTCPeerConnection>,
+    body: RTCSessionDescription,
+    trickle: bool,
+) -> Response<Body> {
     let offer = body;
 
     if let Err(err) = pc.set_remote_description(offer).await {
@@ -441,13 +908,18 @@ async fn do_signaling(pc: &Arc<RTCPeerConnection>, body: RTCSessionDescription)
         panic!("{}", err);
     }
 
-    // Block until ICE Gathering is complete, disabling trickle ICE
-    // we do this because we only can exchange one signaling message
-    // in a production application you should exchange ICE Candidates via OnICECandidate
-    let _ = gather_complete.recv().await;
+    // Without trickle ICE the answer has to contain every candidate, so block until gathering is complete.
+    // With trickle ICE the client receives candidates from /iceCandidates as they are gathered.
+    if !trickle {
+        let _ = gather_complete.recv().await;
+    }
 
     let payload = if let Some(local_desc) = pc.local_description().await {
-        match serde_json::to_string(&local_desc) {
+        let answer = SignalingAnswer {
+            viewer_id: viewer_id.to_string(),
+            description: local_desc,
+        };
+        match serde_json::to_string(&answer) {
             Ok(p) => p,
             Err(err) => panic!("{}", err),
         }
@@ -469,19 +941,21 @@ async fn do_signaling(pc: &Arc<RTCPeerConnection>, body: RTCSessionDescription)
 
 async fn add_media(
     state: State<AppState>,
+    Query(query): Query<SignalingQuery>,
     Json(body): Json<RTCSessionDescription>,
-) -> impl IntoResponse {
+) -> Response {
+    let Some((viewer_id, peer_connection)) = find_viewer(&state.viewers, &query) else {
+        return unknown_viewer(&query);
+    };
+
+    let codec = state.format.lock().unwrap().codec;
     let video_track = Arc::new(TrackLocalStaticSample::new(
-        RTCRtpCodecCapability {
-            mime_type: MIME_TYPE_VP8.to_owned(),
-            ..Default::default()
-        },
+        codec.codec_capability(),
         format!("video-{}", uuid::Uuid::new_v4()),
         format!("video-{}", uuid::Uuid::new_v4()),
     ));
 
-    let rtp_sender = match state
-        .peer_connection
+    let rtp_sender = match peer_connection
         .add_track(Arc::clone(&video_track) as Arc<dyn TrackLocal + Send + Sync>)
         .await
     {
@@ -489,9 +963,8 @@ async fn add_media(
         Err(err) => panic!("{}", err),
     };
 
-    {
-        let mut video_tracks = state.video_tracks.lock().unwrap();
-        video_tracks.push(video_track);
+    if let Some(viewer) = state.viewers.lock().unwrap().get_mut(&viewer_id) {
+        viewer.video_tracks.push((codec, video_track));
     }
 
     // Read incoming RTCP packets
@@ -507,16 +980,16 @@ async fn add_media(
     let audio_track = Arc::new(TrackLocalStaticSample::new(
         RTCRtpCodecCapability {
             mime_type: MIME_TYPE_OPUS.to_owned(),
-            channels: 1,       // TODO
-            clock_rate: 48000, // TODO
+            // Opus is always signalled as two channels, mono and stereo streams both decode
+            channels: 2,
+            clock_rate: OPUS_SAMPLE_RATE,
             ..Default::default()
         },
         format!("audio-{}", uuid::Uuid::new_v4()),
         format!("audio-{}", uuid::Uuid::new_v4()),
     ));
 
-    let rtp_sender = match state
-        .peer_connection
+    let rtp_sender = match peer_connection
         .add_track(Arc::clone(&audio_track) as Arc<dyn TrackLocal + Send + Sync>)
         .await
     {
@@ -524,9 +997,8 @@ async fn add_media(
         Err(err) => panic!("{}", err),
     };
 
-    {
-        let mut audio_tracks = state.audio_tracks.lock().unwrap();
-        audio_tracks.push(audio_track);
+    if let Some(viewer) = state.viewers.lock().unwrap().get_mut(&viewer_id) {
+        viewer.audio_tracks.push(audio_track);
     }
 
     // Read incoming RTCP packets
@@ -539,7 +1011,67 @@ async fn add_media(
 
     debug!("Audio track has been added");
 
-    do_signaling(&state.peer_connection, body).await
+    do_signaling(&viewer_id, &peer_connection, body, query.trickle)
+        .await
+        .into_response()
+}
+
+/// Encodes video in the codec of the output format.
+enum VideoEncoder {
+    Vp8(VPXEncoder),
+    #[cfg(feature = "h264")]
+    H264(H264Encoder),
+}
+
+impl VideoEncoder {
+    // Only H.264 is configured with the frame rate
+    #[cfg_attr(not(feature = "h264"), allow(unused_variables))]
+    fn new(format: &OutputFormat, frame_rate: FrameRate) -> Self {
+        match format.codec {
+            VideoCodec::Vp8 => {
+                let vpx = vpx_encode::Encoder::new(format.vpx_config()).unwrap();
+                VideoEncoder::Vp8(VPXEncoder::new(vpx))
+            }
+            #[cfg(feature = "h264")]
+            VideoCodec::H264 => VideoEncoder::H264(
+                H264Encoder::new(
+                    format.width,
+                    format.height,
+                    format.video_bitrate_kbps,
+                    frame_rate.num as f32 / frame_rate.den as f32,
+                )
+                .unwrap(),
+            ),
+            #[cfg(not(feature = "h264"))]
+            VideoCodec::H264 => {
+                unreachable!("H.264 is rejected by apply_state without the h264 feature")
+            }
+        }
+    }
+
+    fn encode(&mut self, pts: i64, data: &[u8]) -> Vec<Vec<u8>> {
+        match self {
+            VideoEncoder::Vp8(vpx) => {
+                let packets = vpx.encode(pts, data).unwrap();
+                packets
+                    .into_iter()
+                    .map(|frame| frame.data.to_vec())
+                    .collect()
+            }
+            #[cfg(feature = "h264")]
+            VideoEncoder::H264(h264) => vec![h264.encode(data).unwrap()],
+        }
+    }
+
+    /// Flushes the encoder, returning the frames it was still holding.
+    fn finish(self) -> Vec<Vec<u8>> {
+        match self {
+            VideoEncoder::Vp8(vpx) => vpx.finish(),
+            // Frames are encoded as soon as they are received
+            #[cfg(feature = "h264")]
+            VideoEncoder::H264(_) => vec![],
+        }
+    }
 }
 
 /// Lies to rust because we want the encoder to go into a tokio task
@@ -555,10 +1087,26 @@ impl VPXEncoder {
     fn encode(&mut self, pts: i64, data: &[u8]) -> vpx_encode::Result<vpx_encode::Packets> {
         self.encoder.encode(pts, data)
     }
+
+    /// Flushes the encoder, returning the frames it was still holding.
+    fn finish(self) -> Vec<Vec<u8>> {
+        let mut frames = vec![];
+        match self.encoder.finish() {
+            Ok(mut finish) => loop {
+                match finish.next() {
+                    Ok(Some(frame)) => frames.push(frame.data.to_vec()),
+                    Ok(None) => break,
+                    Err(err) => {
+                        error!("Failed to flush video encoder: {err:?}");
+                        break;
+                    }
+                }
+            },
+            Err(err) => error!("Failed to flush video encoder: {err:?}"),
+        }
+        frames
+    }
 }
 
 unsafe impl Send for VPXEncoder {}
 unsafe impl Sync for VPXEncoder {}
-
-type VideoTracks = Arc<Mutex<Vec<Arc<TrackLocalStaticSample>>>>;
-type AudioTracks = Arc<Mutex<Vec<Arc<TrackLocalStaticSample>>>>;
@@ -0,0 +1,59 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::*;
+
+#[test]
+fn output_clock_follows_the_graph_frame_rate() {
+    assert_eq!(
+        frame_duration(FrameRate { num: 25, den: 1 }),
+        Duration::from_millis(40)
+    );
+    assert_eq!(
+        frame_duration(FrameRate {
+            num: 30000,
+            den: 1001
+        }),
+        Duration::from_nanos(33_366_666)
+    );
+}
+
+#[test]
+fn audio_is_rebuffered_into_opus_packets() {
+    let mut encoder = AudioEncoder::new(&OutputFormat::default()).unwrap();
+
+    // 29.97fps alternates between 1601 and 1602 samples per frame
+    assert_eq!(encoder.encode(&[0; 1601]).unwrap().len(), 1);
+    assert_eq!(encoder.samples.len(), 1601 - OPUS_FRAME_SAMPLES);
+    assert_eq!(encoder.encode(&[0; 1602]).unwrap().len(), 2);
+    assert_eq!(encoder.samples.len(), 1601 + 1602 - 3 * OPUS_FRAME_SAMPLES);
+    assert!(encoder.encode(&[]).unwrap().is_empty());
+}
+
+#[test]
+fn stereo_packets_hold_samples_for_both_channels() {
+    let format = OutputFormat {
+        audio_channels: 2,
+        ..Default::default()
+    };
+    let mut encoder = AudioEncoder::new(&format).unwrap();
+
+    // 30fps, one and two thirds of a packet per frame
+    assert_eq!(encoder.encode(&[0; 1600 * 2]).unwrap().len(), 1);
+    assert_eq!(encoder.samples.len(), (1600 - OPUS_FRAME_SAMPLES) * 2);
+}
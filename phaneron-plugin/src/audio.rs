@@ -1,9 +1,10 @@
 use abi_stable::StableAbi;
+use serde::{Deserialize, Serialize};
 
 /// Supported audio I/O formats.
 /// Audio will be converted to 32 bit floating-point on input.
 #[repr(C)]
-#[derive(StableAbi)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, StableAbi)]
 pub enum AudioFormat {
     I16,
     U16,
@@ -12,8 +13,10 @@ pub enum AudioFormat {
 }
 
 /// Supported audio channel layouts.
+/// Channels are named in the order their buffers appear in an [`AudioFrame`](crate::traits::AudioFrame)
+/// and their samples are interleaved in I/O.
 #[repr(C)]
-#[derive(StableAbi)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, StableAbi, Serialize, Deserialize)]
 #[allow(non_camel_case_types)]
 pub enum AudioChannelLayout {
     Mono,
@@ -21,4 +24,36 @@ pub enum AudioChannelLayout {
     R,
     L_R,
     R_L,
+    /// 5.1 surround: left, right, center, low-frequency effects, left surround, right surround.
+    L_R_C_LFE_Ls_Rs,
+}
+
+impl Default for AudioChannelLayout {
+    fn default() -> Self {
+        AudioChannelLayout::Mono
+    }
+}
+
+impl AudioChannelLayout {
+    /// Number of channels, and so of buffers in a frame, for this layout.
+    pub fn num_channels(&self) -> usize {
+        match self {
+            AudioChannelLayout::Mono => 1,
+            AudioChannelLayout::L => 1,
+            AudioChannelLayout::R => 1,
+            AudioChannelLayout::L_R => 2,
+            AudioChannelLayout::R_L => 2,
+            AudioChannelLayout::L_R_C_LFE_Ls_Rs => 6,
+        }
+    }
+
+    /// The conventional layout for a number of channels, `None` if there is no such layout.
+    pub fn from_num_channels(num_channels: usize) -> Option<Self> {
+        match num_channels {
+            1 => Some(AudioChannelLayout::Mono),
+            2 => Some(AudioChannelLayout::L_R),
+            6 => Some(AudioChannelLayout::L_R_C_LFE_Ls_Rs),
+            _ => None,
+        }
+    }
 }
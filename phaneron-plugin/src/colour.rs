@@ -5,11 +5,16 @@
 use abi_stable::StableAbi;
 
 pub use self::{
-    bt_2020::COLOUR_SPEC_BT_2020, bt_601_525::COLOUR_SPEC_BT_601_525,
-    bt_601_625::COLOUR_SPEC_BT_601_625, bt_709::COLOUR_SPEC_BT_709, srgb::COLOUR_SPEC_SRGB,
+    bt_2020::COLOUR_SPEC_BT_2020,
+    bt_2100::{COLOUR_SPEC_BT_2100_HLG, COLOUR_SPEC_BT_2100_PQ},
+    bt_601_525::COLOUR_SPEC_BT_601_525,
+    bt_601_625::COLOUR_SPEC_BT_601_625,
+    bt_709::COLOUR_SPEC_BT_709,
+    srgb::COLOUR_SPEC_SRGB,
 };
 
 mod bt_2020;
+mod bt_2100;
 mod bt_601_525;
 mod bt_601_625;
 mod bt_709;
@@ -29,6 +34,37 @@ pub enum ColourSpace {
     BT_709,
     #[allow(non_camel_case_types)]
     BT_2020,
+    /// BT.2020 primaries with the PQ (SMPTE ST 2084) HDR transfer function.
+    #[allow(non_camel_case_types)]
+    BT_2100_PQ,
+    /// BT.2020 primaries with the HLG (ARIB STD-B67) HDR transfer function.
+    #[allow(non_camel_case_types)]
+    BT_2100_HLG,
+}
+
+/// Range of the code values used by a video format.
+/// Limited (studio / MPEG) range uses 16-235 for luma in 8 bits, full (JPEG) range uses 0-255.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, StableAbi)]
+pub enum ColourRange {
+    #[default]
+    Limited,
+    Full,
+}
+
+/// Transfer function between the encoded signal and linear light.
+/// Frames are held in linear light with SDR reference white at 1.0, HDR highlights go above 1.0.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, StableAbi)]
+pub enum TransferFunction {
+    /// Power law with a linear segment near black, defined by `alpha`, `beta`, `gamma` and `delta`
+    /// of the [`ColourSpec`].
+    #[default]
+    Gamma,
+    /// Perceptual Quantizer, the absolute HDR transfer function of SMPTE ST 2084 / BT.2100.
+    PQ,
+    /// Hybrid Log-Gamma, the relative HDR transfer function of ARIB STD-B67 / BT.2100.
+    HLG,
 }
 
 /// Defines the transformation function for a colourspace.
@@ -51,12 +87,16 @@ pub struct ColourSpec {
     pub beta: f32,
     pub gamma: f32,
     pub delta: f32,
+    /// `alpha`, `beta`, `gamma` and `delta` only apply to [`TransferFunction::Gamma`].
+    pub transfer: TransferFunction,
 }
 
 impl ColourSpace {
     pub fn colour_spec(&self) -> ColourSpec {
         match self {
             ColourSpace::BT_2020 => COLOUR_SPEC_BT_2020,
+            ColourSpace::BT_2100_PQ => COLOUR_SPEC_BT_2100_PQ,
+            ColourSpace::BT_2100_HLG => COLOUR_SPEC_BT_2100_HLG,
             ColourSpace::BT_601_525 => COLOUR_SPEC_BT_601_525,
             ColourSpace::BT_601_625 => COLOUR_SPEC_BT_601_625,
             ColourSpace::BT_709 => COLOUR_SPEC_BT_709,
@@ -0,0 +1,16 @@
+use super::{ColourSpec, TransferFunction, COLOUR_SPEC_BT_2020};
+
+/// Colour space transformation for BT.2100 HDR with the Perceptual Quantizer transfer function.
+/// BT.2100 uses the primaries and luma coefficients of BT.2020.
+/// Reference: <https://www.itu.int/dms_pubrec/itu-r/rec/bt/R-REC-BT.2100-2-201807-I!!PDF-E.pdf>
+pub const COLOUR_SPEC_BT_2100_PQ: ColourSpec = ColourSpec {
+    transfer: TransferFunction::PQ,
+    ..COLOUR_SPEC_BT_2020
+};
+
+/// Colour space transformation for BT.2100 HDR with the Hybrid Log-Gamma transfer function.
+/// Reference: <https://www.itu.int/dms_pubrec/itu-r/rec/bt/R-REC-BT.2100-2-201807-I!!PDF-E.pdf>
+pub const COLOUR_SPEC_BT_2100_HLG: ColourSpec = ColourSpec {
+    transfer: TransferFunction::HLG,
+    ..COLOUR_SPEC_BT_2020
+};
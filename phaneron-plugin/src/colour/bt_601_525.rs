@@ -1,4 +1,4 @@
-use super::ColourSpec;
+use super::{ColourSpec, TransferFunction};
 
 /// Colour space transformation for BT.601 (525 lines).
 /// Reference: <https://www.itu.int/dms_pubrec/itu-r/rec/bt/R-REC-BT.601-7-201103-I!!PDF-E.pdf>
@@ -17,4 +17,5 @@ pub const COLOUR_SPEC_BT_601_525: ColourSpec = ColourSpec {
     beta: 0.018,
     gamma: 0.45,
     delta: 4.5,
+    transfer: TransferFunction::Gamma,
 };
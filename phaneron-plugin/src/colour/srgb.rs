@@ -1,4 +1,4 @@
-use super::ColourSpec;
+use super::{ColourSpec, TransferFunction};
 
 /// Colour space transformation for sRGB using kR and kB values from BT.709.
 /// Reference: <https://en.wikipedia.org/wiki/SRGB>
@@ -17,4 +17,5 @@ pub const COLOUR_SPEC_SRGB: ColourSpec = ColourSpec {
     beta: 0.0031308,
     gamma: 1.0 / 2.4,
     delta: 12.92,
+    transfer: TransferFunction::Gamma,
 };
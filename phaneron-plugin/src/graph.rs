@@ -13,7 +13,7 @@ impl AudioInputId {
 }
 impl Default for AudioInputId {
     fn default() -> Self {
-        Self(uuid::Uuid::new_v4().to_string().into())
+        Self(crate::ids::new_id().into())
     }
 }
 impl Display for AudioInputId {
@@ -32,7 +32,7 @@ impl AudioOutputId {
 }
 impl Default for AudioOutputId {
     fn default() -> Self {
-        Self(uuid::Uuid::new_v4().to_string().into())
+        Self(crate::ids::new_id().into())
     }
 }
 impl Display for AudioOutputId {
@@ -51,7 +51,7 @@ impl VideoInputId {
 }
 impl Default for VideoInputId {
     fn default() -> Self {
-        Self(uuid::Uuid::new_v4().to_string().into())
+        Self(crate::ids::new_id().into())
     }
 }
 impl Display for VideoInputId {
@@ -70,7 +70,7 @@ impl VideoOutputId {
 }
 impl Default for VideoOutputId {
     fn default() -> Self {
-        Self(uuid::Uuid::new_v4().to_string().into())
+        Self(crate::ids::new_id().into())
     }
 }
 impl Display for VideoOutputId {
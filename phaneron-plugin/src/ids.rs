@@ -0,0 +1,54 @@
+//! Ids of graphs, nodes, inputs, outputs and frames are random UUIDs. With the
+//! `deterministic-ids` feature, tests can call [`use_sequential_ids`] so that ids minted
+//! on the current thread are numbered from 1 instead, which allows asserting on exact ids.
+//!
+//! Plugins are separate libraries, so ids minted inside a plugin are not affected.
+
+#[cfg(feature = "deterministic-ids")]
+use std::cell::Cell;
+
+#[cfg(feature = "deterministic-ids")]
+thread_local! {
+    static NEXT_SEQUENTIAL_ID: Cell<Option<u64>> = Cell::new(None);
+}
+
+/// Creates a new unique id.
+pub fn new_id() -> String {
+    sequential_id().unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+#[cfg(feature = "deterministic-ids")]
+fn sequential_id() -> Option<String> {
+    let id = NEXT_SEQUENTIAL_ID.with(|next| {
+        let id = next.get()?;
+        next.set(Some(id + 1));
+        Some(id)
+    })?;
+    Some(format!("00000000-0000-0000-0000-{id:012x}"))
+}
+
+#[cfg(not(feature = "deterministic-ids"))]
+fn sequential_id() -> Option<String> {
+    None
+}
+
+/// Makes [`new_id`] return sequential ids on the current thread until the returned guard is dropped.
+#[cfg(feature = "deterministic-ids")]
+#[must_use]
+pub fn use_sequential_ids() -> SequentialIds {
+    NEXT_SEQUENTIAL_ID.with(|next| next.set(Some(1)));
+    SequentialIds { _private: () }
+}
+
+/// Returned by [`use_sequential_ids`], restores random ids when dropped.
+#[cfg(feature = "deterministic-ids")]
+pub struct SequentialIds {
+    _private: (),
+}
+
+#[cfg(feature = "deterministic-ids")]
+impl Drop for SequentialIds {
+    fn drop(&mut self) {
+        NEXT_SEQUENTIAL_ID.with(|next| next.set(None));
+    }
+}
@@ -54,6 +54,10 @@
 //! #     fn destroy_node(&self, node_id: RString) -> RResult<(), RString> {
 //! #         todo!()
 //! #     }
+//! #
+//! #     fn build_info(&self) -> phaneron_plugin::traits::BuildInfo {
+//! #         phaneron_plugin::build_info!()
+//! #     }
 //! # }
 //! ```
 //!
@@ -78,14 +82,17 @@ pub use crate::{
     audio::{AudioChannelLayout, AudioFormat},
     colour::*,
     graph::{AudioInputId, AudioOutputId, VideoInputId, VideoOutputId},
+    metadata::FrameMetadata,
     video::{InterlaceMode, VideoFormat},
 };
 
 mod audio;
 mod colour;
 mod graph;
+mod metadata;
 mod video;
 
+pub mod ids;
 pub mod traits;
 pub mod types;
 
@@ -160,6 +167,14 @@ impl RootModule for PhaneronPluginRootModuleRef {
     const VERSION_STRINGS: VersionStrings = package_version_strings!();
 }
 
+/// Frame rate of a graph in frames per second as a fraction, e.g. `30000 / 1001`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, StableAbi)]
+pub struct FrameRate {
+    pub num: u32,
+    pub den: u32,
+}
+
 /// A video frame along with its associated output Id.
 #[repr(C)]
 #[derive(Clone, StableAbi)]
@@ -210,6 +225,30 @@ impl ShaderParams {
         self.params.push(ShaderParam::F32Input(val));
     }
 
+    pub fn set_param_i32_input(&mut self, val: i32) {
+        self.params.push(ShaderParam::I32Input(val));
+    }
+
+    /// Sets a `float2` parameter, e.g. a position.
+    pub fn set_param_vec2_input(&mut self, val: [f32; 2]) {
+        self.params.push(ShaderParam::Vec2Input(val));
+    }
+
+    /// Sets a `float4` parameter, e.g. an RGBA colour.
+    pub fn set_param_vec4_input(&mut self, val: [f32; 4]) {
+        self.params.push(ShaderParam::Vec4Input(val));
+    }
+
+    /// Sets a `__global const float*` parameter holding a copy of `vals`, e.g. a lookup table.
+    /// Element `i` of the slice is element `i` of the kernel's array, the length is not passed so
+    /// shaders that need it should take it as another parameter. Multi-dimensional data is
+    /// flattened by the plugin, e.g. a 3D LUT of size `n` with RGB entries in `.cube` order has
+    /// red varying fastest: the entry for `(r, g, b)` starts at `((b * n + g) * n + r) * 3`.
+    /// An empty slice is passed as a single `0.0`.
+    pub fn set_param_f32_array_input(&mut self, vals: &[f32]) {
+        self.params.push(ShaderParam::F32ArrayInput(vals.into()));
+    }
+
     pub fn set_param_bool_input(&mut self, val: bool) {
         self.params.push(ShaderParam::Bool(val));
     }
@@ -232,10 +271,28 @@ pub enum ShaderParam {
     VideoFrameInput(types::VideoFrame),
     U32Input(u32),
     F32Input(f32),
+    I32Input(i32),
+    Vec2Input([f32; 2]),
+    Vec4Input([f32; 4]),
+    F32ArrayInput(RVec<f32>),
     Bool(bool),
     VideoFrameOutput { width: usize, height: usize },
 }
 
+/// Creates a [`BuildInfo`](traits::BuildInfo) describing the crate that invokes it.
+/// The commit and compiler are read from the `PHANERON_BUILD_COMMIT` and `PHANERON_BUILD_RUSTC`
+/// environment variables at compile time, if they are set.
+#[macro_export]
+macro_rules! build_info {
+    () => {
+        $crate::traits::BuildInfo::new(
+            env!("CARGO_PKG_VERSION"),
+            option_env!("PHANERON_BUILD_COMMIT"),
+            option_env!("PHANERON_BUILD_RUSTC"),
+        )
+    };
+}
+
 /// Provides logging to a plugin.
 /// It can be set as the default logger for the `log` trait by calling `init`.
 pub struct PluginLogger {
@@ -0,0 +1,41 @@
+use abi_stable::{
+    std_types::{RString, RVec, Tuple2},
+    StableAbi,
+};
+
+/// Small key/value data carried along with a video frame, such as a source timecode, captions or custom tags.
+///
+/// Producers attach metadata using [`VideoFrame::with_metadata`](crate::traits::VideoFrame::with_metadata)
+/// and consumers read it using [`VideoFrame::metadata`](crate::traits::VideoFrame::metadata).
+/// Frames that are passed on unchanged keep their metadata. Nodes that create new frames must propagate
+/// the metadata of their primary input, process shaders do this automatically using their first video frame input.
+#[repr(C)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, StableAbi)]
+pub struct FrameMetadata(RVec<Tuple2<RString, RString>>);
+
+impl FrameMetadata {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|entry| entry.0.as_str() == key)
+            .map(|entry| entry.1.as_str())
+    }
+
+    /// Sets the value of a key, replacing any existing value.
+    pub fn set(&mut self, key: &str, value: &str) {
+        match self.0.iter_mut().find(|entry| entry.0.as_str() == key) {
+            Some(entry) => entry.1 = value.into(),
+            None => self.0.push(Tuple2(key.into(), value.into())),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|entry| (entry.0.as_str(), entry.1.as_str()))
+    }
+}
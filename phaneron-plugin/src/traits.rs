@@ -19,6 +19,35 @@ pub trait PhaneronPlugin: Send + Sync {
         description: CreateNodeDescription,
     ) -> RResult<crate::types::NodeHandle, RString>;
     fn destroy_node(&self, node_id: RString) -> RResult<(), RString>;
+    /// Returns information about the build of the plugin, can be created using the [`build_info`](crate::build_info) macro.
+    fn build_info(&self) -> BuildInfo;
+    /// Returns a JSON Schema describing the state accepted by nodes of `node_type`, so that clients can
+    /// build forms for editing it. Returns an error for node types that don't provide a schema.
+    fn get_node_state_schema(&self, node_type: RString) -> RResult<RString, RString> {
+        RResult::RErr(format!("Node type {node_type} does not provide a state schema").into())
+    }
+}
+
+/// Describes the build of a plugin so that the host can report which build is loaded.
+#[repr(C)]
+#[derive(StableAbi)]
+pub struct BuildInfo {
+    /// Version of the plugin.
+    pub version: RString,
+    /// Commit that the plugin was built from, if known.
+    pub commit: ROption<RString>,
+    /// Version of the compiler used to build the plugin, if known.
+    pub compiler: ROption<RString>,
+}
+
+impl BuildInfo {
+    pub fn new(version: &str, commit: Option<&str>, compiler: Option<&str>) -> Self {
+        Self {
+            version: version.into(),
+            commit: commit.map(RString::from).into(),
+            compiler: compiler.map(RString::from).into(),
+        }
+    }
 }
 
 /// Provides a description of an available node type provided by a plugin.
@@ -77,6 +106,7 @@ pub trait NodeContext: Send + Sync {
         &self,
         video_format: &VideoFormat,
         colour_space: &ColourSpec,
+        colour_range: ColourRange,
         width: usize,
         height: usize,
     ) -> crate::types::ToRGBA;
@@ -85,6 +115,7 @@ pub trait NodeContext: Send + Sync {
         &self,
         video_format: &VideoFormat,
         colour_space: &ColourSpec,
+        colour_range: ColourRange,
         width: usize,
         height: usize,
         interlace: InterlaceMode,
@@ -104,11 +135,18 @@ pub trait NodeContext: Send + Sync {
     /// Create a shader from a source string.
     /// * `kernel` - Shader code.
     /// * `program_name` - Name of the kernel function.
+    ///
+    /// Returns an error if the shader fails to build or doesn't contain `program_name`, the error
+    /// includes the compiler's build log.
     fn create_process_shader(
         &self,
         kernel: RStr<'_>,
         program_name: RStr<'_>,
-    ) -> crate::types::ProcessShader;
+    ) -> RResult<crate::types::ProcessShader, RString>;
+    /// Report values that the node has determined itself, such as a format negotiated with a device.
+    /// `state` is a JSON object whose fields are merged into the node's state as seen by clients,
+    /// they are not applied to the node.
+    fn report_state(&self, state: RString);
 }
 
 /// Provides proof that frame processing operations can be performed.
@@ -123,21 +161,77 @@ pub trait ProcessFrameContext {
     fn get_audio_input(&self, id: &AudioInputId) -> ROption<&crate::AudioFrameWithId>;
     fn get_black_frame(&self) -> &crate::VideoFrameWithId;
     fn get_silence_frame(&self) -> &crate::AudioFrameWithId;
+    /// Frame rate of the graph that the node belongs to.
+    fn get_frame_rate(&self) -> crate::FrameRate;
+    /// Audio sample rate of the graph that the node belongs to, in Hz.
+    fn get_sample_rate(&self) -> u32;
+    /// Frame of the graph clock that is being processed, counted from when the graph was created.
+    fn get_frame_number(&self) -> u64;
+    /// Number of samples in each block of audio of the graph, audio frames hold a whole number of
+    /// blocks. `RNone` when the graph handles audio in one block per frame.
+    fn get_audio_block_samples(&self) -> ROption<u32>;
 }
 
 /// Provides proof that frame copy operations can be performed.
+/// Frames consumed during processing are copied from the GPU through this context
+/// once [`ProcessFrameContext::submit`] has been called.
 #[sabi_trait]
-pub trait FrameContext {}
+pub trait FrameContext {
+    /// Copies a video frame from the GPU, each plane of the frame is returned in its own buffer.
+    fn copy_video_frame(
+        &self,
+        from_rgba: &crate::types::FromRGBA,
+        frame: crate::types::ConsumedVideoFrame,
+    ) -> RVec<RVec<u8>>;
+    /// Copies a video frame from the GPU into a single buffer with the planes written back-to-back.
+    fn copy_video_frame_contiguous(
+        &self,
+        from_rgba: &crate::types::FromRGBA,
+        frame: crate::types::ConsumedVideoFrame,
+    ) -> RVec<u8>;
+    /// Starts copying a video frame from the GPU without waiting for it and returns the frame that was
+    /// passed to the previous call for the same [`FromRGBA`], in the same layout as [`copy_video_frame_contiguous`].
+    /// This allows the download of one frame to overlap with the consumer's work on the previous one,
+    /// at the cost of one frame of latency. Returns `None` for the first frame.
+    fn copy_video_frame_pipelined(
+        &self,
+        from_rgba: &crate::types::FromRGBA,
+        frame: crate::types::ConsumedVideoFrame,
+    ) -> ROption<RVec<u8>>;
+    /// Provides an audio frame as a single buffer.
+    fn copy_audio_frame(
+        &self,
+        from_audio_f32: &crate::types::FromAudioF32,
+        frame: crate::types::ConsumedAudioFrame,
+    ) -> RVec<u8>;
+}
 
 /// Once a process shader has been created, this trait allows a node
 /// to interact with the shader.
 #[sabi_trait]
 pub trait ProcessShader: Send + Sync {
+    /// Runs the shader, returning a frame for each
+    /// [`ShaderParam::VideoFrameOutput`](crate::ShaderParam::VideoFrameOutput).
+    ///
+    /// If the buffer pool stays full for longer than a frame can wait then the shader is skipped:
+    /// the error is logged and each output repeats its frame from the previous run if the size is
+    /// unchanged, otherwise the first video frame input or a black frame takes its place.
     fn run(
         &self,
         params: crate::ShaderParams,
         global_work_size: &[usize; 2],
     ) -> RVec<crate::types::VideoFrame>;
+    /// Runs the shader with a fixed work-group (tile) size instead of letting the driver choose one.
+    ///
+    /// Each global work size must be divisible by the matching local work size, and the total size
+    /// of a work-group must not exceed the device's limit. If the local work size doesn't divide the
+    /// global work size then it is ignored and the driver chooses as with [`ProcessShader::run`].
+    fn run_with_local_size(
+        &self,
+        params: crate::ShaderParams,
+        global_work_size: &[usize; 2],
+        local_work_size: &[usize; 2],
+    ) -> RVec<crate::types::VideoFrame>;
 }
 
 /// Provides a handle to a video frame on the GPU.
@@ -146,26 +240,54 @@ pub trait VideoFrame: Send + Sync {
     fn buffer_index(&self) -> usize;
     fn width(&self) -> usize;
     fn height(&self) -> usize;
+    /// Data carried along with the frame, see [`FrameMetadata`](crate::FrameMetadata).
+    fn metadata(&self) -> &crate::FrameMetadata;
+    /// Creates a frame that shares the pixels of this frame but carries different metadata.
+    fn with_metadata(&self, metadata: crate::FrameMetadata) -> crate::types::VideoFrame;
 }
 
 /// Provides a handle to an audio frame (and the data).
 #[sabi_trait]
 pub trait AudioFrame: Send + Sync {
+    /// One buffer of samples per channel, in the order of [`channel_layout`](Self::channel_layout).
     fn buffers(&self) -> &RVec<RVec<f32>>;
+    /// Which channel each buffer holds.
+    fn channel_layout(&self) -> AudioChannelLayout;
 }
 
 /// A video output from a node, this is where the video frames a node creates should be sent to
 /// be forwarded to other nodes.
 #[sabi_trait]
 pub trait VideoOutput: Send + Sync {
-    fn push_frame(&self, context: &crate::types::FrameContext, frame: crate::types::VideoFrame);
+    /// Sends a frame to every node connected to this output.
+    /// Returns an error if the frame was not delivered to any node, in which case a node may
+    /// choose to skip the work of producing further frames for this output.
+    fn push_frame(
+        &self,
+        context: &crate::types::FrameContext,
+        frame: crate::types::VideoFrame,
+    ) -> RResult<(), PushFrameError>;
 }
 
 /// An audio output from a node, this is where the audio frames a node creates shoud be sent to
 /// be forwarded to other nodes.
 #[sabi_trait]
 pub trait AudioOutput: Send + Sync {
-    fn push_frame(&self, context: &crate::types::FrameContext, frame: crate::types::AudioFrame);
+    /// Sends a frame to every node connected to this output.
+    /// Returns an error if the frame was not delivered to any node.
+    fn push_frame(
+        &self,
+        context: &crate::types::FrameContext,
+        frame: crate::types::AudioFrame,
+    ) -> RResult<(), PushFrameError>;
+}
+
+/// Reasons that a frame pushed to an output was not delivered.
+#[repr(C)]
+#[derive(Debug, Clone, PartialEq, Eq, StableAbi)]
+pub enum PushFrameError {
+    /// Nothing is connected to the output, or every connected node has gone away.
+    NoReceivers,
 }
 
 /// Provides functions for loading video frames onto the GPU.
@@ -193,6 +315,7 @@ pub trait ToRGBA: Send + Sync {
 pub trait LoadedVideoFrame {}
 
 /// Provides functions for consuming video frames from the GPU.
+/// Consumed frames are copied from the GPU using a [`FrameContext`].
 #[sabi_trait]
 pub trait FromRGBA: Send + Sync {
     /// Can be used to obtain the size of the copied frame.
@@ -207,12 +330,6 @@ pub trait FromRGBA: Send + Sync {
         context: &crate::types::ProcessFrameContext,
         frame: crate::types::VideoFrame,
     ) -> crate::types::ConsumedVideoFrame;
-    /// Copies a frame from the GPU.
-    fn copy_frame(
-        &self,
-        context: &crate::types::FrameContext, // Required to prove that processing has finished
-        frame: crate::types::ConsumedVideoFrame,
-    ) -> RVec<RVec<u8>>;
 }
 
 /// A handle to a video frame that has been transformed into a requested colour space.
@@ -242,12 +359,6 @@ pub trait FromAudioF32: Send + Sync {
         context: &crate::types::ProcessFrameContext,
         frame: crate::types::AudioFrame,
     ) -> crate::types::ConsumedAudioFrame;
-    /// Provides an audio frame as a single buffer.
-    fn copy_frame(
-        &self,
-        context: &crate::types::FrameContext,
-        frame: crate::types::ConsumedAudioFrame,
-    ) -> RVec<u8>;
 }
 
 /// Provides a handle to an audio frame that has been transformed into a requested format.
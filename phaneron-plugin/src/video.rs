@@ -19,4 +19,8 @@ pub enum VideoFormat {
     YUV420p,
     YUV422p8,
     YUV422p10,
+    /// 8-bit 4:2:0 with a luma plane followed by a plane of interleaved U/V samples.
+    NV12,
+    /// 10-bit 4:2:0 semi-planar, samples held in the high bits of little-endian 16-bit words.
+    P010,
 }
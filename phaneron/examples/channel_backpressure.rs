@@ -0,0 +1,100 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Compares the CPU time used by a producer that is faster than its consumer when the producer
+//! checks again straight away whether the consumer is ready, against when it blocks on a full
+//! [`Channel`].
+//!
+//! Run with `cargo run --release --example channel_backpressure`. CPU time is read from
+//! `/proc/self/stat`, so this only runs on Linux.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use phaneron::{Channel, ChannelSemaphoreProvider};
+
+const FRAMES: usize = 100;
+const CAPACITY: usize = 2;
+const CONSUMER_FRAME_TIME: Duration = Duration::from_millis(10);
+/// `USER_HZ`, which is 100 on every architecture Linux supports.
+const CLOCK_TICKS_PER_SEC: u64 = 100;
+
+/// User and system CPU time of the whole process.
+fn cpu_time() -> Duration {
+    let stat = std::fs::read_to_string("/proc/self/stat").unwrap();
+    // The command name may contain spaces, the fields after it are space separated
+    let fields: Vec<&str> = stat[stat.rfind(')').unwrap() + 2..].split(' ').collect();
+    let ticks: u64 = fields[11].parse::<u64>().unwrap() + fields[12].parse::<u64>().unwrap();
+    Duration::from_millis(ticks * 1000 / CLOCK_TICKS_PER_SEC)
+}
+
+/// Pushes `FRAMES` values to a consumer that takes `CONSUMER_FRAME_TIME` to process each of them,
+/// returning the wall time and the CPU time used.
+fn run(spin: bool) -> (Duration, Duration) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let channel = Channel::with_capacity(CAPACITY);
+    let mut receiver = runtime.block_on(channel.subscribe());
+    let received = Arc::new(AtomicUsize::new(0));
+
+    let consumer = runtime.spawn({
+        let received = received.clone();
+        async move {
+            while let Some((_, semaphore)) = receiver.recv().await {
+                tokio::time::sleep(CONSUMER_FRAME_TIME).await;
+                received.fetch_add(1, Ordering::SeqCst);
+                semaphore.signal().await;
+            }
+        }
+    });
+
+    let started = Instant::now();
+    let cpu_started = cpu_time();
+    let semaphore_provider = ChannelSemaphoreProvider::default();
+    let mut sent = 0;
+    while sent < FRAMES {
+        if spin && sent - received.load(Ordering::SeqCst) >= CAPACITY {
+            // The consumer isn't ready, check again
+            std::hint::spin_loop();
+            continue;
+        }
+        channel.send(&semaphore_provider, sent);
+        sent += 1;
+    }
+    channel.close();
+    runtime.block_on(consumer).unwrap();
+
+    (started.elapsed(), cpu_time() - cpu_started)
+}
+
+fn main() {
+    println!(
+        "{FRAMES} frames, consumer takes {CONSUMER_FRAME_TIME:?} per frame, capacity {CAPACITY}"
+    );
+    for (name, spin) in [("spinning", true), ("blocking", false)] {
+        let (wall, cpu) = run(spin);
+        println!(
+            "{name:>10}  wall {wall:>10.1?}  cpu {cpu:>10.1?}  {:.0}% of a core",
+            cpu.as_secs_f64() / wall.as_secs_f64() * 100.0
+        );
+    }
+}
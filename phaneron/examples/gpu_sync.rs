@@ -0,0 +1,146 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Compares the throughput and CPU time of a graph of nodes running a blur on the GPU each frame
+//! when the compute context waits for the GPU with blocking waits, against when it waits on fences.
+//!
+//! Run with `cargo run --release --example gpu_sync [device index]`. CPU time is read from
+//! `/proc/self/stat`, so this only runs on Linux.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use phaneron::{create_compute_context, ComputeDeviceSelection, GpuSyncMode};
+use phaneron_plugin::ShaderParams;
+
+const WIDTH: usize = 1920;
+const HEIGHT: usize = 1080;
+const NODES: usize = 8;
+const FRAMES: usize = 200;
+/// `USER_HZ`, which is 100 on every architecture Linux supports.
+const CLOCK_TICKS_PER_SEC: u64 = 100;
+
+const GRADIENT_KERNEL: &str = r#"
+__kernel void gradient(__write_only image2d_t output) {
+    int x = get_global_id(0);
+    int y = get_global_id(1);
+    float2 size = convert_float2(get_image_dim(output));
+
+    write_imagef(output, (int2)(x, y), (float4)(x / size.x, y / size.y, (x ^ y) & 1, 1.0f));
+}
+"#;
+
+const BLUR_KERNEL: &str = r#"
+__constant sampler_t sampler1 =
+    CLK_NORMALIZED_COORDS_FALSE
+    | CLK_ADDRESS_CLAMP_TO_EDGE
+    | CLK_FILTER_NEAREST;
+
+__kernel void blur(__read_only image2d_t input, __write_only image2d_t output) {
+    int x = get_global_id(0);
+    int y = get_global_id(1);
+
+    float4 sum = (float4)(0.0f);
+    for (int dy = -2; dy <= 2; dy++) {
+        for (int dx = -2; dx <= 2; dx++) {
+            sum += read_imagef(input, sampler1, (int2)(x + dx, y + dy));
+        }
+    }
+
+    write_imagef(output, (int2)(x, y), sum / 25.0f);
+}
+"#;
+
+/// User and system CPU time of the whole process.
+fn cpu_time() -> Duration {
+    let stat = std::fs::read_to_string("/proc/self/stat").unwrap();
+    // The command name may contain spaces, the fields after it are space separated
+    let fields: Vec<&str> = stat[stat.rfind(')').unwrap() + 2..].split(' ').collect();
+    let ticks: u64 = fields[11].parse::<u64>().unwrap() + fields[12].parse::<u64>().unwrap();
+    Duration::from_millis(ticks * 1000 / CLOCK_TICKS_PER_SEC)
+}
+
+/// Runs `NODES` nodes for `FRAMES` frames each, returning the wall time and the CPU time used.
+/// Like `run_node`, each node processes a frame on its own thread and then waits from its task for
+/// the frame to be complete before starting the next one.
+fn run(sync_mode: GpuSyncMode, device: ComputeDeviceSelection) -> (Duration, Duration) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async move {
+        let context = create_compute_context(sync_mode, device, 0).await.unwrap();
+        let gradient = context
+            .create_process_shader(GRADIENT_KERNEL, "gradient")
+            .unwrap();
+        let blur = Arc::new(context.create_process_shader(BLUR_KERNEL, "blur").unwrap());
+        let mut params = ShaderParams::default();
+        params.set_param_video_frame_output(WIDTH, HEIGHT);
+        let input = gradient.run(params, &[WIDTH, HEIGHT])[0].clone();
+        context.wait_for_process_queue().await.unwrap();
+
+        let started = Instant::now();
+        let cpu_started = cpu_time();
+        let nodes: Vec<_> = (0..NODES)
+            .map(|_| {
+                let context = context.clone();
+                let blur = blur.clone();
+                let input = input.clone();
+                tokio::spawn(async move {
+                    for _ in 0..FRAMES {
+                        let blur = blur.clone();
+                        let input = input.clone();
+                        tokio::task::spawn_blocking(move || {
+                            let mut params = ShaderParams::default();
+                            params.set_param_video_frame_input(input);
+                            params.set_param_video_frame_output(WIDTH, HEIGHT);
+                            blur.run(params, &[WIDTH, HEIGHT])
+                        })
+                        .await
+                        .unwrap();
+                        context.wait_for_process_queue().await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        for node in nodes {
+            node.await.unwrap();
+        }
+
+        (started.elapsed(), cpu_time() - cpu_started)
+    })
+}
+
+fn main() {
+    let device = std::env::args()
+        .nth(1)
+        .map(|device| ComputeDeviceSelection::from_env_value(&device))
+        .unwrap_or_default();
+
+    println!("{NODES} nodes, {FRAMES} frames each, {WIDTH}x{HEIGHT} 5x5 box blur per frame");
+    for (name, sync_mode) in [
+        ("blocking", GpuSyncMode::Blocking),
+        ("fence", GpuSyncMode::Fence),
+    ] {
+        let (wall, cpu) = run(sync_mode, device.clone());
+        println!(
+            "{name:>10}  {:>6.1} fps  cpu {cpu:>10.1?}  {:.0}% of a core",
+            (NODES * FRAMES) as f64 / wall.as_secs_f64(),
+            cpu.as_secs_f64() / wall.as_secs_f64() * 100.0
+        );
+    }
+}
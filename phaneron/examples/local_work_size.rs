@@ -0,0 +1,124 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Compares the time a representative kernel takes to run with the driver's choice of work-group
+//! size against a range of fixed work-group sizes.
+//!
+//! Run with `cargo run --release --example local_work_size [device index]`.
+
+use std::time::{Duration, Instant};
+
+use phaneron::{create_compute_context, ComputeDeviceSelection, GpuSyncMode};
+use phaneron_plugin::ShaderParams;
+
+const WIDTH: usize = 1920;
+const HEIGHT: usize = 1080;
+const ITERATIONS: u32 = 200;
+
+const GRADIENT_KERNEL: &str = r#"
+__kernel void gradient(__write_only image2d_t output) {
+    int x = get_global_id(0);
+    int y = get_global_id(1);
+    float2 size = convert_float2(get_image_dim(output));
+
+    write_imagef(output, (int2)(x, y), (float4)(x / size.x, y / size.y, (x ^ y) & 1, 1.0f));
+}
+"#;
+
+// A 5x5 box blur reads each pixel's neighbours, so it is sensitive to how work-groups map onto the
+// texture cache, like most filters that nodes run.
+const BLUR_KERNEL: &str = r#"
+__constant sampler_t sampler1 =
+    CLK_NORMALIZED_COORDS_FALSE
+    | CLK_ADDRESS_CLAMP_TO_EDGE
+    | CLK_FILTER_NEAREST;
+
+__kernel void blur(__read_only image2d_t input, __write_only image2d_t output) {
+    int x = get_global_id(0);
+    int y = get_global_id(1);
+
+    float4 sum = (float4)(0.0f);
+    for (int dy = -2; dy <= 2; dy++) {
+        for (int dx = -2; dx <= 2; dx++) {
+            sum += read_imagef(input, sampler1, (int2)(x + dx, y + dy));
+        }
+    }
+
+    write_imagef(output, (int2)(x, y), sum / 25.0f);
+}
+"#;
+
+const LOCAL_WORK_SIZES: [[usize; 2]; 7] =
+    [[8, 4], [8, 8], [16, 4], [16, 8], [32, 4], [32, 8], [64, 4]];
+
+#[tokio::main]
+async fn main() {
+    let device = std::env::args()
+        .nth(1)
+        .map(|device| ComputeDeviceSelection::from_env_value(&device))
+        .unwrap_or_default();
+    // Blocking waits for each kernel to complete, so the wall time of a run is the kernel time
+    let context = create_compute_context(GpuSyncMode::Blocking, device, 0)
+        .await
+        .unwrap();
+
+    let gradient = context
+        .create_process_shader(GRADIENT_KERNEL, "gradient")
+        .unwrap();
+    let blur = context.create_process_shader(BLUR_KERNEL, "blur").unwrap();
+
+    let mut params = ShaderParams::default();
+    params.set_param_video_frame_output(WIDTH, HEIGHT);
+    let input = gradient.run(params, &[WIDTH, HEIGHT])[0].clone();
+
+    let time = |local_work_size: Option<[usize; 2]>| {
+        let mut elapsed = Duration::ZERO;
+        // The first runs include compiling and allocating output images
+        for iteration in 0..ITERATIONS + 10 {
+            let mut params = ShaderParams::default();
+            params.set_param_video_frame_input(input.clone());
+            params.set_param_video_frame_output(WIDTH, HEIGHT);
+
+            let start = Instant::now();
+            match local_work_size {
+                Some(local_work_size) => {
+                    blur.run_with_local_size(params, &[WIDTH, HEIGHT], &local_work_size)
+                }
+                None => blur.run(params, &[WIDTH, HEIGHT]),
+            };
+            if iteration >= 10 {
+                elapsed += start.elapsed();
+            }
+        }
+
+        elapsed / ITERATIONS
+    };
+
+    let driver = time(None);
+    println!("{WIDTH}x{HEIGHT} 5x5 box blur, mean of {ITERATIONS} runs");
+    println!("{:>10}  {:>10?}", "driver", driver);
+    for local_work_size in LOCAL_WORK_SIZES {
+        let tuned = time(Some(local_work_size));
+        println!(
+            "{:>10}  {:>10?}  {:+.1}%",
+            format!("{}x{}", local_work_size[0], local_work_size[1]),
+            tuned,
+            (tuned.as_secs_f64() / driver.as_secs_f64() - 1.0) * 100.0
+        );
+    }
+}
@@ -23,7 +23,9 @@ __kernel void write(
     __global uchar4* restrict output,
     __private unsigned int width,
     __private unsigned int interlace,
-    __global float* restrict gammaLut
+    __global float* restrict gammaLut,
+    __private float gammaLutScale,
+    __constant float4* restrict gamutMatrix
 ) {
     bool lastItemOnLine = get_local_id(0) == get_local_size(0) - 1;
 
@@ -36,14 +38,27 @@ __kernel void write(
     uint inOff = width * line + get_local_id(0) * 64;
     uint outOff = width * line + get_local_id(0) * 64;
 
+    // optimise loading of the 3x3 gamut matrix
+    float4 gamutMat0 = gamutMatrix[0];
+    float4 gamutMat1 = gamutMatrix[1];
+    float4 gamutMat2 = gamutMatrix[2];
+    float3 gamutMatR = (float3)(gamutMat0.s0, gamutMat0.s1, gamutMat0.s2);
+    float3 gamutMatG = (float3)(gamutMat0.s3, gamutMat1.s0, gamutMat1.s1);
+    float3 gamutMatB = (float3)(gamutMat1.s2, gamutMat1.s3, gamutMat2.s0);
+
     for (uint i=0; i<numLoops; ++i) {
         uchar4 rgba;
 
-        float4 rgba_l = input[inOff];
+        float4 rgba_c = input[inOff];
+        float4 rgba_l;
+        rgba_l.s0 = dot(rgba_c.s012, gamutMatR);
+        rgba_l.s1 = dot(rgba_c.s012, gamutMatG);
+        rgba_l.s2 = dot(rgba_c.s012, gamutMatB);
+        rgba_l.s3 = rgba_c.s3;
         float3 rgb_f;
-        rgb_f.s0 = gammaLut[convert_ushort_sat_rte(rgba_l.s0 * 65535.0f)];
-        rgb_f.s1 = gammaLut[convert_ushort_sat_rte(rgba_l.s1 * 65535.0f)];
-        rgb_f.s2 = gammaLut[convert_ushort_sat_rte(rgba_l.s2 * 65535.0f)];
+        rgb_f.s0 = gammaLut[convert_ushort_sat_rte(rgba_l.s0 * gammaLutScale)];
+        rgb_f.s1 = gammaLut[convert_ushort_sat_rte(rgba_l.s1 * gammaLutScale)];
+        rgb_f.s2 = gammaLut[convert_ushort_sat_rte(rgba_l.s2 * gammaLutScale)];
 
         rgba.s0 = convert_uchar_sat_rte(rgb_f.s0 * 255.0f);
         rgba.s1 = convert_uchar_sat_rte(rgb_f.s1 * 255.0f);
@@ -24,19 +24,23 @@ __kernel void write(
     __private unsigned int width,
     __private unsigned int interlace,
     __constant float4* restrict colMatrix,
-    __global float* restrict gammaLut
+    __global float* restrict gammaLut,
+    __private float gammaLutScale,
+    __constant float4* restrict gamutMatrix
 ) {
     bool lastItemOnLine = get_local_id(0) == get_local_size(0) - 1;
 
     // 48 pixels per workItem = 8 output uint4s per work item
     uint numPixels = lastItemOnLine && (0 != width % 48) ? width % 48 : 48;
-    uint numLoops = numPixels / 6;
-    uint remain = numPixels % 6;
+    // 6 pixels per uint4, the last group on a line may hold fewer
+    uint numGroups = (numPixels + 5) / 6;
 
     uint interlaceOff = (3 == interlace) ? 1 : 0;
     uint line = get_group_id(0) * ((0 == interlace) ? 1 : 2) + interlaceOff;
     uint inOff = width * line + get_local_id(0) * 48;
-    uint outOff = width * line / 6 + get_local_id(0) * 8;
+    // Lines are padded to a multiple of 48 pixels, which is 128 bytes
+    uint lineWords = get_local_size(0) * 8;
+    uint outOff = lineWords * line + get_local_id(0) * 8;
 
     if (48 != numPixels) {
         // clear the output buffer for the last item, partially overwritten below
@@ -50,15 +54,30 @@ __kernel void write(
     float4 matU = colMatrix[1];
     float4 matV = colMatrix[2];
 
-    for (uint i=0; i<numLoops; ++i) {
+    // optimise loading of the 3x3 gamut matrix
+    float4 gamutMat0 = gamutMatrix[0];
+    float4 gamutMat1 = gamutMatrix[1];
+    float4 gamutMat2 = gamutMatrix[2];
+    float3 gamutMatR = (float3)(gamutMat0.s0, gamutMat0.s1, gamutMat0.s2);
+    float3 gamutMatG = (float3)(gamutMat0.s3, gamutMat1.s0, gamutMat1.s1);
+    float3 gamutMatB = (float3)(gamutMat1.s2, gamutMat1.s3, gamutMat2.s0);
+
+    for (uint i=0; i<numGroups; ++i) {
         ushort3 yuv[6];
 
+        // Pixels past the end of the line repeat the last pixel so that its chroma pair is complete
+        uint lastPixel = min(numPixels - i * 6, 6u) - 1;
         for (uint p=0; p<6; ++p) {
-            float4 rgba_l = input[inOff+p];
+            float4 rgba_c = input[inOff+min(p, lastPixel)];
+            float4 rgba_l;
+            rgba_l.s0 = dot(rgba_c.s012, gamutMatR);
+            rgba_l.s1 = dot(rgba_c.s012, gamutMatG);
+            rgba_l.s2 = dot(rgba_c.s012, gamutMatB);
+            rgba_l.s3 = rgba_c.s3;
             float4 rgba;
-            rgba.s0 = gammaLut[convert_ushort_sat_rte(rgba_l.s0 * 65535.0f)];
-            rgba.s1 = gammaLut[convert_ushort_sat_rte(rgba_l.s1 * 65535.0f)];
-            rgba.s2 = gammaLut[convert_ushort_sat_rte(rgba_l.s2 * 65535.0f)];
+            rgba.s0 = gammaLut[convert_ushort_sat_rte(rgba_l.s0 * gammaLutScale)];
+            rgba.s1 = gammaLut[convert_ushort_sat_rte(rgba_l.s1 * gammaLutScale)];
+            rgba.s2 = gammaLut[convert_ushort_sat_rte(rgba_l.s2 * gammaLutScale)];
             rgba.s3 = 1.0f;
 
             yuv[p].s0 = convert_ushort_sat_rte(dot(rgba, matY));
@@ -76,31 +95,4 @@ __kernel void write(
         inOff+=6;
         outOff++;
     }
-
-    if (remain > 0) {
-        uint4 w = (uint4)(0, 0, 0, 0);
-
-        ushort3 yuv[4];
-        for (uint p=0; p<remain; ++p) {
-            float4 rgba_l = input[inOff+p];
-            float4 rgba;
-            rgba.s0 = gammaLut[convert_ushort_sat_rtz(rgba_l.s0 * 65535.0f)];
-            rgba.s1 = gammaLut[convert_ushort_sat_rtz(rgba_l.s1 * 65535.0f)];
-            rgba.s2 = gammaLut[convert_ushort_sat_rtz(rgba_l.s2 * 65535.0f)];
-            rgba.s3 = 1.0;
-
-            yuv[p].s0 = convert_ushort_sat(round(dot(rgba, matY)));
-            yuv[p].s1 = convert_ushort_sat(round(dot(rgba, matU)));
-            yuv[p].s2 = convert_ushort_sat(round(dot(rgba, matV)));
-        }
-
-        w.s0 = yuv[0].s2 << 20 | yuv[0].s0 << 10 | yuv[0].s1;
-        if (2 == remain) {
-            w.s1 = yuv[1].s0;
-        } else if (4 == remain) {
-            w.s1 = yuv[2].s0 << 20 | yuv[2].s1 << 10 | yuv[1].s0;
-            w.s2 = yuv[3].s0 << 10 | yuv[2].s2;
-        }
-        output[outOff] = w;
-    }
 }
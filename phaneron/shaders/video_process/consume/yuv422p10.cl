@@ -26,7 +26,9 @@ __kernel void write(
     __private unsigned int width,
     __private unsigned int interlace,
     __constant float4* restrict colMatrix,
-    __global float* restrict gammaLut
+    __global float* restrict gammaLut,
+    __private float gammaLutScale,
+    __constant float4* restrict gamutMatrix
 ) {
     bool lastItemOnLine = get_local_id(0) == get_local_size(0) - 1;
 
@@ -46,15 +48,28 @@ __kernel void write(
     float4 matU = colMatrix[1];
     float4 matV = colMatrix[2];
 
+    // optimise loading of the 3x3 gamut matrix
+    float4 gamutMat0 = gamutMatrix[0];
+    float4 gamutMat1 = gamutMatrix[1];
+    float4 gamutMat2 = gamutMatrix[2];
+    float3 gamutMatR = (float3)(gamutMat0.s0, gamutMat0.s1, gamutMat0.s2);
+    float3 gamutMatG = (float3)(gamutMat0.s3, gamutMat1.s0, gamutMat1.s1);
+    float3 gamutMatB = (float3)(gamutMat1.s2, gamutMat1.s3, gamutMat2.s0);
+
     for (uint i=0; i<numLoops; ++i) {
         ushort3 yuv[8];
 
         for (uint p=0; p<8; ++p) {
-        float4 rgba_l = input[inOff+p];
+        float4 rgba_c = input[inOff+p];
+        float4 rgba_l;
+        rgba_l.s0 = dot(rgba_c.s012, gamutMatR);
+        rgba_l.s1 = dot(rgba_c.s012, gamutMatG);
+        rgba_l.s2 = dot(rgba_c.s012, gamutMatB);
+        rgba_l.s3 = rgba_c.s3;
         float4 rgba;
-            rgba.s0 = gammaLut[convert_ushort_sat_rte(rgba_l.s0 * 65535.0f)];
-            rgba.s1 = gammaLut[convert_ushort_sat_rte(rgba_l.s1 * 65535.0f)];
-            rgba.s2 = gammaLut[convert_ushort_sat_rte(rgba_l.s2 * 65535.0f)];
+            rgba.s0 = gammaLut[convert_ushort_sat_rte(rgba_l.s0 * gammaLutScale)];
+            rgba.s1 = gammaLut[convert_ushort_sat_rte(rgba_l.s1 * gammaLutScale)];
+            rgba.s2 = gammaLut[convert_ushort_sat_rte(rgba_l.s2 * gammaLutScale)];
             rgba.s3 = 1.0f;
 
             yuv[p].s0 = convert_ushort_sat_rte(dot(rgba, matY));
@@ -80,11 +95,16 @@ __kernel void write(
 
         ushort3 yuv[6];
         for (uint p=0; p<remain; ++p) {
-            float4 rgba_l = input[inOff+p];
+            float4 rgba_c = input[inOff+p];
+            float4 rgba_l;
+            rgba_l.s0 = dot(rgba_c.s012, gamutMatR);
+            rgba_l.s1 = dot(rgba_c.s012, gamutMatG);
+            rgba_l.s2 = dot(rgba_c.s012, gamutMatB);
+            rgba_l.s3 = rgba_c.s3;
             float4 rgba;
-            rgba.s0 = gammaLut[convert_ushort_sat_rte(rgba_l.s0 * 65535.0f)];
-            rgba.s1 = gammaLut[convert_ushort_sat_rte(rgba_l.s1 * 65535.0f)];
-            rgba.s2 = gammaLut[convert_ushort_sat_rte(rgba_l.s2 * 65535.0f)];
+            rgba.s0 = gammaLut[convert_ushort_sat_rte(rgba_l.s0 * gammaLutScale)];
+            rgba.s1 = gammaLut[convert_ushort_sat_rte(rgba_l.s1 * gammaLutScale)];
+            rgba.s2 = gammaLut[convert_ushort_sat_rte(rgba_l.s2 * gammaLutScale)];
             rgba.s3 = 1.0;
 
             yuv[p].s0 = convert_ushort_sat_rte(round(dot(rgba, matY)));
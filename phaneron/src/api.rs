@@ -16,11 +16,12 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use anyhow::Context;
 use axum::extract::ws::Message;
-use axum::extract::{State, WebSocketUpgrade};
+use axum::extract::{Query, State, WebSocketUpgrade};
 use axum::http::StatusCode;
 use axum::response::Html;
-use axum::routing::post;
+use axum::routing::{post, put};
 use axum::Json;
 use axum::{
     body::Bytes,
@@ -30,9 +31,10 @@ use axum::{
     routing::get,
     Router,
 };
+use phaneron_plugin::{AudioInputId, VideoInputId, VideoOutputId};
 use std::{
     collections::HashMap,
-    net::{Ipv4Addr, SocketAddr},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
     sync::Arc,
     time::Duration,
 };
@@ -49,8 +51,22 @@ use tracing::info;
 use uuid::Uuid;
 
 use crate::{
-    api::message::RegisterResponse,
-    state::{PhaneronState, PhaneronStateRepresentation},
+    api::message::{
+        ComputeProfiling, CreateAutomationResponse, CreateGraphFromTemplateRequest,
+        CreateGraphFromTemplateResponse, DisconnectInputResponse, GraphModeRequest, GraphPanic,
+        GraphPaused, InputMonitoringRequest, RegisterResponse, ReorderInputsRequest, SnapshotQuery,
+        UpdateNodeRequest,
+    },
+    automation::{AutomationError, AutomationId, CreateAutomation},
+    graph::{GraphId, NodeId, Slate},
+    inputs::{InputsManager, VideoInput},
+    plugins::{NodeStateSchemaError, PluginId, PluginManager, PluginReloadError},
+    saved_graph::SavedGraph,
+    state::{
+        GraphError, ImportGraphError, InputError, NodeStateError, OutputError, PhaneronState,
+        PhaneronStateRepresentation, SnapshotError,
+    },
+    templates::{GraphTemplate, TemplateError, MAX_REPEAT},
 };
 
 use self::message::{RegisterRequest, ServerEvent};
@@ -67,9 +83,24 @@ pub struct Client {
 
 type Clients = Arc<Mutex<HashMap<Uuid, Client>>>;
 
-pub async fn initialize_api(state_context: PhaneronState) {
+/// How often node metrics are sent to websocket clients.
+const METRICS_INTERVAL: Duration = Duration::from_secs(1);
+type GraphTemplates = Arc<Mutex<HashMap<String, GraphTemplate>>>;
+
+/// Serves the API on `addr` until the server stops.
+/// Fails if `addr` can't be bound, e.g. because the port is already in use.
+pub async fn initialize_api(
+    state_context: PhaneronState,
+    plugin_manager: Arc<PluginManager>,
+    inputs_manager: Option<InputsManager>,
+    addr: SocketAddr,
+) -> anyhow::Result<()> {
     info!("Initializing API");
 
+    // Bound before anything else is started so that a port in use fails startup straight away
+    let server = axum::Server::try_bind(&addr)
+        .with_context(|| format!("Failed to bind the API to {addr}"))?;
+
     let clients: Clients = Default::default();
 
     let mut state_rx = state_context.subscribe().await;
@@ -84,41 +115,86 @@ pub async fn initialize_api(state_context: PhaneronState) {
             // TODO: Handle case of receiver closing
             if let Ok(phaneron_state) = state_rx.recv().await {
                 let mut state = state_loop.lock().await;
-                *state = phaneron_state.clone();
+                let previous_state = std::mem::replace(&mut *state, phaneron_state.clone());
 
                 let clients = state_clients.lock().await;
-                let state_json =
-                    serde_json::to_string(&ServerEvent::PhaneronState(phaneron_state)).unwrap();
+                // Serialized once for all of the clients without topics
+                let mut state_json: Option<String> = None;
                 for (_, client) in clients.iter() {
-                    if let Some(sender) = &client.sender {
-                        let message: Message = Message::Text(state_json.clone());
+                    let Some(sender) = &client.sender else {
+                        continue;
+                    };
+                    if client.topics.is_empty() {
+                        let state_json = state_json.get_or_insert_with(|| {
+                            serde_json::to_string(&ServerEvent::PhaneronState(
+                                phaneron_state.clone(),
+                            ))
+                            .unwrap()
+                        });
                         // TODO: Do something if this fails
-                        sender.send(message).ok();
+                        sender.send(Message::Text(state_json.clone())).ok();
+                    } else {
+                        for event in
+                            ws::node_changes(&previous_state, &phaneron_state, &client.topics)
+                        {
+                            let event_json = serde_json::to_string(&event).unwrap();
+                            sender.send(Message::Text(event_json)).ok();
+                        }
                     }
                 }
             }
         }
     });
 
+    let metrics_clients = clients.clone();
+    let metrics_state = state.clone();
+    let metrics_context = state_context.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(METRICS_INTERVAL);
+        loop {
+            interval.tick().await;
+            let metrics = metrics_context.all_node_metrics().await;
+            let state = metrics_state.lock().await;
+            let clients = metrics_clients.lock().await;
+            for client in clients.values() {
+                let Some(sender) = &client.sender else {
+                    continue;
+                };
+                if let Some(event) = ws::node_metrics(&metrics, &state, &client.topics) {
+                    let event_json = serde_json::to_string(&event).unwrap();
+                    sender.send(Message::Text(event_json)).ok();
+                }
+            }
+        }
+    });
+
     let app_state = AppState {
         context: state_context.clone(),
+        plugin_manager,
+        inputs_manager,
+        templates: Default::default(),
         phaneron_state: state.clone(),
         clients: clients.clone(),
+        addr,
     };
 
-    let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 8080));
     info!("Listening on {}", addr);
-    // TODO: This could fail, need to figure out how to get a result from this
-    let _ = axum::Server::bind(&addr)
+    server
         .serve(app(app_state).into_make_service())
-        .await;
+        .await
+        .context("API server stopped")
 }
 
 #[derive(Clone)]
 struct AppState {
     context: PhaneronState,
+    plugin_manager: Arc<PluginManager>,
+    inputs_manager: Option<InputsManager>,
+    templates: GraphTemplates,
     phaneron_state: Arc<Mutex<PhaneronStateRepresentation>>,
     clients: Clients,
+    /// Address the API is bound to.
+    addr: SocketAddr,
 }
 
 fn app(state: AppState) -> Router {
@@ -152,6 +228,7 @@ fn app(state: AppState) -> Router {
         .allow_methods(vec![
             Method::GET,
             Method::POST,
+            Method::PUT,
             Method::DELETE,
             Method::OPTIONS,
         ])
@@ -166,6 +243,79 @@ fn app(state: AppState) -> Router {
             post(register_handler).delete(unregister_handler),
         )
         .route("/ws/:clientId", get(state_ws))
+        .route("/plugins", get(get_plugins))
+        .route("/plugins/usage", get(get_plugin_usage))
+        .route("/plugins/:pluginId", get(get_plugin))
+        .route("/plugins/:pluginId/reload", post(reload_plugin))
+        .route(
+            "/plugins/:pluginId/nodes/:nodeType/state-schema",
+            get(get_node_state_schema),
+        )
+        .route("/compute", get(get_compute_health))
+        .route("/compute/stats", get(get_compute_stats))
+        .route("/compute/profiling", put(put_compute_profiling))
+        .route("/templates", get(get_templates))
+        .route(
+            "/templates/:templateName",
+            get(get_template).put(put_template),
+        )
+        .route("/graphs/from-template", post(create_graph_from_template))
+        .route("/graphs/import", post(import_graph))
+        .route("/graphs/:graphId/export", get(export_graph))
+        .route(
+            "/graphs/:graphId/paused",
+            get(get_graph_paused).put(put_graph_paused),
+        )
+        .route("/graphs/:graphId/pause", post(pause_graph))
+        .route("/graphs/:graphId/resume", post(resume_graph))
+        .route(
+            "/graphs/:graphId/panic",
+            get(get_graph_panic)
+                .put(put_graph_panic)
+                .delete(delete_graph_panic),
+        )
+        .route("/graphs/:graphId/alarms", get(get_graph_alarms))
+        .route(
+            "/graphs/:graphId/mode",
+            get(get_graph_mode).put(put_graph_mode),
+        )
+        .route("/graphs/:graphId/state-batch", post(set_node_states))
+        .route("/graphs/:graphId/dot", get(get_graph_dot))
+        .route("/inputs", get(get_inputs).put(put_inputs))
+        .route("/inputs/reload", post(reload_inputs))
+        .route(
+            "/graphs/:graphId/nodes/:nodeId",
+            axum::routing::put(put_graph_node).delete(delete_graph_node),
+        )
+        .route("/graphs/:graphId/nodes/:nodeId/events", get(node_events_ws))
+        .route(
+            "/graphs/:graphId/nodes/:nodeId/metrics",
+            get(get_node_metrics),
+        )
+        .route(
+            "/graphs/:graphId/nodes/:nodeId/outputs/:outputId/format",
+            get(get_output_format),
+        )
+        .route(
+            "/graphs/:graphId/nodes/:nodeId/outputs/:outputId/snapshot",
+            get(get_output_snapshot),
+        )
+        .route(
+            "/graphs/:graphId/nodes/:nodeId/inputs/reorder",
+            post(reorder_inputs),
+        )
+        .route(
+            "/graphs/:graphId/nodes/:nodeId/inputs/:inputId",
+            axum::routing::put(put_input_monitoring).delete(disconnect_graph_node_input),
+        )
+        .route(
+            "/graphs/:graphId/nodes/:nodeId/automations",
+            post(create_automation).delete(cancel_automations),
+        )
+        .route(
+            "/graphs/:graphId/nodes/:nodeId/automations/:automationId",
+            axum::routing::delete(cancel_automation),
+        )
         .layer(middleware)
         .layer(cors)
         .with_state(state)
@@ -188,10 +338,23 @@ async fn register_handler(
 
     register_client(uuid, user_id, state.clients.clone()).await;
     Json(RegisterResponse {
-        url: format!("ws://127.0.0.1:8080/ws/{uuid}"),
+        url: format!("ws://{}/ws/{uuid}", client_addr(state.addr)),
     })
 }
 
+/// Address clients connect to, an unspecified bind address is reachable through loopback.
+fn client_addr(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(v4) if v4.ip().is_unspecified() => {
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), v4.port())
+        }
+        SocketAddr::V6(v6) if v6.ip().is_unspecified() => {
+            SocketAddr::new(Ipv6Addr::LOCALHOST.into(), v6.port())
+        }
+        addr => addr,
+    }
+}
+
 async fn register_client(id: Uuid, user_id: String, clients: Clients) {
     clients.lock().await.insert(
         id,
@@ -209,6 +372,734 @@ async fn unregister_handler(Path(id): Path<Uuid>, state: State<AppState>) -> imp
     StatusCode::OK
 }
 
+async fn get_plugins(state: State<AppState>) -> impl IntoResponse {
+    let plugin_ids: Vec<String> = state
+        .plugin_manager
+        .get_plugin_ids()
+        .iter()
+        .map(|id| id.to_string())
+        .collect();
+    Json(plugin_ids)
+}
+
+async fn get_plugin(Path(id): Path<String>, state: State<AppState>) -> impl IntoResponse {
+    match state
+        .plugin_manager
+        .get_plugin_info(&PluginId::new_from(id))
+    {
+        Some(info) => Ok(Json(info)),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+async fn reload_plugin(Path(id): Path<String>, state: State<AppState>) -> impl IntoResponse {
+    // Reloading compiles kernels, which blocks
+    let plugin_manager = state.plugin_manager.clone();
+    let result =
+        tokio::task::spawn_blocking(move || plugin_manager.reload_plugin(&PluginId::new_from(id)))
+            .await
+            .unwrap();
+    match result {
+        Ok(report) => Ok(Json(report)),
+        Err(PluginReloadError::PluginDoesNotExist(plugin_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Plugin {plugin_id} does not exist"),
+        )),
+        Err(PluginReloadError::NotReloadable(plugin_id)) => Err((
+            StatusCode::BAD_REQUEST,
+            format!("Plugin {plugin_id} can't be reloaded"),
+        )),
+        Err(PluginReloadError::Failed(err)) => {
+            Err((StatusCode::INTERNAL_SERVER_ERROR, format!("{err:#}")))
+        }
+    }
+}
+
+async fn get_node_state_schema(
+    Path((plugin_id, node_type)): Path<(String, String)>,
+    state: State<AppState>,
+) -> impl IntoResponse {
+    match state
+        .plugin_manager
+        .get_node_state_schema(&PluginId::new_from(plugin_id), &node_type)
+    {
+        Ok(schema) => Ok(Json(schema)),
+        Err(NodeStateSchemaError::PluginDoesNotExist(plugin_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Plugin {plugin_id} does not exist"),
+        )),
+        Err(NodeStateSchemaError::NodeTypeDoesNotExist(node_type)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Plugin does not provide node type {node_type}"),
+        )),
+        Err(NodeStateSchemaError::NoSchema(err)) => Err((StatusCode::NOT_FOUND, err)),
+        Err(NodeStateSchemaError::InvalidSchema(err)) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Plugin returned an invalid schema: {err}"),
+        )),
+    }
+}
+
+async fn get_plugin_usage(state: State<AppState>) -> impl IntoResponse {
+    Json(state.context.node_type_usage().await)
+}
+
+async fn get_compute_health(state: State<AppState>) -> impl IntoResponse {
+    Json(state.context.compute_health().await)
+}
+
+async fn get_compute_stats(state: State<AppState>) -> impl IntoResponse {
+    Json(state.context.compute_stats())
+}
+
+async fn put_compute_profiling(
+    state: State<AppState>,
+    Json(body): Json<ComputeProfiling>,
+) -> impl IntoResponse {
+    match state.context.set_compute_profiling(body.enabled) {
+        Ok(()) => Ok(Json(body)),
+        Err(err) => Err((StatusCode::INTERNAL_SERVER_ERROR, err.to_string())),
+    }
+}
+
+async fn get_templates(state: State<AppState>) -> impl IntoResponse {
+    let template_names: Vec<String> = state.templates.lock().await.keys().cloned().collect();
+    Json(template_names)
+}
+
+async fn get_template(Path(name): Path<String>, state: State<AppState>) -> impl IntoResponse {
+    match state.templates.lock().await.get(&name) {
+        Some(template) => Ok(Json(template.clone())),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+async fn put_template(
+    Path(name): Path<String>,
+    state: State<AppState>,
+    Json(body): Json<GraphTemplate>,
+) -> impl IntoResponse {
+    state.templates.lock().await.insert(name, body);
+    StatusCode::OK
+}
+
+async fn create_graph_from_template(
+    state: State<AppState>,
+    Json(body): Json<CreateGraphFromTemplateRequest>,
+) -> impl IntoResponse {
+    let instantiated = {
+        let templates = state.templates.lock().await;
+        templates
+            .get(&body.template_name)
+            .ok_or_else(|| TemplateError::TemplateDoesNotExist(body.template_name.clone()))
+            .and_then(|template| template.instantiate(&body.parameters))
+    };
+    let instantiated = match instantiated {
+        Ok(instantiated) => instantiated,
+        Err(TemplateError::TemplateDoesNotExist(name)) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("Template {name} does not exist"),
+            ))
+        }
+        Err(TemplateError::UnknownParameter(name)) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Unknown template parameter {name}"),
+            ))
+        }
+        Err(TemplateError::InvalidRepeat(repeat)) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Repeat {repeat} is not a number"),
+            ))
+        }
+        Err(TemplateError::RepeatTooLarge(repeat)) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Repeat {repeat} is more than the limit of {MAX_REPEAT}"),
+            ))
+        }
+        Err(TemplateError::InvalidEntry(err)) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Invalid template entry: {err}"),
+            ))
+        }
+        Err(TemplateError::UnknownNode(node_id)) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Connection refers to unknown node {node_id}"),
+            ))
+        }
+        Err(TemplateError::DuplicateNode(node_id)) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Node {node_id} appears more than once in the template"),
+            ))
+        }
+    };
+
+    if !body.frame_format.is_valid() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Frame rate and sample rate must not be zero, and the audio block size must divide the samples of a frame".to_string(),
+        ));
+    }
+
+    let graph_id = match body.graph_id {
+        Some(graph_id) => GraphId::new_from(graph_id),
+        None => GraphId::default(),
+    };
+    state
+        .context
+        .create_graph(
+            &state.plugin_manager,
+            &graph_id,
+            instantiated.nodes,
+            instantiated.connections,
+            false,
+            body.safety,
+            body.frame_format,
+        )
+        .await
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+
+    Ok(Json(CreateGraphFromTemplateResponse {
+        graph_id: graph_id.to_string(),
+        node_ids: instantiated
+            .node_ids
+            .into_iter()
+            .map(|(template_node_id, node_id)| (template_node_id, node_id.to_string()))
+            .collect(),
+    }))
+}
+
+async fn get_inputs(state: State<AppState>) -> impl IntoResponse {
+    match &state.inputs_manager {
+        Some(inputs_manager) => Ok(Json(inputs_manager.get_inputs().await)),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+async fn put_inputs(
+    state: State<AppState>,
+    Json(body): Json<Vec<VideoInput>>,
+) -> impl IntoResponse {
+    let inputs_manager = match &state.inputs_manager {
+        Some(inputs_manager) => inputs_manager,
+        None => return Err((StatusCode::NOT_FOUND, "Inputs are not managed".to_string())),
+    };
+    match inputs_manager.apply(body).await {
+        Ok(()) => Ok(Json(inputs_manager.get_inputs().await)),
+        Err(err) => Err((StatusCode::BAD_REQUEST, err.to_string())),
+    }
+}
+
+async fn reload_inputs(state: State<AppState>) -> impl IntoResponse {
+    let inputs_manager = match &state.inputs_manager {
+        Some(inputs_manager) => inputs_manager,
+        None => return Err((StatusCode::NOT_FOUND, "Inputs are not managed".to_string())),
+    };
+    match inputs_manager.reload().await {
+        Ok(()) => Ok(Json(inputs_manager.get_inputs().await)),
+        Err(err) => Err((StatusCode::BAD_REQUEST, err.to_string())),
+    }
+}
+
+async fn get_graph_paused(
+    Path(graph_id): Path<String>,
+    state: State<AppState>,
+) -> impl IntoResponse {
+    match state
+        .context
+        .is_graph_paused(&GraphId::new_from(graph_id))
+        .await
+    {
+        Ok(paused) => Ok(Json(GraphPaused { paused })),
+        Err(GraphError::GraphDoesNotExist(graph_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+    }
+}
+
+async fn put_graph_paused(
+    Path(graph_id): Path<String>,
+    state: State<AppState>,
+    Json(body): Json<GraphPaused>,
+) -> impl IntoResponse {
+    match state
+        .context
+        .set_graph_paused(&GraphId::new_from(graph_id), body.paused)
+        .await
+    {
+        Ok(()) => Ok(Json(body)),
+        Err(GraphError::GraphDoesNotExist(graph_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+    }
+}
+
+async fn pause_graph(Path(graph_id): Path<String>, state: State<AppState>) -> impl IntoResponse {
+    set_graph_running(graph_id, state, false).await
+}
+
+async fn resume_graph(Path(graph_id): Path<String>, state: State<AppState>) -> impl IntoResponse {
+    set_graph_running(graph_id, state, true).await
+}
+
+async fn set_graph_running(
+    graph_id: String,
+    state: State<AppState>,
+    running: bool,
+) -> Result<Json<GraphPaused>, (StatusCode, String)> {
+    match state
+        .context
+        .set_graph_running(&GraphId::new_from(graph_id), running)
+        .await
+    {
+        Ok(()) => Ok(Json(GraphPaused { paused: !running })),
+        Err(GraphError::GraphDoesNotExist(graph_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+    }
+}
+
+async fn get_graph_panic(
+    Path(graph_id): Path<String>,
+    state: State<AppState>,
+) -> impl IntoResponse {
+    match state
+        .context
+        .graph_panic(&GraphId::new_from(graph_id))
+        .await
+    {
+        Ok(slate) => Ok(Json(GraphPanic { slate })),
+        Err(GraphError::GraphDoesNotExist(graph_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+    }
+}
+
+async fn put_graph_panic(
+    Path(graph_id): Path<String>,
+    state: State<AppState>,
+    Json(slate): Json<Slate>,
+) -> impl IntoResponse {
+    match state
+        .context
+        .panic_graph(&GraphId::new_from(graph_id), slate)
+        .await
+    {
+        Ok(()) => Ok(Json(GraphPanic { slate: Some(slate) })),
+        Err(GraphError::GraphDoesNotExist(graph_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+    }
+}
+
+async fn delete_graph_panic(
+    Path(graph_id): Path<String>,
+    state: State<AppState>,
+) -> impl IntoResponse {
+    match state
+        .context
+        .clear_graph_panic(&GraphId::new_from(graph_id))
+        .await
+    {
+        Ok(()) => Ok(Json(GraphPanic { slate: None })),
+        Err(GraphError::GraphDoesNotExist(graph_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+    }
+}
+
+async fn get_graph_alarms(
+    Path(graph_id): Path<String>,
+    state: State<AppState>,
+) -> impl IntoResponse {
+    match state
+        .context
+        .graph_alarms(&GraphId::new_from(graph_id))
+        .await
+    {
+        Ok(alarms) => Ok(Json(alarms)),
+        Err(GraphError::GraphDoesNotExist(graph_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+    }
+}
+
+async fn get_graph_mode(Path(graph_id): Path<String>, state: State<AppState>) -> impl IntoResponse {
+    match state
+        .context
+        .graph_pacing(&GraphId::new_from(graph_id))
+        .await
+    {
+        Ok(pacing) => Ok(Json(pacing)),
+        Err(GraphError::GraphDoesNotExist(graph_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+    }
+}
+
+async fn put_graph_mode(
+    Path(graph_id): Path<String>,
+    state: State<AppState>,
+    Json(body): Json<GraphModeRequest>,
+) -> impl IntoResponse {
+    let graph_id = GraphId::new_from(graph_id);
+    let pacing = match state.context.set_graph_mode(&graph_id, body.mode).await {
+        Ok(()) => state.context.graph_pacing(&graph_id).await,
+        Err(err) => Err(err),
+    };
+    match pacing {
+        Ok(pacing) => Ok(Json(pacing)),
+        Err(GraphError::GraphDoesNotExist(graph_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+    }
+}
+
+async fn get_graph_dot(Path(graph_id): Path<String>, state: State<AppState>) -> impl IntoResponse {
+    match state
+        .context
+        .graph_topology(&GraphId::new_from(graph_id))
+        .await
+    {
+        Ok(topology) => Ok((
+            [(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("text/vnd.graphviz"),
+            )],
+            topology.to_dot(),
+        )),
+        Err(GraphError::GraphDoesNotExist(graph_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+    }
+}
+
+async fn export_graph(Path(graph_id): Path<String>, state: State<AppState>) -> impl IntoResponse {
+    match state
+        .context
+        .export_graph(&GraphId::new_from(graph_id))
+        .await
+    {
+        Ok(saved) => Ok(Json(saved)),
+        Err(GraphError::GraphDoesNotExist(graph_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+    }
+}
+
+async fn import_graph(state: State<AppState>, Json(body): Json<SavedGraph>) -> impl IntoResponse {
+    match state
+        .context
+        .import_graph(&state.plugin_manager, body)
+        .await
+    {
+        Ok(_) => Ok(StatusCode::OK),
+        Err(ImportGraphError::GraphAlreadyExists(graph_id)) => Err((
+            StatusCode::CONFLICT,
+            format!("Graph {graph_id} already exists"),
+        )),
+        Err(ImportGraphError::UnknownNodeType(node_type)) => Err((
+            StatusCode::BAD_REQUEST,
+            format!("No plugin provides node type {node_type}"),
+        )),
+        Err(ImportGraphError::InvalidFrameFormat(_)) => Err((
+            StatusCode::BAD_REQUEST,
+            "Frame rate and sample rate must not be zero, and the audio block size must divide the samples of a frame".to_string(),
+        )),
+        Err(ImportGraphError::CreateFailed(err)) => Err((StatusCode::BAD_REQUEST, err.to_string())),
+    }
+}
+
+/// Takes a map of node Ids to the state to set on that node.
+async fn set_node_states(
+    Path(graph_id): Path<String>,
+    state: State<AppState>,
+    Json(body): Json<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let states = body
+        .into_iter()
+        .map(|(node_id, node_state)| (NodeId::new_from(node_id), node_state))
+        .collect();
+    match state
+        .context
+        .set_node_states(&GraphId::new_from(graph_id), states)
+        .await
+    {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(NodeStateError::GraphDoesNotExist(graph_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+        Err(NodeStateError::NodeDoesNotExist(node_id)) => Err((
+            StatusCode::BAD_REQUEST,
+            format!("Node {node_id} does not exist in the graph"),
+        )),
+    }
+}
+
+async fn reorder_inputs(
+    Path((graph_id, node_id)): Path<(String, String)>,
+    state: State<AppState>,
+    Json(body): Json<ReorderInputsRequest>,
+) -> impl IntoResponse {
+    let video_order = body.video_inputs.map(|inputs| {
+        inputs
+            .into_iter()
+            .map(|input| VideoInputId::new_from(input.into()))
+            .collect()
+    });
+    let audio_order = body.audio_inputs.map(|inputs| {
+        inputs
+            .into_iter()
+            .map(|input| AudioInputId::new_from(input.into()))
+            .collect()
+    });
+    let result = state
+        .context
+        .reorder_node_inputs(
+            &GraphId::new_from(graph_id),
+            &NodeId::new_from(node_id),
+            video_order,
+            audio_order,
+        )
+        .await;
+    match result {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(InputError::NodeDoesNotExist(node_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Node {node_id} does not exist"),
+        )),
+        Err(InputError::InputDoesNotExist(input_id)) => Err((
+            StatusCode::BAD_REQUEST,
+            format!("Input {input_id} does not exist"),
+        )),
+        Err(InputError::InvalidOrder) => Err((
+            StatusCode::BAD_REQUEST,
+            "Order must contain each of the node's inputs exactly once".to_string(),
+        )),
+    }
+}
+
+async fn put_input_monitoring(
+    Path((graph_id, node_id, input_id)): Path<(String, String, String)>,
+    state: State<AppState>,
+    Json(body): Json<InputMonitoringRequest>,
+) -> impl IntoResponse {
+    let result = state
+        .context
+        .set_node_input_monitoring(
+            &GraphId::new_from(graph_id),
+            &NodeId::new_from(node_id),
+            &input_id,
+            body.muted,
+            body.soloed,
+        )
+        .await;
+    match result {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(InputError::NodeDoesNotExist(node_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Node {node_id} does not exist"),
+        )),
+        Err(InputError::InputDoesNotExist(input_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Input {input_id} does not exist"),
+        )),
+        Err(InputError::InvalidOrder) => unreachable!("Input monitoring does not reorder inputs"),
+    }
+}
+
+async fn put_graph_node(
+    Path((graph_id, node_id)): Path<(String, String)>,
+    state: State<AppState>,
+    Json(body): Json<UpdateNodeRequest>,
+) -> impl IntoResponse {
+    match state
+        .context
+        .set_node_name(
+            &GraphId::new_from(graph_id),
+            &NodeId::new_from(node_id),
+            body.name,
+        )
+        .await
+    {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(NodeStateError::GraphDoesNotExist(graph_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+        Err(NodeStateError::NodeDoesNotExist(node_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Node {node_id} does not exist"),
+        )),
+    }
+}
+
+async fn delete_graph_node(
+    Path((graph_id, node_id)): Path<(String, String)>,
+    state: State<AppState>,
+) -> impl IntoResponse {
+    match state
+        .context
+        .remove_node(
+            &state.plugin_manager,
+            &GraphId::new_from(graph_id),
+            &NodeId::new_from(node_id),
+        )
+        .await
+    {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(NodeStateError::GraphDoesNotExist(graph_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+        Err(NodeStateError::NodeDoesNotExist(node_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Node {node_id} does not exist"),
+        )),
+    }
+}
+
+async fn get_node_metrics(
+    Path((graph_id, node_id)): Path<(String, String)>,
+    state: State<AppState>,
+) -> impl IntoResponse {
+    match state
+        .context
+        .get_node_metrics(&GraphId::new_from(graph_id), &NodeId::new_from(node_id))
+        .await
+    {
+        Ok(metrics) => Ok(Json(metrics)),
+        Err(NodeStateError::GraphDoesNotExist(graph_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+        Err(NodeStateError::NodeDoesNotExist(node_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Node {node_id} does not exist"),
+        )),
+    }
+}
+
+async fn disconnect_graph_node_input(
+    Path((graph_id, node_id, input_id)): Path<(String, String, String)>,
+    state: State<AppState>,
+) -> impl IntoResponse {
+    let result = state
+        .context
+        .remove_connection(
+            &GraphId::new_from(graph_id),
+            &NodeId::new_from(node_id),
+            &input_id,
+        )
+        .await;
+    match result {
+        Ok(connected_output_id) => Ok(Json(DisconnectInputResponse {
+            connected_output_id,
+        })),
+        Err(InputError::NodeDoesNotExist(node_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Node {node_id} does not exist"),
+        )),
+        Err(InputError::InputDoesNotExist(input_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Input {input_id} does not exist"),
+        )),
+        Err(InputError::InvalidOrder) => unreachable!("Disconnecting does not reorder inputs"),
+    }
+}
+
+async fn create_automation(
+    Path((graph_id, node_id)): Path<(String, String)>,
+    state: State<AppState>,
+    Json(body): Json<CreateAutomation>,
+) -> impl IntoResponse {
+    let result = state
+        .context
+        .add_node_automation(
+            &GraphId::new_from(graph_id),
+            &NodeId::new_from(node_id),
+            body,
+        )
+        .await;
+    match result {
+        Ok(automation_id) => Ok(Json(CreateAutomationResponse {
+            automation_id: automation_id.to_string(),
+        })),
+        Err(err) => Err(automation_error_response(err)),
+    }
+}
+
+fn automation_error_response(err: AutomationError) -> (StatusCode, String) {
+    match err {
+        AutomationError::GraphDoesNotExist(graph_id) => (
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        ),
+        AutomationError::NodeDoesNotExist(node_id) => (
+            StatusCode::NOT_FOUND,
+            format!("Node {node_id} does not exist"),
+        ),
+        AutomationError::AutomationDoesNotExist(automation_id) => (
+            StatusCode::NOT_FOUND,
+            format!("Automation {automation_id} does not exist"),
+        ),
+        AutomationError::ParameterIsNotNumeric(param) => (
+            StatusCode::BAD_REQUEST,
+            format!("Parameter {param} is not a numeric value in the node state"),
+        ),
+    }
+}
+
+async fn cancel_automations(
+    Path((graph_id, node_id)): Path<(String, String)>,
+    state: State<AppState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state
+        .context
+        .cancel_node_automations(
+            &GraphId::new_from(graph_id),
+            &NodeId::new_from(node_id),
+            None,
+        )
+        .await
+        .map_err(automation_error_response)?;
+    Ok(StatusCode::OK)
+}
+
+async fn cancel_automation(
+    Path((graph_id, node_id, automation_id)): Path<(String, String, String)>,
+    state: State<AppState>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    state
+        .context
+        .cancel_node_automations(
+            &GraphId::new_from(graph_id),
+            &NodeId::new_from(node_id),
+            Some(&AutomationId::new_from(automation_id)),
+        )
+        .await
+        .map_err(automation_error_response)?;
+    Ok(StatusCode::OK)
+}
+
 async fn state_ws(
     ws: WebSocketUpgrade,
     Path(id): Path<Uuid>,
@@ -230,3 +1121,107 @@ async fn state_ws(
         None => Err("Client not found"),
     }
 }
+
+/// Resolution of the most recent frame on a video output.
+async fn get_output_format(
+    Path((graph_id, node_id, output_id)): Path<(String, String, String)>,
+    state: State<AppState>,
+) -> impl IntoResponse {
+    match state
+        .context
+        .get_video_output_format(
+            &GraphId::new_from(graph_id),
+            &NodeId::new_from(node_id),
+            &VideoOutputId::new_from(output_id.into()),
+        )
+        .await
+    {
+        Ok(Some(format)) => Ok(Json(format)),
+        Ok(None) => Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Output has not produced a frame yet".to_string(),
+        )),
+        Err(OutputError::GraphDoesNotExist(graph_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+        Err(OutputError::NodeDoesNotExist(node_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Node {node_id} does not exist in the graph"),
+        )),
+        Err(OutputError::OutputDoesNotExist(output_id)) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Video output {output_id} does not exist"),
+        )),
+    }
+}
+
+async fn get_output_snapshot(
+    Path((graph_id, node_id, output_id)): Path<(String, String, String)>,
+    Query(query): Query<SnapshotQuery>,
+    state: State<AppState>,
+) -> impl IntoResponse {
+    match state
+        .context
+        .snapshot_video_output(
+            &GraphId::new_from(graph_id),
+            &NodeId::new_from(node_id),
+            &VideoOutputId::new_from(output_id.into()),
+            query.format,
+        )
+        .await
+    {
+        Ok(image) => Ok(([(header::CONTENT_TYPE, query.format.content_type())], image)),
+        Err(SnapshotError::Output(OutputError::GraphDoesNotExist(graph_id))) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Graph {graph_id} does not exist"),
+        )),
+        Err(SnapshotError::Output(OutputError::NodeDoesNotExist(node_id))) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Node {node_id} does not exist in the graph"),
+        )),
+        Err(SnapshotError::Output(OutputError::OutputDoesNotExist(output_id))) => Err((
+            StatusCode::NOT_FOUND,
+            format!("Video output {output_id} does not exist"),
+        )),
+        Err(SnapshotError::NoFrame) => Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Output did not produce a frame".to_string(),
+        )),
+        Err(SnapshotError::DownloadFailed(err)) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to download frame: {err}"),
+        )),
+    }
+}
+
+async fn node_events_ws(
+    ws: WebSocketUpgrade,
+    Path((graph_id, node_id)): Path<(String, String)>,
+    state: State<AppState>,
+) -> impl IntoResponse {
+    let node_id = NodeId::new_from(node_id);
+    match state
+        .context
+        .check_node_in_graph(&GraphId::new_from(graph_id), &node_id)
+        .await
+    {
+        Ok(()) => {}
+        Err(NodeStateError::GraphDoesNotExist(graph_id)) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("Graph {graph_id} does not exist"),
+            ))
+        }
+        Err(NodeStateError::NodeDoesNotExist(node_id)) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("Node {node_id} does not exist in the graph"),
+            ))
+        }
+    }
+
+    // Subscribing sends the current state, so the client receives the node's state straight away
+    let state_rx = state.context.subscribe().await;
+    Ok(ws.on_upgrade(move |socket| ws::node_events_connection(socket, node_id, state_rx)))
+}
@@ -16,9 +16,16 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::collections::{BTreeMap, HashMap};
+
 use serde::{Deserialize, Serialize};
 
-use crate::state::PhaneronStateRepresentation;
+use crate::{
+    graph::{FrameFormat, GraphMode, GraphSafety, Slate},
+    metrics::NodeMetricsReport,
+    snapshot::SnapshotFormat,
+    state::{PhaneronNodeRepresentation, PhaneronStateRepresentation},
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RegisterRequest {
@@ -31,14 +38,99 @@ pub struct RegisterResponse {
     pub url: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateAutomationResponse {
+    pub automation_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateGraphFromTemplateRequest {
+    pub template_name: String,
+    pub graph_id: Option<String>,
+    #[serde(default)]
+    pub parameters: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    pub safety: GraphSafety,
+    #[serde(default)]
+    pub frame_format: FrameFormat,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateGraphFromTemplateResponse {
+    pub graph_id: String,
+    pub node_ids: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GraphPaused {
+    pub paused: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ComputeProfiling {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GraphPanic {
+    /// `None` if the graph is not in panic.
+    pub slate: Option<Slate>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GraphModeRequest {
+    pub mode: GraphMode,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SnapshotQuery {
+    #[serde(default)]
+    pub format: SnapshotFormat,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DisconnectInputResponse {
+    /// The output the input was connected to, `None` if it was not connected.
+    pub connected_output_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateNodeRequest {
+    /// `None` clears the node's name.
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InputMonitoringRequest {
+    #[serde(default)]
+    pub muted: Option<bool>,
+    #[serde(default)]
+    pub soloed: Option<bool>,
+}
+
+/// The desired order of a node's input Ids, inputs that are omitted are left as they are.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReorderInputsRequest {
+    #[serde(default)]
+    pub video_inputs: Option<Vec<String>>,
+    #[serde(default)]
+    pub audio_inputs: Option<Vec<String>>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 #[serde(tag = "event")]
 pub enum ClientEvent {
-    Topics(TopicsRequest),
+    /// Replaces the client's topics, `topics` was the original name of this event.
+    #[serde(alias = "topics")]
+    Subscribe(TopicsRequest),
     NodeState(NodeStateRequest),
 }
 
+/// Graph Ids and node Ids that a client wants updates for. A graph Id covers each of its nodes.
+/// Clients without topics receive the whole state on every change.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TopicsRequest {
     pub topics: Vec<String>,
@@ -55,7 +147,26 @@ pub struct FlipperState {
     pub flipped: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum ServerEvent {
+    /// The whole state, sent on connect and to clients without topics on every change.
     PhaneronState(PhaneronStateRepresentation),
+    /// Sent to clients subscribed to a node or its graph when the node changes,
+    /// `state` is `None` once the node has been removed.
+    NodeStateChanged {
+        node_id: String,
+        state: Option<PhaneronNodeRepresentation>,
+    },
+    /// Processing metrics by node Id, sent periodically. Clients with topics only receive the
+    /// metrics of the nodes they are subscribed to.
+    NodeMetrics(BTreeMap<String, NodeMetricsReport>),
+}
+
+/// Events sent on the event socket of a single node.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[serde(tag = "event")]
+pub enum NodeServerEvent {
+    /// Sent when the socket is opened and whenever the node's state, name, or input monitoring changes.
+    StateChanged(PhaneronNodeRepresentation),
 }
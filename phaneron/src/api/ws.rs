@@ -16,23 +16,35 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{future, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    future,
+    sync::Arc,
+};
 
-use axum::extract::ws::{Message, WebSocket};
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
 use futures::StreamExt;
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast::error::RecvError, Mutex};
 use tokio_stream::wrappers::BroadcastStream;
 use tracing::{debug, error, log::info};
 use uuid::Uuid;
 
 use crate::{
-    api::message::ServerEvent,
-    state::{PhaneronState, PhaneronStateRepresentation},
+    api::message::{NodeServerEvent, ServerEvent},
+    metrics::NodeMetricsReport,
+    state::{PhaneronNodeRepresentation, PhaneronState, PhaneronStateRepresentation},
     GraphId, NodeId,
 };
 
 use super::{Client, Clients};
 
+#[cfg(test)]
+mod tests;
+
+/// Messages queued for a client before the oldest are dropped. Clients with topics are sent an
+/// event per changed node, so this leaves room for several nodes changing at once.
+const CLIENT_QUEUE_SIZE: usize = 100;
+
 pub async fn client_connection(
     state_context: PhaneronState,
     ws: WebSocket,
@@ -42,7 +54,7 @@ pub async fn client_connection(
     mut client: Client,
 ) {
     let (client_ws_sender, mut client_ws_rcv) = ws.split();
-    let (client_sender, client_rcv) = tokio::sync::broadcast::channel::<Message>(10);
+    let (client_sender, client_rcv) = tokio::sync::broadcast::channel::<Message>(CLIENT_QUEUE_SIZE);
     let client_rcv = BroadcastStream::new(client_rcv);
 
     tokio::task::spawn(
@@ -69,14 +81,20 @@ pub async fn client_connection(
                 break;
             }
         };
-        client_msg(state_context.clone(), &id, msg, &clients).await;
+        client_msg(state_context.clone(), &id, msg, &state, &clients).await;
     }
 
     clients.lock().await.remove(&id);
     info!("{} disconnected", id);
 }
 
-async fn client_msg(state_context: PhaneronState, id: &Uuid, msg: Message, clients: &Clients) {
+async fn client_msg(
+    state_context: PhaneronState,
+    id: &Uuid,
+    msg: Message,
+    state: &Arc<Mutex<PhaneronStateRepresentation>>,
+    clients: &Clients,
+) {
     debug!("received message from {}: {:?}", id, msg);
     let message = match msg.into_text() {
         Ok(v) => v,
@@ -99,18 +117,24 @@ async fn client_msg(state_context: PhaneronState, id: &Uuid, msg: Message, clien
     };
 
     match topics_req {
-        super::message::ClientEvent::Topics(topics_req) => {
+        super::message::ClientEvent::Subscribe(topics_req) => {
             debug!("Topics req: {:?}", topics_req);
+            // The state is locked before the clients, in the same order as the state broadcast
+            let phaneron_state = state.lock().await.clone();
             let mut locked = clients.lock().await;
             if let Some(v) = locked.get_mut(id) {
-                v.topics = topics_req.topics.clone();
+                v.topics = topics_req.topics;
+                // Brings the client up to date with its topics, later changes are sent as they happen
+                let events = if v.topics.is_empty() {
+                    vec![ServerEvent::PhaneronState(phaneron_state)]
+                } else {
+                    node_changes(&Default::default(), &phaneron_state, &v.topics)
+                };
                 if let Some(sender) = &v.sender {
-                    sender
-                        .send(Message::Text(format!(
-                            "You are now subscribed to {}",
-                            topics_req.topics.join(",")
-                        )))
-                        .unwrap();
+                    for event in events {
+                        let event_json = serde_json::to_string(&event).unwrap();
+                        sender.send(Message::Text(event_json)).ok();
+                    }
                 }
             };
         }
@@ -126,3 +150,112 @@ async fn client_msg(state_context: PhaneronState, id: &Uuid, msg: Message, clien
         }
     }
 }
+
+/// Whether `node_id` is one of `topics` or is in a graph that is.
+fn in_topics(topics: &[String], graphs: &HashMap<String, Vec<String>>, node_id: &str) -> bool {
+    topics.iter().any(|topic| {
+        topic == node_id
+            || graphs
+                .get(topic)
+                .is_some_and(|nodes| nodes.iter().any(|node| node == node_id))
+    })
+}
+
+/// Events for the nodes in `topics` that were added, changed or removed between `previous` and
+/// `current`, in order of node Id.
+pub fn node_changes(
+    previous: &PhaneronStateRepresentation,
+    current: &PhaneronStateRepresentation,
+    topics: &[String],
+) -> Vec<ServerEvent> {
+    let mut changes: Vec<(&String, Option<&PhaneronNodeRepresentation>)> = vec![];
+    for (node_id, node) in current.nodes.iter() {
+        if previous.nodes.get(node_id) != Some(node) && in_topics(topics, &current.graphs, node_id)
+        {
+            changes.push((node_id, Some(node)));
+        }
+    }
+    for node_id in previous.nodes.keys() {
+        if !current.nodes.contains_key(node_id) && in_topics(topics, &previous.graphs, node_id) {
+            changes.push((node_id, None));
+        }
+    }
+    changes.sort_by_key(|(node_id, _)| *node_id);
+
+    changes
+        .into_iter()
+        .map(|(node_id, node)| ServerEvent::NodeStateChanged {
+            node_id: node_id.clone(),
+            state: node.cloned(),
+        })
+        .collect()
+}
+
+/// Metrics of the nodes in `topics`, or of every node if there are no topics.
+/// `None` if none of the nodes are in the topics.
+pub fn node_metrics(
+    metrics: &BTreeMap<String, NodeMetricsReport>,
+    state: &PhaneronStateRepresentation,
+    topics: &[String],
+) -> Option<ServerEvent> {
+    let metrics: BTreeMap<String, NodeMetricsReport> = metrics
+        .iter()
+        .filter(|(node_id, _)| topics.is_empty() || in_topics(topics, &state.graphs, node_id))
+        .map(|(node_id, metrics)| (node_id.clone(), metrics.clone()))
+        .collect();
+    if metrics.is_empty() {
+        return None;
+    }
+
+    Some(ServerEvent::NodeMetrics(metrics))
+}
+
+/// Sends the events of a single node until the client disconnects or the node is removed,
+/// in which case the socket is closed with a reason.
+pub async fn node_events_connection(
+    mut ws: WebSocket,
+    node_id: NodeId,
+    mut state_rx: tokio::sync::broadcast::Receiver<PhaneronStateRepresentation>,
+) {
+    info!("Node {} events connected", node_id);
+    let mut last_node: Option<PhaneronNodeRepresentation> = None;
+
+    loop {
+        tokio::select! {
+            phaneron_state = state_rx.recv() => {
+                let phaneron_state = match phaneron_state {
+                    Ok(phaneron_state) => phaneron_state,
+                    Err(RecvError::Lagged(_)) => continue, // Only the latest state is relevant
+                    Err(RecvError::Closed) => break,
+                };
+                let node = match phaneron_state.nodes.get(&node_id.to_string()) {
+                    Some(node) => node,
+                    None => {
+                        let close = Message::Close(Some(CloseFrame {
+                            code: close_code::NORMAL,
+                            reason: "Node was removed".into(),
+                        }));
+                        ws.send(close).await.ok();
+                        break;
+                    }
+                };
+                if last_node.as_ref() == Some(node) {
+                    continue;
+                }
+
+                let event_json =
+                    serde_json::to_string(&NodeServerEvent::StateChanged(node.clone())).unwrap();
+                if ws.send(Message::Text(event_json)).await.is_err() {
+                    break;
+                }
+                last_node = Some(node.clone());
+            }
+            msg = ws.recv() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {} // Nothing is expected from the client
+            }
+        }
+    }
+
+    info!("Node {} events disconnected", node_id);
+}
@@ -0,0 +1,151 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    api::message::ServerEvent,
+    metrics::NodeMetricsReport,
+    state::{PhaneronNodeRepresentation, PhaneronStateRepresentation},
+};
+
+use super::{node_changes, node_metrics};
+
+/// A state of two graphs, `graph1` with nodes `a` and `b`, and `graph2` with node `c`.
+fn state(node_states: [&str; 3]) -> PhaneronStateRepresentation {
+    let nodes: HashMap<String, PhaneronNodeRepresentation> = ["a", "b", "c"]
+        .into_iter()
+        .zip(node_states)
+        .map(|(node_id, state)| {
+            let node = serde_json::from_value(serde_json::json!({ "name": null, "state": state }));
+            (node_id.to_string(), node.unwrap())
+        })
+        .collect();
+    let graphs = HashMap::from([
+        ("graph1".to_string(), vec!["a".to_string(), "b".to_string()]),
+        ("graph2".to_string(), vec!["c".to_string()]),
+    ]);
+
+    PhaneronStateRepresentation {
+        nodes,
+        graphs,
+        ..Default::default()
+    }
+}
+
+fn changed_node_ids(events: Vec<ServerEvent>) -> Vec<String> {
+    events
+        .into_iter()
+        .map(|event| match event {
+            ServerEvent::NodeStateChanged { node_id, .. } => node_id,
+            event => panic!("Expected only node events, got {event:?}"),
+        })
+        .collect()
+}
+
+#[test]
+fn only_changed_nodes_in_topics_are_sent() {
+    let previous = state(["1", "1", "1"]);
+    let current = state(["2", "1", "2"]);
+
+    let events = node_changes(&previous, &current, &["a".to_string()]);
+
+    assert_eq!(changed_node_ids(events), vec!["a"]);
+}
+
+#[test]
+fn graph_topic_covers_its_nodes() {
+    let previous = state(["1", "1", "1"]);
+    let current = state(["2", "2", "2"]);
+
+    let events = node_changes(&previous, &current, &["graph1".to_string()]);
+
+    assert_eq!(changed_node_ids(events), vec!["a", "b"]);
+}
+
+#[test]
+fn removed_node_is_sent_without_state() {
+    let previous = state(["1", "1", "1"]);
+    let mut current = previous.clone();
+    current.nodes.remove("c");
+    current.graphs.remove("graph2");
+
+    let events = node_changes(&previous, &current, &["graph2".to_string()]);
+
+    assert_eq!(
+        events,
+        vec![ServerEvent::NodeStateChanged {
+            node_id: "c".to_string(),
+            state: None
+        }]
+    );
+}
+
+#[test]
+fn renamed_node_is_sent_with_its_new_name() {
+    let previous = state(["1", "1", "1"]);
+    let mut current = previous.clone();
+    let renamed: PhaneronNodeRepresentation =
+        serde_json::from_value(serde_json::json!({ "name": "Program", "state": "1" })).unwrap();
+    current.nodes.insert("a".to_string(), renamed.clone());
+
+    let events = node_changes(&previous, &current, &["graph1".to_string()]);
+
+    assert_eq!(
+        events,
+        vec![ServerEvent::NodeStateChanged {
+            node_id: "a".to_string(),
+            state: Some(renamed)
+        }]
+    );
+    let event_json = serde_json::to_value(&events[0]).unwrap();
+    assert_eq!(event_json["NodeStateChanged"]["state"]["name"], "Program");
+}
+
+#[test]
+fn unchanged_state_sends_nothing() {
+    let current = state(["1", "1", "1"]);
+
+    assert!(node_changes(&current, &current, &["graph1".to_string()]).is_empty());
+}
+
+#[test]
+fn metrics_are_limited_to_topics() {
+    let state = state(["1", "1", "1"]);
+    let metrics: BTreeMap<String, NodeMetricsReport> = ["a", "b", "c"]
+        .into_iter()
+        .map(|node_id| (node_id.to_string(), NodeMetricsReport::default()))
+        .collect();
+
+    let node_ids = |event: Option<ServerEvent>| match event {
+        Some(ServerEvent::NodeMetrics(metrics)) => metrics.into_keys().collect::<Vec<_>>(),
+        event => panic!("Expected metrics, got {event:?}"),
+    };
+    assert_eq!(
+        node_ids(node_metrics(&metrics, &state, &[])),
+        ["a", "b", "c"]
+    );
+    assert_eq!(
+        node_ids(node_metrics(&metrics, &state, &["graph2".to_string()])),
+        ["c"]
+    );
+    assert_eq!(
+        node_metrics(&metrics, &state, &["unknown".to_string()]),
+        None
+    );
+}
@@ -0,0 +1,149 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{fmt::Display, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{graph::GraphClock, state::NodeStateError, GraphId, NodeId};
+
+#[cfg(test)]
+mod tests;
+
+/// Easing applied to the progress of an automation.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutomationCurve {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl AutomationCurve {
+    /// Maps linear progress through an automation (0-1) onto the curve.
+    pub fn apply(&self, progress: f64) -> f64 {
+        let t = progress.clamp(0.0, 1.0);
+        match self {
+            AutomationCurve::Linear => t,
+            AutomationCurve::EaseIn => t * t,
+            AutomationCurve::EaseOut => t * (2.0 - t),
+            AutomationCurve::EaseInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    -1.0 + (4.0 - 2.0 * t) * t
+                }
+            }
+        }
+    }
+}
+
+/// Request to ramp a numeric parameter of a node's state to a new value.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateAutomation {
+    pub param: String,
+    pub to: f64,
+    pub duration_ms: u64,
+    #[serde(default)]
+    pub curve: AutomationCurve,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AutomationId(String);
+impl AutomationId {
+    pub fn new_from(id: String) -> Self {
+        Self(id)
+    }
+}
+impl Default for AutomationId {
+    fn default() -> Self {
+        Self(phaneron_plugin::ids::new_id())
+    }
+}
+impl Display for AutomationId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A running ramp of a single parameter from one value to another, stepped once per frame of
+/// the node's graph.
+#[derive(Debug, Clone)]
+pub struct Automation {
+    pub id: AutomationId,
+    pub param: String,
+    from: f64,
+    to: f64,
+    start_frame: u64,
+    frames: u64,
+    curve: AutomationCurve,
+}
+
+impl Automation {
+    pub fn new(id: AutomationId, from: f64, request: CreateAutomation, clock: &GraphClock) -> Self {
+        let frame_duration = clock.frame_format().frame_duration();
+        let frames = (Duration::from_millis(request.duration_ms).as_secs_f64()
+            / frame_duration.as_secs_f64())
+        .round() as u64;
+        Self {
+            id,
+            param: request.param,
+            from,
+            to: request.to,
+            start_frame: clock.frame(),
+            frames,
+            curve: request.curve,
+        }
+    }
+
+    pub fn value_at(&self, frame: u64) -> f64 {
+        let elapsed = frame.saturating_sub(self.start_frame);
+        let progress = if self.frames == 0 {
+            1.0
+        } else {
+            elapsed as f64 / self.frames as f64
+        };
+
+        self.from + (self.to - self.from) * self.curve.apply(progress)
+    }
+
+    pub fn is_finished_at(&self, frame: u64) -> bool {
+        frame.saturating_sub(self.start_frame) >= self.frames
+    }
+}
+
+#[derive(Debug)]
+pub enum AutomationError {
+    GraphDoesNotExist(GraphId),
+    NodeDoesNotExist(NodeId),
+    AutomationDoesNotExist(AutomationId),
+    ParameterIsNotNumeric(String),
+}
+
+impl From<NodeStateError> for AutomationError {
+    fn from(err: NodeStateError) -> Self {
+        match err {
+            NodeStateError::GraphDoesNotExist(graph_id) => {
+                AutomationError::GraphDoesNotExist(graph_id)
+            }
+            NodeStateError::NodeDoesNotExist(node_id) => AutomationError::NodeDoesNotExist(node_id),
+        }
+    }
+}
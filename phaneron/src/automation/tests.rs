@@ -0,0 +1,69 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::*;
+use crate::graph::FrameFormat;
+
+fn ramp(duration_ms: u64, curve: AutomationCurve, clock: &GraphClock) -> Automation {
+    Automation::new(
+        AutomationId::default(),
+        0.0,
+        CreateAutomation {
+            param: "opacity".to_string(),
+            to: 1.0,
+            duration_ms,
+            curve,
+        },
+        clock,
+    )
+}
+
+#[test]
+fn ramp_lasts_a_whole_number_of_frames() {
+    let clock = GraphClock::new(FrameFormat::default());
+    let automation = ramp(1000, AutomationCurve::Linear, &clock);
+    let start = automation.start_frame;
+
+    assert_eq!(automation.frames, 25);
+    assert_eq!(automation.value_at(start), 0.0);
+    assert_eq!(automation.value_at(start + 5), 0.2);
+    assert!(!automation.is_finished_at(start + 24));
+    assert!(automation.is_finished_at(start + 25));
+    assert_eq!(automation.value_at(start + 30), 1.0);
+}
+
+#[test]
+fn ramp_follows_the_graph_frame_rate() {
+    let clock = GraphClock::new(FrameFormat {
+        frame_rate_num: 50,
+        ..Default::default()
+    });
+    let automation = ramp(1000, AutomationCurve::EaseIn, &clock);
+
+    assert_eq!(automation.frames, 50);
+    assert_eq!(automation.value_at(automation.start_frame + 25), 0.25);
+}
+
+#[test]
+fn zero_duration_jumps_to_the_target() {
+    let clock = GraphClock::new(FrameFormat::default());
+    let automation = ramp(0, AutomationCurve::Linear, &clock);
+
+    assert_eq!(automation.value_at(automation.start_frame), 1.0);
+    assert!(automation.is_finished_at(automation.start_frame));
+}
@@ -20,6 +20,15 @@ use std::sync::{Arc, Mutex};
 
 use std::fmt::Debug;
 
+#[cfg(test)]
+mod tests;
+
+/// Frames that can be queued for each subscriber by default, which bounds how far a graph can be
+/// configured to let producers run ahead of their consumers.
+pub const MAX_FRAMES_AHEAD: usize = 16;
+
+/// Sends values to every subscriber. Each subscriber queues up to the channel's capacity, once a
+/// subscriber's queue is full [`Channel::send`] blocks until the subscriber has taken a value.
 pub struct Channel<T>
 where
     T: Clone,
@@ -31,19 +40,40 @@ impl<T> Channel<T>
 where
     T: Clone,
 {
+    /// `capacity` is clamped to at least 1.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(ChannelInner::new(capacity.max(1)))),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.lock().unwrap().capacity
+    }
+
     pub async fn subscribe(&self) -> tokio::sync::mpsc::Receiver<(T, ChannelSemaphore)> {
-        let (sender, receiver) = tokio::sync::mpsc::channel(1);
         let mut inner = self.inner.lock().unwrap();
+        let (sender, receiver) = tokio::sync::mpsc::channel(inner.capacity);
         inner.senders.push(sender);
         receiver
     }
 
-    pub fn send(&self, semaphore_provider: &ChannelSemaphoreProvider, value: T) {
-        let inner = self.inner.lock().unwrap();
-        for sender in &inner.senders {
+    /// Sends a value to all subscribers, returning the number of subscribers that received it.
+    /// Blocks while a subscriber's queue is full, so must not be called from an async context.
+    /// Subscribers that have gone away are removed.
+    pub fn send(&self, semaphore_provider: &ChannelSemaphoreProvider, value: T) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        inner.senders.retain(|sender| {
             let semaphore = semaphore_provider.get_semaphore();
-            sender.blocking_send((value.clone(), semaphore)).ok();
-        }
+            sender.blocking_send((value.clone(), semaphore)).is_ok()
+        });
+        inner.senders.len()
+    }
+
+    /// Drops every subscriber, their receivers return `None` once they have received
+    /// the values already sent to them.
+    pub fn close(&self) {
+        self.inner.lock().unwrap().senders.clear();
     }
 
     pub async fn no_receivers(&self) -> bool {
@@ -67,9 +97,7 @@ where
     T: Clone,
 {
     fn default() -> Self {
-        Self {
-            inner: Arc::new(Mutex::new(ChannelInner::new())),
-        }
+        Self::with_capacity(MAX_FRAMES_AHEAD)
     }
 }
 
@@ -78,7 +106,9 @@ where
     T: Clone,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Channel").finish()
+        f.debug_struct("Channel")
+            .field("capacity", &self.capacity())
+            .finish()
     }
 }
 
@@ -87,14 +117,18 @@ where
     T: Clone,
 {
     senders: Vec<tokio::sync::mpsc::Sender<(T, ChannelSemaphore)>>,
+    capacity: usize,
 }
 
 impl<T> ChannelInner<T>
 where
     T: Clone,
 {
-    fn new() -> Self {
-        ChannelInner { senders: vec![] }
+    fn new(capacity: usize) -> Self {
+        ChannelInner {
+            senders: vec![],
+            capacity,
+        }
     }
 }
 
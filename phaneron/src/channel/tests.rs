@@ -0,0 +1,76 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use super::{Channel, ChannelSemaphoreProvider, MAX_FRAMES_AHEAD};
+
+#[test]
+fn capacity_is_at_least_one() {
+    assert_eq!(Channel::<usize>::default().capacity(), MAX_FRAMES_AHEAD);
+    assert_eq!(Channel::<usize>::with_capacity(0).capacity(), 1);
+}
+
+#[tokio::test]
+async fn send_blocks_until_subscriber_is_ready() {
+    let channel = Channel::with_capacity(2);
+    let mut receiver = channel.subscribe().await;
+    let sent = Arc::new(AtomicUsize::new(0));
+    let producer = std::thread::spawn({
+        let channel = channel.clone();
+        let sent = sent.clone();
+        move || {
+            let semaphore_provider = ChannelSemaphoreProvider::default();
+            for value in 0..4 {
+                channel.send(&semaphore_provider, value);
+                sent.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(sent.load(Ordering::SeqCst), 2);
+
+    let (value, _) = receiver.recv().await.unwrap();
+    assert_eq!(value, 0);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert_eq!(sent.load(Ordering::SeqCst), 3);
+
+    for expected in 1..4 {
+        let (value, _) = receiver.recv().await.unwrap();
+        assert_eq!(value, expected);
+    }
+    producer.join().unwrap();
+}
+
+#[test]
+fn send_without_subscribers_does_not_block() {
+    let channel = Channel::with_capacity(1);
+    let semaphore_provider = ChannelSemaphoreProvider::default();
+
+    assert!(futures::executor::block_on(channel.no_receivers()));
+    for value in 0..4 {
+        assert_eq!(channel.send(&semaphore_provider, value), 0);
+    }
+}
@@ -19,49 +19,139 @@
 #![allow(non_snake_case)]
 
 use nalgebra::{Matrix3, Matrix3x1, Matrix3x4, Matrix4x3};
-use phaneron_plugin::{ColourSpec, COLOUR_SPEC_BT_709};
+use phaneron_plugin::{ColourSpec, TransferFunction, COLOUR_SPEC_BT_709};
+
+#[cfg(test)]
+mod tests;
 
 const LUT_ARRAY_ENTRIES: usize = 65536;
+
+/// Luminance of HDR reference (diffuse) white, which is held at 1.0 in linear light like SDR white.
+/// Reference: <https://www.itu.int/pub/R-REP-BT.2408>
+const HDR_REFERENCE_WHITE_NITS: f32 = 203.0;
+/// HLG signal level of reference white.
+const HLG_REFERENCE_WHITE_SIGNAL: f32 = 0.75;
+
+const PQ_PEAK_NITS: f32 = 10000.0;
+const PQ_M1: f32 = 2610.0 / 16384.0;
+const PQ_M2: f32 = 2523.0 / 4096.0 * 128.0;
+const PQ_C1: f32 = 3424.0 / 4096.0;
+const PQ_C2: f32 = 2413.0 / 4096.0 * 32.0;
+const PQ_C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+const HLG_A: f32 = 0.178_832_77;
+const HLG_B: f32 = 1.0 - 4.0 * HLG_A;
+const HLG_C: f32 = 0.559_910_7;
+
+/// Converts a gamma-encoded value in the range 0-1 into linear light using the
+/// transfer function of the given colour space.
+/// Frames are held on the GPU in linear light so that blending and compositing
+/// operations performed by nodes are colour-correct. Reference white is 1.0 for both SDR and HDR
+/// sources, so HDR values go up to [`linear_range`].
+pub fn gamma_to_linear(colour_spec: &ColourSpec, value: f32) -> f32 {
+    match colour_spec.transfer {
+        TransferFunction::Gamma => {
+            if value < colour_spec.beta {
+                value / colour_spec.delta
+            } else {
+                f32::powf(
+                    (value + (colour_spec.alpha - 1.0)) / colour_spec.alpha,
+                    1.0 / colour_spec.gamma,
+                )
+            }
+        }
+        TransferFunction::PQ => pq_to_nits(value) / HDR_REFERENCE_WHITE_NITS,
+        TransferFunction::HLG => hlg_to_scene_linear(value) / hlg_reference_white(),
+    }
+}
+
+/// Converts a linear light value in the range 0-[`linear_range`] into a gamma-encoded value
+/// using the transfer function of the given colour space.
+pub fn linear_to_gamma(colour_spec: &ColourSpec, value: f32) -> f32 {
+    match colour_spec.transfer {
+        TransferFunction::Gamma => {
+            if value < colour_spec.beta {
+                value * colour_spec.delta
+            } else {
+                colour_spec.alpha * f32::powf(value, colour_spec.gamma) - (colour_spec.alpha - 1.0)
+            }
+        }
+        TransferFunction::PQ => nits_to_pq(value * HDR_REFERENCE_WHITE_NITS),
+        TransferFunction::HLG => scene_linear_to_hlg(value * hlg_reference_white()),
+    }
+}
+
+/// Largest linear light value the colour space can encode, 1.0 for SDR.
+pub fn linear_range(colour_spec: &ColourSpec) -> f32 {
+    gamma_to_linear(colour_spec, 1.0)
+}
+
+/// PQ EOTF, SMPTE ST 2084.
+fn pq_to_nits(value: f32) -> f32 {
+    let p = value.clamp(0.0, 1.0).powf(1.0 / PQ_M2);
+    let y = ((p - PQ_C1).max(0.0) / (PQ_C2 - PQ_C3 * p)).powf(1.0 / PQ_M1);
+    y * PQ_PEAK_NITS
+}
+
+/// Inverse PQ EOTF, SMPTE ST 2084.
+fn nits_to_pq(nits: f32) -> f32 {
+    let y = (nits / PQ_PEAK_NITS).clamp(0.0, 1.0).powf(PQ_M1);
+    ((PQ_C1 + PQ_C2 * y) / (1.0 + PQ_C3 * y)).powf(PQ_M2)
+}
+
+/// Inverse HLG OETF, ARIB STD-B67. Returns scene light in the range 0-1.
+fn hlg_to_scene_linear(value: f32) -> f32 {
+    let value = value.max(0.0);
+    if value <= 0.5 {
+        value * value / 3.0
+    } else {
+        (((value - HLG_C) / HLG_A).exp() + HLG_B) / 12.0
+    }
+}
+
+/// HLG OETF, ARIB STD-B67.
+fn scene_linear_to_hlg(value: f32) -> f32 {
+    let value = value.max(0.0);
+    if value <= 1.0 / 12.0 {
+        (3.0 * value).sqrt()
+    } else {
+        HLG_A * (12.0 * value - HLG_B).ln() + HLG_C
+    }
+}
+
+fn hlg_reference_white() -> f32 {
+    hlg_to_scene_linear(HLG_REFERENCE_WHITE_SIGNAL)
+}
+
 pub fn gamma_to_linear_lut(colour_spec: &ColourSpec) -> Vec<f32> {
     let mut lut_array = vec![1.0; LUT_ARRAY_ENTRIES];
 
-    let alpha = colour_spec.alpha;
-    let delta = colour_spec.delta;
-    let beta = colour_spec.beta;
-    let gamma = colour_spec.gamma;
-
     for (i, entry) in lut_array.iter_mut().enumerate() {
         let fi = (i as f32) / ((LUT_ARRAY_ENTRIES - 1) as f32);
-        if fi < beta {
-            *entry = fi / delta;
-        } else {
-            *entry = f32::powf((fi + (alpha - 1.0)) / alpha, 1.0 / gamma);
-        }
+        *entry = gamma_to_linear(colour_spec, fi);
     }
 
     lut_array
 }
 
+/// Covers linear light from 0 to [`linear_range`], shaders index it with [`linear_to_gamma_lut_scale`].
 pub fn linear_to_gamma_lut(colour_spec: &ColourSpec) -> Vec<f32> {
     let mut lut_array = vec![1.0; LUT_ARRAY_ENTRIES];
-
-    let alpha = colour_spec.alpha;
-    let beta = colour_spec.beta;
-    let gamma = colour_spec.gamma;
-    let delta = colour_spec.delta;
+    let range = linear_range(colour_spec);
 
     for (i, entry) in lut_array.iter_mut().enumerate() {
         let fi = (i as f32) / ((LUT_ARRAY_ENTRIES - 1) as f32);
-        if fi < beta {
-            *entry = fi * delta;
-        } else {
-            *entry = alpha * f32::powf(fi, gamma) - (alpha - 1.0);
-        }
+        *entry = linear_to_gamma(colour_spec, fi * range);
     }
 
     lut_array
 }
 
+/// Multiplier from a linear light value to its index in [`linear_to_gamma_lut`].
+pub fn linear_to_gamma_lut_scale(colour_spec: &ColourSpec) -> f32 {
+    ((LUT_ARRAY_ENTRIES - 1) as f32) / linear_range(colour_spec)
+}
+
 pub fn rgb_to_xyz_matrix(colour_spec: &ColourSpec) -> Matrix3<f32> {
     let w = Matrix3x1::new(
         colour_spec.wx,
@@ -103,6 +193,8 @@ pub fn xyz_to_rgb_matrix(colour_spec: &ColourSpec) -> Matrix3<f32> {
     rgb_to_xyz_matrix(colour_spec).try_inverse().unwrap()
 }
 
+/// Frames are held with BT.709 primaries. Colours outside of its gamut, e.g. from BT.2020 sources,
+/// have components below 0 so that they survive the conversion back to a wider gamut.
 pub fn rgb_to_common_space_matrix(source_colour_spec: &ColourSpec) -> Matrix3<f32> {
     (xyz_to_rgb_matrix(&COLOUR_SPEC_BT_709) * rgb_to_xyz_matrix(source_colour_spec)).transpose()
 }
@@ -112,6 +204,13 @@ pub fn common_space_to_rgb_matrix(destination_colour_space: &ColourSpec) -> Matr
         .transpose()
 }
 
+/// Luma black, luma white and chroma range code values for a full range format.
+/// Limited range values depend on the format and are provided by its packer / unpacker.
+pub fn full_range_levels(number_of_bits: usize) -> (f32, f32, f32) {
+    let max = ((1u32 << number_of_bits) - 1) as f32;
+    (0.0, max, max)
+}
+
 pub fn ycbcr_to_rgb_matrix(
     colour_spec: &ColourSpec,
     number_of_bits: usize,
@@ -0,0 +1,160 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use nalgebra::{Vector3, Vector4};
+use phaneron_plugin::{
+    COLOUR_SPEC_BT_2100_HLG, COLOUR_SPEC_BT_2100_PQ, COLOUR_SPEC_BT_709, COLOUR_SPEC_SRGB,
+};
+
+use super::{
+    full_range_levels, gamma_to_linear, gamma_to_linear_lut, linear_range, linear_to_gamma,
+    linear_to_gamma_lut, linear_to_gamma_lut_scale, rgb_to_ycbcr_matrix, ycbcr_to_rgb_matrix,
+};
+
+fn blend(a: f32, b: f32, mix: f32) -> f32 {
+    a * mix + b * (1.0 - mix)
+}
+
+#[test]
+fn linear_blend_differs_from_gamma_blend() {
+    let black = 0.0;
+    let white = 1.0;
+
+    let naive = blend(black, white, 0.5);
+    let linear = linear_to_gamma(
+        &COLOUR_SPEC_BT_709,
+        blend(
+            gamma_to_linear(&COLOUR_SPEC_BT_709, black),
+            gamma_to_linear(&COLOUR_SPEC_BT_709, white),
+            0.5,
+        ),
+    );
+
+    assert_eq!(naive, 0.5);
+    assert!((linear - 0.7055).abs() < 0.001, "got {linear}");
+}
+
+#[test]
+fn linear_blend_of_colours() {
+    // A 50% blend of a saturated and a dark value, encoded as BT.709.
+    let a = 0.9;
+    let b = 0.2;
+
+    let naive = blend(a, b, 0.5);
+    let linear = linear_to_gamma(
+        &COLOUR_SPEC_BT_709,
+        blend(
+            gamma_to_linear(&COLOUR_SPEC_BT_709, a),
+            gamma_to_linear(&COLOUR_SPEC_BT_709, b),
+            0.5,
+        ),
+    );
+
+    // Blending in linear light produces a brighter result than blending the encoded values.
+    assert!(linear > naive);
+    assert!((linear - 0.6544).abs() < 0.001, "got {linear}");
+}
+
+#[test]
+fn transfer_functions_round_trip() {
+    for colour_spec in [
+        COLOUR_SPEC_BT_709,
+        COLOUR_SPEC_SRGB,
+        COLOUR_SPEC_BT_2100_PQ,
+        COLOUR_SPEC_BT_2100_HLG,
+    ] {
+        for value in [0.0, 0.25, 0.5, 0.75, 1.0] {
+            let round_trip = linear_to_gamma(&colour_spec, gamma_to_linear(&colour_spec, value));
+            assert!(
+                (round_trip - value).abs() < 0.0001,
+                "{value} became {round_trip}"
+            );
+        }
+    }
+}
+
+#[test]
+fn luts_match_transfer_functions() {
+    let to_linear = gamma_to_linear_lut(&COLOUR_SPEC_BT_709);
+    let to_gamma = linear_to_gamma_lut(&COLOUR_SPEC_BT_709);
+
+    assert_eq!(to_linear[0], gamma_to_linear(&COLOUR_SPEC_BT_709, 0.0));
+    assert_eq!(to_linear[65535], gamma_to_linear(&COLOUR_SPEC_BT_709, 1.0));
+    assert_eq!(to_gamma[0], linear_to_gamma(&COLOUR_SPEC_BT_709, 0.0));
+    assert_eq!(to_gamma[65535], linear_to_gamma(&COLOUR_SPEC_BT_709, 1.0));
+}
+
+#[test]
+fn hdr_reference_white_is_sdr_white() {
+    // BT.2408 reference white is 203 nits for PQ and a 75% signal for HLG
+    let pq = linear_to_gamma(&COLOUR_SPEC_BT_2100_PQ, 1.0);
+    assert!((pq - 0.5807).abs() < 0.001, "got {pq}");
+    let hlg = linear_to_gamma(&COLOUR_SPEC_BT_2100_HLG, 1.0);
+    assert!((hlg - 0.75).abs() < 0.0001, "got {hlg}");
+
+    assert_eq!(linear_range(&COLOUR_SPEC_BT_709), 1.0);
+    let pq_peak = linear_range(&COLOUR_SPEC_BT_2100_PQ);
+    assert!((pq_peak - 10000.0 / 203.0).abs() < 0.01, "got {pq_peak}");
+    assert!(linear_range(&COLOUR_SPEC_BT_2100_HLG) > 1.0);
+}
+
+#[test]
+fn hdr_lut_covers_highlights() {
+    let to_gamma = linear_to_gamma_lut(&COLOUR_SPEC_BT_2100_PQ);
+    let scale = linear_to_gamma_lut_scale(&COLOUR_SPEC_BT_2100_PQ);
+
+    let peak = linear_range(&COLOUR_SPEC_BT_2100_PQ);
+    assert!((to_gamma[(peak * scale).round() as usize] - 1.0).abs() < 0.0001);
+    let white = to_gamma[scale.round() as usize];
+    assert!((white - linear_to_gamma(&COLOUR_SPEC_BT_2100_PQ, 1.0)).abs() < 0.001);
+}
+
+fn assert_close(actual: Vector3<f32>, expected: Vector3<f32>) {
+    assert!(
+        (actual - expected).abs().max() < 1e-4,
+        "expected {expected:?}, got {actual:?}"
+    );
+}
+
+fn round_trip_levels(luma_black: f32, luma_white: f32, chroma_range: f32) {
+    let to_rgb = ycbcr_to_rgb_matrix(&COLOUR_SPEC_BT_709, 8, luma_black, luma_white, chroma_range)
+        .transpose();
+    let to_ycbcr =
+        rgb_to_ycbcr_matrix(&COLOUR_SPEC_BT_709, 8, luma_black, luma_white, chroma_range)
+            .transpose();
+
+    for (luma, expected) in [(luma_black, 0.0), (luma_white, 1.0)] {
+        let rgb = to_rgb * Vector4::new(luma, 128.0, 128.0, 1.0);
+        assert_close(rgb, Vector3::repeat(expected));
+
+        let ycbcr = to_ycbcr * Vector4::new(rgb.x, rgb.y, rgb.z, 1.0);
+        assert_close(ycbcr, Vector3::new(luma, 128.0, 128.0));
+    }
+}
+
+#[test]
+fn limited_range_levels() {
+    round_trip_levels(16.0, 235.0, 224.0);
+}
+
+#[test]
+fn full_range_levels_are_not_scaled() {
+    let (luma_black, luma_white, chroma_range) = full_range_levels(8);
+    assert_eq!((luma_black, luma_white, chroma_range), (0.0, 255.0, 255.0));
+    round_trip_levels(luma_black, luma_white, chroma_range);
+}
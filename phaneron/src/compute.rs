@@ -16,136 +16,507 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{ptr, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    ops::Deref,
+    ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Weak,
+    },
+    time::{Duration, Instant},
+};
 
 use abi_stable::{
     sabi_trait::TD_Opaque,
     std_types::{RArc, RVec},
 };
 use opencl3::{
+    error_codes::{
+        error_text, ClError, CL_DEVICE_NOT_AVAILABLE, CL_INVALID_COMMAND_QUEUE, CL_INVALID_CONTEXT,
+    },
     memory::{CL_FLOAT, CL_MEM_OBJECT_IMAGE2D, CL_RGBA},
-    types::{cl_image_desc, cl_image_format},
+    types::{
+        cl_command_queue, cl_context, cl_device_id, cl_image_desc, cl_image_format, cl_int,
+        cl_queue_properties,
+    },
+};
+use phaneron_plugin::{
+    traits::ProcessShader_TO, traits::VideoFrame_TO, FrameMetadata, ShaderParam, ShaderParams,
 };
-use phaneron_plugin::{traits::ProcessShader_TO, traits::VideoFrame_TO, ShaderParam, ShaderParams};
-use tracing::debug;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, warn};
+
+use crate::graph::NodeId;
 
-use self::video_frame::{VideoFrame, VideoFrameId};
+use self::{
+    device::{find_device, ComputeDeviceSelection, DeviceInfo},
+    fence::{GpuFence, GpuSyncMode},
+    video_frame::{VideoFrame, VideoFrameId},
+};
 
 pub mod audio_frame;
 pub mod audio_output;
+pub mod device;
+pub mod fence;
 pub mod video_frame;
 pub mod video_output;
 
-pub trait AsKernalParamU32 {
-    fn as_kernel_param(&self) -> u32;
+#[cfg(test)]
+mod tests;
+
+/// Priority of the GPU work submitted on behalf of a node.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComputePriority {
+    /// Process shaders run on the shared process queue.
+    #[default]
+    Normal,
+    /// Process shaders run on a dedicated queue so that they are not queued behind background work.
+    /// The queue is created with a high priority on devices that support `cl_khr_priority_hints`,
+    /// and its work only waits for the work producing the shader's input frames.
+    High,
 }
 
-#[cfg(Debug)]
-fn cl_queue_properties() -> opencl3::types::cl_command_queue_properties {
-    opencl3::command_queue::CL_QUEUE_PROFILING_ENABLE
+#[derive(Debug)]
+pub enum ComputeError {
+    /// The compute context has been shut down.
+    ShutDown,
+    /// A shader failed to build or the kernel could not be created from it.
+    ShaderCompilationFailed(BuildError),
+    /// An OpenCL call failed, e.g. because the device is out of memory or has been lost.
+    OpenCl(ClError),
+    /// Every video buffer in the pool is in use and the pool has reached its limit, contains the limit.
+    /// Buffers are returned to the pool as frames are dropped, so a later attempt may succeed.
+    BufferPoolFull(usize),
+    /// A frame refers to a video buffer that is not in the pool, contains the buffer's index.
+    /// Frames created before the context was recreated no longer have a buffer.
+    InvalidBuffer(usize),
+    /// The device can't create an image of the requested size, contains the width and height.
+    ImageTooLarge(usize, usize),
 }
 
-fn cl_queue_properties() -> opencl3::types::cl_command_queue_properties {
-    0
+impl From<ClError> for ComputeError {
+    fn from(value: ClError) -> Self {
+        Self::OpenCl(value)
+    }
 }
 
-pub async fn create_compute_context() -> PhaneronComputeContext {
-    // Find a usable device for this application
-    let device_id = *opencl3::device::get_all_devices(opencl3::device::CL_DEVICE_TYPE_GPU)
-        .unwrap()
-        .first()
-        .expect("no device found in platform");
-    let device = opencl3::device::Device::new(device_id);
-    let extensions = device.extensions().unwrap();
-    debug!("Device extensions: {}", extensions);
+impl Display for ComputeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ComputeError::ShutDown => write!(f, "The compute context has been shut down"),
+            ComputeError::ShaderCompilationFailed(err) => {
+                write!(f, "Failed to compile shader: {err}")
+            }
+            ComputeError::OpenCl(err) => write!(f, "OpenCL error {}: {}", err.0, error_text(err.0)),
+            ComputeError::BufferPoolFull(max_buffers) => {
+                write!(f, "All {max_buffers} video buffers are in use")
+            }
+            ComputeError::InvalidBuffer(index) => {
+                write!(f, "Video buffer {index} is not in the buffer pool")
+            }
+            ComputeError::ImageTooLarge(width, height) => {
+                write!(f, "The device does not support {width}x{height} images")
+            }
+        }
+    }
+}
 
-    // Create a Context on an OpenCL device
-    let cl_context =
-        opencl3::context::Context::from_device(&device).expect("Context::from_device failed");
+impl std::error::Error for ComputeError {}
 
-    // Create a command_queue on the Context's device
-    let load_queue = unsafe {
-        opencl3::command_queue::CommandQueue::create_with_properties(
-            &cl_context,
-            cl_context.default_device(),
-            cl_queue_properties(),
-            0,
+/// Why a shader failed to build, along with what the compiler logged while building it.
+#[derive(Debug)]
+pub struct BuildError {
+    /// The OpenCL error, e.g. `CL_BUILD_PROGRAM_FAILURE` or `CL_INVALID_KERNEL_NAME`.
+    pub error: ClError,
+    /// The build log of each device the program was built for, empty if nothing was logged.
+    pub log: String,
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "OpenCL error {}: {}",
+            self.error.0,
+            error_text(self.error.0)
+        )?;
+        if !self.log.is_empty() {
+            write!(f, "\n{}", self.log)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ComputeError {
+    /// Whether the error means the device, and with it every OpenCL resource, has been lost, e.g.
+    /// after a driver crash or GPU reset. The context must be recreated to carry on.
+    pub fn is_device_lost(&self) -> bool {
+        matches!(
+            self,
+            ComputeError::OpenCl(ClError(
+                CL_DEVICE_NOT_AVAILABLE | CL_INVALID_CONTEXT | CL_INVALID_COMMAND_QUEUE
+            ))
         )
-        .expect("CommandQueue::create failed")
+    }
+}
+
+/// Rejects images larger than the device supports, which would otherwise fail with an unspecific
+/// OpenCL error when the image is created.
+fn check_image_size(info: &DeviceInfo, width: usize, height: usize) -> Result<(), ComputeError> {
+    if info.supports_image_size(width, height) {
+        Ok(())
+    } else {
+        Err(ComputeError::ImageTooLarge(width, height))
+    }
+}
+
+pub trait AsKernalParamU32 {
+    fn as_kernel_param(&self) -> u32;
+}
+
+fn create_queue(
+    cl_context: &opencl3::context::Context,
+    profiling: bool,
+) -> Result<opencl3::command_queue::CommandQueue, ComputeError> {
+    let properties = if profiling {
+        opencl3::command_queue::CL_QUEUE_PROFILING_ENABLE
+    } else {
+        0
     };
-    let process_queue = unsafe {
+    Ok(unsafe {
         opencl3::command_queue::CommandQueue::create_with_properties(
-            &cl_context,
+            cl_context,
             cl_context.default_device(),
-            cl_queue_properties(),
+            properties,
             0,
-        )
-        .expect("CommandQueue::create failed")
+        )?
+    })
+}
+
+const CL_QUEUE_PROPERTIES: cl_queue_properties = 0x1093;
+// From cl_khr_priority_hints
+const CL_QUEUE_PRIORITY_KHR: cl_queue_properties = 0x1096;
+const CL_QUEUE_PRIORITY_HIGH_KHR: cl_queue_properties = 1 << 0;
+
+extern "system" {
+    fn clCreateCommandQueueWithProperties(
+        context: cl_context,
+        device: cl_device_id,
+        properties: *const cl_queue_properties,
+        errcode_ret: *mut cl_int,
+    ) -> cl_command_queue;
+}
+
+/// Creates a queue that the driver schedules ahead of the other queues, if the device supports
+/// queue priorities. Otherwise the queue is created like any other.
+fn create_high_priority_queue(
+    cl_context: &opencl3::context::Context,
+    profiling: bool,
+) -> Result<opencl3::command_queue::CommandQueue, ComputeError> {
+    let device = opencl3::device::Device::new(cl_context.default_device());
+    let supports_priority = device
+        .extensions()?
+        .split_whitespace()
+        .any(|extension| extension == "cl_khr_priority_hints");
+    if !supports_priority {
+        debug!("Device does not support queue priorities, high priority work uses a normal queue");
+        return create_queue(cl_context, profiling);
+    }
+
+    let queue_properties = if profiling {
+        opencl3::command_queue::CL_QUEUE_PROFILING_ENABLE
+    } else {
+        0
     };
-    let unload_queue = unsafe {
-        opencl3::command_queue::CommandQueue::create_with_properties(
-            &cl_context,
+    let properties: [cl_queue_properties; 5] = [
+        CL_QUEUE_PROPERTIES,
+        queue_properties,
+        CL_QUEUE_PRIORITY_KHR,
+        CL_QUEUE_PRIORITY_HIGH_KHR,
+        0,
+    ];
+    let mut status: cl_int = 0;
+    let queue = unsafe {
+        clCreateCommandQueueWithProperties(
+            cl_context.get(),
             cl_context.default_device(),
-            cl_queue_properties(),
-            0,
+            properties.as_ptr(),
+            &mut status,
         )
-        .expect("CommandQueue::create failed")
     };
+    if status != opencl3::error_codes::CL_SUCCESS {
+        return Err(ClError(status).into());
+    }
+
+    Ok(opencl3::command_queue::CommandQueue::new(
+        queue,
+        device.max_work_item_dimensions()?,
+    ))
+}
+
+/// The OpenCL objects that are replaced when the compute context is recreated.
+struct ClResources {
+    cl_context: opencl3::context::Context,
+    load_queue: opencl3::command_queue::CommandQueue,
+    process_queue: opencl3::command_queue::CommandQueue,
+    high_priority_queue: opencl3::command_queue::CommandQueue,
+    unload_queue: opencl3::command_queue::CommandQueue,
+    device_info: DeviceInfo,
+}
+
+fn create_cl_resources(
+    device: &ComputeDeviceSelection,
+    profiling: bool,
+) -> Result<ClResources, ComputeError> {
+    let device = find_device(device)?;
+
+    // Create a Context on an OpenCL device
+    let cl_context = opencl3::context::Context::from_device(&device)?;
+
+    // Create the command_queues on the Context's device
+    let load_queue = create_queue(&cl_context, profiling)?;
+    let process_queue = create_queue(&cl_context, profiling)?;
+    let high_priority_queue = create_high_priority_queue(&cl_context, profiling)?;
+    let unload_queue = create_queue(&cl_context, profiling)?;
+    let device_info = DeviceInfo::from_device(&device)?;
+
+    Ok(ClResources {
+        cl_context,
+        load_queue,
+        process_queue,
+        high_priority_queue,
+        unload_queue,
+    })
+}
+
+/// `max_video_buffers` limits the number of video buffers kept in the pool, `0` for no limit.
+pub async fn create_compute_context(
+    sync_mode: GpuSyncMode,
+    device: ComputeDeviceSelection,
+    max_video_buffers: usize,
+) -> Result<PhaneronComputeContext, ComputeError> {
+    let resources = create_cl_resources(&device, false)?;
+    let extensions =
+        opencl3::device::Device::new(resources.cl_context.default_device()).extensions()?;
+    debug!("Device extensions: {}", extensions);
+
+    debug!("Using {:?} GPU synchronization", sync_mode);
 
     let (buffer_drop_event_tx, mut buffer_drop_event_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (dropper_shutdown_tx, mut dropper_shutdown_rx) = tokio::sync::oneshot::channel::<()>();
     let inner_context = PhaneronComputeContextInner {
-        cl_context: std::sync::Mutex::new(cl_context),
-        load_queue: std::sync::Mutex::new(load_queue),
-        process_queue: std::sync::Mutex::new(process_queue),
-        unload_queue: std::sync::Mutex::new(unload_queue),
+        sync_mode,
+        device,
+        cl_context: std::sync::Mutex::new(Some(resources.cl_context)),
+        load_queue: std::sync::Mutex::new(Some(resources.load_queue)),
+        process_queue: std::sync::Mutex::new(Some(resources.process_queue)),
+        high_priority_queue: std::sync::Mutex::new(Some(resources.high_priority_queue)),
+        unload_queue: std::sync::Mutex::new(Some(resources.unload_queue)),
+        device_info: std::sync::Mutex::new(resources.device_info),
         video_buffers: Default::default(),
+        max_video_buffers,
+        buffer_uses: Default::default(),
+        profiling: Default::default(),
+        total_stats: Default::default(),
+        node_stats: Default::default(),
+        generation: Default::default(),
+        device_lost: Default::default(),
+        device_lost_error: Default::default(),
         buffer_drop_event_tx,
+        dropper_shutdown_tx: std::sync::Mutex::new(Some(dropper_shutdown_tx)),
     };
     let inner_context = Arc::new(inner_context);
 
-    let dropper_context = inner_context.clone();
+    // Only holds a weak reference so that the context is dropped along with its last clone,
+    // which also drops the shutdown sender and stops the task.
+    let dropper_context = Arc::downgrade(&inner_context);
     tokio::spawn(async move {
-        while let Some(buffer_index) = buffer_drop_event_rx.recv().await {
-            let mut buffers = dropper_context.video_buffers.lock().unwrap();
-            buffers.get_mut(buffer_index).unwrap().available = true;
+        loop {
+            let dropped_buffer = tokio::select! {
+                dropped_buffer = buffer_drop_event_rx.recv() => dropped_buffer,
+                _ = &mut dropper_shutdown_rx => None,
+            };
+            let ((generation, buffer_index), context) =
+                match (dropped_buffer, Weak::upgrade(&dropper_context)) {
+                    (Some(dropped_buffer), Some(context)) => (dropped_buffer, context),
+                    _ => break,
+                };
+            let mut buffers = context.video_buffers.lock().unwrap();
+            // Buffers from before the context was recreated are no longer in the pool
+            if generation != context.generation.load(Ordering::SeqCst) {
+                continue;
+            }
+            if let Some(buffer) = buffers.get_mut(buffer_index) {
+                buffer.available = true;
+            }
         }
+        debug!("Compute context buffer dropper stopped");
     });
 
-    PhaneronComputeContext {
+    Ok(PhaneronComputeContext {
         inner: inner_context,
-    }
+        priority: ComputePriority::Normal,
+        stats: None,
+    })
 }
 
 pub struct PhaneronComputeContext {
     inner: Arc<PhaneronComputeContextInner>,
+    priority: ComputePriority,
+    /// GPU time of the node this context was created for, see [`Self::with_node_stats`].
+    stats: Option<Arc<ComputeStatsCounter>>,
 }
 
 impl PhaneronComputeContext {
+    /// Returns a context that submits process shaders with the given priority.
+    pub fn with_priority(&self, priority: ComputePriority) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            priority,
+            stats: self.stats.clone(),
+        }
+    }
+
+    /// Returns a context that adds the GPU time of the work it submits to the stats of a node.
+    pub fn with_node_stats(&self, node_id: &NodeId) -> Self {
+        let stats = self
+            .inner
+            .node_stats
+            .lock()
+            .unwrap()
+            .entry(node_id.clone())
+            .or_default()
+            .clone();
+        Self {
+            inner: self.inner.clone(),
+            priority: self.priority,
+            stats: Some(stats),
+        }
+    }
+
+    pub fn remove_node_stats(&self, node_id: &NodeId) {
+        self.inner.node_stats.lock().unwrap().remove(node_id);
+    }
+
+    pub fn is_profiling(&self) -> bool {
+        self.inner.profiling.load(Ordering::Relaxed)
+    }
+
+    /// Incremented each time the context is recreated, see [`Self::recreate`].
+    pub fn generation(&self) -> u64 {
+        self.inner.generation.load(Ordering::SeqCst)
+    }
+
+    /// Recreates the command queues with or without profiling, waiting for the work submitted to
+    /// the old queues to complete so that work stays in order. GPU time is only measured while
+    /// profiling, which makes every process shader wait for completion.
+    pub fn set_profiling(&self, enabled: bool) -> Result<(), ComputeError> {
+        if self.is_profiling() == enabled {
+            return Ok(());
+        }
+        let context = lock_resource(&self.inner.cl_context)?;
+        for (queue, high_priority) in [
+            (&self.inner.load_queue, false),
+            (&self.inner.process_queue, false),
+            (&self.inner.high_priority_queue, true),
+            (&self.inner.unload_queue, false),
+        ] {
+            let new_queue = if high_priority {
+                create_high_priority_queue(&context, enabled)?
+            } else {
+                create_queue(&context, enabled)?
+            };
+            let mut queue = queue.lock().unwrap();
+            if let Some(queue) = queue.as_ref() {
+                queue.finish()?;
+            }
+            *queue = Some(new_queue);
+        }
+        self.inner.profiling.store(enabled, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// GPU time measured while profiling, in total and by node.
+    pub fn compute_stats(&self) -> ComputeStatsReport {
+        ComputeStatsReport {
+            profiling: self.is_profiling(),
+            total: self.inner.total_stats.snapshot(),
+            nodes: self
+                .inner
+                .node_stats
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(node_id, stats)| (node_id.to_string(), stats.snapshot()))
+                .collect(),
+        }
+    }
+
+    /// Adds the GPU time of a completed command to the stats.
+    fn record_event(&self, stage: ComputeStage, event: &opencl3::event::Event) {
+        // Commands submitted before profiling was enabled have no timings
+        let (Ok(start), Ok(end)) = (
+            event.profiling_command_start(),
+            event.profiling_command_end(),
+        ) else {
+            return;
+        };
+        let nanos = end.saturating_sub(start);
+        self.inner.total_stats.add(stage, nanos);
+        if let Some(stats) = &self.stats {
+            stats.add(stage, nanos);
+        }
+    }
+
+    /// Waits for a command to complete, adding its GPU time to the stats while profiling.
+    fn wait_for_stage(
+        &self,
+        stage: ComputeStage,
+        event: opencl3::event::Event,
+    ) -> Result<(), ComputeError> {
+        if !self.is_profiling() {
+            return self.wait_for_event(event);
+        }
+        event.wait()?;
+        self.record_event(stage, &event);
+
+        Ok(())
+    }
+
     pub fn load_frame_to_buffer(
         &self,
         data: &[u8],
-    ) -> (
-        opencl3::memory::Buffer<opencl3::types::cl_uchar>,
-        opencl3::event::Event,
-    ) {
-        let context = self.inner.cl_context.lock().unwrap();
+    ) -> Result<
+        (
+            opencl3::memory::Buffer<opencl3::types::cl_uchar>,
+            opencl3::event::Event,
+        ),
+        ComputeError,
+    > {
+        let context = lock_resource(&self.inner.cl_context)?;
         let mut buf = unsafe {
             opencl3::memory::Buffer::<opencl3::types::cl_uchar>::create(
                 &context,
                 opencl3::memory::CL_MEM_READ_ONLY,
                 data.len(),
                 ptr::null_mut(),
-            )
-            .unwrap()
+            )?
         };
-        let queue = self.inner.load_queue.lock().unwrap();
+        let queue = lock_resource(&self.inner.load_queue)?;
         let load_frame_event = unsafe {
-            queue
-                .enqueue_write_buffer(&mut buf, opencl3::types::CL_BLOCKING, 0, data, &[])
-                .unwrap()
+            queue.enqueue_write_buffer(&mut buf, opencl3::types::CL_BLOCKING, 0, data, &[])?
         };
+        // The write is blocking, the command has completed
+        if self.is_profiling() {
+            self.record_event(ComputeStage::Load, &load_frame_event);
+        }
 
-        (buf, load_frame_event)
+        Ok((buf, load_frame_event))
     }
 
     pub fn copy_frame_from_buffer(
@@ -153,56 +524,101 @@ impl PhaneronComputeContext {
         buffer: &opencl3::memory::Buffer<opencl3::types::cl_uchar>,
         out: &mut [u8],
         wait_events: &[opencl3::event::Event],
-    ) {
+    ) -> Result<(), ComputeError> {
         let mut events: Vec<opencl3::types::cl_event> = vec![];
         for event in wait_events.iter() {
             events.push(event.get());
         }
 
-        let queue = self.inner.unload_queue.lock().unwrap();
-        let copy_event = unsafe {
-            queue
-                .enqueue_read_buffer(buffer, opencl3::types::CL_BLOCKING, 0, out, &events)
-                .unwrap()
+        let blocking = match self.inner.sync_mode {
+            GpuSyncMode::Blocking => opencl3::types::CL_BLOCKING,
+            GpuSyncMode::Fence => opencl3::types::CL_NON_BLOCKING,
+        };
+        let copy_event = {
+            let queue = lock_resource(&self.inner.unload_queue)?;
+            unsafe { queue.enqueue_read_buffer(buffer, blocking, 0, out, &events)? }
         };
-        copy_event.wait().unwrap();
+        self.wait_for_stage(ComputeStage::Unload, copy_event)
+    }
+
+    /// Starts copying a buffer into `out` and returns without waiting for the copy to complete.
+    ///
+    /// # Safety
+    /// `out` is written to asynchronously, it must not be read, moved or freed until the
+    /// returned event has completed.
+    pub unsafe fn start_copy_frame_from_buffer(
+        &self,
+        buffer: &opencl3::memory::Buffer<opencl3::types::cl_uchar>,
+        out: &mut [u8],
+        wait_events: &[opencl3::event::Event],
+    ) -> Result<opencl3::event::Event, ComputeError> {
+        let mut events: Vec<opencl3::types::cl_event> = vec![];
+        for event in wait_events.iter() {
+            events.push(event.get());
+        }
+
+        let queue = lock_resource(&self.inner.unload_queue)?;
+        Ok(queue.enqueue_read_buffer(buffer, opencl3::types::CL_NON_BLOCKING, 0, out, &events)?)
     }
 
     pub fn create_video_frame_buffer(
         &self,
         num_bytes_rgba: usize,
-    ) -> opencl3::memory::Buffer<opencl3::types::cl_uchar> {
+    ) -> Result<opencl3::memory::Buffer<opencl3::types::cl_uchar>, ComputeError> {
         self.create_buffer(num_bytes_rgba)
     }
 
     pub fn create_buffer(
         &self,
         num_bytes: usize,
-    ) -> opencl3::memory::Buffer<opencl3::types::cl_uchar> {
-        let context = self.inner.cl_context.lock().unwrap();
-        unsafe {
+    ) -> Result<opencl3::memory::Buffer<opencl3::types::cl_uchar>, ComputeError> {
+        let context = lock_resource(&self.inner.cl_context)?;
+        Ok(unsafe {
             opencl3::memory::Buffer::<opencl3::types::cl_uchar>::create(
                 &context,
                 opencl3::memory::CL_MEM_READ_WRITE,
                 num_bytes,
                 ptr::null_mut(),
-            )
-            .unwrap()
-        }
+            )?
+        })
     }
 
-    fn create_image(&self, width: usize, height: usize) -> VideoBufferRef {
+    fn create_image(&self, width: usize, height: usize) -> Result<VideoBufferRef, ComputeError> {
+        self.create_image_within(width, height, self.inner.max_video_buffers)
+    }
+
+    /// Creates an image from the pool, growing the pool up to `max_buffers`, `0` for no limit.
+    fn create_image_within(
+        &self,
+        width: usize,
+        height: usize,
+        max_buffers: usize,
+    ) -> Result<VideoBufferRef, ComputeError> {
+        check_image_size(&self.inner.device_info.lock().unwrap(), width, height)?;
         let mut buffers = self.inner.video_buffers.lock().unwrap();
+        let last_used = self.inner.buffer_uses.fetch_add(1, Ordering::Relaxed);
         let available_buffer = buffers.iter().position(|buffer| {
             buffer.available && buffer.width == width && buffer.height == height
         });
         let index = match available_buffer {
             Some(index) => {
-                buffers.get_mut(index).unwrap().available = false;
+                let buffer = buffers.get_mut(index).unwrap();
+                buffer.available = false;
+                buffer.last_used = last_used;
+                buffer.write_event = None;
                 index
             }
             None => {
-                let context = self.inner.cl_context.lock().unwrap();
+                let slot = new_buffer_slot(
+                    buffers
+                        .iter()
+                        .map(|buffer| buffer.available.then_some(buffer.last_used)),
+                    max_buffers,
+                );
+                if slot == BufferSlot::Full {
+                    return Err(ComputeError::BufferPoolFull(self.inner.max_video_buffers));
+                }
+                let context = lock_resource(&self.inner.cl_context)?;
                 let buffer = unsafe {
                     opencl3::memory::Image::create(
                         &context,
@@ -224,15 +640,28 @@ impl PhaneronComputeContext {
                             buffer: std::ptr::null_mut(),
                         },
                         std::ptr::null_mut(),
-                    )
-                    .unwrap()
+                    )?
                 };
-                buffers.push(VideoBuffer::new(buffer, width, height));
-                buffers.len() - 1
+                let buffer = VideoBuffer::new(buffer, width, height, last_used);
+                match slot {
+                    // The evicted buffer's image is released as it is replaced
+                    BufferSlot::Replace(index) => {
+                        buffers[index] = buffer;
+                        index
+                    }
+                    _ => {
+                        buffers.push(buffer);
+                        buffers.len() - 1
+                    }
+                }
             }
         };
 
-        VideoBufferRef::new(self.inner.buffer_drop_event_tx.clone(), index)
+        Ok(VideoBufferRef::new(
+            self.inner.buffer_drop_event_tx.clone(),
+            self.inner.generation.load(Ordering::SeqCst),
+            index,
+        ))
     }
 
     // TODO: Not pub!
@@ -241,34 +670,36 @@ impl PhaneronComputeContext {
         width: usize,
         height: usize,
         buffer: &opencl3::memory::Buffer<opencl3::types::cl_uchar>,
-    ) -> VideoBufferRef {
+    ) -> Result<VideoBufferRef, ComputeError> {
         // TODO: A copy can be avoided by using cl_khr_image2d_from_buffer on platforms that support it.
 
-        let image = self.create_image(width, height);
+        let image = self.create_image(width, height)?;
         let image_index = image.video_buffer_index;
         let mut buffers = self.inner.video_buffers.lock().unwrap();
-        let image_buffer = buffers.get_mut(image_index).unwrap();
+        let image_buffer = buffers
+            .get_mut(image_index)
+            .ok_or(ComputeError::InvalidBuffer(image_index))?;
 
         let dst_origin: [usize; 3] = [0, 0, 0];
         let region: [usize; 3] = [width, height, 1];
-        let queue = self.inner.process_queue.lock().unwrap();
+        let queue = lock_resource(&self.inner.process_queue)?;
 
         let wait_event = unsafe {
-            queue
-                .enqueue_copy_buffer_to_image(
-                    buffer,
-                    &mut image_buffer.buffer,
-                    0,
-                    dst_origin.as_ptr(),
-                    region.as_ptr(),
-                    &[],
-                )
-                .unwrap()
+            queue.enqueue_copy_buffer_to_image(
+                buffer,
+                &mut image_buffer.buffer,
+                0,
+                dst_origin.as_ptr(),
+                region.as_ptr(),
+                &[],
+            )?
         };
 
-        wait_event.wait().unwrap();
+        drop(queue);
+        drop(buffers);
+        self.wait_for_stage(ComputeStage::Load, wait_event)?;
 
-        image
+        Ok(image)
     }
 
     pub fn create_buffer_from_image(
@@ -277,115 +708,412 @@ impl PhaneronComputeContext {
         height: usize,
         total_bytes: usize,
         image: phaneron_plugin::types::VideoFrame,
-    ) -> opencl3::memory::Buffer<opencl3::types::cl_uchar> {
-        let mut output_buffer = self.create_buffer(total_bytes);
+    ) -> Result<opencl3::memory::Buffer<opencl3::types::cl_uchar>, ComputeError> {
+        let mut output_buffer = self.create_buffer(total_bytes)?;
         let buffers = self.inner.video_buffers.lock().unwrap();
-        let input_buffer = buffers.get(image.buffer_index()).unwrap();
+        let input_buffer = buffers
+            .get(image.buffer_index())
+            .ok_or(ComputeError::InvalidBuffer(image.buffer_index()))?;
         let src_origin: [usize; 3] = [0, 0, 0];
         let region: [usize; 3] = [width, height, 1];
-        let queue = self.inner.process_queue.lock().unwrap();
+        let queue = lock_resource(&self.inner.process_queue)?;
         let wait_event = unsafe {
-            queue
-                .enqueue_copy_image_to_buffer(
-                    &input_buffer.buffer,
-                    &mut output_buffer,
-                    src_origin.as_ptr(),
-                    region.as_ptr(),
-                    0,
-                    &[],
-                )
-                .unwrap()
+            queue.enqueue_copy_image_to_buffer(
+                &input_buffer.buffer,
+                &mut output_buffer,
+                src_origin.as_ptr(),
+                region.as_ptr(),
+                0,
+                &[],
+            )?
         };
-        wait_event.wait().unwrap();
+        drop(queue);
+        drop(buffers);
+        self.wait_for_stage(ComputeStage::Unload, wait_event)?;
 
-        output_buffer
+        Ok(output_buffer)
     }
 
-    pub fn create_black_frame(&self, width: usize, height: usize) -> VideoFrame {
-        let buffer = self.create_image(width, height);
+    pub fn create_black_frame(
+        &self,
+        width: usize,
+        height: usize,
+    ) -> Result<VideoFrame, ComputeError> {
+        let buffer = self.create_image(width, height)?;
 
-        VideoFrame::new(VideoFrameId::default(), buffer, width, height)
+        Ok(VideoFrame::new(
+            VideoFrameId::default(),
+            buffer,
+            width,
+            height,
+        ))
     }
 
-    pub fn create_load_shader(&self, kernel: &str) -> opencl3::kernel::Kernel {
-        let context = self.inner.cl_context.lock().unwrap();
-        let program = opencl3::program::Program::create_and_build_from_source(&context, kernel, "")
-            .expect("Program::create_and_build_from_source failed");
-        opencl3::kernel::Kernel::create(&program, "read").expect("Kernel::create failed")
+    /// Creates a frame filled with a single colour, given as linear RGBA.
+    pub fn create_colour_frame(
+        &self,
+        width: usize,
+        height: usize,
+        colour: [f32; 4],
+    ) -> Result<VideoFrame, ComputeError> {
+        let (frame, wait_event) = self.enqueue_colour_frame(width, height, colour)?;
+        self.wait_for_event(wait_event)?;
+
+        Ok(frame)
     }
 
-    pub fn create_save_shader(&self, kernel: &str) -> opencl3::kernel::Kernel {
-        let context = self.inner.cl_context.lock().unwrap();
-        let program = opencl3::program::Program::create_and_build_from_source(&context, kernel, "")
-            .expect("Program::create_and_build_from_source failed");
-        opencl3::kernel::Kernel::create(&program, "write").expect("Kernel::create failed")
+    /// Creates a frame filled with a single colour from a task, see [`Self::wait_for_event_async`].
+    pub async fn create_colour_frame_async(
+        &self,
+        width: usize,
+        height: usize,
+        colour: [f32; 4],
+    ) -> Result<VideoFrame, ComputeError> {
+        let (frame, wait_event) = self.enqueue_colour_frame(width, height, colour)?;
+        self.wait_for_event_async(wait_event).await?;
+
+        Ok(frame)
+    }
+
+    fn enqueue_colour_frame(
+        &self,
+        width: usize,
+        height: usize,
+        colour: [f32; 4],
+    ) -> Result<(VideoFrame, opencl3::event::Event), ComputeError> {
+        let image = self.create_image(width, height)?;
+        self.enqueue_fill_image(image, width, height, colour)
+    }
+
+    /// A black frame for a process shader that was skipped as the pool is full, created past the
+    /// pool's limit so that the shader still returns a frame for each of its outputs.
+    fn create_skipped_shader_frame(
+        &self,
+        width: usize,
+        height: usize,
+    ) -> Result<VideoFrame, ComputeError> {
+        let image = self.create_image_within(width, height, 0)?;
+        let (frame, wait_event) =
+            self.enqueue_fill_image(image, width, height, [0.0, 0.0, 0.0, 1.0])?;
+        self.wait_for_event(wait_event)?;
+
+        Ok(frame)
+    }
+
+    fn enqueue_fill_image(
+        &self,
+        image: VideoBufferRef,
+        width: usize,
+        height: usize,
+        colour: [f32; 4],
+    ) -> Result<(VideoFrame, opencl3::event::Event), ComputeError> {
+        let mut buffers = self.inner.video_buffers.lock().unwrap();
+        let image_buffer = buffers
+            .get_mut(image.video_buffer_index)
+            .ok_or(ComputeError::InvalidBuffer(image.video_buffer_index))?;
+
+        let origin: [usize; 3] = [0, 0, 0];
+        let region: [usize; 3] = [width, height, 1];
+        let queue = lock_resource(&self.inner.process_queue)?;
+
+        let wait_event = unsafe {
+            queue.enqueue_fill_image(
+                &mut image_buffer.buffer,
+                colour.as_ptr() as *const std::ffi::c_void,
+                origin.as_ptr(),
+                region.as_ptr(),
+                &[],
+            )?
+        };
+
+        Ok((
+            VideoFrame::new(VideoFrameId::default(), image, width, height),
+            wait_event,
+        ))
+    }
+
+    pub fn create_load_shader(
+        &self,
+        kernel: &str,
+    ) -> Result<opencl3::kernel::Kernel, ComputeError> {
+        let context = lock_resource(&self.inner.cl_context)?;
+        build_kernel(&context, kernel, "read").map_err(ComputeError::ShaderCompilationFailed)
+    }
+
+    pub fn create_save_shader(
+        &self,
+        kernel: &str,
+    ) -> Result<opencl3::kernel::Kernel, ComputeError> {
+        let context = lock_resource(&self.inner.cl_context)?;
+        build_kernel(&context, kernel, "write").map_err(ComputeError::ShaderCompilationFailed)
     }
 
     pub fn create_process_shader(
         &self,
         kernel: &str,
         program_name: &str,
-    ) -> phaneron_plugin::types::ProcessShader {
-        let context = self.inner.cl_context.lock().unwrap();
-        let program = opencl3::program::Program::create_and_build_from_source(&context, kernel, "")
-            .expect("Program::create_and_build_from_source failed");
-        let kernel =
-            opencl3::kernel::Kernel::create(&program, program_name).expect("Kernel::create failed");
-
-        ProcessShader_TO::from_value(
-            ProcessShaderImpl::new(
-                PhaneronComputeContext {
-                    inner: self.inner.clone(),
-                },
-                kernel,
-            ),
+    ) -> Result<phaneron_plugin::types::ProcessShader, ComputeError> {
+        let context = lock_resource(&self.inner.cl_context)?;
+        let kernel = build_kernel(&context, kernel, program_name)
+            .map_err(ComputeError::ShaderCompilationFailed)?;
+
+        Ok(ProcessShader_TO::from_value(
+            ProcessShaderImpl::new(self.clone(), kernel),
             TD_Opaque,
-        )
+        ))
     }
 
-    pub fn create_loadsave_params_buffer<T>(&self, data: &[T]) -> opencl3::memory::Buffer<T> {
-        let context = self.inner.cl_context.lock().unwrap();
+    pub fn create_loadsave_params_buffer<T>(
+        &self,
+        data: &[T],
+    ) -> Result<opencl3::memory::Buffer<T>, ComputeError> {
+        let context = lock_resource(&self.inner.cl_context)?;
         let mut buffer = unsafe {
             opencl3::memory::Buffer::<T>::create(
                 &context,
                 opencl3::memory::CL_MEM_READ_ONLY,
                 data.len(),
                 ptr::null_mut(),
-            )
-            .unwrap()
+            )?
         };
 
-        let queue = self.inner.load_queue.lock().unwrap();
+        let queue = lock_resource(&self.inner.load_queue)?;
         let load_buffer_event = unsafe {
-            queue
-                .enqueue_write_buffer(&mut buffer, opencl3::types::CL_BLOCKING, 0, data, &[])
-                .unwrap()
+            queue.enqueue_write_buffer(&mut buffer, opencl3::types::CL_BLOCKING, 0, data, &[])?
         };
-        load_buffer_event.wait().unwrap();
+        drop(queue);
+        self.wait_for_stage(ComputeStage::Load, load_buffer_event)?;
 
-        buffer
+        Ok(buffer)
     }
 
     pub fn run_loadsave_shader(
         &self,
         mut execute_kernel: opencl3::kernel::ExecuteKernel<'_>,
         wait_events: &[opencl3::event::Event],
-    ) -> opencl3::event::Event {
+    ) -> Result<opencl3::event::Event, ComputeError> {
         let mut events: Vec<opencl3::types::cl_event> = vec![];
         for event in wait_events.iter() {
             events.push(event.get());
         }
 
         execute_kernel.set_event_wait_list(&events);
-        let queue = self.inner.process_queue.lock().unwrap();
-        unsafe { execute_kernel.enqueue_nd_range(&queue).unwrap() }
+        let queue = lock_resource(&self.inner.process_queue)?;
+        Ok(unsafe { execute_kernel.enqueue_nd_range(&queue)? })
+    }
+
+    /// Runs a process shader reading the video buffers at `inputs` and writing those at `outputs`.
+    pub fn run_process_shader(
+        &self,
+        mut execute_kernel: opencl3::kernel::ExecuteKernel<'_>,
+        inputs: &[usize],
+        outputs: &[usize],
+    ) -> Result<(), ComputeError> {
+        match self.priority {
+            ComputePriority::Normal => {
+                let queue = lock_resource(&self.inner.process_queue)?;
+                let wait_event = unsafe { execute_kernel.enqueue_nd_range(&queue)? };
+                drop(queue);
+
+                // Anything that reads the output is either on the (in-order) process queue or waits on
+                // an event from it, so there is no need to wait here unless blocking is requested.
+                if self.is_profiling() {
+                    self.wait_for_stage(ComputeStage::Process, wait_event)?;
+                } else if self.inner.sync_mode == GpuSyncMode::Blocking {
+                    wait_event.wait()?;
+                } else {
+                    let wait_event = Arc::new(wait_event);
+                    let mut buffers = self.inner.video_buffers.lock().unwrap();
+                    for index in outputs {
+                        if let Some(buffer) = buffers.get_mut(*index) {
+                            buffer.write_event = Some(wait_event.clone());
+                        }
+                    }
+                }
+            }
+            ComputePriority::High => {
+                // Inputs may still be being written by work on the process queue, that work is
+                // waited for without waiting for the rest of the queue.
+                let input_events: Vec<Arc<opencl3::event::Event>> = {
+                    let buffers = self.inner.video_buffers.lock().unwrap();
+                    inputs
+                        .iter()
+                        .filter_map(|index| buffers.get(*index)?.write_event.clone())
+                        .collect()
+                };
+                let wait_events: Vec<opencl3::types::cl_event> =
+                    input_events.iter().map(|event| event.get()).collect();
+                if !wait_events.is_empty() {
+                    // Submits the work to the device, otherwise the driver may hold it back and
+                    // this queue would wait for it indefinitely
+                    lock_resource(&self.inner.process_queue)?.flush()?;
+                    execute_kernel.set_event_wait_list(&wait_events);
+                }
+
+                let queue = lock_resource(&self.inner.high_priority_queue)?;
+                let wait_event = unsafe { execute_kernel.enqueue_nd_range(&queue)? };
+                drop(queue);
+
+                // Always wait, so that work on the process queue reading the output doesn't need to know about this queue.
+                self.wait_for_stage(ComputeStage::Process, wait_event)?;
+            }
+        }
+
+        Ok(())
     }
 
-    pub fn run_process_shader(&self, mut execute_kernel: opencl3::kernel::ExecuteKernel<'_>) {
-        let queue = self.inner.process_queue.lock().unwrap();
-        let wait_event = unsafe { execute_kernel.enqueue_nd_range(&queue).unwrap() };
+    /// Finishes any submitted work and then releases the buffer pool, queues and OpenCL context,
+    /// and stops the task that returns buffers to the pool. Any further use of this context or its
+    /// clones returns [`ComputeError::ShutDown`].
+    pub fn shutdown(&self) {
+        if let Some(dropper_shutdown_tx) = self.inner.dropper_shutdown_tx.lock().unwrap().take() {
+            dropper_shutdown_tx.send(()).ok();
+        }
 
-        wait_event.wait().unwrap();
+        for queue in [
+            &self.inner.load_queue,
+            &self.inner.process_queue,
+            &self.inner.high_priority_queue,
+            &self.inner.unload_queue,
+        ] {
+            if let Some(queue) = queue.lock().unwrap().take() {
+                queue.finish().ok();
+            }
+        }
+        self.inner.video_buffers.lock().unwrap().clear();
+        self.inner.cl_context.lock().unwrap().take();
+    }
+
+    /// Replaces the OpenCL context, queues and buffer pool after the device has been lost, clones
+    /// of this context use the new resources. Frames, shaders, loaders and savers created before
+    /// belong to the old context and must be recreated by their owners.
+    pub fn recreate(&self) -> Result<(), ComputeError> {
+        if self.is_shut_down() {
+            return Err(ComputeError::ShutDown);
+        }
+        let resources = create_cl_resources(&self.inner.device, self.is_profiling())?;
+
+        // Nothing can be waited for on a lost device, the old queues are dropped without finishing
+        let mut buffers = self.inner.video_buffers.lock().unwrap();
+        buffers.clear();
+        self.inner.generation.fetch_add(1, Ordering::SeqCst);
+        *self.inner.load_queue.lock().unwrap() = Some(resources.load_queue);
+        *self.inner.process_queue.lock().unwrap() = Some(resources.process_queue);
+        *self.inner.high_priority_queue.lock().unwrap() = Some(resources.high_priority_queue);
+        *self.inner.unload_queue.lock().unwrap() = Some(resources.unload_queue);
+        *self.inner.device_info.lock().unwrap() = resources.device_info;
+        *self.inner.cl_context.lock().unwrap() = Some(resources.cl_context);
+
+        Ok(())
+    }
+
+    /// Passes errors hit while processing frames on to [`Self::device_lost`] if they show that the
+    /// device was lost, so that recovery starts without waiting for the next device check.
+    pub fn report_error(&self, err: &ComputeError) {
+        if let (true, ComputeError::OpenCl(ClError(code))) = (err.is_device_lost(), err) {
+            self.inner
+                .device_lost_error
+                .lock()
+                .unwrap()
+                .get_or_insert(*code);
+            self.inner.device_lost.notify_one();
+        }
+    }
+
+    /// Panics the thread processing a frame after GPU work for the frame failed, reporting the
+    /// error first, see [`Self::report_error`].
+    pub fn fail_frame(&self, message: &str, err: ComputeError) -> ! {
+        self.report_error(&err);
+        panic!("{message}: {err}")
+    }
+
+    /// Waits for an error that shows the device was lost to be reported with [`Self::report_error`].
+    pub async fn device_lost(&self) -> ComputeError {
+        loop {
+            self.inner.device_lost.notified().await;
+            if let Some(code) = self.inner.device_lost_error.lock().unwrap().take() {
+                return ClError(code).into();
+            }
+        }
+    }
+
+    /// Checks that the device is still available and completes work, see [`ComputeError::is_device_lost`].
+    pub fn check_device(&self) -> Result<(), ComputeError> {
+        let device = {
+            let context = lock_resource(&self.inner.cl_context)?;
+            opencl3::device::Device::new(context.default_device())
+        };
+        if !device.available()? {
+            return Err(ClError(CL_DEVICE_NOT_AVAILABLE).into());
+        }
+
+        let marker = {
+            let queue = lock_resource(&self.inner.process_queue)?;
+            unsafe { queue.enqueue_marker_with_wait_list(&[])? }
+        };
+        marker.wait()?;
+
+        Ok(())
+    }
+
+    /// The device the context is running on, queried from OpenCL on each call.
+    pub fn device_info(&self) -> Result<DeviceInfo, ComputeError> {
+        let device = {
+            let context = lock_resource(&self.inner.cl_context)?;
+            opencl3::device::Device::new(context.default_device())
+        };
+
+        Ok(DeviceInfo::from_device(&device)?)
+    }
+
+    /// Size of the video buffer pool and how many of its buffers are held by frames.
+    pub fn buffer_pool_usage(&self) -> BufferPoolUsage {
+        let buffers = self.inner.video_buffers.lock().unwrap();
+        BufferPoolUsage {
+            buffers: buffers.len(),
+            in_use: buffers.iter().filter(|buffer| !buffer.available).count(),
+            bytes: buffers.iter().map(VideoBuffer::num_bytes).sum(),
+            max_buffers: self.inner.max_video_buffers,
+        }
+    }
+
+    pub fn is_shut_down(&self) -> bool {
+        self.inner.cl_context.lock().unwrap().is_none()
+    }
+
+    pub fn wait_for_event(&self, event: opencl3::event::Event) -> Result<(), ComputeError> {
+        match self.inner.sync_mode {
+            GpuSyncMode::Blocking => event.wait()?,
+            // Fences can't be waited on synchronously from within the async runtime, fall back to
+            // blocking there. Tasks should use `wait_for_event_async` instead.
+            GpuSyncMode::Fence if tokio::runtime::Handle::try_current().is_ok() => event.wait()?,
+            GpuSyncMode::Fence => GpuFence::new(event)?.wait().map_err(ClError)?,
+        }
+
+        Ok(())
+    }
+
+    /// Waits for a command to complete from a task. In fence mode the task is suspended until the
+    /// driver signals completion, in blocking mode the thread running the task blocks.
+    pub async fn wait_for_event_async(
+        &self,
+        event: opencl3::event::Event,
+    ) -> Result<(), ComputeError> {
+        match self.inner.sync_mode {
+            GpuSyncMode::Blocking => event.wait()?,
+            GpuSyncMode::Fence => GpuFence::new(event)?.completed().await.map_err(ClError)?,
+        }
+
+        Ok(())
+    }
+
+    /// Waits from a task for the work submitted to the process queue so far to complete.
+    pub async fn wait_for_process_queue(&self) -> Result<(), ComputeError> {
+        let marker = {
+            let queue = lock_resource(&self.inner.process_queue)?;
+            unsafe { queue.enqueue_marker_with_wait_list(&[])? }
+        };
+        self.wait_for_event_async(marker).await
     }
 }
 
@@ -393,29 +1121,210 @@ impl Clone for PhaneronComputeContext {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            priority: self.priority,
+            stats: self.stats.clone(),
         }
     }
 }
 
 struct PhaneronComputeContextInner {
+    sync_mode: GpuSyncMode,
+    device: ComputeDeviceSelection,
+    /// Incremented each time the context is recreated.
+    generation: AtomicU64,
+    /// Notified when an error reported by [`PhaneronComputeContext::report_error`] shows the device
+    /// was lost, the error code is kept for the watchdog.
+    device_lost: tokio::sync::Notify,
+    device_lost_error: std::sync::Mutex<Option<cl_int>>,
     // Mutexes needed to make opencl types by treated as Send and Sync
-    buffer_drop_event_tx: tokio::sync::mpsc::UnboundedSender<usize>,
-    cl_context: std::sync::Mutex<opencl3::context::Context>,
-    load_queue: std::sync::Mutex<opencl3::command_queue::CommandQueue>,
-    process_queue: std::sync::Mutex<opencl3::command_queue::CommandQueue>,
-    unload_queue: std::sync::Mutex<opencl3::command_queue::CommandQueue>,
+    buffer_drop_event_tx: tokio::sync::mpsc::UnboundedSender<(u64, usize)>,
+    dropper_shutdown_tx: std::sync::Mutex<Option<tokio::sync::oneshot::Sender<()>>>,
+    // Resources are taken when the context is shut down
+    cl_context: std::sync::Mutex<Option<opencl3::context::Context>>,
+    load_queue: std::sync::Mutex<Option<opencl3::command_queue::CommandQueue>>,
+    process_queue: std::sync::Mutex<Option<opencl3::command_queue::CommandQueue>>,
+    high_priority_queue: std::sync::Mutex<Option<opencl3::command_queue::CommandQueue>>,
+    unload_queue: std::sync::Mutex<Option<opencl3::command_queue::CommandQueue>>,
+    /// The device as it was when the context was created, images are checked against its limits.
+    device_info: std::sync::Mutex<DeviceInfo>,
     video_buffers: std::sync::Mutex<Vec<VideoBuffer>>,
+    /// `0` for no limit.
+    max_video_buffers: usize,
+    /// Counts the buffers taken from the pool, orders buffers by when they were last used.
+    buffer_uses: AtomicU64,
+    /// Whether the queues were created with profiling enabled.
+    profiling: AtomicBool,
+    total_stats: ComputeStatsCounter,
+    node_stats: std::sync::Mutex<HashMap<NodeId, Arc<ComputeStatsCounter>>>,
+}
+
+/// GPU time spent on loading frames to the GPU, processing them and unloading them again, measured
+/// while profiling. Loading and unloading include the copies between buffers and images.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComputeStats {
+    pub load_ns: u64,
+    pub process_ns: u64,
+    pub unload_ns: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComputeStatsReport {
+    pub profiling: bool,
+    pub total: ComputeStats,
+    /// By node Id.
+    pub nodes: BTreeMap<String, ComputeStats>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ComputeStage {
+    Load,
+    Process,
+    Unload,
+}
+
+#[derive(Debug, Default)]
+struct ComputeStatsCounter {
+    load_ns: AtomicU64,
+    process_ns: AtomicU64,
+    unload_ns: AtomicU64,
+}
+
+impl ComputeStatsCounter {
+    fn add(&self, stage: ComputeStage, nanos: u64) {
+        let counter = match stage {
+            ComputeStage::Load => &self.load_ns,
+            ComputeStage::Process => &self.process_ns,
+            ComputeStage::Unload => &self.unload_ns,
+        };
+        counter.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> ComputeStats {
+        ComputeStats {
+            load_ns: self.load_ns.load(Ordering::Relaxed),
+            process_ns: self.process_ns.load(Ordering::Relaxed),
+            unload_ns: self.unload_ns.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BufferPoolUsage {
+    pub buffers: usize,
+    pub in_use: usize,
+    /// Approximate device memory held by the pool's images.
+    pub bytes: usize,
+    /// `0` for no limit.
+    pub max_buffers: usize,
+}
+
+/// Where a new buffer is placed in the pool.
+#[derive(Debug, PartialEq, Eq)]
+enum BufferSlot {
+    Push,
+    /// Replaces the least recently used available buffer, as the pool has reached its limit.
+    Replace(usize),
+    /// The pool has reached its limit and every buffer is in use.
+    Full,
+}
+
+/// `buffers` gives when each buffer in the pool was last used, `None` for buffers in use.
+fn new_buffer_slot(
+    buffers: impl ExactSizeIterator<Item = Option<u64>>,
+    max_buffers: usize,
+) -> BufferSlot {
+    if max_buffers == 0 || buffers.len() < max_buffers {
+        return BufferSlot::Push;
+    }
+
+    buffers
+        .enumerate()
+        .filter_map(|(index, last_used)| last_used.map(|last_used| (last_used, index)))
+        .min()
+        .map_or(BufferSlot::Full, |(_, index)| BufferSlot::Replace(index))
+}
+
+/// A locked resource of the compute context that has not been shut down.
+struct ResourceGuard<'a, T>(std::sync::MutexGuard<'a, Option<T>>);
+
+impl<T> Deref for ResourceGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref().unwrap()
+    }
+}
+
+/// Builds `source` and creates `kernel_name` from it. The build log is kept whether the build fails
+/// or the program builds but the kernel can't be created from it, e.g. if the program only built for
+/// some of the devices.
+fn build_kernel(
+    context: &opencl3::context::Context,
+    source: &str,
+    kernel_name: &str,
+) -> Result<opencl3::kernel::Kernel, BuildError> {
+    let mut program =
+        opencl3::program::Program::create_from_source(context, source).map_err(|error| {
+            BuildError {
+                error,
+                log: String::new(),
+            }
+        })?;
+    let built = program.build(context.devices(), "");
+    let log = build_log(context, &program);
+    if let Err(error) = built {
+        return Err(BuildError { error, log });
+    }
+
+    opencl3::kernel::Kernel::create(&program, kernel_name)
+        .map_err(|error| BuildError { error, log })
+}
+
+/// The build log of each device that logged something, in the order of the devices.
+fn build_log(context: &opencl3::context::Context, program: &opencl3::program::Program) -> String {
+    context
+        .devices()
+        .iter()
+        .filter_map(|device| program.get_build_log(*device).ok())
+        .map(|log| {
+            log.trim_matches(|c: char| c.is_whitespace() || c == '\0')
+                .to_string()
+        })
+        .filter(|log| !log.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn lock_resource<T>(
+    resource: &std::sync::Mutex<Option<T>>,
+) -> Result<ResourceGuard<'_, T>, ComputeError> {
+    let guard = resource.lock().unwrap();
+    if guard.is_none() {
+        return Err(ComputeError::ShutDown);
+    }
+
+    Ok(ResourceGuard(guard))
 }
 
 #[derive(Debug)]
 pub struct VideoBufferRef {
-    drop_event_tx: tokio::sync::mpsc::UnboundedSender<usize>,
+    drop_event_tx: tokio::sync::mpsc::UnboundedSender<(u64, usize)>,
+    /// Generation of the context the buffer was taken from.
+    generation: u64,
     video_buffer_index: usize,
 }
 impl VideoBufferRef {
-    fn new(drop_event_tx: tokio::sync::mpsc::UnboundedSender<usize>, index: usize) -> Self {
+    fn new(
+        drop_event_tx: tokio::sync::mpsc::UnboundedSender<(u64, usize)>,
+        generation: u64,
+        index: usize,
+    ) -> Self {
         Self {
             drop_event_tx,
+            generation,
             video_buffer_index: index,
         }
     }
@@ -423,7 +1332,9 @@ impl VideoBufferRef {
 
 impl Drop for VideoBufferRef {
     fn drop(&mut self) {
-        self.drop_event_tx.send(self.video_buffer_index).ok(); // Nothing we can do if this fails!
+        self.drop_event_tx
+            .send((self.generation, self.video_buffer_index))
+            .ok(); // Nothing we can do if this fails!
     }
 }
 
@@ -432,44 +1343,149 @@ struct VideoBuffer {
     buffer: opencl3::memory::Image,
     width: usize,
     height: usize,
+    last_used: u64,
+    /// The process shader that last wrote to the buffer, if it was not waited for.
+    write_event: Option<Arc<opencl3::event::Event>>,
 }
 
 impl VideoBuffer {
-    fn new(buffer: opencl3::memory::Image, width: usize, height: usize) -> Self {
+    /// Buffers are created for a frame, they are in use until the frame is dropped.
+    fn new(buffer: opencl3::memory::Image, width: usize, height: usize, last_used: u64) -> Self {
         Self {
-            available: true,
+            available: false,
             buffer,
             width,
             height,
+            last_used,
+            write_event: None,
         }
     }
+
+    /// RGBA with 32 bit floats per channel.
+    fn num_bytes(&self) -> usize {
+        self.width * self.height * 16
+    }
+}
+
+/// How long a process shader waits for frames to return their buffers to a full pool before the
+/// shader is skipped.
+const BUFFER_POOL_WAIT: Duration = Duration::from_millis(40);
+/// How often a full pool is retried while waiting for a buffer.
+const BUFFER_POOL_RETRY: Duration = Duration::from_millis(2);
+
+/// Calls `attempt` until it does not fail with [`ComputeError::BufferPoolFull`] or `timeout` has
+/// passed, sleeping for `retry` between attempts. Blocks the calling thread.
+fn wait_for_buffer_pool<T>(
+    timeout: Duration,
+    retry: Duration,
+    mut attempt: impl FnMut() -> Result<T, ComputeError>,
+) -> Result<T, ComputeError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match attempt() {
+            Err(ComputeError::BufferPoolFull(_)) if Instant::now() < deadline => {
+                std::thread::sleep(retry)
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The frames returned by a process shader that was skipped, one for each of the `outputs` by
+/// size. Each output repeats the frame of the previous run if it had the same size, otherwise the
+/// first video frame input takes its place, or a frame from `fallback` without an input.
+fn skipped_shader_outputs<T: Clone, E>(
+    first_input: Option<&T>,
+    previous_outputs: &[((usize, usize), T)],
+    outputs: &[(usize, usize)],
+    mut fallback: impl FnMut(usize, usize) -> Result<T, E>,
+) -> Result<Vec<T>, E> {
+    outputs
+        .iter()
+        .enumerate()
+        .map(|(index, size)| match previous_outputs.get(index) {
+            Some((previous_size, previous)) if previous_size == size => Ok(previous.clone()),
+            _ => match first_input {
+                Some(input) => Ok(input.clone()),
+                None => fallback(size.0, size.1),
+            },
+        })
+        .collect()
 }
 
 pub struct ProcessShaderImpl {
     context: PhaneronComputeContext,
     kernel: opencl3::kernel::Kernel,
+    /// The frames of the last run by size, repeated if the shader has to be skipped.
+    previous_outputs: std::sync::Mutex<Vec<((usize, usize), phaneron_plugin::types::VideoFrame)>>,
 }
 impl ProcessShaderImpl {
     fn new(context: PhaneronComputeContext, kernel: opencl3::kernel::Kernel) -> Self {
-        Self { context, kernel }
+        Self {
+            context,
+            kernel,
+            previous_outputs: Default::default(),
+        }
     }
-}
-impl phaneron_plugin::traits::ProcessShader for ProcessShaderImpl {
-    fn run(
+
+    /// Returns a frame for each output of a run that could not create its outputs, see
+    /// [`skipped_shader_outputs`].
+    fn skip_run(
+        &self,
+        first_input: Option<&phaneron_plugin::types::VideoFrame>,
+        outputs: &[(usize, usize)],
+    ) -> RVec<phaneron_plugin::types::VideoFrame> {
+        let previous_outputs = self.previous_outputs.lock().unwrap();
+        skipped_shader_outputs(
+            first_input,
+            previous_outputs.as_slice(),
+            outputs,
+            |width, height| {
+                let frame = self.context.create_skipped_shader_frame(width, height)?;
+                Ok(RArc::new(VideoFrame_TO::from_value(frame, TD_Opaque)))
+            },
+        )
+        .unwrap_or_else(|err| {
+            self.context
+                .fail_frame("Failed to create frame for skipped shader", err)
+        })
+        .into()
+    }
+
+    fn run_kernel(
         &self,
         params: ShaderParams,
         global_work_size: &[usize; 2],
+        local_work_size: Option<&[usize; 2]>,
     ) -> RVec<phaneron_plugin::types::VideoFrame> {
-        let mut output_frames: Vec<phaneron_plugin::types::VideoFrame> = vec![];
+        let mut output_frames: Vec<VideoFrame> = vec![];
+        // Outputs carry the metadata of the first video frame input
+        let mut metadata: Option<FrameMetadata> = None;
         let mut execute_kernel = opencl3::kernel::ExecuteKernel::new(&self.kernel);
+        // Array parameters are uploaded for this run only, released once the kernel has completed
+        let mut array_buffers: Vec<opencl3::memory::Buffer<f32>> = vec![];
+        let mut inputs: Vec<usize> = vec![];
+        let mut outputs: Vec<usize> = vec![];
+        let mut first_input: Option<phaneron_plugin::types::VideoFrame> = None;
+        let output_sizes: Vec<(usize, usize)> = params
+            .get_params()
+            .iter()
+            .filter_map(|param| match param {
+                ShaderParam::VideoFrameOutput { width, height } => Some((*width, *height)),
+                _ => None,
+            })
+            .collect();
 
         for params in params.get_params() {
             match params {
                 ShaderParam::VideoFrameInput(video_frame) => {
+                    metadata.get_or_insert_with(|| video_frame.metadata().clone());
+                    first_input.get_or_insert_with(|| video_frame.clone());
                     let buffers = self.context.inner.video_buffers.lock().unwrap(); // TODO: Nasty reaching into context
                     let buffer = buffers.get(video_frame.buffer_index()).unwrap();
                     let image: &opencl3::memory::Image = &buffer.buffer;
                     unsafe { execute_kernel.set_arg(image) };
+                    inputs.push(video_frame.buffer_index());
                 }
                 ShaderParam::U32Input(val) => {
                     unsafe { execute_kernel.set_arg(val) };
@@ -477,6 +1493,30 @@ impl phaneron_plugin::traits::ProcessShader for ProcessShaderImpl {
                 ShaderParam::F32Input(val) => {
                     unsafe { execute_kernel.set_arg(val) };
                 }
+                ShaderParam::I32Input(val) => {
+                    unsafe { execute_kernel.set_arg(val) };
+                }
+                // OpenCL vector arguments are passed as their packed components, so arrays of
+                // floats have the size of float2 and float4
+                ShaderParam::Vec2Input(val) => {
+                    unsafe { execute_kernel.set_arg(val) };
+                }
+                ShaderParam::Vec4Input(val) => {
+                    unsafe { execute_kernel.set_arg(val) };
+                }
+                ShaderParam::F32ArrayInput(vals) => {
+                    // OpenCL buffers can't be empty
+                    let vals: &[f32] = if vals.is_empty() { &[0.0] } else { vals };
+                    let buffer = self
+                        .context
+                        .create_loadsave_params_buffer(vals)
+                        .unwrap_or_else(|err| {
+                            self.context
+                                .fail_frame("Failed to create shader array", err)
+                        });
+                    unsafe { execute_kernel.set_arg(&buffer) };
+                    array_buffers.push(buffer);
+                }
                 ShaderParam::Bool(val) => {
                     if *val {
                         unsafe {
@@ -489,27 +1529,101 @@ impl phaneron_plugin::traits::ProcessShader for ProcessShaderImpl {
                     }
                 }
                 ShaderParam::VideoFrameOutput { width, height } => {
-                    let image_ref = self.context.create_image(*width, *height);
+                    // Buffers return to a full pool as frames are dropped, so the frame is only
+                    // skipped if none come back in time
+                    let image_ref =
+                        match wait_for_buffer_pool(BUFFER_POOL_WAIT, BUFFER_POOL_RETRY, || {
+                            self.context.create_image(*width, *height)
+                        }) {
+                            Ok(image_ref) => image_ref,
+                            Err(err) => {
+                                error!(
+                                "Skipping process shader as its output could not be created: {err}"
+                            );
+                                self.context.report_error(&err);
+                                return self.skip_run(first_input.as_ref(), &output_sizes);
+                            }
+                        };
                     let image_index = image_ref.video_buffer_index;
                     let buffers = self.context.inner.video_buffers.lock().unwrap(); // TODO: Nasty!
                     let buffer = buffers.get(image_index).unwrap();
                     let image: &opencl3::memory::Image = &buffer.buffer;
                     unsafe { execute_kernel.set_arg(image) };
+                    outputs.push(image_index);
 
-                    let frame =
-                        VideoFrame::new(VideoFrameId::default(), image_ref, *width, *height);
-                    output_frames.push(RArc::new(VideoFrame_TO::from_value(frame, TD_Opaque)))
+                    output_frames.push(VideoFrame::new(
+                        VideoFrameId::default(),
+                        image_ref,
+                        *width,
+                        *height,
+                    ));
                 }
             }
         }
 
         execute_kernel.set_global_work_sizes(global_work_size);
-        self.context.run_process_shader(execute_kernel);
+        if let Some(local_work_size) = local_work_size {
+            if divides_work_size(global_work_size, local_work_size) {
+                execute_kernel.set_local_work_sizes(local_work_size);
+            } else {
+                warn!(
+                    "Local work size {local_work_size:?} does not divide global work size {global_work_size:?}, ignoring it"
+                );
+            }
+        }
+        // The outputs are still returned so that the node can carry on, their content is undefined
+        if let Err(err) = self
+            .context
+            .run_process_shader(execute_kernel, &inputs, &outputs)
+        {
+            error!("Failed to run process shader: {err}");
+            self.context.report_error(&err);
+        }
+        // OpenCL keeps buffers alive until the kernels using them have completed
+        drop(array_buffers);
+
+        let output_frames: RVec<phaneron_plugin::types::VideoFrame> = output_frames
+            .into_iter()
+            .map(|mut frame| {
+                frame.set_metadata(metadata.clone().unwrap_or_default());
+                RArc::new(VideoFrame_TO::from_value(frame, TD_Opaque))
+            })
+            .collect();
+        *self.previous_outputs.lock().unwrap() = output_sizes
+            .into_iter()
+            .zip(output_frames.iter().cloned())
+            .collect();
+
+        output_frames
+    }
+}
+impl phaneron_plugin::traits::ProcessShader for ProcessShaderImpl {
+    fn run(
+        &self,
+        params: ShaderParams,
+        global_work_size: &[usize; 2],
+    ) -> RVec<phaneron_plugin::types::VideoFrame> {
+        self.run_kernel(params, global_work_size, None)
+    }
 
-        output_frames.into()
+    fn run_with_local_size(
+        &self,
+        params: ShaderParams,
+        global_work_size: &[usize; 2],
+        local_work_size: &[usize; 2],
+    ) -> RVec<phaneron_plugin::types::VideoFrame> {
+        self.run_kernel(params, global_work_size, Some(local_work_size))
     }
 }
 
+/// OpenCL 1.2 requires each global work size to be a multiple of the local work size.
+fn divides_work_size(global_work_size: &[usize; 2], local_work_size: &[usize; 2]) -> bool {
+    global_work_size
+        .iter()
+        .zip(local_work_size.iter())
+        .all(|(global, local)| *local != 0 && global % local == 0)
+}
+
 // Safe to implement because:
 // - We create execute kernels when the shader is run
 // - Kernels can be safely shared
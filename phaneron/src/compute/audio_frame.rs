@@ -19,6 +19,7 @@
 use std::fmt::{Debug, Display};
 
 use abi_stable::std_types::RVec;
+use phaneron_plugin::AudioChannelLayout;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct AudioBufferId(String);
@@ -29,7 +30,7 @@ impl AudioBufferId {
 }
 impl Default for AudioBufferId {
     fn default() -> Self {
-        Self(uuid::Uuid::new_v4().to_string())
+        Self(phaneron_plugin::ids::new_id())
     }
 }
 impl Display for AudioBufferId {
@@ -47,7 +48,7 @@ impl AudioFrameId {
 }
 impl Default for AudioFrameId {
     fn default() -> Self {
-        Self(uuid::Uuid::new_v4().to_string())
+        Self(phaneron_plugin::ids::new_id())
     }
 }
 impl Display for AudioFrameId {
@@ -59,24 +60,48 @@ impl Display for AudioFrameId {
 #[derive(Debug, Clone)]
 pub struct AudioFrame {
     pub id: AudioFrameId,
+    pub channel_layout: AudioChannelLayout,
     pub audio_buffers: RVec<RVec<f32>>,
 }
 
 impl AudioFrame {
-    pub fn new(id: AudioFrameId, audio_buffers: Vec<Vec<f32>>) -> Self {
+    pub fn new(
+        id: AudioFrameId,
+        channel_layout: AudioChannelLayout,
+        audio_buffers: Vec<Vec<f32>>,
+    ) -> Self {
         let mut buffers = RVec::with_capacity(audio_buffers.len());
         for buffer in audio_buffers {
             buffers.push(buffer.into());
         }
         Self {
             id,
+            channel_layout,
             audio_buffers: buffers,
         }
     }
+
+    /// Creates a frame with one silent buffer per channel of `channel_layout`.
+    pub fn silence(
+        id: AudioFrameId,
+        channel_layout: AudioChannelLayout,
+        num_samples: usize,
+    ) -> Self {
+        let num_channels = channel_layout.num_channels();
+        Self::new(
+            id,
+            channel_layout,
+            vec![vec![0f32; num_samples]; num_channels],
+        )
+    }
 }
 
 impl phaneron_plugin::traits::AudioFrame for AudioFrame {
     fn buffers(&self) -> &RVec<RVec<f32>> {
         &self.audio_buffers
     }
+
+    fn channel_layout(&self) -> AudioChannelLayout {
+        self.channel_layout
+    }
 }
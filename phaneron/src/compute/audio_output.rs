@@ -18,7 +18,8 @@
 
 use std::sync::Arc;
 
-use phaneron_plugin::AudioOutputId;
+use abi_stable::std_types::{RErr, ROk, RResult};
+use phaneron_plugin::{traits::PushFrameError, AudioOutputId};
 
 use crate::channel::{Channel, ChannelSemaphore, ChannelSemaphoreProvider};
 
@@ -45,8 +46,11 @@ impl phaneron_plugin::traits::AudioOutput for AudioOutput {
         &self,
         context: &phaneron_plugin::types::FrameContext,
         frame: phaneron_plugin::types::AudioFrame,
-    ) {
-        self.inner.channel.send(&self.semaphore_provider, frame);
+    ) -> RResult<(), PushFrameError> {
+        match self.inner.channel.send(&self.semaphore_provider, frame) {
+            0 => RErr(PushFrameError::NoReceivers),
+            _ => ROk(()),
+        }
     }
 }
 
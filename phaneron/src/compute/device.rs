@@ -0,0 +1,166 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use opencl3::{
+    device::{Device, CL_DEVICE_TYPE_ALL, CL_DEVICE_TYPE_CPU, CL_DEVICE_TYPE_GPU},
+    error_codes::{ClError, CL_DEVICE_NOT_FOUND},
+    types::cl_device_type,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+/// Which OpenCL device the compute context runs on.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComputeDeviceSelection {
+    /// The first GPU, or the first device of any type if there is no GPU.
+    #[default]
+    PreferGpu,
+    /// The first GPU, fails if there is no GPU.
+    RequireGpu,
+    /// The first CPU device.
+    Cpu,
+    /// A device by its index in the order devices of all types are reported by OpenCL.
+    ByIndex(usize),
+    /// The first device whose name contains the string, ignoring case.
+    ByNameSubstring(String),
+}
+
+impl ComputeDeviceSelection {
+    /// `prefer_gpu`, `require_gpu` and `cpu` select by type, a number selects by index and
+    /// anything else by name.
+    pub fn from_env_value(value: &str) -> Self {
+        if let Ok(index) = value.parse() {
+            return Self::ByIndex(index);
+        }
+        match value.to_lowercase().as_str() {
+            "prefer_gpu" => Self::PreferGpu,
+            "require_gpu" => Self::RequireGpu,
+            "cpu" => Self::Cpu,
+            _ => Self::ByNameSubstring(value.to_string()),
+        }
+    }
+
+    /// Index of the selected device in `devices`, `None` if no device matches.
+    pub fn select(&self, devices: &[DeviceDescription]) -> Option<usize> {
+        let first_of_kind = |kind| devices.iter().position(|device| device.kind == kind);
+        match self {
+            Self::PreferGpu => {
+                first_of_kind(DeviceKind::Gpu).or((!devices.is_empty()).then_some(0))
+            }
+            Self::RequireGpu => first_of_kind(DeviceKind::Gpu),
+            Self::Cpu => first_of_kind(DeviceKind::Cpu),
+            Self::ByIndex(index) => (*index < devices.len()).then_some(*index),
+            Self::ByNameSubstring(name) => {
+                let name = name.to_lowercase();
+                devices
+                    .iter()
+                    .position(|device| device.name.to_lowercase().contains(&name))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeviceKind {
+    Gpu,
+    Cpu,
+    Other,
+}
+
+impl From<cl_device_type> for DeviceKind {
+    fn from(device_type: cl_device_type) -> Self {
+        if device_type & CL_DEVICE_TYPE_GPU != 0 {
+            Self::Gpu
+        } else if device_type & CL_DEVICE_TYPE_CPU != 0 {
+            Self::Cpu
+        } else {
+            Self::Other
+        }
+    }
+}
+
+/// The device a compute context runs on and its limits, for diagnosing which device was selected.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceInfo {
+    pub name: String,
+    pub vendor: String,
+    pub kind: DeviceKind,
+    pub global_mem_bytes: u64,
+    pub image2d_max_width: usize,
+    pub image2d_max_height: usize,
+    /// Space separated, as reported by OpenCL.
+    pub extensions: String,
+}
+
+impl DeviceInfo {
+    pub fn from_device(device: &Device) -> Result<Self, ClError> {
+        Ok(Self {
+            name: device.name()?,
+            vendor: device.vendor()?,
+            kind: device.dev_type()?.into(),
+            global_mem_bytes: device.global_mem_size()?,
+            image2d_max_width: device.image2d_max_width()?,
+            image2d_max_height: device.image2d_max_height()?,
+            extensions: device.extensions()?,
+        })
+    }
+
+    /// Whether the device can create 2D images of the given size.
+    pub fn supports_image_size(&self, width: usize, height: usize) -> bool {
+        width <= self.image2d_max_width && height <= self.image2d_max_height
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceDescription {
+    pub name: String,
+    pub kind: DeviceKind,
+}
+
+/// Finds the device to run on among the devices of every OpenCL platform.
+pub fn find_device(selection: &ComputeDeviceSelection) -> Result<Device, ClError> {
+    let device_ids = opencl3::device::get_all_devices(CL_DEVICE_TYPE_ALL)?;
+    let mut devices = Vec::with_capacity(device_ids.len());
+    for (index, device_id) in device_ids.iter().enumerate() {
+        let device = Device::new(*device_id);
+        let description = DeviceDescription {
+            name: device.name()?,
+            kind: device.dev_type()?.into(),
+        };
+        debug!(
+            "OpenCL device {index}: {} ({:?})",
+            description.name, description.kind
+        );
+        devices.push(description);
+    }
+
+    let index = selection
+        .select(&devices)
+        .ok_or(ClError(CL_DEVICE_NOT_FOUND))?;
+    let device = Device::new(device_ids[index]);
+    info!(
+        "Using OpenCL device {index}: {} with {} compute units",
+        devices[index].name,
+        device.max_compute_units()?
+    );
+
+    Ok(device)
+}
@@ -0,0 +1,101 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::ffi::c_void;
+
+use opencl3::{
+    error_codes::ClError,
+    types::{cl_event, cl_int},
+};
+use serde::Deserialize;
+
+/// How the compute context waits for work on the GPU to complete.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuSyncMode {
+    /// Waits on events with clWaitForEvents and blocking reads.
+    /// Some drivers spin the calling thread until the GPU is done.
+    #[default]
+    Blocking,
+    /// Waits on events using completion callbacks, the waiting thread is parked (or the
+    /// waiting task suspended) until the driver signals that the work is complete.
+    /// Process shaders do not wait for completion, ordering is guaranteed by the
+    /// in-order process queue and the events attached to consumed frames.
+    Fence,
+}
+
+impl GpuSyncMode {
+    pub fn from_env_value(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "blocking" => Some(Self::Blocking),
+            "fence" => Some(Self::Fence),
+            _ => None,
+        }
+    }
+}
+
+/// Resolves once the OpenCL event it was created from has completed.
+pub struct GpuFence {
+    // Held so that the event outlives the callback registered against it.
+    _event: opencl3::event::Event,
+    receiver: tokio::sync::oneshot::Receiver<cl_int>,
+}
+
+impl GpuFence {
+    pub fn new(event: opencl3::event::Event) -> Result<Self, ClError> {
+        let (sender, receiver) = tokio::sync::oneshot::channel::<cl_int>();
+        let user_data = Box::into_raw(Box::new(sender)) as *mut c_void;
+        if let Err(err) = event.set_callback(opencl3::event::CL_COMPLETE, fence_complete, user_data)
+        {
+            // The callback will never be called to free the sender
+            drop(unsafe { Box::from_raw(user_data as *mut tokio::sync::oneshot::Sender<cl_int>) });
+            return Err(err);
+        }
+
+        Ok(Self {
+            _event: event,
+            receiver,
+        })
+    }
+
+    /// Waits for completion from a thread outside of the async runtime.
+    pub fn wait(self) -> Result<(), cl_int> {
+        fence_status(self.receiver.blocking_recv())
+    }
+
+    /// Waits for completion from a task, which is suspended rather than blocking its thread.
+    pub async fn completed(self) -> Result<(), cl_int> {
+        fence_status(self.receiver.await)
+    }
+}
+
+fn fence_status(
+    status: Result<cl_int, tokio::sync::oneshot::error::RecvError>,
+) -> Result<(), cl_int> {
+    match status {
+        Ok(status) if status >= 0 => Ok(()),
+        Ok(status) => Err(status),
+        Err(_) => Err(opencl3::error_codes::CL_INVALID_EVENT),
+    }
+}
+
+// Called by the OpenCL runtime exactly once, either on completion or with a negative error status.
+extern "C" fn fence_complete(_event: cl_event, status: cl_int, user_data: *mut c_void) {
+    let sender = unsafe { Box::from_raw(user_data as *mut tokio::sync::oneshot::Sender<cl_int>) };
+    sender.send(status).ok();
+}
@@ -0,0 +1,290 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use opencl3::error_codes::{
+    ClError, CL_BUILD_PROGRAM_FAILURE, CL_DEVICE_NOT_AVAILABLE, CL_INVALID_COMMAND_QUEUE,
+    CL_INVALID_CONTEXT, CL_INVALID_KERNEL_NAME, CL_MEM_OBJECT_ALLOCATION_FAILURE,
+};
+
+use std::time::Duration;
+
+use super::{
+    check_image_size,
+    device::{ComputeDeviceSelection, DeviceDescription, DeviceInfo, DeviceKind},
+    new_buffer_slot, skipped_shader_outputs, wait_for_buffer_pool, BufferSlot, BuildError,
+    ComputeError, ComputeStage, ComputeStats, ComputeStatsCounter,
+};
+
+#[test]
+fn device_loss_is_recognised() {
+    for code in [
+        CL_DEVICE_NOT_AVAILABLE,
+        CL_INVALID_CONTEXT,
+        CL_INVALID_COMMAND_QUEUE,
+    ] {
+        assert!(ComputeError::OpenCl(ClError(code)).is_device_lost());
+    }
+
+    assert!(!ComputeError::OpenCl(ClError(CL_MEM_OBJECT_ALLOCATION_FAILURE)).is_device_lost());
+    assert!(!ComputeError::ShutDown.is_device_lost());
+    assert!(!ComputeError::ShaderCompilationFailed(BuildError {
+        error: ClError(CL_BUILD_PROGRAM_FAILURE),
+        log: "error".to_string(),
+    })
+    .is_device_lost());
+}
+
+#[test]
+fn build_error_includes_log() {
+    let err = ComputeError::ShaderCompilationFailed(BuildError {
+        error: ClError(CL_BUILD_PROGRAM_FAILURE),
+        log: "<source>:3:5: error: use of undeclared identifier 'x'".to_string(),
+    });
+    assert_eq!(
+        err.to_string(),
+        "Failed to compile shader: OpenCL error -11: CL_BUILD_PROGRAM_FAILURE\n\
+         <source>:3:5: error: use of undeclared identifier 'x'"
+    );
+
+    let err = BuildError {
+        error: ClError(CL_INVALID_KERNEL_NAME),
+        log: String::new(),
+    };
+    assert_eq!(err.to_string(), "OpenCL error -46: CL_INVALID_KERNEL_NAME");
+}
+
+#[test]
+fn unlimited_pool_grows() {
+    let in_use = vec![None; 100];
+    assert_eq!(new_buffer_slot(in_use.into_iter(), 0), BufferSlot::Push);
+}
+
+#[test]
+fn full_pool_replaces_least_recently_used_available_buffer() {
+    let buffers = vec![None, Some(7), None, Some(3), Some(5)];
+    assert_eq!(
+        new_buffer_slot(buffers.clone().into_iter(), 6),
+        BufferSlot::Push
+    );
+    assert_eq!(
+        new_buffer_slot(buffers.into_iter(), 5),
+        BufferSlot::Replace(3)
+    );
+}
+
+#[test]
+fn full_pool_with_every_buffer_in_use_is_an_error() {
+    let in_use = vec![None; 4];
+    assert_eq!(new_buffer_slot(in_use.into_iter(), 4), BufferSlot::Full);
+    assert!(!ComputeError::BufferPoolFull(4).is_device_lost());
+}
+
+#[test]
+fn full_pool_is_retried_until_a_buffer_returns() {
+    let mut attempts = 0;
+    let result = wait_for_buffer_pool(Duration::from_secs(5), Duration::ZERO, || {
+        attempts += 1;
+        if attempts < 3 {
+            Err(ComputeError::BufferPoolFull(4))
+        } else {
+            Ok(attempts)
+        }
+    });
+    assert!(matches!(result, Ok(3)));
+}
+
+#[test]
+fn full_pool_is_an_error_once_the_wait_has_passed() {
+    let mut attempts = 0;
+    let result: Result<(), ComputeError> =
+        wait_for_buffer_pool(Duration::from_millis(10), Duration::from_millis(1), || {
+            attempts += 1;
+            Err(ComputeError::BufferPoolFull(4))
+        });
+    assert!(matches!(result, Err(ComputeError::BufferPoolFull(4))));
+    assert!(attempts > 1);
+}
+
+#[test]
+fn other_errors_are_not_retried() {
+    let mut attempts = 0;
+    let result: Result<(), ComputeError> =
+        wait_for_buffer_pool(Duration::from_secs(5), Duration::ZERO, || {
+            attempts += 1;
+            Err(ComputeError::OpenCl(ClError(
+                CL_MEM_OBJECT_ALLOCATION_FAILURE,
+            )))
+        });
+    assert!(matches!(result, Err(ComputeError::OpenCl(_))));
+    assert_eq!(attempts, 1);
+}
+
+#[test]
+fn skipped_shader_passes_its_input_through() {
+    let outputs =
+        skipped_shader_outputs(Some(&"input"), &[], &[(1920, 1080), (1280, 720)], |_, _| {
+            Err::<&str, _>(ComputeError::ShutDown)
+        });
+    assert_eq!(outputs.unwrap(), vec!["input", "input"]);
+}
+
+#[test]
+fn skipped_shader_without_inputs_returns_a_frame_per_output_when_the_pool_is_full() {
+    let mut created = vec![];
+    let outputs = skipped_shader_outputs(None, &[], &[(1920, 1080), (1280, 720)], |w, h| {
+        created.push((w, h));
+        Ok::<_, ComputeError>(format!("black {w}x{h}"))
+    });
+    assert_eq!(
+        outputs.unwrap(),
+        vec!["black 1920x1080".to_string(), "black 1280x720".to_string()]
+    );
+    assert_eq!(created, vec![(1920, 1080), (1280, 720)]);
+
+    let failed = skipped_shader_outputs::<&str, _>(None, &[], &[(1920, 1080)], |_, _| {
+        Err(ComputeError::BufferPoolFull(4))
+    });
+    assert!(matches!(failed, Err(ComputeError::BufferPoolFull(4))));
+}
+
+#[test]
+fn skipped_shader_repeats_previous_outputs_of_the_same_size() {
+    let previous = [((1920, 1080), "previous"), ((1920, 1080), "resized")];
+    let outputs = skipped_shader_outputs(None, &previous, &[(1920, 1080), (1280, 720)], |_, _| {
+        Ok::<_, ComputeError>("black")
+    });
+    assert_eq!(outputs.unwrap(), vec!["previous", "black"]);
+}
+
+#[test]
+fn missing_buffer_names_its_index() {
+    let err = ComputeError::InvalidBuffer(7);
+    assert!(!err.is_device_lost());
+    assert_eq!(err.to_string(), "Video buffer 7 is not in the buffer pool");
+}
+
+fn device(name: &str, kind: DeviceKind) -> DeviceDescription {
+    DeviceDescription {
+        name: name.to_string(),
+        kind,
+    }
+}
+
+#[test]
+fn image_size_is_checked_against_device_limits() {
+    let info = DeviceInfo {
+        name: "NVIDIA GeForce RTX 3080".to_string(),
+        vendor: "NVIDIA Corporation".to_string(),
+        kind: DeviceKind::Gpu,
+        global_mem_bytes: 10 << 30,
+        image2d_max_width: 16384,
+        image2d_max_height: 8192,
+        extensions: "cl_khr_global_int32_base_atomics cl_khr_fp64".to_string(),
+    };
+
+    assert!(info.supports_image_size(7680, 4320));
+    assert!(!info.supports_image_size(16385, 1080));
+    assert!(!info.supports_image_size(1920, 8193));
+
+    assert!(check_image_size(&info, 16384, 8192).is_ok());
+    let too_large = check_image_size(&info, 16385, 1080).unwrap_err();
+    assert!(matches!(
+        too_large,
+        ComputeError::ImageTooLarge(16385, 1080)
+    ));
+    assert_eq!(
+        too_large.to_string(),
+        "The device does not support 16385x1080 images"
+    );
+
+    let json = serde_json::to_value(&info).unwrap();
+    assert_eq!(json["image2dMaxWidth"], 16384);
+    assert_eq!(json["kind"], "gpu");
+}
+
+#[test]
+fn gpu_is_preferred_and_cpu_is_the_fallback() {
+    let with_gpu = [
+        device("Intel(R) Core(TM) i7", DeviceKind::Cpu),
+        device("NVIDIA GeForce RTX 3080", DeviceKind::Gpu),
+    ];
+    let cpu_only = [device("pthread-AMD Ryzen 7", DeviceKind::Cpu)];
+
+    assert_eq!(ComputeDeviceSelection::PreferGpu.select(&with_gpu), Some(1));
+    assert_eq!(ComputeDeviceSelection::PreferGpu.select(&cpu_only), Some(0));
+    assert_eq!(ComputeDeviceSelection::PreferGpu.select(&[]), None);
+    assert_eq!(ComputeDeviceSelection::RequireGpu.select(&cpu_only), None);
+    assert_eq!(ComputeDeviceSelection::Cpu.select(&with_gpu), Some(0));
+}
+
+#[test]
+fn devices_are_selected_by_index_or_name() {
+    let devices = [
+        device("Intel(R) Core(TM) i7", DeviceKind::Cpu),
+        device("NVIDIA GeForce RTX 3080", DeviceKind::Gpu),
+    ];
+
+    assert_eq!(ComputeDeviceSelection::ByIndex(1).select(&devices), Some(1));
+    assert_eq!(ComputeDeviceSelection::ByIndex(2).select(&devices), None);
+    assert_eq!(
+        ComputeDeviceSelection::ByNameSubstring("nvidia".to_string()).select(&devices),
+        Some(1)
+    );
+    assert_eq!(
+        ComputeDeviceSelection::ByNameSubstring("AMD".to_string()).select(&devices),
+        None
+    );
+}
+
+#[test]
+fn device_selection_is_parsed_from_the_environment() {
+    assert_eq!(
+        ComputeDeviceSelection::from_env_value("2"),
+        ComputeDeviceSelection::ByIndex(2)
+    );
+    assert_eq!(
+        ComputeDeviceSelection::from_env_value("CPU"),
+        ComputeDeviceSelection::Cpu
+    );
+    assert_eq!(
+        ComputeDeviceSelection::from_env_value("require_gpu"),
+        ComputeDeviceSelection::RequireGpu
+    );
+    assert_eq!(
+        ComputeDeviceSelection::from_env_value("Radeon"),
+        ComputeDeviceSelection::ByNameSubstring("Radeon".to_string())
+    );
+}
+
+#[test]
+fn stage_times_accumulate() {
+    let stats = ComputeStatsCounter::default();
+    stats.add(ComputeStage::Load, 100);
+    stats.add(ComputeStage::Process, 250);
+    stats.add(ComputeStage::Process, 50);
+    stats.add(ComputeStage::Unload, 25);
+
+    assert_eq!(
+        stats.snapshot(),
+        ComputeStats {
+            load_ns: 100,
+            process_ns: 300,
+            unload_ns: 25,
+        }
+    );
+}
@@ -21,7 +21,13 @@ use std::{
     sync::Arc,
 };
 
-use super::VideoBufferRef;
+use abi_stable::{sabi_trait::TD_Opaque, std_types::RArc};
+use phaneron_plugin::{traits::VideoFrame_TO, FrameMetadata};
+
+use super::{ComputeError, PhaneronComputeContext, VideoBufferRef};
+
+#[cfg(test)]
+mod tests;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct VideoBufferId(String);
@@ -32,7 +38,7 @@ impl VideoBufferId {
 }
 impl Default for VideoBufferId {
     fn default() -> Self {
-        Self(uuid::Uuid::new_v4().to_string())
+        Self(phaneron_plugin::ids::new_id())
     }
 }
 impl Display for VideoBufferId {
@@ -50,7 +56,7 @@ impl VideoFrameId {
 }
 impl Default for VideoFrameId {
     fn default() -> Self {
-        Self(uuid::Uuid::new_v4().to_string())
+        Self(phaneron_plugin::ids::new_id())
     }
 }
 impl Display for VideoFrameId {
@@ -65,6 +71,7 @@ pub struct VideoFrame {
     video_buffer_ref: Arc<VideoBufferRef>,
     width: usize,
     height: usize,
+    metadata: FrameMetadata,
 }
 
 impl VideoFrame {
@@ -79,9 +86,14 @@ impl VideoFrame {
             video_buffer_ref: Arc::new(video_buffer_ref),
             width,
             height,
+            metadata: FrameMetadata::default(),
         }
     }
 
+    pub fn set_metadata(&mut self, metadata: FrameMetadata) {
+        self.metadata = metadata;
+    }
+
     pub fn width(&self) -> usize {
         self.width
     }
@@ -89,6 +101,60 @@ impl VideoFrame {
     pub fn height(&self) -> usize {
         self.height
     }
+
+    /// Downloads the frame and returns a hash of its contents, see [`content_hash`].
+    pub fn content_hash(&self, context: &PhaneronComputeContext) -> Result<u64, ComputeError> {
+        let image = RArc::new(VideoFrame_TO::from_value(self.clone(), TD_Opaque));
+        let pixels = download_frame(context, &image)?;
+
+        Ok(content_hash(self.width, self.height, &pixels))
+    }
+}
+
+/// Downloads a frame from the GPU as floating-point RGBA pixels in the working colour space,
+/// linear light with BT.709 primaries.
+pub fn download_frame(
+    context: &PhaneronComputeContext,
+    frame: &phaneron_plugin::types::VideoFrame,
+) -> Result<Vec<f32>, ComputeError> {
+    const BYTES_PER_PIXEL: usize = 4 * std::mem::size_of::<f32>();
+    let total_bytes = frame.width() * frame.height() * BYTES_PER_PIXEL;
+    let buffer = context.create_buffer_from_image(
+        frame.width(),
+        frame.height(),
+        total_bytes,
+        frame.clone(),
+    )?;
+    let mut bytes = vec![0u8; total_bytes];
+    context.copy_frame_from_buffer(&buffer, &mut bytes, &[])?;
+
+    Ok(bytes
+        .chunks_exact(std::mem::size_of::<f32>())
+        .map(|value| f32::from_ne_bytes(value.try_into().unwrap()))
+        .collect())
+}
+
+/// Hashes a frame of floating-point RGBA pixels, for comparing rendered frames against golden values in tests.
+///
+/// Pixels are quantised to RGBA8 before hashing so that small differences in floating-point precision
+/// between devices don't change the hash. The hash is only stable for a fixed internal format and
+/// colour pipeline, any change to how frames are loaded or processed may change it.
+pub fn content_hash(width: usize, height: usize, pixels: &[f32]) -> u64 {
+    // FNV-1a, unlike the std hashers this is guaranteed to be the same across builds.
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    let dimensions = (width as u64)
+        .to_le_bytes()
+        .into_iter()
+        .chain((height as u64).to_le_bytes());
+    let rgba8 = pixels
+        .iter()
+        .map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8);
+
+    dimensions.chain(rgba8).fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(PRIME)
+    })
 }
 
 impl phaneron_plugin::traits::VideoFrame for VideoFrame {
@@ -103,4 +169,14 @@ impl phaneron_plugin::traits::VideoFrame for VideoFrame {
     fn height(&self) -> usize {
         self.height
     }
+
+    fn metadata(&self) -> &FrameMetadata {
+        &self.metadata
+    }
+
+    fn with_metadata(&self, metadata: FrameMetadata) -> phaneron_plugin::types::VideoFrame {
+        let mut frame = self.clone();
+        frame.metadata = metadata;
+        RArc::new(VideoFrame_TO::from_value(frame, TD_Opaque))
+    }
 }
@@ -0,0 +1,41 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::content_hash;
+
+fn solid_frame(width: usize, height: usize, colour: [f32; 4]) -> Vec<f32> {
+    colour.repeat(width * height)
+}
+
+#[test]
+fn solid_colour_frame_matches_golden_hash() {
+    let pixels = solid_frame(4, 2, [1.0, 0.5, 0.0, 1.0]);
+
+    assert_eq!(content_hash(4, 2, &pixels), 0x60592a37cccdea93);
+}
+
+#[test]
+fn hash_ignores_sub_quantisation_differences() {
+    let pixels = solid_frame(4, 2, [1.0, 0.5, 0.0, 1.0]);
+    let nearly = solid_frame(4, 2, [1.0, 0.5001, 0.0, 1.0]);
+    let different = solid_frame(4, 2, [1.0, 0.6, 0.0, 1.0]);
+
+    assert_eq!(content_hash(4, 2, &pixels), content_hash(4, 2, &nearly));
+    assert_ne!(content_hash(4, 2, &pixels), content_hash(4, 2, &different));
+    assert_ne!(content_hash(4, 2, &pixels), content_hash(2, 4, &pixels));
+}
@@ -16,12 +16,24 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use phaneron_plugin::VideoOutputId;
+use abi_stable::std_types::{RErr, ROk, RResult};
+use phaneron_plugin::{traits::PushFrameError, VideoOutputId};
+use serde::Serialize;
 
 use crate::channel::{Channel, ChannelSemaphore, ChannelSemaphoreProvider};
 
+/// Format of the frames a video output is producing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct VideoOutputFormat {
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Holds the format of the most recent frame pushed to a video output, `None` until a frame is pushed.
+pub type VideoFormatTap = Arc<Mutex<Option<VideoOutputFormat>>>;
+
 #[derive(Debug, Clone)]
 pub struct VideoOutput {
     semaphore_provider: ChannelSemaphoreProvider,
@@ -32,10 +44,11 @@ impl VideoOutput {
     pub fn new(
         semaphore_provider: ChannelSemaphoreProvider,
         channel: Channel<phaneron_plugin::types::VideoFrame>,
+        format_tap: VideoFormatTap,
     ) -> Self {
         Self {
             semaphore_provider,
-            inner: Arc::new(VideoOutputInner { channel }),
+            inner: Arc::new(VideoOutputInner::new(channel, format_tap)),
         }
     }
 }
@@ -45,14 +58,38 @@ impl phaneron_plugin::traits::VideoOutput for VideoOutput {
         &self,
         context: &phaneron_plugin::types::FrameContext,
         frame: phaneron_plugin::types::VideoFrame,
-    ) {
-        self.inner.channel.send(&self.semaphore_provider, frame);
+    ) -> RResult<(), PushFrameError> {
+        self.inner
+            .format_tap
+            .lock()
+            .unwrap()
+            .replace(VideoOutputFormat {
+                width: frame.width(),
+                height: frame.height(),
+            });
+        match self.inner.channel.send(&self.semaphore_provider, frame) {
+            0 => RErr(PushFrameError::NoReceivers),
+            _ => ROk(()),
+        }
     }
 }
 
 #[derive(Debug)]
 struct VideoOutputInner {
     channel: Channel<phaneron_plugin::types::VideoFrame>,
+    format_tap: VideoFormatTap,
+}
+
+impl VideoOutputInner {
+    fn new(
+        channel: Channel<phaneron_plugin::types::VideoFrame>,
+        format_tap: VideoFormatTap,
+    ) -> Self {
+        Self {
+            channel,
+            format_tap,
+        }
+    }
 }
 
 pub struct VideoPipe {
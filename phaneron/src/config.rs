@@ -0,0 +1,264 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    fs,
+    net::{Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    channel::MAX_FRAMES_AHEAD,
+    compute::{device::ComputeDeviceSelection, fence::GpuSyncMode},
+    plugins::{DevPluginManifest, PluginLoadType},
+};
+
+#[cfg(test)]
+mod tests;
+
+/// Startup configuration of the host, loaded from `phaneron.toml`.
+///
+/// Every value has a default so the file is optional, environment variables override
+/// values from the file so that containers can be configured without mounting a file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Address the API listens on.
+    pub bind_address: SocketAddr,
+    /// Filter used when `RUST_LOG` is not set.
+    pub log_level: String,
+    /// Path to the file listing the switcher's video inputs.
+    pub inputs_file: PathBuf,
+    /// Graph files exported with `GET /graphs/:graphId/export` that are imported on startup.
+    pub saved_graphs: Vec<PathBuf>,
+    pub plugins: PluginsConfig,
+    pub compute: ComputeConfig,
+    pub graphs: GraphsConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PluginsConfig {
+    /// Load plugins from the `target/` directory using the manifest instead of from `directory`.
+    pub develop: bool,
+    pub manifest: PathBuf,
+    pub directory: PathBuf,
+    /// Defaults to `phaneron-plugin-shaders` in development and `directory` otherwise.
+    pub shader_directory: Option<PathBuf>,
+    /// Reload shader plugins when the files in the shader directory change.
+    pub watch_shaders: bool,
+    /// Seconds to wait for a plugin to initialize a node before the node is abandoned.
+    pub initialize_timeout_secs: u64,
+    pub log_repeat_window_secs: LogRepeatWindows,
+}
+
+/// Seconds during which identical messages logged by a plugin at each level are collapsed
+/// into a single line followed by a count of the repeats, 0 logs every message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct LogRepeatWindows {
+    pub error: u64,
+    pub warn: u64,
+    pub info: u64,
+    pub debug: u64,
+    pub trace: u64,
+}
+
+/// How many frames a node may push ahead of the nodes consuming them, by graph mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct GraphsConfig {
+    pub live_max_frames_ahead: usize,
+    pub batch_max_frames_ahead: usize,
+    /// Frames queued for each node consuming an output before the node pushing to it blocks,
+    /// also the most frames a node may be ahead in either mode.
+    pub channel_capacity: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ComputeConfig {
+    /// Which OpenCL device to use.
+    pub device: ComputeDeviceSelection,
+    pub gpu_sync: GpuSyncMode,
+    /// How often to check whether the device has been lost, `0` only checks after frame errors.
+    pub device_check_interval_ms: u64,
+    /// Maximum number of video buffers kept in the pool, `0` for no limit.
+    pub max_video_buffers: usize,
+    /// Measures GPU time from startup, profiling can also be enabled and disabled through the API.
+    pub profiling: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind_address: SocketAddr::from((Ipv4Addr::UNSPECIFIED, 8080)),
+            log_level: "phaneron=info".to_string(),
+            inputs_file: PathBuf::from("video_inputs.json"),
+            saved_graphs: vec![],
+            plugins: Default::default(),
+            compute: Default::default(),
+            graphs: Default::default(),
+        }
+    }
+}
+
+impl Default for GraphsConfig {
+    fn default() -> Self {
+        Self {
+            live_max_frames_ahead: 1,
+            batch_max_frames_ahead: 4,
+            channel_capacity: MAX_FRAMES_AHEAD,
+        }
+    }
+}
+
+impl Default for ComputeConfig {
+    fn default() -> Self {
+        Self {
+            device: Default::default(),
+            gpu_sync: Default::default(),
+            device_check_interval_ms: 1000,
+            max_video_buffers: 0,
+            profiling: false,
+        }
+    }
+}
+
+impl Default for PluginsConfig {
+    fn default() -> Self {
+        Self {
+            develop: false,
+            manifest: PathBuf::from("plugins.toml"),
+            directory: PathBuf::from("plugins"),
+            shader_directory: None,
+            watch_shaders: false,
+            initialize_timeout_secs: 30,
+            log_repeat_window_secs: Default::default(),
+        }
+    }
+}
+
+impl Default for LogRepeatWindows {
+    fn default() -> Self {
+        Self {
+            error: 10,
+            warn: 10,
+            info: 10,
+            debug: 0,
+            trace: 0,
+        }
+    }
+}
+
+impl LogRepeatWindows {
+    pub fn window(&self, level: tracing::Level) -> Duration {
+        let secs = match level {
+            tracing::Level::ERROR => self.error,
+            tracing::Level::WARN => self.warn,
+            tracing::Level::INFO => self.info,
+            tracing::Level::DEBUG => self.debug,
+            _ => self.trace,
+        };
+        Duration::from_secs(secs)
+    }
+}
+
+impl Config {
+    /// Loads the config file at `path` if it exists, then applies overrides from the environment.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut config: Config = match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Config::default(),
+            Err(err) => return Err(err.into()),
+        };
+        config.apply_overrides(|name| std::env::var(name).ok())?;
+
+        Ok(config)
+    }
+
+    /// Overrides values using the environment variables that were used before the config file existed.
+    pub fn apply_overrides(&mut self, var: impl Fn(&str) -> Option<String>) -> anyhow::Result<()> {
+        if let Some(bind_address) = var("PHANERON_BIND_ADDRESS") {
+            self.bind_address = bind_address.parse()?;
+        }
+        if let Some(log_level) = var("RUST_LOG") {
+            self.log_level = log_level;
+        }
+        if let Some(inputs_file) = var("VIDEO_INPUTS_FILE") {
+            self.inputs_file = inputs_file.into();
+        }
+        if var("DEVELOP_PLUGINS").is_some() {
+            self.plugins.develop = true;
+        }
+        if let Some(manifest) = var("PLUGINS_CFG_FILE") {
+            self.plugins.manifest = manifest.into();
+        }
+        if let Some(directory) = var("PLUGINS_DIRECTORY") {
+            self.plugins.directory = directory.into();
+        }
+        if let Some(shader_directory) = var("SHADER_PLUGINS_DIR") {
+            self.plugins.shader_directory = Some(shader_directory.into());
+        }
+        if let Some(device) = var("COMPUTE_DEVICE") {
+            self.compute.device = ComputeDeviceSelection::from_env_value(&device);
+        }
+        if let Some(gpu_sync) = var("GPU_SYNC") {
+            self.compute.gpu_sync = GpuSyncMode::from_env_value(&gpu_sync).ok_or_else(|| {
+                anyhow::anyhow!("GPU_SYNC should be one of \"blocking\" or \"fence\"")
+            })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn plugin_load_type(&self) -> anyhow::Result<PluginLoadType> {
+        if self.plugins.develop {
+            let plugins = fs::read_to_string(&self.plugins.manifest)?;
+            let plugins: DevPluginManifest = toml::from_str(&plugins)?;
+            Ok(PluginLoadType::Development(plugins))
+        } else {
+            Ok(PluginLoadType::Production {
+                plugins_directory: self.plugins.directory.to_string_lossy().to_string(),
+            })
+        }
+    }
+
+    pub fn node_initialize_timeout(&self) -> Duration {
+        Duration::from_secs(self.plugins.initialize_timeout_secs)
+    }
+
+    /// `None` if the device is not checked.
+    pub fn device_check_interval(&self) -> Option<Duration> {
+        match self.compute.device_check_interval_ms {
+            0 => None,
+            interval => Some(Duration::from_millis(interval)),
+        }
+    }
+
+    pub fn shader_directory(&self) -> PathBuf {
+        match &self.plugins.shader_directory {
+            Some(shader_directory) => shader_directory.clone(),
+            None if self.plugins.develop => PathBuf::from("phaneron-plugin-shaders"),
+            None => self.plugins.directory.clone(),
+        }
+    }
+}
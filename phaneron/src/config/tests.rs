@@ -0,0 +1,111 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+use super::Config;
+use crate::compute::{device::ComputeDeviceSelection, fence::GpuSyncMode};
+
+#[test]
+fn missing_values_use_defaults() {
+    let config: Config = toml::from_str(
+        r#"
+        bind_address = "127.0.0.1:9000"
+
+        [compute]
+        gpu_sync = "fence"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.bind_address, "127.0.0.1:9000".parse().unwrap());
+    assert_eq!(config.compute.gpu_sync, GpuSyncMode::Fence);
+    assert_eq!(config.compute.device, ComputeDeviceSelection::PreferGpu);
+    assert_eq!(
+        config.device_check_interval(),
+        Some(Duration::from_millis(1000))
+    );
+    assert_eq!(config.log_level, "phaneron=info");
+    assert!(config.saved_graphs.is_empty());
+    assert_eq!(config.shader_directory(), PathBuf::from("plugins"));
+    assert_eq!(config.node_initialize_timeout(), Duration::from_secs(30));
+    assert_eq!(config.graphs.channel_capacity, 16);
+    assert_eq!(
+        config
+            .plugins
+            .log_repeat_window_secs
+            .window(tracing::Level::ERROR),
+        Duration::from_secs(10)
+    );
+}
+
+#[test]
+fn log_repeat_windows_are_set_per_level() {
+    let config: Config = toml::from_str(
+        r#"
+        [plugins.log_repeat_window_secs]
+        error = 60
+        debug = 5
+        "#,
+    )
+    .unwrap();
+    let windows = config.plugins.log_repeat_window_secs;
+
+    assert_eq!(
+        windows.window(tracing::Level::ERROR),
+        Duration::from_secs(60)
+    );
+    assert_eq!(
+        windows.window(tracing::Level::WARN),
+        Duration::from_secs(10)
+    );
+    assert_eq!(
+        windows.window(tracing::Level::DEBUG),
+        Duration::from_secs(5)
+    );
+    assert_eq!(windows.window(tracing::Level::TRACE), Duration::ZERO);
+}
+
+#[test]
+fn environment_overrides_file() {
+    let mut config: Config = toml::from_str(
+        r#"
+        log_level = "phaneron=debug"
+
+        [plugins]
+        directory = "/opt/phaneron/plugins"
+        "#,
+    )
+    .unwrap();
+    let env = HashMap::from([
+        ("RUST_LOG", "phaneron=trace"),
+        ("DEVELOP_PLUGINS", "true"),
+        ("COMPUTE_DEVICE", "1"),
+    ]);
+    config
+        .apply_overrides(|name| env.get(name).map(|value| value.to_string()))
+        .unwrap();
+
+    assert_eq!(config.log_level, "phaneron=trace");
+    assert!(config.plugins.develop);
+    assert_eq!(config.compute.device, ComputeDeviceSelection::ByIndex(1));
+    assert_eq!(
+        config.shader_directory(),
+        PathBuf::from("phaneron-plugin-shaders")
+    );
+}
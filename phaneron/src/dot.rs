@@ -0,0 +1,107 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::fmt::Write;
+
+#[cfg(test)]
+mod tests;
+
+/// The nodes and connections of a graph, used to render the graph as Graphviz DOT.
+#[derive(Debug, Default)]
+pub struct GraphTopology {
+    pub graph_id: String,
+    pub nodes: Vec<TopologyNode>,
+    pub connections: Vec<TopologyConnection>,
+}
+
+#[derive(Debug)]
+pub struct TopologyNode {
+    pub node_id: String,
+    pub name: Option<String>,
+    pub node_type: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TopologyConnectionType {
+    Video,
+    Audio,
+}
+
+#[derive(Debug)]
+pub struct TopologyConnection {
+    pub connection_type: TopologyConnectionType,
+    pub from_node_id: String,
+    pub from_output_index: usize,
+    pub to_node_id: String,
+    pub to_input_index: usize,
+}
+
+impl GraphTopology {
+    /// Nodes are drawn as boxes labelled with their name and type. Video connections are solid
+    /// and audio connections dashed, labelled with the output and input indices they connect.
+    pub fn to_dot(&self) -> String {
+        let mut nodes: Vec<&TopologyNode> = self.nodes.iter().collect();
+        nodes.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        let mut connections: Vec<&TopologyConnection> = self.connections.iter().collect();
+        connections.sort_by(|a, b| {
+            (&a.to_node_id, a.connection_type, a.to_input_index).cmp(&(
+                &b.to_node_id,
+                b.connection_type,
+                b.to_input_index,
+            ))
+        });
+
+        let mut dot = String::new();
+        writeln!(dot, "digraph \"{}\" {{", escape(&self.graph_id)).unwrap();
+        writeln!(dot, "    node [shape=box];").unwrap();
+        for node in nodes {
+            let label = match &node.name {
+                Some(name) => format!("{}\\n{}", escape(name), escape(&node.node_type)),
+                None => escape(&node.node_type),
+            };
+            writeln!(
+                dot,
+                "    \"{}\" [label=\"{label}\"];",
+                escape(&node.node_id)
+            )
+            .unwrap();
+        }
+        for connection in connections {
+            let (kind, style) = match connection.connection_type {
+                TopologyConnectionType::Video => ("video", "solid"),
+                TopologyConnectionType::Audio => ("audio", "dashed"),
+            };
+            writeln!(
+                dot,
+                "    \"{}\" -> \"{}\" [label=\"{kind} {} → {}\", style={style}];",
+                escape(&connection.from_node_id),
+                escape(&connection.to_node_id),
+                connection.from_output_index,
+                connection.to_input_index,
+            )
+            .unwrap();
+        }
+        writeln!(dot, "}}").unwrap();
+
+        dot
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
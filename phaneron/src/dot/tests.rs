@@ -0,0 +1,66 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::*;
+
+fn node(node_id: &str, name: Option<&str>, node_type: &str) -> TopologyNode {
+    TopologyNode {
+        node_id: node_id.to_string(),
+        name: name.map(|name| name.to_string()),
+        node_type: node_type.to_string(),
+    }
+}
+
+#[test]
+fn renders_nodes_and_connections() {
+    let topology = GraphTopology {
+        graph_id: "graph1".to_string(),
+        nodes: vec![
+            node("switcher", None, "traditional_mixer_emulator"),
+            node("input", Some("Clip \"A\""), "ffmpeg_producer"),
+        ],
+        connections: vec![
+            TopologyConnection {
+                connection_type: TopologyConnectionType::Audio,
+                from_node_id: "input".to_string(),
+                from_output_index: 0,
+                to_node_id: "switcher".to_string(),
+                to_input_index: 0,
+            },
+            TopologyConnection {
+                connection_type: TopologyConnectionType::Video,
+                from_node_id: "input".to_string(),
+                from_output_index: 0,
+                to_node_id: "switcher".to_string(),
+                to_input_index: 1,
+            },
+        ],
+    };
+
+    assert_eq!(
+        topology.to_dot(),
+        r#"digraph "graph1" {
+    node [shape=box];
+    "input" [label="Clip \"A\"\nffmpeg_producer"];
+    "switcher" [label="traditional_mixer_emulator"];
+    "input" -> "switcher" [label="video 0 → 1", style=solid];
+    "input" -> "switcher" [label="audio 0 → 0", style=dashed];
+}
+"#
+    );
+}
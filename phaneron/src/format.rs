@@ -23,6 +23,7 @@ use crate::io::{Packer, Unpacker};
 use self::{
     bgra::{BGRA8Reader, BGRA8Writer},
     rgba8::{RGBA8Reader, RGBA8Writer},
+    semi_planar::{SemiPlanarDepth, SemiPlanarReader, SemiPlanarWriter},
     v210::{V210Reader, V210Writer},
     yuv420p::{YUV420pReader, YUV420pWriter},
     yuv422p10::{YUV422p10Reader, YUV422p10Writer},
@@ -31,6 +32,7 @@ use self::{
 
 pub mod bgra;
 pub mod rgba8;
+pub mod semi_planar;
 pub mod v210;
 pub mod yuv420p;
 pub mod yuv422p10;
@@ -57,6 +59,14 @@ impl VideoFormat for phaneron_plugin::VideoFormat {
             phaneron_plugin::VideoFormat::YUV422p10 => {
                 Box::new(YUV422p10Reader::new(width, height))
             }
+            phaneron_plugin::VideoFormat::NV12 => {
+                Box::new(SemiPlanarReader::new(width, height, SemiPlanarDepth::Bits8))
+            }
+            phaneron_plugin::VideoFormat::P010 => Box::new(SemiPlanarReader::new(
+                width,
+                height,
+                SemiPlanarDepth::Bits10,
+            )),
         }
     }
 
@@ -85,6 +95,18 @@ impl VideoFormat for phaneron_plugin::VideoFormat {
             phaneron_plugin::VideoFormat::YUV422p10 => {
                 Box::new(YUV422p10Writer::new(width, height, interlace))
             }
+            phaneron_plugin::VideoFormat::NV12 => Box::new(SemiPlanarWriter::new(
+                width,
+                height,
+                interlace,
+                SemiPlanarDepth::Bits8,
+            )),
+            phaneron_plugin::VideoFormat::P010 => Box::new(SemiPlanarWriter::new(
+                width,
+                height,
+                interlace,
+                SemiPlanarDepth::Bits10,
+            )),
         }
     }
 }
@@ -0,0 +1,353 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use phaneron_plugin::InterlaceMode;
+
+use crate::{
+    compute::AsKernalParamU32,
+    io::{Packer, Unpacker},
+};
+
+#[cfg(test)]
+mod tests;
+
+const PIXELS_PER_WORK_ITEM: f32 = 64.0;
+
+/// The sample depth of a semi-planar 4:2:0 format, a luma plane followed by a plane of
+/// interleaved chroma at half the height.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SemiPlanarDepth {
+    /// NV12, a byte per sample.
+    Bits8,
+    /// P010, 10 bit samples in the high bits of 16 bit little-endian words.
+    Bits10,
+}
+
+impl SemiPlanarDepth {
+    fn name(&self) -> &'static str {
+        match self {
+            SemiPlanarDepth::Bits8 => "NV12",
+            SemiPlanarDepth::Bits10 => "P010",
+        }
+    }
+
+    fn bytes_per_sample(&self) -> usize {
+        match self {
+            SemiPlanarDepth::Bits8 => 1,
+            SemiPlanarDepth::Bits10 => 2,
+        }
+    }
+
+    fn num_bits(&self) -> usize {
+        match self {
+            SemiPlanarDepth::Bits8 => 8,
+            SemiPlanarDepth::Bits10 => 10,
+        }
+    }
+
+    fn luma_black(&self) -> f32 {
+        match self {
+            SemiPlanarDepth::Bits8 => 16.0,
+            SemiPlanarDepth::Bits10 => 64.0,
+        }
+    }
+
+    fn luma_white(&self) -> f32 {
+        match self {
+            SemiPlanarDepth::Bits8 => 235.0,
+            SemiPlanarDepth::Bits10 => 940.0,
+        }
+    }
+
+    fn chroma_range(&self) -> f32 {
+        match self {
+            SemiPlanarDepth::Bits8 => 224.0,
+            SemiPlanarDepth::Bits10 => 896.0,
+        }
+    }
+
+    /// The defines that select the sample type of the semi-planar shaders.
+    fn kernel_defines(&self) -> &'static str {
+        match self {
+            SemiPlanarDepth::Bits8 => {
+                "#define SAMPLE3 uchar3\n#define SAMPLE4 uchar4\n#define SAMPLE8 uchar8\n\
+                 #define SAMPLE_SHIFT 0\n#define SAMPLE_BLACK 16\n#define SAMPLE_MID 128\n"
+            }
+            SemiPlanarDepth::Bits10 => {
+                "#define SAMPLE3 ushort3\n#define SAMPLE4 ushort4\n#define SAMPLE8 ushort8\n\
+                 #define SAMPLE_SHIFT 6\n#define SAMPLE_BLACK 64\n#define SAMPLE_MID 512\n"
+            }
+        }
+    }
+}
+
+fn get_pitch(width: usize) -> usize {
+    width + 7 - ((width - 1) % 8)
+}
+
+fn get_pitch_bytes(width: usize, depth: SemiPlanarDepth) -> usize {
+    get_pitch(width) * depth.bytes_per_sample()
+}
+
+/// The size of the luma plane followed by the chroma plane.
+fn get_num_bytes(width: usize, height: usize, depth: SemiPlanarDepth) -> Vec<usize> {
+    let luma_bytes = get_pitch_bytes(width, depth) * height;
+    vec![luma_bytes, luma_bytes / 2]
+}
+
+pub struct SemiPlanarReader {
+    width: usize,
+    height: usize,
+    depth: SemiPlanarDepth,
+    name: String,
+    kernel: String,
+    num_bytes: Vec<usize>,
+    work_items_per_group: usize,
+    global_work_items: usize,
+}
+
+impl SemiPlanarReader {
+    pub fn new(width: usize, height: usize, depth: SemiPlanarDepth) -> Self {
+        let pitch = get_pitch(width) as f32;
+        let work_items_per_group = f32::ceil(pitch / PIXELS_PER_WORK_ITEM) as usize;
+        let global_work_items = (work_items_per_group * height) / 2;
+
+        Self {
+            width,
+            height,
+            depth,
+            name: format!("{} Reader", depth.name()),
+            kernel: [
+                depth.kernel_defines(),
+                include_str!("../../shaders/video_process/load/semi_planar.cl"),
+            ]
+            .concat(),
+            num_bytes: get_num_bytes(width, height, depth),
+            work_items_per_group,
+            global_work_items,
+        }
+    }
+}
+
+impl Packer for SemiPlanarReader {
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_kernel(&self) -> &str {
+        &self.kernel
+    }
+
+    fn get_width(&self) -> usize {
+        self.width
+    }
+
+    fn get_height(&self) -> usize {
+        self.height
+    }
+
+    fn get_num_bits(&self) -> usize {
+        self.depth.num_bits()
+    }
+
+    fn get_luma_black(&self) -> f32 {
+        self.depth.luma_black()
+    }
+
+    fn get_luma_white(&self) -> f32 {
+        self.depth.luma_white()
+    }
+
+    fn get_chroma_range(&self) -> f32 {
+        self.depth.chroma_range()
+    }
+
+    fn get_num_bytes(&self) -> Vec<usize> {
+        self.num_bytes.clone()
+    }
+
+    fn get_num_bytes_rgba(&self) -> usize {
+        self.width * self.height * 4 * 4
+    }
+
+    fn get_is_rgb(&self) -> bool {
+        false
+    }
+
+    fn get_total_bytes(&self) -> usize {
+        self.num_bytes.iter().sum()
+    }
+
+    fn get_work_items_per_group(&self) -> usize {
+        self.work_items_per_group
+    }
+
+    fn get_global_work_items(&self) -> usize {
+        self.global_work_items
+    }
+
+    fn get_kernel_params(
+        &self,
+        kernel: &mut opencl3::kernel::ExecuteKernel,
+        inputs: &[&opencl3::memory::Buffer<opencl3::types::cl_uchar>],
+        output: &mut opencl3::memory::Buffer<opencl3::types::cl_uchar>,
+    ) {
+        if inputs.len() != 2 {
+            panic!(
+                "Reader for {} requires exactly 2 inputs, received {}",
+                self.get_name(),
+                inputs.len()
+            );
+        }
+
+        let width = self.width as u32;
+
+        unsafe {
+            kernel
+                .set_arg(inputs[0])
+                .set_arg(inputs[1])
+                .set_arg(output)
+                .set_arg(&width)
+        };
+    }
+}
+
+pub struct SemiPlanarWriter {
+    width: usize,
+    height: usize,
+    depth: SemiPlanarDepth,
+    name: String,
+    kernel: String,
+    num_bytes: Vec<usize>,
+    interlace: InterlaceMode,
+    work_items_per_group: usize,
+    global_work_items: usize,
+}
+
+impl SemiPlanarWriter {
+    pub fn new(
+        width: usize,
+        height: usize,
+        interlace: InterlaceMode,
+        depth: SemiPlanarDepth,
+    ) -> Self {
+        let pitch = get_pitch(width) as f32;
+        let work_items_per_group = f32::ceil(pitch / PIXELS_PER_WORK_ITEM) as usize;
+        let global_work_items = (work_items_per_group * height) / 2;
+
+        Self {
+            width,
+            height,
+            depth,
+            name: format!("{} Writer", depth.name()),
+            kernel: [
+                depth.kernel_defines(),
+                include_str!("../../shaders/video_process/consume/semi_planar.cl"),
+            ]
+            .concat(),
+            num_bytes: get_num_bytes(width, height, depth),
+            interlace,
+            work_items_per_group,
+            global_work_items,
+        }
+    }
+}
+
+impl Unpacker for SemiPlanarWriter {
+    fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_kernel(&self) -> &str {
+        &self.kernel
+    }
+
+    fn get_width(&self) -> usize {
+        self.width
+    }
+
+    fn get_height(&self) -> usize {
+        self.height
+    }
+
+    fn get_num_bits(&self) -> usize {
+        self.depth.num_bits()
+    }
+
+    fn get_luma_black(&self) -> f32 {
+        self.depth.luma_black()
+    }
+
+    fn get_luma_white(&self) -> f32 {
+        self.depth.luma_white()
+    }
+
+    fn get_chroma_range(&self) -> f32 {
+        self.depth.chroma_range()
+    }
+
+    fn get_num_bytes(&self) -> Vec<usize> {
+        self.num_bytes.clone()
+    }
+
+    fn get_num_bytes_rgba(&self) -> usize {
+        self.width * self.height * 4 * 4
+    }
+
+    fn get_is_rgb(&self) -> bool {
+        false
+    }
+
+    fn get_total_bytes(&self) -> usize {
+        self.num_bytes.iter().sum()
+    }
+
+    fn get_work_items_per_group(&self) -> usize {
+        self.work_items_per_group
+    }
+
+    fn get_global_work_items(&self) -> usize {
+        self.global_work_items
+    }
+
+    fn get_kernel_params(
+        &self,
+        kernel: &mut opencl3::kernel::ExecuteKernel,
+        input: &opencl3::memory::Buffer<opencl3::types::cl_uchar>,
+        outputs: &mut Vec<opencl3::memory::Buffer<opencl3::types::cl_uchar>>,
+    ) {
+        if outputs.len() != 2 {
+            panic!(
+                "Reader for {} requires exactly 2 outputs, received {}",
+                self.get_name(),
+                outputs.len()
+            );
+        }
+
+        let width = self.width as u32;
+
+        unsafe {
+            kernel
+                .set_arg(input)
+                .set_arg(&outputs[0])
+                .set_arg(&outputs[1])
+                .set_arg(&width)
+                .set_arg(&self.interlace.as_kernel_param())
+        };
+    }
+}
@@ -0,0 +1,97 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use phaneron_plugin::InterlaceMode;
+
+use crate::io::{Packer, Unpacker};
+
+use super::{get_num_bytes, get_pitch_bytes, SemiPlanarDepth, SemiPlanarReader, SemiPlanarWriter};
+
+#[test]
+fn lines_are_padded_to_8_pixels() {
+    assert_eq!(get_pitch_bytes(1920, SemiPlanarDepth::Bits8), 1920);
+    assert_eq!(get_pitch_bytes(1, SemiPlanarDepth::Bits8), 8);
+    // 1366 is not a multiple of 8, the line is padded to 1368 pixels
+    assert_eq!(get_pitch_bytes(1366, SemiPlanarDepth::Bits8), 1368);
+    for width in [1, 7, 8, 9, 720, 1366, 1920, 3840] {
+        assert_eq!(get_pitch_bytes(width, SemiPlanarDepth::Bits8) % 8, 0);
+    }
+}
+
+#[test]
+fn p010_lines_are_twice_as_long() {
+    for width in [1, 9, 720, 1366, 1920] {
+        assert_eq!(
+            get_pitch_bytes(width, SemiPlanarDepth::Bits10),
+            get_pitch_bytes(width, SemiPlanarDepth::Bits8) * 2
+        );
+    }
+    assert_eq!(get_pitch_bytes(1366, SemiPlanarDepth::Bits10), 2736);
+}
+
+#[test]
+fn chroma_plane_is_half_the_luma_plane() {
+    assert_eq!(
+        get_num_bytes(1920, 1080, SemiPlanarDepth::Bits8),
+        [1920 * 1080, 1920 * 540]
+    );
+    assert_eq!(
+        get_num_bytes(1920, 1080, SemiPlanarDepth::Bits10),
+        [3840 * 1080, 3840 * 540]
+    );
+    // The chroma plane holds a line of interleaved cb and cr for every pair of luma lines
+    assert_eq!(
+        get_num_bytes(1366, 768, SemiPlanarDepth::Bits8),
+        [1368 * 768, 1368 * 384]
+    );
+}
+
+#[test]
+fn reader_and_writer_agree_on_planes() {
+    for depth in [SemiPlanarDepth::Bits8, SemiPlanarDepth::Bits10] {
+        let reader = SemiPlanarReader::new(1366, 768, depth);
+        let writer = SemiPlanarWriter::new(1366, 768, InterlaceMode::Progressive, depth);
+        assert_eq!(reader.get_num_bytes(), get_num_bytes(1366, 768, depth));
+        assert_eq!(writer.get_num_bytes(), reader.get_num_bytes());
+        assert_eq!(
+            reader.get_total_bytes(),
+            get_pitch_bytes(1366, depth) * 768 * 3 / 2
+        );
+        // Each work item covers two lines, one line of the chroma plane
+        assert_eq!(reader.get_global_work_items(), 22 * 384);
+    }
+}
+
+#[test]
+fn shaders_are_built_for_the_depth() {
+    let nv12 = SemiPlanarReader::new(1920, 1080, SemiPlanarDepth::Bits8);
+    assert_eq!(nv12.get_name(), "NV12 Reader");
+    assert!(nv12.get_kernel().starts_with("#define SAMPLE3 uchar3\n"));
+    assert_eq!(nv12.get_num_bits(), 8);
+
+    let p010 = SemiPlanarWriter::new(
+        1920,
+        1080,
+        InterlaceMode::Progressive,
+        SemiPlanarDepth::Bits10,
+    );
+    assert_eq!(p010.get_name(), "P010 Writer");
+    assert!(p010.get_kernel().contains("#define SAMPLE_SHIFT 6\n"));
+    assert!(p010.get_kernel().contains("__kernel void write("));
+    assert_eq!(p010.get_luma_black(), 64.0);
+}
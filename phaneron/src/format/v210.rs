@@ -23,10 +23,15 @@ use crate::{
     io::{Packer, Unpacker},
 };
 
+#[cfg(test)]
+mod tests;
+
+/// Width of a line including padding, lines are padded to a multiple of 48 pixels.
 fn get_pitch(width: usize) -> usize {
     width + 47 - ((width - 1) % 48)
 }
 
+/// Bytes in a line, every 6 pixels are packed into 16 bytes so lines are a multiple of 128 bytes.
 fn get_pitch_bytes(width: usize) -> usize {
     (get_pitch(width) * 8) / 3
 }
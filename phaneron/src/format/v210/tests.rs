@@ -0,0 +1,246 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use abi_stable::{sabi_trait::TD_Opaque, std_types::RArc};
+use phaneron_plugin::{traits::VideoFrame_TO, ColourRange, ColourSpace, InterlaceMode};
+
+use crate::{
+    compute::{create_compute_context, device::ComputeDeviceSelection, fence::GpuSyncMode},
+    io::LoadedVideoFrame,
+    load_save::{Loader, Saver},
+};
+
+use super::{get_pitch_bytes, V210Reader, V210Writer};
+
+/// Packs 10 bit `[y, cb, cr]` pixels into V210 in the same way as the writer shader, a reference
+/// for the layout that the shaders read and write. Each pair of pixels shares the chroma of its
+/// first pixel. The last pixel of a line is repeated to fill its group of 6 and the padding at the
+/// end of a line is zeroed.
+fn pack(width: usize, height: usize, pixels: &[[u16; 3]]) -> Vec<u8> {
+    let pitch_bytes = get_pitch_bytes(width);
+    let mut data = vec![0; pitch_bytes * height];
+    for (pixels, data) in pixels
+        .chunks(width)
+        .zip(data.chunks_mut(pitch_bytes))
+        .take(height)
+    {
+        for (group, data) in data.chunks_mut(16).take((width + 5) / 6).enumerate() {
+            let p = |index: usize| pixels[(group * 6 + index).min(width - 1)].map(u32::from);
+            let words = [
+                p(0)[2] << 20 | p(0)[0] << 10 | p(0)[1],
+                p(2)[0] << 20 | p(2)[1] << 10 | p(1)[0],
+                p(4)[1] << 20 | p(3)[0] << 10 | p(2)[2],
+                p(5)[0] << 20 | p(4)[2] << 10 | p(4)[0],
+            ];
+            for (word, data) in words.iter().zip(data.chunks_mut(4)) {
+                data.copy_from_slice(&word.to_le_bytes());
+            }
+        }
+    }
+
+    data
+}
+
+/// Unpacks V210 into 10 bit `[y, cb, cr]` pixels in the same way as the reader shader.
+fn unpack(width: usize, height: usize, data: &[u8]) -> Vec<[u16; 3]> {
+    let mut pixels = Vec::with_capacity(width * height);
+    for data in data.chunks(get_pitch_bytes(width)).take(height) {
+        let line_start = pixels.len();
+        for data in data.chunks(16).take((width + 5) / 6) {
+            let w: Vec<u32> = data
+                .chunks(4)
+                .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+                .collect();
+            let c = |word: u32, shift: u32| ((word >> shift) & 0x3ff) as u16;
+            let (cb0, cr0) = (c(w[0], 0), c(w[0], 20));
+            let (cb2, cr2) = (c(w[1], 10), c(w[2], 0));
+            let (cb4, cr4) = (c(w[2], 20), c(w[3], 10));
+            pixels.extend([
+                [c(w[0], 10), cb0, cr0],
+                [c(w[1], 0), cb0, cr0],
+                [c(w[1], 20), cb2, cr2],
+                [c(w[2], 10), cb2, cr2],
+                [c(w[3], 0), cb4, cr4],
+                [c(w[3], 20), cb4, cr4],
+            ]);
+        }
+        pixels.truncate(line_start + width);
+    }
+
+    pixels
+}
+
+/// A line of pixels where each pair shares its chroma, which V210 preserves exactly.
+fn line(width: usize, seed: u16) -> Vec<[u16; 3]> {
+    (0..width)
+        .map(|x| {
+            let pair = (x / 2) as u16;
+            [
+                (64 + seed + x as u16 * 7) % 1024,
+                (512 + seed + pair * 13) % 1024,
+                (448 + seed + pair * 29) % 1024,
+            ]
+        })
+        .collect()
+}
+
+/// A line of legal, in gamut pixels where each pair shares its chroma, which survive conversion
+/// to RGB and back.
+fn legal_line(width: usize, seed: u16) -> Vec<[u16; 3]> {
+    (0..width)
+        .map(|x| {
+            let pair = (x / 2) as u16;
+            [
+                200 + (seed + x as u16 * 7) % 600,
+                472 + (seed + pair * 13) % 80,
+                472 + (seed + pair * 29) % 80,
+            ]
+        })
+        .collect()
+}
+
+#[test]
+fn lines_are_padded_to_128_bytes() {
+    assert_eq!(get_pitch_bytes(1920), 5120);
+    assert_eq!(get_pitch_bytes(720), 1920);
+    // 1280 is not a multiple of 48, the line is padded to 1296 pixels
+    assert_eq!(get_pitch_bytes(1280), 3456);
+    assert_eq!(get_pitch_bytes(1), 128);
+    for width in [1, 6, 47, 48, 49, 1280, 1920, 3840] {
+        assert_eq!(get_pitch_bytes(width) % 128, 0);
+    }
+}
+
+#[test]
+fn six_pixels_pack_into_four_words() {
+    let pixels = [
+        [0x040, 0x200, 0x1c0],
+        [0x041, 0x200, 0x1c0],
+        [0x042, 0x201, 0x1c1],
+        [0x043, 0x201, 0x1c1],
+        [0x044, 0x202, 0x1c2],
+        [0x3ac, 0x202, 0x1c2],
+    ];
+    let data = pack(6, 1, &pixels);
+    assert_eq!(data.len(), 128);
+
+    let words: Vec<u32> = data
+        .chunks(4)
+        .take(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect();
+    assert_eq!(
+        words,
+        [
+            0x1c0 << 20 | 0x040 << 10 | 0x200,
+            0x042 << 20 | 0x201 << 10 | 0x041,
+            0x202 << 20 | 0x043 << 10 | 0x1c1,
+            0x3ac << 20 | 0x1c2 << 10 | 0x044,
+        ]
+    );
+    assert!(data[16..].iter().all(|byte| *byte == 0));
+}
+
+#[test]
+fn round_trip_is_lossless() {
+    for (width, height) in [(6, 1), (48, 2), (1280, 3), (1920, 2), (10, 4)] {
+        let pixels: Vec<[u16; 3]> = (0..height)
+            .flat_map(|y| line(width, y as u16 * 101))
+            .collect();
+        let data = pack(width, height, &pixels);
+        assert_eq!(data.len(), get_pitch_bytes(width) * height);
+        assert_eq!(unpack(width, height, &data), pixels, "{width}x{height}");
+    }
+}
+
+#[test]
+fn lines_start_at_the_pitch() {
+    // Each line starts on a 128 byte boundary, reading lines at the width would shear the image
+    let (width, height) = (1280, 2);
+    let pixels: Vec<[u16; 3]> = line(width, 0).into_iter().chain(line(width, 500)).collect();
+    let data = pack(width, height, &pixels);
+
+    let second_line = &data[get_pitch_bytes(width)..];
+    assert_eq!(unpack(width, 1, second_line), line(width, 500));
+}
+
+#[test]
+fn odd_width_repeats_last_pixel() {
+    let pixels = [[100, 200, 300], [101, 200, 300], [102, 201, 301]];
+    let data = pack(3, 1, &pixels);
+    assert_eq!(unpack(3, 1, &data), pixels);
+    // The pixels after the end of the line in the group repeat the last pixel
+    assert_eq!(unpack(6, 1, &data)[3..], [[102, 201, 301]; 3]);
+}
+
+/// Runs the reader and writer shaders, which the tests above only model.
+#[tokio::test]
+#[ignore = "Needs an OpenCL device"]
+async fn shaders_round_trip_on_the_gpu() {
+    let context =
+        create_compute_context(GpuSyncMode::default(), ComputeDeviceSelection::default(), 0)
+            .await
+            .unwrap();
+    let colour_spec = ColourSpace::BT_709.colour_spec();
+
+    // Widths that are a multiple of 48, padded to 48 and shorter than a group of 6
+    for (width, height) in [(1920, 2), (1280, 4), (10, 2)] {
+        let pixels: Vec<[u16; 3]> = (0..height)
+            .flat_map(|y| legal_line(width, y as u16 * 37))
+            .collect();
+        let data = pack(width, height, &pixels);
+
+        let loader = Loader::new(
+            context.clone(),
+            &colour_spec,
+            ColourRange::Limited,
+            Box::new(V210Reader::new(width, height)),
+        );
+        let (buffer, event) = context.load_frame_to_buffer(&data).unwrap();
+        let frame = loader.run(LoadedVideoFrame {
+            buffers: vec![buffer],
+            events: vec![event],
+            width,
+            height,
+        });
+
+        let saver = Saver::new(
+            context.clone(),
+            &colour_spec,
+            ColourRange::Limited,
+            Box::new(V210Writer::new(width, height, InterlaceMode::Progressive)),
+        );
+        let consumed = saver.run(RArc::new(VideoFrame_TO::from_value(frame, TD_Opaque)));
+        let mut saved = vec![0; data.len()];
+        context
+            .copy_frame_from_buffer(&consumed.buffers[0], &mut saved, &consumed.events)
+            .unwrap();
+
+        // Converting to linear light and back rounds each component
+        for (index, (saved, pixel)) in unpack(width, height, &saved)
+            .iter()
+            .zip(pixels.iter())
+            .enumerate()
+        {
+            assert!(
+                saved.iter().zip(pixel).all(|(a, b)| a.abs_diff(*b) <= 2),
+                "{width}x{height} pixel {index}: {saved:?} != {pixel:?}"
+            );
+        }
+    }
+}
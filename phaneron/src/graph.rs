@@ -16,10 +16,24 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::fmt::Display;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 
+use phaneron_plugin::{AudioChannelLayout, COLOUR_SPEC_SRGB};
 use serde::{Deserialize, Serialize};
 
+use crate::{colour::gamma_to_linear, config::GraphsConfig};
+
+#[cfg(test)]
+mod tests;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct GraphId(String);
 impl GraphId {
@@ -29,7 +43,7 @@ impl GraphId {
 }
 impl Default for GraphId {
     fn default() -> Self {
-        Self(uuid::Uuid::new_v4().to_string())
+        Self(phaneron_plugin::ids::new_id())
     }
 }
 impl Display for GraphId {
@@ -47,7 +61,7 @@ impl NodeId {
 }
 impl Default for NodeId {
     fn default() -> Self {
-        Self(uuid::Uuid::new_v4().to_string())
+        Self(phaneron_plugin::ids::new_id())
     }
 }
 impl Display for NodeId {
@@ -55,3 +69,392 @@ impl Display for NodeId {
         write!(f, "{}", self.0)
     }
 }
+
+/// Shared by the nodes of a graph. While the graph is paused producers wait at the gate before
+/// producing their next frame and the other nodes repeat the last frames they received,
+/// connections and node state are left intact.
+#[derive(Clone)]
+pub struct PauseGate {
+    sender: Arc<tokio::sync::watch::Sender<bool>>,
+}
+
+impl PauseGate {
+    pub fn set_paused(&self, paused: bool) {
+        self.sender.send_replace(paused);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Returns immediately if the graph is not paused.
+    pub async fn wait_until_resumed(&self) {
+        let mut receiver = self.sender.subscribe();
+        while *receiver.borrow_and_update() {
+            if receiver.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+impl Default for PauseGate {
+    fn default() -> Self {
+        let (sender, _) = tokio::sync::watch::channel(false);
+        Self {
+            sender: Arc::new(sender),
+        }
+    }
+}
+
+/// Live graphs keep latency low by letting producers run only a frame or two ahead of the
+/// nodes consuming their frames, batch graphs let producers run further ahead for throughput.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GraphMode {
+    #[default]
+    Live,
+    Batch,
+}
+
+/// Shared by the nodes of a graph. Limits how many frames a node may have pushed to its
+/// outputs that have not yet been processed by every node connected to them.
+#[derive(Debug, Clone)]
+pub struct FrameLeadLimit {
+    limits: GraphsConfig,
+    batch: Arc<AtomicBool>,
+}
+
+impl FrameLeadLimit {
+    pub fn new(limits: GraphsConfig) -> Self {
+        Self {
+            limits,
+            batch: Default::default(),
+        }
+    }
+
+    pub fn mode(&self) -> GraphMode {
+        if self.batch.load(Ordering::Relaxed) {
+            GraphMode::Batch
+        } else {
+            GraphMode::Live
+        }
+    }
+
+    pub fn set_mode(&self, mode: GraphMode) {
+        self.batch
+            .store(mode == GraphMode::Batch, Ordering::Relaxed);
+    }
+
+    /// At least 1, the frame a node has just pushed counts towards the limit.
+    pub fn max_frames_ahead(&self) -> usize {
+        let max_frames_ahead = match self.mode() {
+            GraphMode::Live => self.limits.live_max_frames_ahead,
+            GraphMode::Batch => self.limits.batch_max_frames_ahead,
+        };
+        max_frames_ahead.clamp(1, self.limits.channel_capacity.max(1))
+    }
+}
+
+/// Safety behaviour of a graph, chosen when the graph is created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphSafety {
+    /// Once a video input has not received a frame for this many milliseconds the node holds the
+    /// last frame the input received, audio inputs receive silence, and an alarm is raised until
+    /// frames arrive again. Without this nodes wait for their inputs indefinitely.
+    pub hold_on_stall_ms: Option<u64>,
+}
+
+/// Frame rate, audio sample rate and audio channel layout of a graph, chosen when the graph is
+/// created. Inputs without audio receive silence as long as a frame, see [`SampleCadence`], with
+/// one buffer per channel of the layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameFormat {
+    pub frame_rate_num: u32,
+    pub frame_rate_den: u32,
+    pub sample_rate: u32,
+    #[serde(default)]
+    pub channel_layout: AudioChannelLayout,
+    /// Number of samples in each block of audio, passed to nodes so they can handle the audio of
+    /// a frame in smaller blocks. Must divide the samples of a frame, so only frame rates with a
+    /// whole number of samples per frame can set it. Audio still moves through the graph one frame
+    /// at a time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_block_samples: Option<u32>,
+}
+
+impl Default for FrameFormat {
+    fn default() -> Self {
+        Self {
+            frame_rate_num: 25,
+            frame_rate_den: 1,
+            sample_rate: 48000,
+            channel_layout: AudioChannelLayout::Mono,
+            audio_block_samples: None,
+        }
+    }
+}
+
+impl FrameFormat {
+    pub fn is_valid(&self) -> bool {
+        if self.frame_rate_num == 0 || self.frame_rate_den == 0 || self.sample_rate == 0 {
+            return false;
+        }
+        match (self.audio_block_samples, self.samples_per_frame()) {
+            (None, _) => true,
+            (Some(block_samples), Some(frame_samples)) => {
+                block_samples > 0 && frame_samples % block_samples == 0
+            }
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Number of samples in each frame, `None` when frames do not hold a whole number of samples.
+    pub fn samples_per_frame(&self) -> Option<u32> {
+        let samples = u64::from(self.sample_rate) * u64::from(self.frame_rate_den);
+        let frame_rate_num = u64::from(self.frame_rate_num.max(1));
+        (samples % frame_rate_num == 0).then(|| (samples / frame_rate_num) as u32)
+    }
+
+    pub fn frame_duration(&self) -> Duration {
+        Duration::from_secs_f64(
+            f64::from(self.frame_rate_den) / f64::from(self.frame_rate_num.max(1)),
+        )
+    }
+}
+
+/// Counts out the audio samples of each frame of a [`FrameFormat`]. When a frame does not hold a
+/// whole number of samples, e.g. 1601.6 at 48 kHz and 29.97 fps, frames alternate between the
+/// rounded down and rounded up counts so that audio does not drift from video.
+#[derive(Debug, Clone)]
+pub struct SampleCadence {
+    format: FrameFormat,
+    frames: u64,
+    samples: u64,
+}
+
+impl SampleCadence {
+    pub fn new(format: FrameFormat) -> Self {
+        Self {
+            format,
+            frames: 0,
+            samples: 0,
+        }
+    }
+
+    /// Number of samples in the next frame.
+    pub fn next_samples(&mut self) -> usize {
+        self.frames += 1;
+        let samples = self.frames
+            * u64::from(self.format.sample_rate)
+            * u64::from(self.format.frame_rate_den)
+            / u64::from(self.format.frame_rate_num.max(1));
+        let next_samples = samples - self.samples;
+        self.samples = samples;
+
+        next_samples as usize
+    }
+}
+
+/// Counts the frames of a graph at its frame rate from when the graph was created. Used for work
+/// that follows the graph's cadence without a frame flowing through it, such as automations, and
+/// passed to nodes as the number of the frame they are processing.
+#[derive(Debug, Clone, Copy)]
+pub struct GraphClock {
+    format: FrameFormat,
+    start: Instant,
+}
+
+impl GraphClock {
+    pub fn new(format: FrameFormat) -> Self {
+        Self {
+            format,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn frame_format(&self) -> FrameFormat {
+        self.format
+    }
+
+    /// Number of whole frames between the start of the clock and `at`.
+    pub fn frame_at(&self, at: Instant) -> u64 {
+        let elapsed = at.saturating_duration_since(self.start).as_nanos();
+        (elapsed * u128::from(self.format.frame_rate_num)
+            / (u128::from(self.format.frame_rate_den.max(1)) * 1_000_000_000)) as u64
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame_at(Instant::now())
+    }
+
+    /// Time at which the given frame starts.
+    pub fn frame_start(&self, frame: u64) -> Instant {
+        let nanos = u128::from(frame) * u128::from(self.format.frame_rate_den) * 1_000_000_000
+            / u128::from(self.format.frame_rate_num.max(1));
+        self.start + Duration::from_nanos(nanos as u64)
+    }
+
+    /// Waits for the start of the next frame and returns its number.
+    pub async fn next_frame(&self) -> u64 {
+        let frame = self.frame() + 1;
+        tokio::time::sleep_until(self.frame_start(frame).into()).await;
+        frame
+    }
+}
+
+/// An input that is holding its last frame because it has stopped receiving frames.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StallAlarm {
+    pub node_id: String,
+    pub input_id: String,
+    pub stalled_for_ms: u128,
+}
+
+/// Shared by the nodes of a graph to apply the graph's [`GraphSafety`] and collect its alarms.
+#[derive(Debug, Clone, Default)]
+pub struct StallMonitor {
+    safety: GraphSafety,
+    /// Stalled inputs by input Id, with the node they belong to and when they stalled.
+    stalled_inputs: Arc<Mutex<HashMap<String, (NodeId, Instant)>>>,
+}
+
+impl StallMonitor {
+    pub fn new(safety: GraphSafety) -> Self {
+        Self {
+            safety,
+            stalled_inputs: Default::default(),
+        }
+    }
+
+    pub fn safety(&self) -> GraphSafety {
+        self.safety
+    }
+
+    /// How long to wait for a frame on an input before holding, `None` to wait indefinitely.
+    pub fn stall_timeout(&self) -> Option<Duration> {
+        self.safety.hold_on_stall_ms.map(Duration::from_millis)
+    }
+
+    /// Raises an alarm for an input, an input that is already stalled keeps the time it stalled.
+    pub fn raise(&self, node_id: &NodeId, input_id: &str) {
+        self.stalled_inputs
+            .lock()
+            .unwrap()
+            .entry(input_id.to_string())
+            .or_insert_with(|| (node_id.clone(), Instant::now()));
+    }
+
+    pub fn clear(&self, input_id: &str) {
+        self.stalled_inputs.lock().unwrap().remove(input_id);
+    }
+
+    /// Clears the alarms of a node, e.g. when it is removed.
+    pub fn clear_node(&self, node_id: &NodeId) {
+        self.stalled_inputs
+            .lock()
+            .unwrap()
+            .retain(|_, (stalled_node_id, _)| stalled_node_id != node_id);
+    }
+
+    /// Current alarms, longest stalled first.
+    pub fn alarms(&self) -> Vec<StallAlarm> {
+        let now = Instant::now();
+        let mut stalled: Vec<(Instant, StallAlarm)> = self
+            .stalled_inputs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(input_id, (node_id, since))| {
+                let alarm = StallAlarm {
+                    node_id: node_id.to_string(),
+                    input_id: input_id.clone(),
+                    stalled_for_ms: now.duration_since(*since).as_millis(),
+                };
+                (*since, alarm)
+            })
+            .collect();
+        stalled.sort_by_key(|(since, _)| *since);
+
+        stalled.into_iter().map(|(_, alarm)| alarm).collect()
+    }
+}
+
+/// What a graph's consumers receive while the graph is in panic, see [`PanicSlate`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Slate {
+    /// sRGB-encoded red, green and blue in the range 0-1, black if not given.
+    #[serde(default)]
+    pub colour: [f32; 3],
+}
+
+impl Slate {
+    /// Opaque colour of the slate in the working colour space, linear light with BT.709 primaries.
+    pub fn linear_colour(&self) -> [f32; 4] {
+        let [red, green, blue] = self
+            .colour
+            .map(|value| gamma_to_linear(&COLOUR_SPEC_SRGB, value.clamp(0.0, 1.0)));
+        [red, green, blue, 1.0]
+    }
+}
+
+/// Shared by the nodes of a graph. While a slate is set the nodes consuming the graph's video,
+/// those without video outputs, receive the slate in place of every video frame. Unlike pausing,
+/// the rest of the graph keeps running so consumers keep emitting frames.
+#[derive(Debug, Clone, Default)]
+pub struct PanicSlate {
+    slate: Arc<Mutex<Option<Slate>>>,
+}
+
+impl PanicSlate {
+    /// Sets the slate, `None` clears the panic and consumers receive their inputs again.
+    pub fn set(&self, slate: Option<Slate>) {
+        *self.slate.lock().unwrap() = slate;
+    }
+
+    pub fn slate(&self) -> Option<Slate> {
+        *self.slate.lock().unwrap()
+    }
+}
+
+/// The objects shared by the nodes of a graph that control how each node runs.
+#[derive(Clone)]
+pub struct GraphControls {
+    pub pause_gate: PauseGate,
+    pub frame_lead: FrameLeadLimit,
+    pub stall_monitor: StallMonitor,
+    pub panic_slate: PanicSlate,
+    pub clock: GraphClock,
+}
+
+/// Whether connecting an output of `from` to an input of `to` would make a node wait on its own
+/// frames. `edges` are the existing connections as (upstream, downstream) node pairs, video and
+/// audio alike since a node waits on both.
+pub fn would_create_cycle<'a>(
+    edges: impl IntoIterator<Item = (&'a NodeId, &'a NodeId)>,
+    from: &NodeId,
+    to: &NodeId,
+) -> bool {
+    let mut downstream: HashMap<&NodeId, Vec<&NodeId>> = HashMap::new();
+    for (upstream, node) in edges {
+        downstream.entry(upstream).or_default().push(node);
+    }
+
+    // The connection closes a loop if `from` can already be reached from `to`
+    let mut visited = HashSet::new();
+    let mut to_visit = vec![to];
+    while let Some(node) = to_visit.pop() {
+        if node == from {
+            return true;
+        }
+        if visited.insert(node) {
+            to_visit.extend(downstream.get(node).into_iter().flatten().copied());
+        }
+    }
+
+    false
+}
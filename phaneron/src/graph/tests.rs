@@ -0,0 +1,224 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::{Duration, Instant};
+
+use super::{
+    would_create_cycle, FrameFormat, FrameLeadLimit, GraphClock, GraphId, GraphMode, GraphSafety,
+    NodeId, PanicSlate, SampleCadence, Slate, StallMonitor,
+};
+use crate::config::GraphsConfig;
+
+#[test]
+fn sequential_ids_are_deterministic() {
+    let ids = phaneron_plugin::ids::use_sequential_ids();
+    assert_eq!(
+        GraphId::default().to_string(),
+        "00000000-0000-0000-0000-000000000001"
+    );
+    assert_eq!(
+        NodeId::default().to_string(),
+        "00000000-0000-0000-0000-000000000002"
+    );
+    drop(ids);
+
+    assert_ne!(
+        NodeId::default().to_string(),
+        "00000000-0000-0000-0000-000000000003"
+    );
+}
+
+#[test]
+fn frame_lead_follows_graph_mode() {
+    let frame_lead = FrameLeadLimit::new(GraphsConfig {
+        live_max_frames_ahead: 2,
+        batch_max_frames_ahead: 1000,
+        ..Default::default()
+    });
+    assert_eq!(frame_lead.mode(), GraphMode::Live);
+    assert_eq!(frame_lead.max_frames_ahead(), 2);
+
+    frame_lead.clone().set_mode(GraphMode::Batch);
+    assert_eq!(frame_lead.mode(), GraphMode::Batch);
+    // Limited by how many frames can be queued for each consumer
+    assert_eq!(
+        frame_lead.max_frames_ahead(),
+        crate::channel::MAX_FRAMES_AHEAD
+    );
+}
+
+#[test]
+fn frame_lead_is_at_least_one() {
+    let frame_lead = FrameLeadLimit::new(GraphsConfig {
+        live_max_frames_ahead: 0,
+        batch_max_frames_ahead: 0,
+        channel_capacity: 0,
+    });
+
+    assert_eq!(frame_lead.max_frames_ahead(), 1);
+}
+
+#[test]
+fn stall_alarms_are_raised_and_cleared() {
+    let monitor = StallMonitor::new(GraphSafety {
+        hold_on_stall_ms: Some(200),
+    });
+    let node_a = NodeId::new_from("a".to_string());
+    let node_b = NodeId::new_from("b".to_string());
+    assert_eq!(monitor.stall_timeout(), Some(Duration::from_millis(200)));
+
+    monitor.raise(&node_a, "a_input");
+    monitor.raise(&node_b, "b_input");
+    monitor.raise(&node_a, "a_input");
+    let alarms = monitor.alarms();
+    assert_eq!(alarms.len(), 2);
+    assert_eq!(alarms[0].input_id, "a_input");
+
+    monitor.clear("a_input");
+    assert_eq!(monitor.alarms()[0].node_id, "b");
+
+    monitor.clear_node(&node_b);
+    assert!(monitor.alarms().is_empty());
+}
+
+#[test]
+fn graphs_wait_for_stalled_inputs_by_default() {
+    assert_eq!(StallMonitor::default().stall_timeout(), None);
+}
+
+#[test]
+fn panic_slate_is_shared_until_cleared() {
+    let panic_slate = PanicSlate::default();
+    let node_view = panic_slate.clone();
+    assert_eq!(node_view.slate(), None);
+
+    let slate = Slate {
+        colour: [1.0, 0.0, 0.0],
+    };
+    panic_slate.set(Some(slate));
+    assert_eq!(node_view.slate(), Some(slate));
+
+    panic_slate.set(None);
+    assert_eq!(node_view.slate(), None);
+}
+
+#[test]
+fn slate_colour_is_converted_to_linear_light() {
+    let slate: Slate = serde_json::from_str("{}").unwrap();
+    assert_eq!(slate.linear_colour(), [0.0, 0.0, 0.0, 1.0]);
+
+    let slate: Slate = serde_json::from_str(r#"{ "colour": [1.0, 0.5, 2.0] }"#).unwrap();
+    let [red, green, blue, alpha] = slate.linear_colour();
+    assert!((red - 1.0).abs() < 1e-6);
+    assert!((green - 0.214).abs() < 1e-3);
+    assert!((blue - 1.0).abs() < 1e-6);
+    assert_eq!(alpha, 1.0);
+}
+
+#[test]
+fn connections_that_close_a_loop_are_cycles() {
+    let [a, b, c, d] = ["a", "b", "c", "d"].map(|id| NodeId::new_from(id.to_string()));
+    let edges = vec![(&a, &b), (&b, &c)];
+
+    assert!(would_create_cycle(edges.clone(), &c, &a));
+    assert!(would_create_cycle(edges.clone(), &a, &a));
+    assert!(!would_create_cycle(edges.clone(), &a, &c));
+    assert!(!would_create_cycle(edges, &c, &d));
+}
+
+#[test]
+fn frame_duration_follows_frame_rate() {
+    assert_eq!(
+        FrameFormat::default().frame_duration(),
+        Duration::from_millis(40)
+    );
+    let ntsc = FrameFormat {
+        frame_rate_num: 30000,
+        frame_rate_den: 1001,
+        ..Default::default()
+    };
+    assert_eq!(ntsc.frame_duration().as_micros(), 33366);
+}
+
+#[test]
+fn whole_samples_per_frame_are_constant() {
+    let mut cadence = SampleCadence::new(FrameFormat::default());
+    for _ in 0..100 {
+        assert_eq!(cadence.next_samples(), 1920);
+    }
+}
+
+#[test]
+fn fractional_samples_per_frame_do_not_drift() {
+    let mut cadence = SampleCadence::new(FrameFormat {
+        frame_rate_num: 30000,
+        frame_rate_den: 1001,
+        sample_rate: 48000,
+        ..Default::default()
+    });
+    let samples: Vec<usize> = (0..5).map(|_| cadence.next_samples()).collect();
+    assert_eq!(samples, vec![1601, 1602, 1601, 1602, 1602]);
+
+    // Every 5 frames hold exactly 8008 samples
+    let total: usize = (0..5 * 1000).map(|_| cadence.next_samples()).sum();
+    assert_eq!(total, 8008 * 1000);
+}
+
+#[test]
+fn audio_block_samples_divide_the_frame() {
+    let with_blocks = |frame_rate_num, frame_rate_den, audio_block_samples| FrameFormat {
+        frame_rate_num,
+        frame_rate_den,
+        audio_block_samples: Some(audio_block_samples),
+        ..Default::default()
+    };
+
+    assert_eq!(FrameFormat::default().samples_per_frame(), Some(1920));
+    assert!(with_blocks(25, 1, 480).is_valid());
+    assert!(with_blocks(25, 1, 1920).is_valid());
+    assert!(!with_blocks(25, 1, 256).is_valid());
+    assert!(!with_blocks(25, 1, 0).is_valid());
+    // 1601.6 samples per frame can not be split into equal blocks
+    assert!(!with_blocks(30000, 1001, 8).is_valid());
+}
+
+#[test]
+fn clock_counts_frames_at_the_frame_rate() {
+    let clock = GraphClock::new(FrameFormat {
+        frame_rate_num: 30000,
+        frame_rate_den: 1001,
+        ..Default::default()
+    });
+    let start = clock.frame_start(0);
+    assert_eq!(clock.frame_at(start), 0);
+    assert_eq!(clock.frame_at(start + Duration::from_millis(33)), 0);
+    assert_eq!(clock.frame_at(start + Duration::from_millis(34)), 1);
+    assert_eq!(clock.frame_at(start + Duration::from_secs(1001)), 30000);
+    assert_eq!(clock.frame_at(clock.frame_start(12345)), 12345);
+}
+
+#[tokio::test]
+async fn clock_waits_for_the_next_frame() {
+    let clock = GraphClock::new(FrameFormat {
+        frame_rate_num: 50,
+        ..Default::default()
+    });
+    let frame = clock.next_frame().await;
+    assert!(Instant::now() >= clock.frame_start(frame));
+    assert_eq!(clock.next_frame().await, frame + 1);
+}
@@ -0,0 +1,249 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::info;
+
+use crate::{
+    compute::ComputePriority,
+    graph::{FrameFormat, GraphId, GraphSafety, NodeId},
+    plugins::PluginManager,
+    state::{CreateConnection, CreateConnectionType, CreateNode, PhaneronState},
+};
+
+/// Contents of the video_inputs.json file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InputsFile {
+    pub videos: Vec<VideoInput>,
+    /// Number of switcher inputs to create, allows for inputs to be added at runtime.
+    /// Defaults to the number of videos.
+    #[serde(default)]
+    pub max_inputs: Option<usize>,
+}
+
+impl InputsFile {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let inputs = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&inputs)?)
+    }
+
+    pub fn number_of_inputs(&self) -> usize {
+        self.videos.len().max(self.max_inputs.unwrap_or_default())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoInput {
+    pub path: String,
+    pub display_name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FFmpegProducerState {
+    file: String,
+}
+
+struct ManagedInput {
+    input: VideoInput,
+    node_id: NodeId,
+    switcher_input_index: usize,
+}
+
+/// Keeps the FFmpeg producers connected to the switcher in line with a list of inputs,
+/// so that inputs can be added and removed without restarting.
+#[derive(Clone)]
+pub struct InputsManager {
+    inner: Arc<Mutex<InputsManagerInner>>,
+}
+
+struct InputsManagerInner {
+    state: PhaneronState,
+    plugin_manager: Arc<PluginManager>,
+    graph_id: GraphId,
+    switcher_node_id: NodeId,
+    audio_node_id: Option<NodeId>,
+    inputs_file_path: PathBuf,
+    inputs: Vec<ManagedInput>,
+    audio_source: Option<NodeId>,
+}
+
+impl InputsManager {
+    /// The audio of the first input that is created is connected to `audio_node_id`,
+    /// if that input is removed then the next input to be created will take its place.
+    pub fn new(
+        state: PhaneronState,
+        plugin_manager: Arc<PluginManager>,
+        graph_id: GraphId,
+        switcher_node_id: NodeId,
+        audio_node_id: Option<NodeId>,
+        inputs_file_path: PathBuf,
+    ) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(InputsManagerInner {
+                state,
+                plugin_manager,
+                graph_id,
+                switcher_node_id,
+                audio_node_id,
+                inputs_file_path,
+                inputs: vec![],
+                audio_source: None,
+            })),
+        }
+    }
+
+    pub async fn get_inputs(&self) -> Vec<VideoInput> {
+        self.inner
+            .lock()
+            .await
+            .inputs
+            .iter()
+            .map(|managed| managed.input.clone())
+            .collect()
+    }
+
+    /// Re-reads the inputs file and applies any changes.
+    pub async fn reload(&self) -> anyhow::Result<()> {
+        let inputs_file_path = self.inner.lock().await.inputs_file_path.clone();
+        let inputs_file = InputsFile::load(&inputs_file_path)?;
+        self.apply(inputs_file.videos).await
+    }
+
+    /// Removes producers for inputs that are no longer in the list and creates producers for new inputs.
+    /// Inputs that are unchanged are left running.
+    pub async fn apply(&self, videos: Vec<VideoInput>) -> anyhow::Result<()> {
+        let mut inner = self.inner.lock().await;
+
+        let removed: Vec<NodeId> = inner
+            .inputs
+            .iter()
+            .filter(|managed| !videos.contains(&managed.input))
+            .map(|managed| managed.node_id.clone())
+            .collect();
+        for node_id in removed.iter() {
+            info!("Removing input {node_id}");
+            inner
+                .state
+                .remove_node(&inner.plugin_manager, &inner.graph_id, node_id)
+                .await
+                .map_err(|err| anyhow::anyhow!("Failed to remove input {node_id}: {err:?}"))?;
+            inner.inputs.retain(|managed| &managed.node_id != node_id);
+            if inner.audio_source.as_ref() == Some(node_id) {
+                inner.audio_source = None;
+            }
+        }
+
+        let added: Vec<VideoInput> = videos
+            .into_iter()
+            .filter(|video| !inner.inputs.iter().any(|managed| &managed.input == video))
+            .collect();
+        for video in added {
+            inner.add_input(video).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl InputsManagerInner {
+    async fn add_input(&mut self, video: VideoInput) -> anyhow::Result<()> {
+        let switcher_inputs = self
+            .state
+            .get_available_video_inputs(&self.graph_id, &self.switcher_node_id)
+            .await;
+        let switcher_input_index = (0..switcher_inputs.len())
+            .find(|index| {
+                !self
+                    .inputs
+                    .iter()
+                    .any(|managed| managed.switcher_input_index == *index)
+            })
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "No free switcher inputs for {}, increase maxInputs",
+                    video.display_name
+                )
+            })?;
+
+        let node_id = NodeId::default();
+        info!(
+            "Adding input {} ({}) as {node_id}",
+            video.display_name, video.path
+        );
+        let mut connections = vec![CreateConnection {
+            connection_type: CreateConnectionType::Video,
+            from_node_id: node_id.to_string(),
+            from_output_index: 0,
+            to_node_id: self.switcher_node_id.to_string(),
+            to_input_index: switcher_input_index,
+        }];
+        let connect_audio = self.audio_source.is_none();
+        if let (true, Some(audio_node_id)) = (connect_audio, &self.audio_node_id) {
+            connections.push(CreateConnection {
+                connection_type: CreateConnectionType::Audio,
+                from_node_id: node_id.to_string(),
+                from_output_index: 0,
+                to_node_id: audio_node_id.to_string(),
+                to_input_index: 0,
+            });
+        }
+
+        self.state
+            .create_graph(
+                &self.plugin_manager,
+                &self.graph_id,
+                vec![CreateNode {
+                    node_id: node_id.to_string(),
+                    node_type: "ffmpeg_producer".to_string(),
+                    node_name: Some(video.display_name.clone()),
+                    state: Some(serde_json::to_string(&FFmpegProducerState {
+                        file: video.path.clone(),
+                    })?),
+                    configuration: None,
+                    priority: ComputePriority::Normal,
+                    ports: Default::default(),
+                }],
+                connections,
+                false,
+                GraphSafety::default(),
+                FrameFormat::default(),
+            )
+            .await?;
+
+        if connect_audio && self.audio_node_id.is_some() {
+            self.audio_source = Some(node_id.clone());
+        }
+        self.inputs.push(ManagedInput {
+            input: video,
+            node_id,
+            switcher_input_index,
+        });
+
+        Ok(())
+    }
+}
@@ -16,16 +16,26 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
 use abi_stable::{
     sabi_trait::{TD_CanDowncast, TD_Opaque},
-    std_types::{RArc, RSlice, RVec},
+    std_types::{
+        RArc, RBox,
+        ROption::{self, RNone, RSome},
+        RSlice, RVec,
+    },
 };
 use byteorder::{ByteOrder, LittleEndian};
 use phaneron_plugin::{
     traits::AudioFrame_TO, traits::ConsumedAudioFrame_TO, traits::ConsumedVideoFrame_TO,
     traits::LoadedAudioFrame_TO, traits::LoadedVideoFrame_TO, traits::VideoFrame_TO,
-    AudioChannelLayout, AudioFormat, ColourSpec,
+    AudioChannelLayout, AudioFormat, ColourRange, ColourSpec,
 };
+use tracing::{error, warn};
 
 use crate::{
     compute::{
@@ -79,6 +89,7 @@ impl ToRGBA {
     pub fn new(
         context: PhaneronComputeContext,
         colour_spec: &ColourSpec,
+        colour_range: ColourRange,
         reader: Box<dyn Packer>,
     ) -> Self {
         let num_bytes = reader.get_num_bytes();
@@ -86,7 +97,7 @@ impl ToRGBA {
         let total_bytes = reader.get_total_bytes();
         let width = reader.get_width();
         let height = reader.get_height();
-        let loader = Loader::new(context.clone(), colour_spec, reader);
+        let loader = Loader::new(context.clone(), colour_spec, colour_range, reader);
 
         Self {
             context,
@@ -118,7 +129,10 @@ impl phaneron_plugin::traits::ToRGBA for ToRGBA {
         let mut events: Vec<opencl3::event::Event> = vec![];
 
         for input in inputs.as_slice() {
-            let (buffer, event) = self.context.load_frame_to_buffer(input);
+            let (buffer, event) = self
+                .context
+                .load_frame_to_buffer(input)
+                .unwrap_or_else(|err| self.context.fail_frame("Failed to load frame", err));
             buffers.push(buffer);
             events.push(event);
         }
@@ -154,18 +168,32 @@ pub struct FromRGBA {
     num_bytes: Vec<usize>,
     num_bytes_rgba: usize,
     total_bytes: usize,
+    pending_readback: Mutex<Option<PendingReadback>>,
 }
 
+/// A download that has been started by `copy_frame_pipelined` and not yet handed to the consumer.
+struct PendingReadback {
+    /// Written to by the GPU until all of the events have completed.
+    data: Vec<u8>,
+    events: Vec<opencl3::event::Event>,
+    // Keeps the GPU buffers alive until the download has completed.
+    _frame: RBox<ConsumedVideoFrame>,
+}
+
+// Safe to implement because the readback is only accessed through the mutex in FromRGBA
+unsafe impl Send for PendingReadback {}
+
 impl FromRGBA {
     pub fn new(
         context: PhaneronComputeContext,
         colour_spec: &ColourSpec,
+        colour_range: ColourRange,
         writer: Box<dyn Unpacker>,
     ) -> Self {
         let num_bytes = writer.get_num_bytes();
         let num_bytes_rgba = writer.get_num_bytes_rgba();
         let total_bytes = writer.get_total_bytes();
-        let saver = Saver::new(context.clone(), colour_spec, writer);
+        let saver = Saver::new(context.clone(), colour_spec, colour_range, writer);
 
         Self {
             context,
@@ -173,26 +201,12 @@ impl FromRGBA {
             num_bytes,
             num_bytes_rgba,
             total_bytes,
+            pending_readback: Default::default(),
         }
     }
-}
-
-impl phaneron_plugin::traits::FromRGBA for FromRGBA {
-    fn get_num_bytes(&self) -> RVec<usize> {
-        self.num_bytes.clone().into()
-    }
-
-    fn get_num_bytes_rgba(&self) -> usize {
-        self.num_bytes_rgba
-    }
 
-    fn get_total_bytes(&self) -> usize {
-        self.total_bytes
-    }
-
-    fn copy_frame(
+    pub(crate) fn copy_frame(
         &self,
-        _context: &phaneron_plugin::types::FrameContext, // Required to prove that processing has finished
         frame: phaneron_plugin::types::ConsumedVideoFrame,
     ) -> RVec<RVec<u8>> {
         let consumed_video_frame = frame.obj.downcast_into::<ConsumedVideoFrame>().unwrap();
@@ -200,14 +214,123 @@ impl phaneron_plugin::traits::FromRGBA for FromRGBA {
 
         for (i, buffer) in consumed_video_frame.buffers.iter().enumerate() {
             let mut out = vec![0u8; self.num_bytes[i]];
-            self.context
-                .copy_frame_from_buffer(buffer, &mut out, &consumed_video_frame.events);
+            if let Err(err) =
+                self.context
+                    .copy_frame_from_buffer(buffer, &mut out, &consumed_video_frame.events)
+            {
+                error!("Failed to copy frame from the GPU: {err}");
+                self.context.report_error(&err);
+            }
             buffers.push(out.into());
         }
 
         buffers
     }
 
+    pub(crate) fn copy_frame_contiguous(
+        &self,
+        frame: phaneron_plugin::types::ConsumedVideoFrame,
+    ) -> RVec<u8> {
+        let consumed_video_frame = frame.obj.downcast_into::<ConsumedVideoFrame>().unwrap();
+        let mut out = vec![0u8; self.num_bytes.iter().sum()];
+
+        let mut offset = 0;
+        for (i, buffer) in consumed_video_frame.buffers.iter().enumerate() {
+            let num_bytes = self.num_bytes[i];
+            if let Err(err) = self.context.copy_frame_from_buffer(
+                buffer,
+                &mut out[offset..offset + num_bytes],
+                &consumed_video_frame.events,
+            ) {
+                error!("Failed to copy frame from the GPU: {err}");
+                self.context.report_error(&err);
+            }
+            offset += num_bytes;
+        }
+
+        out.into()
+    }
+
+    /// The frame passed in is returned by the next call, which adds one frame of latency.
+    pub(crate) fn copy_frame_pipelined(
+        &self,
+        frame: phaneron_plugin::types::ConsumedVideoFrame,
+    ) -> ROption<RVec<u8>> {
+        let consumed_video_frame = frame.obj.downcast_into::<ConsumedVideoFrame>().unwrap();
+        let mut data = vec![0u8; self.num_bytes.iter().sum()];
+        let mut events = Vec::with_capacity(consumed_video_frame.buffers.len());
+
+        let mut offset = 0;
+        for (i, buffer) in consumed_video_frame.buffers.iter().enumerate() {
+            let num_bytes = self.num_bytes[i];
+            // The data is held in the pending readback until the events have completed
+            let event = unsafe {
+                self.context.start_copy_frame_from_buffer(
+                    buffer,
+                    &mut data[offset..offset + num_bytes],
+                    &consumed_video_frame.events,
+                )
+            }
+            .unwrap_or_else(|err| {
+                self.context
+                    .fail_frame("Failed to start copying frame from the GPU", err)
+            });
+            events.push(event);
+            offset += num_bytes;
+        }
+
+        let previous = self
+            .pending_readback
+            .lock()
+            .unwrap()
+            .replace(PendingReadback {
+                data,
+                events,
+                _frame: consumed_video_frame,
+            });
+
+        match previous {
+            Some(previous) => {
+                for event in previous.events {
+                    if let Err(err) = self.context.wait_for_event(event) {
+                        error!("Failed to copy frame from the GPU: {err}");
+                        self.context.report_error(&err);
+                    }
+                }
+                RSome(previous.data.into())
+            }
+            None => RNone,
+        }
+    }
+}
+
+impl Drop for FromRGBA {
+    fn drop(&mut self) {
+        // The GPU may still be writing into a pending readback, so wait for it before freeing the data
+        if let Some(pending) = self.pending_readback.get_mut().unwrap().take() {
+            for event in pending.events {
+                if let Err(err) = self.context.wait_for_event(event) {
+                    error!("Failed to copy frame from the GPU: {err}");
+                    self.context.report_error(&err);
+                }
+            }
+        }
+    }
+}
+
+impl phaneron_plugin::traits::FromRGBA for FromRGBA {
+    fn get_num_bytes(&self) -> RVec<usize> {
+        self.num_bytes.clone().into()
+    }
+
+    fn get_num_bytes_rgba(&self) -> usize {
+        self.num_bytes_rgba
+    }
+
+    fn get_total_bytes(&self) -> usize {
+        self.total_bytes
+    }
+
     fn process_frame(
         &self,
         _context: &phaneron_plugin::types::ProcessFrameContext,
@@ -304,13 +427,7 @@ impl phaneron_plugin::traits::ToAudioF32 for ToAudioF32 {
         &self,
         source: phaneron_plugin::types::LoadedAudioFrame,
     ) -> phaneron_plugin::types::AudioFrame {
-        let num_channels: usize = match self.channel_layout {
-            AudioChannelLayout::Mono => 1,
-            AudioChannelLayout::L => 1,
-            AudioChannelLayout::R => 1,
-            AudioChannelLayout::L_R => 2,
-            AudioChannelLayout::R_L => 2,
-        };
+        let num_channels = self.channel_layout.num_channels();
         let bytes_per_sample: usize = match self.audio_format {
             AudioFormat::I16 => 2,
             AudioFormat::U16 => 2,
@@ -321,17 +438,17 @@ impl phaneron_plugin::traits::ToAudioF32 for ToAudioF32 {
         let frame = source.obj.downcast_into::<LoadedAudioFrame>().unwrap();
         let mut processed_buffers: Vec<Vec<f32>> = Vec::with_capacity(num_channels);
         for i in 0..num_channels {
+            // Samples are interleaved, gather the bytes of this channel's samples
             let buffer: Vec<u8> = frame
                 .audio
-                .iter()
-                .skip(i * bytes_per_sample)
-                .step_by((bytes_per_sample * i).max(1))
+                .chunks_exact(bytes_per_sample * num_channels)
+                .flat_map(|samples| &samples[i * bytes_per_sample..(i + 1) * bytes_per_sample])
                 .copied()
                 .collect();
             match self.audio_format {
                 AudioFormat::I16 => {
                     let mut grouped_sample_buffer: Vec<i16> =
-                        vec![0i16; (buffer.len() / num_channels) / bytes_per_sample];
+                        vec![0i16; buffer.len() / bytes_per_sample];
                     LittleEndian::read_i16_into(&buffer, &mut grouped_sample_buffer);
                     let processed_buffer: Vec<f32> = grouped_sample_buffer
                         .iter()
@@ -341,7 +458,7 @@ impl phaneron_plugin::traits::ToAudioF32 for ToAudioF32 {
                 }
                 AudioFormat::U16 => {
                     let mut grouped_sample_buffer: Vec<u16> =
-                        vec![0u16; (buffer.len() / num_channels) / bytes_per_sample];
+                        vec![0u16; buffer.len() / bytes_per_sample];
                     LittleEndian::read_u16_into(&buffer, &mut grouped_sample_buffer);
                     let processed_buffer: Vec<f32> = grouped_sample_buffer
                         .iter()
@@ -351,7 +468,7 @@ impl phaneron_plugin::traits::ToAudioF32 for ToAudioF32 {
                 }
                 AudioFormat::I32 => {
                     let mut grouped_sample_buffer: Vec<i32> =
-                        vec![0i32; (buffer.len() / num_channels) / bytes_per_sample];
+                        vec![0i32; buffer.len() / bytes_per_sample];
                     LittleEndian::read_i32_into(&buffer, &mut grouped_sample_buffer);
                     let processed_buffer: Vec<f32> = grouped_sample_buffer
                         .iter()
@@ -361,7 +478,7 @@ impl phaneron_plugin::traits::ToAudioF32 for ToAudioF32 {
                 }
                 AudioFormat::F32 => {
                     let mut grouped_sample_buffer: Vec<f32> =
-                        vec![0f32; (buffer.len() / num_channels) / bytes_per_sample];
+                        vec![0f32; buffer.len() / bytes_per_sample];
                     LittleEndian::read_f32_into(&buffer, &mut grouped_sample_buffer);
                     processed_buffers.push(grouped_sample_buffer);
                 }
@@ -369,7 +486,11 @@ impl phaneron_plugin::traits::ToAudioF32 for ToAudioF32 {
         }
 
         RArc::new(AudioFrame_TO::from_value(
-            AudioFrame::new(AudioFrameId::default(), processed_buffers),
+            AudioFrame::new(
+                AudioFrameId::default(),
+                self.channel_layout,
+                processed_buffers,
+            ),
             TD_CanDowncast,
         ))
     }
@@ -378,6 +499,8 @@ impl phaneron_plugin::traits::ToAudioF32 for ToAudioF32 {
 pub struct FromAudioF32 {
     audio_format: AudioFormat,
     channel_layout: AudioChannelLayout,
+    warned_empty_frame: AtomicBool,
+    warned_channel_mismatch: AtomicBool,
 }
 
 impl FromAudioF32 {
@@ -385,26 +508,57 @@ impl FromAudioF32 {
         Self {
             audio_format,
             channel_layout,
+            warned_empty_frame: AtomicBool::new(false),
+            warned_channel_mismatch: AtomicBool::new(false),
         }
     }
+
+    pub(crate) fn copy_frame(
+        &self,
+        mut frame: phaneron_plugin::types::ConsumedAudioFrame,
+    ) -> RVec<u8> {
+        let frame = std::mem::take(frame.obj.downcast_as_mut::<ConsumedAudioFrame>().unwrap());
+        frame.buffer.into()
+    }
 }
 
 impl phaneron_plugin::traits::FromAudioF32 for FromAudioF32 {
     fn process_frame(
         &self,
-        _context: &phaneron_plugin::types::ProcessFrameContext,
+        context: &phaneron_plugin::types::ProcessFrameContext,
         frame: phaneron_plugin::types::AudioFrame,
     ) -> phaneron_plugin::types::ConsumedAudioFrame {
-        let num_channels: usize = match self.channel_layout {
-            AudioChannelLayout::Mono => 1,
-            AudioChannelLayout::L => 1,
-            AudioChannelLayout::R => 1,
-            AudioChannelLayout::L_R => 2,
-            AudioChannelLayout::R_L => 2,
+        let num_channels = self.channel_layout.num_channels();
+
+        // Some decoders emit zero-sample frames at stream boundaries, treat these as silence.
+        let num_samples = frame.buffers().iter().map(|buffer| buffer.len()).min();
+        let frame = match num_samples {
+            Some(num_samples) if num_samples > 0 => frame,
+            _ => {
+                if !self.warned_empty_frame.swap(true, Ordering::Relaxed) {
+                    warn!("Received an empty audio frame, substituting silence");
+                }
+                // The graph's silence frame is as long as a frame of the graph
+                let num_samples = context
+                    .get_silence_frame()
+                    .frame
+                    .buffers()
+                    .first()
+                    .map_or(0, |buffer| buffer.len());
+                let silence =
+                    AudioFrame::silence(AudioFrameId::default(), self.channel_layout, num_samples);
+                RArc::new(AudioFrame_TO::from_value(silence, TD_CanDowncast))
+            }
         };
 
-        if frame.buffers().len() != num_channels {
-            todo!("Return a reasonable error")
+        if frame.buffers().len() != num_channels
+            && !self.warned_channel_mismatch.swap(true, Ordering::Relaxed)
+        {
+            warn!(
+                "Received {:?} audio for a {:?} output, remixing",
+                frame.channel_layout(),
+                self.channel_layout
+            );
         }
 
         let num_bytes: usize = match self.audio_format {
@@ -414,17 +568,22 @@ impl phaneron_plugin::traits::FromAudioF32 for FromAudioF32 {
             AudioFormat::F32 => 4,
         };
 
-        let num_samples = frame.buffers().get(0).unwrap().len();
+        let num_samples = frame
+            .buffers()
+            .iter()
+            .map(|buffer| buffer.len())
+            .min()
+            .unwrap_or_default();
 
         let mut bytes = vec![0u8; num_bytes];
         let mut output_buffer = vec![0u8; num_channels * num_bytes * num_samples];
 
         for sample_index in 0..num_samples {
             for channel_index in 0..num_channels {
-                let sample = &frame.buffers()[channel_index][sample_index];
+                let sample = mix_sample(frame.buffers(), num_channels, channel_index, sample_index);
                 match self.audio_format {
                     AudioFormat::I16 => {
-                        let sample = f64::round(*sample as f64 * i16::MAX as f64) as i16;
+                        let sample = f64::round(sample as f64 * i16::MAX as f64) as i16;
                         LittleEndian::write_i16_into(&[sample], &mut bytes);
                     }
                     AudioFormat::U16 => {
@@ -433,10 +592,10 @@ impl phaneron_plugin::traits::FromAudioF32 for FromAudioF32 {
                         LittleEndian::write_u16_into(&[sample], &mut bytes);
                     }
                     AudioFormat::F32 => {
-                        LittleEndian::write_f32_into(&[*sample], &mut bytes);
+                        LittleEndian::write_f32_into(&[sample], &mut bytes);
                     }
                     AudioFormat::I32 => {
-                        let sample = f64::round(*sample as f64 * i32::MAX as f64) as i32;
+                        let sample = f64::round(sample as f64 * i32::MAX as f64) as i32;
                         LittleEndian::write_i32_into(&[sample], &mut bytes);
                     }
                 }
@@ -453,13 +612,30 @@ impl phaneron_plugin::traits::FromAudioF32 for FromAudioF32 {
             TD_CanDowncast,
         )
     }
+}
 
-    fn copy_frame(
-        &self,
-        _context: &phaneron_plugin::types::FrameContext,
-        mut frame: phaneron_plugin::types::ConsumedAudioFrame,
-    ) -> RVec<u8> {
-        let frame = std::mem::take(frame.obj.downcast_as_mut::<ConsumedAudioFrame>().unwrap());
-        frame.buffer.into()
+/// Gets a sample of `channel_index` of an output with `num_channels` channels from `buffers`.
+/// When the channel counts differ mono is copied to every channel, several channels are averaged
+/// into mono, and otherwise channels are matched by position with missing channels left silent.
+fn mix_sample(
+    buffers: &RVec<RVec<f32>>,
+    num_channels: usize,
+    channel_index: usize,
+    sample_index: usize,
+) -> f32 {
+    if buffers.len() == num_channels {
+        buffers[channel_index][sample_index]
+    } else if buffers.len() == 1 {
+        buffers[0][sample_index]
+    } else if num_channels == 1 {
+        buffers
+            .iter()
+            .map(|buffer| buffer[sample_index])
+            .sum::<f32>()
+            / buffers.len() as f32
+    } else {
+        buffers
+            .get(channel_index)
+            .map_or(0.0, |buffer| buffer[sample_index])
     }
 }
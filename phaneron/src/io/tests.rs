@@ -22,17 +22,20 @@ use abi_stable::{
 };
 use byteorder::{ByteOrder, LittleEndian};
 use phaneron_plugin::{
-    traits::FromAudioF32 as FromAudioF32Trait, traits::ProcessFrameContext_TO,
-    traits::ToAudioF32 as ToAudioF32Trait, AudioChannelLayout, AudioFormat, AudioFrameWithId,
-    AudioOutputId, VideoFrameWithId, VideoOutputId,
+    traits::FrameContext as FrameContextTrait, traits::FromAudioF32 as FromAudioF32Trait,
+    traits::FromAudioF32_TO, traits::ProcessFrameContext_TO, traits::ToAudioF32 as ToAudioF32Trait,
+    types::ProcessFrameContext, AudioChannelLayout, AudioFormat, AudioFrameWithId, AudioOutputId,
+    FrameMetadata, VideoFrameWithId, VideoOutputId,
 };
 
-use crate::{io::FromAudioF32, node_context::ProcessFrameContextImpl};
+use crate::{graph::FrameFormat, io::FromAudioF32, node_context::ProcessFrameContextImpl};
 
 use super::ToAudioF32;
 
 #[derive(Default)]
-struct TestVideoFrame {}
+struct TestVideoFrame {
+    metadata: FrameMetadata,
+}
 impl phaneron_plugin::traits::VideoFrame for TestVideoFrame {
     fn buffer_index(&self) -> usize {
         0
@@ -45,6 +48,17 @@ impl phaneron_plugin::traits::VideoFrame for TestVideoFrame {
     fn height(&self) -> usize {
         1080
     }
+
+    fn metadata(&self) -> &FrameMetadata {
+        &self.metadata
+    }
+
+    fn with_metadata(&self, metadata: FrameMetadata) -> phaneron_plugin::types::VideoFrame {
+        RArc::new(phaneron_plugin::traits::VideoFrame_TO::from_value(
+            TestVideoFrame { metadata },
+            TD_Opaque,
+        ))
+    }
 }
 
 #[derive(Default)]
@@ -55,25 +69,25 @@ impl phaneron_plugin::traits::AudioFrame for TestAudioFrame {
     fn buffers<'_self>(&self) -> &abi_stable::std_types::RVec<abi_stable::std_types::RVec<f32>> {
         &self.buffers
     }
+
+    fn channel_layout(&self) -> AudioChannelLayout {
+        AudioChannelLayout::from_num_channels(self.buffers.len()).unwrap_or_default()
+    }
 }
 
-#[test]
-fn from_i32_mono() {
-    let to_audio_f32 = ToAudioF32::new(AudioFormat::I32, AudioChannelLayout::Mono);
-    let audio = vec![i32::MAX; 1024];
-    let mut audio_buf = vec![0u8; 1024 * 4];
-    LittleEndian::write_i32_into(&audio, &mut audio_buf);
-    let loaded = to_audio_f32.load_frame(&audio_buf.as_slice().into());
-    let processed = to_audio_f32.process_frame(loaded);
-    assert_eq!(processed.buffers().get(0).unwrap(), &vec![1.0f32; 1024]);
-    let from_audio_f32 = FromAudioF32::new(AudioFormat::U16, AudioChannelLayout::Mono);
+/// Samples in the silence frame of the process frame context, a frame at 48 kHz and 25 fps.
+const SILENCE_SAMPLES: usize = 1920;
+
+fn create_process_frame_context() -> ProcessFrameContext {
     let black_frame = TestVideoFrame::default();
     let black_frame = RArc::new(phaneron_plugin::traits::VideoFrame_TO::from_value(
         black_frame,
         TD_Opaque,
     ));
     let black_frame = VideoFrameWithId::new(VideoOutputId::default(), black_frame);
-    let silence_frame = TestAudioFrame::default();
+    let silence_frame = TestAudioFrame {
+        buffers: RVec::from(vec![RVec::from(vec![0f32; SILENCE_SAMPLES])]),
+    };
     let silence_frame = RArc::new(phaneron_plugin::traits::AudioFrame_TO::from_value(
         silence_frame,
         TD_Opaque,
@@ -84,9 +98,204 @@ fn from_i32_mono() {
         RHashMap::default(),
         black_frame,
         silence_frame,
+        FrameFormat::default(),
+        0,
     );
-    let process_context = ProcessFrameContext_TO::from_value(process_context, TD_CanDowncast);
+    ProcessFrameContext_TO::from_value(process_context, TD_CanDowncast)
+}
+
+#[test]
+fn from_i32_mono() {
+    let to_audio_f32 = ToAudioF32::new(AudioFormat::I32, AudioChannelLayout::Mono);
+    let audio = vec![i32::MAX; 1024];
+    let mut audio_buf = vec![0u8; 1024 * 4];
+    LittleEndian::write_i32_into(&audio, &mut audio_buf);
+    let loaded = to_audio_f32.load_frame(&audio_buf.as_slice().into());
+    let processed = to_audio_f32.process_frame(loaded);
+    assert_eq!(processed.buffers().get(0).unwrap(), &vec![1.0f32; 1024]);
+    let from_audio_f32 = FromAudioF32_TO::from_value(
+        FromAudioF32::new(AudioFormat::U16, AudioChannelLayout::Mono),
+        TD_CanDowncast,
+    );
+    let process_context = create_process_frame_context();
     let processed = from_audio_f32.process_frame(&process_context, processed);
-    let frame = from_audio_f32.copy_frame(&process_context.submit().unwrap(), processed);
+    let frame = process_context
+        .submit()
+        .unwrap()
+        .copy_audio_frame(&from_audio_f32, processed);
     assert_eq!(frame, vec![255u8; 1024 * 2]);
 }
+
+#[test]
+fn from_empty_frame_is_silence() {
+    let from_audio_f32 = FromAudioF32_TO::from_value(
+        FromAudioF32::new(AudioFormat::I16, AudioChannelLayout::Mono),
+        TD_CanDowncast,
+    );
+    let empty_frames = [RVec::new(), RVec::from(vec![RVec::new()])];
+    for buffers in empty_frames {
+        let process_context = create_process_frame_context();
+        let empty_frame = RArc::new(phaneron_plugin::traits::AudioFrame_TO::from_value(
+            TestAudioFrame { buffers },
+            TD_Opaque,
+        ));
+        let processed = from_audio_f32.process_frame(&process_context, empty_frame);
+        let frame = process_context
+            .submit()
+            .unwrap()
+            .copy_audio_frame(&from_audio_f32, processed);
+        assert_eq!(frame, vec![0u8; SILENCE_SAMPLES * 2]);
+    }
+}
+
+/// Converts 32 bit float audio into `audio_format` with `FromAudioF32`, then back with `ToAudioF32`.
+fn round_trip(
+    audio_format: AudioFormat,
+    channel_layout: AudioChannelLayout,
+    buffers: Vec<Vec<f32>>,
+) -> (Vec<u8>, Vec<Vec<f32>>) {
+    let from_audio_f32 = FromAudioF32_TO::from_value(
+        FromAudioF32::new(audio_format, channel_layout),
+        TD_CanDowncast,
+    );
+    let frame = RArc::new(phaneron_plugin::traits::AudioFrame_TO::from_value(
+        TestAudioFrame {
+            buffers: buffers.into_iter().map(RVec::from).collect(),
+        },
+        TD_Opaque,
+    ));
+    let process_context = create_process_frame_context();
+    let processed = from_audio_f32.process_frame(&process_context, frame);
+    let bytes = process_context
+        .submit()
+        .unwrap()
+        .copy_audio_frame(&from_audio_f32, processed)
+        .to_vec();
+
+    let to_audio_f32 = ToAudioF32::new(audio_format, channel_layout);
+    let loaded = to_audio_f32.load_frame(&bytes.as_slice().into());
+    let processed = to_audio_f32.process_frame(loaded);
+    let buffers = processed
+        .buffers()
+        .iter()
+        .map(|buffer| buffer.to_vec())
+        .collect();
+
+    (bytes, buffers)
+}
+
+#[test]
+fn audio_formats_round_trip() {
+    let formats = [
+        (AudioFormat::I16, 2, 1.0 / i16::MAX as f32),
+        (AudioFormat::U16, 2, 2.0 / u16::MAX as f32),
+        (AudioFormat::I32, 4, 1e-6),
+        (AudioFormat::F32, 4, 0.0),
+    ];
+    let layouts = [(AudioChannelLayout::Mono, 1), (AudioChannelLayout::L_R, 2)];
+    let samples = [-1.0, -0.5, -0.125, 0.0, 0.25, 0.75, 1.0];
+
+    for (audio_format, bytes_per_sample, tolerance) in formats {
+        for (channel_layout, num_channels) in layouts {
+            // Each channel is different so that swapped or mixed up channels are detected
+            let buffers: Vec<Vec<f32>> = (0..num_channels)
+                .map(|channel| {
+                    samples
+                        .iter()
+                        .map(|sample| if channel == 0 { *sample } else { -sample / 2.0 })
+                        .collect()
+                })
+                .collect();
+
+            let (bytes, round_tripped) = round_trip(audio_format, channel_layout, buffers.clone());
+
+            let case = format!("{audio_format:?} {channel_layout:?}");
+            assert_eq!(
+                bytes.len(),
+                samples.len() * num_channels * bytes_per_sample,
+                "{case}"
+            );
+            assert_eq!(round_tripped.len(), num_channels, "{case}");
+            for (expected, actual) in buffers.iter().zip(round_tripped.iter()) {
+                assert_eq!(actual.len(), expected.len(), "{case}");
+                for (expected, actual) in expected.iter().zip(actual.iter()) {
+                    assert!(
+                        (expected - actual).abs() <= tolerance,
+                        "{case}: expected {expected}, got {actual}"
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn stereo_source_survives_consumer() {
+    // A stereo source, as a producer would create it from interleaved 16 bit samples
+    let left = [0.5f32, 0.25, -0.25, -0.5];
+    let right = [-1.0f32, -0.75, 0.75, 1.0];
+    let interleaved: Vec<i16> = left
+        .iter()
+        .zip(right.iter())
+        .flat_map(|(left, right)| [left, right])
+        .map(|sample| (sample * i16::MAX as f32).round() as i16)
+        .collect();
+    let mut source_buf = vec![0u8; interleaved.len() * 2];
+    LittleEndian::write_i16_into(&interleaved, &mut source_buf);
+    let to_audio_f32 = ToAudioF32::new(AudioFormat::I16, AudioChannelLayout::L_R);
+    let loaded = to_audio_f32.load_frame(&source_buf.as_slice().into());
+    let source = to_audio_f32.process_frame(loaded);
+    assert_eq!(source.channel_layout(), AudioChannelLayout::L_R);
+    assert_eq!(source.buffers().len(), 2);
+
+    let from_audio_f32 = FromAudioF32_TO::from_value(
+        FromAudioF32::new(AudioFormat::F32, AudioChannelLayout::L_R),
+        TD_CanDowncast,
+    );
+    let process_context = create_process_frame_context();
+    let processed = from_audio_f32.process_frame(&process_context, source);
+    let bytes = process_context
+        .submit()
+        .unwrap()
+        .copy_audio_frame(&from_audio_f32, processed);
+    let mut consumed = vec![0f32; bytes.len() / 4];
+    LittleEndian::read_f32_into(&bytes, &mut consumed);
+
+    assert_eq!(consumed.len(), left.len() * 2);
+    for (index, samples) in consumed.chunks_exact(2).enumerate() {
+        assert!((samples[0] - left[index]).abs() <= 1.0 / i16::MAX as f32);
+        assert!((samples[1] - right[index]).abs() <= 1.0 / i16::MAX as f32);
+    }
+}
+
+#[test]
+fn mismatched_channels_are_remixed() {
+    let stereo = vec![vec![0.5, -0.5], vec![0.25, 0.5]];
+    let (bytes, _) = round_trip(AudioFormat::F32, AudioChannelLayout::Mono, stereo);
+    let mut mono = vec![0f32; bytes.len() / 4];
+    LittleEndian::read_f32_into(&bytes, &mut mono);
+    assert_eq!(mono, vec![0.375, 0.0]);
+
+    let mono = vec![vec![0.5, -0.5]];
+    let (_, stereo) = round_trip(AudioFormat::F32, AudioChannelLayout::L_R, mono);
+    assert_eq!(stereo, vec![vec![0.5, -0.5], vec![0.5, -0.5]]);
+}
+
+#[test]
+fn empty_frame_is_silence_in_every_channel() {
+    let from_audio_f32 = FromAudioF32_TO::from_value(
+        FromAudioF32::new(AudioFormat::I16, AudioChannelLayout::L_R_C_LFE_Ls_Rs),
+        TD_CanDowncast,
+    );
+    let process_context = create_process_frame_context();
+    let empty_frame = RArc::new(phaneron_plugin::traits::AudioFrame_TO::from_value(
+        TestAudioFrame::default(),
+        TD_Opaque,
+    ));
+    let processed = from_audio_f32.process_frame(&process_context, empty_frame);
+    let frame = process_context
+        .submit()
+        .unwrap()
+        .copy_audio_frame(&from_audio_f32, processed);
+    assert_eq!(frame, vec![0u8; SILENCE_SAMPLES * 6 * 2]);
+}
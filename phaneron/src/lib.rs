@@ -19,9 +19,16 @@
 pub use opencl3;
 
 pub use crate::api::initialize_api;
-pub use crate::compute::{audio_output::AudioPipe, create_compute_context};
-pub use crate::graph::{GraphId, NodeId};
+pub use crate::channel::{Channel, ChannelSemaphoreProvider};
+pub use crate::compute::{
+    audio_output::AudioPipe, create_compute_context, device::ComputeDeviceSelection,
+    fence::GpuSyncMode, ComputeError, ComputePriority,
+};
+pub use crate::config::Config;
+pub use crate::graph::{FrameFormat, GraphId, GraphSafety, NodeId};
+pub use crate::inputs::{InputsFile, InputsManager, VideoInput};
 pub use crate::node_context::NodeRunContext;
+pub use crate::saved_graph::SavedGraph;
 pub use plugins::{
     cl_shader_plugin::ClShaderPlugin, DevPluginManifest, PluginLoadType, PluginManager,
 };
@@ -30,13 +37,21 @@ pub use state::{
 };
 
 mod api;
+mod automation;
 mod channel;
 mod colour;
 mod compute;
+mod config;
+mod dot;
 mod format;
 mod graph;
+mod inputs;
 mod io;
 mod load_save;
+mod metrics;
 mod node_context;
 mod plugins;
+mod saved_graph;
+mod snapshot;
 mod state;
+mod templates;
@@ -16,12 +16,13 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use phaneron_plugin::ColourSpec;
+use phaneron_plugin::{ColourRange, ColourSpec};
 
 use crate::{
     colour::{
-        common_space_to_rgb_matrix, gamma_to_linear_lut, linear_to_gamma_lut,
-        rgb_to_common_space_matrix, rgb_to_ycbcr_matrix, ycbcr_to_rgb_matrix,
+        common_space_to_rgb_matrix, full_range_levels, gamma_to_linear_lut, linear_to_gamma_lut,
+        linear_to_gamma_lut_scale, rgb_to_common_space_matrix, rgb_to_ycbcr_matrix,
+        ycbcr_to_rgb_matrix,
     },
     compute::{
         video_frame::{VideoFrame, VideoFrameId},
@@ -43,6 +44,7 @@ impl Loader {
     pub fn new(
         context: PhaneronComputeContext,
         colour_spec: &ColourSpec,
+        colour_range: ColourRange,
         packer: Box<dyn Packer>,
     ) -> Self {
         let rgb = packer.get_is_rgb();
@@ -61,12 +63,20 @@ impl Loader {
         let yuv_to_rgb_matrix = if rgb {
             None
         } else {
+            let (luma_black, luma_white, chroma_range) = match colour_range {
+                ColourRange::Limited => (
+                    packer.get_luma_black(),
+                    packer.get_luma_white(),
+                    packer.get_chroma_range(),
+                ),
+                ColourRange::Full => full_range_levels(packer.get_num_bits()),
+            };
             let yuv_to_rgb_matrix = ycbcr_to_rgb_matrix(
                 colour_spec,
                 packer.get_num_bits(),
-                packer.get_luma_black(),
-                packer.get_luma_white(),
-                packer.get_chroma_range(),
+                luma_black,
+                luma_white,
+                chroma_range,
             );
             let yuv_to_rgb_matrix = yuv_to_rgb_matrix
                 .data
@@ -75,12 +85,18 @@ impl Loader {
                 .cloned()
                 .flatten()
                 .collect::<Vec<f32>>();
-            Some(context.create_loadsave_params_buffer(&yuv_to_rgb_matrix))
+            Some(
+                context
+                    .create_loadsave_params_buffer(&yuv_to_rgb_matrix)
+                    .unwrap(),
+            )
         };
 
-        let shader = context.create_load_shader(kernel);
-        let gamma_lut = context.create_loadsave_params_buffer(&gamma_lut);
-        let gamut_matrix = context.create_loadsave_params_buffer(&gamut_matrix);
+        let shader = context.create_load_shader(kernel).unwrap();
+        let gamma_lut = context.create_loadsave_params_buffer(&gamma_lut).unwrap();
+        let gamut_matrix = context
+            .create_loadsave_params_buffer(&gamut_matrix)
+            .unwrap();
 
         Self {
             context,
@@ -96,7 +112,11 @@ impl Loader {
         let mut execute_kernel = opencl3::kernel::ExecuteKernel::new(&self.shader);
         let mut dest = self
             .context
-            .create_video_frame_buffer(self.packer.get_num_bytes_rgba());
+            .create_video_frame_buffer(self.packer.get_num_bytes_rgba())
+            .unwrap_or_else(|err| {
+                self.context
+                    .fail_frame("Failed to create buffer for loaded frame", err)
+            });
 
         self.packer.get_kernel_params(
             &mut execute_kernel,
@@ -124,13 +144,16 @@ impl Loader {
             .set_global_work_size(self.packer.get_global_work_items());
 
         self.context
-            .run_loadsave_shader(execute_kernel, &source.events);
+            .run_loadsave_shader(execute_kernel, &source.events)
+            .unwrap_or_else(|err| self.context.fail_frame("Failed to run load shader", err));
 
-        let out = self.context.create_image_from_buffer(
-            self.packer.get_width(),
-            self.packer.get_height(),
-            &dest,
-        );
+        let out = self
+            .context
+            .create_image_from_buffer(self.packer.get_width(), self.packer.get_height(), &dest)
+            .unwrap_or_else(|err| {
+                self.context
+                    .fail_frame("Failed to create image for loaded frame", err)
+            });
 
         VideoFrame::new(
             VideoFrameId::default(),
@@ -150,6 +173,7 @@ pub struct Saver {
     shader: opencl3::kernel::Kernel,
     num_bytes: Vec<usize>,
     gamma_lut: opencl3::memory::Buffer<opencl3::types::cl_float>,
+    gamma_lut_scale: f32,
     gamut_matrix: opencl3::memory::Buffer<opencl3::types::cl_float>,
     rgb_to_yuv_matrix: Option<opencl3::memory::Buffer<opencl3::types::cl_float>>,
 }
@@ -158,10 +182,12 @@ impl Saver {
     pub fn new(
         context: PhaneronComputeContext,
         colour_spec: &ColourSpec,
+        colour_range: ColourRange,
         unpacker: Box<dyn Unpacker>,
     ) -> Self {
         let rgb = unpacker.get_is_rgb();
         let gamma_lut = linear_to_gamma_lut(colour_spec);
+        let gamma_lut_scale = linear_to_gamma_lut_scale(colour_spec);
         let gamut_matrix = common_space_to_rgb_matrix(colour_spec);
         let gamut_matrix = gamut_matrix
             .data
@@ -176,12 +202,20 @@ impl Saver {
         let rgb_to_yuv_matrix = if rgb {
             None
         } else {
+            let (luma_black, luma_white, chroma_range) = match colour_range {
+                ColourRange::Limited => (
+                    unpacker.get_luma_black(),
+                    unpacker.get_luma_white(),
+                    unpacker.get_chroma_range(),
+                ),
+                ColourRange::Full => full_range_levels(unpacker.get_num_bits()),
+            };
             let yuv_to_rgb_matrix = rgb_to_ycbcr_matrix(
                 colour_spec,
                 unpacker.get_num_bits(),
-                unpacker.get_luma_black(),
-                unpacker.get_luma_white(),
-                unpacker.get_chroma_range(),
+                luma_black,
+                luma_white,
+                chroma_range,
             );
             let yuv_to_rgb_matrix = yuv_to_rgb_matrix
                 .data
@@ -190,12 +224,18 @@ impl Saver {
                 .cloned()
                 .flatten()
                 .collect::<Vec<f32>>();
-            Some(context.create_loadsave_params_buffer(&yuv_to_rgb_matrix))
+            Some(
+                context
+                    .create_loadsave_params_buffer(&yuv_to_rgb_matrix)
+                    .unwrap(),
+            )
         };
 
-        let shader = context.create_save_shader(kernel);
-        let gamma_lut = context.create_loadsave_params_buffer(&gamma_lut);
-        let gamut_matrix = context.create_loadsave_params_buffer(&gamut_matrix);
+        let shader = context.create_save_shader(kernel).unwrap();
+        let gamma_lut = context.create_loadsave_params_buffer(&gamma_lut).unwrap();
+        let gamut_matrix = context
+            .create_loadsave_params_buffer(&gamut_matrix)
+            .unwrap();
 
         Self {
             context,
@@ -203,6 +243,7 @@ impl Saver {
             shader,
             num_bytes,
             gamma_lut,
+            gamma_lut_scale,
             gamut_matrix,
             rgb_to_yuv_matrix,
         }
@@ -214,15 +255,28 @@ impl Saver {
             Vec::with_capacity(self.num_bytes.len());
 
         for dest_size in self.num_bytes.iter() {
-            dests.push(self.context.create_buffer(*dest_size));
+            dests.push(
+                self.context
+                    .create_buffer(*dest_size)
+                    .unwrap_or_else(|err| {
+                        self.context
+                            .fail_frame("Failed to create buffer for saved frame", err)
+                    }),
+            );
         }
 
-        let buffer = self.context.create_buffer_from_image(
-            self.unpacker.get_width(),
-            self.unpacker.get_height(),
-            self.unpacker.get_num_bytes_rgba(),
-            source,
-        );
+        let buffer = self
+            .context
+            .create_buffer_from_image(
+                self.unpacker.get_width(),
+                self.unpacker.get_height(),
+                self.unpacker.get_num_bytes_rgba(),
+                source,
+            )
+            .unwrap_or_else(|err| {
+                self.context
+                    .fail_frame("Failed to copy frame to be saved", err)
+            });
         self.unpacker
             .get_kernel_params(&mut execute_kernel, &buffer, &mut dests);
 
@@ -231,15 +285,20 @@ impl Saver {
         }
 
         unsafe {
-            execute_kernel.set_arg(&self.gamma_lut);
+            execute_kernel
+                .set_arg(&self.gamma_lut)
+                .set_arg(&self.gamma_lut_scale)
+                .set_arg(&self.gamut_matrix);
         }
 
         execute_kernel
-            // .set_arg(&self.gamut_matrix) // TODO: Colour space transforms
             .set_local_work_size(self.unpacker.get_work_items_per_group())
             .set_global_work_size(self.unpacker.get_global_work_items());
 
-        let save_event = self.context.run_loadsave_shader(execute_kernel, &[]); // TODO: Events
+        let save_event = self
+            .context
+            .run_loadsave_shader(execute_kernel, &[])
+            .unwrap_or_else(|err| self.context.fail_frame("Failed to run save shader", err)); // TODO: Events
 
         ConsumedVideoFrame {
             buffers: dests,
@@ -16,18 +16,22 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{fs, path::Path};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use abi_stable::sabi_trait::TD_Opaque;
 use phaneron::{
-    create_phaneron_state, ClShaderPlugin, CreateConnection, CreateConnectionType, CreateNode,
-    DevPluginManifest, NodeId, PluginLoadType, PluginManager,
+    create_phaneron_state, ClShaderPlugin, ComputePriority, Config, CreateConnection,
+    CreateConnectionType, CreateNode, FrameFormat, GraphSafety, InputsFile, InputsManager, NodeId,
+    PluginManager, SavedGraph,
 };
 use phaneron_plugin::traits::PhaneronPlugin_TO;
 use serde::{Deserialize, Serialize};
-use tracing::info;
+use tracing::{error, info};
 use tracing_subscriber::{prelude::*, EnvFilter};
 
+/// How often the shader directory is checked for changes when `plugins.watch_shaders` is set.
+const SHADER_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
 // TODO: Remove
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -41,12 +45,8 @@ pub struct TraditionalMixerEmulatorState {
     pub active_input: Option<String>,
     pub next_input: Option<String>,
     pub transition: Option<TraditionalMixerEmulatorTransition>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct FFmpegProducerState {
-    pub file: String,
+    #[serde(default)]
+    pub audio_follow: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -56,30 +56,22 @@ pub enum TraditionalMixerEmulatorTransition {
     Mix { position: f32 },
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct InputsFile {
-    videos: Vec<VideoInput>,
-}
-
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct VideoInput {
-    path: String,
-    display_name: String,
-}
-
 #[tokio::main]
 async fn main() {
     #[cfg(debug_assertions)]
     dotenv::dotenv().ok();
 
-    let video_inputs = fs::read_to_string("video_inputs.json")
-        .expect("A file called video_inputs.json should exist in the current directory. [This is a hack for now].");
-    let video_inputs: InputsFile =
-        serde_json::from_str(&video_inputs).expect("video_inputs.json is invalid");
+    let config_path = std::env::var("PHANERON_CONFIG").unwrap_or("phaneron.toml".to_string());
+    let config = Config::load(&PathBuf::from(config_path)).unwrap();
+
+    let video_inputs = InputsFile::load(&config.inputs_file).unwrap_or_else(|err| {
+        panic!(
+            "A valid inputs file should exist at {}: {err}",
+            config.inputs_file.display()
+        )
+    });
     if video_inputs.videos.is_empty() {
-        panic!("video_inputs.json must contain some videos.")
+        panic!("{} must contain some videos.", config.inputs_file.display())
     }
 
     #[cfg(debug_assertions)]
@@ -87,34 +79,10 @@ async fn main() {
         std::env::set_var("RUST_LIB_BACKTRACE", "1")
     }
 
-    if std::env::var("RUST_LOG").is_err() {
-        std::env::set_var("RUST_LOG", "phaneron=info")
-    }
-
-    let develop_plugins = std::env::var("DEVELOP_PLUGINS");
-    let plugin_load_type = match develop_plugins {
-        Ok(_) => {
-            let plugins_cfg_file =
-                std::env::var("PLUGINS_CFG_FILE").unwrap_or("plugins.toml".to_string());
-            let plugins = fs::read_to_string(plugins_cfg_file).unwrap();
-            let plugins: DevPluginManifest = toml::from_str(&plugins).unwrap();
-            PluginLoadType::Development(plugins)
-        }
-        Err(_) => {
-            let plugins_directory =
-                std::env::var("PLUGINS_DIRECTORY").unwrap_or("plugins".to_string());
-            PluginLoadType::Production { plugins_directory }
-        }
-    };
-
-    let shader_plugins_directory =
-        std::env::var("SHADER_PLUGINS_DIR").unwrap_or_else(|_| match &plugin_load_type {
-            PluginLoadType::Development(_) => "phaneron-plugin-shaders".to_string(),
-            PluginLoadType::Production { plugins_directory } => plugins_directory.clone(),
-        });
+    let plugin_load_type = config.plugin_load_type().unwrap();
 
     let stdout_log = tracing_subscriber::fmt::layer().compact();
-    let env_filter = EnvFilter::from_default_env();
+    let env_filter = EnvFilter::new(&config.log_level);
     tracing_subscriber::registry()
         .with(stdout_log.with_filter(env_filter))
         .init();
@@ -125,11 +93,27 @@ async fn main() {
         "Phaneron Copyright (C) 2023 SuperFlyTV AB. This program comes with ABSOLUTELY NO WARRANTY. This is free software, and you are welcome to redistribute it under certain conditions; refer to the LICENSE for details."
     );
 
-    let context = phaneron::create_compute_context().await;
-    let state = create_phaneron_state(context.clone());
+    let context = phaneron::create_compute_context(
+        config.compute.gpu_sync,
+        config.compute.device.clone(),
+        config.compute.max_video_buffers,
+    )
+    .await
+    .unwrap_or_else(|err| panic!("Failed to create a compute context: {err}"));
+    if config.compute.profiling {
+        context
+            .set_profiling(true)
+            .unwrap_or_else(|err| panic!("Failed to enable GPU profiling: {err}"));
+    }
+    let state = create_phaneron_state(
+        context.clone(),
+        config.node_initialize_timeout(),
+        config.graphs,
+    );
 
     info!("Loading plugins");
     let mut plugin_manager = PluginManager::default();
+    plugin_manager.set_log_repeat_windows(config.plugins.log_repeat_window_secs);
     let loaded_plugins = plugin_manager.load_from(plugin_load_type).unwrap();
     info!(
         "Loaded {} plugin{}",
@@ -138,19 +122,28 @@ async fn main() {
     );
 
     let mut shader_plugin = ClShaderPlugin::default();
-    shader_plugin.load_from(&context, Path::new(&shader_plugins_directory).into());
+    shader_plugin.load_from(&context, config.shader_directory());
+    if config.plugins.watch_shaders {
+        shader_plugin.watch(SHADER_WATCH_INTERVAL);
+    }
     plugin_manager
-        .add_plugin(PhaneronPlugin_TO::from_value(shader_plugin, TD_Opaque))
+        .add_reloadable_plugin(
+            PhaneronPlugin_TO::from_value(shader_plugin.clone(), TD_Opaque),
+            Arc::new(shader_plugin),
+        )
         .unwrap();
+    let plugin_manager = Arc::new(plugin_manager);
 
     let graph_id = phaneron::GraphId::new_from("graph1".to_string());
-    let mut create_nodes = vec![
+    let create_nodes = vec![
         CreateNode {
             node_id: "active_input_webrtc_consumer".to_string(),
             node_type: "webrtc_consumer".to_string(),
             node_name: None,
             state: None,
             configuration: None,
+            priority: ComputePriority::Normal,
+            ports: Default::default(),
         },
         CreateNode {
             node_id: "switcher".to_string(),
@@ -161,15 +154,19 @@ async fn main() {
                     active_input: None,
                     next_input: None,
                     transition: None,
+                    audio_follow: false,
                 })
                 .unwrap(),
             ),
             configuration: Some(
                 serde_json::to_string(&TraditionalMixerEmulatorConfiguration {
-                    number_of_inputs: video_inputs.videos.len(),
+                    number_of_inputs: video_inputs.number_of_inputs(),
+                    paired_audio: false,
                 })
                 .unwrap(),
             ),
+            priority: ComputePriority::Normal,
+            ports: Default::default(),
         },
         CreateNode {
             node_id: "flipper".to_string(),
@@ -177,9 +174,11 @@ async fn main() {
             node_name: Some("flip".to_string()),
             state: None,
             configuration: None,
+            priority: ComputePriority::Normal,
+            ports: Default::default(),
         },
     ];
-    let mut connections = vec![
+    let connections = vec![
         CreateConnection {
             connection_type: CreateConnectionType::Video,
             from_node_id: "switcher".to_string(),
@@ -195,44 +194,31 @@ async fn main() {
             to_input_index: 0,
         },
     ];
-    for (index, input) in video_inputs.videos.iter().enumerate() {
-        let ffmpeg_producer_id = phaneron::NodeId::default();
-        create_nodes.push(CreateNode {
-            node_id: ffmpeg_producer_id.to_string(),
-            node_type: "ffmpeg_producer".to_string(),
-            node_name: Some(input.display_name.clone()),
-            state: Some(
-                serde_json::to_string(&FFmpegProducerState {
-                    file: input.path.to_string(),
-                })
-                .unwrap(),
-            ),
-            configuration: None,
-        });
-        connections.push(CreateConnection {
-            connection_type: CreateConnectionType::Video,
-            from_node_id: ffmpeg_producer_id.to_string(),
-            from_output_index: 0,
-            to_node_id: "switcher".to_string(),
-            to_input_index: index,
-        });
-
-        // Connect first audio output
-        if index == 0 {
-            connections.push(CreateConnection {
-                connection_type: CreateConnectionType::Audio,
-                from_node_id: ffmpeg_producer_id.to_string(),
-                from_output_index: 0,
-                to_node_id: "active_input_webrtc_consumer".to_string(),
-                to_input_index: 0,
-            });
-        }
+    // Keep whatever could be created so that the API is still available to fix the graph
+    if let Err(err) = state
+        .create_graph(
+            &plugin_manager,
+            &graph_id,
+            create_nodes,
+            connections,
+            true,
+            GraphSafety::default(),
+            FrameFormat::default(),
+        )
+        .await
+    {
+        error!("{err}");
     }
 
-    state
-        .create_graph(&plugin_manager, &graph_id, create_nodes, connections)
-        .await
-        .unwrap();
+    let inputs_manager = InputsManager::new(
+        state.clone(),
+        plugin_manager.clone(),
+        graph_id.clone(),
+        NodeId::new_from("switcher".to_string()),
+        Some(NodeId::new_from("active_input_webrtc_consumer".to_string())),
+        config.inputs_file.clone(),
+    );
+    inputs_manager.apply(video_inputs.videos).await.unwrap();
 
     let available_inputs = state
         .get_available_video_inputs(&graph_id, &NodeId::new_from("switcher".to_string()))
@@ -253,5 +239,40 @@ async fn main() {
         )
         .await;
 
-    phaneron::initialize_api(state.clone()).await;
+    for path in config.saved_graphs.iter() {
+        let saved = match SavedGraph::load(path) {
+            Ok(saved) => saved,
+            Err(err) => {
+                error!("Failed to load graph from {}: {err}", path.display());
+                continue;
+            }
+        };
+        match state.import_graph(&plugin_manager, saved).await {
+            Ok(_) => info!("Imported graph from {}", path.display()),
+            Err(err) => error!("Failed to import graph from {}: {err:?}", path.display()),
+        }
+    }
+
+    tokio::spawn({
+        let state = state.clone();
+        let plugin_manager = plugin_manager.clone();
+        let device_check_interval = config.device_check_interval();
+        async move {
+            state
+                .watch_compute_device(plugin_manager, device_check_interval)
+                .await
+        }
+    });
+
+    if let Err(err) = phaneron::initialize_api(
+        state.clone(),
+        plugin_manager,
+        Some(inputs_manager),
+        config.bind_address,
+    )
+    .await
+    {
+        error!("{err:#}");
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,118 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::VecDeque,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(test)]
+mod tests;
+
+/// Number of most recent frames the frame rate and processing time are averaged over.
+const METRICS_WINDOW: usize = 50;
+
+/// Counters updated by a node's run loop on every frame, cheap enough to always be collected.
+#[derive(Debug, Default)]
+pub struct NodeMetrics {
+    frames: AtomicU64,
+    upstream_waits: AtomicU64,
+    downstream_waits: AtomicU64,
+    stalled_frames: AtomicU64,
+    /// Completion time and `process_frame` time of the most recent frames, oldest first.
+    recent_frames: std::sync::Mutex<VecDeque<(Instant, Duration)>>,
+}
+
+impl NodeMetrics {
+    /// Records a frame that finished processing at `completed` after spending `process_time` in
+    /// the node's `process_frame`.
+    pub fn record_frame(&self, completed: Instant, process_time: Duration) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        let mut recent_frames = self.recent_frames.lock().unwrap();
+        if recent_frames.len() == METRICS_WINDOW {
+            recent_frames.pop_front();
+        }
+        recent_frames.push_back((completed, process_time));
+    }
+
+    /// The node had to wait for at least one of its inputs to receive a frame.
+    pub fn record_upstream_wait(&self) {
+        self.upstream_waits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The node had to wait for downstream nodes to process its frames before it could continue.
+    pub fn record_downstream_wait(&self) {
+        self.downstream_waits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// An input stalled, so the node processes a held or black frame in its place.
+    pub fn record_stall(&self) {
+        self.stalled_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn report(&self) -> NodeMetricsReport {
+        let (fps, average_process_frame_ns) = {
+            let recent_frames = self.recent_frames.lock().unwrap();
+            let fps = match (recent_frames.front(), recent_frames.back()) {
+                (Some((first, _)), Some((last, _))) if last > first => {
+                    (recent_frames.len() - 1) as f64 / (*last - *first).as_secs_f64()
+                }
+                _ => 0.0,
+            };
+            let average_process_frame_ns = match recent_frames.len() {
+                0 => 0,
+                len => {
+                    let total: Duration = recent_frames.iter().map(|(_, time)| *time).sum();
+                    (total / len as u32).as_nanos() as u64
+                }
+            };
+            (fps, average_process_frame_ns)
+        };
+
+        NodeMetricsReport {
+            frames: self.frames.load(Ordering::Relaxed),
+            fps,
+            average_process_frame_ns,
+            upstream_waits: self.upstream_waits.load(Ordering::Relaxed),
+            downstream_waits: self.downstream_waits.load(Ordering::Relaxed),
+            stalled_frames: self.stalled_frames.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Processing metrics of a node, see [`NodeMetrics`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeMetricsReport {
+    /// Frames processed since the node was created.
+    pub frames: u64,
+    /// Frames processed per second over the most recent frames, `0` until two frames have been processed.
+    pub fps: f64,
+    /// Average time spent in `process_frame` over the most recent frames.
+    pub average_process_frame_ns: u64,
+    /// Frames for which the node waited on an upstream node.
+    pub upstream_waits: u64,
+    /// Frames after which the node waited on downstream nodes, as it was as far ahead of them as
+    /// the graph's pacing allows.
+    pub downstream_waits: u64,
+    /// Times an input stalled and a held or black frame was processed in its place.
+    pub stalled_frames: u64,
+}
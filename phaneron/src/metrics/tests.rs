@@ -0,0 +1,88 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::{Duration, Instant};
+
+use super::{NodeMetrics, METRICS_WINDOW};
+
+#[test]
+fn no_frames() {
+    let report = NodeMetrics::default().report();
+    assert_eq!(report.frames, 0);
+    assert_eq!(report.fps, 0.0);
+    assert_eq!(report.average_process_frame_ns, 0);
+}
+
+#[test]
+fn frame_rate_and_process_time() {
+    let metrics = NodeMetrics::default();
+    let start = Instant::now();
+    for frame in 0..26 {
+        let process_time = Duration::from_millis(if frame % 2 == 0 { 4 } else { 6 });
+        metrics.record_frame(start + Duration::from_millis(40) * frame, process_time);
+    }
+
+    let report = metrics.report();
+    assert_eq!(report.frames, 26);
+    assert!((report.fps - 25.0).abs() < 0.001, "got {}", report.fps);
+    assert_eq!(report.average_process_frame_ns, 5_000_000);
+}
+
+#[test]
+fn only_recent_frames_are_averaged() {
+    let metrics = NodeMetrics::default();
+    let start = Instant::now();
+    // A slow start is forgotten once a full window of frames has been processed since
+    for frame in 0..10 {
+        metrics.record_frame(
+            start + Duration::from_secs(frame),
+            Duration::from_millis(500),
+        );
+    }
+    let start = start + Duration::from_secs(10);
+    for frame in 0..METRICS_WINDOW as u32 {
+        metrics.record_frame(
+            start + Duration::from_millis(20) * frame,
+            Duration::from_millis(2),
+        );
+    }
+
+    let report = metrics.report();
+    assert_eq!(report.frames, 10 + METRICS_WINDOW as u64);
+    assert!((report.fps - 50.0).abs() < 0.001, "got {}", report.fps);
+    assert_eq!(report.average_process_frame_ns, 2_000_000);
+}
+
+#[test]
+fn waits_and_stalls_are_counted() {
+    let metrics = NodeMetrics::default();
+    metrics.record_upstream_wait();
+    metrics.record_upstream_wait();
+    metrics.record_downstream_wait();
+    metrics.record_stall();
+
+    let report = metrics.report();
+    assert_eq!(
+        (
+            report.upstream_waits,
+            report.downstream_waits,
+            report.stalled_frames
+        ),
+        (2, 1, 1)
+    );
+}
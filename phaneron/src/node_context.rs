@@ -16,38 +16,106 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    future::Future,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use abi_stable::{
-    sabi_trait::TD_Opaque,
+    sabi_trait::{TD_CanDowncast, TD_Opaque},
     std_types::{
         RArc, RHashMap, ROption,
         RResult::{self, RErr, ROk},
-        RStr, RString,
+        RStr, RString, RVec,
     },
 };
+use futures::FutureExt;
 use phaneron_plugin::{
-    AudioChannelLayout, AudioFrameWithId, AudioInputId, AudioOutputId, ColourSpec, InterlaceMode,
-    VideoFrameWithId, VideoInputId, VideoOutputId,
+    AudioChannelLayout, AudioFrameWithId, AudioInputId, AudioOutputId, ColourRange, ColourSpec,
+    FrameRate, InterlaceMode, VideoFrameWithId, VideoInputId, VideoOutputId,
 };
 use tokio::sync::{
     mpsc::{UnboundedReceiver, UnboundedSender},
     Mutex,
 };
+use tracing::{debug, error};
 
 use crate::{
     channel::{Channel, ChannelSemaphore, ChannelSemaphoreProvider},
     compute::{
         audio_frame::{AudioFrame, AudioFrameId},
         audio_output::{AudioOutput, AudioPipe},
-        video_output::{VideoOutput, VideoPipe},
-        PhaneronComputeContext,
+        video_output::{VideoFormatTap, VideoOutput, VideoOutputFormat, VideoPipe},
+        ComputeError, PhaneronComputeContext,
     },
     format::VideoFormat,
-    graph::NodeId,
+    graph::{FrameFormat, GraphControls, NodeId, SampleCadence, Slate},
     io::{FromAudioF32, FromRGBA, ToAudioF32, ToRGBA},
+    metrics::{NodeMetrics, NodeMetricsReport},
 };
 
+#[cfg(test)]
+mod tests;
+
+/// Inputs that are replaced with black / silence before frames reach the node,
+/// so that a composite can be checked without rewiring it.
+/// Soloing a video input mutes the other video inputs, audio is handled separately.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputMonitor {
+    pub muted_video_inputs: HashSet<VideoInputId>,
+    pub soloed_video_inputs: HashSet<VideoInputId>,
+    pub muted_audio_inputs: HashSet<AudioInputId>,
+    pub soloed_audio_inputs: HashSet<AudioInputId>,
+}
+
+impl InputMonitor {
+    pub fn is_video_input_muted(&self, input_id: &VideoInputId) -> bool {
+        is_muted(
+            &self.muted_video_inputs,
+            &self.soloed_video_inputs,
+            input_id,
+        )
+    }
+
+    pub fn is_audio_input_muted(&self, input_id: &AudioInputId) -> bool {
+        is_muted(
+            &self.muted_audio_inputs,
+            &self.soloed_audio_inputs,
+            input_id,
+        )
+    }
+
+    /// Replaces the frames of muted inputs.
+    pub fn apply(
+        &self,
+        video_frames: &mut HashMap<VideoInputId, VideoFrameWithId>,
+        audio_frames: &mut HashMap<AudioInputId, AudioFrameWithId>,
+        black_frame: &VideoFrameWithId,
+        silence_frame: &AudioFrameWithId,
+    ) {
+        for (input_id, frame) in video_frames.iter_mut() {
+            if self.is_video_input_muted(input_id) {
+                *frame = black_frame.clone();
+            }
+        }
+        for (input_id, frame) in audio_frames.iter_mut() {
+            if self.is_audio_input_muted(input_id) {
+                *frame = silence_frame.clone();
+            }
+        }
+    }
+}
+
+fn is_muted<I: Eq + Hash>(muted: &HashSet<I>, soloed: &HashSet<I>, input_id: &I) -> bool {
+    muted.contains(input_id) || (!soloed.is_empty() && !soloed.contains(input_id))
+}
+
 #[derive(Clone)]
 pub struct NodeRunContext {
     node_id: NodeId,
@@ -66,14 +134,57 @@ impl NodeRunContext {
                 audio_outputs: Default::default(),
                 video_input_ids: Default::default(),
                 video_outputs: Default::default(),
+                video_output_formats: Default::default(),
                 connected_audio_pipes: Default::default(),
                 connected_video_pipes: Default::default(),
+                input_monitor: Default::default(),
                 state_tx,
                 pending_state: Default::default(),
+                stopped: Default::default(),
+                connections_changed: Default::default(),
+                frames_ahead: Default::default(),
+                metrics: Default::default(),
             },
         }
     }
 
+    /// Requests that the node stops processing frames, the node's run loop will exit
+    /// the next time it checks for this.
+    pub fn stop(&self) {
+        self.inner.stopped.store(true, Ordering::SeqCst);
+        self.inner.connections_changed.notify_one();
+    }
+
+    /// Ends every pipe subscribed to the node's outputs, used when the node is removed.
+    pub async fn close_outputs(&self) {
+        for channel in self.inner.video_outputs.lock().await.values() {
+            channel.close();
+        }
+        for channel in self.inner.audio_outputs.lock().await.values() {
+            channel.close();
+        }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.inner.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once an input has been connected, an output has been subscribed to or the node
+    /// has been stopped. A change that happens while nothing is waiting resolves the next wait.
+    pub async fn connections_changed(&self) {
+        self.inner.connections_changed.notified().await
+    }
+
+    /// Number of frames the node has pushed to its outputs that have not yet been processed by
+    /// every connected node, as of the start of the node's current frame.
+    pub fn frames_ahead(&self) -> usize {
+        self.inner.frames_ahead.load(Ordering::Relaxed)
+    }
+
+    pub fn metrics(&self) -> NodeMetricsReport {
+        self.inner.metrics.report()
+    }
+
     pub async fn get_run_process_frame_context(&self) -> RunProcessFrameContext {
         let connected_audio_pipes = self.inner.connected_audio_pipes.clone();
         let connected_video_pipes = self.inner.connected_video_pipes.clone();
@@ -101,6 +212,14 @@ impl NodeRunContext {
         self.inner.pending_state.lock().await.replace(state);
     }
 
+    pub async fn get_input_monitor(&self) -> InputMonitor {
+        self.inner.input_monitor.lock().await.clone()
+    }
+
+    pub async fn set_input_monitor(&self, input_monitor: InputMonitor) {
+        *self.inner.input_monitor.lock().await = input_monitor;
+    }
+
     pub fn get_pending_state_channel(&self) -> Arc<tokio::sync::Mutex<Option<String>>> {
         self.inner.pending_state.clone()
     }
@@ -152,12 +271,18 @@ impl NodeRunContext {
         &self,
         output_id: VideoOutputId,
         channel: Channel<phaneron_plugin::types::VideoFrame>,
+        format_tap: VideoFormatTap,
     ) {
         self.inner
             .video_outputs
             .lock()
             .await
             .insert(output_id.clone(), channel.clone());
+        self.inner
+            .video_output_formats
+            .lock()
+            .await
+            .insert(output_id.clone(), format_tap);
         self.inner
             .state_tx
             .send(NodeStateEvent::VideoOutputAdded(
@@ -188,6 +313,7 @@ impl NodeRunContext {
         }
 
         connected_video_pipes.insert(to_video_input.clone(), (video_pipe.id.clone(), video_pipe));
+        self.inner.connections_changed.notify_one();
 
         Ok(())
     }
@@ -213,10 +339,61 @@ impl NodeRunContext {
         }
 
         connected_audio_pipes.insert(to_audio_input.clone(), (audio_pipe.id.clone(), audio_pipe));
+        self.inner.connections_changed.notify_one();
 
         Ok(())
     }
 
+    pub async fn disconnect_video_pipe(&self, video_input: &VideoInputId) -> Option<VideoOutputId> {
+        self.inner
+            .connected_video_pipes
+            .lock()
+            .await
+            .remove(video_input)
+            .map(|(video_output_id, _)| video_output_id)
+    }
+
+    pub async fn disconnect_audio_pipe(&self, audio_input: &AudioInputId) -> Option<AudioOutputId> {
+        self.inner
+            .connected_audio_pipes
+            .lock()
+            .await
+            .remove(audio_input)
+            .map(|(audio_output_id, _)| audio_output_id)
+    }
+
+    /// Moves the pipe connected to `order[i]` onto the i-th video input, `order` must contain each
+    /// of the node's video inputs exactly once. Pipes are moved rather than reconnected so no frames are lost.
+    /// Returns the connections of the node's video inputs after the move.
+    pub async fn reorder_video_inputs(
+        &self,
+        order: &[VideoInputId],
+    ) -> HashMap<VideoInputId, VideoOutputId> {
+        let video_input_ids = self.inner.video_input_ids.lock().await;
+        let mut connected_video_pipes = self.inner.connected_video_pipes.lock().await;
+        reorder_pipes(&video_input_ids, order, &mut connected_video_pipes);
+
+        connected_video_pipes
+            .iter()
+            .map(|(input, (output, _))| (input.clone(), output.clone()))
+            .collect()
+    }
+
+    /// Audio equivalent of [`NodeRunContext::reorder_video_inputs`].
+    pub async fn reorder_audio_inputs(
+        &self,
+        order: &[AudioInputId],
+    ) -> HashMap<AudioInputId, AudioOutputId> {
+        let audio_input_ids = self.inner.audio_input_ids.lock().await;
+        let mut connected_audio_pipes = self.inner.connected_audio_pipes.lock().await;
+        reorder_pipes(&audio_input_ids, order, &mut connected_audio_pipes);
+
+        connected_audio_pipes
+            .iter()
+            .map(|(input, (output, _))| (input.clone(), output.clone()))
+            .collect()
+    }
+
     #[deprecated]
     pub async fn get_available_audio_inputs(&self) -> Vec<AudioInputId> {
         self.inner.audio_input_ids.lock().await.clone()
@@ -244,15 +421,46 @@ impl NodeRunContext {
     pub async fn get_audio_pipe(&self, audio_output_id: &AudioOutputId) -> AudioPipe {
         let audio_outputs = self.inner.audio_outputs.lock().await;
         let audio_output = audio_outputs.get(audio_output_id).unwrap();
+        let audio_pipe = AudioPipe::new(audio_output_id.clone(), audio_output.subscribe().await);
+        self.inner.connections_changed.notify_one();
 
-        AudioPipe::new(audio_output_id.clone(), audio_output.subscribe().await)
+        audio_pipe
+    }
+
+    /// Format of the most recent frame pushed to a video output, `None` if the output does not exist.
+    /// The inner option is `None` until the output has produced a frame.
+    pub async fn get_video_output_format(
+        &self,
+        video_output_id: &VideoOutputId,
+    ) -> Option<Option<VideoOutputFormat>> {
+        self.inner
+            .video_output_formats
+            .lock()
+            .await
+            .get(video_output_id)
+            .map(|format_tap| *format_tap.lock().unwrap())
     }
 
     pub async fn get_video_pipe(&self, video_output_id: &VideoOutputId) -> VideoPipe {
         let video_outputs = self.inner.video_outputs.lock().await;
         let video_output = video_outputs.get(video_output_id).unwrap();
+        let video_pipe = VideoPipe::new(video_output_id.clone(), video_output.subscribe().await);
+        self.inner.connections_changed.notify_one();
 
-        VideoPipe::new(video_output_id.clone(), video_output.subscribe().await)
+        video_pipe
+    }
+}
+
+fn reorder_pipes<I: Clone + Eq + std::hash::Hash, P>(
+    input_ids: &[I],
+    order: &[I],
+    pipes: &mut HashMap<I, P>,
+) {
+    let moved: Vec<Option<P>> = order.iter().map(|input| pipes.remove(input)).collect();
+    for (input, pipe) in input_ids.iter().zip(moved) {
+        if let Some(pipe) = pipe {
+            pipes.insert(input.clone(), pipe);
+        }
     }
 }
 
@@ -262,10 +470,16 @@ struct NodeRunContextInner {
     audio_outputs: Arc<Mutex<HashMap<AudioOutputId, Channel<phaneron_plugin::types::AudioFrame>>>>,
     video_input_ids: Arc<Mutex<Vec<VideoInputId>>>,
     video_outputs: Arc<Mutex<HashMap<VideoOutputId, Channel<phaneron_plugin::types::VideoFrame>>>>,
+    video_output_formats: Arc<Mutex<HashMap<VideoOutputId, VideoFormatTap>>>,
     connected_audio_pipes: Arc<Mutex<HashMap<AudioInputId, (AudioOutputId, AudioPipe)>>>,
     connected_video_pipes: Arc<Mutex<HashMap<VideoInputId, (VideoOutputId, VideoPipe)>>>,
+    input_monitor: Arc<Mutex<InputMonitor>>,
     state_tx: tokio::sync::mpsc::UnboundedSender<NodeStateEvent>,
     pending_state: Arc<tokio::sync::Mutex<Option<String>>>,
+    stopped: Arc<AtomicBool>,
+    connections_changed: Arc<tokio::sync::Notify>,
+    frames_ahead: Arc<AtomicUsize>,
+    metrics: Arc<NodeMetrics>,
 }
 
 pub struct NodeContextImpl {
@@ -279,6 +493,8 @@ impl NodeContextImpl {
         compute_context: PhaneronComputeContext,
         event_tx: tokio::sync::mpsc::UnboundedSender<NodeEvent>,
         channel_semaphore_provider: ChannelSemaphoreProvider,
+        channel_capacity: usize,
+        ports: NodePorts,
     ) -> Self {
         Self {
             node_id: node_id.clone(),
@@ -287,6 +503,8 @@ impl NodeContextImpl {
                 compute_context,
                 event_tx,
                 channel_semaphore_provider,
+                channel_capacity,
+                ports: std::sync::Mutex::new(ports),
             }),
         }
     }
@@ -294,7 +512,7 @@ impl NodeContextImpl {
 
 impl phaneron_plugin::traits::NodeContext for NodeContextImpl {
     fn add_audio_input(&self) -> AudioInputId {
-        let audio_input_id = AudioInputId::default();
+        let audio_input_id = next_port_id(&mut self.inner.ports.lock().unwrap().audio_inputs);
         self.inner
             .event_tx
             .send(NodeEvent::AudioInputAdded(
@@ -307,7 +525,7 @@ impl phaneron_plugin::traits::NodeContext for NodeContextImpl {
     }
 
     fn add_video_input(&self) -> VideoInputId {
-        let video_input_id = VideoInputId::default();
+        let video_input_id = next_port_id(&mut self.inner.ports.lock().unwrap().video_inputs);
         self.inner
             .event_tx
             .send(NodeEvent::VideoInputAdded(
@@ -320,8 +538,8 @@ impl phaneron_plugin::traits::NodeContext for NodeContextImpl {
     }
 
     fn add_audio_output(&self) -> phaneron_plugin::types::AudioOutput {
-        let audio_output_id = AudioOutputId::default();
-        let channel = Channel::default();
+        let audio_output_id = next_port_id(&mut self.inner.ports.lock().unwrap().audio_outputs);
+        let channel = Channel::with_capacity(self.inner.channel_capacity);
         self.inner
             .event_tx
             .send(NodeEvent::AudioOutputAdded(
@@ -338,19 +556,25 @@ impl phaneron_plugin::traits::NodeContext for NodeContextImpl {
     }
 
     fn add_video_output(&self) -> phaneron_plugin::types::VideoOutput {
-        let video_output_id = VideoOutputId::default();
-        let channel = Channel::default();
+        let video_output_id = next_port_id(&mut self.inner.ports.lock().unwrap().video_outputs);
+        let channel = Channel::with_capacity(self.inner.channel_capacity);
+        let format_tap = VideoFormatTap::default();
         self.inner
             .event_tx
             .send(NodeEvent::VideoOutputAdded(
                 self.node_id.clone(),
                 video_output_id,
                 channel.clone(),
+                format_tap.clone(),
             ))
             .ok(); // If receiver is dropped, not much we can do
 
         phaneron_plugin::traits::VideoOutput_TO::from_value(
-            VideoOutput::new(self.inner.channel_semaphore_provider.clone(), channel),
+            VideoOutput::new(
+                self.inner.channel_semaphore_provider.clone(),
+                channel,
+                format_tap,
+            ),
             TD_Opaque,
         )
     }
@@ -359,12 +583,18 @@ impl phaneron_plugin::traits::NodeContext for NodeContextImpl {
         &self,
         video_format: &phaneron_plugin::VideoFormat,
         colour_spec: &ColourSpec,
+        colour_range: ColourRange,
         width: usize,
         height: usize,
     ) -> phaneron_plugin::types::ToRGBA {
         let reader = video_format.get_reader(width, height);
         phaneron_plugin::traits::ToRGBA_TO::from_value(
-            ToRGBA::new(self.inner.compute_context.clone(), colour_spec, reader),
+            ToRGBA::new(
+                self.inner.compute_context.clone(),
+                colour_spec,
+                colour_range,
+                reader,
+            ),
             TD_Opaque,
         )
     }
@@ -373,14 +603,20 @@ impl phaneron_plugin::traits::NodeContext for NodeContextImpl {
         &self,
         video_format: &phaneron_plugin::VideoFormat,
         colour_spec: &ColourSpec,
+        colour_range: ColourRange,
         width: usize,
         height: usize,
         interlace: InterlaceMode,
     ) -> phaneron_plugin::types::FromRGBA {
         let writer = video_format.get_writer(width, height, interlace);
         phaneron_plugin::traits::FromRGBA_TO::from_value(
-            FromRGBA::new(self.inner.compute_context.clone(), colour_spec, writer),
-            TD_Opaque,
+            FromRGBA::new(
+                self.inner.compute_context.clone(),
+                colour_spec,
+                colour_range,
+                writer,
+            ),
+            TD_CanDowncast,
         )
     }
 
@@ -388,10 +624,19 @@ impl phaneron_plugin::traits::NodeContext for NodeContextImpl {
         &self,
         kernel: RStr<'_>,
         program_name: RStr<'_>,
-    ) -> phaneron_plugin::types::ProcessShader {
+    ) -> RResult<phaneron_plugin::types::ProcessShader, RString> {
         self.inner
             .compute_context
             .create_process_shader(kernel.into(), program_name.into())
+            .map_err(|err| RString::from(err.to_string()))
+            .into()
+    }
+
+    fn report_state(&self, state: RString) {
+        self.inner
+            .event_tx
+            .send(NodeEvent::StateReported(self.node_id.clone(), state.into()))
+            .ok(); // If receiver is dropped, not much we can do
     }
 
     fn create_to_audio_f32(
@@ -412,7 +657,7 @@ impl phaneron_plugin::traits::NodeContext for NodeContextImpl {
     ) -> phaneron_plugin::types::FromAudioF32 {
         phaneron_plugin::traits::FromAudioF32_TO::from_value(
             FromAudioF32::new(audio_format, channel_layout),
-            TD_Opaque,
+            TD_CanDowncast,
         )
     }
 }
@@ -429,10 +674,16 @@ impl Clone for NodeContextImpl {
 #[derive(Debug, Clone)]
 pub enum NodeStateEvent {
     StateChanged(NodeId, String),
+    /// Fields reported by the node, see [`phaneron_plugin::traits::NodeContext::report_state`].
+    StateReported(NodeId, String),
     AudioInputAdded(NodeId, AudioInputId),
     VideoInputAdded(NodeId, VideoInputId),
     AudioOutputAdded(NodeId, AudioOutputId),
     VideoOutputAdded(NodeId, VideoOutputId),
+    /// The upstream node of an input has stopped, the input is no longer connected to the output.
+    AudioInputDisconnected(NodeId, AudioInputId, AudioOutputId),
+    /// The upstream node of an input has stopped, the input is no longer connected to the output.
+    VideoInputDisconnected(NodeId, VideoInputId, VideoOutputId),
 }
 
 #[derive(Debug, Clone)]
@@ -448,7 +699,9 @@ pub enum NodeEvent {
         NodeId,
         VideoOutputId,
         Channel<phaneron_plugin::types::VideoFrame>,
+        VideoFormatTap,
     ),
+    StateReported(NodeId, String),
 }
 
 struct NodeContextInner {
@@ -456,6 +709,28 @@ struct NodeContextInner {
     compute_context: PhaneronComputeContext,
     event_tx: tokio::sync::mpsc::UnboundedSender<NodeEvent>,
     channel_semaphore_provider: ChannelSemaphoreProvider,
+    channel_capacity: usize,
+    /// Ids still to be given to the inputs and outputs the node adds.
+    ports: std::sync::Mutex<NodePorts>,
+}
+
+/// The Ids of a node's inputs and outputs in the order the node added them. Passed when creating a
+/// node so that a recreated node keeps its Ids, inputs and outputs that the node adds beyond these
+/// get new Ids.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NodePorts {
+    pub audio_inputs: Vec<AudioInputId>,
+    pub video_inputs: Vec<VideoInputId>,
+    pub audio_outputs: Vec<AudioOutputId>,
+    pub video_outputs: Vec<VideoOutputId>,
+}
+
+fn next_port_id<T: Default>(ids: &mut Vec<T>) -> T {
+    if ids.is_empty() {
+        T::default()
+    } else {
+        ids.remove(0)
+    }
 }
 
 pub struct RunProcessFrameContext {
@@ -493,6 +768,8 @@ pub struct ProcessFrameContextImpl {
     audio_frames: RHashMap<AudioInputId, AudioFrameWithId>,
     black_frame: VideoFrameWithId,
     silence_frame: AudioFrameWithId,
+    frame_format: FrameFormat,
+    frame_number: u64,
 }
 
 impl ProcessFrameContextImpl {
@@ -501,6 +778,8 @@ impl ProcessFrameContextImpl {
         audio_frames: RHashMap<AudioInputId, AudioFrameWithId>,
         black_frame: VideoFrameWithId,
         silence_frame: AudioFrameWithId,
+        frame_format: FrameFormat,
+        frame_number: u64,
     ) -> Self {
         Self {
             submitted: std::sync::Mutex::default(),
@@ -508,6 +787,8 @@ impl ProcessFrameContextImpl {
             audio_frames,
             black_frame,
             silence_frame,
+            frame_format,
+            frame_number,
         }
     }
 }
@@ -529,6 +810,25 @@ impl phaneron_plugin::traits::ProcessFrameContext for ProcessFrameContextImpl {
         &self.silence_frame
     }
 
+    fn get_frame_rate(&self) -> FrameRate {
+        FrameRate {
+            num: self.frame_format.frame_rate_num,
+            den: self.frame_format.frame_rate_den,
+        }
+    }
+
+    fn get_sample_rate(&self) -> u32 {
+        self.frame_format.sample_rate
+    }
+
+    fn get_frame_number(&self) -> u64 {
+        self.frame_number
+    }
+
+    fn get_audio_block_samples(&self) -> ROption<u32> {
+        self.frame_format.audio_block_samples.into()
+    }
+
     fn submit(&self) -> RResult<phaneron_plugin::types::FrameContext, RString> {
         if *self.submitted.lock().unwrap() {
             return RErr("Already submitted".to_string().into());
@@ -542,7 +842,55 @@ impl phaneron_plugin::traits::ProcessFrameContext for ProcessFrameContextImpl {
 }
 
 pub struct FrameContextImpl {}
-impl phaneron_plugin::traits::FrameContext for FrameContextImpl {}
+impl phaneron_plugin::traits::FrameContext for FrameContextImpl {
+    fn copy_video_frame(
+        &self,
+        from_rgba: &phaneron_plugin::types::FromRGBA,
+        frame: phaneron_plugin::types::ConsumedVideoFrame,
+    ) -> RVec<RVec<u8>> {
+        from_rgba
+            .obj
+            .downcast_as::<FromRGBA>()
+            .unwrap()
+            .copy_frame(frame)
+    }
+
+    fn copy_video_frame_contiguous(
+        &self,
+        from_rgba: &phaneron_plugin::types::FromRGBA,
+        frame: phaneron_plugin::types::ConsumedVideoFrame,
+    ) -> RVec<u8> {
+        from_rgba
+            .obj
+            .downcast_as::<FromRGBA>()
+            .unwrap()
+            .copy_frame_contiguous(frame)
+    }
+
+    fn copy_video_frame_pipelined(
+        &self,
+        from_rgba: &phaneron_plugin::types::FromRGBA,
+        frame: phaneron_plugin::types::ConsumedVideoFrame,
+    ) -> ROption<RVec<u8>> {
+        from_rgba
+            .obj
+            .downcast_as::<FromRGBA>()
+            .unwrap()
+            .copy_frame_pipelined(frame)
+    }
+
+    fn copy_audio_frame(
+        &self,
+        from_audio_f32: &phaneron_plugin::types::FromAudioF32,
+        frame: phaneron_plugin::types::ConsumedAudioFrame,
+    ) -> RVec<u8> {
+        from_audio_f32
+            .obj
+            .downcast_as::<FromAudioF32>()
+            .unwrap()
+            .copy_frame(frame)
+    }
+}
 
 #[derive(Debug)]
 pub enum AudioConnectionError {
@@ -556,10 +904,14 @@ pub enum VideoConnectionError {
     InputAlreadyConnectedTo(VideoInputId, VideoOutputId),
 }
 
+/// `channel_capacity` is the number of frames queued for each node consuming one of the node's outputs.
+/// The node's inputs and outputs take their Ids from `ports` in the order they are added.
 pub async fn create_node_context(
     context: PhaneronComputeContext,
     node_id: NodeId,
     state_tx: UnboundedSender<NodeStateEvent>,
+    channel_capacity: usize,
+    ports: NodePorts,
 ) -> (
     phaneron_plugin::types::NodeContext,
     NodeRunContext,
@@ -574,6 +926,8 @@ pub async fn create_node_context(
         context,
         node_event_tx,
         node_semaphore_provider.clone(),
+        channel_capacity,
+        ports,
     );
     let node_context = RArc::new(phaneron_plugin::traits::NodeContext_TO::from_value(
         node_context,
@@ -588,39 +942,137 @@ pub async fn create_node_context(
     )
 }
 
+/// Creates the frames that `run_node` gives to inputs without a frame of their own, and shows in
+/// place of every video frame while the graph is in panic. Implemented by the compute context,
+/// tests provide frames without a GPU.
+#[async_trait::async_trait]
+pub trait FillFrames: Send + Sync + 'static {
+    fn black_frame(
+        &self,
+        width: usize,
+        height: usize,
+    ) -> Result<phaneron_plugin::types::VideoFrame, ComputeError>;
+
+    async fn colour_frame(
+        &self,
+        width: usize,
+        height: usize,
+        colour: [f32; 4],
+    ) -> Result<phaneron_plugin::types::VideoFrame, ComputeError>;
+
+    /// Passed the errors from creating frames, see [`PhaneronComputeContext::report_error`].
+    fn report_error(&self, err: &ComputeError);
+}
+
+#[async_trait::async_trait]
+impl FillFrames for PhaneronComputeContext {
+    fn black_frame(
+        &self,
+        width: usize,
+        height: usize,
+    ) -> Result<phaneron_plugin::types::VideoFrame, ComputeError> {
+        let frame = self.create_black_frame(width, height)?;
+
+        Ok(RArc::new(
+            phaneron_plugin::traits::VideoFrame_TO::from_value(frame, TD_Opaque),
+        ))
+    }
+
+    async fn colour_frame(
+        &self,
+        width: usize,
+        height: usize,
+        colour: [f32; 4],
+    ) -> Result<phaneron_plugin::types::VideoFrame, ComputeError> {
+        let frame = self
+            .create_colour_frame_async(width, height, colour)
+            .await?;
+
+        Ok(RArc::new(
+            phaneron_plugin::traits::VideoFrame_TO::from_value(frame, TD_Opaque),
+        ))
+    }
+
+    fn report_error(&self, err: &ComputeError) {
+        PhaneronComputeContext::report_error(self, err);
+    }
+}
+
 pub async fn run_node(
-    context: PhaneronComputeContext,
+    context: impl FillFrames,
     node_context: NodeRunContext,
     node: Arc<phaneron_plugin::types::Node>,
-    pending_state: Arc<tokio::sync::Mutex<Option<String>>>,
     node_state_event_tx: tokio::sync::mpsc::UnboundedSender<NodeStateEvent>,
     mut node_event_rx: tokio::sync::mpsc::UnboundedReceiver<NodeEvent>,
     semaphore_provider: ChannelSemaphoreProvider,
+    controls: GraphControls,
 ) {
+    let GraphControls {
+        pause_gate,
+        frame_lead,
+        stall_monitor,
+        panic_slate,
+        clock,
+    } = controls;
+    let frame_format = clock.frame_format();
+    let pending_state = node_context.get_pending_state_channel();
+    // Last frame received on each video input, held while the input is stalled or the graph is paused
+    let mut held_video_frames: HashMap<VideoInputId, VideoFrameWithId> = HashMap::new();
+    // Semaphores of the frames pushed to downstream nodes that they have not yet processed, oldest first
+    let mut frames_ahead: VecDeque<Vec<tokio::sync::oneshot::Receiver<()>>> = VecDeque::new();
     let mut previous_black_frame: Option<(usize, usize, VideoFrameWithId)> = None;
-    let mut previous_silence_frame: Option<AudioFrameWithId> = None;
+    let mut previous_silence_frame: Option<(usize, AudioFrameWithId)> = None;
+    let mut sample_cadence = SampleCadence::new(frame_format);
+    let mut previous_slate_frame: Option<(Slate, usize, usize, VideoFrameWithId)> = None;
+    // Set when an input's upstream node ends, the node then keeps running on black / silence at the
+    // graph's frame rate rather than waiting for a connection. Cleared once an input is connected.
+    let mut upstream_ended = false;
     loop {
+        if node_context.is_stopped() {
+            return;
+        }
+
         let run_node_context = node_context.get_run_process_frame_context().await;
-        if !run_node_context.video_input_ids.is_empty()
-            && run_node_context.connected_video_pipes.lock().await.len()
-                != run_node_context.video_input_ids.len()
-        {
-            // No connections, can't make progress
-            while let Ok(event) = node_event_rx.try_recv() {
-                handle_node_event(event, node_context.clone()).await;
-            }
+        let has_inputs = !run_node_context.video_input_ids.is_empty()
+            || !run_node_context.audio_input_ids.is_empty();
+
+        // While the graph is paused producers wait at the gate, nodes with inputs keep emitting
+        // the last frames they received once per frame of the graph's clock so consumers keep running
+        let paused = pause_gate.is_paused();
+        if paused && !has_inputs {
+            pause_gate.wait_until_resumed().await;
             continue;
         }
-
-        if !run_node_context.audio_input_ids.is_empty()
-            && run_node_context.connected_audio_pipes.lock().await.len()
-                != run_node_context.audio_input_ids.len()
-        {
-            // No connections, can't make progress
+        if paused {
+            clock.next_frame().await;
+        }
+        let connected = !run_node_context
+            .connected_video_pipes
+            .lock()
+            .await
+            .is_empty()
+            || !run_node_context
+                .connected_audio_pipes
+                .lock()
+                .await
+                .is_empty();
+        if has_inputs && !connected {
+            if !upstream_ended {
+                // No connections, can't make progress
+                // Inputs that are not connected receive black / silence once at least one input is connected
+                wait_for_connections(&node_context, &mut node_event_rx).await;
+                continue;
+            }
+            // Nothing paces the node without connections, each black / silence frame waits for the next frame of the graph
+            tokio::select! {
+                _ = clock.next_frame() => {}
+                _ = node_context.connections_changed() => continue,
+            }
             while let Ok(event) = node_event_rx.try_recv() {
                 handle_node_event(event, node_context.clone()).await;
             }
-            continue;
+        } else {
+            upstream_ended = false;
         }
 
         let mut no_connections = false;
@@ -632,9 +1084,7 @@ pub async fn run_node(
 
         if no_connections {
             // No connections, can't make progress
-            while let Ok(event) = node_event_rx.try_recv() {
-                handle_node_event(event, node_context.clone()).await;
-            }
+            wait_for_connections(&node_context, &mut node_event_rx).await;
             continue;
         }
 
@@ -647,9 +1097,7 @@ pub async fn run_node(
 
         if no_connections {
             // No connections, can't make progress
-            while let Ok(event) = node_event_rx.try_recv() {
-                handle_node_event(event, node_context.clone()).await;
-            }
+            wait_for_connections(&node_context, &mut node_event_rx).await;
             continue;
         }
 
@@ -672,75 +1120,153 @@ pub async fn run_node(
         let mut max_height = 1;
 
         let mut upstream_semaphores: Vec<ChannelSemaphore> = vec![];
+        let mut waited_upstream = false;
+        let metrics = &node_context.inner.metrics;
 
         for input_id in run_node_context.audio_input_ids.clone() {
             let mut audio_pipes_lock = run_node_context.connected_audio_pipes.lock().await;
             match audio_pipes_lock.get_mut(&input_id) {
-                Some((pipe_id, pipe)) => match pipe.next_frame().await {
-                    Some((frame, semaphore)) => {
-                        upstream_semaphores.push(semaphore);
-                        audio_frames
-                            .insert(input_id, AudioFrameWithId::new(pipe_id.clone(), frame));
-                    }
-                    None => {
-                        inputs_requiring_silence.push(input_id.clone());
-                        todo!("Tell context to disconnect pipe");
+                Some((pipe_id, pipe)) => {
+                    let next_frame = wait_for(pipe.next_frame(), &mut waited_upstream);
+                    let next_frame =
+                        next_input_frame(paused, stall_monitor.stall_timeout(), next_frame);
+                    match next_frame.await {
+                        Some(Some((frame, semaphore))) => {
+                            stall_monitor.clear(&input_id.to_string());
+                            upstream_semaphores.push(semaphore);
+                            audio_frames
+                                .insert(input_id, AudioFrameWithId::new(pipe_id.clone(), frame));
+                        }
+                        Some(None) => {
+                            stall_monitor.clear(&input_id.to_string());
+                            end_pipe(
+                                &mut *audio_pipes_lock,
+                                &input_id,
+                                |input_id, output_id| {
+                                    NodeStateEvent::AudioInputDisconnected(
+                                        node_context.node_id.clone(),
+                                        input_id,
+                                        output_id,
+                                    )
+                                },
+                                &node_state_event_tx,
+                            );
+                            upstream_ended = true;
+                            inputs_requiring_silence.push(input_id.clone());
+                        }
+                        None => {
+                            if !paused {
+                                stall_monitor.raise(&node_context.node_id, &input_id.to_string());
+                                metrics.record_stall();
+                            }
+                            inputs_requiring_silence.push(input_id.clone());
+                        }
                     }
-                },
-                None => inputs_requiring_silence.push(input_id.clone()),
+                }
+                None => {
+                    stall_monitor.clear(&input_id.to_string());
+                    inputs_requiring_silence.push(input_id.clone());
+                }
             }
         }
 
         for input_id in run_node_context.video_input_ids.clone() {
             let mut video_pipes_lock = run_node_context.connected_video_pipes.lock().await;
             match video_pipes_lock.get_mut(&input_id) {
-                Some((pipe_id, pipe)) => match pipe.next_frame().await {
-                    Some((frame, semaphore)) => {
-                        upstream_semaphores.push(semaphore);
-                        max_width = max_width.max(frame.width());
-                        max_height = max_height.max(frame.height());
-                        video_frames
-                            .insert(input_id, VideoFrameWithId::new(pipe_id.clone(), frame));
-                    }
-                    None => {
-                        inputs_requiring_black_frames.push(input_id);
-                        todo!("Tell context to disconnect pipe");
+                Some((pipe_id, pipe)) => {
+                    let next_frame = wait_for(pipe.next_frame(), &mut waited_upstream);
+                    let next_frame =
+                        next_input_frame(paused, stall_monitor.stall_timeout(), next_frame);
+                    match next_frame.await {
+                        Some(Some((frame, semaphore))) => {
+                            stall_monitor.clear(&input_id.to_string());
+                            upstream_semaphores.push(semaphore);
+                            max_width = max_width.max(frame.width());
+                            max_height = max_height.max(frame.height());
+                            let frame = VideoFrameWithId::new(pipe_id.clone(), frame);
+                            held_video_frames.insert(input_id.clone(), frame.clone());
+                            video_frames.insert(input_id, frame);
+                        }
+                        Some(None) => {
+                            stall_monitor.clear(&input_id.to_string());
+                            held_video_frames.remove(&input_id);
+                            end_pipe(
+                                &mut *video_pipes_lock,
+                                &input_id,
+                                |input_id, output_id| {
+                                    NodeStateEvent::VideoInputDisconnected(
+                                        node_context.node_id.clone(),
+                                        input_id,
+                                        output_id,
+                                    )
+                                },
+                                &node_state_event_tx,
+                            );
+                            upstream_ended = true;
+                            inputs_requiring_black_frames.push(input_id);
+                        }
+                        None => {
+                            if !paused {
+                                stall_monitor.raise(&node_context.node_id, &input_id.to_string());
+                                metrics.record_stall();
+                            }
+                            match held_video_frames.get(&input_id) {
+                                Some(held_frame) => {
+                                    max_width = max_width.max(held_frame.frame.width());
+                                    max_height = max_height.max(held_frame.frame.height());
+                                    video_frames.insert(input_id, held_frame.clone());
+                                }
+                                None => inputs_requiring_black_frames.push(input_id),
+                            }
+                        }
                     }
-                },
+                }
                 None => {
+                    stall_monitor.clear(&input_id.to_string());
+                    held_video_frames.remove(&input_id);
                     inputs_requiring_black_frames.push(input_id);
                 }
             }
         }
 
+        if waited_upstream && !paused {
+            metrics.record_upstream_wait();
+        }
+
         let black_frame = match previous_black_frame.take() {
-            Some((width, height, frame)) => {
-                if max_width > width || max_height > height {
-                    let frame = context.create_black_frame(max_width, max_height);
-                    let frame = RArc::new(phaneron_plugin::traits::VideoFrame_TO::from_value(
-                        frame, TD_Opaque,
-                    ));
-                    VideoFrameWithId::new(VideoOutputId::new_from("black".into()), frame)
-                } else {
-                    frame
-                }
-            }
-            None => {
-                let frame = context.create_black_frame(max_width, max_height);
-                let frame = RArc::new(phaneron_plugin::traits::VideoFrame_TO::from_value(
-                    frame, TD_Opaque,
-                ));
+            Some((width, height, frame)) if max_width <= width && max_height <= height => frame,
+            _ => {
+                let frame = match context.black_frame(max_width, max_height) {
+                    Ok(frame) => frame,
+                    Err(ComputeError::ShutDown) => {
+                        debug!(
+                            "Stopping node {} as the compute context has been shut down",
+                            node_context.node_id
+                        );
+                        return;
+                    }
+                    Err(err) => {
+                        error!(
+                            "Stopping node {} as a black frame could not be created: {err}",
+                            node_context.node_id
+                        );
+                        context.report_error(&err);
+                        return;
+                    }
+                };
                 VideoFrameWithId::new(VideoOutputId::new_from("black".into()), frame)
             }
         };
 
+        let num_samples = sample_cadence.next_samples();
         let silence_frame = match previous_silence_frame.take() {
-            Some(frame) => frame,
-            None => {
-                let frame = AudioFrame::new(
+            Some((samples, frame)) if samples == num_samples => frame,
+            _ => {
+                let frame = AudioFrame::silence(
                     AudioFrameId::new_from("silence".to_string()),
-                    vec![vec![0f32; 48000 / 25]],
-                ); // TODO: Framerate, number of samples, frames
+                    frame_format.channel_layout,
+                    num_samples,
+                );
                 let frame = phaneron_plugin::traits::AudioFrame_TO::from_value(frame, TD_Opaque);
                 AudioFrameWithId::new(AudioOutputId::new_from("silence".into()), RArc::new(frame))
             }
@@ -758,11 +1284,59 @@ pub async fn run_node(
             }
         }
 
+        node_context.get_input_monitor().await.apply(
+            &mut video_frames,
+            &mut audio_frames,
+            &black_frame,
+            &silence_frame,
+        );
+
+        // Consumers show the slate while the graph is in panic
+        let panic = panic_slate
+            .slate()
+            .filter(|_| run_node_context.video_outputs.is_empty());
+        match panic {
+            Some(slate) if !video_frames.is_empty() => {
+                let slate_frame = match previous_slate_frame.take() {
+                    Some((previous, width, height, frame))
+                        if previous == slate && width == max_width && height == max_height =>
+                    {
+                        frame
+                    }
+                    _ => match context
+                        .colour_frame(max_width, max_height, slate.linear_colour())
+                        .await
+                    {
+                        Ok(frame) => {
+                            VideoFrameWithId::new(VideoOutputId::new_from("slate".into()), frame)
+                        }
+                        Err(err) => {
+                            // Kept until the slate or frame size changes so the failure is only logged once
+                            error!(
+                                "Node {} is showing black as the slate could not be created: {err}",
+                                node_context.node_id
+                            );
+                            context.report_error(&err);
+                            black_frame.clone()
+                        }
+                    },
+                };
+                for frame in video_frames.values_mut() {
+                    *frame = slate_frame.clone();
+                }
+                let _ = previous_slate_frame.insert((slate, max_width, max_height, slate_frame));
+            }
+            Some(_) => {}
+            None => previous_slate_frame = None,
+        }
+
         {
             let node = node.clone();
             let silence = silence_frame.clone();
             let black = black_frame.clone();
+            let frame_number = clock.frame();
             let (sender, mut receiver) = tokio::sync::mpsc::channel(1);
+            let started = Instant::now();
             std::thread::spawn(move || {
                 node.process_frame(phaneron_plugin::traits::ProcessFrameContext_TO::from_value(
                     ProcessFrameContextImpl::new(
@@ -770,22 +1344,41 @@ pub async fn run_node(
                         audio_frames.into(),
                         black,
                         silence,
+                        frame_format,
+                        frame_number,
                     ),
                     TD_Opaque,
                 ));
                 sender.blocking_send(()).unwrap();
             });
             receiver.recv().await;
+            metrics.record_frame(Instant::now(), started.elapsed());
         }
 
         let _ = previous_black_frame.insert((max_width, max_height, black_frame));
-        let _ = previous_silence_frame.insert(silence_frame);
+        let _ = previous_silence_frame.insert((num_samples, silence_frame));
 
         let downstream_semaphores = semaphore_provider.drain();
+        if !downstream_semaphores.is_empty() {
+            frames_ahead.push_back(downstream_semaphores);
+        }
 
-        for semaphore in downstream_semaphores {
-            semaphore.await.ok();
+        while frames_ahead.len() >= frame_lead.max_frames_ahead() {
+            let Some(oldest_frame) = frames_ahead.pop_front() else {
+                break;
+            };
+            let mut waited_downstream = false;
+            for semaphore in oldest_frame {
+                wait_for(semaphore, &mut waited_downstream).await.ok();
+            }
+            if waited_downstream {
+                metrics.record_downstream_wait();
+            }
         }
+        node_context
+            .inner
+            .frames_ahead
+            .store(frames_ahead.len(), Ordering::Relaxed);
 
         for semaphore in upstream_semaphores {
             semaphore.signal().await
@@ -797,6 +1390,65 @@ pub async fn run_node(
     }
 }
 
+/// Waits until the node's connections change or the node receives an event, rather than
+/// checking again straight away while the node can't make progress.
+async fn wait_for_connections(
+    node_context: &NodeRunContext,
+    node_event_rx: &mut UnboundedReceiver<NodeEvent>,
+) {
+    tokio::select! {
+        _ = node_context.connections_changed() => {}
+        Some(event) = node_event_rx.recv() => handle_node_event(event, node_context.clone()).await,
+    }
+    while let Ok(event) = node_event_rx.try_recv() {
+        handle_node_event(event, node_context.clone()).await;
+    }
+}
+
+/// Removes the pipe of an input whose upstream node has stopped, so the input receives black or
+/// silence from now on, and tells the state to clear the connection.
+fn end_pipe<I: Clone + Eq + Hash, O, P>(
+    pipes: &mut HashMap<I, (O, P)>,
+    input_id: &I,
+    disconnected: impl FnOnce(I, O) -> NodeStateEvent,
+    node_state_event_tx: &UnboundedSender<NodeStateEvent>,
+) {
+    if let Some((output_id, _)) = pipes.remove(input_id) {
+        debug!("Disconnecting an input as its upstream node has stopped");
+        node_state_event_tx
+            .send(disconnected(input_id.clone(), output_id))
+            .ok();
+    }
+}
+
+/// Awaits `future`, setting `waited` if it wasn't ready straight away.
+async fn wait_for<T>(future: impl Future<Output = T>, waited: &mut bool) -> T {
+    let mut future = std::pin::pin!(future);
+    match future.as_mut().now_or_never() {
+        Some(output) => output,
+        None => {
+            *waited = true;
+            future.await
+        }
+    }
+}
+
+/// Waits for the next frame from a pipe, `None` if the input stalls for longer than `stall_timeout`.
+/// While the graph is paused only a frame that has already been pushed is taken, `None` otherwise.
+async fn next_input_frame<T>(
+    paused: bool,
+    stall_timeout: Option<Duration>,
+    next_frame: impl Future<Output = T>,
+) -> Option<T> {
+    if paused {
+        return next_frame.now_or_never();
+    }
+    match stall_timeout {
+        Some(stall_timeout) => tokio::time::timeout(stall_timeout, next_frame).await.ok(),
+        None => Some(next_frame.await),
+    }
+}
+
 pub async fn handle_node_event(event: NodeEvent, node_context: NodeRunContext) {
     match event {
         NodeEvent::AudioInputAdded(_, audio_input_id) => {
@@ -810,11 +1462,18 @@ pub async fn handle_node_event(event: NodeEvent, node_context: NodeRunContext) {
                 .add_audio_output(audio_output_id, channel)
                 .await;
         }
-        NodeEvent::VideoOutputAdded(_, video_output_id, channel) => {
+        NodeEvent::VideoOutputAdded(_, video_output_id, channel, format_tap) => {
             node_context
-                .add_video_output(video_output_id, channel)
+                .add_video_output(video_output_id, channel, format_tap)
                 .await
         }
+        NodeEvent::StateReported(node_id, state) => {
+            node_context
+                .inner
+                .state_tx
+                .send(NodeStateEvent::StateReported(node_id, state))
+                .ok();
+        }
     }
 }
 
@@ -0,0 +1,603 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use abi_stable::{
+    sabi_trait::TD_Opaque,
+    std_types::{RArc, RHashMap, RString, RVec},
+};
+use phaneron_plugin::{
+    traits::{Node as NodeTrait, Node_TO, ProcessFrameContext_TO, VideoOutput_TO},
+    types::{ProcessFrameContext, VideoOutput},
+    AudioChannelLayout, AudioFrameWithId, AudioInputId, AudioOutputId, FrameMetadata,
+    VideoFrameWithId, VideoInputId, VideoOutputId,
+};
+
+use crate::{
+    channel::{Channel, ChannelSemaphoreProvider},
+    compute::{
+        video_output::{VideoFormatTap, VideoOutput as HostVideoOutput, VideoPipe},
+        ComputeError,
+    },
+    config::GraphsConfig,
+    graph::{
+        FrameFormat, FrameLeadLimit, GraphClock, GraphControls, NodeId, PanicSlate, PauseGate,
+        StallMonitor,
+    },
+};
+
+use super::{
+    next_input_frame, next_port_id, run_node, FillFrames, InputMonitor, NodeEvent, NodeRunContext,
+    NodeStateEvent, ProcessFrameContextImpl,
+};
+
+#[derive(Default)]
+struct TestVideoFrame {
+    metadata: FrameMetadata,
+}
+impl phaneron_plugin::traits::VideoFrame for TestVideoFrame {
+    fn buffer_index(&self) -> usize {
+        0
+    }
+
+    fn width(&self) -> usize {
+        1920
+    }
+
+    fn height(&self) -> usize {
+        1080
+    }
+
+    fn metadata(&self) -> &FrameMetadata {
+        &self.metadata
+    }
+
+    fn with_metadata(&self, metadata: FrameMetadata) -> phaneron_plugin::types::VideoFrame {
+        RArc::new(phaneron_plugin::traits::VideoFrame_TO::from_value(
+            TestVideoFrame { metadata },
+            TD_Opaque,
+        ))
+    }
+}
+
+#[derive(Default)]
+struct TestAudioFrame {
+    buffers: RVec<RVec<f32>>,
+}
+impl phaneron_plugin::traits::AudioFrame for TestAudioFrame {
+    fn buffers(&self) -> &RVec<RVec<f32>> {
+        &self.buffers
+    }
+
+    fn channel_layout(&self) -> AudioChannelLayout {
+        AudioChannelLayout::from_num_channels(self.buffers.len()).unwrap_or_default()
+    }
+}
+
+fn video_frame(output_id: &str) -> VideoFrameWithId {
+    let frame =
+        phaneron_plugin::traits::VideoFrame_TO::from_value(TestVideoFrame::default(), TD_Opaque);
+    VideoFrameWithId::new(VideoOutputId::new_from(output_id.into()), RArc::new(frame))
+}
+
+fn audio_frame(output_id: &str) -> AudioFrameWithId {
+    let frame =
+        phaneron_plugin::traits::AudioFrame_TO::from_value(TestAudioFrame::default(), TD_Opaque);
+    AudioFrameWithId::new(AudioOutputId::new_from(output_id.into()), RArc::new(frame))
+}
+
+#[test]
+fn muted_input_receives_black() {
+    let first = VideoInputId::default();
+    let second = VideoInputId::default();
+    let mut video_frames = HashMap::from([
+        (first.clone(), video_frame("first")),
+        (second.clone(), video_frame("second")),
+    ]);
+    let mut audio_frames = HashMap::new();
+
+    let mut input_monitor = InputMonitor::default();
+    input_monitor.muted_video_inputs.insert(first.clone());
+    input_monitor.apply(
+        &mut video_frames,
+        &mut audio_frames,
+        &video_frame("black"),
+        &audio_frame("silence"),
+    );
+
+    assert_eq!(video_frames[&first].output_id.to_string(), "black");
+    assert_eq!(video_frames[&second].output_id.to_string(), "second");
+}
+
+#[test]
+fn solo_mutes_other_inputs_of_the_same_kind() {
+    let first = VideoInputId::default();
+    let second = VideoInputId::default();
+    let audio = AudioInputId::default();
+    let mut video_frames = HashMap::from([
+        (first.clone(), video_frame("first")),
+        (second.clone(), video_frame("second")),
+    ]);
+    let mut audio_frames = HashMap::from([(audio.clone(), audio_frame("audio"))]);
+
+    let mut input_monitor = InputMonitor::default();
+    input_monitor.soloed_video_inputs.insert(second.clone());
+    input_monitor.apply(
+        &mut video_frames,
+        &mut audio_frames,
+        &video_frame("black"),
+        &audio_frame("silence"),
+    );
+
+    assert_eq!(video_frames[&first].output_id.to_string(), "black");
+    assert_eq!(video_frames[&second].output_id.to_string(), "second");
+    assert_eq!(audio_frames[&audio].output_id.to_string(), "audio");
+}
+
+/// Passes its input on unchanged, like most nodes do when they have nothing to do.
+struct PassthroughNode {
+    video_input: VideoInputId,
+    video_output: VideoOutput,
+}
+impl NodeTrait for PassthroughNode {
+    fn apply_state(&self, _state: RString) -> bool {
+        true
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let frame = frame_context
+            .get_video_input(&self.video_input)
+            .unwrap_or(frame_context.get_black_frame())
+            .frame
+            .clone();
+        let frame_context = frame_context.submit().unwrap();
+        self.video_output.push_frame(&frame_context, frame).ok();
+    }
+}
+
+#[test]
+fn metadata_survives_passthrough_node() {
+    let mut metadata = FrameMetadata::default();
+    metadata.set("timecode", "10:00:00:00");
+    metadata.set("caption", "Hello");
+    let input = video_frame("producer");
+    let input = VideoFrameWithId::new(input.output_id, input.frame.with_metadata(metadata));
+
+    let channel = Channel::default();
+    let mut receiver = futures::executor::block_on(channel.subscribe());
+    let video_input = VideoInputId::default();
+    let node = PassthroughNode {
+        video_input: video_input.clone(),
+        video_output: VideoOutput_TO::from_value(
+            HostVideoOutput::new(
+                ChannelSemaphoreProvider::default(),
+                channel,
+                VideoFormatTap::default(),
+            ),
+            TD_Opaque,
+        ),
+    };
+    let frame_context = ProcessFrameContextImpl::new(
+        [(video_input, input)].into_iter().collect(),
+        RHashMap::default(),
+        video_frame("black"),
+        audio_frame("silence"),
+        FrameFormat::default(),
+        0,
+    );
+    node.process_frame(ProcessFrameContext_TO::from_value(frame_context, TD_Opaque));
+
+    let (output, _) = receiver.try_recv().unwrap();
+    assert_eq!(output.metadata().get("timecode"), Some("10:00:00:00"));
+    assert_eq!(output.metadata().get("caption"), Some("Hello"));
+    assert_eq!(video_frame("black").frame.metadata().get("timecode"), None);
+}
+
+#[test]
+fn ports_keep_their_ids_in_order() {
+    let first = VideoInputId::new_from("first".into());
+    let second = VideoInputId::new_from("second".into());
+    let mut ids = vec![first.clone(), second.clone()];
+
+    assert_eq!(next_port_id(&mut ids), first);
+    assert_eq!(next_port_id(&mut ids), second);
+    // Ports added beyond the recorded ones get new Ids
+    let added = next_port_id(&mut ids);
+    assert_ne!(added, first);
+    assert_ne!(added, second);
+}
+
+#[tokio::test]
+async fn stalled_input_gives_up_after_timeout() {
+    let stalled = next_input_frame(
+        false,
+        Some(std::time::Duration::from_millis(10)),
+        std::future::pending::<()>(),
+    )
+    .await;
+    assert_eq!(stalled, None);
+
+    let delivered = next_input_frame(false, None, async { 1 }).await;
+    assert_eq!(delivered, Some(1));
+}
+
+#[tokio::test]
+async fn paused_input_only_takes_pushed_frames() {
+    let held = next_input_frame(true, None, std::future::pending::<()>()).await;
+    assert_eq!(held, None);
+
+    let pushed = next_input_frame(true, None, async { 1 }).await;
+    assert_eq!(pushed, Some(1));
+}
+
+#[tokio::test]
+async fn subscribing_to_an_output_wakes_the_node() {
+    let (state_tx, _state_rx) = tokio::sync::mpsc::unbounded_channel();
+    let node_context = NodeRunContext::new(NodeId::default(), state_tx);
+    let output_id = VideoOutputId::default();
+    node_context
+        .add_video_output(
+            output_id.clone(),
+            Channel::default(),
+            VideoFormatTap::default(),
+        )
+        .await;
+    let waiting = tokio::spawn({
+        let node_context = node_context.clone();
+        async move { node_context.connections_changed().await }
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    assert!(!waiting.is_finished());
+
+    let _pipe = node_context.get_video_pipe(&output_id).await;
+
+    tokio::time::timeout(std::time::Duration::from_secs(1), waiting)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn closing_outputs_ends_subscribed_pipes() {
+    let (state_tx, _state_rx) = tokio::sync::mpsc::unbounded_channel();
+    let node_context = NodeRunContext::new(NodeId::default(), state_tx);
+    let output_id = VideoOutputId::default();
+    node_context
+        .add_video_output(
+            output_id.clone(),
+            Channel::default(),
+            VideoFormatTap::default(),
+        )
+        .await;
+    let mut pipe = node_context.get_video_pipe(&output_id).await;
+
+    node_context.close_outputs().await;
+
+    assert!(pipe.next_frame().await.is_none());
+}
+
+/// Gives `run_node` frames without a GPU.
+struct TestFillFrames;
+#[async_trait::async_trait]
+impl FillFrames for TestFillFrames {
+    fn black_frame(
+        &self,
+        _width: usize,
+        _height: usize,
+    ) -> Result<phaneron_plugin::types::VideoFrame, ComputeError> {
+        Ok(video_frame("black").frame)
+    }
+
+    async fn colour_frame(
+        &self,
+        _width: usize,
+        _height: usize,
+        _colour: [f32; 4],
+    ) -> Result<phaneron_plugin::types::VideoFrame, ComputeError> {
+        Ok(video_frame("slate").frame)
+    }
+
+    fn report_error(&self, _err: &ComputeError) {}
+}
+
+/// Pushes a new frame to its output each time it is asked to produce one.
+struct ProducerNode {
+    video_output: VideoOutput,
+}
+impl NodeTrait for ProducerNode {
+    fn apply_state(&self, _state: RString) -> bool {
+        true
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let frame_context = frame_context.submit().unwrap();
+        self.video_output
+            .push_frame(&frame_context, video_frame("producer").frame)
+            .ok();
+    }
+}
+
+fn graph_controls(pause_gate: PauseGate) -> GraphControls {
+    GraphControls {
+        pause_gate,
+        frame_lead: FrameLeadLimit::new(GraphsConfig::default()),
+        stall_monitor: StallMonitor::default(),
+        panic_slate: PanicSlate::default(),
+        clock: GraphClock::new(FrameFormat::default()),
+    }
+}
+
+/// A node with a single video output run by `run_node`, along with a pipe subscribed to the output.
+struct RunningNode {
+    task: tokio::task::JoinHandle<()>,
+    pipe: VideoPipe,
+    _node_event_tx: tokio::sync::mpsc::UnboundedSender<NodeEvent>,
+}
+
+async fn run_with_output(
+    node: impl FnOnce(VideoOutput) -> phaneron_plugin::types::Node,
+    node_context: NodeRunContext,
+    controls: GraphControls,
+) -> RunningNode {
+    let (state_tx, _state_rx) = tokio::sync::mpsc::unbounded_channel();
+    let semaphore_provider = ChannelSemaphoreProvider::default();
+    let channel = Channel::default();
+    let output_id = VideoOutputId::default();
+    node_context
+        .add_video_output(
+            output_id.clone(),
+            channel.clone(),
+            VideoFormatTap::default(),
+        )
+        .await;
+    let pipe = node_context.get_video_pipe(&output_id).await;
+    let node = node(VideoOutput_TO::from_value(
+        HostVideoOutput::new(
+            semaphore_provider.clone(),
+            channel,
+            VideoFormatTap::default(),
+        ),
+        TD_Opaque,
+    ));
+    let (node_event_tx, node_event_rx) = tokio::sync::mpsc::unbounded_channel();
+    let task = tokio::spawn(run_node(
+        TestFillFrames,
+        node_context,
+        Arc::new(node),
+        state_tx,
+        node_event_rx,
+        semaphore_provider,
+        controls,
+    ));
+
+    RunningNode {
+        task,
+        pipe,
+        _node_event_tx: node_event_tx,
+    }
+}
+
+async fn run_producer(controls: GraphControls) -> RunningNode {
+    let (state_tx, _state_rx) = tokio::sync::mpsc::unbounded_channel();
+    run_with_output(
+        |video_output| Node_TO::from_value(ProducerNode { video_output }, TD_Opaque),
+        NodeRunContext::new(NodeId::default(), state_tx),
+        controls,
+    )
+    .await
+}
+
+/// Takes the frames pushed to a pipe within `duration` as a downstream node would, returning how
+/// many there were.
+async fn receive_frames(pipe: &mut VideoPipe, duration: Duration) -> usize {
+    let deadline = tokio::time::Instant::now() + duration;
+    let mut frames = 0;
+    while let Ok(Some((_, semaphore))) = tokio::time::timeout_at(deadline, pipe.next_frame()).await
+    {
+        semaphore.signal().await;
+        frames += 1;
+    }
+
+    frames
+}
+
+#[tokio::test]
+async fn paused_graph_stops_and_resumes() {
+    let pause_gate = PauseGate::default();
+    let mut producer = run_producer(graph_controls(pause_gate.clone())).await;
+    assert!(receive_frames(&mut producer.pipe, Duration::from_millis(50)).await > 0);
+
+    pause_gate.set_paused(true);
+    // A frame that was already being produced is still pushed
+    receive_frames(&mut producer.pipe, Duration::from_millis(50)).await;
+    assert_eq!(
+        receive_frames(&mut producer.pipe, Duration::from_millis(50)).await,
+        0
+    );
+
+    pause_gate.set_paused(false);
+    assert!(receive_frames(&mut producer.pipe, Duration::from_millis(50)).await > 0);
+
+    producer.task.abort();
+}
+
+/// Pushes the frame on its video input to its video output.
+struct PassThroughNode {
+    video_input: VideoInputId,
+    video_output: VideoOutput,
+}
+impl NodeTrait for PassThroughNode {
+    fn apply_state(&self, _state: RString) -> bool {
+        true
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let frame = frame_context
+            .get_video_input(&self.video_input)
+            .into_option()
+            .map(|frame| frame.frame.clone());
+        let frame_context = frame_context.submit().unwrap();
+        if let Some(frame) = frame {
+            self.video_output.push_frame(&frame_context, frame).ok();
+        }
+    }
+}
+
+#[tokio::test]
+async fn paused_graph_keeps_downstream_nodes_running() {
+    let pause_gate = PauseGate::default();
+    let RunningNode {
+        task: producer_task,
+        pipe: producer_pipe,
+        _node_event_tx: _producer_event_tx,
+    } = run_producer(graph_controls(pause_gate.clone())).await;
+
+    let (state_tx, _state_rx) = tokio::sync::mpsc::unbounded_channel();
+    let node_context = NodeRunContext::new(NodeId::default(), state_tx);
+    let input_id = VideoInputId::default();
+    node_context.add_video_input(input_id.clone()).await;
+    node_context
+        .connect_video_pipe(&input_id, producer_pipe)
+        .await
+        .unwrap();
+    let mut pass_through = run_with_output(
+        |video_output| {
+            Node_TO::from_value(
+                PassThroughNode {
+                    video_input: input_id,
+                    video_output,
+                },
+                TD_Opaque,
+            )
+        },
+        node_context,
+        graph_controls(pause_gate.clone()),
+    )
+    .await;
+    assert!(receive_frames(&mut pass_through.pipe, Duration::from_millis(100)).await > 0);
+
+    // The producer stops while the node downstream repeats the held frame on the graph's clock,
+    // neither waits on the other's semaphores
+    pause_gate.set_paused(true);
+    for _ in 0..3 {
+        assert!(receive_frames(&mut pass_through.pipe, Duration::from_millis(200)).await > 0);
+    }
+
+    pause_gate.set_paused(false);
+    assert!(receive_frames(&mut pass_through.pipe, Duration::from_millis(200)).await > 0);
+
+    producer_task.abort();
+    pass_through.task.abort();
+}
+
+/// Records which output each frame on its video input came from.
+struct ConsumerNode {
+    video_input: VideoInputId,
+    received: Arc<std::sync::Mutex<Vec<String>>>,
+}
+impl NodeTrait for ConsumerNode {
+    fn apply_state(&self, _state: RString) -> bool {
+        true
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        if let Some(frame) = frame_context
+            .get_video_input(&self.video_input)
+            .into_option()
+        {
+            self.received
+                .lock()
+                .unwrap()
+                .push(frame.output_id.to_string());
+        }
+        frame_context.submit().unwrap();
+    }
+}
+
+#[tokio::test]
+async fn input_receives_black_after_upstream_ends() {
+    let (state_tx, mut state_rx) = tokio::sync::mpsc::unbounded_channel();
+    let node_context = NodeRunContext::new(NodeId::default(), state_tx.clone());
+    let input_id = VideoInputId::default();
+    node_context.add_video_input(input_id.clone()).await;
+
+    // The upstream node pushes 3 frames and then ends
+    let upstream: Channel<phaneron_plugin::types::VideoFrame> = Channel::default();
+    let output_id = VideoOutputId::new_from("upstream".into());
+    node_context
+        .connect_video_pipe(
+            &input_id,
+            VideoPipe::new(output_id.clone(), upstream.subscribe().await),
+        )
+        .await
+        .unwrap();
+    tokio::task::spawn_blocking({
+        let upstream = upstream.clone();
+        move || {
+            let semaphore_provider = ChannelSemaphoreProvider::default();
+            for _ in 0..3 {
+                upstream.send(&semaphore_provider, video_frame("upstream").frame);
+            }
+        }
+    })
+    .await
+    .unwrap();
+    upstream.close();
+
+    let received = Arc::new(std::sync::Mutex::new(vec![]));
+    let node = Node_TO::from_value(
+        ConsumerNode {
+            video_input: input_id.clone(),
+            received: received.clone(),
+        },
+        TD_Opaque,
+    );
+    let (_node_event_tx, node_event_rx) = tokio::sync::mpsc::unbounded_channel();
+    let task = tokio::spawn(run_node(
+        TestFillFrames,
+        node_context,
+        Arc::new(node),
+        state_tx,
+        node_event_rx,
+        ChannelSemaphoreProvider::default(),
+        graph_controls(PauseGate::default()),
+    ));
+
+    // The consumer keeps processing frames on black at the graph's frame rate
+    tokio::time::timeout(Duration::from_secs(2), async {
+        while received.lock().unwrap().len() < 6 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    task.abort();
+
+    let received = received.lock().unwrap().clone();
+    assert_eq!(received[..3], ["upstream", "upstream", "upstream"]);
+    assert!(received[3..].iter().all(|output_id| output_id == "black"));
+    let disconnected = std::iter::from_fn(|| state_rx.try_recv().ok()).any(|event| {
+        matches!(
+            event,
+            NodeStateEvent::VideoInputDisconnected(_, input, output)
+                if input == input_id && output == output_id
+        )
+    });
+    assert!(disconnected);
+}
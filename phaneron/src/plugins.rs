@@ -21,6 +21,8 @@ use std::{
     fmt::Display,
     fs, io,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Instant,
 };
 
 use abi_stable::{
@@ -40,8 +42,16 @@ use phaneron_plugin::{
     PhaneronPluginRootModuleRef,
 };
 use serde::{Deserialize, Serialize};
+use tracing::Level;
+
+use crate::config::LogRepeatWindows;
+
+use self::logging::RepeatFilter;
 
 pub(super) mod cl_shader_plugin;
+mod logging;
+#[cfg(test)]
+mod tests;
 
 #[derive(Debug, Deserialize)]
 pub struct DevPluginManifest {
@@ -51,8 +61,67 @@ pub struct DevPluginManifest {
 #[derive(Default)]
 pub struct PluginManager {
     plugins: HashMap<PluginId, PhaneronPlugin>,
+    plugin_sources: HashMap<PluginId, PluginSource>,
     nodes_provided_by_plugins: HashMap<String, PluginId>,
     node_descriptions: HashMap<String, PluginNodeDescription>,
+    reloadable_plugins: HashMap<PluginId, Arc<dyn ReloadablePlugin>>,
+    log_repeat_windows: LogRepeatWindows,
+}
+
+/// A plugin built into Phaneron that can reload the nodes it provides while Phaneron is running.
+pub trait ReloadablePlugin: Send + Sync {
+    fn reload(&self) -> anyhow::Result<PluginReloadReport>;
+}
+
+/// The node types that were changed by a reload.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginReloadReport {
+    /// Node types that were replaced, running nodes switch to them on their next frame.
+    pub reloaded: Vec<String>,
+    /// Node types that failed to load and keep their previous version.
+    pub failed: Vec<String>,
+}
+
+/// Where a plugin was loaded from, recorded at load time.
+#[derive(Debug, Clone, Default)]
+struct PluginSource {
+    name: Option<String>,
+    load_path: Option<PathBuf>,
+    version_strings: Option<String>,
+}
+
+/// Information about a loaded plugin, combining what the host knows about how the plugin
+/// was loaded with the build information reported by the plugin itself.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginInfo {
+    pub plugin_id: String,
+    pub name: Option<String>,
+    pub load_path: Option<String>,
+    pub version_strings: Option<String>,
+    pub version: String,
+    pub commit: Option<String>,
+    pub compiler: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum NodeStateSchemaError {
+    PluginDoesNotExist(PluginId),
+    /// The plugin does not provide the node type.
+    NodeTypeDoesNotExist(String),
+    /// The node type does not provide a schema.
+    NoSchema(String),
+    /// The plugin returned a schema that is not valid JSON.
+    InvalidSchema(String),
+}
+
+#[derive(Debug)]
+pub enum PluginReloadError {
+    PluginDoesNotExist(PluginId),
+    /// The plugin can't be reloaded while Phaneron is running.
+    NotReloadable(PluginId),
+    Failed(anyhow::Error),
 }
 
 pub enum PluginLoadType {
@@ -61,6 +130,11 @@ pub enum PluginLoadType {
 }
 
 impl PluginManager {
+    /// Applies to plugins loaded after this is called.
+    pub fn set_log_repeat_windows(&mut self, windows: LogRepeatWindows) {
+        self.log_repeat_windows = windows;
+    }
+
     pub fn load_from(&mut self, load_type: PluginLoadType) -> anyhow::Result<usize> {
         let (plugins_to_load, plugins_directory) = match load_type {
             PluginLoadType::Development(manifest) => (manifest.plugins, None),
@@ -90,6 +164,24 @@ impl PluginManager {
     }
 
     pub fn add_plugin(&mut self, plugin: PhaneronPlugin) -> anyhow::Result<()> {
+        self.insert_plugin(plugin);
+
+        Ok(())
+    }
+
+    /// Adds a plugin that can be reloaded with [`reload_plugin`](Self::reload_plugin).
+    pub fn add_reloadable_plugin(
+        &mut self,
+        plugin: PhaneronPlugin,
+        reloadable: Arc<dyn ReloadablePlugin>,
+    ) -> anyhow::Result<()> {
+        let plugin_id = self.insert_plugin(plugin);
+        self.reloadable_plugins.insert(plugin_id, reloadable);
+
+        Ok(())
+    }
+
+    fn insert_plugin(&mut self, plugin: PhaneronPlugin) -> PluginId {
         let nodes = plugin.get_available_node_types();
 
         let plugin_id = PluginId::default();
@@ -99,9 +191,89 @@ impl PluginManager {
             self.node_descriptions
                 .insert(node_type.id.to_string(), node_type);
         }
-        self.plugins.insert(plugin_id, plugin);
+        self.plugin_sources
+            .insert(plugin_id.clone(), PluginSource::default());
+        self.plugins.insert(plugin_id.clone(), plugin);
 
-        Ok(())
+        plugin_id
+    }
+
+    pub fn reload_plugin(
+        &self,
+        plugin_id: &PluginId,
+    ) -> Result<PluginReloadReport, PluginReloadError> {
+        if !self.plugins.contains_key(plugin_id) {
+            return Err(PluginReloadError::PluginDoesNotExist(plugin_id.clone()));
+        }
+        let reloadable = self
+            .reloadable_plugins
+            .get(plugin_id)
+            .ok_or_else(|| PluginReloadError::NotReloadable(plugin_id.clone()))?;
+
+        reloadable.reload().map_err(PluginReloadError::Failed)
+    }
+
+    /// Sorted by plugin name and then by Id, so the order is the same each time.
+    pub fn get_plugin_ids(&self) -> Vec<PluginId> {
+        let mut plugin_ids: Vec<PluginId> = self.plugins.keys().cloned().collect();
+        plugin_ids.sort_by(|a, b| {
+            let name_a = self
+                .plugin_sources
+                .get(a)
+                .and_then(|source| source.name.as_ref());
+            let name_b = self
+                .plugin_sources
+                .get(b)
+                .and_then(|source| source.name.as_ref());
+            name_a.cmp(&name_b).then_with(|| a.0.cmp(&b.0))
+        });
+
+        plugin_ids
+    }
+
+    pub fn get_plugin_info(&self, plugin_id: &PluginId) -> Option<PluginInfo> {
+        let plugin = self.plugins.get(plugin_id)?;
+        let source = self
+            .plugin_sources
+            .get(plugin_id)
+            .cloned()
+            .unwrap_or_default();
+        let build_info = plugin.build_info();
+
+        Some(PluginInfo {
+            plugin_id: plugin_id.to_string(),
+            name: source.name,
+            load_path: source.load_path.map(|path| path.display().to_string()),
+            version_strings: source.version_strings,
+            version: build_info.version.into(),
+            commit: build_info.commit.into_option().map(Into::into),
+            compiler: build_info.compiler.into_option().map(Into::into),
+        })
+    }
+
+    /// JSON Schema of the state accepted by nodes of `node_type`, as provided by the plugin.
+    pub fn get_node_state_schema(
+        &self,
+        plugin_id: &PluginId,
+        node_type: &str,
+    ) -> Result<serde_json::Value, NodeStateSchemaError> {
+        let plugin = self
+            .plugins
+            .get(plugin_id)
+            .ok_or_else(|| NodeStateSchemaError::PluginDoesNotExist(plugin_id.clone()))?;
+        if self.nodes_provided_by_plugins.get(node_type) != Some(plugin_id) {
+            return Err(NodeStateSchemaError::NodeTypeDoesNotExist(
+                node_type.to_string(),
+            ));
+        }
+
+        let schema: String = plugin
+            .get_node_state_schema(node_type.into())
+            .into_result()
+            .map_err(|err| NodeStateSchemaError::NoSchema(err.into()))?
+            .into();
+        serde_json::from_str(&schema)
+            .map_err(|err| NodeStateSchemaError::InvalidSchema(err.to_string()))
     }
 
     fn load_plugin(
@@ -116,16 +288,20 @@ impl PluginManager {
 
         let res = (|| {
             let header = lib_header_from_path(&library_path)?;
-            header.init_root_module::<PhaneronPluginRootModuleRef>()
+            let version_strings = header.version_strings().version.to_string();
+            header
+                .init_root_module::<PhaneronPluginRootModuleRef>()
+                .map(|root_module| (root_module, version_strings))
         })();
 
-        let root_module = match res {
+        let (root_module, version_strings) = match res {
             Ok(x) => x,
             Err(e) => return Err(anyhow!(e)),
         };
 
         let logger = PluginLogger {
             plugin_name: plugin_name.to_string(),
+            repeats: Arc::new(Mutex::new(RepeatFilter::new(self.log_repeat_windows))),
         };
         let logger = PhaneronLoggingContext_TO::from_value(logger, TD_Opaque);
         let plugin_context = PhaneronPluginContext::new(logger);
@@ -141,6 +317,14 @@ impl PluginManager {
             self.node_descriptions
                 .insert(node_type.id.to_string(), node_type);
         }
+        self.plugin_sources.insert(
+            plugin_id.clone(),
+            PluginSource {
+                name: Some(plugin_name.to_string()),
+                load_path: Some(library_path),
+                version_strings: Some(version_strings),
+            },
+        );
         self.plugins.insert(plugin_id, plugin);
 
         Ok(())
@@ -151,8 +335,11 @@ impl PluginManager {
         node_id: String,
         node_type: String,
     ) -> Result<NodeHandle, String> {
-        let plugin_id = self.nodes_provided_by_plugins.get(&node_type).unwrap();
-        let plugin = self.plugins.get(plugin_id).unwrap();
+        let plugin = self
+            .nodes_provided_by_plugins
+            .get(&node_type)
+            .and_then(|plugin_id| self.plugins.get(plugin_id))
+            .ok_or_else(|| format!("No plugin provides node type {node_type}"))?;
         plugin
             .create_node(CreateNodeDescription {
                 node_type: node_type.into(),
@@ -162,6 +349,23 @@ impl PluginManager {
             .into()
     }
 
+    pub fn provides_node_type(&self, node_type: &str) -> bool {
+        self.nodes_provided_by_plugins.contains_key(node_type)
+    }
+
+    /// Lets the plugin that provides `node_type` release anything it holds for the node.
+    pub fn destroy_node(&self, node_id: String, node_type: &str) -> Result<(), String> {
+        let plugin = self
+            .nodes_provided_by_plugins
+            .get(node_type)
+            .and_then(|plugin_id| self.plugins.get(plugin_id))
+            .ok_or_else(|| format!("No plugin provides node type {node_type}"))?;
+        plugin
+            .destroy_node(node_id.into())
+            .map_err(|err| err.into())
+            .into()
+    }
+
     pub fn initialize_node(
         &self,
         context: NodeContext,
@@ -210,24 +414,30 @@ fn compute_plugin_path(plugins_dir: &Option<String>, base_name: &str) -> io::Res
 #[derive(Debug, Clone)]
 struct PluginLogger {
     plugin_name: String,
+    repeats: Arc<Mutex<RepeatFilter>>,
 }
 impl PhaneronLoggingContext for PluginLogger {
     fn log(&self, level: phaneron_plugin::LogLevel, message: abi_stable::std_types::RString) {
-        match level {
-            phaneron_plugin::LogLevel::Error => {
-                tracing::error!("PLUGIN {}: {}", self.plugin_name, message.to_string())
-            }
-            phaneron_plugin::LogLevel::Warn => {
-                tracing::warn!("PLUGIN {}: {}", self.plugin_name, message.to_string())
-            }
-            phaneron_plugin::LogLevel::Info => {
-                tracing::info!("PLUGIN {}: {}", self.plugin_name, message.to_string())
-            }
-            phaneron_plugin::LogLevel::Debug => {
-                tracing::debug!("PLUGIN {}: {}", self.plugin_name, message.to_string())
-            }
-            phaneron_plugin::LogLevel::Trace => {
-                tracing::trace!("PLUGIN {}: {}", self.plugin_name, message.to_string())
+        let level = match level {
+            phaneron_plugin::LogLevel::Error => Level::ERROR,
+            phaneron_plugin::LogLevel::Warn => Level::WARN,
+            phaneron_plugin::LogLevel::Info => Level::INFO,
+            phaneron_plugin::LogLevel::Debug => Level::DEBUG,
+            phaneron_plugin::LogLevel::Trace => Level::TRACE,
+        };
+        let lines = self
+            .repeats
+            .lock()
+            .unwrap()
+            .filter(level, message.into(), Instant::now());
+
+        for (level, line) in lines {
+            match level {
+                Level::ERROR => tracing::error!("PLUGIN {}: {}", self.plugin_name, line),
+                Level::WARN => tracing::warn!("PLUGIN {}: {}", self.plugin_name, line),
+                Level::INFO => tracing::info!("PLUGIN {}: {}", self.plugin_name, line),
+                Level::DEBUG => tracing::debug!("PLUGIN {}: {}", self.plugin_name, line),
+                _ => tracing::trace!("PLUGIN {}: {}", self.plugin_name, line),
             }
         }
     }
@@ -242,7 +452,7 @@ impl PluginId {
 }
 impl Default for PluginId {
     fn default() -> Self {
-        Self(uuid::Uuid::new_v4().to_string())
+        Self(phaneron_plugin::ids::new_id())
     }
 }
 impl Display for PluginId {
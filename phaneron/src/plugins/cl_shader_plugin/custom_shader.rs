@@ -0,0 +1,421 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::sync::{Arc, Mutex};
+
+use abi_stable::{
+    sabi_trait::TD_Opaque,
+    std_types::{ROption, RString},
+};
+use phaneron_plugin::{
+    traits::Node_TO,
+    types::{Node, NodeContext, ProcessFrameContext, ProcessShader, VideoOutput},
+    ShaderParams, VideoInputId,
+};
+use serde::Deserialize;
+use tracing::{error, info};
+
+use crate::compute::PhaneronComputeContext;
+
+use super::ShaderArg;
+
+#[cfg(test)]
+mod tests;
+
+pub(super) const CUSTOM_SHADER_NODE_TYPE: &str = "custom_shader";
+
+pub(super) struct CustomShaderHandle {
+    id: String,
+    compute_context: PhaneronComputeContext,
+}
+
+impl CustomShaderHandle {
+    pub(super) fn new(id: String, compute_context: PhaneronComputeContext) -> Self {
+        Self {
+            id,
+            compute_context,
+        }
+    }
+}
+
+impl phaneron_plugin::traits::NodeHandle for CustomShaderHandle {
+    fn initialize(&self, context: NodeContext, _configuration: ROption<RString>) -> Node {
+        let node = CustomShader::new(self.id.clone(), context, self.compute_context.clone());
+
+        Node_TO::from_value(node, TD_Opaque)
+    }
+}
+
+/// State of a `custom_shader` node, the kernel is described in the same way as a shader plugin's
+/// description file. The values of the declared parameters are read from the other fields of the state
+/// using the parameter keys, missing values use the parameter defaults.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CustomShaderState {
+    kernel: String,
+    program_name: String,
+    args: Vec<ShaderArg>,
+    #[serde(flatten)]
+    values: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ShaderValue {
+    VideoInput(usize),
+    VideoOutput(usize),
+    F32(f32),
+    U32(u32),
+    Bool(bool),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum SignatureError {
+    KernelNotFound,
+    WrongNumberOfParameters {
+        expected: usize,
+        found: usize,
+    },
+    WrongParameterType {
+        index: usize,
+        expected: &'static str,
+    },
+}
+
+struct CompiledShader {
+    kernel: String,
+    program_name: String,
+    shader: Arc<ProcessShader>,
+    values: Vec<ShaderValue>,
+}
+
+struct CustomShader {
+    id: String,
+    context: NodeContext,
+    compute_context: PhaneronComputeContext,
+    /// Inputs and outputs are created as kernels declare them and reused by position when the kernel changes.
+    video_inputs: Mutex<Vec<VideoInputId>>,
+    video_outputs: Mutex<Vec<VideoOutput>>,
+    compiled: Mutex<Option<Arc<CompiledShader>>>,
+}
+
+impl CustomShader {
+    fn new(id: String, context: NodeContext, compute_context: PhaneronComputeContext) -> Self {
+        Self {
+            id,
+            context,
+            compute_context,
+            video_inputs: Default::default(),
+            video_outputs: Default::default(),
+            compiled: Default::default(),
+        }
+    }
+}
+
+impl phaneron_plugin::traits::Node for CustomShader {
+    fn apply_state(&self, state: RString) -> bool {
+        let state: CustomShaderState = match serde_json::from_str(&state) {
+            Ok(state) => state,
+            Err(err) => {
+                error!("{}: Invalid state: {err}", self.id);
+                return false;
+            }
+        };
+
+        if !state
+            .args
+            .iter()
+            .any(|arg| matches!(arg, ShaderArg::VideoOutput { .. }))
+        {
+            error!("{}: Shader does not have any outputs", self.id);
+            return false;
+        }
+        if let Some(arg) = state.args.iter().find(|arg| !arg.is_valid_arg()) {
+            error!(
+                "{}: Shader has invalid arg {}",
+                self.id,
+                arg.arg_display_name()
+            );
+            return false;
+        }
+        if let Err(err) = check_signature(&state.kernel, &state.program_name, &state.args) {
+            error!(
+                "{}: Kernel {} does not match its args: {err:?}",
+                self.id, state.program_name
+            );
+            return false;
+        }
+
+        let values = resolve_values(&state.args, &state.values);
+        let mut compiled = self.compiled.lock().unwrap();
+        let shader = match compiled.as_ref() {
+            Some(current)
+                if current.kernel == state.kernel && current.program_name == state.program_name =>
+            {
+                current.shader.clone()
+            }
+            _ => {
+                info!("{}: Compiling {}", self.id, state.program_name);
+                match self
+                    .compute_context
+                    .create_process_shader(&state.kernel, &state.program_name)
+                {
+                    Ok(shader) => Arc::new(shader),
+                    Err(err) => {
+                        error!("{}: Failed to compile shader: {err:?}", self.id);
+                        return false;
+                    }
+                }
+            }
+        };
+
+        let input_count = values
+            .iter()
+            .filter(|value| matches!(value, ShaderValue::VideoInput(_)))
+            .count();
+        let output_count = values
+            .iter()
+            .filter(|value| matches!(value, ShaderValue::VideoOutput(_)))
+            .count();
+        let mut video_inputs = self.video_inputs.lock().unwrap();
+        while video_inputs.len() < input_count {
+            video_inputs.push(self.context.add_video_input());
+        }
+        let mut video_outputs = self.video_outputs.lock().unwrap();
+        while video_outputs.len() < output_count {
+            video_outputs.push(self.context.add_video_output());
+        }
+
+        *compiled = Some(Arc::new(CompiledShader {
+            kernel: state.kernel,
+            program_name: state.program_name,
+            shader,
+            values,
+        }));
+
+        true
+    }
+
+    fn process_frame(&self, frame_context: ProcessFrameContext) {
+        let compiled = self.compiled.lock().unwrap().clone();
+        let video_inputs = self.video_inputs.lock().unwrap().clone();
+        let video_outputs = self.video_outputs.lock().unwrap();
+
+        let Some(compiled) = compiled else {
+            frame_context.submit().unwrap();
+            return;
+        };
+
+        let inputs: Vec<_> = video_inputs
+            .iter()
+            .map(|input_id| {
+                frame_context
+                    .get_video_input(input_id)
+                    .unwrap_or(frame_context.get_black_frame())
+                    .frame
+                    .clone()
+            })
+            .collect();
+        // TODO: Outputs take the size of the first input until output sizes can be declared
+        let (width, height) = inputs
+            .first()
+            .map(|frame| (frame.width(), frame.height()))
+            .unwrap_or((1920, 1080));
+
+        let mut params = ShaderParams::default();
+        let mut outputs_used = 0;
+        for value in compiled.values.iter() {
+            match value {
+                ShaderValue::VideoInput(index) => {
+                    params.set_param_video_frame_input(inputs[*index].clone())
+                }
+                ShaderValue::VideoOutput(_) => {
+                    params.set_param_video_frame_output(width, height);
+                    outputs_used += 1;
+                }
+                ShaderValue::F32(val) => params.set_param_f32_input(*val),
+                ShaderValue::U32(val) => params.set_param_u32_input(*val),
+                ShaderValue::Bool(val) => params.set_param_bool_input(*val),
+            }
+        }
+
+        let output_frames = compiled.shader.run(params, &[width, height]);
+        let black_frame = frame_context.get_black_frame().frame.clone();
+        let frame_context = frame_context.submit().unwrap();
+        for (output, output_frame) in video_outputs.iter().zip(output_frames.into_iter()) {
+            output.push_frame(&frame_context, output_frame).ok();
+        }
+        // Outputs that the current kernel doesn't use keep downstream nodes running
+        for output in video_outputs.iter().skip(outputs_used) {
+            output.push_frame(&frame_context, black_frame.clone()).ok();
+        }
+    }
+}
+
+/// Maps each arg to the value that is passed to the kernel, using the same clamping as shader plugins.
+fn resolve_values(
+    args: &[ShaderArg],
+    values: &serde_json::Map<String, serde_json::Value>,
+) -> Vec<ShaderValue> {
+    let mut video_inputs = 0;
+    let mut video_outputs = 0;
+    args.iter()
+        .map(|arg| match arg {
+            ShaderArg::VideoInput { .. } => {
+                video_inputs += 1;
+                ShaderValue::VideoInput(video_inputs - 1)
+            }
+            ShaderArg::VideoOutput { .. } => {
+                video_outputs += 1;
+                ShaderValue::VideoOutput(video_outputs - 1)
+            }
+            ShaderArg::F32 {
+                key, default_val, ..
+            } => ShaderValue::F32(
+                values
+                    .get(key)
+                    .and_then(|val| val.as_f64())
+                    .map(|val| (val as f32).clamp(0.0, 1.0))
+                    .unwrap_or(*default_val),
+            ),
+            ShaderArg::U32 {
+                key,
+                inclusive_minimum,
+                inclusive_maximum,
+                default_val,
+                ..
+            } => ShaderValue::U32(
+                values
+                    .get(key)
+                    .and_then(|val| val.as_u64())
+                    .map(|val| {
+                        val.clamp(
+                            *inclusive_minimum as u64,
+                            inclusive_maximum.unwrap_or(u32::MAX) as u64,
+                        ) as u32
+                    })
+                    .unwrap_or(*default_val),
+            ),
+            ShaderArg::Bool {
+                key, default_val, ..
+            } => ShaderValue::Bool(
+                values
+                    .get(key)
+                    .and_then(|val| val.as_bool())
+                    .unwrap_or(*default_val),
+            ),
+        })
+        .collect()
+}
+
+/// Checks that `kernel` declares a kernel called `program_name` with one parameter per arg,
+/// each of the type that the arg is passed to the kernel as.
+fn check_signature(
+    kernel: &str,
+    program_name: &str,
+    args: &[ShaderArg],
+) -> Result<(), SignatureError> {
+    let source = strip_comments(kernel);
+    let parameters =
+        find_kernel_parameters(&source, program_name).ok_or(SignatureError::KernelNotFound)?;
+    if parameters.len() != args.len() {
+        return Err(SignatureError::WrongNumberOfParameters {
+            expected: args.len(),
+            found: parameters.len(),
+        });
+    }
+
+    for (index, (parameter, arg)) in parameters.iter().zip(args.iter()).enumerate() {
+        let words: Vec<&str> = parameter
+            .split(|c: char| c.is_whitespace() || c == '*')
+            .filter(|word| !word.is_empty())
+            .map(|word| word.trim_start_matches("__"))
+            .collect();
+        let has = |word: &str| words.contains(&word);
+        let (matches, expected) = match arg {
+            ShaderArg::VideoInput { .. } => (
+                has("image2d_t") && !has("write_only"),
+                "read_only image2d_t",
+            ),
+            ShaderArg::VideoOutput { .. } => (
+                has("image2d_t") && has("write_only"),
+                "write_only image2d_t",
+            ),
+            ShaderArg::F32 { .. } => (has("float"), "float"),
+            ShaderArg::U32 { .. } => (has("uint") || (has("unsigned") && has("int")), "uint"),
+            ShaderArg::Bool { .. } => (has("char") || has("uchar"), "char"),
+        };
+        if !matches {
+            return Err(SignatureError::WrongParameterType { index, expected });
+        }
+    }
+
+    Ok(())
+}
+
+fn find_kernel_parameters(source: &str, program_name: &str) -> Option<Vec<String>> {
+    let mut rest = source;
+    while let Some(position) = rest.find(program_name) {
+        let before: Vec<&str> = rest[..position].split_whitespace().rev().take(2).collect();
+        let after = rest[position + program_name.len()..].trim_start();
+        let is_kernel = matches!(before.as_slice(), ["void", "kernel" | "__kernel"]);
+        if is_kernel && after.starts_with('(') {
+            let end = after.find(')')?;
+            let parameters = &after[1..end];
+            if parameters.trim().is_empty() || parameters.trim() == "void" {
+                return Some(vec![]);
+            }
+            return Some(
+                parameters
+                    .split(',')
+                    .map(|parameter| parameter.trim().to_string())
+                    .collect(),
+            );
+        }
+        rest = &rest[position + program_name.len()..];
+    }
+
+    None
+}
+
+fn strip_comments(source: &str) -> String {
+    let mut stripped = String::with_capacity(source.len());
+    let mut rest = source;
+    loop {
+        let line_comment = rest.find("//").map(|start| (start, false));
+        let block_comment = rest.find("/*").map(|start| (start, true));
+        match line_comment.into_iter().chain(block_comment).min() {
+            Some((start, false)) => {
+                stripped.push_str(&rest[..start]);
+                rest = rest[start..]
+                    .find('\n')
+                    .map_or("", |end| &rest[start + end..]);
+            }
+            Some((start, true)) => {
+                stripped.push_str(&rest[..start]);
+                stripped.push(' ');
+                rest = rest[start + 2..]
+                    .find("*/")
+                    .map_or("", |end| &rest[start + 2 + end + 2..]);
+            }
+            None => {
+                stripped.push_str(rest);
+                return stripped;
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::*;
+
+const FLIP: &str = r#"
+// Flips an image
+__kernel void flip(
+    __read_only image2d_t input,
+    __private char flip_horizontal, /* bool */
+    __write_only image2d_t output
+) {
+}
+"#;
+
+fn flip_args() -> Vec<ShaderArg> {
+    serde_json::from_str(
+        r#"[
+            { "type": "videoInput", "displayName": "Input" },
+            { "type": "bool", "key": "flipHorizontal", "displayName": "Flip Horizontal", "defaultVal": false },
+            { "type": "videoOutput", "displayName": "Output" }
+        ]"#,
+    )
+    .unwrap()
+}
+
+#[test]
+fn accepts_matching_signature() {
+    assert_eq!(check_signature(FLIP, "flip", &flip_args()), Ok(()));
+}
+
+#[test]
+fn rejects_missing_kernel() {
+    assert_eq!(
+        check_signature(FLIP, "mirror", &flip_args()),
+        Err(SignatureError::KernelNotFound)
+    );
+}
+
+#[test]
+fn ignores_kernels_in_comments() {
+    let kernel = "/* __kernel void flip(float a) */\n".to_string() + FLIP;
+    assert_eq!(check_signature(&kernel, "flip", &flip_args()), Ok(()));
+}
+
+#[test]
+fn rejects_wrong_number_of_parameters() {
+    let mut args = flip_args();
+    args.pop();
+    assert_eq!(
+        check_signature(FLIP, "flip", &args),
+        Err(SignatureError::WrongNumberOfParameters {
+            expected: 2,
+            found: 3
+        })
+    );
+}
+
+#[test]
+fn rejects_wrong_parameter_type() {
+    let mut args = flip_args();
+    args.swap(0, 2);
+    assert_eq!(
+        check_signature(FLIP, "flip", &args),
+        Err(SignatureError::WrongParameterType {
+            index: 0,
+            expected: "write_only image2d_t"
+        })
+    );
+}
+
+#[test]
+fn resolves_values_with_defaults() {
+    let args: Vec<ShaderArg> = serde_json::from_str(
+        r#"[
+            { "type": "videoInput", "displayName": "A" },
+            { "type": "videoInput", "displayName": "B" },
+            { "type": "f32", "key": "mix", "displayName": "Mix", "defaultVal": 0.5 },
+            { "type": "u32", "key": "steps", "displayName": "Steps", "inclusiveMinimum": 1, "inclusiveMaximum": 8, "defaultVal": 4 },
+            { "type": "bool", "key": "invert", "displayName": "Invert", "defaultVal": true },
+            { "type": "videoOutput", "displayName": "Output" }
+        ]"#,
+    )
+    .unwrap();
+    let values = serde_json::json!({ "mix": 2.0, "steps": 20 });
+
+    assert_eq!(
+        resolve_values(&args, values.as_object().unwrap()),
+        vec![
+            ShaderValue::VideoInput(0),
+            ShaderValue::VideoInput(1),
+            ShaderValue::F32(1.0),
+            ShaderValue::U32(8),
+            ShaderValue::Bool(true),
+            ShaderValue::VideoOutput(0),
+        ]
+    );
+}
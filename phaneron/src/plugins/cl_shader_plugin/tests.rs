@@ -0,0 +1,160 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use abi_stable::std_types::RVec;
+use phaneron_plugin::traits::ProcessShader_TO;
+
+use super::*;
+
+/// Stands in for a compiled kernel, nothing is run.
+struct NoopShader;
+
+impl phaneron_plugin::traits::ProcessShader for NoopShader {
+    fn run(
+        &self,
+        _params: ShaderParams,
+        _global_work_size: &[usize; 2],
+    ) -> RVec<phaneron_plugin::types::VideoFrame> {
+        RVec::new()
+    }
+
+    fn run_with_local_size(
+        &self,
+        _params: ShaderParams,
+        _global_work_size: &[usize; 2],
+        _local_work_size: &[usize; 2],
+    ) -> RVec<phaneron_plugin::types::VideoFrame> {
+        RVec::new()
+    }
+}
+
+fn source(kernel: &str, args: &str) -> PluginProvidedShader {
+    PluginProvidedShader {
+        name: "Flip".to_string(),
+        kernel: kernel.to_string(),
+        program_name: "flip".to_string(),
+        args: serde_json::from_str(args).unwrap(),
+    }
+}
+
+const ARGS: &str = r#"[
+    { "type": "videoInput", "displayName": "Input" },
+    { "type": "videoOutput", "displayName": "Output" }
+]"#;
+
+const MORE_ARGS: &str = r#"[
+    { "type": "videoInput", "displayName": "Input" },
+    { "type": "videoInput", "displayName": "Mask" },
+    { "type": "videoOutput", "displayName": "Output" }
+]"#;
+
+fn compile(source: PluginProvidedShader) -> Result<CompiledShader, ComputeError> {
+    Ok(CompiledShader {
+        source,
+        process_shader: Arc::new(ProcessShader_TO::from_value(NoopShader, TD_Opaque)),
+        generation: 0,
+    })
+}
+
+fn shared(source: PluginProvidedShader) -> SharedShader {
+    Arc::new(Mutex::new(Arc::new(compile(source).unwrap())))
+}
+
+#[test]
+fn reload_replaces_a_changed_shader() {
+    let current = shared(source("kernel v1", ARGS));
+    let previous = current.lock().unwrap().clone();
+
+    assert!(matches!(
+        replace_shader(&current, source("kernel v2", ARGS), compile),
+        Ok(true)
+    ));
+    let latest = current.lock().unwrap().clone();
+    assert!(!Arc::ptr_eq(&latest, &previous));
+    assert_eq!(latest.source.kernel, "kernel v2");
+}
+
+#[test]
+fn reload_skips_an_unchanged_shader() {
+    let current = shared(source("kernel v1", ARGS));
+    let previous = current.lock().unwrap().clone();
+
+    let result = replace_shader(&current, source("kernel v1", ARGS), |_| {
+        panic!("An unchanged shader is not compiled again")
+    });
+    assert!(matches!(result, Ok(false)));
+    assert!(Arc::ptr_eq(&current.lock().unwrap(), &previous));
+}
+
+#[test]
+fn reload_keeps_the_old_kernel_on_a_compile_error() {
+    let current = shared(source("kernel v1", ARGS));
+    let previous = current.lock().unwrap().clone();
+
+    let result = replace_shader(&current, source("kernel v2", ARGS), |_| {
+        Err(ComputeError::ShutDown)
+    });
+    assert!(matches!(result, Err(ComputeError::ShutDown)));
+    assert!(Arc::ptr_eq(&current.lock().unwrap(), &previous));
+}
+
+#[test]
+fn node_switches_to_the_reloaded_kernel() {
+    let current = shared(source("kernel v1", ARGS));
+    let first = current.lock().unwrap().clone();
+    let mut kernel = NodeKernel {
+        shader: first.process_shader.clone(),
+        source: first,
+    };
+    assert_eq!(
+        kernel.update(current.lock().unwrap().clone(), &source("", ARGS).args),
+        KernelUpdate::Unchanged
+    );
+
+    replace_shader(&current, source("kernel v2", ARGS), compile).unwrap();
+    let latest = current.lock().unwrap().clone();
+    assert_eq!(
+        kernel.update(latest.clone(), &source("", ARGS).args),
+        KernelUpdate::Switched
+    );
+    // The node shares the kernel that was compiled on reload
+    assert!(Arc::ptr_eq(&kernel.shader, &latest.process_shader));
+}
+
+#[test]
+fn node_skips_the_swap_when_the_args_change() {
+    let current = shared(source("kernel v1", ARGS));
+    let first = current.lock().unwrap().clone();
+    let mut kernel = NodeKernel {
+        shader: first.process_shader.clone(),
+        source: first.clone(),
+    };
+
+    replace_shader(&current, source("kernel v2", MORE_ARGS), compile).unwrap();
+    let latest = current.lock().unwrap().clone();
+    assert_eq!(
+        kernel.update(latest.clone(), &source("", ARGS).args),
+        KernelUpdate::ArgsChanged
+    );
+    assert!(Arc::ptr_eq(&kernel.shader, &first.process_shader));
+    // The change is only reported once
+    assert_eq!(
+        kernel.update(latest, &source("", ARGS).args),
+        KernelUpdate::Unchanged
+    );
+}
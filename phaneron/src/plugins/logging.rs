@@ -0,0 +1,107 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    time::{Duration, Instant},
+};
+
+use tracing::Level;
+
+use crate::config::LogRepeatWindows;
+
+#[cfg(test)]
+mod tests;
+
+/// Collapses identical messages logged by a plugin so that a node that fails on every frame
+/// logs the failure once per window instead of once per frame.
+///
+/// The number of repeats is only reported when the plugin next logs something after the
+/// window has passed, as the filter has no timer of its own.
+#[derive(Debug)]
+pub(super) struct RepeatFilter {
+    windows: LogRepeatWindows,
+    repeats: HashMap<(Level, String), Repeat>,
+}
+
+#[derive(Debug)]
+struct Repeat {
+    first_logged: Instant,
+    suppressed: usize,
+}
+
+impl RepeatFilter {
+    pub fn new(windows: LogRepeatWindows) -> Self {
+        Self {
+            windows,
+            repeats: HashMap::new(),
+        }
+    }
+
+    /// Returns the lines to log for a message, which may include counts of repeats of
+    /// earlier messages whose window has ended.
+    pub fn filter(&mut self, level: Level, message: String, now: Instant) -> Vec<(Level, String)> {
+        let mut lines = self.take_expired(now);
+
+        if self.windows.window(level) == Duration::ZERO {
+            lines.push((level, message));
+            return lines;
+        }
+
+        match self.repeats.entry((level, message)) {
+            Entry::Occupied(mut entry) => entry.get_mut().suppressed += 1,
+            Entry::Vacant(entry) => {
+                lines.push((level, entry.key().1.clone()));
+                entry.insert(Repeat {
+                    first_logged: now,
+                    suppressed: 0,
+                });
+            }
+        }
+
+        lines
+    }
+
+    fn take_expired(&mut self, now: Instant) -> Vec<(Level, String)> {
+        let windows = self.windows;
+        let mut expired = vec![];
+        self.repeats.retain(|(level, message), repeat| {
+            if now.duration_since(repeat.first_logged) < windows.window(*level) {
+                return true;
+            }
+            if repeat.suppressed > 0 {
+                expired.push((
+                    repeat.first_logged,
+                    *level,
+                    message.clone(),
+                    repeat.suppressed,
+                ));
+            }
+            false
+        });
+        expired.sort_by_key(|(first_logged, ..)| *first_logged);
+
+        expired
+            .into_iter()
+            .map(|(_, level, message, suppressed)| {
+                let times = if suppressed == 1 { "time" } else { "times" };
+                (level, format!("{message} (repeated {suppressed} {times})"))
+            })
+            .collect()
+    }
+}
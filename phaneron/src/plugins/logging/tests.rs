@@ -0,0 +1,72 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::{Duration, Instant};
+
+use tracing::Level;
+
+use super::RepeatFilter;
+use crate::config::LogRepeatWindows;
+
+fn lines(filter: &mut RepeatFilter, level: Level, message: &str, now: Instant) -> Vec<String> {
+    filter
+        .filter(level, message.to_string(), now)
+        .into_iter()
+        .map(|(_, line)| line)
+        .collect()
+}
+
+#[test]
+fn repeats_within_window_are_counted_once_it_ends() {
+    let mut filter = RepeatFilter::new(LogRepeatWindows::default());
+    let start = Instant::now();
+
+    assert_eq!(
+        lines(&mut filter, Level::ERROR, "decode failed", start),
+        vec!["decode failed"]
+    );
+    for frame in 1..50 {
+        let now = start + Duration::from_millis(20 * frame);
+        assert!(lines(&mut filter, Level::ERROR, "decode failed", now).is_empty());
+    }
+
+    let later = start + Duration::from_secs(11);
+    assert_eq!(
+        lines(&mut filter, Level::ERROR, "decode failed", later),
+        vec!["decode failed (repeated 49 times)", "decode failed"]
+    );
+}
+
+#[test]
+fn different_messages_and_levels_are_not_collapsed() {
+    let mut filter = RepeatFilter::new(LogRepeatWindows::default());
+    let now = Instant::now();
+
+    assert_eq!(lines(&mut filter, Level::ERROR, "a", now), vec!["a"]);
+    assert_eq!(lines(&mut filter, Level::ERROR, "b", now), vec!["b"]);
+    assert_eq!(lines(&mut filter, Level::WARN, "a", now), vec!["a"]);
+}
+
+#[test]
+fn levels_without_window_log_every_message() {
+    let mut filter = RepeatFilter::new(LogRepeatWindows::default());
+    let now = Instant::now();
+
+    assert_eq!(lines(&mut filter, Level::DEBUG, "a", now), vec!["a"]);
+    assert_eq!(lines(&mut filter, Level::DEBUG, "a", now), vec!["a"]);
+}
@@ -0,0 +1,149 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use abi_stable::{
+    sabi_trait::TD_Opaque,
+    std_types::{RResult, RString, RVec},
+};
+use phaneron_plugin::{
+    traits::{BuildInfo, CreateNodeDescription, PhaneronPlugin_TO, PluginNodeDescription},
+    types::NodeHandle,
+};
+
+use super::{NodeStateSchemaError, PluginManager};
+
+struct TestPlugin {}
+impl phaneron_plugin::traits::PhaneronPlugin for TestPlugin {
+    fn get_available_node_types(&self) -> RVec<PluginNodeDescription> {
+        RVec::new()
+    }
+
+    fn create_node(&self, description: CreateNodeDescription) -> RResult<NodeHandle, RString> {
+        RResult::RErr(format!("Unknown node type: {}", description.node_type).into())
+    }
+
+    fn destroy_node(&self, _node_id: RString) -> RResult<(), RString> {
+        RResult::ROk(())
+    }
+
+    fn build_info(&self) -> BuildInfo {
+        phaneron_plugin::build_info!()
+    }
+}
+
+/// Provides a node type with a state schema and one without.
+struct SchemaPlugin {}
+impl phaneron_plugin::traits::PhaneronPlugin for SchemaPlugin {
+    fn get_available_node_types(&self) -> RVec<PluginNodeDescription> {
+        vec![
+            PluginNodeDescription {
+                id: "with_schema".into(),
+                name: "With Schema".into(),
+            },
+            PluginNodeDescription {
+                id: "without_schema".into(),
+                name: "Without Schema".into(),
+            },
+        ]
+        .into()
+    }
+
+    fn create_node(&self, description: CreateNodeDescription) -> RResult<NodeHandle, RString> {
+        RResult::RErr(format!("Unknown node type: {}", description.node_type).into())
+    }
+
+    fn destroy_node(&self, _node_id: RString) -> RResult<(), RString> {
+        RResult::ROk(())
+    }
+
+    fn build_info(&self) -> BuildInfo {
+        phaneron_plugin::build_info!()
+    }
+
+    fn get_node_state_schema(&self, node_type: RString) -> RResult<RString, RString> {
+        match node_type.as_str() {
+            "with_schema" => RResult::ROk(r#"{ "type": "object" }"#.into()),
+            _ => RResult::RErr("No schema".into()),
+        }
+    }
+}
+
+#[test]
+fn plugin_ids_are_listed_in_a_consistent_order() {
+    let mut plugin_manager = PluginManager::default();
+    for _ in 0..8 {
+        plugin_manager
+            .add_plugin(PhaneronPlugin_TO::from_value(TestPlugin {}, TD_Opaque))
+            .unwrap();
+    }
+
+    let first = plugin_manager.get_plugin_ids();
+    let second = plugin_manager.get_plugin_ids();
+    assert_eq!(first, second);
+    assert_eq!(first.len(), 8);
+    // Plugins without a name are ordered by Id
+    assert!(first.windows(2).all(|ids| ids[0].0 < ids[1].0));
+}
+
+#[test]
+fn creating_node_of_unknown_type_is_an_error() {
+    let plugin_manager = PluginManager::default();
+
+    let Err(err) = plugin_manager.create_node_handle("node".to_string(), "unknown".to_string())
+    else {
+        panic!("Node of an unknown type was created");
+    };
+    assert_eq!(err, "No plugin provides node type unknown");
+}
+
+#[test]
+fn node_state_schema_is_provided_by_the_plugin() {
+    let mut plugin_manager = PluginManager::default();
+    plugin_manager
+        .add_plugin(PhaneronPlugin_TO::from_value(SchemaPlugin {}, TD_Opaque))
+        .unwrap();
+    plugin_manager
+        .add_plugin(PhaneronPlugin_TO::from_value(TestPlugin {}, TD_Opaque))
+        .unwrap();
+    let plugin_ids = plugin_manager.get_plugin_ids();
+    let schema_plugin_id = plugin_ids
+        .iter()
+        .find(|plugin_id| {
+            plugin_manager
+                .get_node_state_schema(plugin_id, "with_schema")
+                .is_ok()
+        })
+        .unwrap();
+    let test_plugin_id = plugin_ids
+        .iter()
+        .find(|plugin_id| *plugin_id != schema_plugin_id)
+        .unwrap();
+
+    let schema = plugin_manager
+        .get_node_state_schema(schema_plugin_id, "with_schema")
+        .unwrap();
+    assert_eq!(schema, serde_json::json!({ "type": "object" }));
+    assert!(matches!(
+        plugin_manager.get_node_state_schema(schema_plugin_id, "without_schema"),
+        Err(NodeStateSchemaError::NoSchema(_))
+    ));
+    assert!(matches!(
+        plugin_manager.get_node_state_schema(test_plugin_id, "with_schema"),
+        Err(NodeStateSchemaError::NodeTypeDoesNotExist(_))
+    ));
+}
@@ -0,0 +1,122 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    compute::ComputePriority,
+    graph::{FrameFormat, GraphMode, GraphSafety},
+    state::{CreateConnection, CreateConnectionType, CreateNode},
+};
+
+#[cfg(test)]
+mod tests;
+
+/// A graph's nodes, their state and configuration, and the connections between them, in a form
+/// that can be written to a file and used to recreate the graph.
+///
+/// Connections refer to the inputs and outputs of nodes by index, as input and output Ids are
+/// generated when nodes are created. Node Ids are kept so clients can keep referring to them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedGraph {
+    pub graph_id: String,
+    #[serde(default)]
+    pub mode: GraphMode,
+    #[serde(default)]
+    pub safety: GraphSafety,
+    #[serde(default)]
+    pub frame_format: FrameFormat,
+    pub nodes: Vec<SavedNode>,
+    #[serde(default)]
+    pub connections: Vec<SavedConnection>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedNode {
+    pub node_id: String,
+    pub node_type: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub configuration: Option<String>,
+    #[serde(default)]
+    pub priority: ComputePriority,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SavedConnectionType {
+    Video,
+    Audio,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedConnection {
+    pub connection_type: SavedConnectionType,
+    pub from_node_id: String,
+    pub from_output_index: usize,
+    pub to_node_id: String,
+    pub to_input_index: usize,
+}
+
+impl SavedGraph {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let graph = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&graph)?)
+    }
+
+    /// The nodes and connections to pass to `create_graph` to recreate the graph.
+    pub fn to_create(&self) -> (Vec<CreateNode>, Vec<CreateConnection>) {
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|node| CreateNode {
+                node_id: node.node_id.clone(),
+                node_type: node.node_type.clone(),
+                node_name: node.name.clone(),
+                state: node.state.clone(),
+                configuration: node.configuration.clone(),
+                priority: node.priority,
+                ports: Default::default(),
+            })
+            .collect();
+        let connections = self
+            .connections
+            .iter()
+            .map(|connection| CreateConnection {
+                connection_type: match connection.connection_type {
+                    SavedConnectionType::Video => CreateConnectionType::Video,
+                    SavedConnectionType::Audio => CreateConnectionType::Audio,
+                },
+                from_node_id: connection.from_node_id.clone(),
+                from_output_index: connection.from_output_index,
+                to_node_id: connection.to_node_id.clone(),
+                to_input_index: connection.to_input_index,
+            })
+            .collect();
+
+        (nodes, connections)
+    }
+}
@@ -0,0 +1,125 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use phaneron_plugin::AudioChannelLayout;
+
+use crate::{
+    compute::ComputePriority,
+    graph::{FrameFormat, GraphMode, GraphSafety},
+    state::CreateConnectionType,
+};
+
+use super::{SavedConnection, SavedConnectionType, SavedGraph, SavedNode};
+
+fn saved_graph() -> SavedGraph {
+    SavedGraph {
+        graph_id: "graph".to_string(),
+        mode: GraphMode::Batch,
+        safety: GraphSafety {
+            hold_on_stall_ms: Some(500),
+        },
+        frame_format: FrameFormat {
+            frame_rate_num: 30000,
+            frame_rate_den: 1001,
+            sample_rate: 48000,
+            channel_layout: AudioChannelLayout::L_R,
+            audio_block_samples: None,
+        },
+        nodes: vec![
+            SavedNode {
+                node_id: "producer".to_string(),
+                node_type: "ffmpeg_producer".to_string(),
+                name: Some("Producer".to_string()),
+                state: Some(r#"{"file":"clip.mov"}"#.to_string()),
+                configuration: None,
+                priority: ComputePriority::High,
+            },
+            SavedNode {
+                node_id: "consumer".to_string(),
+                node_type: "webrtc_consumer".to_string(),
+                name: None,
+                state: None,
+                configuration: None,
+                priority: ComputePriority::Normal,
+            },
+        ],
+        connections: vec![
+            SavedConnection {
+                connection_type: SavedConnectionType::Video,
+                from_node_id: "producer".to_string(),
+                from_output_index: 0,
+                to_node_id: "consumer".to_string(),
+                to_input_index: 0,
+            },
+            SavedConnection {
+                connection_type: SavedConnectionType::Audio,
+                from_node_id: "producer".to_string(),
+                from_output_index: 0,
+                to_node_id: "consumer".to_string(),
+                to_input_index: 0,
+            },
+        ],
+    }
+}
+
+#[test]
+fn saved_graph_survives_serialization() {
+    let saved = saved_graph();
+
+    let serialized = serde_json::to_string(&saved).unwrap();
+    let deserialized: SavedGraph = serde_json::from_str(&serialized).unwrap();
+
+    assert_eq!(deserialized, saved);
+}
+
+#[test]
+fn missing_graph_settings_use_defaults() {
+    let saved: SavedGraph = serde_json::from_str(
+        r#"{
+            "graphId": "graph",
+            "nodes": [{ "nodeId": "producer", "nodeType": "ffmpeg_producer" }]
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(saved.mode, GraphMode::Live);
+    assert_eq!(saved.safety, GraphSafety::default());
+    assert_eq!(saved.frame_format, FrameFormat::default());
+    assert_eq!(saved.nodes[0].priority, ComputePriority::Normal);
+    assert!(saved.connections.is_empty());
+}
+
+#[test]
+fn saved_graph_is_recreated_with_the_same_ids_and_indices() {
+    let saved = saved_graph();
+
+    let (nodes, connections) = saved.to_create();
+
+    assert_eq!(nodes.len(), 2);
+    assert_eq!(nodes[0].node_id, "producer");
+    assert_eq!(nodes[0].node_name.as_deref(), Some("Producer"));
+    assert_eq!(nodes[0].state.as_deref(), Some(r#"{"file":"clip.mov"}"#));
+    assert_eq!(nodes[0].priority, ComputePriority::High);
+    assert_eq!(connections.len(), 2);
+    assert!(matches!(
+        connections[1].connection_type,
+        CreateConnectionType::Audio
+    ));
+    assert_eq!(connections[1].from_node_id, "producer");
+    assert_eq!(connections[1].to_node_id, "consumer");
+}
@@ -0,0 +1,206 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::io::Write;
+
+use phaneron_plugin::COLOUR_SPEC_SRGB;
+use serde::Deserialize;
+
+use crate::colour::linear_to_gamma;
+
+#[cfg(test)]
+mod tests;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const PNG_COLOUR_TYPE_RGBA: u8 = 6;
+/// Gamma and chromaticities that PNG writers are recommended to write alongside the `sRGB` chunk
+/// for decoders that don't understand it, scaled by 100000.
+const PNG_SRGB_GAMMA: u32 = 45455;
+const PNG_SRGB_CHROMATICITIES: [u32; 8] = [31270, 32900, 64000, 33000, 30000, 60000, 15000, 6000];
+
+/// Image format a still is encoded to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotFormat {
+    #[default]
+    Png8,
+    Png16,
+    /// 16 bits per sample.
+    Tiff,
+}
+
+impl SnapshotFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            SnapshotFormat::Png8 | SnapshotFormat::Png16 => "image/png",
+            SnapshotFormat::Tiff => "image/tiff",
+        }
+    }
+
+    fn bits_per_sample(&self) -> u32 {
+        match self {
+            SnapshotFormat::Png8 => 8,
+            SnapshotFormat::Png16 | SnapshotFormat::Tiff => 16,
+        }
+    }
+}
+
+/// Encodes a frame of floating-point RGBA pixels in the working colour space (linear light, BT.709 primaries)
+/// as a still image.
+///
+/// The working space shares its primaries and white point with sRGB so only the sRGB transfer function
+/// is applied before quantising to the bit depth of the format. PNGs are tagged as sRGB, TIFFs carry no
+/// colour tag and are assumed to be sRGB by most readers.
+pub fn encode_snapshot(
+    format: SnapshotFormat,
+    width: usize,
+    height: usize,
+    pixels: &[f32],
+) -> Vec<u8> {
+    let samples = to_srgb_samples(pixels, format.bits_per_sample());
+    match format {
+        SnapshotFormat::Png8 | SnapshotFormat::Png16 => {
+            encode_png(width, height, format.bits_per_sample(), &samples)
+        }
+        SnapshotFormat::Tiff => encode_tiff(width, height, &samples),
+    }
+}
+
+/// Converts linear RGBA pixels to sRGB-encoded integer samples, alpha is left linear.
+fn to_srgb_samples(pixels: &[f32], bits_per_sample: u32) -> Vec<u16> {
+    let max = ((1u32 << bits_per_sample) - 1) as f32;
+    pixels
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let encode = |value: f32| linear_to_gamma(&COLOUR_SPEC_SRGB, value.clamp(0.0, 1.0));
+            [
+                encode(pixel[0]),
+                encode(pixel[1]),
+                encode(pixel[2]),
+                pixel[3].clamp(0.0, 1.0),
+            ]
+        })
+        .map(|value| (value * max).round() as u16)
+        .collect()
+}
+
+fn encode_png(width: usize, height: usize, bits_per_sample: u32, samples: &[u16]) -> Vec<u8> {
+    let mut png = PNG_SIGNATURE.to_vec();
+
+    let mut header = vec![];
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // Bit depth, colour type, compression, filter and interlace methods
+    header.extend_from_slice(&[bits_per_sample as u8, PNG_COLOUR_TYPE_RGBA, 0, 0, 0]);
+    write_png_chunk(&mut png, b"IHDR", &header);
+
+    // Perceptual rendering intent
+    write_png_chunk(&mut png, b"sRGB", &[0]);
+    write_png_chunk(&mut png, b"gAMA", &PNG_SRGB_GAMMA.to_be_bytes());
+    let chromaticities: Vec<u8> = PNG_SRGB_CHROMATICITIES
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect();
+    write_png_chunk(&mut png, b"cHRM", &chromaticities);
+
+    let row_samples = width * 4;
+    let mut scanlines = Vec::with_capacity(height * (1 + row_samples * 2));
+    for row in samples.chunks_exact(row_samples.max(1)) {
+        // No filtering
+        scanlines.push(0);
+        for sample in row {
+            match bits_per_sample {
+                8 => scanlines.push(*sample as u8),
+                _ => scanlines.extend_from_slice(&sample.to_be_bytes()),
+            }
+        }
+    }
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&scanlines).unwrap();
+    write_png_chunk(&mut png, b"IDAT", &encoder.finish().unwrap());
+
+    write_png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_png_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let crc_start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    let crc = crc32fast::hash(&png[crc_start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Writes an uncompressed little-endian baseline TIFF with 16-bit RGBA samples in a single strip.
+fn encode_tiff(width: usize, height: usize, samples: &[u16]) -> Vec<u8> {
+    const TYPE_SHORT: u16 = 3;
+    const TYPE_LONG: u16 = 4;
+    const HEADER_LENGTH: u32 = 8;
+
+    let strip_length = (samples.len() * 2) as u32;
+    // Tag, type, count and value, tags must be sorted
+    let mut entries: Vec<(u16, u16, u32, u32)> = vec![
+        (256, TYPE_LONG, 1, width as u32),  // ImageWidth
+        (257, TYPE_LONG, 1, height as u32), // ImageLength
+        (258, TYPE_SHORT, 4, 0),            // BitsPerSample, written after the IFD
+        (259, TYPE_SHORT, 1, 1),            // Compression, none
+        (262, TYPE_SHORT, 1, 2),            // PhotometricInterpretation, RGB
+        (273, TYPE_LONG, 1, 0),             // StripOffsets, written after the IFD
+        (277, TYPE_SHORT, 1, 4),            // SamplesPerPixel
+        (278, TYPE_LONG, 1, height as u32), // RowsPerStrip
+        (279, TYPE_LONG, 1, strip_length),  // StripByteCounts
+        (284, TYPE_SHORT, 1, 1),            // PlanarConfiguration, interleaved
+        (338, TYPE_SHORT, 1, 2),            // ExtraSamples, unassociated alpha
+    ];
+    let ifd_length = 2 + entries.len() as u32 * 12 + 4;
+    let bits_per_sample_offset = HEADER_LENGTH + ifd_length;
+    let strip_offset = bits_per_sample_offset + 4 * 2;
+    entries[2].3 = bits_per_sample_offset;
+    entries[5].3 = strip_offset;
+
+    let mut tiff = Vec::with_capacity(strip_offset as usize + strip_length as usize);
+    tiff.extend_from_slice(b"II");
+    tiff.extend_from_slice(&42u16.to_le_bytes());
+    tiff.extend_from_slice(&HEADER_LENGTH.to_le_bytes());
+
+    tiff.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (tag, field_type, count, value) in entries {
+        tiff.extend_from_slice(&tag.to_le_bytes());
+        tiff.extend_from_slice(&field_type.to_le_bytes());
+        tiff.extend_from_slice(&count.to_le_bytes());
+        match (field_type, count) {
+            // Single shorts are left-justified in the value field
+            (TYPE_SHORT, 1) => {
+                tiff.extend_from_slice(&(value as u16).to_le_bytes());
+                tiff.extend_from_slice(&[0, 0]);
+            }
+            _ => tiff.extend_from_slice(&value.to_le_bytes()),
+        }
+    }
+    // No further IFDs
+    tiff.extend_from_slice(&0u32.to_le_bytes());
+
+    for _ in 0..4 {
+        tiff.extend_from_slice(&16u16.to_le_bytes());
+    }
+    for sample in samples {
+        tiff.extend_from_slice(&sample.to_le_bytes());
+    }
+    tiff
+}
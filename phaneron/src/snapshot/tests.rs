@@ -0,0 +1,136 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::io::Read;
+
+use phaneron_plugin::COLOUR_SPEC_SRGB;
+
+use super::{encode_snapshot, SnapshotFormat};
+use crate::colour::gamma_to_linear;
+
+fn png_chunks(png: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+    let mut chunks = vec![];
+    let mut position = 8;
+    while position < png.len() {
+        let length = u32::from_be_bytes(png[position..position + 4].try_into().unwrap()) as usize;
+        let chunk_type: [u8; 4] = png[position + 4..position + 8].try_into().unwrap();
+        let data = png[position + 8..position + 8 + length].to_vec();
+        let crc = u32::from_be_bytes(
+            png[position + 8 + length..position + 12 + length]
+                .try_into()
+                .unwrap(),
+        );
+        assert_eq!(
+            crc,
+            crc32fast::hash(&png[position + 4..position + 8 + length])
+        );
+        chunks.push((chunk_type, data));
+        position += 12 + length;
+    }
+    chunks
+}
+
+/// Decodes the red samples of a 16-bit RGBA PNG written without filtering.
+fn png16_red_samples(png: &[u8], width: usize) -> Vec<u16> {
+    let compressed: Vec<u8> = png_chunks(png)
+        .into_iter()
+        .filter(|(chunk_type, _)| chunk_type == b"IDAT")
+        .flat_map(|(_, data)| data)
+        .collect();
+    let mut scanlines = vec![];
+    flate2::read::ZlibDecoder::new(compressed.as_slice())
+        .read_to_end(&mut scanlines)
+        .unwrap();
+
+    scanlines
+        .chunks_exact(1 + width * 8)
+        .flat_map(|row| {
+            assert_eq!(row[0], 0);
+            row[1..]
+                .chunks_exact(8)
+                .map(|pixel| u16::from_be_bytes([pixel[0], pixel[1]]))
+                .collect::<Vec<u16>>()
+        })
+        .collect()
+}
+
+/// Every code value of a 10-bit sRGB ramp, converted to linear light as it would be held on the GPU.
+fn ten_bit_gradient() -> Vec<f32> {
+    (0..1024)
+        .flat_map(|code| {
+            let value = gamma_to_linear(&COLOUR_SPEC_SRGB, code as f32 / 1023.0);
+            [value, value, value, 1.0]
+        })
+        .collect()
+}
+
+#[test]
+fn ten_bit_gradient_survives_as_png16() {
+    let png = encode_snapshot(SnapshotFormat::Png16, 1024, 1, &ten_bit_gradient());
+
+    let red = png16_red_samples(&png, 1024);
+    assert_eq!(red.len(), 1024);
+    assert!(red.windows(2).all(|pair| pair[0] < pair[1]));
+    for (code, sample) in red.iter().enumerate() {
+        let expected = code as f32 / 1023.0 * 65535.0;
+        assert!((*sample as f32 - expected).abs() <= 1.0);
+    }
+}
+
+#[test]
+fn png_bit_depth_follows_format() {
+    for (format, bit_depth) in [(SnapshotFormat::Png8, 8), (SnapshotFormat::Png16, 16)] {
+        let png = encode_snapshot(format, 1024, 1, &ten_bit_gradient());
+
+        let (chunk_type, header) = png_chunks(&png).remove(0);
+        assert_eq!(&chunk_type, b"IHDR");
+        assert_eq!(header[8], bit_depth);
+    }
+}
+
+#[test]
+fn png_is_tagged_as_srgb() {
+    let png = encode_snapshot(SnapshotFormat::Png8, 2, 2, &[0.5; 16]);
+
+    let chunk_types: Vec<[u8; 4]> = png_chunks(&png)
+        .into_iter()
+        .map(|(chunk_type, _)| chunk_type)
+        .collect();
+    assert_eq!(
+        chunk_types,
+        vec![*b"IHDR", *b"sRGB", *b"gAMA", *b"cHRM", *b"IDAT", *b"IEND"]
+    );
+}
+
+#[test]
+fn tiff_holds_16_bit_srgb_samples() {
+    let white = [1.0, 1.0, 1.0, 1.0];
+    let grey = [0.214, 0.214, 0.214, 0.5];
+    let pixels: Vec<f32> = white.iter().chain(grey.iter()).copied().collect();
+    let tiff = encode_snapshot(SnapshotFormat::Tiff, 2, 1, &pixels);
+
+    assert_eq!(&tiff[0..4], &[b'I', b'I', 42, 0]);
+    let samples: Vec<u16> = tiff[tiff.len() - 16..]
+        .chunks_exact(2)
+        .map(|sample| u16::from_le_bytes([sample[0], sample[1]]))
+        .collect();
+    assert_eq!(&samples[0..4], &[65535, 65535, 65535, 65535]);
+    // Linear 0.214 is close to the sRGB mid-point
+    assert!((samples[4] as i32 - 32768).abs() < 200);
+    assert_eq!(samples[7], 32768);
+}
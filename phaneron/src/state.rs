@@ -16,48 +16,142 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    hash::Hash,
+    sync::Arc,
+    time::Duration,
+};
 
 use abi_stable::std_types::ROption::{RNone, RSome};
 use phaneron_plugin::{
     types::Node, types::NodeHandle, AudioInputId, AudioOutputId, VideoInputId, VideoOutputId,
 };
 use serde::{Deserialize, Serialize};
-use tokio::sync::{mpsc::UnboundedReceiver, Mutex};
-use tracing::debug;
+use tokio::{
+    sync::{mpsc::UnboundedReceiver, Mutex},
+    time::MissedTickBehavior,
+};
+use tracing::{debug, error, info, warn};
 
 use crate::{
+    automation::{Automation, AutomationError, AutomationId, CreateAutomation},
     channel::ChannelSemaphoreProvider,
-    compute::PhaneronComputeContext,
+    compute::{
+        device::DeviceInfo, video_frame::download_frame, video_output::VideoOutputFormat,
+        BufferPoolUsage, ComputeError, ComputePriority, ComputeStatsReport, PhaneronComputeContext,
+    },
+    config::GraphsConfig,
+    dot::{GraphTopology, TopologyConnection, TopologyConnectionType, TopologyNode},
+    graph::{
+        would_create_cycle, FrameFormat, FrameLeadLimit, GraphClock, GraphControls, GraphMode,
+        GraphSafety, PanicSlate, PauseGate, Slate, StallAlarm, StallMonitor,
+    },
+    metrics::NodeMetricsReport,
     node_context::{
-        apply_node_state, create_node_context, handle_node_event, run_node, NodeEvent,
-        NodeRunContext, NodeStateEvent,
+        apply_node_state, create_node_context, handle_node_event, run_node, AudioConnectionError,
+        NodeEvent, NodePorts, NodeRunContext, NodeStateEvent, VideoConnectionError,
     },
     plugins::PluginManager,
+    saved_graph::{SavedConnection, SavedConnectionType, SavedGraph, SavedNode},
+    snapshot::{encode_snapshot, SnapshotFormat},
     GraphId, NodeId,
 };
 
+#[cfg(test)]
+mod tests;
+
+/// How long a snapshot waits for an output to produce a frame.
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait between attempts to recreate the compute context after the device was lost.
+const COMPUTE_RECREATE_RETRY: Duration = Duration::from_secs(1);
+
 /// Representation of the state that is safe to expose to the outside world
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct PhaneronStateRepresentation {
     pub nodes: HashMap<String, PhaneronNodeRepresentation>,
+    /// Maps graph Ids to the Ids of the nodes in the graph.
+    #[serde(default)]
+    pub graphs: HashMap<String, Vec<String>>,
     pub video_outputs: HashMap<String, Vec<String>>,
     pub video_inputs: HashMap<String, Vec<String>>,
+    pub audio_outputs: HashMap<String, Vec<String>>,
+    pub audio_inputs: HashMap<String, Vec<String>>,
+    /// Maps video input Ids to the video output Id they are connected to.
+    /// Kept as `connections` for existing clients, it predates `audio_connections`.
     pub connections: HashMap<String, String>,
+    /// Maps audio input Ids to the audio output Id they are connected to.
+    pub audio_connections: HashMap<String, String>,
+}
+
+/// The safety behaviour of a graph and the inputs that are currently holding their last frame.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphAlarms {
+    pub safety: GraphSafety,
+    pub alarms: Vec<StallAlarm>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The device the compute context runs on, whether the context is being recovered, how the last
+/// recovery went and how much of the video buffer pool is in use.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComputeHealth {
+    /// `None` while the device can't be queried, e.g. after it has been lost.
+    pub device: Option<DeviceInfo>,
+    pub recovering: bool,
+    pub recoveries: usize,
+    pub last_recovery: Option<ComputeRecovery>,
+    pub buffer_pool: BufferPoolUsage,
+}
+
+/// A warm restart of the GPU pipeline after the device was lost, see [`PhaneronState::recover_compute_context`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComputeRecovery {
+    /// The error that showed the device had been lost.
+    pub cause: String,
+    /// Seconds since the Unix epoch at which the nodes had been recreated.
+    pub recovered_at: u64,
+    pub recovery_ms: u128,
+    pub recreated_nodes: Vec<String>,
+    pub failed_nodes: Vec<String>,
+    pub failed_connections: Vec<String>,
+}
+
+/// The mode of a graph and how far each of its nodes is running ahead of the nodes consuming its frames.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphPacing {
+    pub mode: GraphMode,
+    pub max_frames_ahead: usize,
+    pub frames_ahead: HashMap<String, usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PhaneronNodeRepresentation {
     name: Option<String>,
     state: Option<String>,
+    #[serde(default)]
+    muted_inputs: Vec<String>,
+    #[serde(default)]
+    soloed_inputs: Vec<String>,
 }
 
-pub fn create_phaneron_state(context: PhaneronComputeContext) -> PhaneronState {
+/// `node_initialize_timeout` is how long graph creation waits for a plugin to initialize a node.
+/// `graphs_config` sets how far nodes may run ahead of their consumers in each graph mode.
+pub fn create_phaneron_state(
+    context: PhaneronComputeContext,
+    node_initialize_timeout: Duration,
+    graphs_config: GraphsConfig,
+) -> PhaneronState {
     let (node_event_tx, node_event_rx) = tokio::sync::mpsc::unbounded_channel();
     let (state_event_tx, state_event_rx) = tokio::sync::broadcast::channel(10);
     let inner = Arc::new(PhaneronStateInner::new(
         node_event_tx,
         state_event_tx.clone(),
+        node_initialize_timeout,
+        graphs_config,
     ));
     tokio::spawn(handle_node_events(
         node_event_rx,
@@ -83,13 +177,139 @@ pub struct CreateNode {
     pub node_name: Option<String>,
     pub state: Option<String>,
     pub configuration: Option<String>,
+    pub priority: ComputePriority,
+    /// Ids for the node's inputs and outputs, new Ids are used when empty.
+    pub ports: NodePorts,
+}
+
+#[derive(Debug)]
+pub enum GraphError {
+    GraphDoesNotExist(GraphId),
+}
+
+#[derive(Debug)]
+pub enum NodeStateError {
+    GraphDoesNotExist(GraphId),
+    NodeDoesNotExist(NodeId),
+}
+
+#[derive(Debug)]
+pub enum OutputError {
+    GraphDoesNotExist(GraphId),
+    NodeDoesNotExist(NodeId),
+    OutputDoesNotExist(String),
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Output(OutputError),
+    /// The output did not produce a frame before the snapshot timed out.
+    NoFrame,
+    /// The frame could not be downloaded from the GPU.
+    DownloadFailed(ComputeError),
+}
+
+#[derive(Debug)]
+pub enum InputError {
+    NodeDoesNotExist(NodeId),
+    InputDoesNotExist(String),
+    /// The order does not contain each of the node's inputs exactly once.
+    InvalidOrder,
+}
+
+#[derive(Debug)]
+pub enum CreateNodeError {
+    /// A node with the same Id already exists.
+    NodeAlreadyExists,
+    /// The plugin providing the node type could not create the node.
+    CreateFailed(String),
+    /// The plugin panicked while initializing the node.
+    InitializeFailed,
+    /// The plugin did not initialize the node within the initialize timeout.
+    InitializeTimedOut(Duration),
+}
+
+#[derive(Debug)]
+pub enum ConnectionError {
+    /// The node was part of the same graph creation and failed to be created.
+    NodeNotCreated(NodeId),
+    NodeDoesNotExist(NodeId),
+    /// The node has no output at the index.
+    OutputDoesNotExist(NodeId, usize),
+    /// The node has no input at the index.
+    InputDoesNotExist(NodeId, usize),
+    /// The connection from the first node to the second would close a loop, in which the nodes
+    /// would wait on each other's frames forever.
+    WouldCreateCycle(NodeId, NodeId),
+    Video(VideoConnectionError),
+    Audio(AudioConnectionError),
+}
+
+/// What was and wasn't created by [`PhaneronState::create_graph`].
+#[derive(Debug, Default)]
+pub struct CreateGraphReport {
+    /// Nodes that were added to the graph, empty if they were removed again.
+    pub created_nodes: Vec<NodeId>,
+    pub failed_nodes: Vec<(NodeId, CreateNodeError)>,
+    pub failed_connections: Vec<(CreateConnection, ConnectionError)>,
+    /// Whether the created nodes were removed again because something failed.
+    pub rolled_back: bool,
+}
+
+impl CreateGraphReport {
+    pub fn is_complete(&self) -> bool {
+        self.failed_nodes.is_empty() && self.failed_connections.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub struct CreateGraphError(pub CreateGraphReport);
+
+impl std::fmt::Display for CreateGraphError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let report = &self.0;
+        let mut failures: Vec<String> = report
+            .failed_nodes
+            .iter()
+            .map(|(node_id, err)| format!("node {node_id}: {err:?}"))
+            .collect();
+        failures.extend(report.failed_connections.iter().map(|(connection, err)| {
+            format!(
+                "connection {}:{} -> {}:{}: {err:?}",
+                connection.from_node_id,
+                connection.from_output_index,
+                connection.to_node_id,
+                connection.to_input_index
+            )
+        }));
+        write!(f, "Failed to create graph ({})", failures.join(", "))?;
+        if report.rolled_back {
+            write!(f, ", no nodes were created")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for CreateGraphError {}
+
+#[derive(Debug)]
+pub enum ImportGraphError {
+    GraphAlreadyExists(GraphId),
+    /// No loaded plugin provides the node type.
+    UnknownNodeType(String),
+    /// The frame rate or sample rate of the graph is zero.
+    InvalidFrameFormat(FrameFormat),
+    CreateFailed(CreateGraphError),
 }
 
+#[derive(Debug)]
 pub enum CreateConnectionType {
     Video,
     Audio,
 }
 
+#[derive(Debug)]
 pub struct CreateConnection {
     pub connection_type: CreateConnectionType,
     pub from_node_id: String,
@@ -105,20 +325,73 @@ pub struct PhaneronState {
 }
 
 impl PhaneronState {
+    /// Creates nodes in a graph and connects them. Connections may also refer to nodes that already exist.
+    ///
+    /// Nodes that fail to be created or don't initialize within the initialize timeout are skipped along
+    /// with their connections, as are connections that can't be made. If anything fails then the nodes
+    /// created by this call are removed again, unless `keep_partial` is set, in which case they are
+    /// left running. Either way the error reports exactly what was created and what failed.
+    #[allow(clippy::too_many_arguments)]
     pub async fn create_graph(
         &self,
         plugin_manager: &PluginManager,
         graph_id: &GraphId,
         nodes: Vec<CreateNode>,
         connections: Vec<CreateConnection>,
-    ) -> anyhow::Result<()> {
+        keep_partial: bool,
+        safety: GraphSafety,
+        frame_format: FrameFormat,
+    ) -> Result<CreateGraphReport, CreateGraphError> {
+        let mut report = CreateGraphReport::default();
+        let graph_existed = self.inner.graphs.lock().await.contains_key(graph_id);
+        // The safety and frame format of an existing graph are kept, nodes added to it share its behaviour
+        self.inner
+            .graph_stall_monitors
+            .lock()
+            .await
+            .entry(graph_id.clone())
+            .or_insert_with(|| StallMonitor::new(safety));
+        self.inner
+            .graph_frame_formats
+            .lock()
+            .await
+            .entry(graph_id.clone())
+            .or_insert(frame_format);
+        self.inner
+            .graph_clocks
+            .lock()
+            .await
+            .entry(graph_id.clone())
+            .or_insert_with(|| GraphClock::new(frame_format));
+
         let mut created_node_handles: Vec<(NodeId, NodeHandle)> = vec![];
         let mut node_configurations: HashMap<NodeId, String> = HashMap::new();
+        let mut node_priorities: HashMap<NodeId, ComputePriority> = HashMap::new();
+        let mut node_ports: HashMap<NodeId, NodePorts> = HashMap::new();
         for create_node in nodes.iter() {
-            let node = plugin_manager
-                .create_node_handle(create_node.node_id.clone(), create_node.node_type.clone())
-                .unwrap(); // TODO: Don't panic!
             let node_id = NodeId::new_from(create_node.node_id.clone());
+            let exists = self.inner.nodes.lock().await.contains_key(&node_id)
+                || created_node_handles.iter().any(|(id, _)| id == &node_id);
+            if exists {
+                report
+                    .failed_nodes
+                    .push((node_id, CreateNodeError::NodeAlreadyExists));
+                continue;
+            }
+            let node = match plugin_manager
+                .create_node_handle(create_node.node_id.clone(), create_node.node_type.clone())
+            {
+                Ok(node) => node,
+                Err(err) => {
+                    error!("Failed to create node {node_id}: {err}");
+                    report
+                        .failed_nodes
+                        .push((node_id, CreateNodeError::CreateFailed(err)));
+                    continue;
+                }
+            };
+            node_priorities.insert(node_id.clone(), create_node.priority);
+            node_ports.insert(node_id.clone(), create_node.ports.clone());
             created_node_handles.push((node_id.clone(), node));
             if let Some(config) = &create_node.configuration {
                 node_configurations.insert(node_id, config.clone());
@@ -134,35 +407,67 @@ impl PhaneronState {
                 ChannelSemaphoreProvider,
             ),
         > = HashMap::new();
+        let initialize_timeout = self.inner.node_initialize_timeout;
         for (node_id, handle) in created_node_handles {
             let (node_context, node_run_context, state_rx, semaphore_provider) =
                 create_node_context(
-                    self.context.clone(),
+                    self.context
+                        .with_priority(node_priorities.remove(&node_id).unwrap_or_default())
+                        .with_node_stats(&node_id),
                     node_id.clone(),
                     self.get_node_event_channel().await,
+                    self.inner.graphs_config.channel_capacity,
+                    node_ports.remove(&node_id).unwrap_or_default(),
                 )
                 .await;
             let (sender, receiver) = tokio::sync::oneshot::channel();
             let configuration = node_configurations.remove(&node_id);
-            std::thread::spawn(move || {
-                let configuration = match configuration {
-                    Some(config) => RSome(config.into()),
-                    None => RNone,
-                };
-                let node = handle.initialize(node_context, configuration);
-                sender.send(node).ok();
+            // The thread can't be stopped if initialize hangs, if it finishes after the timeout
+            // the receiver has been dropped and the node is discarded.
+            std::thread::spawn({
+                let node_id = node_id.clone();
+                move || {
+                    let configuration = match configuration {
+                        Some(config) => RSome(config.into()),
+                        None => RNone,
+                    };
+                    let node = handle.initialize(node_context, configuration);
+                    if sender.send(node).is_err() {
+                        warn!("Node {node_id} finished initializing after it was abandoned, discarding it");
+                    }
+                }
             });
-            let node = receiver.await.unwrap();
-            initialzed_nodes.insert(
-                node_id,
-                (node, node_run_context, state_rx, semaphore_provider),
-            );
+            match tokio::time::timeout(initialize_timeout, receiver).await {
+                Ok(Ok(node)) => {
+                    initialzed_nodes.insert(
+                        node_id,
+                        (node, node_run_context, state_rx, semaphore_provider),
+                    );
+                }
+                Ok(Err(_)) => {
+                    // The sender is only dropped without sending if initialize panicked
+                    error!("Node {node_id} failed to initialize");
+                    report
+                        .failed_nodes
+                        .push((node_id, CreateNodeError::InitializeFailed));
+                }
+                Err(_) => {
+                    error!("Node {node_id} did not initialize within {initialize_timeout:?}, abandoning it");
+                    report.failed_nodes.push((
+                        node_id,
+                        CreateNodeError::InitializeTimedOut(initialize_timeout),
+                    ));
+                }
+            }
         }
 
         for create_node in nodes {
             let node_id = NodeId::new_from(create_node.node_id.clone());
-            let (node, run_context, node_event_rx, semaphore_provider) =
-                initialzed_nodes.remove(&node_id).unwrap();
+            let Some((node, run_context, node_event_rx, semaphore_provider)) =
+                initialzed_nodes.remove(&node_id)
+            else {
+                continue;
+            };
             let node = Arc::new(node);
             if let Some(state) = create_node.state {
                 apply_node_state(
@@ -176,135 +481,211 @@ impl PhaneronState {
             self.add_node(
                 graph_id,
                 &node_id,
-                create_node.node_name,
-                run_context,
+                PhaneronStateNode {
+                    name: create_node.node_name,
+                    node_type: create_node.node_type,
+                    configuration: create_node.configuration,
+                    priority: create_node.priority,
+                    context: run_context,
+                },
                 node,
                 node_event_rx,
                 semaphore_provider,
             )
             .await;
+            report.created_nodes.push(node_id);
         }
 
         for connection in connections {
-            match connection.connection_type {
-                CreateConnectionType::Video => {
-                    let output = {
-                        let video_outputs = self.inner.video_outputs.lock().await;
-                        let from_node_outputs = video_outputs
-                            .get(&NodeId::new_from(connection.from_node_id.clone()))
-                            .unwrap();
-                        from_node_outputs
-                            .get(connection.from_output_index)
-                            .unwrap()
-                            .clone()
-                    };
-                    let input = {
-                        let video_inputs = self.inner.video_inputs.lock().await;
-                        let to_node_inputs = video_inputs
-                            .get(&NodeId::new_from(connection.to_node_id.clone()))
-                            .unwrap();
-                        to_node_inputs
-                            .get(connection.to_input_index)
-                            .unwrap()
-                            .clone()
-                    };
-
-                    let video_pipe = {
-                        let nodes_lock = self.inner.nodes.lock().await;
-                        let from_node = nodes_lock
-                            .get(&NodeId::new_from(connection.from_node_id.clone()))
-                            .unwrap();
-                        from_node.context.get_video_pipe(&output).await
-                    };
+            let failed_node = report.failed_nodes.iter().find(|(node_id, _)| {
+                node_id.to_string() == connection.from_node_id
+                    || node_id.to_string() == connection.to_node_id
+            });
+            let result = match failed_node {
+                Some((node_id, _)) => Err(ConnectionError::NodeNotCreated(node_id.clone())),
+                None => self.create_connection(&connection).await,
+            };
+            if let Err(err) = result {
+                error!(
+                    "Failed to connect {}:{} to {}:{}: {err:?}",
+                    connection.from_node_id,
+                    connection.from_output_index,
+                    connection.to_node_id,
+                    connection.to_input_index
+                );
+                report.failed_connections.push((connection, err));
+            }
+        }
 
-                    let to_node_context = {
-                        let nodes_lock = self.inner.nodes.lock().await;
-                        let to_node = nodes_lock
-                            .get(&NodeId::new_from(connection.to_node_id.clone()))
-                            .unwrap();
-                        to_node.context.clone()
-                    };
+        if report.is_complete() {
+            return Ok(report);
+        }
 
-                    to_node_context
-                        .connect_video_pipe(&input, video_pipe)
-                        .await
-                        .unwrap();
-
-                    video_pipe_connected(
-                        PhaneronState {
-                            context: self.context.clone(),
-                            inner: self.inner.clone(),
-                        },
-                        input,
-                        output,
-                    )
-                    .await;
+        if !keep_partial {
+            for node_id in report.created_nodes.drain(..) {
+                self.remove_node(plugin_manager, graph_id, &node_id)
+                    .await
+                    .ok();
+            }
+            // The graph is only known if a node was added to it, its format is set up either way
+            if !graph_existed {
+                let mut graphs = self.inner.graphs.lock().await;
+                if graphs.get(graph_id).is_some_and(|nodes| nodes.is_empty()) {
+                    graphs.remove(graph_id);
                 }
-                CreateConnectionType::Audio => {
-                    let output = {
-                        let audio_outputs = self.inner.audio_outputs.lock().await;
-                        let from_node_outputs = audio_outputs
-                            .get(&NodeId::new_from(connection.from_node_id.clone()))
-                            .unwrap();
-                        from_node_outputs
-                            .get(connection.from_output_index)
-                            .unwrap()
-                            .clone()
-                    };
-                    let input = {
-                        let audio_inputs = self.inner.audio_inputs.lock().await;
-                        let to_node_inputs = audio_inputs
-                            .get(&NodeId::new_from(connection.to_node_id.clone()))
-                            .unwrap();
-                        to_node_inputs
-                            .get(connection.to_input_index)
-                            .unwrap()
-                            .clone()
-                    };
+                self.inner.graph_pause_gates.lock().await.remove(graph_id);
+                self.inner.graph_frame_leads.lock().await.remove(graph_id);
+                self.inner.graph_panic_slates.lock().await.remove(graph_id);
+                self.inner.graph_frame_formats.lock().await.remove(graph_id);
+                self.inner.graph_clocks.lock().await.remove(graph_id);
+                self.inner
+                    .graph_stall_monitors
+                    .lock()
+                    .await
+                    .remove(graph_id);
+            }
+            report.rolled_back = true;
+        }
 
-                    let audio_pipe = {
-                        let nodes_lock = self.inner.nodes.lock().await;
-                        let from_node = nodes_lock
-                            .get(&NodeId::new_from(connection.from_node_id.clone()))
-                            .unwrap();
-                        from_node.context.get_audio_pipe(&output).await
-                    };
+        Err(CreateGraphError(report))
+    }
 
-                    let to_node_context = {
-                        let nodes_lock = self.inner.nodes.lock().await;
-                        let to_node = nodes_lock
-                            .get(&NodeId::new_from(connection.to_node_id.clone()))
-                            .unwrap();
-                        to_node.context.clone()
-                    };
+    async fn create_connection(
+        &self,
+        connection: &CreateConnection,
+    ) -> Result<(), ConnectionError> {
+        let from_node_id = NodeId::new_from(connection.from_node_id.clone());
+        let to_node_id = NodeId::new_from(connection.to_node_id.clone());
+        let (from_node_context, to_node_context) = {
+            let nodes = self.inner.nodes.lock().await;
+            let from_node = nodes
+                .get(&from_node_id)
+                .ok_or_else(|| ConnectionError::NodeDoesNotExist(from_node_id.clone()))?;
+            let to_node = nodes
+                .get(&to_node_id)
+                .ok_or_else(|| ConnectionError::NodeDoesNotExist(to_node_id.clone()))?;
+            (from_node.context.clone(), to_node.context.clone())
+        };
 
-                    to_node_context
-                        .connect_audio_pipe(&input, audio_pipe)
-                        .await
-                        .unwrap();
-
-                    audio_pipe_connected(
-                        PhaneronState {
-                            context: self.context.clone(),
-                            inner: self.inner.clone(),
-                        },
-                        input,
-                        output,
-                    )
-                    .await;
-                }
+        if self.would_create_cycle(&from_node_id, &to_node_id).await {
+            return Err(ConnectionError::WouldCreateCycle(from_node_id, to_node_id));
+        }
+
+        match connection.connection_type {
+            CreateConnectionType::Video => {
+                let output = self
+                    .inner
+                    .video_outputs
+                    .lock()
+                    .await
+                    .get(&from_node_id)
+                    .and_then(|outputs| outputs.get(connection.from_output_index).cloned())
+                    .ok_or_else(|| {
+                        ConnectionError::OutputDoesNotExist(
+                            from_node_id.clone(),
+                            connection.from_output_index,
+                        )
+                    })?;
+                let input = self
+                    .inner
+                    .video_inputs
+                    .lock()
+                    .await
+                    .get(&to_node_id)
+                    .and_then(|inputs| inputs.get(connection.to_input_index).cloned())
+                    .ok_or_else(|| {
+                        ConnectionError::InputDoesNotExist(
+                            to_node_id.clone(),
+                            connection.to_input_index,
+                        )
+                    })?;
+
+                let video_pipe = from_node_context.get_video_pipe(&output).await;
+                to_node_context
+                    .connect_video_pipe(&input, video_pipe)
+                    .await
+                    .map_err(ConnectionError::Video)?;
+
+                video_pipe_connected(
+                    PhaneronState {
+                        context: self.context.clone(),
+                        inner: self.inner.clone(),
+                    },
+                    input,
+                    output,
+                )
+                .await;
+            }
+            CreateConnectionType::Audio => {
+                let output = self
+                    .inner
+                    .audio_outputs
+                    .lock()
+                    .await
+                    .get(&from_node_id)
+                    .and_then(|outputs| outputs.get(connection.from_output_index).cloned())
+                    .ok_or_else(|| {
+                        ConnectionError::OutputDoesNotExist(
+                            from_node_id.clone(),
+                            connection.from_output_index,
+                        )
+                    })?;
+                let input = self
+                    .inner
+                    .audio_inputs
+                    .lock()
+                    .await
+                    .get(&to_node_id)
+                    .and_then(|inputs| inputs.get(connection.to_input_index).cloned())
+                    .ok_or_else(|| {
+                        ConnectionError::InputDoesNotExist(
+                            to_node_id.clone(),
+                            connection.to_input_index,
+                        )
+                    })?;
+
+                let audio_pipe = from_node_context.get_audio_pipe(&output).await;
+                to_node_context
+                    .connect_audio_pipe(&input, audio_pipe)
+                    .await
+                    .map_err(ConnectionError::Audio)?;
+
+                audio_pipe_connected(
+                    PhaneronState {
+                        context: self.context.clone(),
+                        inner: self.inner.clone(),
+                    },
+                    input,
+                    output,
+                )
+                .await;
             }
         }
 
         Ok(())
     }
 
+    /// Whether connecting `from` to `to` would close a loop through the existing connections.
+    async fn would_create_cycle(&self, from: &NodeId, to: &NodeId) -> bool {
+        let mut edges = connection_edges(
+            &*self.inner.video_connections.lock().await,
+            &*self.inner.video_inputs.lock().await,
+            &*self.inner.video_outputs.lock().await,
+        );
+        edges.extend(connection_edges(
+            &*self.inner.audio_connections.lock().await,
+            &*self.inner.audio_inputs.lock().await,
+            &*self.inner.audio_outputs.lock().await,
+        ));
+
+        would_create_cycle(edges.iter().map(|(from, to)| (from, to)), from, to)
+    }
+
     async fn add_node<'a>(
         &self,
         graph_id: &'a GraphId,
         node_id: &'a NodeId,
-        name: Option<String>,
-        node_context: NodeRunContext,
+        state_node: PhaneronStateNode,
         node: Arc<Node>,
         mut node_event_rx: tokio::sync::mpsc::UnboundedReceiver<NodeEvent>,
         semaphore_provider: ChannelSemaphoreProvider,
@@ -313,187 +694,1575 @@ impl PhaneronState {
         let graph_entry = graphs.entry(graph_id.clone()).or_default();
         graph_entry.push(node_id.clone());
 
-        let mut nodes = self.inner.nodes.lock().await;
-        nodes.insert(
-            node_id.clone(),
-            PhaneronStateNode {
-                name,
-                context: node_context.clone(),
-            },
-        );
+        let pause_gate = self
+            .inner
+            .graph_pause_gates
+            .lock()
+            .await
+            .entry(graph_id.clone())
+            .or_default()
+            .clone();
+        let controls = GraphControls {
+            pause_gate,
+            frame_lead: self.graph_frame_lead(graph_id).await,
+            stall_monitor: self.graph_stall_monitor(graph_id).await,
+            panic_slate: self.graph_panic_slate(graph_id).await,
+            clock: self.graph_clock(graph_id).await,
+        };
 
-        let pending_state_channel = node_context.get_pending_state_channel();
+        let node_context = state_node.context.clone();
+        let mut nodes = self.inner.nodes.lock().await;
+        nodes.insert(node_id.clone(), state_node);
 
         // Block and handle initial events
         while let Ok(event) = node_event_rx.try_recv() {
             handle_node_event(event, node_context.clone()).await;
         }
 
-        tokio::spawn(run_node(
+        let task = tokio::spawn(run_node(
             self.context.clone(),
             node_context,
             node,
-            pending_state_channel,
             self.get_node_event_channel().await,
             node_event_rx,
             semaphore_provider,
+            controls,
         ));
+        self.inner
+            .node_tasks
+            .lock()
+            .await
+            .insert(node_id.clone(), task);
 
         self.inner.state_event_tx.send(()).ok();
     }
 
-    pub async fn get_node_event_channel(
+    /// Pauses or resumes every node in a graph. Producers finish the frame they are producing and
+    /// then wait until the graph is resumed, the other nodes repeat the last frames they received
+    /// on the graph's clock. Connections and state are left intact.
+    pub async fn set_graph_paused(
         &self,
-    ) -> tokio::sync::mpsc::UnboundedSender<NodeStateEvent> {
-        self.inner.node_event_tx.clone()
-    }
+        graph_id: &GraphId,
+        paused: bool,
+    ) -> Result<(), GraphError> {
+        if !self.inner.graphs.lock().await.contains_key(graph_id) {
+            return Err(GraphError::GraphDoesNotExist(graph_id.clone()));
+        }
 
-    pub async fn set_node_name(&self, graph_id: &GraphId, node_id: &NodeId, name: Option<String>) {
-        let mut nodes = self.inner.nodes.lock().await;
-        let node = nodes.get_mut(node_id).unwrap();
-        node.name = name;
+        self.inner
+            .graph_pause_gates
+            .lock()
+            .await
+            .entry(graph_id.clone())
+            .or_default()
+            .set_paused(paused);
+
+        Ok(())
     }
 
-    pub async fn set_node_state(&self, graph_id: &GraphId, node_id: &NodeId, state: String) {
-        debug!("Setting node {} state to {}", node_id, state);
-        let nodes = self.inner.nodes.lock().await;
-        let node = nodes.get(node_id).unwrap();
-        node.context.set_state(state).await;
+    /// Resumes a graph that has been frozen on its current frame, or freezes it, see
+    /// [`Self::set_graph_paused`].
+    pub async fn set_graph_running(
+        &self,
+        graph_id: &GraphId,
+        running: bool,
+    ) -> Result<(), GraphError> {
+        self.set_graph_paused(graph_id, !running).await
     }
 
-    pub async fn get_node_state(&self, graph_id: &GraphId, node_id: &NodeId) -> Option<String> {
-        self.inner.node_states.lock().await.get(node_id).cloned()
+    pub async fn is_graph_paused(&self, graph_id: &GraphId) -> Result<bool, GraphError> {
+        if !self.inner.graphs.lock().await.contains_key(graph_id) {
+            return Err(GraphError::GraphDoesNotExist(graph_id.clone()));
+        }
+
+        Ok(self
+            .inner
+            .graph_pause_gates
+            .lock()
+            .await
+            .get(graph_id)
+            .map(|gate| gate.is_paused())
+            .unwrap_or_default())
     }
 
-    pub async fn get_available_audio_inputs(
-        &self,
-        graph_id: &GraphId,
-        node_id: &NodeId,
-    ) -> Vec<AudioInputId> {
+    async fn graph_frame_lead(&self, graph_id: &GraphId) -> FrameLeadLimit {
         self.inner
-            .audio_inputs
+            .graph_frame_leads
             .lock()
             .await
-            .get(node_id)
-            .cloned()
-            .unwrap_or_default()
+            .entry(graph_id.clone())
+            .or_insert_with(|| FrameLeadLimit::new(self.inner.graphs_config))
+            .clone()
     }
 
-    pub async fn get_available_video_inputs(
-        &self,
-        graph_id: &GraphId,
-        node_id: &NodeId,
-    ) -> Vec<VideoInputId> {
+    async fn graph_stall_monitor(&self, graph_id: &GraphId) -> StallMonitor {
         self.inner
-            .video_inputs
+            .graph_stall_monitors
             .lock()
             .await
-            .get(node_id)
-            .cloned()
-            .unwrap_or_default()
+            .entry(graph_id.clone())
+            .or_default()
+            .clone()
     }
 
-    pub async fn subscribe(&self) -> tokio::sync::broadcast::Receiver<PhaneronStateRepresentation> {
-        let (sender, receiver) = tokio::sync::broadcast::channel(1); // Only the latest value is relevant
-
-        {
-            let state = self.get_state().await;
-            sender.send(state).unwrap();
-        }
-        self.inner.subscribers_to_state.lock().await.push(sender);
+    async fn graph_frame_format(&self, graph_id: &GraphId) -> FrameFormat {
+        *self
+            .inner
+            .graph_frame_formats
+            .lock()
+            .await
+            .entry(graph_id.clone())
+            .or_default()
+    }
 
-        receiver
+    async fn graph_clock(&self, graph_id: &GraphId) -> GraphClock {
+        let frame_format = self.graph_frame_format(graph_id).await;
+        *self
+            .inner
+            .graph_clocks
+            .lock()
+            .await
+            .entry(graph_id.clone())
+            .or_insert_with(|| GraphClock::new(frame_format))
     }
 
-    async fn get_state(&self) -> PhaneronStateRepresentation {
-        let mut nodes = HashMap::new();
-        let mut video_outputs = HashMap::new();
-        let mut video_inputs = HashMap::new();
-        let mut connections = HashMap::new();
+    async fn graph_panic_slate(&self, graph_id: &GraphId) -> PanicSlate {
+        self.inner
+            .graph_panic_slates
+            .lock()
+            .await
+            .entry(graph_id.clone())
+            .or_default()
+            .clone()
+    }
 
-        let inner_node_states = self.inner.node_states.lock().await.clone();
-        for (node_id, node) in self.inner.nodes.lock().await.iter() {
-            let node_state = inner_node_states.get(node_id);
-            nodes.insert(
-                node_id.to_string(),
-                PhaneronNodeRepresentation {
-                    name: node.name.clone(),
-                    state: node_state.cloned(),
-                },
-            );
+    /// Cuts every consumer in a graph to a slate until the panic is cleared. The graph keeps
+    /// running and consumers keep emitting frames, so downstream encoders and devices stay alive.
+    pub async fn panic_graph(&self, graph_id: &GraphId, slate: Slate) -> Result<(), GraphError> {
+        if !self.inner.graphs.lock().await.contains_key(graph_id) {
+            return Err(GraphError::GraphDoesNotExist(graph_id.clone()));
         }
 
-        let inner_video_outputs = self.inner.video_outputs.lock().await.clone();
-        for (node_id, output) in inner_video_outputs.iter() {
-            video_outputs.insert(
-                node_id.to_string(),
-                output.iter().map(|o| o.to_string()).collect(),
-            );
+        self.graph_panic_slate(graph_id).await.set(Some(slate));
+
+        Ok(())
+    }
+
+    /// Returns consumers in a graph to their normal output.
+    pub async fn clear_graph_panic(&self, graph_id: &GraphId) -> Result<(), GraphError> {
+        if !self.inner.graphs.lock().await.contains_key(graph_id) {
+            return Err(GraphError::GraphDoesNotExist(graph_id.clone()));
         }
 
-        let inner_video_inputs = self.inner.video_inputs.lock().await.clone();
-        for (node_id, output) in inner_video_inputs.iter() {
-            video_inputs.insert(
-                node_id.to_string(),
-                output.iter().map(|o| o.to_string()).collect(),
-            );
+        self.graph_panic_slate(graph_id).await.set(None);
+
+        Ok(())
+    }
+
+    /// The slate a graph's consumers are showing, `None` if the graph is not in panic.
+    pub async fn graph_panic(&self, graph_id: &GraphId) -> Result<Option<Slate>, GraphError> {
+        if !self.inner.graphs.lock().await.contains_key(graph_id) {
+            return Err(GraphError::GraphDoesNotExist(graph_id.clone()));
         }
 
-        let inner_connections = self.inner.video_connections.lock().await.clone();
-        for (input, output) in inner_connections.iter() {
-            connections.insert(input.to_string(), output.to_string());
+        Ok(self.graph_panic_slate(graph_id).await.slate())
+    }
+
+    pub async fn graph_alarms(&self, graph_id: &GraphId) -> Result<GraphAlarms, GraphError> {
+        if !self.inner.graphs.lock().await.contains_key(graph_id) {
+            return Err(GraphError::GraphDoesNotExist(graph_id.clone()));
         }
+        let stall_monitor = self.graph_stall_monitor(graph_id).await;
 
-        PhaneronStateRepresentation {
-            nodes,
-            video_outputs,
-            video_inputs,
-            connections,
+        Ok(GraphAlarms {
+            safety: stall_monitor.safety(),
+            alarms: stall_monitor.alarms(),
+        })
+    }
+
+    /// Switches a graph between live and batch mode, which changes how far its nodes may run
+    /// ahead of the nodes consuming their frames from their next frame.
+    pub async fn set_graph_mode(
+        &self,
+        graph_id: &GraphId,
+        mode: GraphMode,
+    ) -> Result<(), GraphError> {
+        if !self.inner.graphs.lock().await.contains_key(graph_id) {
+            return Err(GraphError::GraphDoesNotExist(graph_id.clone()));
         }
+
+        self.graph_frame_lead(graph_id).await.set_mode(mode);
+
+        Ok(())
     }
-}
 
-struct PhaneronStateInner {
-    graphs: Mutex<HashMap<GraphId, Vec<NodeId>>>,
-    nodes: Mutex<HashMap<NodeId, PhaneronStateNode>>,
-    node_states: Mutex<HashMap<NodeId, String>>,
-    audio_inputs: Mutex<HashMap<NodeId, Vec<AudioInputId>>>,
-    audio_outputs: Mutex<HashMap<NodeId, Vec<AudioOutputId>>>,
-    video_inputs: Mutex<HashMap<NodeId, Vec<VideoInputId>>>,
-    video_outputs: Mutex<HashMap<NodeId, Vec<VideoOutputId>>>,
-    video_connections: Mutex<HashMap<VideoInputId, VideoOutputId>>,
-    audio_connections: Mutex<HashMap<AudioInputId, AudioOutputId>>,
-    subscribers_to_state: Mutex<Vec<tokio::sync::broadcast::Sender<PhaneronStateRepresentation>>>,
-    node_event_tx: tokio::sync::mpsc::UnboundedSender<NodeStateEvent>,
-    state_event_tx: tokio::sync::broadcast::Sender<()>,
-}
+    pub async fn graph_pacing(&self, graph_id: &GraphId) -> Result<GraphPacing, GraphError> {
+        let graph_nodes = self
+            .inner
+            .graphs
+            .lock()
+            .await
+            .get(graph_id)
+            .cloned()
+            .ok_or_else(|| GraphError::GraphDoesNotExist(graph_id.clone()))?;
+        let frame_lead = self.graph_frame_lead(graph_id).await;
 
-impl PhaneronStateInner {
-    fn new(
-        node_event_tx: tokio::sync::mpsc::UnboundedSender<NodeStateEvent>,
-        state_event_tx: tokio::sync::broadcast::Sender<()>,
-    ) -> Self {
-        Self {
-            graphs: Default::default(),
-            nodes: Default::default(),
-            node_states: Default::default(),
-            audio_inputs: Default::default(),
-            audio_outputs: Default::default(),
-            video_inputs: Default::default(),
-            video_outputs: Default::default(),
-            video_connections: Default::default(),
-            audio_connections: Default::default(),
-            subscribers_to_state: Default::default(),
-            node_event_tx,
-            state_event_tx,
-        }
+        let nodes = self.inner.nodes.lock().await;
+        let frames_ahead = graph_nodes
+            .iter()
+            .filter_map(|node_id| {
+                let node = nodes.get(node_id)?;
+                Some((node_id.to_string(), node.context.frames_ahead()))
+            })
+            .collect();
+
+        Ok(GraphPacing {
+            mode: frame_lead.mode(),
+            max_frames_ahead: frame_lead.max_frames_ahead(),
+            frames_ahead,
+        })
     }
-}
 
-struct PhaneronStateNode {
-    name: Option<String>,
-    context: NodeRunContext,
-}
+    /// The nodes of a graph and the connections between them.
+    pub async fn graph_topology(&self, graph_id: &GraphId) -> Result<GraphTopology, GraphError> {
+        let graph_nodes = self
+            .inner
+            .graphs
+            .lock()
+            .await
+            .get(graph_id)
+            .cloned()
+            .ok_or_else(|| GraphError::GraphDoesNotExist(graph_id.clone()))?;
+
+        let nodes = {
+            let nodes = self.inner.nodes.lock().await;
+            graph_nodes
+                .iter()
+                .filter_map(|node_id| {
+                    nodes.get(node_id).map(|node| TopologyNode {
+                        node_id: node_id.to_string(),
+                        name: node.name.clone(),
+                        node_type: node.node_type.clone(),
+                    })
+                })
+                .collect()
+        };
+
+        let mut connections = topology_connections(
+            TopologyConnectionType::Video,
+            &graph_nodes,
+            &*self.inner.video_connections.lock().await,
+            &*self.inner.video_inputs.lock().await,
+            &*self.inner.video_outputs.lock().await,
+        );
+        connections.extend(topology_connections(
+            TopologyConnectionType::Audio,
+            &graph_nodes,
+            &*self.inner.audio_connections.lock().await,
+            &*self.inner.audio_inputs.lock().await,
+            &*self.inner.audio_outputs.lock().await,
+        ));
+
+        Ok(GraphTopology {
+            graph_id: graph_id.to_string(),
+            nodes,
+            connections,
+        })
+    }
+
+    /// The graph's nodes, their state and configuration, and the connections between them, in a
+    /// form that can be saved and recreated with [`Self::import_graph`].
+    pub async fn export_graph(&self, graph_id: &GraphId) -> Result<SavedGraph, GraphError> {
+        let topology = self.graph_topology(graph_id).await?;
+        let nodes = {
+            let nodes = self.inner.nodes.lock().await;
+            let node_states = self.inner.node_states.lock().await;
+            topology
+                .nodes
+                .iter()
+                .filter_map(|topology_node| {
+                    let node_id = NodeId::new_from(topology_node.node_id.clone());
+                    nodes.get(&node_id).map(|node| SavedNode {
+                        node_id: topology_node.node_id.clone(),
+                        node_type: node.node_type.clone(),
+                        name: node.name.clone(),
+                        state: node_states.get(&node_id).cloned(),
+                        configuration: node.configuration.clone(),
+                        priority: node.priority,
+                    })
+                })
+                .collect()
+        };
+        let mut connections: Vec<SavedConnection> = topology
+            .connections
+            .into_iter()
+            .map(|connection| SavedConnection {
+                connection_type: match connection.connection_type {
+                    TopologyConnectionType::Video => SavedConnectionType::Video,
+                    TopologyConnectionType::Audio => SavedConnectionType::Audio,
+                },
+                from_node_id: connection.from_node_id,
+                from_output_index: connection.from_output_index,
+                to_node_id: connection.to_node_id,
+                to_input_index: connection.to_input_index,
+            })
+            .collect();
+        // Connections are kept in maps, sorted so that exporting the same graph gives the same result
+        connections.sort();
+
+        Ok(SavedGraph {
+            graph_id: graph_id.to_string(),
+            mode: self.graph_frame_lead(graph_id).await.mode(),
+            safety: self.graph_stall_monitor(graph_id).await.safety(),
+            frame_format: self.graph_frame_format(graph_id).await,
+            nodes,
+            connections,
+        })
+    }
+
+    /// Recreates a graph saved with [`Self::export_graph`], keeping its node Ids.
+    ///
+    /// Nothing is created if the graph already exists or one of its node types is not provided by a
+    /// loaded plugin. If a node or connection fails to be created the rest of the graph is removed again.
+    pub async fn import_graph(
+        &self,
+        plugin_manager: &PluginManager,
+        saved: SavedGraph,
+    ) -> Result<CreateGraphReport, ImportGraphError> {
+        let graph_id = GraphId::new_from(saved.graph_id.clone());
+        if self.inner.graphs.lock().await.contains_key(&graph_id) {
+            return Err(ImportGraphError::GraphAlreadyExists(graph_id));
+        }
+        if let Some(node) = saved
+            .nodes
+            .iter()
+            .find(|node| !plugin_manager.provides_node_type(&node.node_type))
+        {
+            return Err(ImportGraphError::UnknownNodeType(node.node_type.clone()));
+        }
+        if !saved.frame_format.is_valid() {
+            return Err(ImportGraphError::InvalidFrameFormat(saved.frame_format));
+        }
+
+        let (nodes, connections) = saved.to_create();
+        let report = self
+            .create_graph(
+                plugin_manager,
+                &graph_id,
+                nodes,
+                connections,
+                false,
+                saved.safety,
+                saved.frame_format,
+            )
+            .await
+            .map_err(ImportGraphError::CreateFailed)?;
+        self.graph_frame_lead(&graph_id).await.set_mode(saved.mode);
+
+        Ok(report)
+    }
+
+    /// Checks the GPU device every `interval`, and whenever processing a frame fails with an error
+    /// that shows the device was lost, and recovers the compute context if the device has been
+    /// lost. Without an interval the device is only checked after such errors. Returns once the
+    /// compute context has been shut down.
+    pub async fn watch_compute_device(
+        &self,
+        plugin_manager: Arc<PluginManager>,
+        interval: Option<Duration>,
+    ) {
+        let mut interval = interval.map(|interval| {
+            let mut interval = tokio::time::interval(interval);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            interval
+        });
+        loop {
+            let tick = async {
+                match interval.as_mut() {
+                    Some(interval) => {
+                        interval.tick().await;
+                    }
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                _ = tick => {}
+                err = self.context.device_lost() => {
+                    warn!("Processing a frame failed as the compute device may have been lost, checking the device: {err}");
+                }
+            }
+            if self.context.is_shut_down() {
+                return;
+            }
+
+            let context = self.context.clone();
+            let Ok(Err(err)) = tokio::task::spawn_blocking(move || context.check_device()).await
+            else {
+                continue;
+            };
+            if err.is_device_lost() {
+                error!("Compute device lost, recreating the compute context: {err}");
+                if self
+                    .recover_compute_context(&plugin_manager, err)
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Recreates the compute context after the device was lost and re-initializes every node so
+    /// that they allocate their GPU resources again. Nodes keep their Ids, names, configuration,
+    /// state and the Ids of their inputs and outputs, and are reconnected as before. Connections
+    /// between graphs are restored once every graph has been recreated. Automations are cancelled
+    /// and input monitoring is reset. Graphs output nothing from when the device is lost until
+    /// their nodes have been recreated.
+    ///
+    /// Fails with [`ComputeError::ShutDown`] if the compute context is shut down while recovering,
+    /// in which case the nodes are not recreated.
+    pub async fn recover_compute_context(
+        &self,
+        plugin_manager: &PluginManager,
+        cause: ComputeError,
+    ) -> Result<ComputeRecovery, ComputeError> {
+        let started = std::time::Instant::now();
+        self.inner.compute_health.lock().await.recovering = true;
+
+        let graph_ids: Vec<GraphId> = self.inner.graphs.lock().await.keys().cloned().collect();
+        let mut graphs = vec![];
+        for graph_id in graph_ids {
+            if let Ok(saved) = self.export_graph(&graph_id).await {
+                graphs.push((graph_id, saved));
+            }
+        }
+        let mut ports: HashMap<String, NodePorts> = HashMap::new();
+        for (_, saved) in graphs.iter() {
+            for node in saved.nodes.iter() {
+                let node_id = NodeId::new_from(node.node_id.clone());
+                ports.insert(node.node_id.clone(), self.node_ports(&node_id).await);
+            }
+        }
+        let cross_graph_connections = self.cross_graph_connections().await;
+
+        // Nodes hold resources of the lost context, they are stopped before it is replaced
+        for (graph_id, saved) in graphs.iter() {
+            for node in saved.nodes.iter() {
+                self.remove_node(
+                    plugin_manager,
+                    graph_id,
+                    &NodeId::new_from(node.node_id.clone()),
+                )
+                .await
+                .ok();
+            }
+        }
+
+        // The device may take a while to come back after a reset
+        loop {
+            let context = self.context.clone();
+            match tokio::task::spawn_blocking(move || context.recreate()).await {
+                Ok(Ok(())) => break,
+                Ok(Err(ComputeError::ShutDown)) => {
+                    self.inner.compute_health.lock().await.recovering = false;
+                    return Err(ComputeError::ShutDown);
+                }
+                Ok(Err(err)) => warn!("Failed to recreate the compute context, retrying: {err}"),
+                Err(err) => warn!("Failed to recreate the compute context, retrying: {err}"),
+            }
+            tokio::time::sleep(COMPUTE_RECREATE_RETRY).await;
+        }
+
+        let mut recreated_nodes = vec![];
+        let mut failed_nodes = vec![];
+        let mut failed_connections = vec![];
+        let describe_connection = |connection: &CreateConnection, err: &ConnectionError| {
+            format!(
+                "{}:{} -> {}:{}: {err:?}",
+                connection.from_node_id,
+                connection.from_output_index,
+                connection.to_node_id,
+                connection.to_input_index
+            )
+        };
+        for (graph_id, saved) in graphs {
+            let (mut nodes, connections) = saved.to_create();
+            for node in nodes.iter_mut() {
+                node.ports = ports.remove(&node.node_id).unwrap_or_default();
+            }
+            let report = match self
+                .create_graph(
+                    plugin_manager,
+                    &graph_id,
+                    nodes,
+                    connections,
+                    true,
+                    saved.safety,
+                    saved.frame_format,
+                )
+                .await
+            {
+                Ok(report) => report,
+                Err(CreateGraphError(report)) => report,
+            };
+            recreated_nodes.extend(
+                report
+                    .created_nodes
+                    .iter()
+                    .map(|node_id| node_id.to_string()),
+            );
+            failed_nodes.extend(
+                report
+                    .failed_nodes
+                    .iter()
+                    .map(|(node_id, err)| format!("{node_id}: {err:?}")),
+            );
+            failed_connections.extend(
+                report
+                    .failed_connections
+                    .iter()
+                    .map(|(connection, err)| describe_connection(connection, err)),
+            );
+        }
+        // Both ends of a connection between graphs only exist once every graph has been recreated
+        for connection in cross_graph_connections {
+            if let Err(err) = self.create_connection(&connection).await {
+                error!(
+                    "Failed to restore connection between graphs {}:{} to {}:{}: {err:?}",
+                    connection.from_node_id,
+                    connection.from_output_index,
+                    connection.to_node_id,
+                    connection.to_input_index
+                );
+                failed_connections.push(describe_connection(&connection, &err));
+            }
+        }
+        let recovery = ComputeRecovery {
+            cause: cause.to_string(),
+            recovered_at: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_secs())
+                .unwrap_or_default(),
+            recovery_ms: started.elapsed().as_millis(),
+            recreated_nodes,
+            failed_nodes,
+            failed_connections,
+        };
+        info!(
+            "Recovered the compute context in {}ms, recreated {} nodes, {} nodes and {} connections failed",
+            recovery.recovery_ms,
+            recovery.recreated_nodes.len(),
+            recovery.failed_nodes.len(),
+            recovery.failed_connections.len()
+        );
+
+        let mut health = self.inner.compute_health.lock().await;
+        health.recovering = false;
+        health.recoveries += 1;
+        health.last_recovery = Some(recovery.clone());
+
+        Ok(recovery)
+    }
+
+    /// The Ids of a node's inputs and outputs in the order the node added them.
+    async fn node_ports(&self, node_id: &NodeId) -> NodePorts {
+        NodePorts {
+            audio_inputs: self
+                .inner
+                .audio_inputs
+                .lock()
+                .await
+                .get(node_id)
+                .cloned()
+                .unwrap_or_default(),
+            video_inputs: self
+                .inner
+                .video_inputs
+                .lock()
+                .await
+                .get(node_id)
+                .cloned()
+                .unwrap_or_default(),
+            audio_outputs: self
+                .inner
+                .audio_outputs
+                .lock()
+                .await
+                .get(node_id)
+                .cloned()
+                .unwrap_or_default(),
+            video_outputs: self
+                .inner
+                .video_outputs
+                .lock()
+                .await
+                .get(node_id)
+                .cloned()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The connections whose nodes are in different graphs, these are not part of either graph's
+    /// topology.
+    async fn cross_graph_connections(&self) -> Vec<CreateConnection> {
+        let node_graphs: HashMap<NodeId, GraphId> = self
+            .inner
+            .graphs
+            .lock()
+            .await
+            .iter()
+            .flat_map(|(graph_id, node_ids)| {
+                node_ids
+                    .iter()
+                    .map(move |node_id| (node_id.clone(), graph_id.clone()))
+            })
+            .collect();
+        let all_nodes: Vec<NodeId> = node_graphs.keys().cloned().collect();
+
+        let mut connections = topology_connections(
+            TopologyConnectionType::Video,
+            &all_nodes,
+            &*self.inner.video_connections.lock().await,
+            &*self.inner.video_inputs.lock().await,
+            &*self.inner.video_outputs.lock().await,
+        );
+        connections.extend(topology_connections(
+            TopologyConnectionType::Audio,
+            &all_nodes,
+            &*self.inner.audio_connections.lock().await,
+            &*self.inner.audio_inputs.lock().await,
+            &*self.inner.audio_outputs.lock().await,
+        ));
+
+        connections
+            .into_iter()
+            .filter(|connection| {
+                let graph_of =
+                    |node_id: &str| node_graphs.get(&NodeId::new_from(node_id.to_string()));
+                graph_of(&connection.from_node_id) != graph_of(&connection.to_node_id)
+            })
+            .map(|connection| CreateConnection {
+                connection_type: match connection.connection_type {
+                    TopologyConnectionType::Video => CreateConnectionType::Video,
+                    TopologyConnectionType::Audio => CreateConnectionType::Audio,
+                },
+                from_node_id: connection.from_node_id,
+                from_output_index: connection.from_output_index,
+                to_node_id: connection.to_node_id,
+                to_input_index: connection.to_input_index,
+            })
+            .collect()
+    }
+
+    pub async fn compute_health(&self) -> ComputeHealth {
+        let device = match self.context.device_info() {
+            Ok(device) => Some(device),
+            Err(err) => {
+                debug!("Could not query the compute device: {err}");
+                None
+            }
+        };
+        ComputeHealth {
+            device,
+            buffer_pool: self.context.buffer_pool_usage(),
+            ..self.inner.compute_health.lock().await.clone()
+        }
+    }
+
+    pub fn compute_stats(&self) -> ComputeStatsReport {
+        self.context.compute_stats()
+    }
+
+    pub fn set_compute_profiling(&self, enabled: bool) -> Result<(), ComputeError> {
+        self.context.set_profiling(enabled)
+    }
+
+    /// Number of node instances of each node type, across all graphs.
+    pub async fn node_type_usage(&self) -> BTreeMap<String, usize> {
+        let mut usage: BTreeMap<String, usize> = BTreeMap::new();
+        for node in self.inner.nodes.lock().await.values() {
+            *usage.entry(node.node_type.clone()).or_default() += 1;
+        }
+
+        usage
+    }
+
+    pub async fn get_node_event_channel(
+        &self,
+    ) -> tokio::sync::mpsc::UnboundedSender<NodeStateEvent> {
+        self.inner.node_event_tx.clone()
+    }
+
+    /// Renames a node, subscribers to the state are sent the new name.
+    pub async fn set_node_name(
+        &self,
+        graph_id: &GraphId,
+        node_id: &NodeId,
+        name: Option<String>,
+    ) -> Result<(), NodeStateError> {
+        self.check_node_in_graph(graph_id, node_id).await?;
+        {
+            let mut nodes = self.inner.nodes.lock().await;
+            let node = nodes
+                .get_mut(node_id)
+                .ok_or_else(|| NodeStateError::NodeDoesNotExist(node_id.clone()))?;
+            node.name = name;
+        }
+
+        self.inner.state_event_tx.send(()).ok();
+
+        Ok(())
+    }
+
+    pub async fn set_node_state(&self, graph_id: &GraphId, node_id: &NodeId, state: String) {
+        debug!("Setting node {} state to {}", node_id, state);
+        let nodes = self.inner.nodes.lock().await;
+        let node = nodes.get(node_id).unwrap();
+        node.context.set_state(state).await;
+    }
+
+    /// Sets the state of several nodes in a graph together, e.g. when recalling a scene.
+    ///
+    /// Nothing is staged unless every node exists in the graph. The states are staged while holding
+    /// all of the nodes' pending states, so each node picks up its new state on its next frame and no
+    /// other state update can interleave with the batch.
+    pub async fn set_node_states(
+        &self,
+        graph_id: &GraphId,
+        states: HashMap<NodeId, String>,
+    ) -> Result<(), NodeStateError> {
+        let graphs = self.inner.graphs.lock().await;
+        let graph_nodes = graphs
+            .get(graph_id)
+            .ok_or_else(|| NodeStateError::GraphDoesNotExist(graph_id.clone()))?;
+        if let Some(node_id) = states.keys().find(|node_id| !graph_nodes.contains(node_id)) {
+            return Err(NodeStateError::NodeDoesNotExist(node_id.clone()));
+        }
+
+        debug!(
+            "Setting state of {} nodes in graph {graph_id}",
+            states.len()
+        );
+        let pending_states = {
+            let nodes = self.inner.nodes.lock().await;
+            states
+                .into_iter()
+                .map(|(node_id, state)| match nodes.get(&node_id) {
+                    Some(node) => Ok((node.context.get_pending_state_channel(), state)),
+                    None => Err(NodeStateError::NodeDoesNotExist(node_id)),
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        // The graphs lock is held throughout, so batches can't deadlock on each other's pending states
+        let mut staged = Vec::with_capacity(pending_states.len());
+        for (pending_state, state) in pending_states.iter() {
+            staged.push((pending_state.lock().await, state));
+        }
+        for (pending_state, state) in staged.iter_mut() {
+            pending_state.replace(state.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a node exists and belongs to the graph.
+    pub async fn check_node_in_graph(
+        &self,
+        graph_id: &GraphId,
+        node_id: &NodeId,
+    ) -> Result<(), NodeStateError> {
+        let graphs = self.inner.graphs.lock().await;
+        let graph_nodes = graphs
+            .get(graph_id)
+            .ok_or_else(|| NodeStateError::GraphDoesNotExist(graph_id.clone()))?;
+        if !graph_nodes.contains(node_id) {
+            return Err(NodeStateError::NodeDoesNotExist(node_id.clone()));
+        }
+
+        Ok(())
+    }
+
+    /// Processing metrics of a node in a graph.
+    pub async fn get_node_metrics(
+        &self,
+        graph_id: &GraphId,
+        node_id: &NodeId,
+    ) -> Result<NodeMetricsReport, NodeStateError> {
+        self.check_node_in_graph(graph_id, node_id).await?;
+        self.inner
+            .nodes
+            .lock()
+            .await
+            .get(node_id)
+            .map(|node| node.context.metrics())
+            .ok_or_else(|| NodeStateError::NodeDoesNotExist(node_id.clone()))
+    }
+
+    /// Processing metrics of every node, by node Id.
+    pub async fn all_node_metrics(&self) -> BTreeMap<String, NodeMetricsReport> {
+        self.inner
+            .nodes
+            .lock()
+            .await
+            .iter()
+            .map(|(node_id, node)| (node_id.to_string(), node.context.metrics()))
+            .collect()
+    }
+
+    /// Format of the most recent frame a node pushed to one of its video outputs,
+    /// `None` if the output has not produced a frame yet.
+    pub async fn get_video_output_format(
+        &self,
+        graph_id: &GraphId,
+        node_id: &NodeId,
+        output_id: &VideoOutputId,
+    ) -> Result<Option<VideoOutputFormat>, OutputError> {
+        self.check_node_in_graph(graph_id, node_id)
+            .await
+            .map_err(|err| match err {
+                NodeStateError::GraphDoesNotExist(graph_id) => {
+                    OutputError::GraphDoesNotExist(graph_id)
+                }
+                NodeStateError::NodeDoesNotExist(node_id) => OutputError::NodeDoesNotExist(node_id),
+            })?;
+        let node_context = self
+            .get_node_context(Some(node_id))
+            .await
+            .ok_or_else(|| OutputError::NodeDoesNotExist(node_id.clone()))?;
+
+        node_context
+            .get_video_output_format(output_id)
+            .await
+            .ok_or_else(|| OutputError::OutputDoesNotExist(output_id.to_string()))
+    }
+
+    /// Captures the next frame a node pushes to one of its video outputs and encodes it as a still image.
+    /// The node is run for the capture if nothing else is connected to the output.
+    pub async fn snapshot_video_output(
+        &self,
+        graph_id: &GraphId,
+        node_id: &NodeId,
+        output_id: &VideoOutputId,
+        format: SnapshotFormat,
+    ) -> Result<Vec<u8>, SnapshotError> {
+        self.check_node_in_graph(graph_id, node_id)
+            .await
+            .map_err(|err| match err {
+                NodeStateError::GraphDoesNotExist(graph_id) => {
+                    SnapshotError::Output(OutputError::GraphDoesNotExist(graph_id))
+                }
+                NodeStateError::NodeDoesNotExist(node_id) => {
+                    SnapshotError::Output(OutputError::NodeDoesNotExist(node_id))
+                }
+            })?;
+        let node_context = self
+            .get_node_context(Some(node_id))
+            .await
+            .ok_or_else(|| SnapshotError::Output(OutputError::NodeDoesNotExist(node_id.clone())))?;
+        if !node_context
+            .get_available_video_outputs()
+            .await
+            .contains(output_id)
+        {
+            return Err(SnapshotError::Output(OutputError::OutputDoesNotExist(
+                output_id.to_string(),
+            )));
+        }
+
+        // The pipe is dropped once the frame is captured, which disconnects it from the output
+        let mut video_pipe = node_context.get_video_pipe(output_id).await;
+        let (frame, semaphore) = tokio::time::timeout(SNAPSHOT_TIMEOUT, video_pipe.next_frame())
+            .await
+            .ok()
+            .flatten()
+            .ok_or(SnapshotError::NoFrame)?;
+        let pixels = download_frame(&self.context, &frame);
+        semaphore.signal().await;
+        let pixels = pixels.map_err(SnapshotError::DownloadFailed)?;
+
+        Ok(encode_snapshot(
+            format,
+            frame.width(),
+            frame.height(),
+            &pixels,
+        ))
+    }
+
+    pub async fn get_node_state(&self, graph_id: &GraphId, node_id: &NodeId) -> Option<String> {
+        self.inner.node_states.lock().await.get(node_id).cloned()
+    }
+
+    /// Starts ramping a numeric parameter of a node's state towards a new value, one step per frame
+    /// of the node's graph.
+    /// Any running automation of the same parameter is replaced, continuing from its current value.
+    pub async fn add_node_automation(
+        &self,
+        graph_id: &GraphId,
+        node_id: &NodeId,
+        request: CreateAutomation,
+    ) -> Result<AutomationId, AutomationError> {
+        self.check_node_in_graph(graph_id, node_id).await?;
+        let clock = self.graph_clock(graph_id).await;
+
+        let mut automations = self.inner.automations.lock().await;
+        let node_automations = automations.entry(node_id.clone()).or_default();
+        let running = node_automations
+            .iter()
+            .position(|automation| automation.param == request.param);
+        let from = match running {
+            Some(index) => node_automations.remove(index).value_at(clock.frame()),
+            None => {
+                let node_state = self.inner.node_states.lock().await.get(node_id).cloned();
+                node_state
+                    .and_then(|state| serde_json::from_str::<serde_json::Value>(&state).ok())
+                    .and_then(|state| state.get(&request.param).and_then(|val| val.as_f64()))
+                    .ok_or_else(|| AutomationError::ParameterIsNotNumeric(request.param.clone()))?
+            }
+        };
+
+        let automation_id = AutomationId::default();
+        node_automations.push(Automation::new(
+            automation_id.clone(),
+            from,
+            request,
+            &clock,
+        ));
+
+        // Each graph with running automations has a task stepping them on its clock
+        if self
+            .inner
+            .automated_graphs
+            .lock()
+            .await
+            .insert(graph_id.clone())
+        {
+            tokio::spawn(run_graph_automations(self.clone(), graph_id.clone(), clock));
+        }
+
+        Ok(automation_id)
+    }
+
+    /// Cancels automations for a node, leaving parameters at their current values.
+    /// If no automation Id is given then all automations for the node are cancelled, otherwise it is
+    /// an error if the node has no automation with the Id.
+    pub async fn cancel_node_automations(
+        &self,
+        graph_id: &GraphId,
+        node_id: &NodeId,
+        automation_id: Option<&AutomationId>,
+    ) -> Result<(), AutomationError> {
+        self.check_node_in_graph(graph_id, node_id).await?;
+
+        let mut automations = self.inner.automations.lock().await;
+        let node_automations = automations.entry(node_id.clone()).or_default();
+        match automation_id {
+            Some(automation_id) => {
+                let index = node_automations
+                    .iter()
+                    .position(|automation| &automation.id == automation_id)
+                    .ok_or_else(|| {
+                        AutomationError::AutomationDoesNotExist(automation_id.clone())
+                    })?;
+                node_automations.remove(index);
+            }
+            None => node_automations.clear(),
+        }
+
+        Ok(())
+    }
+
+    /// Removes a node from a graph, disconnecting it from any nodes it is connected to
+    /// and stopping it from processing further frames.
+    /// Inputs of other nodes that were connected to this node will receive black / silence,
+    /// anything else subscribed to the node's outputs sees the outputs end.
+    /// The plugin that provided the node is then asked to destroy it.
+    pub async fn remove_node(
+        &self,
+        plugin_manager: &PluginManager,
+        graph_id: &GraphId,
+        node_id: &NodeId,
+    ) -> Result<(), NodeStateError> {
+        self.check_node_in_graph(graph_id, node_id).await?;
+        let (node_context, node_type) = match self.inner.nodes.lock().await.get(node_id) {
+            Some(node) => (node.context.clone(), node.node_type.clone()),
+            None => return Err(NodeStateError::NodeDoesNotExist(node_id.clone())),
+        };
+
+        // Disconnect any downstream nodes first so that they are not waiting on frames from this node.
+        let video_outputs = self
+            .inner
+            .video_outputs
+            .lock()
+            .await
+            .remove(node_id)
+            .unwrap_or_default();
+        let downstream_video_inputs: Vec<VideoInputId> = {
+            let mut connections = self.inner.video_connections.lock().await;
+            let inputs: Vec<VideoInputId> = connections
+                .iter()
+                .filter(|(_, output)| video_outputs.contains(output))
+                .map(|(input, _)| input.clone())
+                .collect();
+            for input in inputs.iter() {
+                connections.remove(input);
+            }
+            inputs
+        };
+        for video_input in downstream_video_inputs.iter() {
+            let owner = self
+                .inner
+                .video_inputs
+                .lock()
+                .await
+                .iter()
+                .find(|(_, inputs)| inputs.contains(video_input))
+                .map(|(owner, _)| owner.clone());
+            if let Some(context) = self.get_node_context(owner.as_ref()).await {
+                context.disconnect_video_pipe(video_input).await;
+            }
+        }
+
+        let audio_outputs = self
+            .inner
+            .audio_outputs
+            .lock()
+            .await
+            .remove(node_id)
+            .unwrap_or_default();
+        let downstream_audio_inputs: Vec<AudioInputId> = {
+            let mut connections = self.inner.audio_connections.lock().await;
+            let inputs: Vec<AudioInputId> = connections
+                .iter()
+                .filter(|(_, output)| audio_outputs.contains(output))
+                .map(|(input, _)| input.clone())
+                .collect();
+            for input in inputs.iter() {
+                connections.remove(input);
+            }
+            inputs
+        };
+        for audio_input in downstream_audio_inputs.iter() {
+            let owner = self
+                .inner
+                .audio_inputs
+                .lock()
+                .await
+                .iter()
+                .find(|(_, inputs)| inputs.contains(audio_input))
+                .map(|(owner, _)| owner.clone());
+            if let Some(context) = self.get_node_context(owner.as_ref()).await {
+                context.disconnect_audio_pipe(audio_input).await;
+            }
+        }
+
+        // Then disconnect this node from anything upstream.
+        let video_inputs = self
+            .inner
+            .video_inputs
+            .lock()
+            .await
+            .remove(node_id)
+            .unwrap_or_default();
+        for video_input in video_inputs.iter() {
+            self.inner
+                .video_connections
+                .lock()
+                .await
+                .remove(video_input);
+            node_context.disconnect_video_pipe(video_input).await;
+        }
+
+        let audio_inputs = self
+            .inner
+            .audio_inputs
+            .lock()
+            .await
+            .remove(node_id)
+            .unwrap_or_default();
+        for audio_input in audio_inputs.iter() {
+            self.inner
+                .audio_connections
+                .lock()
+                .await
+                .remove(audio_input);
+            node_context.disconnect_audio_pipe(audio_input).await;
+        }
+
+        node_context.stop();
+        if let Some(task) = self.inner.node_tasks.lock().await.remove(node_id) {
+            task.abort();
+        }
+        node_context.close_outputs().await;
+        if let Some(stall_monitor) = self.inner.graph_stall_monitors.lock().await.get(graph_id) {
+            stall_monitor.clear_node(node_id);
+        }
+
+        self.inner.nodes.lock().await.remove(node_id);
+        self.inner.node_states.lock().await.remove(node_id);
+        self.inner.automations.lock().await.remove(node_id);
+        self.context.remove_node_stats(node_id);
+        if let Some(graph_nodes) = self.inner.graphs.lock().await.get_mut(graph_id) {
+            graph_nodes.retain(|graph_node_id| graph_node_id != node_id);
+        }
+
+        // The node is already gone from the graph, so a plugin that fails to clean up only leaks
+        if let Err(err) = plugin_manager.destroy_node(node_id.to_string(), &node_type) {
+            warn!("Failed to destroy node {node_id}: {err}");
+        }
+
+        self.inner.state_event_tx.send(()).ok();
+
+        Ok(())
+    }
+
+    /// Moves the connections of a node's inputs so that the connection on `order[i]` ends up on input `i`.
+    /// Connected pipes are moved rather than reconnected, so upstream nodes are unaffected.
+    pub async fn reorder_node_inputs(
+        &self,
+        graph_id: &GraphId,
+        node_id: &NodeId,
+        video_order: Option<Vec<VideoInputId>>,
+        audio_order: Option<Vec<AudioInputId>>,
+    ) -> Result<(), InputError> {
+        let node_context = self
+            .get_node_context(Some(node_id))
+            .await
+            .ok_or_else(|| InputError::NodeDoesNotExist(node_id.clone()))?;
+
+        if let Some(order) = &video_order {
+            let video_inputs = self.get_available_video_inputs(graph_id, node_id).await;
+            if !is_permutation(&video_inputs, order) {
+                return Err(InputError::InvalidOrder);
+            }
+        }
+        if let Some(order) = &audio_order {
+            let audio_inputs = self.get_available_audio_inputs(graph_id, node_id).await;
+            if !is_permutation(&audio_inputs, order) {
+                return Err(InputError::InvalidOrder);
+            }
+        }
+
+        if let Some(order) = video_order {
+            let connected = node_context.reorder_video_inputs(&order).await;
+            let mut connections = self.inner.video_connections.lock().await;
+            for video_input in order.iter() {
+                match connected.get(video_input) {
+                    Some(video_output) => {
+                        connections.insert(video_input.clone(), video_output.clone())
+                    }
+                    None => connections.remove(video_input),
+                };
+            }
+        }
+        if let Some(order) = audio_order {
+            let connected = node_context.reorder_audio_inputs(&order).await;
+            let mut connections = self.inner.audio_connections.lock().await;
+            for audio_input in order.iter() {
+                match connected.get(audio_input) {
+                    Some(audio_output) => {
+                        connections.insert(audio_input.clone(), audio_output.clone())
+                    }
+                    None => connections.remove(audio_input),
+                };
+            }
+        }
+
+        self.inner.state_event_tx.send(()).ok();
+
+        Ok(())
+    }
+
+    /// Sets whether one of a node's inputs is muted or soloed, flags that are `None` are left unchanged.
+    pub async fn set_node_input_monitoring(
+        &self,
+        graph_id: &GraphId,
+        node_id: &NodeId,
+        input_id: &str,
+        muted: Option<bool>,
+        soloed: Option<bool>,
+    ) -> Result<(), InputError> {
+        let node_context = self
+            .get_node_context(Some(node_id))
+            .await
+            .ok_or_else(|| InputError::NodeDoesNotExist(node_id.clone()))?;
+
+        let video_input = VideoInputId::new_from(input_id.into());
+        let audio_input = AudioInputId::new_from(input_id.into());
+        let is_video_input = self
+            .get_available_video_inputs(graph_id, node_id)
+            .await
+            .contains(&video_input);
+        let is_audio_input = self
+            .get_available_audio_inputs(graph_id, node_id)
+            .await
+            .contains(&audio_input);
+
+        let mut input_monitor = node_context.get_input_monitor().await;
+        if is_video_input {
+            set_flag(
+                &mut input_monitor.muted_video_inputs,
+                video_input.clone(),
+                muted,
+            );
+            set_flag(&mut input_monitor.soloed_video_inputs, video_input, soloed);
+        } else if is_audio_input {
+            set_flag(
+                &mut input_monitor.muted_audio_inputs,
+                audio_input.clone(),
+                muted,
+            );
+            set_flag(&mut input_monitor.soloed_audio_inputs, audio_input, soloed);
+        } else {
+            return Err(InputError::InputDoesNotExist(input_id.to_string()));
+        }
+        node_context.set_input_monitor(input_monitor).await;
+
+        self.inner.state_event_tx.send(()).ok();
+
+        Ok(())
+    }
+
+    /// Disconnects an input of a node from the output it is connected to, the node receives black
+    /// or silence on the input from its next frame. Returns the Id of the output the input was
+    /// connected to, `None` if it was not connected.
+    pub async fn remove_connection(
+        &self,
+        graph_id: &GraphId,
+        node_id: &NodeId,
+        input_id: &str,
+    ) -> Result<Option<String>, InputError> {
+        let node_context = self
+            .get_node_context(Some(node_id))
+            .await
+            .ok_or_else(|| InputError::NodeDoesNotExist(node_id.clone()))?;
+
+        let video_input = VideoInputId::new_from(input_id.into());
+        let audio_input = AudioInputId::new_from(input_id.into());
+        let connected_output_id = if self
+            .get_available_video_inputs(graph_id, node_id)
+            .await
+            .contains(&video_input)
+        {
+            self.inner
+                .video_connections
+                .lock()
+                .await
+                .remove(&video_input);
+            node_context
+                .disconnect_video_pipe(&video_input)
+                .await
+                .map(|output_id| output_id.to_string())
+        } else if self
+            .get_available_audio_inputs(graph_id, node_id)
+            .await
+            .contains(&audio_input)
+        {
+            self.inner
+                .audio_connections
+                .lock()
+                .await
+                .remove(&audio_input);
+            node_context
+                .disconnect_audio_pipe(&audio_input)
+                .await
+                .map(|output_id| output_id.to_string())
+        } else {
+            return Err(InputError::InputDoesNotExist(input_id.to_string()));
+        };
+
+        self.inner.state_event_tx.send(()).ok();
+
+        Ok(connected_output_id)
+    }
+
+    async fn get_node_context(&self, node_id: Option<&NodeId>) -> Option<NodeRunContext> {
+        let node_id = node_id?;
+        self.inner
+            .nodes
+            .lock()
+            .await
+            .get(node_id)
+            .map(|node| node.context.clone())
+    }
+
+    pub async fn get_available_audio_inputs(
+        &self,
+        graph_id: &GraphId,
+        node_id: &NodeId,
+    ) -> Vec<AudioInputId> {
+        self.inner
+            .audio_inputs
+            .lock()
+            .await
+            .get(node_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub async fn get_available_video_inputs(
+        &self,
+        graph_id: &GraphId,
+        node_id: &NodeId,
+    ) -> Vec<VideoInputId> {
+        self.inner
+            .video_inputs
+            .lock()
+            .await
+            .get(node_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub async fn subscribe(&self) -> tokio::sync::broadcast::Receiver<PhaneronStateRepresentation> {
+        let (sender, receiver) = tokio::sync::broadcast::channel(1); // Only the latest value is relevant
+
+        {
+            let state = self.get_state().await;
+            sender.send(state).unwrap();
+        }
+        self.inner.subscribers_to_state.lock().await.push(sender);
+
+        receiver
+    }
+
+    async fn get_state(&self) -> PhaneronStateRepresentation {
+        let mut nodes = HashMap::new();
+
+        let inner_node_states = self.inner.node_states.lock().await.clone();
+        for (node_id, node) in self.inner.nodes.lock().await.iter() {
+            let node_state = inner_node_states.get(node_id);
+            let input_monitor = node.context.get_input_monitor().await;
+            let muted_inputs = input_monitor
+                .muted_video_inputs
+                .iter()
+                .map(|input| input.to_string())
+                .chain(
+                    input_monitor
+                        .muted_audio_inputs
+                        .iter()
+                        .map(|input| input.to_string()),
+                )
+                .collect();
+            let soloed_inputs = input_monitor
+                .soloed_video_inputs
+                .iter()
+                .map(|input| input.to_string())
+                .chain(
+                    input_monitor
+                        .soloed_audio_inputs
+                        .iter()
+                        .map(|input| input.to_string()),
+                )
+                .collect();
+            nodes.insert(
+                node_id.to_string(),
+                PhaneronNodeRepresentation {
+                    name: node.name.clone(),
+                    state: node_state.cloned(),
+                    muted_inputs,
+                    soloed_inputs,
+                },
+            );
+        }
+
+        let graphs = represent_ids(&*self.inner.graphs.lock().await);
+        let video_outputs = represent_ids(&*self.inner.video_outputs.lock().await);
+        let video_inputs = represent_ids(&*self.inner.video_inputs.lock().await);
+        let audio_outputs = represent_ids(&*self.inner.audio_outputs.lock().await);
+        let audio_inputs = represent_ids(&*self.inner.audio_inputs.lock().await);
+        let connections = represent_connections(&*self.inner.video_connections.lock().await);
+        let audio_connections = represent_connections(&*self.inner.audio_connections.lock().await);
+
+        PhaneronStateRepresentation {
+            nodes,
+            graphs,
+            video_outputs,
+            video_inputs,
+            audio_outputs,
+            audio_inputs,
+            connections,
+            audio_connections,
+        }
+    }
+}
+
+/// Resolves the connections between nodes in `graph_nodes` to the indices of the ports they connect.
+fn topology_connections<I: Eq + Hash, O: Eq>(
+    connection_type: TopologyConnectionType,
+    graph_nodes: &[NodeId],
+    connections: &HashMap<I, O>,
+    inputs: &HashMap<NodeId, Vec<I>>,
+    outputs: &HashMap<NodeId, Vec<O>>,
+) -> Vec<TopologyConnection> {
+    connections
+        .iter()
+        .filter_map(|(input, output)| {
+            let (to_node_id, to_input_index) = find_port(graph_nodes, inputs, input)?;
+            let (from_node_id, from_output_index) = find_port(graph_nodes, outputs, output)?;
+            Some(TopologyConnection {
+                connection_type,
+                from_node_id: from_node_id.to_string(),
+                from_output_index,
+                to_node_id: to_node_id.to_string(),
+                to_input_index,
+            })
+        })
+        .collect()
+}
+
+/// The (upstream, downstream) node pairs joined by `connections`.
+fn connection_edges<I: Eq + Hash, O: Eq + Hash>(
+    connections: &HashMap<I, O>,
+    inputs: &HashMap<NodeId, Vec<I>>,
+    outputs: &HashMap<NodeId, Vec<O>>,
+) -> Vec<(NodeId, NodeId)> {
+    let input_owners: HashMap<&I, &NodeId> = inputs
+        .iter()
+        .flat_map(|(node_id, inputs)| inputs.iter().map(move |input| (input, node_id)))
+        .collect();
+    let output_owners: HashMap<&O, &NodeId> = outputs
+        .iter()
+        .flat_map(|(node_id, outputs)| outputs.iter().map(move |output| (output, node_id)))
+        .collect();
+
+    connections
+        .iter()
+        .filter_map(|(input, output)| {
+            let from = output_owners.get(output)?;
+            let to = input_owners.get(input)?;
+            Some(((*from).clone(), (*to).clone()))
+        })
+        .collect()
+}
+
+fn find_port<'a, T: Eq>(
+    graph_nodes: &'a [NodeId],
+    ports: &HashMap<NodeId, Vec<T>>,
+    id: &T,
+) -> Option<(&'a NodeId, usize)> {
+    graph_nodes.iter().find_map(|node_id| {
+        ports
+            .get(node_id)
+            .and_then(|ports| ports.iter().position(|port| port == id))
+            .map(|index| (node_id, index))
+    })
+}
+
+fn represent_ids<K: ToString, T: ToString>(
+    ids: &HashMap<K, Vec<T>>,
+) -> HashMap<String, Vec<String>> {
+    ids.iter()
+        .map(|(key, ids)| {
+            (
+                key.to_string(),
+                ids.iter().map(|id| id.to_string()).collect(),
+            )
+        })
+        .collect()
+}
+
+fn represent_connections<I: ToString, O: ToString>(
+    connections: &HashMap<I, O>,
+) -> HashMap<String, String> {
+    connections
+        .iter()
+        .map(|(input, output)| (input.to_string(), output.to_string()))
+        .collect()
+}
+
+struct PhaneronStateInner {
+    graphs: Mutex<HashMap<GraphId, Vec<NodeId>>>,
+    graph_pause_gates: Mutex<HashMap<GraphId, PauseGate>>,
+    graph_frame_leads: Mutex<HashMap<GraphId, FrameLeadLimit>>,
+    graph_stall_monitors: Mutex<HashMap<GraphId, StallMonitor>>,
+    graph_panic_slates: Mutex<HashMap<GraphId, PanicSlate>>,
+    graph_frame_formats: Mutex<HashMap<GraphId, FrameFormat>>,
+    graph_clocks: Mutex<HashMap<GraphId, GraphClock>>,
+    compute_health: Mutex<ComputeHealth>,
+    nodes: Mutex<HashMap<NodeId, PhaneronStateNode>>,
+    /// The `run_node` task of each node.
+    node_tasks: Mutex<HashMap<NodeId, tokio::task::JoinHandle<()>>>,
+    node_states: Mutex<HashMap<NodeId, String>>,
+    audio_inputs: Mutex<HashMap<NodeId, Vec<AudioInputId>>>,
+    audio_outputs: Mutex<HashMap<NodeId, Vec<AudioOutputId>>>,
+    video_inputs: Mutex<HashMap<NodeId, Vec<VideoInputId>>>,
+    video_outputs: Mutex<HashMap<NodeId, Vec<VideoOutputId>>>,
+    video_connections: Mutex<HashMap<VideoInputId, VideoOutputId>>,
+    audio_connections: Mutex<HashMap<AudioInputId, AudioOutputId>>,
+    automations: Mutex<HashMap<NodeId, Vec<Automation>>>,
+    /// Graphs that have a task running their automations.
+    automated_graphs: Mutex<HashSet<GraphId>>,
+    subscribers_to_state: Mutex<Vec<tokio::sync::broadcast::Sender<PhaneronStateRepresentation>>>,
+    node_event_tx: tokio::sync::mpsc::UnboundedSender<NodeStateEvent>,
+    state_event_tx: tokio::sync::broadcast::Sender<()>,
+    node_initialize_timeout: Duration,
+    graphs_config: GraphsConfig,
+}
+
+impl PhaneronStateInner {
+    fn new(
+        node_event_tx: tokio::sync::mpsc::UnboundedSender<NodeStateEvent>,
+        state_event_tx: tokio::sync::broadcast::Sender<()>,
+        node_initialize_timeout: Duration,
+        graphs_config: GraphsConfig,
+    ) -> Self {
+        Self {
+            graphs: Default::default(),
+            graph_pause_gates: Default::default(),
+            graph_frame_leads: Default::default(),
+            graph_stall_monitors: Default::default(),
+            graph_panic_slates: Default::default(),
+            graph_frame_formats: Default::default(),
+            graph_clocks: Default::default(),
+            compute_health: Default::default(),
+            nodes: Default::default(),
+            node_tasks: Default::default(),
+            node_states: Default::default(),
+            audio_inputs: Default::default(),
+            audio_outputs: Default::default(),
+            video_inputs: Default::default(),
+            video_outputs: Default::default(),
+            video_connections: Default::default(),
+            audio_connections: Default::default(),
+            automations: Default::default(),
+            automated_graphs: Default::default(),
+            subscribers_to_state: Default::default(),
+            node_event_tx,
+            state_event_tx,
+            node_initialize_timeout,
+            graphs_config,
+        }
+    }
+}
+
+fn set_flag<T: Eq + Hash>(set: &mut HashSet<T>, item: T, flag: Option<bool>) {
+    match flag {
+        Some(true) => {
+            set.insert(item);
+        }
+        Some(false) => {
+            set.remove(&item);
+        }
+        None => {}
+    }
+}
+
+fn is_permutation<T: Eq + Hash>(items: &[T], order: &[T]) -> bool {
+    let unique: HashSet<&T> = order.iter().collect();
+    unique.len() == items.len()
+        && order.len() == items.len()
+        && items.iter().all(|item| unique.contains(item))
+}
+
+struct PhaneronStateNode {
+    name: Option<String>,
+    node_type: String,
+    /// Kept so that the node can be recreated, e.g. when the compute context is recovered.
+    configuration: Option<String>,
+    priority: ComputePriority,
+    context: NodeRunContext,
+}
 
 async fn handle_state(
     mut state_event_rx: tokio::sync::broadcast::Receiver<()>,
@@ -514,9 +2283,73 @@ async fn handle_state(
 
 async fn notify_state(state: PhaneronState) {
     let state_representation = state.get_state().await;
-    let subscribers_to_state = state.inner.subscribers_to_state.lock().await;
-    for sender in subscribers_to_state.iter() {
-        sender.send(state_representation.clone()).unwrap(); // TODO: Remove dropped senders
+    let mut subscribers_to_state = state.inner.subscribers_to_state.lock().await;
+    // Subscribers that have gone away, such as closed node event sockets, are removed
+    subscribers_to_state.retain(|sender| sender.send(state_representation.clone()).is_ok());
+}
+
+/// Applies the automations of a graph's nodes to their state once per frame of the graph, until
+/// none are left running.
+async fn run_graph_automations(state: PhaneronState, graph_id: GraphId, clock: GraphClock) {
+    loop {
+        let frame = clock.next_frame().await;
+
+        let node_ids = state
+            .inner
+            .graphs
+            .lock()
+            .await
+            .get(&graph_id)
+            .cloned()
+            .unwrap_or_default();
+        let mut updates: Vec<(NodeId, Vec<(String, f64)>)> = vec![];
+        let finished;
+        {
+            let mut automations = state.inner.automations.lock().await;
+            for node_id in node_ids.iter() {
+                let Some(node_automations) = automations.get_mut(node_id) else {
+                    continue;
+                };
+
+                let values = node_automations
+                    .iter()
+                    .map(|automation| (automation.param.clone(), automation.value_at(frame)))
+                    .collect();
+                node_automations.retain(|automation| !automation.is_finished_at(frame));
+                updates.push((node_id.clone(), values));
+            }
+            automations.retain(|_, node_automations| !node_automations.is_empty());
+
+            // Checked under the automations lock so that a newly added automation either sees this
+            // task running or starts a new one
+            finished = !node_ids
+                .iter()
+                .any(|node_id| automations.contains_key(node_id));
+            if finished {
+                state.inner.automated_graphs.lock().await.remove(&graph_id);
+            }
+        }
+
+        for (node_id, values) in updates {
+            let node_state = state.inner.node_states.lock().await.get(&node_id).cloned();
+            let mut node_state = node_state
+                .and_then(|node_state| serde_json::from_str::<serde_json::Value>(&node_state).ok())
+                .unwrap_or_else(|| serde_json::Value::Object(Default::default()));
+            if let Some(node_state) = node_state.as_object_mut() {
+                for (param, value) in values {
+                    node_state.insert(param, serde_json::json!(value));
+                }
+            }
+
+            let nodes = state.inner.nodes.lock().await;
+            if let Some(node) = nodes.get(&node_id) {
+                node.context.set_state(node_state.to_string()).await;
+            }
+        }
+
+        if finished {
+            return;
+        }
     }
 }
 
@@ -526,23 +2359,11 @@ async fn handle_node_events(
     state_event_tx: tokio::sync::broadcast::Sender<()>,
 ) {
     while let Some(event) = node_event_rx.recv().await {
-        let state_modified = match event {
-            NodeStateEvent::StateChanged(node_id, new_state) => {
-                node_state_changed(state.clone(), node_id, new_state).await
-            }
-            NodeStateEvent::AudioInputAdded(node_id, audio_input_id) => {
-                audio_input_added(state.clone(), node_id, audio_input_id).await
-            }
-            NodeStateEvent::VideoInputAdded(node_id, video_input_id) => {
-                video_input_added(state.clone(), node_id, video_input_id).await
-            }
-            NodeStateEvent::AudioOutputAdded(node_id, audio_output_id) => {
-                audio_output_added(state.clone(), node_id, audio_output_id).await
-            }
-            NodeStateEvent::VideoOutputAdded(node_id, video_output_id) => {
-                video_output_added(state.clone(), node_id, video_output_id).await
-            }
-        };
+        let mut state_modified = node_state_event(state.clone(), event).await;
+        // Events that arrive together, such as a batch of node states, are broadcast as one change
+        while let Ok(event) = node_event_rx.try_recv() {
+            state_modified |= node_state_event(state.clone(), event).await;
+        }
 
         if state_modified {
             state_event_tx.send(()).ok();
@@ -552,6 +2373,58 @@ async fn handle_node_events(
     // The loop will exit if there are no senders left.
 }
 
+async fn node_state_event(state: PhaneronState, event: NodeStateEvent) -> bool {
+    match event {
+        NodeStateEvent::StateChanged(node_id, new_state) => {
+            node_state_changed(state, node_id, new_state).await
+        }
+        NodeStateEvent::StateReported(node_id, reported) => {
+            node_state_reported(state, node_id, reported).await
+        }
+        NodeStateEvent::AudioInputAdded(node_id, audio_input_id) => {
+            audio_input_added(state, node_id, audio_input_id).await
+        }
+        NodeStateEvent::VideoInputAdded(node_id, video_input_id) => {
+            video_input_added(state, node_id, video_input_id).await
+        }
+        NodeStateEvent::AudioOutputAdded(node_id, audio_output_id) => {
+            audio_output_added(state, node_id, audio_output_id).await
+        }
+        NodeStateEvent::VideoOutputAdded(node_id, video_output_id) => {
+            video_output_added(state, node_id, video_output_id).await
+        }
+        NodeStateEvent::AudioInputDisconnected(_, audio_input_id, audio_output_id) => {
+            // The input may have been connected to another output in the meantime
+            remove_connection_to(
+                &mut *state.inner.audio_connections.lock().await,
+                &audio_input_id,
+                &audio_output_id,
+            )
+        }
+        NodeStateEvent::VideoInputDisconnected(_, video_input_id, video_output_id) => {
+            remove_connection_to(
+                &mut *state.inner.video_connections.lock().await,
+                &video_input_id,
+                &video_output_id,
+            )
+        }
+    }
+}
+
+/// Removes the connection of `input` if it is connected to `output`.
+fn remove_connection_to<I: Eq + Hash, O: Eq>(
+    connections: &mut HashMap<I, O>,
+    input: &I,
+    output: &O,
+) -> bool {
+    if connections.get(input) != Some(output) {
+        return false;
+    }
+    connections.remove(input);
+
+    true
+}
+
 async fn node_state_changed(state: PhaneronState, node_id: NodeId, new_state: String) -> bool {
     let mut node_states = state.inner.node_states.lock().await;
     node_states.insert(node_id.clone(), new_state.clone());
@@ -559,6 +2432,30 @@ async fn node_state_changed(state: PhaneronState, node_id: NodeId, new_state: St
     true
 }
 
+/// Merges the fields reported by a node into its state, leaving the other fields as they were applied.
+async fn node_state_reported(state: PhaneronState, node_id: NodeId, reported: String) -> bool {
+    let reported = match serde_json::from_str::<serde_json::Value>(&reported) {
+        Ok(serde_json::Value::Object(reported)) => reported,
+        _ => {
+            warn!("Node {node_id} reported state that is not a JSON object: {reported}");
+            return false;
+        }
+    };
+
+    let mut node_states = state.inner.node_states.lock().await;
+    let mut node_state = node_states
+        .get(&node_id)
+        .and_then(|node_state| serde_json::from_str::<serde_json::Value>(node_state).ok())
+        .unwrap_or_else(|| serde_json::Value::Object(Default::default()));
+    let Some(fields) = node_state.as_object_mut() else {
+        return false;
+    };
+    fields.extend(reported);
+    node_states.insert(node_id, node_state.to_string());
+
+    true
+}
+
 async fn audio_input_added(
     state: PhaneronState,
     node_id: NodeId,
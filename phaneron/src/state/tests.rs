@@ -0,0 +1,152 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::time::Duration;
+
+use crate::{
+    automation::{AutomationError, AutomationId},
+    compute::{create_compute_context, device::ComputeDeviceSelection, fence::GpuSyncMode},
+    config::GraphsConfig,
+    graph::{FrameFormat, GraphSafety},
+    plugins::PluginManager,
+    GraphId, NodeId,
+};
+
+use super::{create_phaneron_state, CreateGraphError, CreateNode};
+
+fn unknown_node() -> CreateNode {
+    CreateNode {
+        node_id: "node".to_string(),
+        node_type: "unknown".to_string(),
+        node_name: None,
+        state: None,
+        configuration: None,
+        priority: Default::default(),
+        ports: Default::default(),
+    }
+}
+
+#[tokio::test]
+#[ignore = "Needs an OpenCL device"]
+async fn failed_graph_is_recreated_with_the_new_frame_format() {
+    let context =
+        create_compute_context(GpuSyncMode::default(), ComputeDeviceSelection::default(), 0)
+            .await
+            .unwrap();
+    let state = create_phaneron_state(context, Duration::from_secs(1), GraphsConfig::default());
+    let plugin_manager = PluginManager::default();
+    let graph_id = GraphId::new_from("graph".to_string());
+
+    // Every node fails, so the graph is rolled back before any node was added to it
+    let Err(CreateGraphError(report)) = state
+        .create_graph(
+            &plugin_manager,
+            &graph_id,
+            vec![unknown_node()],
+            vec![],
+            false,
+            GraphSafety::default(),
+            FrameFormat::default(),
+        )
+        .await
+    else {
+        panic!("Graph with a node of an unknown type was created");
+    };
+    assert!(report.rolled_back);
+    assert!(!state
+        .inner
+        .graph_frame_formats
+        .lock()
+        .await
+        .contains_key(&graph_id));
+    assert!(!state
+        .inner
+        .graph_clocks
+        .lock()
+        .await
+        .contains_key(&graph_id));
+
+    let frame_format = FrameFormat {
+        frame_rate_num: 30000,
+        frame_rate_den: 1001,
+        ..Default::default()
+    };
+    state
+        .create_graph(
+            &plugin_manager,
+            &graph_id,
+            vec![],
+            vec![],
+            false,
+            GraphSafety::default(),
+            frame_format,
+        )
+        .await
+        .unwrap();
+    assert_eq!(state.graph_frame_format(&graph_id).await, frame_format);
+    assert_eq!(
+        state.graph_clock(&graph_id).await.frame_format(),
+        frame_format
+    );
+}
+
+#[tokio::test]
+#[ignore = "Needs an OpenCL device"]
+async fn cancelling_automations_of_unknown_ids_is_an_error() {
+    let context =
+        create_compute_context(GpuSyncMode::default(), ComputeDeviceSelection::default(), 0)
+            .await
+            .unwrap();
+    let state = create_phaneron_state(context, Duration::from_secs(1), GraphsConfig::default());
+    let graph_id = GraphId::new_from("graph".to_string());
+    let node_id = NodeId::new_from("node".to_string());
+
+    let result = state
+        .cancel_node_automations(&graph_id, &node_id, None)
+        .await;
+    assert!(matches!(result, Err(AutomationError::GraphDoesNotExist(_))));
+
+    state
+        .inner
+        .graphs
+        .lock()
+        .await
+        .insert(graph_id.clone(), vec![]);
+    let result = state
+        .cancel_node_automations(&graph_id, &node_id, None)
+        .await;
+    assert!(matches!(result, Err(AutomationError::NodeDoesNotExist(_))));
+
+    state
+        .inner
+        .graphs
+        .lock()
+        .await
+        .insert(graph_id.clone(), vec![node_id.clone()]);
+    let automation_id = AutomationId::default();
+    let result = state
+        .cancel_node_automations(&graph_id, &node_id, Some(&automation_id))
+        .await;
+    assert!(
+        matches!(result, Err(AutomationError::AutomationDoesNotExist(id)) if id == automation_id)
+    );
+    assert!(state
+        .cancel_node_automations(&graph_id, &node_id, None)
+        .await
+        .is_ok());
+}
@@ -0,0 +1,268 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    compute::ComputePriority,
+    graph::NodeId,
+    state::{CreateConnection, CreateConnectionType, CreateNode},
+};
+
+#[cfg(test)]
+mod tests;
+
+/// The most times a single entry can be repeated, so that a parameter can't create an unbounded
+/// number of nodes.
+pub const MAX_REPEAT: u64 = 256;
+
+/// A parameterised `{ nodes, connections }` document that can be instantiated into a graph.
+///
+/// Any string in the template may contain `${name}` placeholders which are replaced by parameters.
+/// A string that consists of only a placeholder is replaced by the parameter value itself, so numbers
+/// and objects can be substituted. Entries with a `repeat` field are expanded that many times, within
+/// them `${index}` is the repetition index and `${name[index]}` picks an element from an array parameter.
+/// An entry can be repeated at most [`MAX_REPEAT`] times.
+///
+/// Node Ids in a template are local to the template and must be unique once repeated entries have
+/// been expanded, new node Ids are generated on instantiation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphTemplate {
+    pub nodes: Vec<Value>,
+    #[serde(default)]
+    pub connections: Vec<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TemplateNode {
+    node_id: String,
+    node_type: String,
+    #[serde(default)]
+    node_name: Option<String>,
+    #[serde(default)]
+    state: Option<Value>,
+    #[serde(default)]
+    configuration: Option<Value>,
+    #[serde(default)]
+    priority: ComputePriority,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TemplateConnectionType {
+    Video,
+    Audio,
+}
+
+#[derive(Debug, Deserialize)]
+struct TemplateConnection {
+    connection_type: TemplateConnectionType,
+    from_node_id: String,
+    from_output_index: usize,
+    to_node_id: String,
+    to_input_index: usize,
+}
+
+#[derive(Debug)]
+pub enum TemplateError {
+    TemplateDoesNotExist(String),
+    UnknownParameter(String),
+    InvalidRepeat(String),
+    RepeatTooLarge(u64),
+    InvalidEntry(String),
+    UnknownNode(String),
+    DuplicateNode(String),
+}
+
+pub struct InstantiatedTemplate {
+    pub nodes: Vec<CreateNode>,
+    pub connections: Vec<CreateConnection>,
+    /// Maps node Ids used in the template to the Ids of the created nodes.
+    pub node_ids: HashMap<String, NodeId>,
+}
+
+impl GraphTemplate {
+    pub fn instantiate(
+        &self,
+        parameters: &HashMap<String, Value>,
+    ) -> Result<InstantiatedTemplate, TemplateError> {
+        let mut nodes = vec![];
+        let mut node_ids: HashMap<String, NodeId> = HashMap::new();
+        for entry in expand_entries(&self.nodes, parameters)? {
+            let node: TemplateNode = serde_json::from_value(entry)
+                .map_err(|err| TemplateError::InvalidEntry(err.to_string()))?;
+            let node_id = NodeId::default();
+            if node_ids.contains_key(&node.node_id) {
+                return Err(TemplateError::DuplicateNode(node.node_id));
+            }
+            node_ids.insert(node.node_id, node_id.clone());
+            nodes.push(CreateNode {
+                node_id: node_id.to_string(),
+                node_type: node.node_type,
+                node_name: node.node_name,
+                state: node.state.map(|state| state.to_string()),
+                configuration: node
+                    .configuration
+                    .map(|configuration| configuration.to_string()),
+                priority: node.priority,
+                ports: Default::default(),
+            });
+        }
+
+        let mut connections = vec![];
+        for entry in expand_entries(&self.connections, parameters)? {
+            let connection: TemplateConnection = serde_json::from_value(entry)
+                .map_err(|err| TemplateError::InvalidEntry(err.to_string()))?;
+            let from_node_id = node_ids
+                .get(&connection.from_node_id)
+                .ok_or_else(|| TemplateError::UnknownNode(connection.from_node_id.clone()))?;
+            let to_node_id = node_ids
+                .get(&connection.to_node_id)
+                .ok_or_else(|| TemplateError::UnknownNode(connection.to_node_id.clone()))?;
+            connections.push(CreateConnection {
+                connection_type: match connection.connection_type {
+                    TemplateConnectionType::Video => CreateConnectionType::Video,
+                    TemplateConnectionType::Audio => CreateConnectionType::Audio,
+                },
+                from_node_id: from_node_id.to_string(),
+                from_output_index: connection.from_output_index,
+                to_node_id: to_node_id.to_string(),
+                to_input_index: connection.to_input_index,
+            });
+        }
+
+        Ok(InstantiatedTemplate {
+            nodes,
+            connections,
+            node_ids,
+        })
+    }
+}
+
+fn expand_entries(
+    entries: &[Value],
+    parameters: &HashMap<String, Value>,
+) -> Result<Vec<Value>, TemplateError> {
+    let mut expanded = vec![];
+    for entry in entries {
+        let mut entry = entry.clone();
+        let repeat = entry
+            .as_object_mut()
+            .and_then(|entry| entry.remove("repeat"));
+        match repeat {
+            Some(repeat) => {
+                let count = substitute(&repeat, parameters, None)?;
+                let count = count
+                    .as_u64()
+                    .or_else(|| count.as_str().and_then(|count| count.parse().ok()))
+                    .ok_or_else(|| TemplateError::InvalidRepeat(repeat.to_string()))?;
+                if count > MAX_REPEAT {
+                    return Err(TemplateError::RepeatTooLarge(count));
+                }
+                for index in 0..count as usize {
+                    expanded.push(substitute(&entry, parameters, Some(index))?);
+                }
+            }
+            None => expanded.push(substitute(&entry, parameters, None)?),
+        }
+    }
+
+    Ok(expanded)
+}
+
+fn substitute(
+    value: &Value,
+    parameters: &HashMap<String, Value>,
+    index: Option<usize>,
+) -> Result<Value, TemplateError> {
+    match value {
+        Value::String(template) => substitute_string(template, parameters, index),
+        Value::Array(values) => Ok(Value::Array(
+            values
+                .iter()
+                .map(|value| substitute(value, parameters, index))
+                .collect::<Result<_, _>>()?,
+        )),
+        Value::Object(values) => Ok(Value::Object(
+            values
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), substitute(value, parameters, index)?)))
+                .collect::<Result<_, TemplateError>>()?,
+        )),
+        value => Ok(value.clone()),
+    }
+}
+
+fn substitute_string(
+    template: &str,
+    parameters: &HashMap<String, Value>,
+    index: Option<usize>,
+) -> Result<Value, TemplateError> {
+    // A lone placeholder keeps the type of the parameter
+    if let Some(name) = template
+        .strip_prefix("${")
+        .and_then(|rest| rest.strip_suffix('}'))
+        .filter(|name| !name.contains("${"))
+    {
+        return resolve(name, parameters, index);
+    }
+
+    let mut output = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("${") {
+        output.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| TemplateError::UnknownParameter(rest[start..].to_string()))?;
+        let name = &rest[start + 2..start + end];
+        match resolve(name, parameters, index)? {
+            Value::String(value) => output.push_str(&value),
+            value => output.push_str(&value.to_string()),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    output.push_str(rest);
+
+    Ok(Value::String(output))
+}
+
+fn resolve(
+    name: &str,
+    parameters: &HashMap<String, Value>,
+    index: Option<usize>,
+) -> Result<Value, TemplateError> {
+    let unknown = || TemplateError::UnknownParameter(name.to_string());
+    if name == "index" {
+        return index.map(Value::from).ok_or_else(unknown);
+    }
+
+    match name.strip_suffix("[index]") {
+        Some(array_name) => {
+            let index = index.ok_or_else(unknown)?;
+            parameters
+                .get(array_name)
+                .and_then(|array| array.get(index))
+                .cloned()
+                .ok_or_else(unknown)
+        }
+        None => parameters.get(name).cloned().ok_or_else(unknown),
+    }
+}
@@ -0,0 +1,135 @@
+/*
+ * Phaneron media compositing software.
+ * Copyright (C) 2023 SuperFlyTV AB
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+use super::{GraphTemplate, TemplateError, MAX_REPEAT};
+
+fn switcher_template() -> GraphTemplate {
+    serde_json::from_value(json!({
+        "nodes": [
+            {
+                "repeat": "${input_count}",
+                "node_id": "producer_${index}",
+                "node_type": "ffmpeg_producer",
+                "node_name": "Input ${index}",
+                "state": { "file": "${files[index]}" }
+            },
+            {
+                "node_id": "switcher",
+                "node_type": "traditional_mixer_emulator",
+                "configuration": { "numberOfInputs": "${input_count}" }
+            }
+        ],
+        "connections": [
+            {
+                "repeat": "${input_count}",
+                "connection_type": "video",
+                "from_node_id": "producer_${index}",
+                "from_output_index": 0,
+                "to_node_id": "switcher",
+                "to_input_index": "${index}"
+            }
+        ]
+    }))
+    .unwrap()
+}
+
+#[test]
+fn expands_repeated_entries() {
+    let parameters: HashMap<String, Value> = HashMap::from([
+        ("input_count".to_string(), json!(3)),
+        ("files".to_string(), json!(["a.mp4", "b.mp4", "c.mp4"])),
+    ]);
+    let instantiated = switcher_template().instantiate(&parameters).unwrap();
+
+    assert_eq!(instantiated.nodes.len(), 4);
+    assert_eq!(instantiated.connections.len(), 3);
+
+    let producer = &instantiated.nodes[1];
+    assert_eq!(producer.node_name.as_deref(), Some("Input 1"));
+    assert_eq!(producer.state.as_deref(), Some(r#"{"file":"b.mp4"}"#));
+    assert_eq!(
+        producer.node_id,
+        instantiated.node_ids["producer_1"].to_string()
+    );
+
+    let switcher = &instantiated.nodes[3];
+    assert_eq!(
+        switcher.configuration.as_deref(),
+        Some(r#"{"numberOfInputs":3}"#)
+    );
+
+    let connection = &instantiated.connections[2];
+    assert_eq!(connection.to_input_index, 2);
+    assert_eq!(
+        connection.from_node_id,
+        instantiated.node_ids["producer_2"].to_string()
+    );
+    assert_eq!(
+        connection.to_node_id,
+        instantiated.node_ids["switcher"].to_string()
+    );
+}
+
+#[test]
+fn missing_parameter_is_an_error() {
+    let parameters: HashMap<String, Value> = HashMap::from([("input_count".to_string(), json!(2))]);
+    let result = switcher_template().instantiate(&parameters);
+
+    assert!(matches!(
+        result,
+        Err(TemplateError::UnknownParameter(name)) if name == "files[index]"
+    ));
+}
+
+#[test]
+fn repeat_over_the_limit_is_an_error() {
+    let parameters: HashMap<String, Value> = HashMap::from([
+        ("input_count".to_string(), json!(MAX_REPEAT + 1)),
+        ("files".to_string(), json!([])),
+    ]);
+    let result = switcher_template().instantiate(&parameters);
+
+    assert!(matches!(
+        result,
+        Err(TemplateError::RepeatTooLarge(repeat)) if repeat == MAX_REPEAT + 1
+    ));
+}
+
+#[test]
+fn duplicate_node_ids_are_an_error() {
+    let template: GraphTemplate = serde_json::from_value(json!({
+        "nodes": [
+            {
+                "repeat": 2,
+                "node_id": "producer",
+                "node_type": "ffmpeg_producer"
+            }
+        ]
+    }))
+    .unwrap();
+    let result = template.instantiate(&HashMap::new());
+
+    assert!(matches!(
+        result,
+        Err(TemplateError::DuplicateNode(node_id)) if node_id == "producer"
+    ));
+}